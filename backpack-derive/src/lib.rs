@@ -0,0 +1,165 @@
+use std::path::Path;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derives `backpack::asset::Encode` and `backpack::asset::PackAsset`.
+///
+/// The type id stored in front of every encoded asset defaults to the name of the type,
+/// and can be set with `#[pack_asset(type_id = "...")]`. Keep it stable once assets are packed.
+#[proc_macro_derive(PackAsset, attributes(pack_asset))]
+pub fn derive_pack_asset(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Embeds the files in a directory as a `backpack::embed::Pack`, so they don't have to be
+/// shipped next to the executable. The directory is packed into a backpack at build time by
+/// `backpack_embed::embed` in the crate's build script, and embedded with `include_bytes!`.
+/// It's relative to the crate's `Cargo.toml`, and files are named by their path in it with
+/// `/` between directories.
+///
+/// ```ignore
+/// // build.rs
+/// backpack_embed::embed("assets/");
+///
+/// // src/main.rs
+/// static ASSETS: Pack = include_pack!("assets/");
+/// ```
+#[proc_macro]
+pub fn include_pack(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+    match expand_include_pack(&dir) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_include_pack(dir: &LitStr) -> syn::Result<TokenStream2> {
+    let name = backpack_embed::packed_name(&dir.value());
+    let packed = std::env::var_os("OUT_DIR").map(|out_dir| Path::new(&out_dir).join(&name));
+    if !packed.is_some_and(|packed| packed.exists()) {
+        let message = format!("{:?} wasn't packed, call `backpack_embed::embed({:?})` from the build script", dir.value(), dir.value());
+        return Err(syn::Error::new_spanned(dir, message));
+    }
+
+    Ok(quote! {
+        ::backpack::embed::Pack::new(::std::include_bytes!(::std::concat!(::std::env!("OUT_DIR"), "/", #name)))
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    let mut type_id = name.to_string();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("pack_asset")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("type_id") {
+                type_id = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown pack_asset attribute, expected `type_id`"))
+            }
+        })?;
+    }
+
+    let (encode, decode) = match &input.data {
+        Data::Struct(s) => {
+            let (pattern, construct) = fields(quote!(#name), &s.fields);
+            let encode = encode_fields(&s.fields);
+            (quote! {
+                let #pattern = self;
+                #encode
+            }, construct)
+        }
+        Data::Enum(e) => {
+            let mut encode_arms = Vec::new();
+            let mut decode_arms = Vec::new();
+            for (i, variant) in e.variants.iter().enumerate() {
+                let i = i as u32;
+                let ident = &variant.ident;
+                let (pattern, construct) = fields(quote!(#name::#ident), &variant.fields);
+                let encode = encode_fields(&variant.fields);
+                encode_arms.push(quote! {
+                    #pattern => {
+                        ::backpack::asset::Encode::encode(&#i, out);
+                        #encode
+                    }
+                });
+                decode_arms.push(quote!(#i => #construct,));
+            }
+
+            (quote! {
+                match self {
+                    #(#encode_arms)*
+                }
+            }, quote! {
+                match <u32 as ::backpack::asset::Encode>::decode(input)? {
+                    #(#decode_arms)*
+                    other => return ::std::result::Result::Err(::std::format!("unknown variant {} of {}", other, ::std::stringify!(#name))),
+                }
+            })
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(name, "PackAsset can't be derived for unions")),
+    };
+
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(::backpack::asset::Encode));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let mut asset_generics = generics.clone();
+    for param in asset_generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(::std::marker::Send));
+        param.bounds.push(syn::parse_quote!(::std::marker::Sync));
+        param.bounds.push(syn::parse_quote!('static));
+    }
+    let (asset_impl_generics, _, _) = asset_generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::backpack::asset::Encode for #name #ty_generics #where_clause {
+            fn encode(&self, out: &mut ::std::vec::Vec<u8>) {
+                #encode
+            }
+
+            fn decode(input: &mut &[u8]) -> ::std::result::Result<Self, ::std::string::String> {
+                ::std::result::Result::Ok(#decode)
+            }
+        }
+
+        impl #asset_impl_generics ::backpack::asset::PackAsset for #name #ty_generics #where_clause {
+            const TYPE_ID: &'static str = #type_id;
+        }
+    })
+}
+
+/// A pattern binding every field to `f{i}` and an expression decoding all of them.
+fn fields(path: TokenStream2, fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let bindings = (0..fields.len()).map(|i| format_ident!("f{}", i)).collect::<Vec<_>>();
+    let decode = quote!(::backpack::asset::Encode::decode(input)?);
+
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap()).collect::<Vec<_>>();
+            let decodes = names.iter().map(|_| &decode);
+            (quote!(#path { #(#names: #bindings),* }), quote!(#path { #(#names: #decodes),* }))
+        }
+        Fields::Unnamed(_) => {
+            let decodes = bindings.iter().map(|_| &decode);
+            (quote!(#path ( #(#bindings),* )), quote!(#path ( #(#decodes),* )))
+        }
+        Fields::Unit => (quote!(#path), quote!(#path)),
+    }
+}
+
+fn encode_fields(fields: &Fields) -> TokenStream2 {
+    let bindings = (0..fields.len()).map(|i| format_ident!("f{}", i));
+    quote! {
+        #(::backpack::asset::Encode::encode(#bindings, out);)*
+    }
+}
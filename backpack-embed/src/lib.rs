@@ -0,0 +1,151 @@
+//! Packs directories into backpacks at build time, for `backpack::embed::include_pack!`.
+//! Call [`embed`] from the build script of the crate using the macro:
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     backpack_embed::embed("assets/");
+//! }
+//! ```
+//!
+//! This crate has no dependencies, so build scripts stay quick to build. It only writes the
+//! simplest backpacks: files stored as they are, without checksums or directory entries.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PACK_MAGIC: &[u8; 8] = b"BACKPACK";
+/// the version of the format written, `backpack::PACK_VERSION`
+const PACK_VERSION: u16 = 0;
+const PACK_HEADER_SIZE: usize = 26;
+const TOC_SIZE: usize = 4096;
+/// `filled` and `next`
+const TOC_BLOCK_HEADER_SIZE: usize = 10;
+/// name length, offset and length
+const TOC_ENTRY_SIZE: usize = 18;
+
+/// Pack the directory `dir`, relative to the crate's `Cargo.toml`, into the file
+/// `include_pack!(dir)` embeds, and rebuild the crate whenever anything in the
+/// directory changes, including files being added or removed.
+///
+/// Panics when the directory can't be packed, failing the build.
+pub fn embed(dir: &str) {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").expect("embed is called from a build script");
+    let out_dir = std::env::var_os("OUT_DIR").expect("embed is called from a build script");
+    let root = Path::new(&manifest_dir).join(dir);
+
+    // a directory is checked for changes to anything in it
+    println!("cargo:rerun-if-changed={}", root.display());
+    let bytes = pack_dir(&root).unwrap_or_else(|e| panic!("can't pack {}: {}", root.display(), e));
+    let out = Path::new(&out_dir).join(packed_name(dir));
+    std::fs::write(&out, bytes).unwrap_or_else(|e| panic!("can't write {}: {}", out.display(), e));
+}
+
+/// The name of the file in `OUT_DIR` which [`embed`] packs `dir` into.
+pub fn packed_name(dir: &str) -> String {
+    let dir = dir.trim_end_matches(['/', '\\']);
+    let name = dir.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect::<String>();
+    format!("backpack-embed-{}.bp", name)
+}
+
+/// The files below `dir` as a backpack, named by their path in it with `/` between directories.
+pub fn pack_dir(dir: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut files = Vec::new();
+    collect_files(dir.as_ref(), String::new(), &mut files)?;
+    files.sort();
+
+    let mut blocks: Vec<Vec<u8>> = Vec::new();
+    let mut data = Vec::new();
+    for (name, path) in files {
+        let entry_len = TOC_ENTRY_SIZE + name.len();
+        if entry_len > TOC_SIZE - TOC_BLOCK_HEADER_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("the name {} is too long", name)));
+        }
+        let contents = std::fs::read(&path)?;
+
+        let block = match blocks.last_mut() {
+            Some(block) if block.len() + entry_len <= TOC_SIZE => block,
+            _ => {
+                blocks.push(vec![0; TOC_BLOCK_HEADER_SIZE]);
+                blocks.last_mut().expect("just pushed")
+            }
+        };
+        block.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        block.extend_from_slice(name.as_bytes());
+        // relative to the start of the data, which follows the table of contents
+        block.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        block.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        data.extend_from_slice(&contents);
+    }
+
+    let first_toc = if blocks.is_empty() { 0 } else { PACK_HEADER_SIZE as u64 };
+    let mut res = Vec::with_capacity(PACK_HEADER_SIZE + blocks.len() * TOC_SIZE + data.len());
+    res.extend_from_slice(PACK_MAGIC);
+    res.extend_from_slice(&PACK_VERSION.to_le_bytes());
+    res.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    res.extend_from_slice(&first_toc.to_le_bytes());
+
+    let count = blocks.len();
+    for (i, mut block) in blocks.into_iter().enumerate() {
+        let next = match i + 1 < count {
+            true => (PACK_HEADER_SIZE + (i + 1) * TOC_SIZE) as u64,
+            false => 0,
+        };
+        let filled = block.len() as u16;
+        block[0..2].copy_from_slice(&filled.to_le_bytes());
+        block[2..10].copy_from_slice(&next.to_le_bytes());
+        block.resize(TOC_SIZE, 0);
+        res.extend_from_slice(&block);
+    }
+    res.extend_from_slice(&data);
+    Ok(res)
+}
+
+/// Every file below `dir`, with its name prefixed by `prefix`.
+fn collect_files(dir: &Path, prefix: String, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().into_string()
+            .map_err(|name| io::Error::new(io::ErrorKind::InvalidData, format!("{:?} isn't valid UTF-8", name)))?;
+        let name = format!("{}{}", prefix, file_name);
+        if std::fs::metadata(&path)?.is_dir() {
+            collect_files(&path, format!("{}/", name), files)?;
+        } else {
+            files.push((name, path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pack_dir, packed_name, PACK_HEADER_SIZE, TOC_SIZE};
+
+    #[test]
+    fn test_packed_name() {
+        assert_eq!(packed_name("assets/"), "backpack-embed-assets.bp");
+        assert_eq!(packed_name("../shared/data"), "backpack-embed-___shared_data.bp");
+    }
+
+    #[test]
+    fn test_pack_dir() {
+        let dir = std::env::temp_dir().join("backpack_embed_test_pack_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("b.txt"), "bb").unwrap();
+        std::fs::write(dir.join("sub/a.txt"), "a").unwrap();
+
+        let bytes = pack_dir(&dir).unwrap();
+        assert_eq!(bytes.len(), PACK_HEADER_SIZE + TOC_SIZE + 3);
+        assert_eq!(&bytes[..8], b"BACKPACK");
+        assert_eq!(&bytes[bytes.len() - 3..], b"bba");
+        // the block's filled bytes: its header and two entries
+        assert_eq!(&bytes[PACK_HEADER_SIZE..PACK_HEADER_SIZE + 2], &(10u16 + 18 + 5 + 18 + 9).to_le_bytes());
+
+        assert_eq!(pack_dir(dir.join("missing")).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+#ifndef BACKPACK_H
+#define BACKPACK_H
+
+/* Generated with cbindgen from src/capi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ The contents of an entry, read from the start with [`backpack_read`].
+ */
+typedef struct BackpackEntry BackpackEntry;
+
+/*
+ An open backpack. Only its index is in memory, entries are read when they're opened.
+ */
+typedef struct BackpackPack BackpackPack;
+
+/*
+ A backpack being written, see [`backpack_writer_create`].
+ */
+typedef struct BackpackWriter BackpackWriter;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Open the backpack in the file at `path`. Returns `NULL` when that fails.
+ Close it with [`backpack_close`].
+
+ # Safety
+ `path` is `NULL` or a nul-terminated string.
+ */
+BackpackPack *backpack_open(const char *path);
+
+/*
+ How many entries `pack` has, without hidden ones. 0 when it's `NULL`.
+
+ # Safety
+ `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+ */
+uintptr_t backpack_entry_count(const BackpackPack *pack);
+
+/*
+ The name of entry `index` of `pack`, in sorted order, or `NULL` when `index` is out of
+ range. The name is valid until the backpack is closed.
+
+ # Safety
+ `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+ */
+const char *backpack_entry_name(const BackpackPack *pack, uintptr_t index);
+
+/*
+ Read the entry called `name` from `pack`. Returns `NULL` when there's no such entry or
+ reading it fails. Close it with [`backpack_entry_close`], it stays valid after the
+ backpack is closed.
+
+ # Safety
+ `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet,
+ `name` is `NULL` or a nul-terminated string.
+ */
+BackpackEntry *backpack_get_entry(const BackpackPack *pack, const char *name);
+
+/*
+ The length of the contents of `entry`, 0 when it's `NULL`.
+
+ # Safety
+ `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet.
+ */
+uint64_t backpack_entry_size(const BackpackEntry *entry);
+
+/*
+ Copy up to `len` bytes of `entry` to `buf`, continuing where the last read stopped.
+ Returns how many bytes were copied, 0 at the end of the entry.
+
+ # Safety
+ `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet,
+ `buf` points to at least `len` writable bytes.
+ */
+uintptr_t backpack_read(BackpackEntry *entry, uint8_t *buf, uintptr_t len);
+
+/*
+ Free an entry. Does nothing for `NULL`.
+
+ # Safety
+ `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet.
+ */
+void backpack_entry_close(BackpackEntry *entry);
+
+/*
+ Close a backpack. Does nothing for `NULL`.
+
+ # Safety
+ `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+ */
+void backpack_close(BackpackPack *pack);
+
+/*
+ Start writing a new backpack to the file at `path`, replacing what's there. Returns `NULL`
+ when that fails. Add entries with [`backpack_writer_add`], and finish it with
+ [`backpack_writer_finish`] or give up with [`backpack_writer_discard`].
+
+ # Safety
+ `path` is `NULL` or a nul-terminated string.
+ */
+BackpackWriter *backpack_writer_create(const char *path);
+
+/*
+ Add an entry called `name` holding the `len` bytes at `data`. Returns 0, or -1 when it fails.
+
+ # Safety
+ `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet,
+ `name` is `NULL` or a nul-terminated string, `data` points to at least `len` readable bytes.
+ */
+int32_t backpack_writer_add(BackpackWriter *writer, const char *name, const uint8_t *data, uintptr_t len);
+
+/*
+ Write the index of the backpack and free the writer. Returns 0, or -1 when it fails,
+ in which case the file isn't a valid backpack.
+
+ # Safety
+ `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet.
+ */
+int32_t backpack_writer_finish(BackpackWriter *writer);
+
+/*
+ Free a writer without finishing the backpack. Does nothing for `NULL`.
+
+ # Safety
+ `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet.
+ */
+void backpack_writer_discard(BackpackWriter *writer);
+
+/*
+ Why the last function which failed on this thread failed, or `NULL` when none did.
+ The message is valid until the next call on this thread.
+ */
+const char *backpack_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BACKPACK_H */
@@ -0,0 +1,15 @@
+"""Read and write backpacks from Python.
+
+The classes come from the native module, which maturin builds from the ``python`` feature
+of the crate: ``pip install .`` or ``maturin develop`` in this directory.
+
+>>> with backpack.create("assets.bp") as writer:
+...     writer.write("hello.txt", b"hello world")
+>>> with backpack.open("assets.bp") as pack:
+...     pack.read("hello.txt")
+b'hello world'
+"""
+
+from ._backpack import BackpackError, Pack, Writer, create, open
+
+__all__ = ["BackpackError", "Pack", "Writer", "open", "create"]
@@ -0,0 +1,62 @@
+"""Run with the package built, from the python directory:
+
+    maturin develop
+    python -m unittest discover tests
+"""
+
+import io
+import os
+import tempfile
+import unittest
+
+import backpack
+
+
+class TestBackpack(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.path = os.path.join(self.dir.name, "test.bp")
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_round_trip(self):
+        with backpack.create(self.path) as writer:
+            writer.write("b.txt", b"hello world")
+            writer.write("a/c.bin", io.BytesIO(bytes(range(256))))
+            writer.write("empty", b"")
+
+        with backpack.open(self.path) as pack:
+            self.assertEqual(list(pack), ["a/c.bin", "b.txt", "empty"])
+            self.assertEqual(len(pack), 3)
+            self.assertIn("b.txt", pack)
+            self.assertEqual(pack.read("b.txt"), b"hello world")
+            self.assertEqual(pack.read("empty"), b"")
+            with pack.open("a/c.bin") as entry:
+                self.assertEqual(entry.read(4), bytes([0, 1, 2, 3]))
+                self.assertEqual(len(entry.read()), 252)
+
+    def test_errors(self):
+        with self.assertRaises(backpack.BackpackError):
+            backpack.open(os.path.join(self.dir.name, "missing.bp"))
+
+        with backpack.create(self.path) as writer:
+            writer.write("a", b"a")
+            with self.assertRaises(backpack.BackpackError):
+                writer.write("a", b"again")
+
+        with backpack.open(self.path) as pack:
+            with self.assertRaisesRegex(backpack.BackpackError, "missing.txt"):
+                pack.read("missing.txt")
+
+    def test_discard(self):
+        with self.assertRaises(RuntimeError):
+            with backpack.create(self.path) as writer:
+                writer.write("a", b"a")
+                raise RuntimeError()
+        with backpack.open(self.path) as pack:
+            self.assertEqual(list(pack), [])
+
+
+if __name__ == "__main__":
+    unittest.main()
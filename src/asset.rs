@@ -0,0 +1,216 @@
+use std::path::Path;
+use crate::registry::{AssetRegistry, AssetSource};
+
+#[cfg(feature = "derive")]
+pub use backpack_derive::PackAsset;
+
+/// Binary encoding of assets and their fields. Derive it with [`PackAsset`](derive@PackAsset)
+/// when the `derive` feature is enabled.
+pub trait Encode: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Decodes a value from the start of `input`, advancing it past the value.
+    fn decode(input: &mut &[u8]) -> Result<Self, String>;
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], String> {
+    if input.len() < n {
+        return Err("unexpected end of asset".to_string());
+    }
+    let (res, rest) = input.split_at(n);
+    *input = rest;
+    Ok(res)
+}
+
+macro_rules! encode_number {
+    ($($t: ty),*) => {$(
+        impl Encode for $t {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(input: &mut &[u8]) -> Result<Self, String> {
+                let bytes = take(input, std::mem::size_of::<$t>())?;
+                Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+encode_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        usize::try_from(u64::decode(input)?).map_err(|e| e.to_string())
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("invalid bool {}", other)),
+        }
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        let len = usize::decode(input)?;
+        String::from_utf8(take(input, len)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for i in self {
+            i.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        let len = usize::decode(input)?;
+        // don't trust the length for the allocation, a corrupt asset could claim anything
+        let mut res = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            res.push(T::decode(input)?);
+        }
+        Ok(res)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_some().encode(out);
+        if let Some(i) = self {
+            i.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(if bool::decode(input)? {
+            Some(T::decode(input)?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_ref().encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(Box::new(T::decode(input)?))
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, String> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+/// A type stored as a file in a backpack. Encoded assets start with their [`TYPE_ID`](Self::TYPE_ID),
+/// so loading a file as the wrong type fails instead of producing garbage.
+pub trait PackAsset: Encode + Send + Sync + 'static {
+    const TYPE_ID: &'static str;
+
+    fn to_pack_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        Self::TYPE_ID.to_string().encode(&mut out);
+        self.encode(&mut out);
+        out
+    }
+
+    fn from_pack_bytes(mut bytes: &[u8]) -> Result<Self, String> {
+        let type_id = String::decode(&mut bytes)?;
+        if type_id != Self::TYPE_ID {
+            return Err(format!("asset has type {:?}, expected {:?}", type_id, Self::TYPE_ID));
+        }
+
+        let res = Self::decode(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(format!("{} trailing bytes after asset", bytes.len()));
+        }
+        Ok(res)
+    }
+
+    /// Register a loader for this type with `registry`.
+    fn register<A: AssetSource>(registry: &mut AssetRegistry<A>) {
+        registry.register(|_: &Path, bytes: &[u8]| Self::from_pack_bytes(bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asset::{Encode, PackAsset};
+    #[cfg(not(feature = "derive"))]
+    use backpack_derive::PackAsset;
+    use crate::error::Result;
+    use crate::registry::AssetRegistry;
+    use crate::{BackPack, InMemoryFile, RawFile};
+
+    #[derive(PackAsset, Debug, PartialEq)]
+    struct Texture {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        label: Option<String>,
+    }
+
+    #[derive(PackAsset, Debug, PartialEq)]
+    #[pack_asset(type_id = "game/shape")]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Rect { w: f32, h: f32 },
+    }
+
+    #[derive(PackAsset, Debug, PartialEq)]
+    struct Pair<T>(T, T);
+
+    #[test]
+    fn test_derive() -> Result<()> {
+        let texture = Texture { width: 2, height: 1, pixels: vec![1, 2], label: Some("grass".to_string()) };
+        assert_eq!(Texture::from_pack_bytes(&texture.to_pack_bytes()), Ok(texture));
+
+        for shape in [Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 1.0, h: 2.0 }] {
+            assert_eq!(Shape::from_pack_bytes(&shape.to_pack_bytes()), Ok(shape));
+        }
+        assert_eq!(Shape::TYPE_ID, "game/shape");
+        assert!(Texture::from_pack_bytes(&Shape::Point.to_pack_bytes()).is_err());
+
+        let mut encoded = Vec::new();
+        Pair(1u8, 2u8).encode(&mut encoded);
+        assert_eq!(Pair::<u8>::decode(&mut encoded.as_slice()), Ok(Pair(1, 2)));
+
+        let bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.add_file(InMemoryFile::from(Shape::Circle(2.0).to_pack_bytes()).with_name("circle"))?;
+        let mut registry = AssetRegistry::new(&bp);
+        Shape::register(&mut registry);
+        assert_eq!(*registry.get::<Shape>("circle")?, Shape::Circle(2.0));
+
+        Ok(())
+    }
+}
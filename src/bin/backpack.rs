@@ -0,0 +1,322 @@
+//! Command line tool to create, inspect and unpack backpacks.
+
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use backpack::pack::{AccessTrace, DirectoryOptions, FreezeOptions, PackError, PackStats, StreamReader};
+use backpack::{BackPack, InMemoryFile, RawFile};
+
+const USAGE: &str = "\
+usage:
+    backpack create <dir> <pack>     pack the files in <dir> into <pack>
+    backpack create <dir> <exe> --sfx <stub>
+                                     make a self-extracting executable <exe> of the
+                                     <stub> executable with the files in <dir> appended
+    backpack extract <pack> <dir>    unpack <pack> into <dir>
+    backpack list <pack>             list the files in <pack> with their sizes
+    backpack verify <pack>           check <pack> for damage
+    backpack cat <pack> <entry>      write the contents of <entry> to stdout
+    backpack du <pack>               show what takes up space in <pack>, largest files first
+    backpack lint <dir>              find names in <dir> which break backpacks on some platforms
+    backpack repack <pack> --order <trace.json>
+                                     lay the files in <pack> out in the order of an access trace
+
+<pack> can be - to write the backpack to stdout or read it from stdin, like in
+    backpack create assets - | ssh host 'backpack extract - assets'";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Create { dir: PathBuf, pack: PathBuf, sfx: Option<PathBuf> },
+    Extract { pack: PathBuf, dir: PathBuf },
+    List { pack: PathBuf },
+    Verify { pack: PathBuf },
+    Cat { pack: PathBuf, entry: String },
+    Du { pack: PathBuf },
+    Repack { pack: PathBuf, order: PathBuf },
+    Lint { dir: PathBuf },
+}
+
+fn parse(args: &[String]) -> Option<Command> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    Some(match args[..] {
+        ["create", dir, pack] => Command::Create { dir: dir.into(), pack: pack.into(), sfx: None },
+        ["create", dir, exe, "--sfx", stub] => Command::Create { dir: dir.into(), pack: exe.into(), sfx: Some(stub.into()) },
+        ["extract", pack, dir] => Command::Extract { pack: pack.into(), dir: dir.into() },
+        ["list", pack] => Command::List { pack: pack.into() },
+        ["verify", pack] => Command::Verify { pack: pack.into() },
+        ["cat", pack, entry] => Command::Cat { pack: pack.into(), entry: entry.to_string() },
+        ["du", pack] => Command::Du { pack: pack.into() },
+        ["lint", dir] => Command::Lint { dir: dir.into() },
+        ["repack", pack, "--order", order] => Command::Repack { pack: pack.into(), order: order.into() },
+        _ => return None,
+    })
+}
+
+/// Whether `path` stands for stdin or stdout.
+fn is_pipe(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Read the backpack at `path`, or from stdin for `-`, into memory, so commands
+/// which only read it never write to the file.
+fn open(path: &Path) -> Result<BackPack<'static, 'static>, PackError> {
+    if is_pipe(path) {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        return BackPack::open(RawFile::InMemory(InMemoryFile::from(data)));
+    }
+    BackPack::open(RawFile::open(path)?.convert_into_memory()?)
+}
+
+/// Pack the files in `dir` into a backpack written front to back to `out`, which doesn't
+/// have to be seekable.
+fn create_to(dir: &Path, out: impl Write) -> Result<(), PackError> {
+    let bp = BackPack::from_directory(dir, &DirectoryOptions::default())?;
+    let mut out = BufWriter::new(out);
+    bp.freeze(&mut out, FreezeOptions::default())?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Write a self-extracting executable to `out`: the executable `stub` followed by a
+/// backpack of the files in `dir`.
+fn create_sfx_to(dir: &Path, stub: &Path, out: impl Write) -> Result<(), PackError> {
+    let mut pack = Vec::new();
+    create_to(dir, &mut pack)?;
+    let stub = std::fs::File::open(stub)?;
+    backpack::sfx::build(pack.as_slice(), std::io::BufReader::new(stub), BufWriter::new(out))
+}
+
+/// Unpack a backpack read front to back from `input`, which doesn't have to be seekable.
+fn extract_from(input: impl Read, dir: &Path) -> Result<(), PackError> {
+    StreamReader::new(input)?.extract_to(dir)
+}
+
+/// Write where the space in a backpack goes to `out`.
+fn write_stats(stats: &PackStats, mut out: impl Write) -> Result<(), PackError> {
+    writeln!(out, "{:>12}  {:>12}  {:>6}  name", "stored", "size", "ratio")?;
+    for entry in &stats.entries {
+        writeln!(out, "{:>12}  {:>12}  {:>6.2}  {}", entry.stored_size, entry.size, entry.compression_ratio(), entry.name)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "{:>12}  {:>12}  {:>6.2}  files", stats.stored_size, stats.files_size, stats.compression_ratio())?;
+    writeln!(out, "{:>12}  index", stats.index_size)?;
+    writeln!(out, "{:>12}  metadata", stats.metadata_size)?;
+    writeln!(out, "{:>12}  unused ({:.1}%)", stats.dead_size, stats.dead_ratio() * 100.0)?;
+    writeln!(out, "{:>12}  total", stats.file_size)?;
+    Ok(())
+}
+
+/// Run `command`, returning whether it succeeded.
+fn run(command: Command) -> Result<bool, PackError> {
+    match command {
+        Command::Create { dir, pack, sfx: Some(stub) } if is_pipe(&pack) => create_sfx_to(&dir, &stub, std::io::stdout().lock())?,
+        Command::Create { dir, pack: exe, sfx: Some(stub) } => {
+            create_sfx_to(&dir, &stub, std::fs::File::create(&exe)?)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        Command::Create { dir, pack, sfx: None } if is_pipe(&pack) => create_to(&dir, std::io::stdout().lock())?,
+        Command::Create { dir, pack, sfx: None } => {
+            let bp = BackPack::from_directory(&dir, &DirectoryOptions::default())?;
+            bp.save_atomic(&pack)?;
+        }
+        Command::Extract { pack, dir } if is_pipe(&pack) => extract_from(std::io::stdin().lock(), &dir)?,
+        Command::Extract { pack, dir } => {
+            open(&pack)?.extract_to(&dir)?;
+        }
+        Command::List { pack } if is_pipe(&pack) => {
+            let mut stdin = std::io::stdin().lock();
+            let stream = StreamReader::new(&mut stdin)?;
+            let mut stdout = std::io::stdout().lock();
+            for (name, size) in stream.entries() {
+                writeln!(stdout, "{:>12}  {}", size, name)?;
+            }
+            // so what's writing the backpack doesn't fail with a broken pipe
+            std::io::copy(&mut stdin, &mut std::io::sink())?;
+        }
+        Command::List { pack } => {
+            let bp = open(&pack)?;
+            let mut stdout = std::io::stdout().lock();
+            for entry in bp.entries() {
+                writeln!(stdout, "{:>12}  {}", entry.size, entry.name)?;
+            }
+        }
+        Command::Verify { pack } => {
+            let report = open(&pack)?.verify_all()?;
+            if let Some(reason) = &report.index {
+                eprintln!("index: {}", reason);
+            }
+            for (name, reason) in &report.files {
+                eprintln!("{}: {}", name, reason);
+            }
+            if !report.is_ok() {
+                return Ok(false);
+            }
+            println!("ok");
+        }
+        Command::Cat { pack, entry } if is_pipe(&pack) => {
+            let mut stdin = std::io::stdin().lock();
+            let mut stream = StreamReader::new(&mut stdin)?;
+            let mut stdout = std::io::stdout().lock();
+            loop {
+                match stream.next_entry()? {
+                    Some(mut found) if found.name() == entry => {
+                        std::io::copy(&mut found, &mut stdout)?;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => return Err(PackError::FileNotFound(entry.into())),
+                }
+            }
+            stdout.flush()?;
+            std::io::copy(&mut stdin, &mut std::io::sink())?;
+        }
+        Command::Cat { pack, entry } => {
+            let bp = open(&pack)?;
+            let mut stdout = std::io::stdout().lock();
+            std::io::copy(&mut bp.entry(&entry)?, &mut stdout)?;
+            stdout.flush()?;
+        }
+        Command::Du { pack } if is_pipe(&pack) => {
+            let mut data = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut data)?;
+            write_stats(&BackPack::stats_of(Cursor::new(data))?, std::io::stdout().lock())?;
+        }
+        Command::Du { pack } => write_stats(&BackPack::stats(&pack)?, std::io::stdout().lock())?,
+        Command::Lint { dir } => {
+            let issues = backpack::lint::lint_dir(&dir)?;
+            for issue in &issues {
+                eprintln!("{}", issue);
+            }
+            if !issues.is_empty() {
+                return Ok(false);
+            }
+            println!("ok");
+        }
+        Command::Repack { pack, order } => {
+            let trace = AccessTrace::read_from(std::fs::File::open(&order)?)?;
+            let options = FreezeOptions { trace: Some(trace), ..FreezeOptions::default() };
+            let bp = open(&pack)?;
+            if is_pipe(&pack) {
+                let mut out = BufWriter::new(std::io::stdout().lock());
+                bp.freeze(&mut out, options)?;
+                out.flush()?;
+            } else {
+                bp.save_atomic_with(&pack, options)?;
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let Some(command) = parse(&args) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    match run(command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("backpack: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use backpack::pack::StreamReader;
+    use backpack::BackPack;
+    use crate::{create_to, extract_from, parse, run, Command};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&args(&["list", "a.bp"])), Some(Command::List { pack: "a.bp".into() }));
+        assert_eq!(parse(&args(&["cat", "a.bp", "dir/b.txt"])), Some(Command::Cat { pack: "a.bp".into(), entry: "dir/b.txt".to_string() }));
+        assert_eq!(parse(&args(&["du", "a.bp"])), Some(Command::Du { pack: "a.bp".into() }));
+        assert_eq!(parse(&args(&["repack", "a.bp", "--order", "trace.json"])), Some(Command::Repack { pack: "a.bp".into(), order: "trace.json".into() }));
+        assert_eq!(parse(&args(&["repack", "a.bp"])), None);
+        assert_eq!(parse(&args(&["lint", "assets"])), Some(Command::Lint { dir: "assets".into() }));
+        assert_eq!(parse(&args(&["create", "assets"])), None);
+        assert_eq!(parse(&args(&["create", "assets", "app", "--sfx", "stub"])), Some(Command::Create { dir: "assets".into(), pack: "app".into(), sfx: Some("stub".into()) }));
+        assert_eq!(parse(&args(&["unpack", "a.bp", "out"])), None);
+        assert_eq!(parse(&args(&[])), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = std::env::temp_dir().join("backpack_test_cli");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/sub")).unwrap();
+        std::fs::write(dir.join("src/sub/a.txt"), "a").unwrap();
+        let pack = dir.join("a.bp");
+
+        assert!(run(Command::Create { dir: dir.join("src"), pack: pack.clone(), sfx: None }).unwrap());
+        assert!(run(Command::Verify { pack: pack.clone() }).unwrap());
+        assert!(run(Command::Lint { dir: dir.join("src") }).unwrap());
+        assert!(run(Command::Extract { pack: pack.clone(), dir: dir.join("out") }).unwrap());
+        assert_eq!(std::fs::read(dir.join("out/sub/a.txt")).unwrap(), b"a");
+        assert!(run(Command::Du { pack: pack.clone() }).unwrap());
+        assert!(run(Command::Cat { pack: pack.clone(), entry: "missing".to_string() }).is_err());
+
+        std::fs::write(dir.join("stub"), "#!/bin/sh\n").unwrap();
+        let exe = dir.join("app");
+        assert!(run(Command::Create { dir: dir.join("src"), pack: exe.clone(), sfx: Some(dir.join("stub")) }).unwrap());
+        assert!(std::fs::read(&exe).unwrap().starts_with(b"#!/bin/sh\n"));
+        let bp = BackPack::open_appended(std::fs::File::open(&exe).unwrap()).unwrap();
+        assert_eq!(bp.file_names(), ["sub/a.txt"]);
+        bp.close_drop_unwritten_changes().unwrap();
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&exe).unwrap().permissions()) & 0o111, 0o111);
+
+        std::fs::write(dir.join("src/b.txt"), "b").unwrap();
+        assert!(run(Command::Create { dir: dir.join("src"), pack: pack.clone(), sfx: None }).unwrap());
+        std::fs::write(dir.join("trace.json"), r#"["sub/a.txt", "b.txt"]"#).unwrap();
+        assert!(run(Command::Repack { pack: pack.clone(), order: dir.join("trace.json") }).unwrap());
+        let bytes = std::fs::read(&pack).unwrap();
+        let stream = StreamReader::new(bytes.as_slice()).unwrap();
+        let order = stream.entries().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order, ["sub/a.txt", "b.txt"]);
+        assert!(run(Command::Repack { pack, order: dir.join("missing.json") }).is_err());
+
+        // names which are the same file on case insensitive filesystems fail the lint
+        std::fs::create_dir_all(dir.join("lint")).unwrap();
+        std::fs::write(dir.join("lint/readme"), "a").unwrap();
+        std::fs::write(dir.join("lint/README"), "b").unwrap();
+        assert!(!run(Command::Lint { dir: dir.join("lint") }).unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pipe() {
+        let dir = std::env::temp_dir().join("backpack_test_cli_pipe");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/sub")).unwrap();
+        std::fs::write(dir.join("src/sub/a.txt"), "same").unwrap();
+        std::fs::write(dir.join("src/b.txt"), "same").unwrap();
+        std::fs::write(dir.join("src/empty"), "").unwrap();
+
+        assert_eq!(parse(&args(&["create", "src", "-"])), Some(Command::Create { dir: "src".into(), pack: "-".into(), sfx: None }));
+        let mut piped = Vec::new();
+        create_to(&dir.join("src"), &mut piped).unwrap();
+        // a byte slice can only be read forwards, like stdin
+        extract_from(piped.as_slice(), &dir.join("out")).unwrap();
+        assert_eq!(std::fs::read(dir.join("out/sub/a.txt")).unwrap(), b"same");
+        assert_eq!(std::fs::read(dir.join("out/b.txt")).unwrap(), b"same");
+        assert_eq!(std::fs::read(dir.join("out/empty")).unwrap(), b"");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
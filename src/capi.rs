@@ -0,0 +1,322 @@
+//! Build a C library with `cargo rustc --release --features capi --crate-type cdylib`
+//! (or `staticlib`), and include `include/backpack.h`. The header is generated with
+//! `cbindgen --config cbindgen.toml --output include/backpack.h`.
+//!
+//! Functions which can fail return `NULL`, and [`backpack_last_error`] says why.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::io::Write;
+use std::ptr;
+use crate::pack::{PackReader, PackWriter};
+use crate::RawFile;
+
+/// An open backpack. Only its index is in memory, entries are read when they're opened.
+pub struct BackpackPack {
+    reader: PackReader<'static, 'static>,
+    /// names of the entries, sorted, for [`backpack_entry_name`]
+    names: Vec<CString>,
+}
+
+/// The contents of an entry, read from the start with [`backpack_read`].
+pub struct BackpackEntry {
+    contents: Vec<u8>,
+    position: usize,
+}
+
+/// A backpack being written, see [`backpack_writer_create`].
+pub struct BackpackWriter {
+    writer: PackWriter<'static, 'static>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(e: impl Display) {
+    let message = CString::new(e.to_string().replace('\0', "")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// `s` as a string, or `None` when it's `NULL` or not UTF-8.
+///
+/// # Safety
+/// `s` is `NULL` or a nul-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_error("argument is NULL");
+        return None;
+    }
+    // Safety: the caller passes a nul-terminated string
+    let res = unsafe { CStr::from_ptr(s) }.to_str();
+    res.map_err(set_error).ok()
+}
+
+/// Open the backpack in the file at `path`. Returns `NULL` when that fails.
+/// Close it with [`backpack_close`].
+///
+/// # Safety
+/// `path` is `NULL` or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_open(path: *const c_char) -> *mut BackpackPack {
+    // Safety: passed on from the caller
+    let Some(path) = (unsafe { str_arg(path) }) else {
+        return ptr::null_mut();
+    };
+    match RawFile::open(path).and_then(PackReader::open) {
+        Ok(reader) => {
+            let names = reader.file_names().into_iter()
+                .filter_map(|name| CString::new(name).ok())
+                .collect();
+            Box::into_raw(Box::new(BackpackPack { reader, names }))
+        }
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// How many entries `pack` has, without hidden ones. 0 when it's `NULL`.
+///
+/// # Safety
+/// `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_entry_count(pack: *const BackpackPack) -> usize {
+    // Safety: passed on from the caller
+    unsafe { pack.as_ref() }.map_or(0, |pack| pack.names.len())
+}
+
+/// The name of entry `index` of `pack`, in sorted order, or `NULL` when `index` is out of
+/// range. The name is valid until the backpack is closed.
+///
+/// # Safety
+/// `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_entry_name(pack: *const BackpackPack, index: usize) -> *const c_char {
+    // Safety: passed on from the caller
+    match unsafe { pack.as_ref() }.and_then(|pack| pack.names.get(index)) {
+        Some(name) => name.as_ptr(),
+        None => {
+            set_error("no entry at this index");
+            ptr::null()
+        }
+    }
+}
+
+/// Read the entry called `name` from `pack`. Returns `NULL` when there's no such entry or
+/// reading it fails. Close it with [`backpack_entry_close`], it stays valid after the
+/// backpack is closed.
+///
+/// # Safety
+/// `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet,
+/// `name` is `NULL` or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_get_entry(pack: *const BackpackPack, name: *const c_char) -> *mut BackpackEntry {
+    // Safety: passed on from the caller
+    let (Some(pack), Some(name)) = (unsafe { pack.as_ref() }, unsafe { str_arg(name) }) else {
+        if pack.is_null() {
+            set_error("pack is NULL");
+        }
+        return ptr::null_mut();
+    };
+    match pack.reader.read(name) {
+        Ok(contents) => Box::into_raw(Box::new(BackpackEntry { contents, position: 0 })),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The length of the contents of `entry`, 0 when it's `NULL`.
+///
+/// # Safety
+/// `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_entry_size(entry: *const BackpackEntry) -> u64 {
+    // Safety: passed on from the caller
+    unsafe { entry.as_ref() }.map_or(0, |entry| entry.contents.len() as u64)
+}
+
+/// Copy up to `len` bytes of `entry` to `buf`, continuing where the last read stopped.
+/// Returns how many bytes were copied, 0 at the end of the entry.
+///
+/// # Safety
+/// `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet,
+/// `buf` points to at least `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_read(entry: *mut BackpackEntry, buf: *mut u8, len: usize) -> usize {
+    // Safety: passed on from the caller
+    let Some(entry) = (unsafe { entry.as_mut() }) else {
+        set_error("entry is NULL");
+        return 0;
+    };
+    let rest = &entry.contents[entry.position..];
+    let n = rest.len().min(len);
+    if n > 0 {
+        // Safety: the caller guarantees room for `len` bytes, and `n` is at most that
+        unsafe { ptr::copy_nonoverlapping(rest.as_ptr(), buf, n) };
+    }
+    entry.position += n;
+    n
+}
+
+/// Free an entry. Does nothing for `NULL`.
+///
+/// # Safety
+/// `entry` is `NULL` or an entry from [`backpack_get_entry`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_entry_close(entry: *mut BackpackEntry) {
+    if !entry.is_null() {
+        // Safety: the entry was made by Box::into_raw, and the caller gives it up
+        drop(unsafe { Box::from_raw(entry) });
+    }
+}
+
+/// Close a backpack. Does nothing for `NULL`.
+///
+/// # Safety
+/// `pack` is `NULL` or a backpack from [`backpack_open`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_close(pack: *mut BackpackPack) {
+    if !pack.is_null() {
+        // Safety: the backpack was made by Box::into_raw, and the caller gives it up
+        drop(unsafe { Box::from_raw(pack) });
+    }
+}
+
+/// Start writing a new backpack to the file at `path`, replacing what's there. Returns `NULL`
+/// when that fails. Add entries with [`backpack_writer_add`], and finish it with
+/// [`backpack_writer_finish`] or give up with [`backpack_writer_discard`].
+///
+/// # Safety
+/// `path` is `NULL` or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_writer_create(path: *const c_char) -> *mut BackpackWriter {
+    // Safety: passed on from the caller
+    let Some(path) = (unsafe { str_arg(path) }) else {
+        return ptr::null_mut();
+    };
+    match RawFile::create(path).and_then(PackWriter::new) {
+        Ok(writer) => Box::into_raw(Box::new(BackpackWriter { writer })),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Add an entry called `name` holding the `len` bytes at `data`. Returns 0, or -1 when it fails.
+///
+/// # Safety
+/// `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet,
+/// `name` is `NULL` or a nul-terminated string, `data` points to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_writer_add(writer: *mut BackpackWriter, name: *const c_char, data: *const u8, len: usize) -> i32 {
+    // Safety: passed on from the caller
+    let (Some(writer), Some(name)) = (unsafe { writer.as_mut() }, unsafe { str_arg(name) }) else {
+        if writer.is_null() {
+            set_error("writer is NULL");
+        }
+        return -1;
+    };
+    let contents = match len {
+        0 => &[][..],
+        // Safety: the caller guarantees `len` readable bytes
+        _ => unsafe { std::slice::from_raw_parts(data, len) },
+    };
+    match writer.writer.add_entry(name, contents) {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Write the index of the backpack and free the writer. Returns 0, or -1 when it fails,
+/// in which case the file isn't a valid backpack.
+///
+/// # Safety
+/// `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_writer_finish(writer: *mut BackpackWriter) -> i32 {
+    if writer.is_null() {
+        set_error("writer is NULL");
+        return -1;
+    }
+    // Safety: the writer was made by Box::into_raw, and the caller gives it up
+    let writer = unsafe { Box::from_raw(writer) };
+    match writer.writer.finish().and_then(|mut file| Ok(file.flush()?)) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Free a writer without finishing the backpack. Does nothing for `NULL`.
+///
+/// # Safety
+/// `writer` is `NULL` or a writer from [`backpack_writer_create`] which wasn't finished yet.
+#[no_mangle]
+pub unsafe extern "C" fn backpack_writer_discard(writer: *mut BackpackWriter) {
+    if !writer.is_null() {
+        // Safety: the writer was made by Box::into_raw, and the caller gives it up
+        drop(unsafe { Box::from_raw(writer) });
+    }
+}
+
+/// Why the last function which failed on this thread failed, or `NULL` when none did.
+/// The message is valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn backpack_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use crate::capi::*;
+
+    #[test]
+    fn test_capi() {
+        let path = std::env::temp_dir().join("backpack_test_capi");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let writer = backpack_writer_create(c_path.as_ptr());
+            assert_eq!(backpack_writer_add(writer, c"hello.txt".as_ptr(), b"hello world".as_ptr(), 11), 0);
+            assert_eq!(backpack_writer_add(writer, c"empty.txt".as_ptr(), std::ptr::null(), 0), 0);
+            assert_eq!(backpack_writer_add(writer, c"hello.txt".as_ptr(), b"again".as_ptr(), 5), -1);
+            assert_eq!(backpack_writer_finish(writer), 0);
+
+            let pack = backpack_open(c_path.as_ptr());
+            assert!(!pack.is_null());
+            assert_eq!(backpack_entry_count(pack), 2);
+            assert_eq!(CStr::from_ptr(backpack_entry_name(pack, 1)), c"hello.txt");
+            assert!(backpack_entry_name(pack, 2).is_null());
+
+            assert!(backpack_get_entry(pack, c"missing.txt".as_ptr()).is_null());
+            assert!(CStr::from_ptr(backpack_last_error()).to_str().unwrap().contains("missing.txt"));
+
+            let entry = backpack_get_entry(pack, c"hello.txt".as_ptr());
+            backpack_close(pack);
+            assert_eq!(backpack_entry_size(entry), 11);
+            let mut buf = [0u8; 8];
+            assert_eq!(backpack_read(entry, buf.as_mut_ptr(), buf.len()), 8);
+            assert_eq!(&buf, b"hello wo");
+            assert_eq!(backpack_read(entry, buf.as_mut_ptr(), buf.len()), 3);
+            assert_eq!(&buf[..3], b"rld");
+            assert_eq!(backpack_read(entry, buf.as_mut_ptr(), buf.len()), 0);
+            backpack_entry_close(entry);
+
+            assert!(backpack_open(c"/nonexistent/pack.bp".as_ptr()).is_null());
+            assert!(backpack_open(std::ptr::null()).is_null());
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
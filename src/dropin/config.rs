@@ -22,7 +22,7 @@ pub struct Config {
 
 impl AsRef<Config> for Config {
     fn as_ref(&self) -> &Config {
-        &self
+        self
     }
 }
 
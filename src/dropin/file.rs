@@ -1,8 +1,7 @@
-use std::fs::Metadata;
 use std::io::{Read, Seek, SeekFrom, Write, Result, Error as IoError};
 use std::path::Path;
 use crate::dropin::config::OpenPolicy;
-use crate::{InMemoryFile, pack};
+use crate::{FileMetadata, InMemoryFile, pack};
 use crate::dropin::scope::{get_backpack, with_config};
 
 pub struct File<'f, 'backpack> {
@@ -64,7 +63,7 @@ impl<'f> File<'f, 'static> {
         self.inner.set_len(size).map_err(Into::<IoError>::into)
     }
 
-    pub fn metadata(&self) -> Result<Metadata> {
+    pub fn metadata(&self) -> Result<FileMetadata> {
         self.inner.metadata().map_err(Into::<IoError>::into)
     }
 
@@ -0,0 +1,90 @@
+use once_cell::sync::OnceCell;
+use crate::error;
+use crate::format::SliceReader;
+use crate::pack::{BackPack, PackReader};
+
+pub use backpack_derive::include_pack;
+
+/// The files of a directory embedded in the executable with [`include_pack!`], for small tools
+/// and WASM builds which shouldn't depend on files next to them at runtime. The directory is
+/// packed into a backpack at build time by `backpack_embed::embed` in the build script, which
+/// also rebuilds the crate when files in it change, are added or removed.
+///
+/// ```ignore
+/// // build.rs
+/// backpack_embed::embed("assets/");
+///
+/// // src/main.rs
+/// use backpack::embed::{include_pack, Pack};
+///
+/// static ASSETS: Pack = include_pack!("assets/");
+///
+/// let config = ASSETS.reader()?.read_to_string("config.toml")?;
+/// ```
+pub struct Pack {
+    bytes: &'static [u8],
+    slice: OnceCell<SliceReader<'static>>,
+    reader: OnceCell<PackReader<'static, 'static>>,
+}
+
+impl Pack {
+    /// The embedded backpack `bytes`, made by [`include_pack!`].
+    pub const fn new(bytes: &'static [u8]) -> Self {
+        Self {
+            bytes,
+            slice: OnceCell::new(),
+            reader: OnceCell::new(),
+        }
+    }
+
+    fn slice(&self) -> error::Result<&SliceReader<'static>> {
+        self.slice.get_or_try_init(|| Ok(SliceReader::open(self.bytes)?))
+    }
+
+    /// Names of the embedded files, sorted.
+    pub fn file_names(&self) -> error::Result<Vec<&str>> {
+        Ok(self.slice()?.file_names())
+    }
+
+    /// The contents of the file called `name`, borrowed from the embedded backpack.
+    pub fn get(&self, name: &str) -> Option<&'static [u8]> {
+        self.slice().ok()?.get(name).ok()
+    }
+
+    /// The embedded backpack.
+    pub fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// A reader of the embedded backpack, shared by every use.
+    pub fn reader(&self) -> error::Result<&PackReader<'static, 'static>> {
+        self.reader.get_or_try_init(|| PackReader::open(self.bytes.to_vec()))
+    }
+
+    /// A copy of the embedded backpack in memory, which can be changed.
+    pub fn open(&self) -> error::Result<BackPack<'static, 'static>> {
+        BackPack::open(self.bytes.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::embed::Pack;
+
+    #[test]
+    fn test_include_pack() {
+        // what include_pack!("backpack-derive/src") embeds
+        let bytes = backpack_embed::pack_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/backpack-derive/src")).unwrap();
+        let sources = Pack::new(bytes.leak());
+
+        let lib = include_bytes!("../backpack-derive/src/lib.rs");
+        assert_eq!(sources.file_names().unwrap(), ["lib.rs"]);
+        assert_eq!(sources.get("lib.rs"), Some(&lib[..]));
+        assert_eq!(sources.get("main.rs"), None);
+
+        assert_eq!(sources.reader().unwrap().read("lib.rs").unwrap(), lib);
+        let bp = sources.open().unwrap();
+        assert_eq!(bp.file_names(), ["lib.rs"]);
+        assert_eq!(&*bp.get_file("lib.rs").unwrap().get_bytes(), lib);
+    }
+}
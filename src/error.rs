@@ -1,22 +1,40 @@
 use std::convert::Infallible;
 use std::io::{Error as IoError, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::string::FromUtf8Error;
 use thiserror::Error;
-use crate::pack::PACK_MAGIC;
+use crate::format::FormatError;
+use crate::pack::{Compression, PACK_MAGIC};
 
 #[derive(Error, Debug)]
 pub enum PackError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
+    /// An I/O error, with the path of the file it happened to.
+    #[error("{path:?}: {source}")]
+    IoAt {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// An error reading a line of a text file in a backpack, like the line not being UTF-8.
+    #[error("{name:?}, line {line}: {source}")]
+    Line {
+        name: PathBuf,
+        line: u64,
+        #[source]
+        source: std::io::Error,
+    },
+
     #[error("backpack magic number, expected {:?}", PACK_MAGIC)]
     BadMagic,
 
     #[error(transparent)]
     Utf8Error(#[from] FromUtf8Error),
 
-    #[error("version {0} found in backpack could not be read by this version of the backpack library")]
+    #[error("backpack is written in format version {0}, which this version of the backpack library can't read (it reads version {})", crate::pack::PACK_VERSION)]
     Incompatible(u16),
 
     #[error("attempted operation on closed file")]
@@ -25,24 +43,244 @@ pub enum PackError {
     #[error("file {0:?} not present in backpack")]
     FileNotFound(PathBuf),
 
+    #[error("file {0:?} already present in backpack")]
+    FileExists(PathBuf),
+
     #[error("attempted to pack a file which has no name")]
     NoName,
 
+    #[error("{0:?} is in .backpack/, which is reserved for the backpack itself")]
+    ReservedName(PathBuf),
+
     #[error("invalid table of content entry in the backpack. this is a bug")]
     InvalidEntry,
+
+    #[error("the index of the backpack is damaged, and it has no intact copy")]
+    DamagedIndex,
+
+    /// The table of contents can't be parsed, `offset` is where the broken block starts.
+    #[error("the index of the backpack is damaged in the table of contents block at offset {offset}")]
+    CorruptIndex {
+        offset: u64,
+    },
+
+    #[error("contents of {0:?} don't match their checksum, the backpack is damaged")]
+    ChecksumMismatch(PathBuf),
+
+    /// Found when opening in [strict](crate::pack::ReadMode::Strict) mode.
+    #[error("malformed backpack: {0}")]
+    Malformed(String),
+
+    #[error("{0:?} would be extracted outside of the target directory")]
+    UnsafePath(PathBuf),
+
+    #[error("the name {0:?} isn't valid UTF-8")]
+    NonUtf8Name(String),
+
+    #[error("backpack can't be read as a stream, its layout requires seeking backwards")]
+    NotSequential,
+
+    #[error("backpack is too large to be written as a zip archive")]
+    ZipTooLarge,
+
+    #[error("backpack needs more data files than a split backpack can refer to")]
+    TooManyDataFiles,
+
+    #[error("{program} exited with {status}")]
+    CommandFailed {
+        program: String,
+        status: std::process::ExitStatus,
+    },
+
+    #[error("no backpack appended to this file")]
+    NoAppendedPack,
+
+    #[error("alignment {0} is not a power of two")]
+    BadAlignment(u64),
+
+    #[error("invalid access trace: {0}")]
+    InvalidTrace(&'static str),
+
+    #[error("request failed with status {0}")]
+    HttpStatus(u16),
+
+    #[error("network error: {0}")]
+    Network(String),
+
+    #[error("{name:?} rejected by {validator}: {reason}")]
+    InvalidAsset {
+        name: PathBuf,
+        validator: String,
+        reason: String,
+    },
+
+    #[error("failed to serialize {name:?}: {reason}")]
+    Serialize {
+        name: PathBuf,
+        reason: String,
+    },
+
+    #[error("the backpack isn't signed")]
+    Unsigned,
+
+    #[error("the signature of the backpack doesn't match, it was changed or signed by another key")]
+    BadSignature,
+
+    #[error("invalid signing key")]
+    InvalidSigningKey,
+
+    #[error("the operation was cancelled")]
+    Cancelled,
+
+    #[error("only backpacks in a named file on disk can be journaled")]
+    NotJournaled,
+
+    #[error("appending to the backpack would drop the protection of its index")]
+    ProtectedIndex,
+
+    #[error("quota for {0:?} exceeded")]
+    QuotaExceeded(String),
+
+    #[error("{0:?} is larger than the limit of {1} bytes per entry")]
+    EntryTooLarge(PathBuf, u64),
+
+    #[error("the entries would be larger than the limit of {0} bytes together")]
+    PackTooLarge(u64),
+
+    #[error("the backpack would have more than the limit of {0} entries")]
+    TooManyEntries(usize),
+
+    #[error("the memory limit of {0} bytes is reached, flush to make room")]
+    MemoryLimit(u64),
+
+    #[error("the index uses field {0:#06x}, which this version of the backpack library doesn't understand")]
+    UnsupportedIndexField(u16),
+
+    #[error("{0:?} compression is not supported by this build of the backpack library")]
+    UnsupportedCompression(Compression),
+
+    #[error("the backpack is encrypted, open it with a passphrase (needs the crypto feature)")]
+    Encrypted,
+
+    #[error("wrong passphrase for the encrypted backpack")]
+    WrongPassphrase,
+
+    #[error("{0:?} isn't encrypted, but the backpack is")]
+    Unencrypted(PathBuf),
+
+    #[error("zip hybrid backpacks can't be encrypted, zip readers couldn't read them")]
+    EncryptedZip,
+
+    #[error("zip archive uses {0}, which the backpack library can't read")]
+    UnsupportedZip(&'static str),
+
+    #[error("manifest schema version {0} is newer than this version of the backpack library supports")]
+    UnsupportedManifest(u32),
+
+    #[error("incompatible options: {0}")]
+    IncompatibleOptions(&'static str),
+
+    #[error("the hash doesn't cover the whole file, parts of it were skipped or written over")]
+    Unhashed,
+
+    #[error("no loader registered for {0}")]
+    NoLoader(&'static str),
+
+    #[error("failed to load {name:?} as {asset}: {reason}")]
+    AssetLoad {
+        name: PathBuf,
+        asset: &'static str,
+        reason: String,
+    },
 }
 
-impl Into<std::io::Error> for PackError {
-    fn into(self) -> IoError {
-        match self {
+impl From<PackError> for IoError {
+    fn from(e: PackError) -> Self {
+        match e {
             PackError::Io(e) => e,
+            PackError::IoAt { path, source } => IoError::new(source.kind(), PackError::IoAt { path, source }),
+            PackError::Line { name, line, source } => IoError::new(source.kind(), PackError::Line { name, line, source }),
             e@PackError::BadMagic |
-            e@PackError::Utf8Error(_) => IoError::new(ErrorKind::InvalidData, e),
-            e@PackError::Incompatible(_) => IoError::new(ErrorKind::Unsupported, e),
-            e@PackError::Closed => IoError::new(ErrorKind::Other, e),
+            e@PackError::Utf8Error(_) |
+            e@PackError::NoAppendedPack |
+            e@PackError::InvalidTrace(_) |
+            e@PackError::DamagedIndex |
+            e@PackError::CorruptIndex { .. } |
+            e@PackError::ChecksumMismatch(_) |
+            e@PackError::Unencrypted(_) |
+            e@PackError::Malformed(_) |
+            e@PackError::UnsafePath(_) |
+            e@PackError::NonUtf8Name(_) |
+            e@PackError::NotSequential => IoError::new(ErrorKind::InvalidData, e),
+            e@PackError::Incompatible(_) |
+            e@PackError::UnsupportedIndexField(_) |
+            e@PackError::UnsupportedCompression(_) |
+            e@PackError::EncryptedZip |
+            e@PackError::UnsupportedZip(_) |
+            e@PackError::UnsupportedManifest(_) => IoError::new(ErrorKind::Unsupported, e),
+            e@PackError::Encrypted |
+            e@PackError::WrongPassphrase |
+            e@PackError::Unsigned |
+            e@PackError::BadSignature => IoError::new(ErrorKind::PermissionDenied, e),
+            e@PackError::Closed |
+            e@PackError::Unhashed => IoError::other(e),
             e@PackError::FileNotFound(_) => IoError::new(ErrorKind::NotFound, e),
+            e@PackError::FileExists(_) => IoError::new(ErrorKind::AlreadyExists, e),
+            e@PackError::BadAlignment(_) |
+            e@PackError::ReservedName(_) |
+            e@PackError::Serialize { .. } |
+            e@PackError::InvalidSigningKey |
+            e@PackError::IncompatibleOptions(_) |
+            e@PackError::InvalidAsset { .. } => IoError::new(ErrorKind::InvalidInput, e),
+            e@PackError::AssetLoad { .. } => IoError::new(ErrorKind::InvalidData, e),
+            e@PackError::NoLoader(_) |
+            e@PackError::NotJournaled |
+            e@PackError::ProtectedIndex => IoError::new(ErrorKind::Unsupported, e),
+            e@PackError::HttpStatus(404) => IoError::new(ErrorKind::NotFound, e),
             e@PackError::NoName |
-            e@PackError::InvalidEntry => IoError::new(ErrorKind::Other, e)
+            e@PackError::InvalidEntry |
+            e@PackError::HttpStatus(_) |
+            e@PackError::Network(_) |
+            e@PackError::CommandFailed { .. } => IoError::other(e),
+            e@PackError::QuotaExceeded(_) |
+            e@PackError::TooManyEntries(_) => IoError::new(ErrorKind::QuotaExceeded, e),
+            e@PackError::MemoryLimit(_) => IoError::new(ErrorKind::OutOfMemory, e),
+            e@PackError::Cancelled => IoError::new(ErrorKind::Interrupted, e),
+            e@PackError::EntryTooLarge(..) |
+            e@PackError::PackTooLarge(_) |
+            e@PackError::ZipTooLarge |
+            e@PackError::TooManyDataFiles => IoError::new(ErrorKind::FileTooLarge, e),
+        }
+    }
+}
+
+/// Adds the path of the file an operation was on to I/O errors, as [`PackError::IoAt`].
+pub(crate) trait AtPath<T> {
+    fn at_path(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T, E: Into<PackError>> AtPath<T> for std::result::Result<T, E> {
+    fn at_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|e| match e.into() {
+            PackError::Io(source) => PackError::IoAt { path: path.as_ref().to_path_buf(), source },
+            e => e,
+        })
+    }
+}
+
+impl From<FormatError> for PackError {
+    fn from(e: FormatError) -> Self {
+        match e {
+            FormatError::BadMagic => PackError::BadMagic,
+            FormatError::Incompatible(version) => PackError::Incompatible(version),
+            FormatError::Truncated => PackError::Io(ErrorKind::UnexpectedEof.into()),
+            FormatError::InvalidEntry => PackError::InvalidEntry,
+            FormatError::CorruptIndex { offset } => PackError::CorruptIndex { offset },
+            FormatError::DamagedIndex => PackError::DamagedIndex,
+            FormatError::UnsupportedIndexField(tag) => PackError::UnsupportedIndexField(tag),
+            FormatError::Encrypted => PackError::Encrypted,
+            FormatError::FileNotFound(name) => PackError::FileNotFound(name.into()),
+            FormatError::ChecksumMismatch(name) => PackError::ChecksumMismatch(name.into()),
         }
     }
 }
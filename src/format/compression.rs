@@ -0,0 +1,64 @@
+use alloc::vec::Vec;
+use crate::format::layout::{U16Le, U64Le, COMPRESSION_FIELD};
+use crate::format::FormatError;
+
+/// How the contents of a file are stored, see [`BackPack::set_compression`](crate::BackPack::set_compression).
+/// Compressed files are decompressed when the backpack is opened, reading them is no different.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Compression {
+    /// stored as is
+    #[default]
+    None,
+    /// deflate, needs the `deflate` feature
+    Deflate,
+    /// zstandard, needs the `zstd` feature. The level only matters when compressing, it isn't stored.
+    Zstd { level: i32 },
+    /// lz4 frames, needs the `lz4` feature
+    Lz4,
+}
+
+impl Compression {
+    fn id(self) -> u16 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+            Compression::Zstd { .. } => 2,
+            Compression::Lz4 => 3,
+        }
+    }
+
+    fn from_id(id: u16) -> Option<Self> {
+        Some(match id {
+            0 => Compression::None,
+            1 => Compression::Deflate,
+            2 => Compression::Zstd { level: 0 },
+            3 => Compression::Lz4,
+            _ => return None,
+        })
+    }
+
+    /// Whether this build of the library can compress and decompress with this method.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Deflate => cfg!(feature = "deflate"),
+            Compression::Zstd { .. } => cfg!(feature = "zstd"),
+            Compression::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+}
+
+/// The value of a [`COMPRESSION_FIELD`]: the method as a `u16`, and the uncompressed length as a `u64`.
+pub(crate) fn encode_field(method: Compression, len: u64) -> Vec<u8> {
+    let mut res = U16Le::new(method.id()).to_bytes().to_vec();
+    res.extend_from_slice(&U64Le::new(len).to_bytes());
+    res
+}
+
+pub(crate) fn decode_field(value: &[u8]) -> Result<(Compression, u64), FormatError> {
+    let id = U16Le::from_slice(value).ok_or(FormatError::InvalidEntry)?.get();
+    let len = value.get(U16Le::SIZE..).and_then(U64Le::from_slice).ok_or(FormatError::InvalidEntry)?.get();
+    // a method from a newer version of the library
+    let method = Compression::from_id(id).ok_or(FormatError::UnsupportedIndexField(COMPRESSION_FIELD))?;
+    Ok((method, len))
+}
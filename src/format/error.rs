@@ -0,0 +1,45 @@
+use alloc::string::String;
+use core::fmt;
+use crate::format::PACK_VERSION;
+
+/// What can go wrong reading a backpack from bytes, without the standard library.
+/// With the `std` feature it converts into the matching [`PackError`](crate::PackError).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    BadMagic,
+    /// written in a format version this version of the library can't read
+    Incompatible(u16),
+    /// the bytes end before what the index says is there
+    Truncated,
+    InvalidEntry,
+    /// The table of contents can't be parsed, `offset` is where the broken block starts.
+    CorruptIndex {
+        offset: u64,
+    },
+    DamagedIndex,
+    /// a field from a newer version of the format which must be understood to read the backpack
+    UnsupportedIndexField(u16),
+    Encrypted,
+    FileNotFound(String),
+    ChecksumMismatch(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "backpack magic number, expected {:?}", crate::format::PACK_MAGIC),
+            FormatError::Incompatible(version) => write!(f, "backpack is written in format version {version}, which this version of the backpack library can't read (it reads version {PACK_VERSION})"),
+            FormatError::Truncated => write!(f, "the backpack ends before its index says it does"),
+            FormatError::InvalidEntry => write!(f, "invalid table of content entry in the backpack. this is a bug"),
+            FormatError::CorruptIndex { offset } => write!(f, "the index of the backpack is damaged in the table of contents block at offset {offset}"),
+            FormatError::DamagedIndex => write!(f, "the index of the backpack is damaged, and it has no intact copy"),
+            FormatError::UnsupportedIndexField(tag) => write!(f, "the index uses field {tag:#06x}, which this version of the backpack library doesn't understand"),
+            FormatError::Encrypted => write!(f, "the backpack is encrypted"),
+            FormatError::FileNotFound(name) => write!(f, "file {name:?} not present in backpack"),
+            FormatError::ChecksumMismatch(name) => write!(f, "contents of {name:?} don't match their checksum, the backpack is damaged"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FormatError {}
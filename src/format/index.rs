@@ -0,0 +1,150 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::format::compression::{self, Compression};
+use crate::format::crc32::crc32;
+use crate::format::layout::{decode_alignment, decode_encryption, IndexTrailer, PackHeader, TocBlockHeader, TocEntry, U16Le, U32Le, ALIGNMENT_FIELD, CHECKSUM_FIELD, COMPRESSION_FIELD, CRITICAL_FIELD, DIRECTORY, ENCRYPTION_FIELD, FLAGS_FIELD, HIDDEN};
+use crate::format::{names, FormatError, PACK_HEADER_SIZE, PACK_VERSION, TOC_SIZE};
+
+/// An entry of the table of contents, with its fields decoded.
+pub(crate) struct TocRecord {
+    /// the name as a key, see [`key_of_bytes`](names::key_of_bytes), with a trailing `/` for directories
+    pub name: String,
+    /// relative to the start of the data, see [`absolute_offset`]
+    pub offset: u64,
+    pub length: u64,
+    pub hidden: bool,
+    pub compression: Option<(Compression, u64)>,
+    pub checksum: Option<u32>,
+    pub encrypted: bool,
+    pub alignment: Option<u64>,
+}
+
+/// Parse the entries in a toc block, of which `filled` bytes are entries, passing them to `record`.
+pub(crate) fn parse_toc_block(filled: u16, block: &[u8], mut record: impl FnMut(TocRecord)) -> Result<(), FormatError> {
+    // a corrupt block may claim more than it holds
+    let filled = (filled as usize).min(block.len());
+
+    let mut curr: usize = 0;
+    while curr < filled {
+        let (entry, len) = TocEntry::parse(&block[curr..])?;
+        curr += len;
+
+        let mut res = TocRecord {
+            name: names::key_of_bytes(entry.name).into_owned(),
+            offset: entry.offset,
+            length: entry.length,
+            hidden: false,
+            compression: None,
+            checksum: None,
+            encrypted: false,
+            alignment: None,
+        };
+
+        for field in entry.fields() {
+            match field? {
+                (FLAGS_FIELD, value) => {
+                    let flags = U16Le::from_slice(value).ok_or(FormatError::InvalidEntry)?.get();
+                    if flags & DIRECTORY != 0 && !res.name.ends_with('/') {
+                        res.name.push('/');
+                    }
+                    res.hidden = flags & HIDDEN != 0;
+                }
+                (COMPRESSION_FIELD, value) => res.compression = Some(compression::decode_field(value)?),
+                (CHECKSUM_FIELD, value) => {
+                    res.checksum = Some(U32Le::from_slice(value).ok_or(FormatError::InvalidEntry)?.get());
+                }
+                (ENCRYPTION_FIELD, value) => {
+                    decode_encryption(value)?;
+                    res.encrypted = true;
+                }
+                (ALIGNMENT_FIELD, value) => res.alignment = Some(decode_alignment(value)?),
+                (tag, _) if tag & CRITICAL_FIELD != 0 => return Err(FormatError::UnsupportedIndexField(tag)),
+                // from a newer version of the format, optional ones can safely be ignored
+                _ => {}
+            }
+        }
+
+        record(res);
+    }
+
+    Ok(())
+}
+
+/// The offset in the backpack of data at `offset` in the data section, which has the toc blocks
+/// at `sorted_toc_blocks` in between.
+pub(crate) fn absolute_offset(sorted_toc_blocks: &[u64], mut offset: u64) -> u64 {
+    offset += PACK_HEADER_SIZE;
+
+    for i in sorted_toc_blocks {
+        if *i <= offset {
+            offset += TOC_SIZE as u64;
+        }
+    }
+
+    offset
+}
+
+/// The header and table of contents at the start of the backpack in `bytes`. When it ends with
+/// a trailer they're checked against it, falling back to the copy when they're damaged.
+pub(crate) fn protected_index(bytes: &[u8]) -> Result<&[u8], FormatError> {
+    let Some(trailer) = IndexTrailer::parse(bytes) else {
+        return Ok(bytes);
+    };
+
+    let region = |offset: u64, length: u64| {
+        let start = usize::try_from(offset).ok()?;
+        bytes.get(start..start.checked_add(usize::try_from(length).ok()?)?)
+    };
+    if let Some(index) = region(0, trailer.index_len.get()) {
+        if crc32(index) == trailer.index_crc.get() {
+            return Ok(index);
+        }
+    }
+    if trailer.copy_len.get() != 0 {
+        // the copy is byte for byte the same as the original,
+        // so the offsets in it are valid relative to its start
+        if let Some(copy) = region(trailer.copy_offset.get(), trailer.copy_len.get()) {
+            if crc32(copy) == trailer.copy_crc.get() {
+                return Ok(copy);
+            }
+        }
+    }
+
+    Err(FormatError::DamagedIndex)
+}
+
+/// Parse the header and the chain of toc blocks in `index`, passing the entries to `record`.
+/// Returns where the blocks are, in chain order.
+pub(crate) fn parse_index(index: &[u8], mut record: impl FnMut(TocRecord)) -> Result<Vec<u64>, FormatError> {
+    let header = PackHeader::parse(index)?;
+    if header.version() != PACK_VERSION {
+        return Err(FormatError::Incompatible(header.version()));
+    }
+
+    let mut toc_blocks = Vec::new();
+    let mut next_toc_offset = header.first_toc.get();
+    while next_toc_offset != 0 {
+        let offset = next_toc_offset;
+        // a damaged pointer could send us around in circles
+        if toc_blocks.contains(&offset) {
+            return Err(FormatError::CorruptIndex { offset });
+        }
+        toc_blocks.push(offset);
+
+        let block = usize::try_from(offset).ok()
+            .and_then(|start| index.get(start..start.checked_add(TOC_SIZE as usize)?))
+            .ok_or(FormatError::Truncated)?;
+        let (header, entries) = block.split_at(TocBlockHeader::SIZE);
+        let header = TocBlockHeader::from_bytes(header.try_into().expect("split at the size"));
+        next_toc_offset = header.next.get();
+
+        header.entries_len()
+            .and_then(|filled| parse_toc_block(filled, entries, &mut record))
+            .map_err(|e| match e {
+                FormatError::InvalidEntry => FormatError::CorruptIndex { offset },
+                e => e,
+            })?;
+    }
+
+    Ok(toc_blocks)
+}
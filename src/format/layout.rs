@@ -0,0 +1,592 @@
+//! The on-disk structures of a backpack.
+//!
+//! Every integer in a backpack is stored little-endian with a fixed width, whatever the
+//! platform writing or reading it. The fields below are plain byte arrays, so a structure
+//! has the same size, alignment and byte order everywhere and can't be misread by a
+//! big-endian host.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+use crate::format::crc32::crc32;
+use crate::format::{FormatError, PACK_HEADER_SIZE, PACK_MAGIC, PACK_VERSION};
+
+type Result<T> = core::result::Result<T, FormatError>;
+
+macro_rules! le_int {
+    ($name: ident, $int: ty, $size: literal) => {
+        #[doc = concat!("A `", stringify!($int), "` stored as ", stringify!($size), " little-endian bytes.")]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        #[repr(transparent)]
+        pub(crate) struct $name([u8; $size]);
+
+        impl $name {
+            pub(crate) const SIZE: usize = $size;
+
+            pub(crate) const fn new(v: $int) -> Self {
+                Self(v.to_le_bytes())
+            }
+
+            pub(crate) const fn get(self) -> $int {
+                <$int>::from_le_bytes(self.0)
+            }
+
+            pub(crate) const fn to_bytes(self) -> [u8; $size] {
+                self.0
+            }
+
+            /// Reads the value from the start of `bytes`, or `None` if there aren't enough bytes.
+            pub(crate) fn from_slice(bytes: &[u8]) -> Option<Self> {
+                bytes.get(..$size)?.try_into().ok().map(Self)
+            }
+        }
+    };
+}
+
+le_int!(U16Le, u16, 2);
+le_int!(U32Le, u32, 4);
+le_int!(U64Le, u64, 8);
+
+/// Set in the version of the header when entries in the table of contents have fields.
+/// Readers from before fields existed don't know that version, and refuse the backpack
+/// instead of misreading the entries.
+const FIELDS_VERSION: u16 = 1 << 15;
+
+/// The header at the very start of every backpack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PackHeader {
+    pub magic: [u8; 8],
+    pub version: U16Le,
+    /// the size of the data section
+    pub size: U64Le,
+    /// absolute offset of the first toc block, 0 if there is none
+    pub first_toc: U64Le,
+}
+
+impl PackHeader {
+    pub(crate) const SIZE: usize = 8 + U16Le::SIZE + U64Le::SIZE + U64Le::SIZE;
+
+    pub(crate) fn new(size: u64, first_toc: u64) -> Self {
+        let mut magic = [0; 8];
+        magic.copy_from_slice(PACK_MAGIC);
+
+        Self {
+            magic,
+            version: U16Le::new(PACK_VERSION),
+            size: U64Le::new(size),
+            first_toc: U64Le::new(first_toc),
+        }
+    }
+
+    /// The header for the table of contents in `toc_blocks`, which marks whether its entries have fields.
+    pub(crate) fn for_toc(mut self, toc_blocks: &[impl AsRef<[u8]>]) -> Self {
+        let version = self.version();
+        let fields = toc_blocks.iter().any(|block| has_fields(block.as_ref()));
+        self.version = U16Le::new(if fields { version | FIELDS_VERSION } else { version });
+        self
+    }
+
+    /// The version of the format, whether entries have fields or not.
+    pub(crate) fn version(self) -> u16 {
+        self.version.get() & !FIELDS_VERSION
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut res = [0; Self::SIZE];
+        res[0..8].copy_from_slice(&self.magic);
+        res[8..10].copy_from_slice(&self.version.to_bytes());
+        res[10..18].copy_from_slice(&self.size.to_bytes());
+        res[18..26].copy_from_slice(&self.first_toc.to_bytes());
+        res
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let mut magic = [0; 8];
+        magic.copy_from_slice(&bytes[0..8]);
+
+        Self {
+            magic,
+            version: U16Le(bytes[8..10].try_into().unwrap()),
+            size: U64Le(bytes[10..18].try_into().unwrap()),
+            first_toc: U64Le(bytes[18..26].try_into().unwrap()),
+        }
+    }
+
+    /// Parses the header at the start of `bytes` and checks its magic. The version is left
+    /// to the caller, who may know how to read older packs.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes.get(..Self::SIZE).ok_or(FormatError::Truncated)?;
+        let res = Self::from_bytes(bytes.try_into().expect("sliced to the size"));
+        if res.magic != PACK_MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+
+        Ok(res)
+    }
+
+    /// Like [`parse`](Self::parse), reading the header from `r`.
+    #[cfg(feature = "std")]
+    pub(crate) fn read_from(r: &mut impl Read) -> crate::error::Result<Self> {
+        let mut bytes = [0; Self::SIZE];
+        r.read_exact(&mut bytes)?;
+        Ok(Self::parse(&bytes)?)
+    }
+}
+
+/// The header at the start of every toc block.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct TocBlockHeader {
+    /// how many bytes of the block are used, including this header
+    pub filled: U16Le,
+    /// absolute offset of the next toc block, 0 if this is the last one
+    pub next: U64Le,
+}
+
+impl TocBlockHeader {
+    pub(crate) const SIZE: usize = U16Le::SIZE + U64Le::SIZE;
+
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut res = [0; Self::SIZE];
+        res[0..2].copy_from_slice(&self.filled.to_bytes());
+        res[2..10].copy_from_slice(&self.next.to_bytes());
+        res
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            filled: U16Le(bytes[0..2].try_into().unwrap()),
+            next: U64Le(bytes[2..10].try_into().unwrap()),
+        }
+    }
+
+    /// The number of bytes of entries in the block.
+    pub(crate) fn entries_len(self) -> Result<u16> {
+        self.filled.get().checked_sub(Self::SIZE as u16).ok_or(FormatError::InvalidEntry)
+    }
+}
+
+/// Set in the name length of a toc entry when fields follow the entry.
+/// An entry always fits in a toc block, so a name can never be long enough to set it by itself.
+const HAS_FIELDS: u16 = 1 << 15;
+/// Set in the tag of a field which readers must understand to read the backpack correctly.
+/// Fields without it are optional, and skipped by readers which don't know them.
+pub(crate) const CRITICAL_FIELD: u16 = 1 << 15;
+
+/// Field holding flags of an entry as a `u16`. Readers ignore flags they don't know.
+pub(crate) const FLAGS_FIELD: u16 = 1;
+/// The entry is left out of listings, see [`BackPack::set_hidden`](crate::BackPack::set_hidden).
+pub(crate) const HIDDEN: u16 = 1;
+/// The entry is a directory, named with a trailing `/` and without contents.
+/// Only needed for directories which would otherwise not exist because they're empty.
+pub(crate) const DIRECTORY: u16 = 2;
+/// Field holding which data file of a split backpack holds the entry, as a `u16`.
+/// The offset of the entry is then relative to the start of that data file,
+/// see [`BackPack::export_split`](crate::BackPack::export_split).
+pub(crate) const DATA_FILE_FIELD: u16 = CRITICAL_FIELD | 2;
+/// Field holding the crc32 of the contents of the entry as a `u32`, as they're stored,
+/// so of the compressed contents for compressed entries.
+pub(crate) const CHECKSUM_FIELD: u16 = 3;
+/// Field holding how the contents of the entry are compressed, see [`Compression`](crate::pack::Compression).
+/// The offset and length of the entry are those of the compressed contents.
+pub(crate) const COMPRESSION_FIELD: u16 = CRITICAL_FIELD | 4;
+/// Field holding how the contents of the entry are encrypted as a `u16`, see
+/// [`BackPack::set_encryption`](crate::BackPack::set_encryption). Encrypted contents are
+/// a nonce, the ciphertext and a tag. Encryption happens after compression.
+pub(crate) const ENCRYPTION_FIELD: u16 = CRITICAL_FIELD | 5;
+/// Field holding the alignment the contents of the entry start at, relative to the start of the
+/// backpack, as the base 2 logarithm in a `u8`. Only written for entries with contents and an
+/// alignment over 1, see [`BackPack::set_alignment`](crate::BackPack::set_alignment).
+pub(crate) const ALIGNMENT_FIELD: u16 = 6;
+
+/// An entry in a toc block: the length of the name, the name, and where the data is.
+///
+/// It may be followed by fields added in later versions of the format: the total length
+/// of the fields as a `u16`, then per field a `u16` tag, a `u16` length and the value.
+/// Because every field carries its length, readers skip the fields they don't know.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TocEntry<'a> {
+    pub name: &'a [u8],
+    /// relative to the start of the data
+    pub offset: u64,
+    pub length: u64,
+    /// the encoded fields, see [`encode_fields`]
+    pub fields: &'a [u8],
+}
+
+/// Encode fields for a [`TocEntry`].
+pub(crate) fn encode_fields(fields: &[(u16, &[u8])]) -> Result<Vec<u8>> {
+    let mut res = Vec::new();
+    for (tag, value) in fields {
+        let len: u16 = value.len().try_into().map_err(|_| FormatError::InvalidEntry)?;
+        res.extend_from_slice(&U16Le::new(*tag).to_bytes());
+        res.extend_from_slice(&U16Le::new(len).to_bytes());
+        res.extend_from_slice(value);
+    }
+    Ok(res)
+}
+
+/// The method of an [`ENCRYPTION_FIELD`] and the [encryption entry](crate::pack::ENCRYPTION_ENTRY).
+pub(crate) const XCHACHA20_POLY1305: u16 = 1;
+
+/// Check the method of an [`ENCRYPTION_FIELD`], which starts with it as a `u16`.
+pub(crate) fn decode_encryption(value: &[u8]) -> Result<()> {
+    match U16Le::from_slice(value).ok_or(FormatError::InvalidEntry)?.get() {
+        XCHACHA20_POLY1305 => Ok(()),
+        // a method from a newer version of the library
+        _ => Err(FormatError::UnsupportedIndexField(ENCRYPTION_FIELD)),
+    }
+}
+
+/// The value of an [`ALIGNMENT_FIELD`].
+pub(crate) fn decode_alignment(value: &[u8]) -> Result<u64> {
+    match value {
+        [log] if *log < 64 => Ok(1 << log),
+        _ => Err(FormatError::InvalidEntry),
+    }
+}
+
+impl<'a> TocEntry<'a> {
+    pub(crate) fn encoded_len(&self) -> usize {
+        let fields = if self.fields.is_empty() { 0 } else { U16Le::SIZE + self.fields.len() };
+        U16Le::SIZE + self.name.len() + U64Le::SIZE + U64Le::SIZE + fields
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn write_to(&self, w: &mut impl Write) -> crate::error::Result<()> {
+        let name_len: u16 = self.name.len().try_into().map_err(|_| FormatError::InvalidEntry)?;
+        if name_len & HAS_FIELDS != 0 {
+            return Err(FormatError::InvalidEntry.into());
+        }
+        let flags = if self.fields.is_empty() { 0 } else { HAS_FIELDS };
+
+        w.write_all(&U16Le::new(name_len | flags).to_bytes())?;
+        w.write_all(self.name)?;
+        w.write_all(&U64Le::new(self.offset).to_bytes())?;
+        w.write_all(&U64Le::new(self.length).to_bytes())?;
+
+        if !self.fields.is_empty() {
+            let fields_len: u16 = self.fields.len().try_into().map_err(|_| FormatError::InvalidEntry)?;
+            w.write_all(&U16Le::new(fields_len).to_bytes())?;
+            w.write_all(self.fields)?;
+        }
+        Ok(())
+    }
+
+    /// The fields of this entry as tags and values.
+    pub(crate) fn fields(&self) -> impl Iterator<Item=Result<(u16, &'a [u8])>> {
+        let fields = self.fields;
+        let mut curr = 0;
+
+        core::iter::from_fn(move || {
+            if curr >= fields.len() {
+                return None;
+            }
+
+            let field = (|| {
+                let tag = U16Le::from_slice(&fields[curr..]).ok_or(FormatError::InvalidEntry)?.get();
+                let len = U16Le::from_slice(&fields[curr + U16Le::SIZE..]).ok_or(FormatError::InvalidEntry)?.get() as usize;
+                let start = curr + 2 * U16Le::SIZE;
+                let value = fields.get(start..start + len).ok_or(FormatError::InvalidEntry)?;
+                curr = start + len;
+                Ok((tag, value))
+            })();
+
+            if field.is_err() {
+                // don't keep returning the same error
+                curr = fields.len();
+            }
+            Some(field)
+        })
+    }
+
+    /// Parses the entry at the start of `bytes`, returns it and how many bytes it took.
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<(Self, usize)> {
+        let name_len = U16Le::from_slice(bytes).ok_or(FormatError::InvalidEntry)?.get();
+        let has_fields = name_len & HAS_FIELDS != 0;
+        let name_len = (name_len & !HAS_FIELDS) as usize;
+        let mut curr = U16Le::SIZE;
+
+        let name = bytes.get(curr..curr + name_len).ok_or(FormatError::InvalidEntry)?;
+        curr += name_len;
+
+        let offset = U64Le::from_slice(&bytes[curr..]).ok_or(FormatError::InvalidEntry)?.get();
+        curr += U64Le::SIZE;
+
+        let length = U64Le::from_slice(&bytes[curr..]).ok_or(FormatError::InvalidEntry)?.get();
+        curr += U64Le::SIZE;
+
+        let mut fields: &[u8] = &[];
+        if has_fields {
+            let fields_len = U16Le::from_slice(&bytes[curr..]).ok_or(FormatError::InvalidEntry)?.get() as usize;
+            curr += U16Le::SIZE;
+
+            fields = bytes.get(curr..curr + fields_len).ok_or(FormatError::InvalidEntry)?;
+            curr += fields_len;
+        }
+
+        Ok((Self { name, offset, length, fields }, curr))
+    }
+}
+
+/// Whether any entry in the toc block `block` has fields.
+fn has_fields(block: &[u8]) -> bool {
+    let Some(header) = block.get(..TocBlockHeader::SIZE) else {
+        return false;
+    };
+    let header = TocBlockHeader::from_bytes(header.try_into().expect("sliced to the size"));
+    let filled = (header.filled.get() as usize).min(block.len());
+
+    let mut curr = TocBlockHeader::SIZE;
+    while curr < filled {
+        match TocEntry::parse(&block[curr..]) {
+            Ok((entry, _)) if !entry.fields.is_empty() => return true,
+            Ok((_, len)) => curr += len,
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// Marks the end of a backpack with a protected index.
+pub(crate) const TRAILER_MAGIC: &[u8; 8] = b"BPINDEX\0";
+
+/// The trailer after the data of a backpack with a protected index,
+/// see [`IndexProtection`](crate::pack::IndexProtection). It's stored with a crc32 of the
+/// fields before the magic, so a backpack which happens to end in the magic isn't mistaken for one.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct IndexTrailer {
+    pub index_len: U64Le,
+    pub index_crc: U32Le,
+    /// absolute offset of the copy of the index, 0 if there is none
+    pub copy_offset: U64Le,
+    pub copy_len: U64Le,
+    pub copy_crc: U32Le,
+    pub magic: [u8; 8],
+}
+
+impl IndexTrailer {
+    pub(crate) const SIZE: usize = U64Le::SIZE + U32Le::SIZE + U64Le::SIZE + U64Le::SIZE + U32Le::SIZE + U32Le::SIZE + 8;
+
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut res = [0; Self::SIZE];
+        res[0..8].copy_from_slice(&self.index_len.to_bytes());
+        res[8..12].copy_from_slice(&self.index_crc.to_bytes());
+        res[12..20].copy_from_slice(&self.copy_offset.to_bytes());
+        res[20..28].copy_from_slice(&self.copy_len.to_bytes());
+        res[28..32].copy_from_slice(&self.copy_crc.to_bytes());
+        let crc = crc32(&res[0..32]);
+        res[32..36].copy_from_slice(&U32Le::new(crc).to_bytes());
+        res[36..44].copy_from_slice(&self.magic);
+        res
+    }
+
+    /// The trailer at the end of `bytes`, if they end with the magic and a trailer which matches its checksum.
+    pub(crate) fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = &bytes[bytes.len().checked_sub(Self::SIZE)?..];
+        if !bytes.ends_with(TRAILER_MAGIC) || crc32(&bytes[0..32]) != U32Le::from_slice(&bytes[32..])?.get() {
+            return None;
+        }
+
+        Some(Self {
+            index_len: U64Le::from_slice(&bytes[0..])?,
+            index_crc: U32Le::from_slice(&bytes[8..])?,
+            copy_offset: U64Le::from_slice(&bytes[12..])?,
+            copy_len: U64Le::from_slice(&bytes[20..])?,
+            copy_crc: U32Le::from_slice(&bytes[28..])?,
+            magic: bytes[36..44].try_into().ok()?,
+        })
+    }
+}
+
+pub(crate) const COMMIT_MAGIC: &[u8; 8] = b"BPCOMMIT";
+
+/// Written right after the table of contents of a generation [committed](crate::pack::PackWriter::commit)
+/// by a writer, before the header points to it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CommitTrailer {
+    /// the header of the generation
+    pub size: U64Le,
+    pub first_toc: U64Le,
+    /// of the toc blocks, in the order of the chain
+    pub toc_crc: U32Le,
+    pub magic: [u8; 8],
+}
+
+impl CommitTrailer {
+    pub(crate) const SIZE: usize = U64Le::SIZE + U64Le::SIZE + U32Le::SIZE + 8;
+
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut res = [0; Self::SIZE];
+        res[0..8].copy_from_slice(&self.size.to_bytes());
+        res[8..16].copy_from_slice(&self.first_toc.to_bytes());
+        res[16..20].copy_from_slice(&self.toc_crc.to_bytes());
+        res[20..28].copy_from_slice(&self.magic);
+        res
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            size: U64Le::from_slice(&bytes[0..]).unwrap(),
+            first_toc: U64Le::from_slice(&bytes[8..]).unwrap(),
+            toc_crc: U32Le::from_slice(&bytes[16..]).unwrap(),
+            magic: bytes[20..28].try_into().unwrap(),
+        }
+    }
+}
+
+const _: () = assert!(PackHeader::SIZE as u64 == PACK_HEADER_SIZE);
+
+#[cfg(test)]
+mod tests {
+    use crate::format::layout::{encode_fields, CommitTrailer, IndexTrailer, COMMIT_MAGIC, PackHeader, TocBlockHeader, TocEntry, U16Le, U32Le, U64Le};
+    use crate::format::PACK_VERSION;
+
+    // These compare against literal bytes rather than `to_le_bytes`, so they only pass
+    // when the encoding is right regardless of the host's byte order.
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(U16Le::new(0x0102).to_bytes(), [0x02, 0x01]);
+        assert_eq!(U32Le::new(0x0102_0304).to_bytes(), [0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(U64Le::new(0x0102_0304_0506_0708).to_bytes(), [8, 7, 6, 5, 4, 3, 2, 1]);
+
+        assert_eq!(U16Le::from_slice(&[0x02, 0x01, 0xff]).unwrap().get(), 0x0102);
+        assert_eq!(U64Le::from_slice(&[8, 7, 6, 5, 4, 3, 2, 1]).unwrap().get(), 0x0102_0304_0506_0708);
+        assert!(U64Le::from_slice(&[1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn test_pack_header() {
+        let header = PackHeader::new(0x0102, 26);
+        let bytes = header.to_bytes();
+
+        let [v0, v1] = PACK_VERSION.to_le_bytes();
+        assert_eq!(bytes, [
+            b'B', b'A', b'C', b'K', b'P', b'A', b'C', b'K',
+            v0, v1,
+            0x02, 0x01, 0, 0, 0, 0, 0, 0,
+            26, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        assert_eq!(PackHeader::from_bytes(&bytes), header);
+        assert_eq!(PackHeader::read_from(&mut &bytes[..]).unwrap(), header);
+
+        let mut bad = bytes;
+        bad[0] = b'b';
+        assert!(PackHeader::read_from(&mut &bad[..]).is_err());
+    }
+
+    #[test]
+    fn test_toc_block_header() {
+        let header = TocBlockHeader {
+            filled: U16Le::new(0x0a01),
+            next: U64Le::new(4122),
+        };
+        let bytes = header.to_bytes();
+
+        assert_eq!(bytes, [0x01, 0x0a, 0x1a, 0x10, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(TocBlockHeader::from_bytes(&bytes), header);
+        assert_eq!(header.entries_len().unwrap(), 0x0a01 - 10);
+
+        let short = TocBlockHeader { filled: U16Le::new(3), next: U64Le::new(0) };
+        assert!(short.entries_len().is_err());
+    }
+
+    #[test]
+    fn test_toc_entry() {
+        let entry = TocEntry { name: b"ab", offset: 0x0102, length: 3, fields: &[] };
+        let mut bytes = Vec::new();
+        entry.write_to(&mut bytes).unwrap();
+
+        assert_eq!(bytes, [
+            2, 0,
+            b'a', b'b',
+            0x02, 0x01, 0, 0, 0, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        assert_eq!(entry.encoded_len(), bytes.len());
+        assert_eq!(TocEntry::parse(&bytes).unwrap(), (entry, bytes.len()));
+        assert!(TocEntry::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_toc_entry_fields() {
+        let fields = encode_fields(&[(7, b"xy"), (8, b"")]).unwrap();
+        let entry = TocEntry { name: b"ab", offset: 1, length: 2, fields: &fields };
+        let mut bytes = Vec::new();
+        entry.write_to(&mut bytes).unwrap();
+
+        assert_eq!(bytes, [
+            2, 0x80,
+            b'a', b'b',
+            1, 0, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0, 0, 0, 0, 0,
+            10, 0,
+            7, 0, 2, 0, b'x', b'y',
+            8, 0, 0, 0,
+        ]);
+        assert_eq!(entry.encoded_len(), bytes.len());
+
+        // whatever follows the fields is the next entry
+        bytes.push(0xff);
+        let (parsed, len) = TocEntry::parse(&bytes).unwrap();
+        assert_eq!((parsed, len), (entry, bytes.len() - 1));
+        let parsed_fields = parsed.fields().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(parsed_fields, [(7, &b"xy"[..]), (8, &b""[..])]);
+
+        // a field claiming more than there is
+        let broken = [7, 0, 5, 0, b'x'];
+        let entry = TocEntry { name: b"", offset: 0, length: 0, fields: &broken };
+        let mut fields = entry.fields();
+        assert!(fields.next().unwrap().is_err());
+        assert!(fields.next().is_none());
+    }
+
+    #[test]
+    fn test_index_trailer() {
+        let trailer = IndexTrailer {
+            index_len: U64Le::new(0x0102),
+            index_crc: U32Le::new(0x0a0b_0c0d),
+            copy_offset: U64Le::new(3),
+            copy_len: U64Le::new(4),
+            copy_crc: U32Le::new(5),
+            magic: *b"BPINDEX\0",
+        };
+        let bytes = trailer.to_bytes();
+
+        assert_eq!(bytes, [
+            0x02, 0x01, 0, 0, 0, 0, 0, 0,
+            0x0d, 0x0c, 0x0b, 0x0a,
+            3, 0, 0, 0, 0, 0, 0, 0,
+            4, 0, 0, 0, 0, 0, 0, 0,
+            5, 0, 0, 0,
+            0xd6, 0x90, 0x9f, 0xcf,
+            b'B', b'P', b'I', b'N', b'D', b'E', b'X', 0,
+        ]);
+        assert_eq!(IndexTrailer::parse(&bytes), Some(trailer));
+
+        // only the magic isn't enough
+        let mut damaged = bytes;
+        damaged[0] ^= 1;
+        assert_eq!(IndexTrailer::parse(&damaged), None);
+        assert_eq!(IndexTrailer::parse(b"BPINDEX\0"), None);
+    }
+
+    #[test]
+    fn test_commit_trailer() {
+        let trailer = CommitTrailer {
+            size: U64Le::new(0x0102),
+            first_toc: U64Le::new(3),
+            toc_crc: U32Le::new(0x0a0b_0c0d),
+            magic: *COMMIT_MAGIC,
+        };
+        let bytes = trailer.to_bytes();
+
+        assert_eq!(bytes, [
+            0x02, 0x01, 0, 0, 0, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0,
+            0x0d, 0x0c, 0x0b, 0x0a,
+            b'B', b'P', b'C', b'O', b'M', b'M', b'I', b'T',
+        ]);
+        assert_eq!(CommitTrailer::from_bytes(&bytes), trailer);
+    }
+}
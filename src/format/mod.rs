@@ -0,0 +1,83 @@
+// what's needed for writing backpacks is only used by `pack`, with the `std` feature
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) mod layout;
+pub(crate) mod crc32;
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) mod compression;
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) mod names;
+pub(crate) mod index;
+mod error;
+mod slice_reader;
+
+pub use compression::Compression;
+pub use crc32::{crc32, Crc32};
+pub use error::FormatError;
+pub use slice_reader::SliceReader;
+
+pub const fn parse_int(s: &'static [u8]) -> u16 {
+    match s {
+        [] => 0,
+        [rest@.., h] => {
+            let rest_int = parse_int(rest);
+
+            let val = match h {
+                b'0' => 0,
+                b'1' => 1,
+                b'2' => 2,
+                b'3' => 3,
+                b'4' => 4,
+                b'5' => 5,
+                b'6' => 6,
+                b'7' => 7,
+                b'8' => 8,
+                b'9' => 9,
+                _ => panic!("couldn't parse to integer; unknown digit in string"),
+            };
+
+            rest_int * 10 + val
+        }
+    }
+}
+
+pub const PACK_MAGIC: &[u8] = b"BACKPACK";
+pub const PACK_VERSION: u16 = parse_int(env!("CARGO_PKG_VERSION_MAJOR").as_bytes());
+pub const TOC_SIZE: u16 = 4096;
+pub const PACK_HEADER_SIZE: u64 = 26;
+
+/// Entry holding the expiry times of files, as lines of `{unix seconds} {name}`.
+/// It's read when opening and written when flushing, and not visible as a file.
+pub const EXPIRY_ENTRY: &str = ".backpack/expiry";
+
+/// Entry holding the [modification times](crate::BackPack::set_modified) of files, in the same way
+/// as [`EXPIRY_ENTRY`]. Like it, it's not visible as a file.
+pub const MODIFIED_ENTRY: &str = ".backpack/modified";
+
+/// Entry holding the [aliases](crate::BackPack::set_alias) of files, as pairs of
+/// `{alias}\0{target}\0`. Like [`EXPIRY_ENTRY`] it's not visible as a file.
+pub const ALIAS_ENTRY: &str = ".backpack/aliases";
+
+/// Entry holding the [attributes](crate::BackPack::set_attribute) of files, as records of
+/// `{name}\0{key}\0`, the length of the value as a little endian u32, and the value.
+/// Like [`EXPIRY_ENTRY`] it's not visible as a file.
+pub const ATTRIBUTES_ENTRY: &str = ".backpack/attributes";
+
+/// Entry holding what's needed to derive the key of an [encrypted](crate::BackPack::set_encryption)
+/// backpack from its passphrase. Like [`EXPIRY_ENTRY`] it's not visible as a file, and it's
+/// never encrypted itself.
+pub const ENCRYPTION_ENTRY: &str = ".backpack/encryption";
+
+/// Entry holding the [signature](crate::BackPack::set_signing) of a backpack: the Ed25519 public key
+/// which made it, followed by the signature. Like [`EXPIRY_ENTRY`] it's not visible as a file.
+pub const SIGNATURE_ENTRY: &str = ".backpack/signature";
+
+/// Entry holding the [tiers](crate::BackPack::set_tier) of files, as lines of `{hot|cold} {name}`.
+/// Like [`EXPIRY_ENTRY`] it's not visible as a file.
+pub const TIERS_ENTRY: &str = ".backpack/tiers";
+
+/// Names starting with this are reserved for the backpack itself, like the [`METADATA_ENTRIES`].
+/// Files can't be added under them.
+pub const RESERVED_PREFIX: &str = ".backpack/";
+
+/// All entries holding metadata of the backpack instead of a file, none of them is a file.
+pub const METADATA_ENTRIES: &[&str] = &[EXPIRY_ENTRY, MODIFIED_ENTRY, ALIAS_ENTRY, ATTRIBUTES_ENTRY, ENCRYPTION_ENTRY, SIGNATURE_ENTRY, TIERS_ENTRY];
@@ -0,0 +1,40 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The characters bytes which aren't UTF-8 stand for in the names of entries, from
+/// U+10FF80 for byte 0x80 to U+10FFFF for byte 0xFF. Private use, so real names don't have them.
+pub(crate) const ESCAPES: u32 = 0x10FF00;
+
+/// `bytes` as a string, with bytes which aren't UTF-8 written as [escapes](ESCAPES).
+pub(crate) fn key_of_bytes(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(key) = core::str::from_utf8(bytes) {
+        return Cow::Borrowed(key);
+    }
+
+    let mut res = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        res.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            res.push(char::from_u32(ESCAPES + *byte as u32).expect("escapes are characters"));
+        }
+    }
+    Cow::Owned(res)
+}
+
+/// The bytes of the name `key` stands for, the reverse of [`key_of_bytes`].
+pub(crate) fn key_bytes(key: &str) -> Cow<'_, [u8]> {
+    let escaped = |c: char| (ESCAPES + 0x80..=ESCAPES + 0xFF).contains(&(c as u32));
+    if !key.chars().any(escaped) {
+        return Cow::Borrowed(key.as_bytes());
+    }
+
+    let mut res = Vec::with_capacity(key.len());
+    for c in key.chars() {
+        match escaped(c) {
+            true => res.push((c as u32 - ESCAPES) as u8),
+            false => res.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Cow::Owned(res)
+}
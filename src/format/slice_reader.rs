@@ -0,0 +1,160 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::format::compression::Compression;
+use crate::format::crc32::crc32;
+use crate::format::index::{absolute_offset, parse_index, protected_index};
+use crate::format::layout::COMPRESSION_FIELD;
+use crate::format::{FormatError, ALIAS_ENTRY, METADATA_ENTRIES};
+
+type Stored<'a> = (&'a [u8], Option<(Compression, u64)>);
+
+/// Reads a backpack from bytes which are in memory already, like a backpack embedded with
+/// `include_bytes!` or in flash, without copying them. Only the index is parsed into memory,
+/// the contents of entries are borrowed from the bytes. Read-only, and encrypted backpacks
+/// can't be read.
+///
+/// Needs only `alloc`, so it works without the `std` feature. Decompressing entries needs it
+/// though, see [`read`](Self::read).
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    /// absolute offset and length of every entry
+    entries: BTreeMap<String, (u64, u64)>,
+    hidden: BTreeSet<String>,
+    aliases: BTreeMap<String, String>,
+    compressed: BTreeMap<String, (Compression, u64)>,
+    checksums: BTreeMap<String, u32>,
+    alignments: BTreeMap<String, u64>,
+    verify: bool,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn open(bytes: &'a [u8]) -> Result<Self, FormatError> {
+        let mut res = Self {
+            bytes,
+            entries: BTreeMap::new(),
+            hidden: BTreeSet::new(),
+            aliases: BTreeMap::new(),
+            compressed: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            alignments: BTreeMap::new(),
+            verify: false,
+        };
+
+        let mut encrypted = false;
+        let mut toc_blocks = parse_index(protected_index(bytes)?, |record| {
+            encrypted |= record.encrypted;
+            if record.hidden {
+                res.hidden.insert(record.name.clone());
+            }
+            if let Some(compression@(method, _)) = record.compression {
+                // entries stored as is because compressing didn't save enough
+                if method != Compression::None {
+                    res.compressed.insert(record.name.clone(), compression);
+                }
+            }
+            if let Some(checksum) = record.checksum {
+                res.checksums.insert(record.name.clone(), checksum);
+            }
+            if let Some(alignment) = record.alignment {
+                res.alignments.insert(record.name.clone(), alignment);
+            }
+            res.entries.insert(record.name, (record.offset, record.length));
+        })?;
+        if encrypted {
+            return Err(FormatError::Encrypted);
+        }
+        toc_blocks.sort();
+        for (offset, _) in res.entries.values_mut() {
+            *offset = absolute_offset(&toc_blocks, *offset);
+        }
+
+        if let Some(&key) = res.entries.get(ALIAS_ENTRY) {
+            res.aliases = decode_aliases(res.slice(ALIAS_ENTRY, key)?)?;
+        }
+        for special in METADATA_ENTRIES {
+            res.entries.remove(*special);
+        }
+        Ok(res)
+    }
+
+    /// Check entries against the checksum stored for them when they're read,
+    /// failing with [`FormatError::ChecksumMismatch`] when they were damaged.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    fn slice(&self, name: &str, (start, length): (u64, u64)) -> Result<&'a [u8], FormatError> {
+        let bytes: &'a [u8] = self.bytes;
+        let contents = usize::try_from(start).ok()
+            .zip(usize::try_from(length).ok())
+            .and_then(|(start, length)| bytes.get(start..start.checked_add(length)?))
+            .ok_or(FormatError::Truncated)?;
+
+        if self.verify && self.checksums.get(name).is_some_and(|checksum| crc32(contents) != *checksum) {
+            return Err(FormatError::ChecksumMismatch(name.to_string()));
+        }
+        Ok(contents)
+    }
+
+    /// The stored name and location of the entry called `name`, or of the entry it's an alias of.
+    fn find(&self, name: &str) -> Result<(&str, (u64, u64)), FormatError> {
+        self.entries.get_key_value(name)
+            .or_else(|| self.entries.get_key_value(self.aliases.get(name)?))
+            .map(|(name, key)| (name.as_str(), *key))
+            .ok_or_else(|| FormatError::FileNotFound(name.to_string()))
+    }
+
+    /// The contents of the entry called `name`, or of the entry it's an [alias](crate::BackPack::set_alias) of,
+    /// borrowed from the bytes. Compressed entries can only be [read](Self::read).
+    pub fn get(&self, name: &str) -> Result<&'a [u8], FormatError> {
+        match self.stored(name)? {
+            (_, Some(_)) => Err(FormatError::UnsupportedIndexField(COMPRESSION_FIELD)),
+            (contents, None) => Ok(contents),
+        }
+    }
+
+    /// The contents of an entry as they're stored, with how they're compressed and their
+    /// uncompressed length.
+    pub(crate) fn stored(&self, name: &str) -> Result<Stored<'a>, FormatError> {
+        let (name, key) = self.find(name)?;
+        Ok((self.slice(name, key)?, self.compressed.get(name).copied()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_ok()
+    }
+
+    /// Names of all files, sorted, without [hidden](crate::BackPack::set_hidden) files and directories.
+    /// Bytes of names which aren't UTF-8 are written as private use characters, like in
+    /// [`BackPack::file_names`](crate::BackPack::file_names).
+    pub fn file_names(&self) -> Vec<&str> {
+        self.entries.keys()
+            .filter(|name| !name.ends_with('/') && !self.hidden.contains(*name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.find(name).is_ok_and(|(name, _)| self.hidden.contains(name))
+    }
+
+    /// The alignment the stored contents of `name` start at, relative to the start of the
+    /// bytes, as recorded by the writer. 1 when no alignment was recorded.
+    pub fn alignment(&self, name: &str) -> Result<u64, FormatError> {
+        let (name, _) = self.find(name)?;
+        Ok(self.alignments.get(name).copied().unwrap_or(1))
+    }
+}
+
+/// The contents of the [`ALIAS_ENTRY`], pairs of `{alias}\0{target}\0`.
+fn decode_aliases(data: &[u8]) -> Result<BTreeMap<String, String>, FormatError> {
+    let data = core::str::from_utf8(data).map_err(|_| FormatError::InvalidEntry)?;
+    let mut parts = data.split_terminator('\0');
+    let mut aliases = BTreeMap::new();
+    while let Some(alias) = parts.next() {
+        let target = parts.next().ok_or(FormatError::InvalidEntry)?;
+        aliases.insert(alias.to_string(), target.to_string());
+    }
+    Ok(aliases)
+}
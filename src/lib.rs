@@ -1,14 +1,75 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+
+/// The backpack format without the standard library: the on-disk structures, parsing the index,
+/// and reading backpacks which are in memory with [`SliceReader`](format::SliceReader).
+/// Everything else needs the `std` feature.
+pub mod format;
 
 /// Packing format
+#[cfg(feature = "std")]
 pub mod pack;
 
 /// Drop-in replacements for std::fs::*;
+#[cfg(feature = "std")]
 pub mod dropin;
+#[cfg(feature = "std")]
 mod error;
 
+/// Reading backpacks from remote storage
+#[cfg(feature = "std")]
+pub mod remote;
+
+/// Loading files from a backpack as Rust types
+#[cfg(feature = "std")]
+pub mod registry;
+
+/// Encoding Rust types as files in a backpack
+#[cfg(feature = "std")]
+pub mod asset;
+
+/// Versioned descriptions of what a backpack contains
+#[cfg(feature = "std")]
+pub mod manifest;
+
+/// Backpacks embedded in the executable at compile time
+#[cfg(feature = "embed")]
+pub mod embed;
+
+/// Generated backpacks for tests and benchmarks
+#[cfg(all(feature = "std", any(test, feature = "testing")))]
+pub mod testing;
+
+// lets code generated by backpack-derive refer to `::backpack` inside this crate too
+extern crate self as backpack;
+
+/// Sharing a loaded backpack between processes
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub mod shared;
+
+/// Self-extracting executables with a backpack appended
+#[cfg(feature = "std")]
+pub mod sfx;
+
+/// Finding names which break backpacks on some platforms, before packing
+#[cfg(feature = "std")]
+pub mod lint;
+
+/// Javascript bindings for reading backpacks from the browser.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// C bindings for loading backpacks from engines written in other languages.
+#[cfg(feature = "capi")]
+pub mod capi;
+
+/// Python bindings, built into the `backpack` package in `python/`.
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "std")]
 pub use dropin::File;
-pub use pack::BackPack;
-pub use pack::RawFile;
-pub use pack::InMemoryFile;
-pub use pack::PackError;
-pub use pack::Result;
+#[cfg(feature = "std")]
+pub use pack::{BackPack, FileMetadata, InMemoryFile, PackError, RawFile, Result};
+#[cfg(feature = "std")]
+pub use lint::lint_dir;
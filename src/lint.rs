@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::error;
+
+/// Longest path Windows handles without long path support.
+pub const MAX_PATH_LEN: usize = 260;
+/// Longest file or directory name most filesystems allow, in bytes.
+pub const MAX_COMPONENT_LEN: usize = 255;
+
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A name which would make a backpack break on some platforms, found by [`lint_dir`] or [`lint_names`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LintIssue {
+    /// the names only differ in case, so they are the same file on case insensitive filesystems
+    CaseCollision(String, String),
+    /// the names only differ in unicode normalization, so they are the same file on filesystems
+    /// which normalize names
+    NormalizationCollision(String, String),
+    /// the path is longer than [`MAX_PATH_LEN`], or one of its parts longer than [`MAX_COMPONENT_LEN`]
+    TooLong(String),
+    /// the name can't be created on some platforms
+    NonPortable { name: String, reason: &'static str },
+    /// the name isn't valid utf-8, so it can't be stored in a backpack as is
+    NotUtf8(PathBuf),
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintIssue::CaseCollision(a, b) => write!(f, "{:?} and {:?} only differ in case", a, b),
+            LintIssue::NormalizationCollision(a, b) => write!(f, "{:?} and {:?} only differ in unicode normalization", a, b),
+            LintIssue::TooLong(name) => write!(f, "{:?} is too long", name),
+            LintIssue::NonPortable { name, reason } => write!(f, "{:?} {}", name, reason),
+            LintIssue::NotUtf8(path) => write!(f, "{:?} is not valid utf-8", path),
+        }
+    }
+}
+
+/// Decompose the precomposed latin-1 letters, the ones which commonly end up
+/// in both forms, into a base letter and a combining mark.
+fn decompose(c: char) -> Option<(char, char)> {
+    const BASES: &[u8; 32] = b"AAAAAA CEEEEIIII NOOOOO  UUUUY  ";
+    const MARKS: [u32; 32] = [
+        0x300, 0x301, 0x302, 0x303, 0x308, 0x30a, 0, 0x327, 0x300, 0x301, 0x302, 0x308, 0x300, 0x301, 0x302, 0x308,
+        0, 0x303, 0x300, 0x301, 0x302, 0x303, 0x308, 0, 0, 0x300, 0x301, 0x302, 0x308, 0x301, 0, 0,
+    ];
+
+    let (index, lowercase) = match c {
+        'ÿ' => return Some(('y', '\u{308}')),
+        '\u{c0}'..='\u{df}' => (c as usize - 0xc0, false),
+        '\u{e0}'..='\u{ff}' => (c as usize - 0xe0, true),
+        _ => return None,
+    };
+    let base = BASES[index];
+    if base == b' ' {
+        return None;
+    }
+
+    let base = if lowercase { base.to_ascii_lowercase() } else { base } as char;
+    Some((base, char::from_u32(MARKS[index]).expect("marks are valid characters")))
+}
+
+/// `name` with all latin-1 letters decomposed. Full normalization needs the unicode
+/// tables, but this finds names which differ only in how an accented latin letter is written.
+fn decomposed(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    for c in name.chars() {
+        match decompose(c) {
+            Some((base, mark)) => {
+                res.push(base);
+                res.push(mark);
+            }
+            None => res.push(c),
+        }
+    }
+    res
+}
+
+fn lint_name(name: &str, issues: &mut Vec<LintIssue>) {
+    let non_portable = |reason| LintIssue::NonPortable { name: name.to_string(), reason };
+
+    if name.chars().count() > MAX_PATH_LEN || name.split('/').any(|part| part.len() > MAX_COMPONENT_LEN) {
+        issues.push(LintIssue::TooLong(name.to_string()));
+    }
+
+    for part in name.split('/') {
+        if part.contains(RESERVED_CHARS) {
+            issues.push(non_portable("contains a character which is reserved on windows"));
+        } else if part.contains(char::is_control) {
+            issues.push(non_portable("contains a control character"));
+        } else if part.ends_with(['.', ' ']) && part != "." && part != ".." {
+            issues.push(non_portable("ends in a dot or space, which windows removes"));
+        } else if RESERVED_NAMES.iter().any(|reserved| part.split('.').next().is_some_and(|stem| stem.eq_ignore_ascii_case(reserved))) {
+            issues.push(non_portable("is a device name on windows"));
+        } else {
+            continue;
+        }
+        // one issue per name is enough
+        break;
+    }
+}
+
+/// Check names of files about to be packed for names which would break the backpack on
+/// some platforms once extracted. Names use `/` as separator, like in a backpack.
+pub fn lint_names<'n>(names: impl IntoIterator<Item = &'n str>) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut by_normalized = HashMap::<String, &str>::new();
+    let mut by_case = HashMap::new();
+
+    for name in names {
+        lint_name(name, &mut issues);
+
+        let normalized = decomposed(name);
+        let folded = normalized.to_lowercase();
+        match by_normalized.get(&normalized) {
+            Some(&other) if other != name => {
+                issues.push(LintIssue::NormalizationCollision(other.to_string(), name.to_string()));
+                continue;
+            }
+            Some(_) => continue,
+            None => {
+                by_normalized.insert(normalized, name);
+            }
+        }
+        if let Some(other) = by_case.insert(folded, name) {
+            issues.push(LintIssue::CaseCollision(other.to_string(), name.to_string()));
+        }
+    }
+
+    issues
+}
+
+fn collect_names(dir: &Path, prefix: &str, names: &mut Vec<String>, issues: &mut Vec<LintIssue>) -> error::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            issues.push(LintIssue::NotUtf8(entry.path()));
+            continue;
+        };
+        let name = format!("{}{}", prefix, file_name);
+        if entry.file_type()?.is_dir() {
+            collect_names(&entry.path(), &format!("{}/", name), names, issues)?;
+        }
+        names.push(name);
+    }
+
+    Ok(())
+}
+
+/// Check all files and directories in `src` with [`lint_names`], before packing them.
+/// An empty result means the tree can be packed and extracted anywhere.
+pub fn lint_dir(src: impl AsRef<Path>) -> error::Result<Vec<LintIssue>> {
+    let mut names = Vec::new();
+    let mut issues = Vec::new();
+    collect_names(src.as_ref(), "", &mut names, &mut issues)?;
+
+    issues.extend(lint_names(names.iter().map(String::as_str)));
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::{lint_dir, lint_names, LintIssue};
+
+    #[test]
+    fn test_collisions() {
+        assert_eq!(lint_names(["readme.md", "README.md", "docs/a"]), vec![
+            LintIssue::CaseCollision("readme.md".to_string(), "README.md".to_string()),
+        ]);
+        assert_eq!(lint_names(["caf\u{e9}", "cafe\u{301}"]), vec![
+            LintIssue::NormalizationCollision("caf\u{e9}".to_string(), "cafe\u{301}".to_string()),
+        ]);
+        // the same name twice is not a collision
+        assert!(lint_names(["a", "a", "caf\u{e9}/x"]).is_empty());
+    }
+
+    #[test]
+    fn test_non_portable() {
+        let issues = lint_names(["a:b", "dir/con.txt", "trailing.", "ok/file.txt", &"x".repeat(300)]);
+        assert!(matches!(&issues[0], LintIssue::NonPortable { name, .. } if name == "a:b"));
+        assert!(matches!(&issues[1], LintIssue::NonPortable { name, .. } if name == "dir/con.txt"));
+        assert!(matches!(&issues[2], LintIssue::NonPortable { name, .. } if name == "trailing."));
+        assert!(matches!(&issues[3], LintIssue::TooLong(_)));
+        assert_eq!(issues.len(), 4);
+    }
+
+    #[test]
+    fn test_lint_dir() -> crate::Result<()> {
+        let dir = std::env::temp_dir().join("backpack_test_lint_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Assets"))?;
+        std::fs::write(dir.join("Assets/image.png"), "")?;
+        std::fs::write(dir.join("assets"), "")?;
+
+        let issues = lint_dir(&dir)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(issues, vec![LintIssue::CaseCollision("Assets".to_string(), "assets".to_string())]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Descriptions of the contents of a backpack, for tools outside this crate such as
+//! launchers and patch servers. With the `serde` feature they can be (de)serialized.
+//!
+//! The shape of these structs only changes in compatible ways within a schema version:
+//! fields are only ever added, always with a default so older manifests still parse,
+//! and unknown fields are ignored so older readers can parse newer manifests.
+//! That's why the structs are `#[non_exhaustive]`; construct them with their constructors.
+//! Removing, renaming or changing the meaning of a field increases [`MANIFEST_SCHEMA_VERSION`].
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::error;
+use crate::error::PackError;
+
+/// Version of the manifest schema written by this version of the library.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// Everything stored in a backpack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Manifest {
+    /// Schema version of this manifest, see the [module documentation](self).
+    pub schema_version: u32,
+    /// Version of the pack format the described backpack is stored in.
+    pub format_version: u16,
+    /// Sorted by name.
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A file stored in a backpack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    pub name: String,
+    /// Offset of the contents from the start of the backpack.
+    /// `None` for files added since the backpack was last flushed,
+    /// and for empty files, which don't take up space in the backpack.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub offset: Option<u64>,
+    pub length: u64,
+}
+
+impl Manifest {
+    pub fn new(format_version: u16, mut entries: Vec<ManifestEntry>) -> Self {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            format_version,
+            entries,
+        }
+    }
+
+    /// Check that this library understands the schema of a manifest read from elsewhere.
+    pub fn check_schema(&self) -> error::Result<()> {
+        if self.schema_version > MANIFEST_SCHEMA_VERSION {
+            return Err(PackError::UnsupportedManifest(self.schema_version));
+        }
+        Ok(())
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ManifestEntry> {
+        self.entries.binary_search_by(|e| e.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+}
+
+impl ManifestEntry {
+    pub fn new(name: impl Into<String>, offset: Option<u64>, length: u64) -> Self {
+        Self {
+            name: name.into(),
+            offset,
+            length,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+    use crate::pack::PACK_VERSION;
+    use crate::remote::RemoteBackPack;
+    use crate::{BackPack, InMemoryFile, RawFile};
+
+    #[test]
+    fn test_manifest() -> Result<()> {
+        let mut bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.add_file(InMemoryFile::from("bbb").with_name("b"))?;
+        bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+        bp.flush()?;
+        bp.add_file(InMemoryFile::from("cc").with_name("c"))?;
+
+        let manifest = bp.manifest();
+        assert_eq!(manifest.format_version, PACK_VERSION);
+        assert_eq!(manifest.entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(manifest.entry("c").unwrap().offset, None);
+        manifest.check_schema()?;
+
+        // closing writes c as well
+        let remote = RemoteBackPack::open(bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec())?;
+        let remote_manifest = remote.manifest();
+        assert!(remote_manifest.entry("c").unwrap().offset.is_some());
+        assert_eq!(remote_manifest.entry("b"), manifest.entry("b"));
+        let b = remote_manifest.entry("b").unwrap();
+        assert_eq!(remote.read_file("b")?.len() as u64, b.length);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_manifest_json() -> Result<()> {
+        use crate::manifest::{Manifest, ManifestEntry};
+
+        let manifest = Manifest::new(PACK_VERSION, vec![ManifestEntry::new("a", Some(4122), 3)]);
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+
+        // newer writers may add fields, older ones may not have written optional ones
+        let json = r#"{"schema_version":1,"format_version":0,"entries":[{"name":"a","length":3,"hash":"..."}],"signed":true}"#;
+        let parsed = serde_json::from_str::<Manifest>(json).unwrap();
+        assert_eq!(parsed.entries, [ManifestEntry::new("a", None, 3)]);
+
+        Ok(())
+    }
+}
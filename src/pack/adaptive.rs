@@ -0,0 +1,117 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::pack::file::temp_file;
+use crate::pack::RawFile;
+
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    Disk(std::fs::File),
+}
+
+/// A file which is kept in memory until it grows past a threshold, and then moves to an
+/// anonymous file on disk (see [`RawFile::temp`]) without its users noticing, for buffering
+/// streams of unknown size without running out of memory.
+pub struct AdaptiveFile {
+    storage: Storage,
+    threshold: u64,
+    dir: PathBuf,
+}
+
+impl AdaptiveFile {
+    /// A file which moves to [`std::env::temp_dir`] when it grows past `threshold` bytes.
+    pub fn new(threshold: u64) -> Self {
+        Self::new_in(threshold, std::env::temp_dir())
+    }
+
+    /// A file which moves to `dir` when it grows past `threshold` bytes.
+    pub fn new_in(threshold: u64, dir: impl AsRef<Path>) -> Self {
+        Self {
+            storage: Storage::Memory(Cursor::new(Vec::new())),
+            threshold,
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Whether the file moved to disk.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::Disk(_))
+    }
+
+    /// Move the contents to disk, keeping the position. Does nothing when they're there already.
+    pub fn spill(&mut self) -> error::Result<()> {
+        if let Storage::Memory(data) = &self.storage {
+            let mut file = temp_file(&self.dir)?;
+            file.write_all(data.get_ref())?;
+            file.seek(SeekFrom::Start(data.position()))?;
+            self.storage = Storage::Disk(file);
+        }
+        Ok(())
+    }
+
+    pub fn set_len(&mut self, size: u64) -> error::Result<()> {
+        if size > self.threshold {
+            self.spill()?;
+        }
+        match &mut self.storage {
+            Storage::Memory(data) => data.get_mut().resize(size as usize, 0),
+            Storage::Disk(file) => file.set_len(size)?,
+        }
+        Ok(())
+    }
+}
+
+impl Read for AdaptiveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.storage {
+            Storage::Memory(data) => data.read(buf),
+            Storage::Disk(file) => file.read(buf),
+        }
+    }
+}
+
+impl Write for AdaptiveFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Storage::Memory(data) = &self.storage {
+            if data.position() + buf.len() as u64 > self.threshold {
+                self.spill()?;
+            }
+        }
+        match &mut self.storage {
+            Storage::Memory(data) => data.write(buf),
+            Storage::Disk(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::Disk(file) => file.flush(),
+        }
+    }
+}
+
+impl Seek for AdaptiveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.storage {
+            Storage::Memory(data) => data.seek(pos),
+            Storage::Disk(file) => file.seek(pos),
+        }
+    }
+}
+
+/// A file in memory or on disk, wherever the contents are. The position is kept.
+impl From<AdaptiveFile> for RawFile<'_, '_> {
+    fn from(f: AdaptiveFile) -> Self {
+        match f.storage {
+            Storage::Memory(data) => {
+                let position = data.position();
+                let mut file = RawFile::from(data.into_inner());
+                // can't fail in memory
+                let _ = file.seek(SeekFrom::Start(position));
+                file
+            }
+            Storage::Disk(file) => file.into(),
+        }
+    }
+}
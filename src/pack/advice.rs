@@ -0,0 +1,47 @@
+use std::fs::File;
+use crate::error;
+
+/// How a region of a backpack is going to be accessed, see [`BackPack::advise`](crate::BackPack::advise).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// No particular access pattern, the default.
+    Normal,
+    /// The region will be read from start to end, so reading ahead aggressively pays off.
+    Sequential,
+    /// The region will be read in random order, reading ahead is wasted.
+    Random,
+    /// The region will be needed soon, start loading it now.
+    WillNeed,
+    /// The region won't be needed any time soon, it can be dropped from the page cache.
+    DontNeed,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn fadvise(file: &File, offset: u64, length: u64, advice: Advice) -> error::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let advice = match advice {
+        Advice::Normal => libc::POSIX_FADV_NORMAL,
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_FADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+
+    // Safety: the file descriptor is valid for as long as we borrow the file
+    let res = unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, length as libc::off_t, advice)
+    };
+
+    if res != 0 {
+        return Err(std::io::Error::from_raw_os_error(res).into());
+    }
+
+    Ok(())
+}
+
+/// Advice is only a hint, on platforms without `posix_fadvise` it's ignored.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn fadvise(_file: &File, _offset: u64, _length: u64, _advice: Advice) -> error::Result<()> {
+    Ok(())
+}
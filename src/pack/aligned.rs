@@ -0,0 +1,133 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::ptr::NonNull;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::maybe_ref::MaybeRef;
+
+/// Bytes stored at an address which is a multiple of a chosen alignment,
+/// for example so they can be uploaded straight to a GPU. Either a copy,
+/// or borrowed from a backpack in memory when they were stored aligned.
+pub struct AlignedBytes<'a> {
+    bytes: Bytes<'a>,
+    alignment: u64,
+}
+
+enum Bytes<'a> {
+    Owned(Allocation),
+    Borrowed(MaybeRef<'a, [u8]>),
+}
+
+struct Allocation {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+// Allocation owns its memory, just like a Vec<u8> would
+unsafe impl Send for Allocation {}
+unsafe impl Sync for Allocation {}
+
+impl AlignedBytes<'static> {
+    /// A copy of `data` at an address aligned to `alignment`.
+    pub fn new(data: &[u8], alignment: u64) -> error::Result<Self> {
+        // never allocate zero bytes, that's not allowed for `alloc`
+        let layout = Layout::from_size_align(data.len().max(1), alignment as usize)
+            .map_err(|_| PackError::BadAlignment(alignment))?;
+
+        // Safety: the layout is never zero sized
+        let ptr = unsafe { alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+
+        // Safety: the allocation is at least data.len() bytes and can't overlap with data
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr.as_ptr(), data.len()) };
+
+        Ok(Self {
+            bytes: Bytes::Owned(Allocation {
+                ptr,
+                len: data.len(),
+                layout,
+            }),
+            alignment,
+        })
+    }
+}
+
+impl<'a> AlignedBytes<'a> {
+    /// `data` itself, when it's at an address aligned to `alignment`.
+    pub(crate) fn borrowed(data: MaybeRef<'a, [u8]>, alignment: u64) -> Option<Self> {
+        if !(data.as_ptr() as u64).is_multiple_of(alignment) {
+            return None;
+        }
+        Some(Self { bytes: Bytes::Borrowed(data), alignment })
+    }
+
+    pub fn alignment(&self) -> u64 {
+        self.alignment
+    }
+
+    /// Whether the bytes are borrowed from the backpack, instead of a copy.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.bytes, Bytes::Borrowed(_))
+    }
+}
+
+impl Deref for AlignedBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match &self.bytes {
+            // Safety: ptr points to at least len initialized bytes owned by self
+            Bytes::Owned(Allocation { ptr, len, .. }) => unsafe { std::slice::from_raw_parts(ptr.as_ptr(), *len) },
+            Bytes::Borrowed(data) => data,
+        }
+    }
+}
+
+impl Debug for AlignedBytes<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AlignedBytes({:?})", self.deref())
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        // Safety: allocated in `new` with the same layout
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// Number of padding bytes needed after `offset` to reach a multiple of `alignment`.
+pub(crate) fn padding(offset: u64, alignment: u64) -> u64 {
+    (alignment - offset % alignment) % alignment
+}
+
+/// Marks a sidecar record at the end of the padding before a file.
+const SIDECAR_MAGIC: &[u8; 2] = b"SC";
+
+/// Store `record` at the end of `padding`, followed by its length and a marker, so it can be found
+/// by looking backwards from the start of the file after it. Returns false if it doesn't fit.
+pub(crate) fn write_sidecar(padding: &mut [u8], record: &[u8]) -> bool {
+    let Ok(len) = u16::try_from(record.len()) else { return false };
+    if padding.len() < record.len() + 4 {
+        return false;
+    }
+
+    let end = padding.len();
+    padding[end - 4 - record.len()..end - 4].copy_from_slice(record);
+    padding[end - 4..end - 2].copy_from_slice(&len.to_le_bytes());
+    padding[end - 2..].copy_from_slice(SIDECAR_MAGIC);
+    true
+}
+
+/// Find a record stored with [`write_sidecar`] in the padding before a file.
+pub(crate) fn read_sidecar(padding: &[u8]) -> Option<Vec<u8>> {
+    let end = padding.len();
+    if end < 4 || &padding[end - 2..] != SIDECAR_MAGIC {
+        return None;
+    }
+
+    let len = u16::from_le_bytes([padding[end - 4], padding[end - 3]]) as usize;
+    padding.get(end.checked_sub(4 + len)?..end - 4).map(<[u8]>::to_vec)
+}
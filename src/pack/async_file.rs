@@ -0,0 +1,157 @@
+use std::future::poll_fn;
+use std::io::{self, Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use crate::error::Result;
+
+/// The async counterpart of [`RawFile`](crate::RawFile), for use with tokio.
+/// Files on disk go through [`tokio::fs::File`], so they need to be used from a tokio runtime.
+pub enum AsyncRawFile {
+    InMemory(Cursor<Vec<u8>>),
+    Disk {
+        name: Option<PathBuf>,
+        file: tokio::fs::File,
+    },
+}
+
+impl AsyncRawFile {
+    pub fn in_memory() -> Self {
+        Self::InMemory(Cursor::new(Vec::new()))
+    }
+
+    pub async fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::Disk {
+            name: Some(path.as_ref().to_path_buf()),
+            file: tokio::fs::File::create(path).await?,
+        })
+    }
+
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::Disk {
+            name: Some(path.as_ref().to_path_buf()),
+            file: tokio::fs::File::open(path).await?,
+        })
+    }
+
+    pub fn name(&self) -> Option<&Path> {
+        match self {
+            AsyncRawFile::InMemory(_) => None,
+            AsyncRawFile::Disk { name, .. } => name.as_deref(),
+        }
+    }
+
+    /// The contents of an in-memory file, `None` for files on disk.
+    pub fn into_memory(self) -> Option<Vec<u8>> {
+        match self {
+            AsyncRawFile::InMemory(data) => Some(data.into_inner()),
+            AsyncRawFile::Disk { .. } => None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for AsyncRawFile {
+    fn from(data: Vec<u8>) -> Self {
+        Self::InMemory(Cursor::new(data))
+    }
+}
+
+impl From<tokio::fs::File> for AsyncRawFile {
+    fn from(file: tokio::fs::File) -> Self {
+        Self::Disk { name: None, file }
+    }
+}
+
+impl AsyncRead for AsyncRawFile {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).poll_read(cx, buf),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for AsyncRawFile {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).poll_write(cx, buf),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).poll_flush(cx),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).poll_shutdown(cx),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).poll_shutdown(cx),
+        }
+    }
+}
+
+impl AsyncSeek for AsyncRawFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).start_seek(position),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match self.get_mut() {
+            AsyncRawFile::InMemory(data) => Pin::new(data).poll_complete(cx),
+            AsyncRawFile::Disk { file, .. } => Pin::new(file).poll_complete(cx),
+        }
+    }
+}
+
+// Small versions of tokio's `AsyncReadExt` and friends, which need more of tokio than we'd like.
+
+pub(crate) async fn seek(f: &mut (impl AsyncSeek + Unpin), position: SeekFrom) -> io::Result<u64> {
+    // files on disk refuse to seek while a write is still in flight
+    poll_fn(|cx| Pin::new(&mut *f).poll_complete(cx)).await?;
+    Pin::new(&mut *f).start_seek(position)?;
+    poll_fn(|cx| Pin::new(&mut *f).poll_complete(cx)).await
+}
+
+/// Read into `buf`, returning how much was read. Only returns 0 at the end of `f`.
+pub(crate) async fn read(f: &mut (impl AsyncRead + Unpin), buf: &mut [u8]) -> io::Result<usize> {
+    let mut buf = ReadBuf::new(buf);
+    poll_fn(|cx| Pin::new(&mut *f).poll_read(cx, &mut buf)).await?;
+    Ok(buf.filled().len())
+}
+
+pub(crate) async fn read_exact(f: &mut (impl AsyncRead + Unpin), mut buf: &mut [u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read(f, buf).await? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => buf = &mut buf[n..],
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn read_exact_at(f: &mut (impl AsyncRead + AsyncSeek + Unpin), offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    seek(f, SeekFrom::Start(offset)).await?;
+    read_exact(f, buf).await
+}
+
+pub(crate) async fn write_all(f: &mut (impl AsyncWrite + Unpin), mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match poll_fn(|cx| Pin::new(&mut *f).poll_write(cx, buf)).await? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => buf = &buf[n..],
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn flush(f: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+    poll_fn(|cx| Pin::new(&mut *f).poll_flush(cx)).await
+}
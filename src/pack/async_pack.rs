@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::io::AsyncRead;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::async_file::{self, AsyncRawFile};
+use crate::pack::backpack::{check_reserved, decode_aliases};
+use crate::pack::compression::Compressed;
+use crate::pack::crc32::Crc32;
+use crate::pack::layout::{PackHeader, TocBlockHeader};
+use crate::pack::{BackPack, Index, ALIAS_ENTRY, ENCRYPTION_FIELD, METADATA_ENTRIES, PACK_HEADER_SIZE, PACK_VERSION, TOC_SIZE};
+
+/// The async counterpart of [`PackReader`](crate::pack::PackReader). Opening only reads the
+/// table of contents, entries are read from the file when they're read. Encrypted backpacks
+/// can't be read, and a damaged index isn't recovered from its copy.
+pub struct AsyncPackReader {
+    file: AsyncRawFile,
+    /// absolute offset and length of every entry
+    entries: HashMap<String, (u64, u64)>,
+    hidden: HashSet<String>,
+    aliases: HashMap<String, String>,
+    compressed: Compressed,
+}
+
+impl AsyncPackReader {
+    pub async fn open(mut file: AsyncRawFile) -> error::Result<Self> {
+        let mut header = [0; PackHeader::SIZE];
+        async_file::read_exact_at(&mut file, 0, &mut header).await?;
+        let header = PackHeader::read_from(&mut header.as_slice())?;
+        let version = header.version();
+        if version != PACK_VERSION {
+            return Err(PackError::Incompatible(version));
+        }
+
+        let mut index = Index::default();
+        let mut next_toc_offset = header.first_toc.get();
+        while next_toc_offset != 0 {
+            // a damaged pointer could send us around in circles
+            if index.toc_blocks.contains(&next_toc_offset) {
+                return Err(PackError::InvalidEntry);
+            }
+            index.toc_blocks.push(next_toc_offset);
+
+            let mut block = vec![0; TOC_SIZE as usize];
+            async_file::read_exact_at(&mut file, next_toc_offset, &mut block).await?;
+            let (header, entries) = block.split_at(TocBlockHeader::SIZE);
+            let header = TocBlockHeader::from_bytes(header.try_into().expect("split at the header size"));
+            next_toc_offset = header.next.get();
+
+            BackPack::parse_toc_block(header.entries_len()?, entries, &mut index)?;
+        }
+        if !index.encrypted.is_empty() {
+            return Err(PackError::UnsupportedIndexField(ENCRYPTION_FIELD));
+        }
+
+        let Index { offsets, mut toc_blocks, hidden, compressed, .. } = index;
+        toc_blocks.sort();
+        let mut entries = offsets.into_iter()
+            .map(|(name, (offset, length))| (name, (BackPack::convert_offset(&toc_blocks, offset), length)))
+            .collect::<HashMap<_, _>>();
+
+        let aliases = match entries.get(ALIAS_ENTRY) {
+            Some(&(offset, length)) => {
+                let mut buf = vec![0; length as usize];
+                async_file::read_exact_at(&mut file, offset, &mut buf).await?;
+                decode_aliases(&buf)?
+            }
+            None => HashMap::new(),
+        };
+        for name in METADATA_ENTRIES {
+            entries.remove(*name);
+        }
+
+        Ok(Self {
+            file,
+            entries,
+            hidden,
+            aliases,
+            compressed,
+        })
+    }
+
+    /// The stored name and location of the entry called `name`, or of the entry it's an alias of.
+    fn find(&self, name: &Path) -> error::Result<(&str, (u64, u64))> {
+        let name_str = name.to_string_lossy();
+        self.entries.get_key_value(name_str.as_ref())
+            .or_else(|| self.entries.get_key_value(self.aliases.get(name_str.as_ref())?))
+            .map(|(name, key)| (name.as_str(), *key))
+            .ok_or_else(|| PackError::FileNotFound(name.to_path_buf()))
+    }
+
+    /// Read all of an entry into memory, decompressing it if it's compressed.
+    pub async fn read(&mut self, name: impl AsRef<Path>) -> error::Result<Vec<u8>> {
+        let (name_str, (start, length)) = self.find(name.as_ref())?;
+        let compression = self.compressed.get(name_str).copied();
+
+        let mut buf = vec![0; length as usize];
+        async_file::read_exact_at(&mut self.file, start, &mut buf).await?;
+        match compression {
+            Some((method, len)) => method.decompress(&buf, len),
+            None => Ok(buf),
+        }
+    }
+
+    pub fn contains(&self, name: impl AsRef<Path>) -> bool {
+        self.find(name.as_ref()).is_ok()
+    }
+
+    /// Names of all files, sorted, without [hidden](BackPack::set_hidden) files and directories.
+    pub fn file_names(&self) -> Vec<&str> {
+        let mut res = self.entries.keys()
+            .filter(|name| !name.ends_with('/') && !self.hidden.contains(*name))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    pub fn is_hidden(&self, name: impl AsRef<Path>) -> bool {
+        self.find(name.as_ref()).is_ok_and(|(name, _)| self.hidden.contains(name))
+    }
+
+    pub fn into_inner(self) -> AsyncRawFile {
+        self.file
+    }
+}
+
+/// The async counterpart of [`PackWriter`](crate::pack::PackWriter): writes a backpack one
+/// entry at a time, with the table of contents after the entries.
+pub struct AsyncPackWriter {
+    file: AsyncRawFile,
+    offsets: HashMap<String, (u64, u64)>,
+    hidden: HashSet<String>,
+    checksums: HashMap<String, u32>,
+    /// bytes of contents written so far
+    size: u64,
+}
+
+impl AsyncPackWriter {
+    /// Start writing a backpack to `file`, from its start. Until [`finish`](Self::finish)
+    /// is called the file is not a valid backpack.
+    pub async fn new(mut file: AsyncRawFile) -> error::Result<Self> {
+        async_file::seek(&mut file, SeekFrom::Start(0)).await?;
+        // the real header is written when the size and table of contents are known
+        async_file::write_all(&mut file, &PackHeader::new(0, 0).to_bytes()).await?;
+
+        Ok(Self {
+            file,
+            offsets: HashMap::new(),
+            hidden: HashSet::new(),
+            checksums: HashMap::new(),
+            size: 0,
+        })
+    }
+
+    /// Copy `contents` into the backpack as `name`. Returns the number of bytes written.
+    pub async fn add_entry(&mut self, name: impl AsRef<Path>, mut contents: impl AsyncRead + Unpin) -> error::Result<u64> {
+        let name = name.as_ref();
+        let name_str = name.to_string_lossy().into_owned();
+        if name_str.is_empty() {
+            return Err(PackError::NoName);
+        }
+        check_reserved(&name_str, name)?;
+        if self.offsets.contains_key(&name_str) {
+            return Err(PackError::FileExists(name.to_path_buf()));
+        }
+
+        let mut buf = vec![0; 64 * 1024];
+        let mut crc = Crc32::new();
+        let mut length = 0;
+        loop {
+            let n = async_file::read(&mut contents, &mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            crc.update(&buf[..n]);
+            async_file::write_all(&mut self.file, &buf[..n]).await?;
+            length += n as u64;
+        }
+
+        // empty files take up no space
+        let offset = if length == 0 { 0 } else { self.size };
+        if length != 0 {
+            self.checksums.insert(name_str.clone(), crc.finish());
+        }
+        self.offsets.insert(name_str, (offset, length));
+        self.size += length;
+
+        Ok(length)
+    }
+
+    /// Like [`add_entry`](Self::add_entry), for a file left out of listings,
+    /// see [`BackPack::set_hidden`].
+    pub async fn add_hidden_entry(&mut self, name: impl AsRef<Path>, contents: impl AsyncRead + Unpin) -> error::Result<u64> {
+        let length = self.add_entry(&name, contents).await?;
+        self.hidden.insert(name.as_ref().to_string_lossy().into_owned());
+        Ok(length)
+    }
+
+    /// Bytes of contents written so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Write the table of contents after the entries and the header before them,
+    /// and return the finished file.
+    pub async fn finish(mut self) -> error::Result<AsyncRawFile> {
+        let first_block = PACK_HEADER_SIZE + self.size;
+        let toc_blocks = BackPack::create_toc_at(&self.offsets, &self.hidden, &HashMap::new(), &HashSet::new(), &self.checksums, &HashMap::new(), first_block)?;
+        for block in &toc_blocks {
+            async_file::write_all(&mut self.file, block).await?;
+        }
+
+        let first_toc = if toc_blocks.is_empty() { 0 } else { first_block };
+        async_file::seek(&mut self.file, SeekFrom::Start(0)).await?;
+        async_file::write_all(&mut self.file, &PackHeader::new(self.size, first_toc).for_toc(&toc_blocks).to_bytes()).await?;
+        async_file::seek(&mut self.file, SeekFrom::End(0)).await?;
+        async_file::flush(&mut self.file).await?;
+
+        Ok(self.file)
+    }
+}
@@ -1,39 +1,582 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use std::ops::{Deref, DerefMut};
-use std::path::Path;
+use std::ops::DerefMut;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use elsa::sync::FrozenMap;
-use parking_lot::RwLock;
-use crate::{error, RawFile};
+use rayon::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use crate::{error, sfx, RawFile};
 use crate::pack::in_memory::InMemoryFile;
+use crate::pack::entry_name;
+use crate::pack::entry_name::EntryName;
 use crate::pack::{PACK_HEADER_SIZE, PACK_MAGIC, PACK_VERSION, TOC_SIZE};
-use crate::error::PackError;
+use crate::error::{AtPath, PackError};
 use crate::error::PackError::{Closed, NoName};
 use crate::pack::slice::PackSlice;
+use crate::pack::zip;
+use crate::pack::aligned;
+use crate::pack::aligned::AlignedBytes;
+use crate::pack::advice::Advice;
+use crate::pack::trace::AccessTrace;
+use crate::pack::validate::Validator;
+use crate::pack::codec::Codec;
+use crate::pack::names::{NameHasher, NameMatching};
+use crate::pack::protection;
+use crate::pack::protection::IndexProtection;
+use crate::pack::handles::Handles;
+use crate::pack::changes::{ChangeEvent, Changes, Subscribers};
+use crate::pack::split::SplitPack;
+use crate::pack::compat::CompatibilityReport;
+use crate::pack::directory;
+use crate::pack::directory::{DirectoryOptions, ExtractOptions};
+use crate::pack::file::FileMetadata;
+use crate::pack::overlay::Overlay;
+use crate::pack::diff::PackDiff;
+use crate::pack::journal;
+use crate::pack::journal::Journal;
+use crate::pack::text;
+use crate::pack::text::Lines;
+use crate::pack::progress;
+use crate::pack::progress::{Progress, Tracker};
+use crate::pack::entries::{Entries, EntriesMut, EntryKind, EntryMetadata, EntryRef};
+use crate::pack::writer::PackWriter;
+use crate::pack::tar;
+use crate::pack::sparse;
+use crate::pack::salvage;
+use crate::pack::info;
+use crate::pack::builder::{Checksum, IndexPlacement};
+use crate::pack::info::{IndexEntry, PackInfo, PackStats};
+use crate::pack::salvage::{ReadMode, SalvageReport, Salvaged};
+use crate::pack::temp::TempEntry;
+use crate::pack::transaction::Transaction;
+use crate::pack::entry_stream::{EntryReader, EntryWriter};
+use crate::pack::compression;
+use crate::pack::compression::{Compressed, Compression};
+use crate::pack::crc32::{crc32, Crc32};
+use crate::pack::encryption;
+use crate::pack::encryption::EncryptionKey;
+#[cfg(feature = "crypto")]
+use crate::pack::encryption::Encryption;
+#[cfg(feature = "signing")]
+use crate::pack::signing;
+#[cfg(feature = "signing")]
+use crate::pack::signing::{SigningKey, PUBLIC_KEY_SIZE};
+#[cfg(feature = "serde")]
+use crate::pack::serialized::Format;
+#[cfg(feature = "json")]
+use crate::pack::serialized::Json;
+use crate::format;
+pub use crate::format::{ALIAS_ENTRY, ATTRIBUTES_ENTRY, ENCRYPTION_ENTRY, EXPIRY_ENTRY, METADATA_ENTRIES, MODIFIED_ENTRY, RESERVED_PREFIX, SIGNATURE_ENTRY, TIERS_ENTRY};
+use crate::pack::layout::{encode_fields, ALIGNMENT_FIELD, CHECKSUM_FIELD, COMPRESSION_FIELD, ENCRYPTION_FIELD, DIRECTORY, FLAGS_FIELD, HIDDEN, CommitTrailer, PackHeader, TocBlockHeader, COMMIT_MAGIC, TocEntry, U16Le, U32Le, U64Le};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::remote::RetryPolicy;
 
-pub struct PartialData {
-    start: u64,
-    end: u64,
+pub(crate) type Offsets = HashMap<String, (u64, u64)>;
 
-    data: Vec<u8>,
+/// Names by the key they're [matched](BackPack::set_name_matching) by, with the sorted names they were made from.
+type MatchedNames = (Arc<Vec<String>>, HashMap<String, String>);
+
+/// Index records of some files, see [`BackPack::records`].
+pub(crate) struct Records {
+    records: Vec<(String, Record)>,
+    /// renames retarget aliases
+    aliases: HashMap<String, String>,
 }
 
-pub enum BackPack<'f, 'backpack> {
-    PartiallyParsed {
-        file: Option<RawFile<'f, 'backpack>>,
-        total_pack_size: u64,
-        max_allowed_in_memory: usize,
+struct Record {
+    key: Option<(u64, u64)>,
+    removed: bool,
+    hidden: bool,
+    expiry: Option<u64>,
+    modified: Option<u64>,
+    attributes: Option<BTreeMap<String, Vec<u8>>>,
+    sidecar: Option<Vec<u8>>,
+    alignment: Option<u64>,
+    compression: Option<Compression>,
+    tier: Option<Tier>,
+}
 
-        offsets: HashMap<String, (u64, u64)>,
-        toc_blocks: Vec<u64>,
+/// Everything read from the header and table of contents.
+#[derive(Default)]
+pub(crate) struct Index {
+    pub offsets: Offsets,
+    /// where the table of content blocks are in the file, in the order they're chained
+    pub toc_blocks: Vec<u64>,
+    pub hidden: HashSet<String>,
+    pub compressed: Compressed,
+    /// entries which were stored as is because compressing them didn't save enough,
+    /// see [`BackPack::set_min_compression_ratio`]
+    pub stored: HashSet<String>,
+    /// crc32 of the contents of entries, as stored
+    pub checksums: HashMap<String, u32>,
+    pub encrypted: HashSet<String>,
+    /// alignment of entries which have one over 1
+    pub alignments: HashMap<String, u64>,
+}
+
+/// Times per file, as lines of `{unix seconds} {name}`.
+fn encode_times(times: &HashMap<String, u64>) -> Vec<u8> {
+    let mut lines = times.iter()
+        .map(|(name, time)| format!("{} {}\n", time, name))
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines.concat().into_bytes()
+}
 
-        data: Vec<PartialData>,
+pub(crate) fn decode_times(data: &[u8]) -> error::Result<HashMap<String, u64>> {
+    String::from_utf8(data.to_vec())?
+        .lines()
+        .map(|line| {
+            let (time, name) = line.split_once(' ').ok_or(PackError::InvalidEntry)?;
+            Ok((name.to_string(), time.parse().map_err(|_| PackError::InvalidEntry)?))
+        })
+        .collect()
+}
 
-        last_toc_offset: u64,
+/// Tiers per file, as lines of `{hot|cold} {name}`.
+fn encode_tiers(tiers: &HashMap<String, Tier>) -> Vec<u8> {
+    let mut lines = tiers.iter()
+        .map(|(name, tier)| match tier {
+            Tier::Hot => format!("hot {}\n", name),
+            Tier::Cold => format!("cold {}\n", name),
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines.concat().into_bytes()
+}
 
-        closed: bool,
-    },
+pub(crate) fn decode_tiers(data: &[u8]) -> error::Result<HashMap<String, Tier>> {
+    String::from_utf8(data.to_vec())?
+        .lines()
+        .map(|line| match line.split_once(' ') {
+            Some(("hot", name)) => Ok((name.to_string(), Tier::Hot)),
+            Some(("cold", name)) => Ok((name.to_string(), Tier::Cold)),
+            _ => Err(PackError::InvalidEntry),
+        })
+        .collect()
+}
+
+/// How the file `name` is compressed: not at all when it's [hot](Tier::Hot), so it's read
+/// quickly, otherwise the way it was added with or the backpack's `compression`.
+fn compression_for(name: &str, compressions: &HashMap<String, Compression>, tiers: &HashMap<String, Tier>, compression: Compression) -> Compression {
+    match tiers.get(name) {
+        Some(Tier::Hot) => Compression::None,
+        _ => compressions.get(name).copied().unwrap_or(compression),
+    }
+}
+
+fn encode_aliases(aliases: &HashMap<String, String>) -> Vec<u8> {
+    let mut pairs = aliases.iter()
+        .map(|(alias, target)| format!("{}\0{}\0", alias, target))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.concat().into_bytes()
+}
+
+pub(crate) fn decode_aliases(data: &[u8]) -> error::Result<HashMap<String, String>> {
+    let data = String::from_utf8(data.to_vec())?;
+    let mut parts = data.split_terminator('\0');
+    let mut aliases = HashMap::new();
+    while let Some(alias) = parts.next() {
+        let target = parts.next().ok_or(PackError::InvalidEntry)?;
+        aliases.insert(alias.to_string(), target.to_string());
+    }
+    Ok(aliases)
+}
+
+/// The [attribute](BackPack::set_attribute) holding the unix permission bits of a file, see [`BackPack::set_mode`].
+pub const MODE_ATTRIBUTE: &str = "unix.mode";
+
+/// The [attribute](BackPack::set_attribute) holding the target of a symbolic link, see [`BackPack::add_symlink`].
+pub const SYMLINK_ATTRIBUTE: &str = "symlink";
+
+/// The [attribute](BackPack::set_attribute) holding the holes of a sparse file, as `{offset}+{length}`
+/// separated by `,`, see [`BackPack::set_holes`].
+pub const SPARSE_ATTRIBUTE: &str = "sparse";
+
+fn encode_attributes(attributes: &HashMap<String, BTreeMap<String, Vec<u8>>>) -> Vec<u8> {
+    let mut names = attributes.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut res = Vec::new();
+    for name in names {
+        for (key, value) in &attributes[name] {
+            res.extend_from_slice(format!("{}\0{}\0", name, key).as_bytes());
+            res.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            res.extend_from_slice(value);
+        }
+    }
+    res
+}
+
+pub(crate) fn decode_attributes(mut data: &[u8]) -> error::Result<HashMap<String, BTreeMap<String, Vec<u8>>>> {
+    let take_str = |data: &mut &[u8]| -> error::Result<String> {
+        let end = data.iter().position(|c| *c == 0).ok_or(PackError::InvalidEntry)?;
+        let res = String::from_utf8(data[..end].to_vec())?;
+        *data = &data[end + 1..];
+        Ok(res)
+    };
+
+    let mut attributes = HashMap::<_, BTreeMap<_, _>>::new();
+    while !data.is_empty() {
+        let name = take_str(&mut data)?;
+        let key = take_str(&mut data)?;
+        let len = data.get(..4).ok_or(PackError::InvalidEntry)?;
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let value = data.get(4..4 + len).ok_or(PackError::InvalidEntry)?;
+        attributes.entry(name).or_default().insert(key, value.to_vec());
+        data = &data[4 + len..];
+    }
+    Ok(attributes)
+}
+
+/// What's signed for a backpack with the files `entries`, with their contents before compression
+/// and encryption: every name with a hash of its contents, and the metadata of those files.
+/// How files are stored isn't covered: their [tier](BackPack::set_tier), alignment and sidecar
+/// records can change without breaking the signature, as they don't change what's read.
+#[cfg(feature = "signing")]
+fn signed_message(
+    entries: &[(&str, &[u8])],
+    hidden: &HashSet<String>,
+    aliases: &HashMap<String, String>,
+    expiry: &HashMap<String, u64>,
+    modified: &HashMap<String, u64>,
+    attributes: &HashMap<String, BTreeMap<String, Vec<u8>>>,
+) -> Vec<u8> {
+    let mut entries = entries.to_vec();
+    entries.sort();
+    let names = entries.iter().map(|(name, _)| *name).collect::<HashSet<_>>();
+    let of_entries = |times: &HashMap<String, u64>| times.iter()
+        .filter(|(name, _)| names.contains(name.as_str()))
+        .map(|(name, time)| (name.clone(), *time))
+        .collect::<HashMap<_, _>>();
+    let aliases = aliases.iter()
+        .filter(|(alias, target)| names.contains(target.as_str()) && !names.contains(alias.as_str()))
+        .map(|(alias, target)| (alias.clone(), target.clone()))
+        .collect();
+    let attributes = attributes.iter()
+        .filter(|(name, _)| names.contains(name.as_str()))
+        .map(|(name, attributes)| (name.clone(), attributes.clone()))
+        .collect();
+
+    let mut res = b"backpack signature 1\0".to_vec();
+    for (name, contents) in entries {
+        res.extend_from_slice(name.as_bytes());
+        res.push(0);
+        res.push(hidden.contains(name) as u8);
+        res.extend_from_slice(&signing::sha256(contents));
+    }
+    for metadata in [encode_aliases(&aliases), encode_times(&of_entries(expiry)), encode_times(&of_entries(modified)), encode_attributes(&attributes)] {
+        res.extend_from_slice(&signing::sha256(&metadata));
+    }
+    res
+}
+
+/// Where everything ended up after writing a backpack to a file
+pub(crate) struct Layout {
+    pub offsets: Offsets,
+    pub toc_blocks: Vec<u64>,
+    pub data_size: u64,
+    /// files whose sidecar record fit in the padding before them
+    pub sidecars: Vec<String>,
+}
+
+/// How a backpack is laid out when it's written to its file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Only a backpack
+    #[default]
+    Native,
+    /// A backpack which is also a valid zip archive, so it can be
+    /// opened with any archiver. Entries are stored uncompressed.
+    ZipHybrid,
+}
+
+/// Where a file goes in the layout of a backpack. Hot files are placed at the front
+/// so they are loaded quickly at startup, and stored uncompressed. Cold files are placed
+/// at the back, compressed like the rest of the backpack. Files without a tier are placed
+/// in between. Tiers are stored in the backpack, so they're kept when it's reopened.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tier {
+    Hot,
+    Cold,
+}
+
+/// Limits on the files under a directory prefix of a backpack, see [`BackPack::set_quota`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_entries: Option<usize>,
+}
+
+/// Limits on what can be added to a backpack, to keep packing untrusted input from producing
+/// an unbounded backpack, see [`BackPack::set_write_limits`] and [`PackWriter::set_limits`].
+/// Every limit is off by default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct WriteLimits {
+    /// bytes of contents of a single entry
+    pub max_entry_size: Option<u64>,
+    /// bytes of contents of all entries together
+    pub max_pack_size: Option<u64>,
+    pub max_entries: Option<usize>,
+}
+
+impl WriteLimits {
+    /// Fails when the entry `name` being `length` bytes, which makes the contents of all
+    /// entries `total` bytes, goes over the limits.
+    pub(crate) fn check_size(&self, name: &str, length: u64, total: u64) -> error::Result<()> {
+        if let Some(max) = self.max_entry_size.filter(|max| length > *max) {
+            return Err(PackError::EntryTooLarge(PathBuf::from(name), max));
+        }
+        if let Some(max) = self.max_pack_size.filter(|max| total > *max) {
+            return Err(PackError::PackTooLarge(max));
+        }
+        Ok(())
+    }
+
+    /// Fails when `entries` entries go over the limit.
+    pub(crate) fn check_entries(&self, entries: usize) -> error::Result<()> {
+        match self.max_entries.filter(|max| entries > *max) {
+            Some(max) => Err(PackError::TooManyEntries(max)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What happens when a file is added under a name which is already in the backpack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Collision {
+    /// Fail with [`PackError::FileExists`]
+    Error,
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Add the new file under a free name, `file (1).txt`, `file (2).txt` and so on
+    KeepBoth,
+    /// Keep the existing file if the contents are the same, fail with
+    /// [`PackError::FileExists`] if they're not
+    DedupeIfIdentical,
+}
+
+/// What [`BackPack::verify`] found wrong with a backpack.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct VerifyReport {
+    /// why the index in the backpack's file is damaged, if it is
+    pub index: Option<String>,
+    /// files which failed a check and why, sorted by name
+    pub files: Vec<(String, String)>,
+    /// reports of the files which are backpacks themselves, sorted by name. Only filled when verifying recursively.
+    pub nested: Vec<(String, VerifyReport)>,
+}
+
+impl VerifyReport {
+    /// Whether nothing is wrong with the backpack, and with the backpacks in it.
+    pub fn is_ok(&self) -> bool {
+        self.index.is_none() && self.files.is_empty() && self.nested.iter().all(|(_, nested)| nested.is_ok())
+    }
+}
+
+/// What to sort files by, see [`BackPack::entries_sorted_by`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    /// The order they're stored in, files added since the last flush come last in the order they were added
+    Offset,
+    /// Smallest first
+    Size,
+}
+
+/// Options for a single file, overriding the defaults of the backpack, see [`BackPack::add_with_options`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct EntryOptions {
+    /// instead of the backpack's [alignment](BackPack::set_alignment), must be a power of two
+    pub alignment: Option<u64>,
+    /// instead of the backpack's [collision policy](BackPack::set_collision_policy)
+    pub collision: Option<Collision>,
+    /// instead of the backpack's [compression](BackPack::set_compression)
+    pub compression: Option<Compression>,
+}
+
+/// Options for [`BackPack::freeze`].
+#[derive(Clone, Debug, Default)]
+pub struct FreezeOptions {
+    /// lay files out in the order they were first accessed in, files which weren't accessed come last
+    pub trace: Option<AccessTrace>,
+    /// instead of the backpack's [alignment](BackPack::set_alignment), must be a power of two
+    pub alignment: Option<u64>,
+    /// store every file's contents, also when another file has the same contents
+    pub keep_duplicates: bool,
+    /// where the table of contents goes. An index at the end can't be [protected](BackPack::set_index_protection).
+    pub index: IndexPlacement,
+    pub checksum: Checksum,
+}
+
+/// How much of a backpack is taken up by files which were overwritten or removed since
+/// the last flush, see [`BackPack::fragmentation_report`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct FragmentationReport {
+    /// bytes of the files in the backpack
+    pub live_bytes: u64,
+    /// bytes of overwritten and removed files, which are still kept around until the next flush
+    pub dead_bytes: u64,
+    /// files removed since the last flush
+    pub tombstones: usize,
+    /// how many bytes of the backpack's file would be freed by flushing, the part of
+    /// `dead_bytes` which was already written to the file
+    pub recoverable_bytes: u64,
+}
+
+impl FragmentationReport {
+    /// The fraction of bytes kept for files which are gone, between 0 and 1.
+    pub fn dead_ratio(&self) -> f64 {
+        let total = self.live_bytes + self.dead_bytes;
+        if total == 0 {
+            0.0
+        } else {
+            self.dead_bytes as f64 / total as f64
+        }
+    }
+}
+
+/// A free variant of `name`, `dir/file (n).ext` for the lowest `n` which isn't taken.
+fn free_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let (dir, file) = match name.rfind('/') {
+        Some(i) => name.split_at(i + 1),
+        None => ("", name),
+    };
+    let (stem, extension) = match file.rfind('.') {
+        Some(i) if i > 0 => file.split_at(i),
+        _ => (file, ""),
+    };
+
+    (1..)
+        .map(|n| format!("{}{} ({}){}", dir, stem, n, extension))
+        .find(|candidate| !taken(candidate))
+        .expect("there's always a free name")
+}
+
+/// `name` with `.` components and repeated separators left out and `..` components resolved,
+/// so every path to a file is stored under the same name. `..` never goes above the root
+/// of the backpack. A trailing `/` is kept.
+pub(crate) fn normalize_name(name: &Path) -> String {
+    let mut parts = Vec::new();
+    let mut root = false;
+    for component in name.components() {
+        match component {
+            Component::RootDir => root = true,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(part) => parts.push(entry_name::key_of(part)),
+            Component::Prefix(prefix) => parts.push(entry_name::key_of(prefix.as_os_str())),
+        }
+    }
+
+    let mut res = parts.join("/");
+    if root {
+        res.insert(0, '/');
+    }
+    if name.as_os_str().as_encoded_bytes().ends_with(b"/") && !res.is_empty() && !res.ends_with('/') {
+        res.push('/');
+    }
+    res
+}
+
+/// Fail when `name_str`, the normalized `name`, is [reserved](RESERVED_PREFIX) for the backpack
+/// itself, so files can't take the place of its metadata.
+pub(crate) fn check_reserved(name_str: &str, name: &Path) -> error::Result<()> {
+    match name_str.trim_start_matches('/').starts_with(RESERVED_PREFIX) {
+        true => Err(PackError::ReservedName(name.to_path_buf())),
+        false => Ok(()),
+    }
+}
+
+/// Give every empty entry a key of its own, counting up from `end`, so empty files never share
+/// contents with each other. They take up no space in the file, so their offset doesn't matter.
+/// Returns the end of the last key.
+fn separate_empty(offsets: &mut Offsets, mut end: u64) -> u64 {
+    let mut empty = offsets.iter_mut()
+        .filter(|(_, (_, length))| *length == 0)
+        .collect::<Vec<_>>();
+    empty.sort_by_key(|(name, _)| *name);
+
+    for (_, key) in empty {
+        *key = (end, 0);
+        end += 1;
+    }
+    end
+}
+
+/// Give every compressed or encrypted entry a key of its own with the length of its plain contents,
+/// counting up from `end`. Their contents are kept decoded in memory, so they aren't at their offset
+/// in the file, which goes in `packed` by their new key. Returns the end of the last key.
+fn separate_packed(offsets: &mut Offsets, compressed: &Compressed, encrypted: &HashSet<String>, mut end: u64, packed: &mut HashMap<(u64, u64), (u64, u64)>) -> u64 {
+    // entries stored as is after trying to compress them are in `compressed` too
+    let compressed = compressed.iter()
+        .filter(|(_, (method, _))| *method != Compression::None)
+        .map(|(name, key)| (name.clone(), *key))
+        .collect::<Compressed>();
+    let mut names = compressed.keys()
+        .chain(encrypted.iter().filter(|name| !compressed.contains_key(*name)))
+        .filter(|name| offsets.contains_key(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort();
+
+    for name in names {
+        let length = match compressed.get(&name) {
+            Some((_, length)) => *length,
+            None => offsets[&name].1.saturating_sub(encryption::OVERHEAD),
+        };
+        if let Some(stored) = offsets.insert(name, (end, length)) {
+            packed.insert((end, length), stored);
+        }
+        end += length.max(1);
+    }
+    end
+}
+
+/// Give every entry which shares its contents with an entry before it by name a key of its own,
+/// counting up from `end`, so changing one of them doesn't change the other. Returns the end
+/// of the last key, and the entries which got a key of their own with the key they shared.
+fn separate_shared(offsets: &mut Offsets, mut end: u64) -> (u64, Offsets) {
+    let mut names = offsets.iter()
+        .filter(|(_, (_, length))| *length != 0)
+        .map(|(name, key)| (name.clone(), *key))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut seen = HashSet::new();
+    let mut separated = Offsets::new();
+    for (name, key) in names {
+        if seen.insert(key) {
+            continue;
+        }
+        offsets.insert(name.clone(), (end, key.1));
+        separated.insert(name, key);
+        end += key.1;
+    }
+    (end, separated)
+}
+
+/// The crc32 of the contents of every entry which has contents.
+pub(crate) fn checksums_of(entries: &[(&str, &[u8])]) -> HashMap<String, u32> {
+    #[cfg(feature = "parallel")]
+    let entries = entries.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let entries = entries.iter();
+    entries
+        .filter(|(_, contents)| !contents.is_empty())
+        .map(|(name, contents)| (name.to_string(), crc32(contents)))
+        .collect()
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum BackPack<'f, 'backpack> {
     Parsed {
         file: Option<RawFile<'f, 'backpack>>,
 
@@ -42,6 +585,87 @@ pub enum BackPack<'f, 'backpack> {
         data: FrozenMap<(u64, u64), Box<RwLock<Vec<u8>>>>,
 
         total_size: AtomicU64,
+        output_mode: OutputMode,
+        alignment: u64,
+
+        /// where the table of content blocks are in the file
+        toc_blocks: Vec<u64>,
+        /// size of the data section in the file as of the last open or flush.
+        /// Files at offsets below this are stored in the file.
+        stored_size: u64,
+        /// offset at which the next added file is placed
+        end_offset: AtomicU64,
+
+        /// the order files are first accessed in, when recording
+        trace: Mutex<Option<AccessTrace>>,
+        /// position of files in the layout, files not in here come last
+        order: HashMap<String, usize>,
+        tiers: HashMap<String, Tier>,
+        /// run on every added file
+        validators: Vec<Box<dyn Validator>>,
+        /// limits per name prefix
+        quotas: HashMap<String, Quota>,
+        /// limits on all files, see [`set_write_limits`](Self::set_write_limits)
+        write_limits: WriteLimits,
+        /// when files expire, in seconds since the unix epoch
+        expiry: HashMap<String, u64>,
+        /// when files were last modified, in seconds since the unix epoch
+        modified: HashMap<String, u64>,
+        /// user defined attributes of files, see [`set_attribute`](Self::set_attribute)
+        attributes: HashMap<String, BTreeMap<String, Vec<u8>>>,
+        /// used by add_file
+        collision: Collision,
+        /// small records stored in the alignment padding before files
+        sidecars: HashMap<String, Vec<u8>>,
+        index_protection: IndexProtection,
+        /// handles to files which are still open
+        handles: Arc<Handles>,
+        /// files left out of listings
+        hidden: HashSet<String>,
+        /// turns names into the names stored in the table of contents
+        name_hasher: Option<Box<dyn NameHasher>>,
+        /// cap on [`memory_bytes`](Self::memory_bytes)
+        memory_limit: Option<u64>,
+        /// stored files whose contents were dropped from memory to stay under the
+        /// memory limit. They are read from the file again when opened.
+        evicted: Mutex<HashSet<(u64, u64)>>,
+        /// listeners for [`changes`](Self::changes)
+        subscribers: Subscribers,
+        /// alignment of files added with their own, instead of `alignment`
+        alignments: Mutex<HashMap<String, u64>>,
+        /// other names files can be opened by, from alias to target
+        aliases: HashMap<String, String>,
+        /// how reads of contents from the backing file are retried
+        read_retry: RetryPolicy,
+        /// how files are compressed when flushing, unless they have their own in `compressions`
+        compression: Compression,
+        /// the fraction of their size compressed files have to save, or they're stored as is
+        min_compression_ratio: f64,
+        compressions: Mutex<HashMap<String, Compression>>,
+        /// checksums of files read from the backing file which weren't checked yet
+        unverified: Mutex<HashMap<(u64, u64), u32>>,
+        /// where compressed and encrypted files are stored in the file, by the key of their
+        /// decoded contents, as of the last open or flush
+        packed: HashMap<(u64, u64), (u64, u64)>,
+        /// names in `offsets` sorted, for prefix queries. `None` after they changed,
+        /// until it's needed again
+        sorted_names: Mutex<Option<Arc<Vec<String>>>>,
+        /// how names are compared, see [`set_name_matching`](Self::set_name_matching)
+        name_matching: NameMatching,
+        /// names by their key for `name_matching`, made again after the names changed
+        matched_names: Mutex<Option<MatchedNames>>,
+        /// whether files are checked against their checksum when read, see [`set_verify_checksums`](Self::set_verify_checksums)
+        verify_checksums: bool,
+        /// key files are encrypted with when flushing, see [`set_encryption`](Self::set_encryption)
+        encryption: Option<EncryptionKey>,
+        /// key the backpack is signed with when flushing, see [`set_signing`](Self::set_signing)
+        #[cfg(feature = "signing")]
+        signing: Option<SigningKey>,
+        /// contents of the signature entry the backpack was opened with
+        #[cfg(feature = "signing")]
+        signature: Option<Vec<u8>>,
+        /// whether flushes go through a journal, see [`set_journaled`](Self::set_journaled)
+        journaled: bool,
 
         closed: bool,
     },
@@ -49,404 +673,3812 @@ pub enum BackPack<'f, 'backpack> {
 
 impl<'f, 'backpack: 'f> BackPack<'f, 'backpack> {
     pub fn open<E: Into<PackError>>(backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
-        if false {
-            Self::open_partial(backing)
-        } else {
-            Self::open_complete(backing)
+        Self::open_complete(backing)
+    }
+
+    pub(crate) fn handles(&self) -> &Arc<Handles> {
+        match self {
+            BackPack::Parsed { handles, .. } => handles,
+        }
+    }
+
+    /// The number of handles to files in this backpack which are still open, counting every
+    /// [`InMemoryFile`] returned by [`get_file`](Self::get_file), [`add_file`](Self::add_file)
+    /// and friends, and the readers returned by [`entry`](Self::entry).
+    pub fn open_handles(&self) -> usize {
+        self.handles().count()
+    }
+
+    /// Remember where every handle to a file is opened, and report the handles which are
+    /// still open when the backpack is dropped. Capturing a backtrace per handle is slow,
+    /// so this is meant for tracking down leaked handles, off by default.
+    pub fn set_leak_detection(&mut self, enabled: bool) {
+        match self {
+            BackPack::Parsed { handles, .. } => handles.set_leak_detection(enabled),
+        }
+    }
+
+    fn report_leaked_handles(&self) {
+        let BackPack::Parsed { handles, offsets, .. } = self;
+
+        let leaked = handles.leaked();
+        if leaked.is_empty() {
+            return;
+        }
+
+        log::error!("backpack dropped with {} open handles", leaked.len());
+        let offsets = offsets.read();
+        for (identifier, backtrace) in leaked {
+            let name = offsets.iter()
+                .find(|(_, i)| **i == identifier)
+                .map_or("<removed file>", |(name, _)| name.as_str());
+            log::error!("handle to {name} leaked, opened at:\n{backtrace}");
+        }
+    }
+
+    pub(crate) fn retrieve_slice(&self, s: &PackSlice) -> &RwLock<Vec<u8>> {
+        match self {
+            BackPack::Parsed { data, .. } => {
+                data.get(&s.identifier())
+                    .expect("no such file (only packslices obtained from a pack should be used in as_slice)")
+
+            }
+        }
+    }
+
+    pub(crate) fn convert_offset(sorted_toc_block_locations: &[u64], offset: u64) -> u64 {
+        format::index::absolute_offset(sorted_toc_block_locations, offset)
+    }
+
+    /// The toc blocks for `offsets`, for a table of contents which is written at `first_block`,
+    /// with `compressed` and `encrypted` entries, the `checksums` of entries and the `alignments`
+    /// their contents start at.
+    pub(crate) fn create_toc_at(offsets: &HashMap<String, (u64, u64)>, hidden: &HashSet<String>, compressed: &Compressed, encrypted: &HashSet<String>, checksums: &HashMap<String, u32>, alignments: &HashMap<String, u64>, first_block: u64) -> error::Result<Vec<Vec<u8>>> {
+        Self::create_toc_in(offsets, hidden, compressed, encrypted, checksums, alignments, &[], first_block)
+    }
+
+    /// Like [`create_toc_at`](Self::create_toc_at), with the first blocks written at `reused`,
+    /// the locations of the blocks of an existing table of contents, see [`create_toc_placed`](Self::create_toc_placed).
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_toc_in(offsets: &HashMap<String, (u64, u64)>, hidden: &HashSet<String>, compressed: &Compressed, encrypted: &HashSet<String>, checksums: &HashMap<String, u32>, alignments: &HashMap<String, u64>, reused: &[u64], first_block: u64) -> error::Result<Vec<Vec<u8>>> {
+        Self::create_toc_placed(offsets, reused, first_block, |name| {
+            let mut flags = 0;
+            if hidden.contains(name) {
+                flags |= HIDDEN;
+            }
+            if name.ends_with('/') {
+                flags |= DIRECTORY;
+            }
+
+            let flags = U16Le::new(flags).to_bytes();
+            let compression = compressed.get(name).map(|(method, len)| compression::encode_field(*method, *len));
+            let checksum = checksums.get(name).map(|crc| U32Le::new(*crc).to_bytes());
+            let encryption = encryption::encode_field();
+            let alignment = alignments.get(name)
+                .filter(|alignment| **alignment > 1 && offsets.get(name).is_some_and(|(_, length)| *length != 0))
+                .map(|alignment| [alignment.trailing_zeros() as u8]);
+
+            let mut fields = Vec::new();
+            if flags != [0; 2] {
+                fields.push((FLAGS_FIELD, flags.as_slice()));
+            }
+            if let Some(compression) = &compression {
+                fields.push((COMPRESSION_FIELD, compression.as_slice()));
+            }
+            if let Some(checksum) = &checksum {
+                fields.push((CHECKSUM_FIELD, checksum.as_slice()));
+            }
+            if encrypted.contains(name) {
+                fields.push((ENCRYPTION_FIELD, encryption.as_slice()));
+            }
+            if let Some(alignment) = &alignment {
+                fields.push((ALIGNMENT_FIELD, alignment.as_slice()));
+            }
+            Ok(encode_fields(&fields)?)
+        })
+    }
+
+    /// Like [`create_toc_at`](Self::create_toc_at), with the encoded fields of every entry given by `fields`.
+    pub(crate) fn create_toc_with(offsets: &HashMap<String, (u64, u64)>, first_block: u64, fields: impl Fn(&str) -> error::Result<Vec<u8>>) -> error::Result<Vec<Vec<u8>>> {
+        Self::create_toc_placed(offsets, &[], first_block, fields)
+    }
+
+    /// The toc blocks for `offsets`, of which the first are written at the locations in `reused` and
+    /// the rest directly after each other from `first_block`. There are at least as many blocks as
+    /// locations in `reused`, because [converting offsets](Self::convert_offset) skips every one of them.
+    pub(crate) fn create_toc_placed(offsets: &HashMap<String, (u64, u64)>, reused: &[u64], first_block: u64, fields: impl Fn(&str) -> error::Result<Vec<u8>>) -> error::Result<Vec<Vec<u8>>> {
+        if offsets.is_empty() && reused.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut res = Vec::new();
+        let mut curr = Cursor::new(Vec::new());
+        curr.write_all(&TocBlockHeader::default().to_bytes())?;
+
+        let mut offsets = offsets.iter().collect::<Vec<_>>();
+        offsets.sort_by_key(|(_, (i, _))| i);
+
+        for (s, (offset, length)) in offsets {
+            let fields = fields(s)?;
+            let name = entry_name::key_bytes(s);
+            let entry = TocEntry { name: &name, offset: *offset, length: *length, fields: &fields };
+            let filled = curr.stream_position()?;
+
+            if filled + entry.encoded_len() as u64 > TOC_SIZE as u64 {
+                res.push(curr.into_inner());
+                curr = Cursor::new(Vec::new());
+                curr.write_all(&TocBlockHeader::default().to_bytes())?;
+            }
+
+            entry.write_to(&mut curr)?;
+        }
+        res.push(curr.into_inner());
+        while res.len() < reused.len() {
+            res.push(TocBlockHeader::default().to_bytes().to_vec());
+        }
+
+        let location = |i: usize| match reused.get(i) {
+            Some(location) => *location,
+            None => first_block + (i - reused.len()) as u64 * TOC_SIZE as u64,
+        };
+        let blocks = res.len();
+        for (i, buf) in res.iter_mut().enumerate() {
+            let header = TocBlockHeader {
+                filled: U16Le::new(buf.len() as u16),
+                next: U64Le::new(if i + 1 < blocks { location(i + 1) } else { 0 }),
+            };
+            buf[..TocBlockHeader::SIZE].copy_from_slice(&header.to_bytes());
+            buf.resize(TOC_SIZE as usize, 0);
+        }
+
+        Ok(res)
+    }
+
+    /// Writes the header and table of contents, returns where the toc blocks were written.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn write_headers(f: &mut impl Write, size: u64, offsets: &HashMap<String, (u64, u64)>, hidden: &HashSet<String>, compressed: &Compressed, encrypted: &HashSet<String>, checksums: &HashMap<String, u32>, alignments: &HashMap<String, u64>) -> error::Result<Vec<u64>> {
+        let toc_blocks = Self::create_toc_at(offsets, hidden, compressed, encrypted, checksums, alignments, PACK_HEADER_SIZE)?;
+        let toc_block_locations = (0..toc_blocks.len() as u64)
+            .map(|i| PACK_HEADER_SIZE + i * TOC_SIZE as u64)
+            .collect();
+
+        let first_toc = if toc_blocks.is_empty() { 0 } else { PACK_HEADER_SIZE };
+        f.write_all(&PackHeader::new(size, first_toc).for_toc(&toc_blocks).to_bytes())?;
+        for i in toc_blocks {
+            f.write_all(&i)?;
+        }
+
+        Ok(toc_block_locations)
+    }
+
+    /// Parse the entries in a toc block into `index`.
+    pub(crate) fn parse_toc_block(filled: u16, block: &[u8], index: &mut Index) -> error::Result<()> {
+        Ok(format::index::parse_toc_block(filled, block, |record| {
+            if record.hidden {
+                index.hidden.insert(record.name.clone());
+            }
+            match record.compression {
+                Some((Compression::None, _)) => {
+                    index.stored.insert(record.name.clone());
+                }
+                Some(compression) => {
+                    index.compressed.insert(record.name.clone(), compression);
+                }
+                None => {}
+            }
+            if let Some(checksum) = record.checksum {
+                index.checksums.insert(record.name.clone(), checksum);
+            }
+            if record.encrypted {
+                index.encrypted.insert(record.name.clone());
+            }
+            if let Some(alignment) = record.alignment {
+                index.alignments.insert(record.name.clone(), alignment);
+            }
+            index.offsets.insert(record.name, (record.offset, record.length));
+        })?)
+    }
+
+    /// Read the index of a backpack written in an older version of the format. Readers for
+    /// older versions go here, so they can be opened and [migrated](Self::migrate).
+    fn parse_backwards_compatible(_file: &mut (impl Read + Seek), version: u16) -> error::Result<Index>{
+        Err(PackError::Incompatible(version))
+    }
+
+    pub(crate) fn parse_headers(file: &mut (impl Read + Seek)) -> error::Result<Index> {
+        let header = PackHeader::read_from(file)?;
+        let version = header.version();
+        if version != PACK_VERSION {
+            return Self::parse_backwards_compatible(file, version);
+        }
+
+        let first_toc_offset = header.first_toc.get();
+
+        assert_eq!(file.stream_position()?, PACK_HEADER_SIZE);
+
+        let mut index = Index::default();
+        index.toc_blocks = Self::read_toc_blocks(file, first_toc_offset, |filled, block| {
+            Self::parse_toc_block(filled, block, &mut index)
+        })?;
+
+        Ok(index)
+    }
+
+    /// Read the chain of toc blocks starting at `first_toc_offset`, passing how much of each
+    /// block is filled and its entries to `block`. Returns where the blocks are, in chain order.
+    pub(crate) fn read_toc_blocks(file: &mut (impl Read + Seek), first_toc_offset: u64, mut block: impl FnMut(u16, &[u8]) -> error::Result<()>) -> error::Result<Vec<u64>> {
+        let mut toc_blocks = Vec::new();
+        let mut next_toc_offset = first_toc_offset;
+
+        while next_toc_offset != 0 {
+            let offset = next_toc_offset;
+            // a damaged pointer could send us around in circles
+            if toc_blocks.contains(&offset) {
+                return Err(PackError::CorruptIndex { offset });
+            }
+            toc_blocks.push(offset);
+
+            file.seek(SeekFrom::Start(offset))?;
+
+            let mut header_bytes = [0u8; TocBlockHeader::SIZE];
+            file.read_exact(&mut header_bytes)?;
+            let header = TocBlockHeader::from_bytes(&header_bytes);
+            next_toc_offset = header.next.get();
+
+            let mut toc_block_bytes = [0u8; TOC_SIZE as usize - TocBlockHeader::SIZE];
+            file.read_exact(&mut toc_block_bytes)?;
+            header.entries_len()
+                .map_err(PackError::from)
+                .and_then(|filled| block(filled, &toc_block_bytes))
+                .map_err(|e| match e {
+                    PackError::InvalidEntry => PackError::CorruptIndex { offset },
+                    e => e,
+                })?;
+        }
+
+        Ok(toc_blocks)
+    }
+
+    pub fn open_complete<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        Self::open_with_passphrase(file.try_into().map_err(Into::into)?, None)
+    }
+
+    /// Open a backpack [encrypted](Self::set_encryption) with `passphrase`. Fails with
+    /// [`PackError::WrongPassphrase`] when it's the wrong one. Unencrypted backpacks open as usual.
+    /// Files are written encrypted with the same key when flushing.
+    #[cfg(feature = "crypto")]
+    pub fn open_encrypted<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>, passphrase: &str) -> error::Result<Self> {
+        Self::open_with_passphrase(file.try_into().map_err(Into::into)?, Some(passphrase))
+    }
+
+    fn open_with_passphrase(mut file: RawFile<'f, 'backpack>, passphrase: Option<&str>) -> error::Result<Self> {
+        let (Index { mut offsets, mut toc_blocks, hidden, compressed, checksums, encrypted, mut alignments, .. }, index_protection) = protection::parse_protected(&mut file)?;
+        toc_blocks.sort();
+
+        let data = FrozenMap::new();
+        let mut total_size = 0;
+        let mut stored_size = 0;
+
+        for (name, (offset, length)) in &offsets {
+            if *length == 0 {
+                continue;
+            }
+            stored_size = stored_size.max(offset + length);
+            // read when they're decoded, below
+            if compressed.contains_key(name) || encrypted.contains(name) {
+                continue;
+            }
+
+            let new_offset = Self::convert_offset(&toc_blocks, *offset);
+            file.seek(SeekFrom::Start(new_offset))?;
+
+            let mut buf = vec![0; *length as usize];
+            file.read_exact(&mut buf)?;
+
+            total_size += buf.len() as u64;
+            data.insert((*offset, *length), Box::new(RwLock::new(buf)));
+        }
+
+        let sidecars = Self::read_sidecars(&mut file, &offsets, &toc_blocks)?;
+
+        let encryption = match offsets.remove(ENCRYPTION_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                Some(EncryptionKey::from_entry(passphrase, &data.get(&key).ok_or(PackError::InvalidEntry)?.read())?)
+            }
+            None if !encrypted.is_empty() => return Err(PackError::InvalidEntry),
+            None => None,
+        };
+        let signature = match offsets.remove(SIGNATURE_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                Some(data.get(&key).ok_or(PackError::InvalidEntry)?.read().clone())
+            }
+            None => None,
+        };
+        #[cfg(not(feature = "signing"))]
+        let _ = signature;
+
+        let expiry = match offsets.remove(EXPIRY_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                decode_times(&data.get(&key).ok_or(PackError::InvalidEntry)?.read())?
+            }
+            None => HashMap::new(),
+        };
+        let modified = match offsets.remove(MODIFIED_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                decode_times(&data.get(&key).ok_or(PackError::InvalidEntry)?.read())?
+            }
+            None => HashMap::new(),
+        };
+        let attributes = match offsets.remove(ATTRIBUTES_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                decode_attributes(&data.get(&key).ok_or(PackError::InvalidEntry)?.read())?
+            }
+            None => HashMap::new(),
+        };
+        let aliases = match offsets.remove(ALIAS_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                decode_aliases(&data.get(&key).ok_or(PackError::InvalidEntry)?.read())?
+            }
+            None => HashMap::new(),
+        };
+        let tiers = match offsets.remove(TIERS_ENTRY) {
+            Some(key) => {
+                total_size -= key.1;
+                decode_tiers(&data.get(&key).ok_or(PackError::InvalidEntry)?.read())?
+            }
+            None => HashMap::new(),
+        };
+        debug_assert!(METADATA_ENTRIES.iter().all(|name| !offsets.contains_key(*name)), "metadata entry which isn't decoded");
+        if encryption.is_some() {
+            encryption::check_encrypted(&offsets, &encrypted)?;
+        }
+
+        let end_offset = separate_empty(&mut offsets, stored_size);
+        for key in offsets.values().filter(|(_, length)| *length == 0) {
+            data.insert(*key, Box::default());
         }
+
+        let unverified = checksums.iter()
+            .filter(|(name, _)| !compressed.contains_key(*name) && !encrypted.contains(*name))
+            .filter_map(|(name, checksum)| Some((*offsets.get(name)?, *checksum)))
+            .collect::<HashMap<_, _>>();
+
+        let packed = offsets.iter()
+            .filter(|(name, (_, length))| *length != 0 && (compressed.contains_key(*name) || encrypted.contains(*name)))
+            .map(|(name, key)| (name.clone(), *key))
+            .collect::<Vec<_>>();
+        let mut stored_packed = HashMap::new();
+        let end_offset = separate_packed(&mut offsets, &compressed, &encrypted, end_offset, &mut stored_packed);
+        let (end_offset, separated) = separate_shared(&mut offsets, end_offset);
+        for (name, shared) in separated {
+            let copy = data.get(&shared).ok_or(PackError::InvalidEntry)?.read().clone();
+            data.insert(offsets[&name], Box::new(RwLock::new(copy)));
+        }
+        // flushing keeps files compressed the way they were
+        let compressions = compressed.iter()
+            .map(|(name, (method, _))| (name.clone(), *method))
+            .collect::<HashMap<_, _>>();
+        for (name, (offset, length)) in packed {
+            let mut buf = vec![0; length as usize];
+            file.read_exact_at(Self::convert_offset(&toc_blocks, offset), &mut buf)?;
+            // they have to be read now, so check them now instead of decoding garbage
+            if checksums.get(&name).is_some_and(|checksum| crc32(&buf) != *checksum) {
+                return Err(PackError::ChecksumMismatch(PathBuf::from(name)));
+            }
+
+            if encrypted.contains(&name) {
+                buf = encryption.as_ref().ok_or(PackError::InvalidEntry)?.decrypt(&name, &buf)?;
+            }
+            let key = offsets[&name];
+            if let Some((method, _)) = compressed.get(&name) {
+                buf = method.decompress(&buf, key.1)?;
+            }
+            total_size += key.1;
+            data.insert(key, Box::new(RwLock::new(buf)));
+        }
+
+        // kept when flushing, for files which aren't replaced
+        alignments.retain(|name, _| offsets.contains_key(name));
+        Ok(Self::Parsed {
+            file: Some(file),
+            offsets: RwLock::new(offsets),
+            removals: FrozenMap::new(),
+            data,
+
+            total_size: AtomicU64::new(total_size),
+            output_mode: OutputMode::Native,
+            alignment: 1,
+
+            toc_blocks,
+            stored_size,
+            end_offset: AtomicU64::new(end_offset),
+
+            trace: Mutex::new(None),
+            order: HashMap::new(),
+            tiers,
+            validators: Vec::new(),
+            quotas: HashMap::new(),
+            write_limits: WriteLimits::default(),
+            collision: Collision::default(),
+            sidecars,
+            expiry,
+            modified,
+            attributes,
+            index_protection,
+            handles: Arc::default(),
+            hidden,
+            name_hasher: None,
+            memory_limit: None,
+            evicted: Mutex::new(HashSet::new()),
+            subscribers: Subscribers::default(),
+            alignments: Mutex::new(alignments),
+            aliases,
+            read_retry: RetryPolicy::none(),
+            compression: Compression::None,
+            min_compression_ratio: 0.0,
+            compressions: Mutex::new(compressions),
+            unverified: Mutex::new(unverified),
+            packed: stored_packed,
+            sorted_names: Mutex::new(None),
+            name_matching: NameMatching::Exact,
+            matched_names: Mutex::new(None),
+            verify_checksums: false,
+            journaled: false,
+            encryption,
+            #[cfg(feature = "signing")]
+            signing: None,
+            #[cfg(feature = "signing")]
+            signature,
+
+            // not closed
+            closed: false
+        })
+    }
+
+    /// Open the last generation of the backpack at `path` which the [`PackWriter`] writing it
+    /// [committed](PackWriter::commit), while the writer keeps adding entries. Only the file up
+    /// to the end of that generation is read, into memory, and its table of contents is checked
+    /// against the trailer written with it. A backpack without a commit, like a finished one,
+    /// is read whole. Changes to the returned backpack are not written back to the file.
+    pub fn open_committed(path: impl AsRef<Path>) -> error::Result<Self> {
+        // a header torn by a commit at the same time doesn't match its trailer
+        const ATTEMPTS: u32 = 3;
+        let path = path.as_ref();
+        let file = RawFile::open(path)?;
+
+        let mut last_error = None;
+        for _ in 0..ATTEMPTS {
+            match Self::read_committed(&file) {
+                Ok(Some(bytes)) => return Self::open_complete(RawFile::from(bytes)),
+                Ok(None) => return Self::open_complete(RawFile::from(std::fs::read(path).at_path(path)?)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("read at least once"))
+    }
+
+    /// The bytes of `file` up to the end of its last [committed](PackWriter::commit) generation,
+    /// or `None` when no commit trailer follows the table of contents the header points to.
+    fn read_committed(file: &RawFile) -> error::Result<Option<Vec<u8>>> {
+        let mut header = [0; PACK_HEADER_SIZE as usize];
+        file.read_exact_at(0, &mut header)?;
+        let parsed = PackHeader::parse(&header)?;
+        let (size, first_toc) = (parsed.size.get(), parsed.first_toc.get());
+
+        let mut toc_blocks = Vec::new();
+        let mut block = vec![0; TOC_SIZE as usize];
+        let mut next = first_toc;
+        while next != 0 {
+            if toc_blocks.contains(&next) {
+                return Err(format::FormatError::CorruptIndex { offset: next }.into());
+            }
+            file.read_exact_at(next, &mut block)?;
+            toc_blocks.push(next);
+            next = TocBlockHeader::from_bytes(block[..TocBlockHeader::SIZE].try_into().expect("sliced to the size")).next.get();
+        }
+
+        let end = PACK_HEADER_SIZE + size + toc_blocks.len() as u64 * TOC_SIZE as u64;
+        let mut trailer = [0; CommitTrailer::SIZE];
+        if file.read_exact_at(end, &mut trailer).is_err() || !trailer.ends_with(COMMIT_MAGIC) {
+            return Ok(None);
+        }
+        let trailer = CommitTrailer::from_bytes(&trailer);
+
+        let mut bytes = vec![0; usize::try_from(end).map_err(|_| format::FormatError::Truncated)?];
+        file.read_exact_at(0, &mut bytes)?;
+        // by now the header may point to a later generation
+        bytes[..header.len()].copy_from_slice(&header);
+
+        // the blocks are checked as they were read, so the bytes are of one generation
+        let mut crc = Crc32::new();
+        for offset in &toc_blocks {
+            let block = usize::try_from(*offset).ok()
+                .and_then(|start| bytes.get(start..start.checked_add(TOC_SIZE as usize)?))
+                .ok_or(format::FormatError::Truncated)?;
+            crc.update(block);
+        }
+        if (trailer.size.get(), trailer.first_toc.get(), trailer.toc_crc.get()) != (size, first_toc, crc.finish()) {
+            return Err(format::FormatError::DamagedIndex.into());
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Open the backpack at `path` to change it in place, with [journaling](Self::set_journaled) on.
+    /// A change to it which was interrupted, like by a crash, is [finished or undone](journal::recover) first.
+    pub fn open_journaled(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        journal::recover(path)?;
+        let file = std::fs::File::options().read(true).write(true).open(path).at_path(path)?;
+        let mut bp = Self::open(RawFile::from(file).with_name(path))?;
+        bp.set_journaled(true)?;
+        Ok(bp)
+    }
+
+    /// Find the sidecar records in the gaps between files.
+    fn read_sidecars(file: &mut RawFile, offsets: &Offsets, toc_blocks: &[u64]) -> error::Result<HashMap<String, Vec<u8>>> {
+        let mut ranges = offsets.values().copied().collect::<Vec<_>>();
+        ranges.sort();
+        ranges.dedup();
+
+        let mut records = HashMap::new();
+        let mut prev_end = 0;
+        for (offset, length) in ranges {
+            let gap_start = Self::convert_offset(toc_blocks, prev_end);
+            let gap_end = Self::convert_offset(toc_blocks, offset);
+            // only look at gaps which aren't interrupted by a table of contents block
+            if offset >= prev_end + 4 && gap_end - gap_start == offset - prev_end {
+                let mut gap = vec![0; (offset - prev_end) as usize];
+                file.seek(SeekFrom::Start(gap_start))?;
+                file.read_exact(&mut gap)?;
+                if let Some(record) = aligned::read_sidecar(&gap) {
+                    records.insert(offset, record);
+                }
+            }
+            prev_end = prev_end.max(offset + length);
+        }
+
+        Ok(offsets.iter()
+            .filter_map(|(name, (offset, _))| Some((name.clone(), records.get(offset)?.clone())))
+            .collect())
+    }
+
+    /// Open a backpack written with [`export_split`](Self::export_split) from its metadata file
+    /// and all of its data files. The pack is read into memory, changes are never written back.
+    pub fn open_split(mut metadata: impl Read + Seek, data_files: &mut [impl Read + Seek]) -> error::Result<Self> {
+        let entries = SplitPack::read_metadata(&mut metadata)?;
+
+        let mut contents = Vec::new();
+        for entry in &entries {
+            let data_file = data_files.get_mut(entry.data_file as usize).ok_or(PackError::InvalidEntry)?;
+            // don't trust the metadata with how much to allocate
+            let data_file_len = data_file.seek(SeekFrom::End(0))?;
+            if entry.offset.checked_add(entry.length).is_none_or(|end| end > data_file_len) {
+                return Err(PackError::InvalidEntry);
+            }
+            data_file.seek(SeekFrom::Start(entry.offset))?;
+            let mut buf = vec![0; entry.length as usize];
+            data_file.read_exact(&mut buf)?;
+            contents.push(buf);
+        }
+
+        let files = entries.iter()
+            .zip(&contents)
+            .map(|(entry, contents)| (entry.name.as_str(), contents.as_slice()))
+            .collect::<Vec<_>>();
+        let hidden = entries.iter()
+            .filter(|entry| entry.hidden)
+            .map(|entry| entry.name.clone())
+            .collect();
+
+        let mut pack = Vec::new();
+        Self::write_native(&mut pack, &files, |_| 1, &HashMap::new(), &hidden, &Compressed::new(), &HashSet::new())?;
+        Self::open_complete(RawFile::from(pack))
+    }
+
+    /// Open a backpack, accepting as much of a malformed one as `mode` says.
+    pub fn open_with_mode<E: Into<PackError>>(backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>, mode: ReadMode) -> error::Result<Self> {
+        let mut file = backing.try_into().map_err(Into::into)?;
+        match mode {
+            ReadMode::Normal => Self::open(file),
+            ReadMode::Strict => {
+                salvage::check_strict(&mut file)?;
+                Self::open(file)
+            }
+            ReadMode::Recover => {
+                let (pack, report) = Self::recover(file)?;
+                if !report.is_complete() {
+                    log::warn!("recovered a damaged backpack, {} entries and {} toc blocks were lost", report.skipped.len(), report.lost_blocks.len());
+                }
+                Ok(pack)
+            }
+        }
+    }
+
+    /// Salvage as many entries as possible from a truncated or damaged backpack, into a new
+    /// backpack in memory, and report what couldn't be salvaged. Entries with a damaged index
+    /// record or damaged contents are left out, as are encrypted ones and the signature.
+    /// Write the result somewhere with [`freeze`](Self::freeze).
+    pub fn recover<E: Into<PackError>>(backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<(Self, SalvageReport)> {
+        let mut file = backing.try_into().map_err(Into::into)?;
+        let Salvaged { entries, hidden, report } = salvage::salvage(&mut file)?;
+
+        let entries = entries.iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+            .collect::<Vec<_>>();
+        let mut pack = Vec::new();
+        Self::write_native(&mut pack, &entries, |_| 1, &HashMap::new(), &hidden, &Compressed::new(), &HashSet::new())?;
+        Ok((Self::open_complete(RawFile::from(pack))?, report))
+    }
+
+    /// Pack every file in `dir` and its subdirectories the `options` ask for, named by their
+    /// path relative to `dir`. Empty directories are kept. The pack is kept in memory,
+    /// write it to a file with [`freeze`](Self::freeze).
+    pub fn from_directory(dir: impl AsRef<Path>, options: &DirectoryOptions) -> error::Result<Self> {
+        Self::from_directory_with_progress(dir, options, &mut progress::ignore)
+    }
+
+    /// Like [`from_directory`](Self::from_directory), reporting to `progress` after every file is read.
+    /// When the progress is [cancelled](Progress::cancelled) this fails with [`PackError::Cancelled`].
+    pub fn from_directory_with_progress(dir: impl AsRef<Path>, options: &DirectoryOptions, progress: &mut dyn Progress) -> error::Result<Self> {
+        let mut files = Vec::new();
+        directory::collect_files(dir.as_ref(), "", options, &mut files)?;
+        for (name, _) in &files {
+            check_reserved(name, Path::new(name))?;
+        }
+        let sizes = files.iter()
+            .map(|(name, path)| match std::fs::symlink_metadata(path) {
+                _ if name.ends_with('/') => 0,
+                Ok(metadata) if options.keep_links && metadata.file_type().is_symlink() => metadata.len(),
+                _ => std::fs::metadata(path).map_or(0, |metadata| metadata.len()),
+            })
+            .collect::<Vec<_>>();
+        options.limits.check_entries(files.len())?;
+        let mut total = 0;
+        for ((name, _), size) in files.iter().zip(&sizes) {
+            total += size;
+            options.limits.check_size(name, *size, total)?;
+        }
+        let mut tracker = Tracker::new(progress, files.len(), sizes.iter().sum());
+        // files may have grown since
+        let mut total = 0;
+
+        let mut contents = Vec::new();
+        let mut metadata = Vec::new();
+        let mut symlinks = Vec::new();
+        // hard links to a file packed before them, and the first name of every file with more than one
+        let mut hardlinks = Vec::new();
+        let mut inodes = HashMap::new();
+        let mut holes = Vec::new();
+        for ((name, path), size) in files.iter().zip(sizes) {
+            let link_metadata = std::fs::symlink_metadata(path).at_path(path)?;
+            if options.keep_links && link_metadata.file_type().is_symlink() {
+                symlinks.push((name, std::fs::read_link(path).at_path(path)?));
+                contents.push((name.as_str(), Vec::new()));
+                metadata.push((name, FileMetadata { mode: None, ..link_metadata.into() }));
+                tracker.done(name, size)?;
+                continue;
+            }
+
+            let file_metadata = std::fs::metadata(path).at_path(path)?;
+            if let Some(inode) = directory::hardlink_id(&file_metadata).filter(|_| options.keep_links) {
+                if let Some(first) = inodes.get(&inode) {
+                    hardlinks.push((name, *first));
+                    tracker.done(name, size)?;
+                    continue;
+                }
+                inodes.insert(inode, name);
+            }
+            let data = if name.ends_with('/') { Vec::new() } else { std::fs::read(path).at_path(path)? };
+            total += data.len() as u64;
+            options.limits.check_size(name, data.len() as u64, total)?;
+            if options.keep_holes && !name.ends_with('/') {
+                let file_holes = std::fs::File::open(path).and_then(|f| sparse::holes(&f)).at_path(path)?;
+                // in case the file changed since it was read
+                let file_holes = sparse::zeroed_holes(&data, &file_holes);
+                if !file_holes.is_empty() {
+                    holes.push((name, file_holes));
+                }
+            }
+            contents.push((name.as_str(), data));
+            metadata.push((name, file_metadata.into()));
+            tracker.done(name, size)?;
+        }
+        let entries = contents.iter()
+            .map(|(name, contents)| (*name, contents.as_slice()))
+            .collect::<Vec<_>>();
+
+        let mut pack = Vec::new();
+        Self::write_native(&mut pack, &entries, |_| 1, &HashMap::new(), &HashSet::new(), &Compressed::new(), &HashSet::new())?;
+        let mut res = Self::open_complete(RawFile::from(pack))?;
+
+        for (name, target) in symlinks {
+            res.set_attribute(name, SYMLINK_ATTRIBUTE, EntryName::from(target.as_os_str()).into_bytes())?;
+        }
+        for (name, target) in hardlinks {
+            res.set_alias(name, target)?;
+        }
+        for (name, holes) in holes {
+            res.set_holes(name, &holes)?;
+        }
+        for (name, metadata) in metadata {
+            if options.keep_modified {
+                res.set_modified(name, metadata.modified)?;
+            }
+            if let Some(mode) = metadata.mode.filter(|_| options.keep_permissions) {
+                res.set_mode(name, Some(mode))?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Read the tar archive `reader` into a backpack in memory, the reverse of [`to_tar`](Self::to_tar).
+    /// Directories in the archive are [added](Self::add_dir), the modification times of files and
+    /// directories are kept, see [`modified`](Self::modified). Links and other special files are
+    /// skipped, and a file which is in the archive more than once is kept as it was last.
+    pub fn from_tar(reader: impl Read) -> error::Result<Self> {
+        Self::from_archive(tar::read_tar(reader)?)
+    }
+
+    /// Open the zip archive at `path` as a backpack in memory, see [`from_zip`](Self::from_zip).
+    pub fn open_zip(path: impl AsRef<Path>) -> error::Result<Self> {
+        Self::from_zip(std::io::BufReader::new(std::fs::File::open(&path).at_path(&path)?)).at_path(path)
+    }
+
+    /// Read the zip archive `reader` into a backpack in memory, so its files can be looked up and
+    /// listed like those of any backpack. Stored and deflated files can be read, deflated ones
+    /// need the `deflate` feature. Directories and modification times are kept like in
+    /// [`from_tar`](Self::from_tar), encrypted archives can't be read.
+    pub fn from_zip(mut reader: impl Read + Seek) -> error::Result<Self> {
+        Self::from_archive(zip::read_zip(&mut reader)?)
+    }
+
+    fn from_archive(members: Vec<tar::Member>) -> error::Result<Self> {
+        for member in &members {
+            check_reserved(&member.name, Path::new(&member.name))?;
+        }
+        let mut seen = HashSet::new();
+        let members = members.into_iter()
+            .rev()
+            .filter(|member| seen.insert(member.name.clone()))
+            .collect::<Vec<_>>();
+
+        let modified = members.iter()
+            .map(|member| (member.name.clone(), member.modified))
+            .collect::<HashMap<_, _>>();
+        let modified_contents = encode_times(&modified);
+        let mut entries = members.iter()
+            .map(|member| (member.name.as_str(), member.contents.as_slice()))
+            .collect::<Vec<_>>();
+        if !modified.is_empty() {
+            entries.push((MODIFIED_ENTRY, &modified_contents));
+        }
+
+        let mut pack = Vec::new();
+        Self::write_native(&mut pack, &entries, |_| 1, &HashMap::new(), &HashSet::new(), &Compressed::new(), &HashSet::new())?;
+        Self::open_complete(RawFile::from(pack))
+    }
+
+    /// Add entries to the backpack at `path` without rewriting it, see [`PackWriter::open_append`].
+    /// The entries are added when the writer is [finished](PackWriter::finish).
+    pub fn open_append(path: impl AsRef<Path>) -> error::Result<PackWriter<'f, 'backpack>> {
+        let file = std::fs::File::options().read(true).write(true).open(&path).at_path(&path)?;
+        PackWriter::open_append(RawFile::from(file).with_name(path))
+    }
+
+    /// Rewrite the backpack at `path` without [dead space](PackWriter::dead_space), like
+    /// [`flush`](Self::flush) does. Returns how many bytes smaller the file got.
+    pub fn compact(path: impl AsRef<Path>) -> error::Result<u64> {
+        Self::compact_with_progress(path, &mut progress::ignore)
+    }
+
+    /// Like [`compact`](Self::compact), reporting to `progress` as the files are gathered to be
+    /// written again. The rewritten file is written after the last update, so when the progress
+    /// is [cancelled](Progress::cancelled) the file is left as it was.
+    pub fn compact_with_progress(path: impl AsRef<Path>, progress: &mut dyn Progress) -> error::Result<u64> {
+        let before = std::fs::metadata(&path).at_path(&path)?.len();
+        let file = std::fs::File::options().read(true).write(true).open(&path).at_path(&path)?;
+        let mut bp = Self::open(RawFile::from(file).with_name(&path))?;
+        bp.flush_with_progress(progress).at_path(&path)?;
+        bp.close_drop_unwritten_changes().at_path(&path)?;
+        Ok(before.saturating_sub(std::fs::metadata(&path).at_path(&path)?.len()))
+    }
+
+    /// Rewrite the backpack at `path` in the current version of the format, when it's written in
+    /// an older one this version of the library can still read. Returns whether it was rewritten,
+    /// backpacks in the current version are left alone. Fails with [`PackError::Incompatible`]
+    /// for versions it can't read, like those of newer versions of the library.
+    pub fn migrate(path: impl AsRef<Path>) -> error::Result<bool> {
+        if Self::compatibility(&path)?.version == PACK_VERSION {
+            return Ok(false);
+        }
+        // opening reads the older version, flushing always writes the current one
+        Self::compact(&path)?;
+        Ok(true)
+    }
+
+    /// Find out which parts of the format the backpack at `path` uses, and whether this build
+    /// of the library can open it, without reading any of the files in it. Lets launchers
+    /// explain why a backpack can't be opened instead of only failing to.
+    pub fn compatibility(path: impl AsRef<Path>) -> error::Result<CompatibilityReport> {
+        Self::compatibility_of(std::io::BufReader::new(std::fs::File::open(&path).at_path(&path)?)).at_path(path)
+    }
+
+    /// Like [`compatibility`](Self::compatibility), for a backpack which isn't on disk.
+    pub fn compatibility_of(mut file: impl Read + Seek) -> error::Result<CompatibilityReport> {
+        CompatibilityReport::inspect(&mut file)
+    }
+
+    /// Summarize the backpack at `path` from its header and index, without reading any files
+    /// in it or opening it. Reading the index usually takes a single read.
+    pub fn stat(path: impl AsRef<Path>) -> error::Result<PackInfo> {
+        Self::stat_of(std::fs::File::open(&path).at_path(&path)?).at_path(path)
+    }
+
+    /// Like [`stat`](Self::stat), for a backpack which isn't on disk.
+    pub fn stat_of(file: impl Read + Seek) -> error::Result<PackInfo> {
+        Ok(info::read_index(file)?.0)
+    }
+
+    /// The files in the backpack at `path` with their sizes and where they're stored, sorted by
+    /// name, read like [`stat`](Self::stat) does. [Hidden](Self::set_hidden) files are left out,
+    /// and aliases aren't listed, since they're stored in an entry.
+    pub fn list_fast(path: impl AsRef<Path>) -> error::Result<Vec<IndexEntry>> {
+        Self::list_fast_of(std::fs::File::open(&path).at_path(&path)?).at_path(path)
+    }
+
+    /// Like [`list_fast`](Self::list_fast), for a backpack which isn't on disk.
+    pub fn list_fast_of(file: impl Read + Seek) -> error::Result<Vec<IndexEntry>> {
+        Ok(info::read_index(file)?.1)
+    }
+
+    /// Where the space in the backpack at `path` goes: stored and read sizes of its files,
+    /// the space its index takes and the space no file uses anymore, to decide whether to
+    /// [compact](Self::compact) it or which files to shrink. Read like [`stat`](Self::stat) does.
+    pub fn stats(path: impl AsRef<Path>) -> error::Result<PackStats> {
+        Self::stats_of(std::fs::File::open(&path).at_path(&path)?).at_path(path)
+    }
+
+    /// Like [`stats`](Self::stats), for a backpack which isn't on disk.
+    pub fn stats_of(file: impl Read + Seek) -> error::Result<PackStats> {
+        info::read_stats(file)
+    }
+
+    /// Open a backpack appended to an executable with [`sfx::build`](crate::sfx::build).
+    /// The pack is read into memory, changes are never written back to the executable.
+    pub fn open_appended(f: impl Read + Seek) -> error::Result<Self> {
+        Self::open(sfx::read_appended(f)?)
+    }
+
+    /// Open the backpack appended to the executable that is currently running.
+    /// See [`sfx::build`](crate::sfx::build).
+    pub fn from_current_exe() -> error::Result<Self> {
+        Self::open_appended(std::fs::File::open(std::env::current_exe()?)?)
+    }
+
+    /// Create a new pack in a file. Usually called after File::create().
+    /// Existing contents of the file are deleted.
+    ///
+    /// ```rust
+    /// # use backpack::RawFile;
+    /// # use backpack::BackPack;
+    /// # use backpack::PackError;
+    ///
+    /// # fn main() -> Result<(), PackError> {
+    ///     let file = RawFile::in_memory("test.bp");
+    ///     let bp = BackPack::create(file)?;
+    ///
+    ///     // when done with it
+    ///     bp.close();
+    /// #   Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn create<E: Into<PackError>>(backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        let mut file = backing.try_into().map_err(Into::into)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        Ok(Self::Parsed {
+            file: Some(file),
+            offsets: Default::default(),
+            removals: FrozenMap::new(),
+            data: FrozenMap::new(),
+            total_size: AtomicU64::new(0),
+            output_mode: OutputMode::Native,
+            alignment: 1,
+
+            toc_blocks: Vec::new(),
+            stored_size: 0,
+            end_offset: AtomicU64::new(0),
+
+            trace: Mutex::new(None),
+            order: HashMap::new(),
+            tiers: HashMap::new(),
+            validators: Vec::new(),
+            quotas: HashMap::new(),
+            write_limits: WriteLimits::default(),
+            collision: Collision::default(),
+            sidecars: Default::default(),
+            index_protection: IndexProtection::None,
+            expiry: Default::default(),
+            modified: Default::default(),
+            attributes: Default::default(),
+            handles: Arc::default(),
+            hidden: HashSet::new(),
+            name_hasher: None,
+            memory_limit: None,
+            evicted: Mutex::new(HashSet::new()),
+            subscribers: Subscribers::default(),
+            alignments: Mutex::new(HashMap::new()),
+            aliases: HashMap::new(),
+            read_retry: RetryPolicy::none(),
+            compression: Compression::None,
+            min_compression_ratio: 0.0,
+            compressions: Mutex::new(HashMap::new()),
+            unverified: Mutex::new(HashMap::new()),
+            packed: HashMap::new(),
+            sorted_names: Mutex::new(None),
+            name_matching: NameMatching::Exact,
+            matched_names: Mutex::new(None),
+            verify_checksums: false,
+            journaled: false,
+            encryption: None,
+            #[cfg(feature = "signing")]
+            signing: None,
+            #[cfg(feature = "signing")]
+            signature: None,
+
+            // not closed
+            closed: false,
+        })
+    }
+
+    /// Choose how the backpack is laid out the next time it's flushed.
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        match self {
+            BackPack::Parsed { output_mode, .. } => *output_mode = mode,
+        }
+    }
+
+    /// Align the start of every file in the backpack to a multiple of `alignment` bytes
+    /// the next time it's flushed, so entries can be mapped into memory and used in place.
+    /// Only applies to [`OutputMode::Native`]. `alignment` must be a power of two.
+    ///
+    /// The alignment of every file is recorded in the index, so readers can rely on it, see
+    /// [`PackReader::alignment`](crate::pack::PackReader::alignment). Files keep it when the
+    /// backpack is opened again, files added then get the backpack's alignment.
+    pub fn set_alignment(&mut self, new_alignment: u64) -> error::Result<()> {
+        if !new_alignment.is_power_of_two() {
+            return Err(PackError::BadAlignment(new_alignment));
+        }
+
+        match self {
+            BackPack::Parsed { alignment, .. } => *alignment = new_alignment,
+        }
+
+        Ok(())
+    }
+
+    pub fn alignment(&self) -> u64 {
+        match self {
+            BackPack::Parsed { alignment, .. } => *alignment,
+        }
+    }
+
+    /// Check every file added from now on with `validator`, rejecting files it finds invalid.
+    pub fn add_validator(&mut self, validator: impl Validator + 'static) {
+        match self {
+            BackPack::Parsed { validators, .. } => validators.push(Box::new(validator)),
+        }
+    }
+
+    /// Attach a small record to a file, like hints on how to load it. Records are stored in
+    /// the padding the backpack's [alignment](Self::set_alignment) leaves before files, so they
+    /// take no extra space. A record only survives a flush when the padding before the file
+    /// has room for it and 4 more bytes, so it's best kept much smaller than the alignment.
+    pub fn set_sidecar(&mut self, name: impl AsRef<Path>, record: Option<Vec<u8>>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, sidecars, .. } => {
+                if !offsets.read().contains_key(&name_str) {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+
+                match record {
+                    Some(record) => { sidecars.insert(name_str, record); }
+                    None => { sidecars.remove(&name_str); }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn sidecar(&self, name: impl AsRef<Path>) -> Option<&[u8]> {
+        match self {
+            BackPack::Parsed { sidecars, .. } => sidecars.get(&self.stored_name(name.as_ref()))
+                .map(Vec::as_slice),
+        }
+    }
+
+    /// Store names only as hashes made by `hasher`, so the names of files can't be read from
+    /// the backpack. Files can still be opened by name, but [`file_names`](Self::file_names)
+    /// and [`purge_expired`](Self::purge_expired) return the hashes. Set it right after creating or opening a backpack, with the same
+    /// hasher it was written with. Quotas don't see the directories of hashed names.
+    pub fn set_name_hasher(&mut self, hasher: impl NameHasher + 'static) {
+        match self {
+            BackPack::Parsed { name_hasher, .. } => *name_hasher = Some(Box::new(hasher)),
+        }
+    }
+
+    /// How names are compared when files are opened, added, renamed or removed, for backpacks
+    /// made on systems which don't tell names apart which only differ in case or in how they're
+    /// encoded in unicode. Adding a file under a name which matches one in the backpack adds it
+    /// under that name, following the [collision policy](Self::set_collision_policy). A name
+    /// which is in the backpack exactly is always used as is.
+    ///
+    /// Hashed names only match exactly, see [`set_name_hasher`](Self::set_name_hasher).
+    pub fn set_name_matching(&mut self, matching: NameMatching) {
+        match self {
+            BackPack::Parsed { name_matching, matched_names, .. } => {
+                *name_matching = matching;
+                *matched_names.get_mut() = None;
+            }
+        }
+    }
+
+    pub fn name_matching(&self) -> NameMatching {
+        match self {
+            BackPack::Parsed { name_matching, .. } => *name_matching,
+        }
+    }
+
+    /// The name `name` is stored under, after [normalizing](normalize_name) it, or the name in
+    /// the backpack it [matches](Self::set_name_matching).
+    fn stored_name(&self, name: &Path) -> String {
+        let name = self.exact_stored_name(name);
+        match self {
+            BackPack::Parsed { name_matching: NameMatching::Exact, .. } |
+            BackPack::Parsed { name_hasher: Some(_), .. } => name,
+            BackPack::Parsed { .. } => self.matching_name(&name).unwrap_or(name),
+        }
+    }
+
+    /// Like [`stored_name`](Self::stored_name), without looking for names it matches, for
+    /// while the offsets are locked.
+    fn exact_stored_name(&self, name: &Path) -> String {
+        let name = normalize_name(name);
+        match self {
+            BackPack::Parsed { name_hasher: Some(hasher), .. } => hasher.hash(&name),
+            _ => name,
+        }
+    }
+
+    /// The name of the file, directory or alias in the backpack which `name` matches, when it
+    /// isn't in the backpack exactly.
+    fn matching_name(&self, name: &str) -> Option<String> {
+        let BackPack::Parsed { offsets, aliases, name_matching, matched_names, .. } = self;
+        if offsets.read().contains_key(name) || aliases.contains_key(name) {
+            return None;
+        }
+
+        let key = name_matching.key(name);
+        if let Some((_, alias)) = aliases.iter().find(|(alias, _)| name_matching.key(alias) == key) {
+            return Some(alias.clone());
+        }
+        let names = self.sorted_names();
+        let mut matched_names = matched_names.lock();
+        if !matches!(&*matched_names, Some((made_from, _)) if Arc::ptr_eq(made_from, &names)) {
+            // the first of the names with the same key, like the backpack lists them
+            let mut by_key = HashMap::new();
+            for name in names.iter() {
+                by_key.entry(name_matching.key(name).into_owned()).or_insert_with(|| name.clone());
+            }
+            *matched_names = Some((names, by_key));
+        }
+        matched_names.as_ref().and_then(|(_, by_key)| by_key.get(key.as_ref()).cloned())
+    }
+
+    /// The name `name` is stored under, or the file it's an [alias](Self::set_alias) of.
+    fn resolved_name(&self, name: &Path) -> String {
+        let stored = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, aliases, .. } if !offsets.read().contains_key(&stored) => {
+                aliases.get(&stored).cloned().unwrap_or(stored)
+            }
+            _ => stored,
+        }
+    }
+
+    /// How the index is protected against damage from the next flush on.
+    pub fn set_index_protection(&mut self, protection: IndexProtection) {
+        match self {
+            BackPack::Parsed { index_protection, .. } => *index_protection = protection,
+        }
+    }
+
+    /// How [`add_file`](Self::add_file) handles adding a file under a name which is already taken.
+    /// Files are overwritten by default.
+    pub fn set_collision_policy(&mut self, policy: Collision) {
+        match self {
+            BackPack::Parsed { collision, .. } => *collision = policy,
+        }
+    }
+
+    /// Fail adding files which would go over `limits`, with [`PackError::EntryTooLarge`],
+    /// [`PackError::PackTooLarge`] or [`PackError::TooManyEntries`]. At most one byte more than
+    /// the size limit of an entry is read from a file being added. Files already in the backpack
+    /// are not affected, but count towards the limits.
+    pub fn set_write_limits(&mut self, limits: WriteLimits) {
+        match self {
+            BackPack::Parsed { write_limits, .. } => *write_limits = limits,
+        }
+    }
+
+    /// Fail when adding `length` bytes as `name_str`, which is `name` before hashing, would go
+    /// over `limits`, with the files in `offsets`.
+    fn check_write_limits(limits: &WriteLimits, offsets: &Offsets, name_str: &str, name: &Path, length: u64) -> error::Result<()> {
+        if *limits == WriteLimits::default() {
+            return Ok(());
+        }
+        let others = offsets.iter().filter(|(n, _)| n.as_str() != name_str);
+        limits.check_entries(others.clone().count() + 1)?;
+        let total = others.map(|(_, (_, length))| length).sum::<u64>() + length;
+        limits.check_size(&entry_name::key_of(name.as_os_str()), length, total)
+    }
+
+    /// Limit the files whose names start with `prefix`, for example `user_saves/`.
+    /// Adding a file which would exceed the quota fails with [`PackError::QuotaExceeded`];
+    /// files already in the backpack are not affected. `None` removes the quota.
+    pub fn set_quota(&mut self, prefix: impl Into<String>, quota: Option<Quota>) {
+        match self {
+            BackPack::Parsed { quotas, .. } => match quota {
+                Some(quota) => { quotas.insert(prefix.into(), quota); }
+                None => { quotas.remove(&prefix.into()); }
+            },
+        }
+    }
+
+    /// Make `name` expire at `time`, after which [`purge_expired`](Self::purge_expired) removes it.
+    /// `None` makes it never expire. Expiry times are stored in the backpack when it's flushed.
+    pub fn set_expiry(&mut self, name: impl AsRef<Path>, time: Option<SystemTime>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, expiry, .. } => {
+                if !offsets.read().contains_key(&name_str) {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+
+                match time {
+                    Some(time) => {
+                        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                        expiry.insert(name_str, secs);
+                    }
+                    None => { expiry.remove(&name_str); }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn expiry(&self, name: impl AsRef<Path>) -> Option<SystemTime> {
+        match self {
+            BackPack::Parsed { expiry, .. } => expiry.get(&self.stored_name(name.as_ref()))
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(*secs)),
+        }
+    }
+
+    /// Record that `name` was last modified at `time`, or forget when it was with `None`.
+    /// The backpack doesn't keep track of this itself, it's for files which come from somewhere
+    /// with modification times, like [tar archives](Self::from_tar). Stored when it's flushed.
+    pub fn set_modified(&mut self, name: impl AsRef<Path>, time: Option<SystemTime>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, modified, .. } => {
+                if !offsets.read().contains_key(&name_str) {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+
+                match time {
+                    Some(time) => {
+                        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                        modified.insert(name_str, secs);
+                    }
+                    None => { modified.remove(&name_str); }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// When `name` was last modified, if that was [recorded](Self::set_modified).
+    pub fn modified(&self, name: impl AsRef<Path>) -> Option<SystemTime> {
+        match self {
+            BackPack::Parsed { modified, .. } => modified.get(&self.stored_name(name.as_ref()))
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(*secs)),
+        }
+    }
+
+    /// Set the user defined attribute `key` of `name` to `value`, like a content type, a hash of
+    /// its source or the build it comes from. Attributes are stored in the index when flushing,
+    /// and go when the file is removed.
+    pub fn set_attribute(&mut self, name: impl AsRef<Path>, key: &str, value: impl Into<Vec<u8>>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, attributes, .. } => {
+                if !offsets.read().contains_key(&name_str) {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+                attributes.entry(name_str).or_default().insert(key.to_string(), value.into());
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the attribute `key` of `name`, returning its value if it had one.
+    pub fn remove_attribute(&mut self, name: impl AsRef<Path>, key: &str) -> Option<Vec<u8>> {
+        let name_str = self.stored_name(name.as_ref());
+        match self {
+            BackPack::Parsed { attributes, .. } => {
+                let file_attributes = attributes.get_mut(&name_str)?;
+                let res = file_attributes.remove(key);
+                if file_attributes.is_empty() {
+                    attributes.remove(&name_str);
+                }
+                res
+            }
+        }
+    }
+
+    /// The attribute `key` of `name`, if it was [set](Self::set_attribute).
+    pub fn attribute(&self, name: impl AsRef<Path>, key: &str) -> Option<&[u8]> {
+        match self {
+            BackPack::Parsed { attributes, .. } => attributes.get(&self.stored_name(name.as_ref()))?
+                .get(key)
+                .map(Vec::as_slice),
+        }
+    }
+
+    /// All attributes of `name`, by key.
+    pub fn attributes(&self, name: impl AsRef<Path>) -> BTreeMap<String, Vec<u8>> {
+        match self {
+            BackPack::Parsed { attributes, .. } => attributes.get(&self.stored_name(name.as_ref()))
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Add a symbolic link called `name` pointing to `target`, which doesn't have to exist.
+    /// It's stored as an empty file with the attribute [`SYMLINK_ATTRIBUTE`], and
+    /// [extracted](Self::extract_to) as a link where the platform allows.
+    pub fn add_symlink(&mut self, name: impl AsRef<Path>, target: impl AsRef<Path>) -> error::Result<()> {
+        let name = name.as_ref();
+        check_reserved(&normalize_name(name), name)?;
+        let name_str = self.stored_name(name);
+        match self {
+            BackPack::Parsed { offsets, removals, data, end_offset, attributes, sorted_names, subscribers, .. } => {
+                let mut offsets = offsets.write();
+                if offsets.contains_key(&name_str) && removals.get(&name_str).is_none() {
+                    return Err(PackError::FileExists(name.to_path_buf()));
+                }
+
+                let key = (end_offset.fetch_add(1, Ordering::SeqCst), 0);
+                offsets.insert(name_str.clone(), key);
+                data.insert(key, Box::default());
+                drop(offsets);
+                *sorted_names.get_mut() = None;
+                attributes.entry(name_str).or_default()
+                    .insert(SYMLINK_ATTRIBUTE.to_string(), EntryName::from(target.as_ref()).into_bytes());
+
+                subscribers.emit(ChangeEvent::Added(entry_name::key_of(name.as_os_str()).into_owned()));
+                Ok(())
+            }
+        }
+    }
+
+    /// What kind of entry `name` is, or `None` when there's nothing called `name`.
+    pub fn entry_kind(&self, name: impl AsRef<Path>) -> Option<EntryKind> {
+        let name = name.as_ref();
+        if let Some(target) = self.alias_target(name) {
+            return Some(EntryKind::Hardlink(target));
+        }
+        if self.is_dir(name) {
+            return Some(EntryKind::Directory);
+        }
+        match self {
+            BackPack::Parsed { offsets, removals, .. } => {
+                let name_str = self.stored_name(name);
+                if !offsets.read().contains_key(&name_str) || removals.get(&name_str).is_some() {
+                    return None;
+                }
+            }
+        }
+        Some(match self.attribute(name, SYMLINK_ATTRIBUTE) {
+            Some(target) => EntryKind::Symlink(EntryName::from_bytes(target).to_path_buf()),
+            None => EntryKind::File,
+        })
+    }
+
+    /// Record the unix permission bits of `name`, or forget them with `None`. They're stored as
+    /// the attribute [`MODE_ATTRIBUTE`], in octal, and restored by [`extract_to`](Self::extract_to).
+    pub fn set_mode(&mut self, name: impl AsRef<Path>, mode: Option<u32>) -> error::Result<()> {
+        match mode {
+            Some(mode) => self.set_attribute(name, MODE_ATTRIBUTE, format!("{:o}", mode)),
+            None => {
+                // fails like setting it does when there's no such file
+                self.set_attribute(&name, MODE_ATTRIBUTE, Vec::new())?;
+                self.remove_attribute(name, MODE_ATTRIBUTE);
+                Ok(())
+            }
+        }
+    }
+
+    /// The unix permission bits of `name`, if they were [recorded](Self::set_mode).
+    pub fn mode(&self, name: impl AsRef<Path>) -> Option<u32> {
+        let mode = std::str::from_utf8(self.attribute(name, MODE_ATTRIBUTE)?).ok()?;
+        u32::from_str_radix(mode, 8).ok()
+    }
+
+    /// Record which regions of `name` were holes in the file it was packed from, as offsets and
+    /// lengths, or forget them with an empty list. They're stored as the attribute [`SPARSE_ATTRIBUTE`],
+    /// and [`extract_to`](Self::extract_to) leaves them out so they're holes again. The contents
+    /// are stored in full, holes only read as zeros.
+    pub fn set_holes(&mut self, name: impl AsRef<Path>, holes: &[(u64, u64)]) -> error::Result<()> {
+        self.set_attribute(&name, SPARSE_ATTRIBUTE, sparse::encode_holes(holes))?;
+        if holes.is_empty() {
+            self.remove_attribute(name, SPARSE_ATTRIBUTE);
+        }
+        Ok(())
+    }
+
+    /// The holes of `name`, if they were [recorded](Self::set_holes).
+    pub fn holes(&self, name: impl AsRef<Path>) -> Vec<(u64, u64)> {
+        self.attribute(name, SPARSE_ATTRIBUTE)
+            .and_then(|value| sparse::decode_holes(value).ok())
+            .unwrap_or_default()
+    }
+
+    /// Let `alias` open the same file as `target`, for example `default_skin.png` for
+    /// `skins/blue.png`. Only the index changes, the contents aren't copied, and pointing the
+    /// alias somewhere else later doesn't touch any data either. An alias of an alias points
+    /// to the file itself. Aliases of removed files are dropped when the backpack is flushed,
+    /// and a file added under the name of an alias takes its place.
+    pub fn set_alias(&mut self, alias: impl AsRef<Path>, target: impl AsRef<Path>) -> error::Result<()> {
+        let (alias, target) = (alias.as_ref(), target.as_ref());
+        check_reserved(&normalize_name(alias), alias)?;
+        let alias_str = self.stored_name(alias);
+        let target_str = self.resolved_name(target);
+        match self {
+            BackPack::Parsed { offsets, removals, aliases, .. } => {
+                let offsets = offsets.read();
+                if offsets.contains_key(&alias_str) && removals.get(&alias_str).is_none() {
+                    return Err(PackError::FileExists(alias.to_path_buf()));
+                }
+                if !offsets.contains_key(&target_str) || removals.get(&target_str).is_some() {
+                    return Err(PackError::FileNotFound(target.to_path_buf()));
+                }
+
+                aliases.insert(alias_str, target_str);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stop `alias` from opening a file. Returns whether it was an alias.
+    pub fn remove_alias(&mut self, alias: impl AsRef<Path>) -> bool {
+        let alias = self.stored_name(alias.as_ref());
+        match self {
+            BackPack::Parsed { aliases, .. } => aliases.remove(&alias).is_some(),
+        }
+    }
+
+    /// The stored name of the file `alias` opens, if it's an alias.
+    pub fn alias_target(&self, alias: impl AsRef<Path>) -> Option<String> {
+        match self {
+            BackPack::Parsed { aliases, .. } => aliases.get(&self.stored_name(alias.as_ref())).cloned(),
+        }
+    }
+
+    /// All aliases with the stored names of the files they open, sorted by alias.
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        match self {
+            BackPack::Parsed { aliases, .. } => {
+                let mut res = aliases.iter()
+                    .map(|(alias, target)| (alias.clone(), target.clone()))
+                    .collect::<Vec<_>>();
+                res.sort();
+                res
+            }
+        }
+    }
+
+    /// Remove all files which have expired, returning their names.
+    pub fn purge_expired(&mut self) -> error::Result<Vec<String>> {
+        self.purge_expired_at(SystemTime::now())
+    }
+
+    /// Remove all files which have expired at `now`, returning their names.
+    pub fn purge_expired_at(&mut self, now: SystemTime) -> error::Result<Vec<String>> {
+        let expired = match self {
+            BackPack::Parsed { expiry, .. } => {
+                let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                expiry.iter()
+                    .filter(|(_, time)| **time <= now)
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // expiry is kept by stored name
+        for name in &expired {
+            self.remove_stored(name.clone(), Path::new(name))?;
+        }
+        Ok(expired)
+    }
+
+    /// Start recording the order in which files are first opened with [`get_file`](Self::get_file).
+    /// Any trace recorded so far is discarded.
+    pub fn start_recording(&self) {
+        match self {
+            BackPack::Parsed { trace, .. } => *trace.lock() = Some(AccessTrace::new()),
+        }
+    }
+
+    /// Stop recording and return what was recorded since [`start_recording`](Self::start_recording).
+    pub fn take_trace(&self) -> Option<AccessTrace> {
+        match self {
+            BackPack::Parsed { trace, .. } => trace.lock().take(),
+        }
+    }
+
+    /// Lay files out in the order of `trace` the next time the backpack is flushed.
+    /// Files which aren't in the trace are placed after those that are.
+    pub fn set_order(&mut self, trace: &AccessTrace) {
+        match self {
+            BackPack::Parsed { order, .. } => {
+                *order = trace.names().iter()
+                    .enumerate()
+                    .map(|(i, name)| (name.clone(), i))
+                    .collect();
+            }
+        }
+    }
+
+    /// Place a file in a [`Tier`] the next time the backpack is flushed.
+    /// `None` removes it from its tier.
+    pub fn set_tier(&mut self, name: impl AsRef<Path>, tier: Option<Tier>) {
+        let name = self.stored_name(name.as_ref());
+        match self {
+            BackPack::Parsed { tiers, .. } => match tier {
+                Some(tier) => { tiers.insert(name, tier); }
+                None => { tiers.remove(&name); }
+            },
+        }
+    }
+
+    /// Make every file in `trace` hot, in the order they were accessed,
+    /// and every other file cold.
+    pub fn set_tiers_from_trace(&mut self, trace: &AccessTrace) {
+        self.set_order(trace);
+
+        let names = self.file_names();
+        let BackPack::Parsed { tiers, order, .. } = self;
+        *tiers = names.into_iter()
+            .map(|name| {
+                let tier = if order.contains_key(&name) { Tier::Hot } else { Tier::Cold };
+                (name, tier)
+            })
+            .collect();
+    }
+
+    /// Check the backpack: that the index in its file is undamaged and only refers to data
+    /// within the file, and that every file passes the [validators](Self::add_validator).
+    /// With `recursive`, files which are backpacks themselves are opened and verified too,
+    /// with the same validators, so a pack of packs is checked in one go.
+    /// Fails only when the file can't be read at all.
+    pub fn verify(&mut self, recursive: bool) -> error::Result<VerifyReport> {
+        self.verify_with(None, recursive)
+    }
+
+    /// A quick check that the backpack is probably fine, for when reading all of it takes too
+    /// long: the index in the file is checked like [`verify`](Self::verify) does, and of every
+    /// file in it only the first and last block are read back. This catches damaged indexes and
+    /// truncated or unreadable files, but not damage in the middle of files.
+    pub fn verify_quick(&mut self) -> error::Result<VerifyReport> {
+        // bytes read at the start and end of every file
+        const SAMPLE: u64 = 4096;
+
+        let mut report = VerifyReport::default();
+        let file = match self {
+            BackPack::Parsed { file, toc_blocks, .. } if !toc_blocks.is_empty() => file.as_mut().ok_or(Closed)?,
+            BackPack::Parsed { .. } => return Ok(report),
+        };
+
+        let Index { offsets, toc_blocks, .. } = match Self::verify_index(file)? {
+            Ok(index) => index,
+            Err(reason) => {
+                report.index = Some(reason);
+                return Ok(report);
+            }
+        };
+
+        for (name, (offset, length)) in offsets {
+            let start = Self::convert_offset(&toc_blocks, offset);
+            let sample = SAMPLE.min(length);
+            let mut buf = vec![0; sample as usize];
+
+            let read = file.read_exact_at(start, &mut buf)
+                .and_then(|_| file.read_exact_at(start + length - sample, &mut buf));
+            if let Err(e) = read {
+                report.files.push((name, e.to_string()));
+            }
+        }
+        report.files.sort();
+
+        Ok(report)
+    }
+
+    /// Read every file back from the backing file and compare it with the checksum stored
+    /// for it, to find files which were damaged on disk. The index is checked like
+    /// [`verify`](Self::verify) does. Files without a checksum, from backpacks written before
+    /// checksums were stored, are skipped, and changes since the last flush aren't checked.
+    pub fn verify_all(&mut self) -> error::Result<VerifyReport> {
+        self.verify_all_with_progress(&mut progress::ignore)
+    }
+
+    /// Like [`verify_all`](Self::verify_all), reporting to `progress` after every file is checked.
+    pub fn verify_all_with_progress(&mut self, progress: &mut dyn Progress) -> error::Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        let file = match self {
+            BackPack::Parsed { file, toc_blocks, .. } if !toc_blocks.is_empty() => file.as_mut().ok_or(Closed)?,
+            BackPack::Parsed { .. } => return Ok(report),
+        };
+
+        let index = match Self::verify_index(file)? {
+            Ok(index) => index,
+            Err(reason) => {
+                report.index = Some(reason);
+                return Ok(report);
+            }
+        };
+
+        let checked = index.offsets.iter()
+            .filter(|(name, _)| index.checksums.contains_key(*name))
+            .collect::<Vec<_>>();
+        let mut tracker = Tracker::new(progress, checked.len(), checked.iter().map(|(_, (_, length))| length).sum());
+        for (name, (offset, length)) in checked {
+            let checksum = &index.checksums[name];
+
+            let mut buf = vec![0; *length as usize];
+            match file.read_exact_at(Self::convert_offset(&index.toc_blocks, *offset), &mut buf) {
+                Ok(()) if crc32(&buf) != *checksum => report.files.push((name.clone(), "contents don't match their checksum".to_string())),
+                Ok(()) => {}
+                Err(e) => report.files.push((name.clone(), e.to_string())),
+            }
+            tracker.done(name, *length)?;
+        }
+        report.files.sort();
+
+        Ok(report)
+    }
+
+    /// Check the index in `file`. Returns it with the toc blocks sorted, or why it's damaged.
+    fn verify_index(file: &mut RawFile) -> error::Result<Result<Index, String>> {
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut index = match protection::parse_protected(file) {
+            Ok((index, _)) => index,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        index.toc_blocks.sort();
+
+        let mut outside = index.offsets.iter()
+            .filter(|(_, (offset, length))| Self::convert_offset(&index.toc_blocks, *offset) + length > len)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        outside.sort();
+        if !outside.is_empty() {
+            return Ok(Err(format!("files reach past the end of the file: {}", outside.join(", "))));
+        }
+
+        Ok(Ok(index))
+    }
+
+    /// Like [`verify`](Self::verify), with the validators of the outer backpack when this one is nested in it.
+    fn verify_with(&mut self, outer_validators: Option<&[Box<dyn Validator>]>, recursive: bool) -> error::Result<VerifyReport> {
+        match self {
+            BackPack::Parsed { file, offsets, data, toc_blocks, evicted, validators, .. } => {
+                let validators = outer_validators.unwrap_or(validators);
+                let mut report = VerifyReport::default();
+
+                // nothing was written to a new backpack's file yet
+                if let Some(file) = file.as_mut().filter(|_| !toc_blocks.is_empty()) {
+                    report.index = Self::verify_index(file)?.err();
+                }
+
+                let mut live = offsets.get_mut().iter()
+                    .map(|(name, key)| (name.clone(), *key))
+                    .collect::<Vec<_>>();
+                live.sort();
+
+                for (name, key) in live {
+                    let contents = if evicted.get_mut().contains(&key) {
+                        let mut buf = vec![0; key.1 as usize];
+                        file.as_ref().ok_or(Closed)?.read_exact_at(Self::convert_offset(toc_blocks, key.0), &mut buf)?;
+                        buf
+                    } else {
+                        data.get(&key).ok_or(PackError::InvalidEntry)?.read().clone()
+                    };
+
+                    for validator in validators.iter().filter(|v| v.applies_to(Path::new(&name))) {
+                        if let Err(reason) = validator.validate(Path::new(&name), &contents) {
+                            report.files.push((name.clone(), format!("{}: {}", validator.name(), reason)));
+                        }
+                    }
+
+                    if recursive && contents.starts_with(PACK_MAGIC) {
+                        match BackPack::open(RawFile::from(contents)) {
+                            Ok(mut nested) => report.nested.push((name, nested.verify_with(Some(validators), recursive)?)),
+                            Err(e) => report.files.push((name, format!("nested backpack can't be opened: {}", e))),
+                        }
+                    }
+                }
+
+                Ok(report)
+            }
+        }
+    }
+
+    /// How many bytes are spent on overwritten and removed files, so tools can decide whether
+    /// rewriting the backpack with a [flush](Self::flush) is worth it.
+    pub fn fragmentation_report(&self) -> FragmentationReport {
+        match self {
+            BackPack::Parsed { offsets, data, removals, stored_size, .. } => {
+                // files with the same contents can share a key
+                let live = offsets.read().values().copied().collect::<HashSet<_>>();
+                let mut report = FragmentationReport {
+                    live_bytes: live.iter().map(|(_, length)| length).sum(),
+                    tombstones: removals.len(),
+                    ..Default::default()
+                };
+
+                for (offset, length) in data.keys_cloned().into_iter().filter(|key| !live.contains(key)) {
+                    report.dead_bytes += length;
+                    if offset + length <= *stored_size {
+                        report.recoverable_bytes += length;
+                    }
+                }
+
+                report
+            }
+        }
+    }
+
+    /// Gets the number of bytes used to store files currently.
+    /// If packs get really large (contain lots of files) you
+    /// might want to flush, or set a [memory limit](Self::set_memory_limit).
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            BackPack::Parsed { total_size, .. } => {
+                total_size.load(Ordering::SeqCst) as usize
+            },
+        }
+    }
+
+    /// Cap the number of bytes of file contents held in memory. When adding a file would
+    /// go over the limit, the contents of files which are stored in the backpack's file and
+    /// not open are dropped from memory, largest first, and read again when they're opened.
+    /// Files added since the last flush only live in memory, so when there's nothing left
+    /// to drop, [`add_file`](Self::add_file) fails with [`PackError::MemoryLimit`] until
+    /// the backpack is flushed. Flushing reads every dropped file back in.
+    pub fn set_memory_limit(&mut self, limit: Option<u64>) {
+        match self {
+            BackPack::Parsed { memory_limit, .. } => *memory_limit = limit,
+        }
+    }
+
+    /// Retry reads of file contents from the backing file which fail with transient errors,
+    /// like `Interrupted` or a timed out network share, according to `policy` instead of
+    /// failing right away. Reads are not retried by default.
+    pub fn set_read_retry(&mut self, policy: RetryPolicy) {
+        match self {
+            BackPack::Parsed { read_retry, .. } => *read_retry = policy,
+        }
+    }
+
+    /// Check files against the checksum stored for them the first time they're read after
+    /// opening, failing with [`PackError::ChecksumMismatch`] when they were damaged on disk.
+    /// Off by default, since it means hashing every file once. [`verify_all`](Self::verify_all)
+    /// checks all files at once. Compressed files are always checked, when they're decompressed.
+    pub fn set_verify_checksums(&mut self, verify: bool) {
+        match self {
+            BackPack::Parsed { verify_checksums, .. } => *verify_checksums = verify,
+        }
+    }
+
+    /// Write flushes through a [journal](journal::journal_path) next to the backpack, so a crash
+    /// halfway through a flush leaves either the old or the new backpack once it's
+    /// [recovered](journal::recover). Every flush then writes the backpack twice.
+    /// Fails with [`PackError::NotJournaled`] unless the backpack is in a named file on disk.
+    pub fn set_journaled(&mut self, on: bool) -> error::Result<()> {
+        match self {
+            BackPack::Parsed { file, journaled, .. } => {
+                if on {
+                    journal::journaled_path(file.as_ref().ok_or(Closed)?)?;
+                }
+                *journaled = on;
+                Ok(())
+            }
+        }
+    }
+
+    /// Compress files with `compression` from the next flush on, unless they were added with
+    /// their own in [`EntryOptions`]. Files which don't get smaller are stored as is.
+    /// Compressed files are decompressed when the backpack is opened, so they take up their
+    /// full size in memory. Zip hybrid backpacks are never compressed.
+    pub fn set_compression(&mut self, new_compression: Compression) -> error::Result<()> {
+        if !new_compression.is_supported() {
+            return Err(PackError::UnsupportedCompression(new_compression));
+        }
+
+        match self {
+            BackPack::Parsed { compression, .. } => *compression = new_compression,
+        }
+        Ok(())
+    }
+
+    /// Store compressed files as is when compressing them saves less than `ratio` of their size,
+    /// like 0.05 for at least 5%, so reading them doesn't pay for decompressing for next to no
+    /// gain. Files stored as is for this are recorded as such in the index, see
+    /// [`IndexEntry::stored_as_is`](crate::pack::IndexEntry::stored_as_is). 0, the default,
+    /// keeps every file which gets smaller at all.
+    pub fn set_min_compression_ratio(&mut self, ratio: f64) -> error::Result<()> {
+        if !(0.0..1.0).contains(&ratio) {
+            return Err(PackError::IncompatibleOptions("the minimum compression ratio is at least 0 and below 1"));
+        }
+
+        match self {
+            BackPack::Parsed { min_compression_ratio, .. } => *min_compression_ratio = ratio,
+        }
+        Ok(())
+    }
+
+    /// Sign the backpack with `key` from the next flush or [freeze](Self::freeze) on, or stop signing
+    /// it with `None`. The signature covers the names, contents, and metadata of all files, so
+    /// any change to them is noticed when the backpack is [opened verified](Self::open_verified).
+    /// A backpack which isn't signed when flushing loses its signature.
+    #[cfg(feature = "signing")]
+    pub fn set_signing(&mut self, key: Option<SigningKey>) {
+        match self {
+            BackPack::Parsed { signing, .. } => *signing = key,
+        }
+    }
+
+    /// The public key of whoever signed the backpack, as it was opened. This is only what the
+    /// backpack claims, use [`open_verified`](Self::open_verified) to check the signature.
+    #[cfg(feature = "signing")]
+    pub fn signer(&self) -> Option<[u8; PUBLIC_KEY_SIZE]> {
+        match self {
+            BackPack::Parsed { signature, .. } => signing::signer(signature.as_ref()?).ok(),
+        }
+    }
+
+    /// Open a backpack [signed](Self::set_signing) with the key belonging to `public_key`.
+    /// Fails with [`PackError::Unsigned`] when it isn't signed, and with [`PackError::BadSignature`]
+    /// when it was signed by another key, or changed after it was signed. The signature covers
+    /// names, contents and metadata of files, but not their tier, alignment or sidecar records.
+    #[cfg(feature = "signing")]
+    pub fn open_verified<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>, public_key: &[u8; PUBLIC_KEY_SIZE]) -> error::Result<Self> {
+        Self::open_complete(file)?.verified(public_key)
+    }
+
+    /// Open a backpack which is both [signed](Self::set_signing) and [encrypted](Self::set_encryption),
+    /// like [`open_verified`](Self::open_verified) does for backpacks which aren't encrypted.
+    /// The signature is checked against the decrypted contents.
+    #[cfg(all(feature = "signing", feature = "crypto"))]
+    pub fn open_verified_encrypted<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>, passphrase: &str, public_key: &[u8; PUBLIC_KEY_SIZE]) -> error::Result<Self> {
+        Self::open_encrypted(file, passphrase)?.verified(public_key)
+    }
+
+    #[cfg(feature = "signing")]
+    fn verified(self, public_key: &[u8; PUBLIC_KEY_SIZE]) -> error::Result<Self> {
+        let res = self;
+        match &res {
+            BackPack::Parsed { offsets, data, hidden, aliases, expiry, modified, attributes, signature, .. } => {
+                let signature = signature.as_ref().ok_or(PackError::Unsigned)?;
+                let offsets = offsets.read();
+                let contents = offsets.iter()
+                    .map(|(name, key)| Ok((name.as_str(), data.get(key).ok_or(PackError::InvalidEntry)?.read())))
+                    .collect::<error::Result<Vec<_>>>()?;
+                let entries = contents.iter()
+                    .map(|(name, contents)| (*name, contents.as_slice()))
+                    .collect::<Vec<_>>();
+                signing::verify(signature, &signed_message(&entries, hidden, aliases, expiry, modified, attributes), public_key)?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Encrypt and authenticate the contents of files from the next flush on, with a key derived
+    /// from the passphrase in `new_encryption`, or store them in the clear again with `None`.
+    /// Names of files, their expiry and aliases aren't encrypted. Encrypted files never share
+    /// contents when [freezing](Self::freeze), and zip hybrid backpacks can't be encrypted.
+    /// Open the backpack again with [`open_encrypted`](Self::open_encrypted).
+    #[cfg(feature = "crypto")]
+    pub fn set_encryption(&mut self, new_encryption: Option<Encryption>) -> error::Result<()> {
+        let key = new_encryption.map(|e| e.key()).transpose()?;
+        match self {
+            BackPack::Parsed { encryption, .. } => *encryption = key,
+        }
+        Ok(())
+    }
+
+    /// Drop contents of stored files from memory until `extra` more bytes fit under the memory limit.
+    fn make_room(&self, extra: u64) -> error::Result<()> {
+        let BackPack::Parsed { memory_limit: Some(limit), offsets, data, handles, evicted, total_size, stored_size, .. } = self else {
+            return Ok(());
+        };
+        if total_size.load(Ordering::SeqCst) + extra <= *limit {
+            return Ok(());
+        }
+
+        // locked in the same order as get_file
+        let offsets = offsets.read();
+        let open_files = handles.open_files();
+        let mut evicted = evicted.lock();
+
+        let mut candidates = offsets.values()
+            .filter(|(offset, length)| offset + length <= *stored_size && *length > 0)
+            .filter(|key| !evicted.contains(key) && !open_files.contains_key(key))
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(offset, length)| (std::cmp::Reverse(*length), *offset));
+        candidates.dedup();
+
+        for key in candidates {
+            if total_size.load(Ordering::SeqCst) + extra <= *limit {
+                break;
+            }
+
+            if let Some(contents) = data.get(&key) {
+                *contents.write() = Vec::new();
+                evicted.insert(key);
+                total_size.fetch_sub(key.1, Ordering::SeqCst);
+            }
+        }
+
+        if total_size.load(Ordering::SeqCst) + extra > *limit {
+            return Err(PackError::MemoryLimit(*limit));
+        }
+        Ok(())
+    }
+
+    /// Read the contents of a file back into memory if they were dropped to stay under the memory limit.
+    fn reload(file: &RawFile, toc_blocks: &[u64], data: &FrozenMap<(u64, u64), Box<RwLock<Vec<u8>>>>, key: (u64, u64), retry: &RetryPolicy) -> error::Result<()> {
+        let mut buf = vec![0; key.1 as usize];
+        retry.run(|| file.read_exact_at(Self::convert_offset(toc_blocks, key.0), &mut buf))?;
+        *data.get(&key).ok_or(PackError::InvalidEntry)?.write() = buf;
+        Ok(())
+    }
+
+    /// Open a handle to a file, making sure its contents are in memory. Once the handle
+    /// is open the contents won't be dropped again until it's closed.
+    fn open_slice(&'f self, key: (u64, u64)) -> error::Result<PackSlice<'f, 'backpack>> {
+        let slice = PackSlice::new(key.0, key.1, self);
+
+        let BackPack::Parsed { file, toc_blocks, data, evicted, total_size, read_retry, .. } = self;
+        if evicted.lock().contains(&key) {
+            // reading is never refused, so going over the limit here is fine
+            let _ = self.make_room(key.1);
+
+            let mut evicted = evicted.lock();
+            if evicted.contains(&key) {
+                Self::reload(file.as_ref().ok_or(Closed)?, toc_blocks, data, key, read_retry)?;
+                evicted.remove(&key);
+                total_size.fetch_add(key.1, Ordering::SeqCst);
+            }
+        }
+
+        Ok(slice)
+    }
+
+    /// Write all changes since the last flush to the file
+    ///
+    /// ```rust
+    /// # use backpack::RawFile;
+    /// # use backpack::pack::BackPack;
+    /// # use backpack::pack::PackError;
+    /// # use std::io::Read;
+    ///
+    /// # fn main() -> Result<(), PackError> {
+    ///     let file = RawFile::in_memory("test.bp");
+    ///     let bp = BackPack::create(file)?;
+    ///
+    ///     // when done with it
+    ///     let mut file = bp.close()?;
+    ///     // now there's stuff in the file, which can be read again with `open`
+    ///     let bp = BackPack::open(file)?;
+    ///
+    /// #   Ok(())
+    /// # }
+    ///
+    /// ```
+    pub fn flush(&mut self) -> error::Result<()> {
+        self.flush_with_progress(&mut progress::ignore)
+    }
+
+    /// Flush, reporting to `progress` as files are gathered to be written.
+    fn flush_with_progress(&mut self, progress: &mut dyn Progress) -> error::Result<()> {
+        match self {
+            BackPack::Parsed {
+                file,
+                offsets,
+                data,
+                removals,
+                total_size,
+                output_mode,
+                alignment,
+                toc_blocks,
+                stored_size,
+                end_offset,
+                order,
+                tiers,
+                expiry,
+                modified,
+                attributes,
+                sidecars,
+                index_protection,
+                evicted,
+                hidden,
+                alignments,
+                aliases,
+                read_retry,
+                compression,
+                min_compression_ratio,
+                compressions,
+                unverified,
+                packed,
+                sorted_names,
+                encryption,
+                #[cfg(feature = "signing")]
+                signing,
+                journaled,
+                ..
+            } => {
+                let file = file.as_mut().ok_or(Closed)?;
+                // zip readers couldn't read encrypted files, and they mustn't be written in the clear
+                if *output_mode == OutputMode::ZipHybrid && encryption.is_some() {
+                    return Err(PackError::EncryptedZip);
+                }
+
+                // the file is about to be overwritten, so read back what was only stored there
+                let dropped = evicted.get_mut().iter().copied().collect::<Vec<_>>();
+                for key in dropped {
+                    Self::reload(file, toc_blocks, data, key, read_retry)?;
+                    evicted.get_mut().remove(&key);
+                }
+
+                let mut live = offsets.read().iter()
+                    .filter(|(name, _)| removals.get(name.as_str()).is_none() && !METADATA_ENTRIES.contains(&name.as_str()))
+                    .map(|(name, key)| (name.clone(), *key))
+                    .collect::<Vec<_>>();
+                // hot files come first and cold files last. Within a tier, files with a
+                // preferred position come first, the rest keeps its current order
+                live.sort_by_key(|(name, (offset, _))| {
+                    let tier = match tiers.get(name) {
+                        Some(Tier::Hot) => 0,
+                        None => 1,
+                        Some(Tier::Cold) => 2,
+                    };
+                    (tier, order.get(name).copied().unwrap_or(usize::MAX), *offset)
+                });
+
+                let mut compressed = Compressed::new();
+                let mut encrypted = HashSet::new();
+                // journaled flushes are written to memory first, to commit them to the file at once
+                let mut image = journaled.then(|| RawFile::from(Vec::new()));
+                let out = match &mut image {
+                    Some(image) => image,
+                    None => &mut *file,
+                };
+                let layout = {
+                    let mut entries = Vec::new();
+                    let mut tracker = Tracker::new(progress, live.len(), live.iter().map(|(_, (_, length))| length).sum());
+                    for (name, key) in &live {
+                        let contents = data.get(key).ok_or(PackError::InvalidEntry)?;
+                        entries.push((name.as_str(), contents.read()));
+                        tracker.done(name, key.1)?;
+                    }
+                    let mut entries = entries.iter()
+                        .map(|(name, contents)| (*name, contents.as_slice()))
+                        .collect::<Vec<_>>();
+
+                    expiry.retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    modified.retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    attributes.retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    hidden.retain(|name| entries.iter().any(|(n, _)| n == name));
+                    alignments.get_mut().retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    compressions.get_mut().retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    tiers.retain(|name, _| entries.iter().any(|(n, _)| n == name));
+                    // aliases of removed files go, and files shadow aliases with the same name
+                    aliases.retain(|alias, target| {
+                        entries.iter().any(|(n, _)| n == target) && !entries.iter().any(|(n, _)| n == alias)
+                    });
+                    #[cfg(feature = "signing")]
+                    let signature = signing.as_ref().map(|key| key.sign(&signed_message(&entries, hidden, aliases, expiry, modified, attributes)));
+                    // zip readers couldn't read compressed files
+                    let compressions = compressions.get_mut();
+                    let compression_of = |name: &str| match output_mode {
+                        OutputMode::Native => compression_for(name, compressions, tiers, *compression),
+                        OutputMode::ZipHybrid => Compression::None,
+                    };
+                    let packed_contents = compression::compress_entries(&entries, compression_of, *min_compression_ratio, &mut compressed)?;
+                    for (i, contents) in &packed_contents {
+                        entries[*i].1 = contents;
+                    }
+                    let encrypted_contents = match encryption {
+                        Some(key) => encryption::encrypt_entries(&entries, key, &mut encrypted)?,
+                        None => Vec::new(),
+                    };
+                    for (i, contents) in &encrypted_contents {
+                        entries[*i].1 = contents;
+                    }
+
+                    let expiry_contents = encode_times(expiry);
+                    if !expiry.is_empty() {
+                        entries.push((EXPIRY_ENTRY, &expiry_contents));
+                    }
+                    let modified_contents = encode_times(modified);
+                    if !modified.is_empty() {
+                        entries.push((MODIFIED_ENTRY, &modified_contents));
+                    }
+                    let attribute_contents = encode_attributes(attributes);
+                    if !attributes.is_empty() {
+                        entries.push((ATTRIBUTES_ENTRY, &attribute_contents));
+                    }
+                    let alias_contents = encode_aliases(aliases);
+                    if !aliases.is_empty() {
+                        entries.push((ALIAS_ENTRY, &alias_contents));
+                    }
+                    let tier_contents = encode_tiers(tiers);
+                    if !tiers.is_empty() {
+                        entries.push((TIERS_ENTRY, &tier_contents));
+                    }
+                    let encryption_contents = encryption.as_ref().map(EncryptionKey::encode);
+                    if let Some(contents) = &encryption_contents {
+                        entries.push((ENCRYPTION_ENTRY, contents));
+                    }
+                    #[cfg(feature = "signing")]
+                    if let Some(signature) = &signature {
+                        entries.push((SIGNATURE_ENTRY, signature));
+                    }
+
+                    out.seek(SeekFrom::Start(0))?;
+                    match output_mode {
+                        OutputMode::Native => {
+                            let alignments = alignments.get_mut();
+                            let alignment_of = |name: &str| alignments.get(name).copied().unwrap_or(*alignment);
+                            BackPack::write_native(out, &entries, alignment_of, sidecars, hidden, &compressed, &encrypted)?
+                        }
+                        OutputMode::ZipHybrid => zip::write_hybrid(out, &entries, hidden)?,
+                    }
+                };
+
+                if *output_mode == OutputMode::Native && *index_protection != IndexProtection::None {
+                    let end = out.current_offset()?;
+                    let index_len = PACK_HEADER_SIZE + layout.toc_blocks.len() as u64 * TOC_SIZE as u64;
+                    let mut index = vec![0; index_len as usize];
+                    out.seek(SeekFrom::Start(0))?;
+                    out.read_exact(&mut index)?;
+                    out.seek(SeekFrom::Start(end))?;
+                    protection::write_trailer(out, &index, end, *index_protection)?;
+                }
+
+                // the previous version of the pack might have been longer
+                let end = out.current_offset()?;
+                out.set_len(end)?;
+                if let Some(RawFile::InMemory(image)) = image {
+                    let mut journal = Journal::new(end);
+                    journal.write(0, image.get_bytes().to_vec());
+                    journal.commit(file)?;
+                    file.seek(SeekFrom::Start(end))?;
+                }
+
+                // from now on, refer to files by where they are stored in the file
+                let mut layout = layout;
+                let end = separate_empty(&mut layout.offsets, layout.data_size);
+                packed.clear();
+                let end = separate_packed(&mut layout.offsets, &compressed, &encrypted, end, packed);
+                let (end, _) = separate_shared(&mut layout.offsets, end);
+                let mut old_data = std::mem::take(data).into_tuple_vec().into_iter().collect::<HashMap<_, _>>();
+                let mut moved = HashMap::new();
+                for (name, old_key) in live {
+                    let new_key = *layout.offsets.get(&name).ok_or(PackError::InvalidEntry)?;
+
+                    if let Some(contents) = old_data.remove(&old_key) {
+                        data.insert(new_key, contents);
+                        moved.insert(old_key, new_key);
+                    } else if let Some(first) = moved.get(&old_key) {
+                        // shared with a file which was already moved
+                        let copy = data.get(first).ok_or(PackError::InvalidEntry)?.read().clone();
+                        data.insert(new_key, Box::new(RwLock::new(copy)));
+                    }
+                }
+
+                sidecars.retain(|name, _| {
+                    let stored = layout.sidecars.contains(name);
+                    if !stored && layout.offsets.contains_key(name) {
+                        log::warn!("sidecar record of {:?} doesn't fit in the padding before it, dropping it", name);
+                    }
+                    stored
+                });
+
+                let mut new_offsets = layout.offsets;
+                for name in METADATA_ENTRIES {
+                    new_offsets.remove(*name);
+                }
+                *total_size.get_mut() = new_offsets.values().map(|(_, length)| length).sum();
+                *offsets.get_mut() = new_offsets;
+                *sorted_names.get_mut() = None;
+                *removals = FrozenMap::new();
+                // everything in memory was just written, so matches the file
+                unverified.get_mut().clear();
+                *toc_blocks = layout.toc_blocks;
+                *stored_size = layout.data_size;
+                *end_offset.get_mut() = end;
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Write a native backpack, with every file aligned to `alignment(name)`.
+    /// The contents of `compressed` and `encrypted` entries must already be compressed and encrypted.
+    pub(crate) fn write_native(f: &mut impl Write, entries: &[(&str, &[u8])], alignment: impl Fn(&str) -> u64, sidecars: &HashMap<String, Vec<u8>>, hidden: &HashSet<String>, compressed: &Compressed, encrypted: &HashSet<String>) -> error::Result<Layout> {
+        let mut offsets = HashMap::new();
+        let checksums = checksums_of(entries);
+        let alignments = entries.iter()
+            .map(|(name, _)| (name.to_string(), alignment(name)))
+            .filter(|(_, alignment)| *alignment > 1)
+            .collect::<HashMap<_, _>>();
+
+        // alignment is relative to the start of the file, so we need to know
+        // where the data starts. The size of the table of contents only depends
+        // on the names, so lay the data out unaligned first.
+        let mut data_start = 0;
+        if !alignments.is_empty() {
+            let mut end = 0;
+            for (name, contents) in entries {
+                offsets.insert(name.to_string(), (end, contents.len() as u64));
+                end += contents.len() as u64;
+            }
+            data_start = PACK_HEADER_SIZE + Self::create_toc_at(&offsets, hidden, compressed, encrypted, &checksums, &alignments, PACK_HEADER_SIZE)?.len() as u64 * TOC_SIZE as u64;
+        }
+
+        let mut data = Vec::new();
+        let mut stored_sidecars = Vec::new();
+        let mut stored = HashMap::<_, (u64, u64)>::new();
+        for (name, contents) in entries {
+            // empty files and directories take up no space
+            if contents.is_empty() {
+                offsets.insert(name.to_string(), (0, 0));
+                continue;
+            }
+
+            // files with the same contents share them, unless that'd leave no room for a sidecar
+            let compression = compressed.get(*name).copied().filter(|(method, _)| *method != Compression::None);
+            let stored_as = (*contents, compression, encrypted.contains(*name));
+            let duplicate = stored.get(&stored_as)
+                .filter(|(offset, _)| !sidecars.contains_key(*name) && (data_start + offset).is_multiple_of(alignment(name)));
+            if let Some(key) = duplicate {
+                offsets.insert(name.to_string(), *key);
+                continue;
+            }
+
+            let padding = aligned::padding(data_start + data.len() as u64, alignment(name));
+            let padding_start = data.len();
+            data.resize(data.len() + padding as usize, 0);
+
+            if let Some(record) = sidecars.get(*name) {
+                if aligned::write_sidecar(&mut data[padding_start..], record) {
+                    stored_sidecars.push(name.to_string());
+                }
+            }
+
+            let key = (data.len() as u64, contents.len() as u64);
+            offsets.insert(name.to_string(), key);
+            stored.entry(stored_as).or_insert(key);
+            data.extend_from_slice(contents);
+        }
+
+        let toc_blocks = Self::write_headers(f, data.len() as u64, &offsets, hidden, compressed, encrypted, &checksums, &alignments)?;
+        f.write_all(&data)?;
+
+        Ok(Layout {
+            offsets,
+            toc_blocks,
+            data_size: data.len() as u64,
+            sidecars: stored_sidecars,
+        })
+    }
+
+    /// Add a file, resolving name collisions with the backpack's [collision policy](Self::set_collision_policy).
+    pub fn add_file<E: Into<PackError>>(&'f self, f: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        let collision = match self {
+            BackPack::Parsed { collision, .. } => *collision,
+        };
+        self.add_file_with(f, collision)
+    }
+
+    /// Add a file, resolving a name collision according to `collision`.
+    pub fn add_file_with<E: Into<PackError>>(&'f self, f: impl TryInto<RawFile<'f, 'backpack>, Error=E>, collision: Collision) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        let mut f = f.try_into().map_err(Into::<PackError>::into)?;
+
+        match self {
+            BackPack::Parsed {
+                offsets,
+                data,
+                total_size,
+                end_offset,
+                validators,
+                quotas,
+                write_limits,
+                subscribers,
+                sorted_names,
+                .. } => {
+
+                // one byte more than fits is enough to know it doesn't
+                let mut f_data = Vec::new();
+                let max_read = write_limits.max_entry_size.map_or(u64::MAX, |max| max.saturating_add(1));
+                (&mut f).take(max_read).read_to_end(&mut f_data)?;
+                let name = f.name().ok_or(NoName)?;
+                write_limits.check_size(&entry_name::key_of(name.as_os_str()), f_data.len() as u64, 0)?;
+
+                for validator in validators.iter().filter(|v| v.applies_to(name)) {
+                    validator.validate(name, &f_data).map_err(|reason| PackError::InvalidAsset {
+                        name: name.to_path_buf(),
+                        validator: validator.name().to_string(),
+                        reason,
+                    })?;
+                }
+
+                self.make_room(f_data.len() as u64)?;
+
+                let mut plain_name = entry_name::key_of(name.as_os_str()).into_owned();
+                let mut name_str = self.stored_name(name);
+                // like `.` or `dir/..`
+                if name_str.is_empty() {
+                    return Err(NoName);
+                }
+                check_reserved(&normalize_name(name), name)?;
+                // hold the lock from checking for collisions and quotas until the
+                // file is added, so concurrently added files can't get in between
+                let mut offsets = offsets.write();
+                let mut replaced = false;
+                if let Some(existing) = offsets.get(&name_str).copied() {
+                    match collision {
+                        Collision::Overwrite => replaced = true,
+                        Collision::Error => return Err(PackError::FileExists(name.to_path_buf())),
+                        Collision::KeepBoth => {
+                            plain_name = free_name(&plain_name, |n| offsets.contains_key(&self.exact_stored_name(Path::new(n))));
+                            name_str = self.exact_stored_name(Path::new(&plain_name));
+                        }
+                        Collision::DedupeIfIdentical => {
+                            // opening the existing file may need to read it back into memory
+                            drop(offsets);
+                            let existing = self.open_slice(existing)?;
+                            if *existing.get_bytes().read() != f_data {
+                                return Err(PackError::FileExists(name.to_path_buf()));
+                            }
+
+                            return Ok(InMemoryFile::Packed {
+                                name: name.to_path_buf(),
+                                data: existing,
+                            });
+                        }
+                    }
+                }
+
+                for (prefix, quota) in quotas.iter().filter(|(prefix, _)| name_str.starts_with(prefix.as_str())) {
+                    let (mut entries, mut bytes) = (1, f_data.len() as u64);
+                    for (_, (_, length)) in offsets.iter().filter(|(n, _)| n.starts_with(prefix.as_str()) && **n != name_str) {
+                        entries += 1;
+                        bytes += length;
+                    }
+
+                    if quota.max_entries.is_some_and(|max| entries > max) || quota.max_bytes.is_some_and(|max| bytes > max) {
+                        return Err(PackError::QuotaExceeded(prefix.clone()));
+                    }
+                }
+
+                Self::check_write_limits(write_limits, &offsets, &name_str, Path::new(&plain_name), f_data.len() as u64)?;
+
+                total_size.fetch_add(f_data.len() as u64, Ordering::SeqCst);
+                // empty files get a key of their own too, so they never share contents
+                let prev = end_offset.fetch_add((f_data.len() as u64).max(1), Ordering::SeqCst);
+                let key = (prev, f_data.len() as u64);
+
+                offsets.deref_mut().insert(name_str.clone(), key);
+                data.insert(key, Box::new(RwLock::new(f_data)));
+                drop(offsets);
+                *sorted_names.lock() = None;
+
+                subscribers.emit(if replaced {
+                    ChangeEvent::Modified(plain_name.clone())
+                } else {
+                    ChangeEvent::Added(plain_name.clone())
+                });
+
+                Ok(InMemoryFile::Packed {
+                    name: PathBuf::from(plain_name),
+                    data: PackSlice::new(key.0, key.1, self),
+                })
+            }
+        }
+    }
+
+    pub fn add_empty_file(&'f self, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        self.add_file(InMemoryFile::new(name))
+    }
+
+    pub fn add_file_named<E: Into<PackError>>(&'f self, f: impl TryInto<RawFile<'f, 'backpack>, Error=E>, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        self.add_file(f.try_into().map_err(Into::<PackError>::into)?.with_name(name))
+    }
+
+    /// Add a file named `name` with contents read from `contents`, with `options` overriding
+    /// the defaults of the backpack for this file only, for example to align a file which is
+    /// uploaded to the GPU as is without padding all others.
+    pub fn add_with_options(&'f self, name: impl AsRef<Path>, mut contents: impl Read, options: EntryOptions) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        if let Some(alignment) = options.alignment {
+            if !alignment.is_power_of_two() {
+                return Err(PackError::BadAlignment(alignment));
+            }
+        }
+        if let Some(compression) = options.compression.filter(|c| !c.is_supported()) {
+            return Err(PackError::UnsupportedCompression(compression));
+        }
+
+        let (collision, alignments, compressions) = match self {
+            BackPack::Parsed { collision, alignments, compressions, .. } => (options.collision.unwrap_or(*collision), alignments, compressions),
+        };
+
+        let mut data = Vec::new();
+        contents.read_to_end(&mut data)?;
+        let f = self.add_file_with(InMemoryFile::from(data).with_name(name), collision)?;
+
+        // the file may have been added under another name
+        let stored = self.stored_name(f.name().ok_or(NoName)?);
+        match options.alignment {
+            Some(alignment) => alignments.lock().insert(stored.clone(), alignment),
+            None => alignments.lock().remove(&stored),
+        };
+        match options.compression {
+            Some(compression) => compressions.lock().insert(stored, compression),
+            None => compressions.lock().remove(&stored),
+        };
+
+        Ok(f)
+    }
+
+    /// Add many files at once, resolving name collisions with the backpack's [collision policy](Self::set_collision_policy).
+    /// The next file is read while the previous ones are validated and added on another thread,
+    /// so slow sources like generators or database cursors don't wait on the pack and vice versa.
+    /// Returns the names the files were added under. Stops at the first error, the files
+    /// before it stay added.
+    pub fn add_all<N: AsRef<Path>, R: Read>(&'f self, files: impl IntoIterator<Item=(N, R)>) -> error::Result<Vec<PathBuf>>
+    where Self: Sync {
+        // files read ahead of the ones being added
+        const READ_AHEAD: usize = 4;
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<(PathBuf, Vec<u8>)>(READ_AHEAD);
+
+        std::thread::scope(|s| {
+            let adder = s.spawn(move || {
+                let mut added = Vec::new();
+                for (name, contents) in receiver {
+                    let f = self.add_file(InMemoryFile::from(contents).with_name(name))?;
+                    added.push(f.name().ok_or(NoName)?.to_path_buf());
+                }
+                Ok::<_, PackError>(added)
+            });
+
+            let mut read_error = None;
+            for (name, mut reader) in files {
+                let mut contents = Vec::new();
+                if let Err(e) = reader.read_to_end(&mut contents) {
+                    read_error = Some(e.into());
+                    break;
+                }
+                // the adder only hangs up when it failed, which it reports below
+                if sender.send((name.as_ref().to_path_buf(), contents)).is_err() {
+                    break;
+                }
+            }
+            drop(sender);
+
+            let added = adder.join().expect("adding files panicked")?;
+            match read_error {
+                Some(e) => Err(e),
+                None => Ok(added),
+            }
+        })
+    }
+
+    pub fn remove_file(&mut self, name: impl AsRef<Path>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = self.stored_name(name);
+        self.remove_stored(name_str, name)
+    }
+
+    /// Stage files to add, remove and rename, which are then [committed](Transaction::commit)
+    /// all at once: either all changes are made, or none are.
+    pub fn transaction(&mut self) -> Transaction<'_, 'f, 'backpack> {
+        Transaction::new(self)
+    }
+
+    /// Stage a file under a free temporary name starting with `prefix`, for derived data which
+    /// might not pan out. Unless the entry is [persisted](TempEntry::persist) under a real name,
+    /// the temporary file is removed when the entry is dropped.
+    pub fn temp_entry(&'f self, prefix: &str) -> error::Result<TempEntry<'f, 'backpack>> {
+        let removals = match self {
+            BackPack::Parsed { removals, .. } => removals,
+        };
+
+        let mut n = 0u64;
+        loop {
+            let name = PathBuf::from(format!("{}{}.tmp", prefix, n));
+            n += 1;
+            // names removed since the last flush can't be opened again until then
+            if removals.get(&self.stored_name(&name)).is_some() {
+                continue;
+            }
+
+            match self.add_file_with(InMemoryFile::new(&name), Collision::Error) {
+                Ok(_) => return Ok(TempEntry::new(self, name)),
+                Err(PackError::FileExists(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Remove a file without exclusive access to the backpack. Only for files which can't have
+    /// expiry times, sidecars or be hidden, since those need exclusive access to set.
+    pub(crate) fn tombstone(&self, name: &Path) {
+        let name_str = self.stored_name(name);
+        let BackPack::Parsed { offsets, removals, alignments, compressions, sorted_names, subscribers, .. } = self;
+        if offsets.write().remove(&name_str).is_some() {
+            *sorted_names.lock() = None;
+            alignments.lock().remove(&name_str);
+            compressions.lock().remove(&name_str);
+            removals.insert(name_str, &());
+            subscribers.emit(ChangeEvent::Removed(entry_name::key_of(name.as_os_str()).into_owned()));
+        }
+    }
+
+    /// Remove the file stored under `name_str`, which is `name` before hashing.
+    fn remove_stored(&mut self, name_str: String, name: &Path) -> error::Result<()> {
+        match self {
+            BackPack::Parsed {
+                offsets,
+                removals,
+                expiry,
+                modified,
+                attributes,
+                sidecars,
+                hidden,
+                subscribers,
+                alignments,
+                compressions,
+                sorted_names,
+                ..
+            } => {
+                expiry.remove(&name_str);
+                modified.remove(&name_str);
+                attributes.remove(&name_str);
+                hidden.remove(&name_str);
+                alignments.get_mut().remove(&name_str);
+                compressions.get_mut().remove(&name_str);
+                sidecars.remove(&name_str);
+                *sorted_names.get_mut() = None;
+                if let Some(ref _identifier) = offsets.write().remove(&name_str) {
+                    removals.insert(name_str, &());
+                    subscribers.emit(ChangeEvent::Removed(entry_name::key_of(name.as_os_str()).into_owned()));
+                    Ok(())
+                } else {
+                    Err(PackError::FileNotFound(name.to_path_buf()))
+                }
+            }
+        }
+    }
+
+    /// Rename the file `from` to `to`. Only its index record changes, its contents stay where
+    /// they are, along with its expiry time, attributes and other metadata. [Aliases](Self::set_alias)
+    /// of it follow it to its new name. Fails when `to` exists.
+    pub fn rename(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> error::Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let from_str = self.stored_name(from);
+        let to_str = self.stored_name(to);
+        if to_str.is_empty() {
+            return Err(NoName);
+        }
+        check_reserved(&normalize_name(to), to)?;
+        if !self.is_live(&from_str) {
+            return Err(PackError::FileNotFound(from.to_path_buf()));
+        }
+        if from_str == to_str {
+            return Ok(());
+        }
+        if self.is_live(&to_str) {
+            return Err(PackError::FileExists(to.to_path_buf()));
+        }
+
+        self.move_stored(&[(from_str, to_str)]);
+        let BackPack::Parsed { subscribers, .. } = self;
+        subscribers.emit(ChangeEvent::Removed(entry_name::key_of(from.as_os_str()).into_owned()));
+        subscribers.emit(ChangeEvent::Added(entry_name::key_of(to.as_os_str()).into_owned()));
+        Ok(())
+    }
+
+    /// Move the directory `from` with everything in it to `to`, like [`rename`](Self::rename)
+    /// does with files, returning how many entries moved. Nothing moves when anything in it
+    /// would replace an existing file. Names are matched as they're stored, so this doesn't
+    /// work with [name hashing](Self::set_name_hasher).
+    pub fn move_dir(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> error::Result<usize> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let from_dir = normalize_name(from);
+        let to_dir = normalize_name(to);
+        let (from_dir, to_dir) = (from_dir.trim_end_matches('/'), to_dir.trim_end_matches('/'));
+        if from_dir.is_empty() || to_dir.is_empty() {
+            return Err(NoName);
+        }
+        let (from_prefix, to_prefix) = (format!("{}/", from_dir), format!("{}/", to_dir));
+        check_reserved(&to_prefix, to)?;
+
+        let moves = match self {
+            BackPack::Parsed { offsets, removals, .. } => offsets.get_mut().keys()
+                .filter(|name| removals.get(name.as_str()).is_none())
+                .filter_map(|name| Some((name.clone(), format!("{}{}", to_prefix, name.strip_prefix(&from_prefix)?))))
+                .collect::<Vec<_>>(),
+        };
+        if moves.is_empty() {
+            return Err(PackError::FileNotFound(from.to_path_buf()));
+        }
+        if from_dir == to_dir {
+            return Ok(moves.len());
+        }
+
+        // entries of the directory may move to where others of it were
+        let sources = moves.iter().map(|(from, _)| from.as_str()).collect::<HashSet<_>>();
+        if let Some((_, taken)) = moves.iter().find(|(_, to)| self.is_live(to) && !sources.contains(to.as_str())) {
+            return Err(PackError::FileExists(PathBuf::from(taken)));
+        }
+
+        self.move_stored(&moves);
+        let BackPack::Parsed { subscribers, .. } = self;
+        for (from, to) in &moves {
+            subscribers.emit(ChangeEvent::Removed(from.clone()));
+            subscribers.emit(ChangeEvent::Added(to.clone()));
+        }
+        Ok(moves.len())
+    }
+
+    /// Whether a file is stored under `name_str`, and wasn't removed since the last flush.
+    fn is_live(&self, name_str: &str) -> bool {
+        match self {
+            BackPack::Parsed { offsets, removals, .. } => {
+                offsets.read().contains_key(name_str) && removals.get(name_str).is_none()
+            }
+        }
+    }
+
+    /// Move the index records of files from one stored name to another, replacing what's there.
+    /// All records are taken out before any is put back, so files can move to each others names.
+    fn move_stored(&mut self, moves: &[(String, String)]) {
+        let BackPack::Parsed {
+            offsets,
+            removals,
+            expiry,
+            modified,
+            attributes,
+            sidecars,
+            hidden,
+            alignments,
+            compressions,
+            tiers,
+            aliases,
+            sorted_names,
+            ..
+        } = self;
+
+        fn move_keys<V>(map: &mut HashMap<String, V>, moves: &[(String, String)]) {
+            let taken = moves.iter().map(|(from, _)| map.remove(from)).collect::<Vec<_>>();
+            for ((_, to), value) in moves.iter().zip(taken) {
+                match value {
+                    Some(v) => { map.insert(to.clone(), v); }
+                    None => { map.remove(to); }
+                }
+            }
+        }
+        move_keys(offsets.get_mut(), moves);
+        move_keys(expiry, moves);
+        move_keys(modified, moves);
+        move_keys(attributes, moves);
+        move_keys(sidecars, moves);
+        move_keys(alignments.get_mut(), moves);
+        move_keys(compressions.get_mut(), moves);
+        move_keys(tiers, moves);
+
+        let was_hidden = moves.iter().map(|(from, _)| hidden.remove(from)).collect::<Vec<_>>();
+        for ((_, to), was_hidden) in moves.iter().zip(was_hidden) {
+            match was_hidden {
+                true => hidden.insert(to.clone()),
+                false => hidden.remove(to),
+            };
+        }
+
+        let targets = moves.iter().map(|(from, to)| (from.as_str(), to.as_str())).collect::<HashMap<_, _>>();
+        for target in aliases.values_mut() {
+            if let Some(to) = targets.get(target.as_str()) {
+                *target = to.to_string();
+            }
+        }
+
+        let destinations = moves.iter().map(|(_, to)| to.as_str()).collect::<HashSet<_>>();
+        for (from, to) in moves {
+            Self::unremove(removals, to);
+            if !destinations.contains(from.as_str()) {
+                removals.insert(from.clone(), &());
+            }
+        }
+        *sorted_names.get_mut() = None;
+    }
+
+    /// Forget that `name_str` was removed since the last flush, for when a file is stored under it again.
+    fn unremove(removals: &mut FrozenMap<String, &'backpack ()>, name_str: &str) {
+        if removals.get(name_str).is_some() {
+            for (name, v) in std::mem::replace(removals, FrozenMap::new()).into_tuple_vec() {
+                if name != name_str {
+                    removals.insert(name, v);
+                }
+            }
+        }
+    }
+
+    /// Make a file stored with [`put_contents`](Self::put_contents) after it was removed survive the next flush.
+    pub(crate) fn revive(&mut self, name: &Path) {
+        let name_str = self.stored_name(name);
+        let BackPack::Parsed { offsets, removals, .. } = self;
+        if offsets.get_mut().contains_key(&name_str) {
+            Self::unremove(removals, &name_str);
+        }
+    }
+
+    /// The index records of `names`, to put back with [`restore_records`](Self::restore_records).
+    pub(crate) fn records<'n>(&self, names: impl IntoIterator<Item=&'n Path>) -> Records {
+        let BackPack::Parsed { offsets, removals, expiry, modified, attributes, sidecars, hidden, alignments, compressions, tiers, aliases, .. } = self;
+        let names = names.into_iter()
+            .map(|name| self.stored_name(name))
+            .collect::<BTreeSet<_>>();
+        let offsets = offsets.read();
+        let records = names.into_iter()
+            .map(|name| {
+                let record = Record {
+                    key: offsets.get(&name).copied(),
+                    removed: removals.get(&name).is_some(),
+                    hidden: hidden.contains(&name),
+                    expiry: expiry.get(&name).copied(),
+                    modified: modified.get(&name).copied(),
+                    attributes: attributes.get(&name).cloned(),
+                    sidecar: sidecars.get(&name).cloned(),
+                    alignment: alignments.lock().get(&name).copied(),
+                    compression: compressions.lock().get(&name).copied(),
+                    tier: tiers.get(&name).copied(),
+                };
+                (name, record)
+            })
+            .collect();
+        Records { records, aliases: aliases.clone() }
+    }
+
+    /// Put the index records of files back the way they were, undoing changes made since
+    /// [`records`](Self::records). Contents stored in between stay in memory until the next flush.
+    pub(crate) fn restore_records(&mut self, records: Records) {
+        let BackPack::Parsed { offsets, removals, expiry, modified, attributes, sidecars, hidden, alignments, compressions, tiers, aliases, sorted_names, .. } = self;
+        fn restore<V>(map: &mut HashMap<String, V>, name: &str, value: Option<V>) {
+            match value {
+                Some(v) => { map.insert(name.to_string(), v); }
+                None => { map.remove(name); }
+            }
+        }
+        *aliases = records.aliases;
+        for (name, record) in records.records {
+            restore(offsets.get_mut(), &name, record.key);
+            restore(expiry, &name, record.expiry);
+            restore(modified, &name, record.modified);
+            restore(attributes, &name, record.attributes);
+            restore(sidecars, &name, record.sidecar);
+            restore(alignments.get_mut(), &name, record.alignment);
+            restore(compressions.get_mut(), &name, record.compression);
+            restore(tiers, &name, record.tier);
+            match record.hidden {
+                true => hidden.insert(name.clone()),
+                false => hidden.remove(&name),
+            };
+            match record.removed {
+                true => { removals.insert(name, &()); }
+                false => Self::unremove(removals, &name),
+            }
+        }
+        *sorted_names.get_mut() = None;
+    }
+
+    /// Bring the backpack up to date with `patch`, made with [`PackDiff::between`] from a backpack
+    /// with the same files as this one. The changes are written with the next flush, like other changes.
+    /// Files the patch removes which are already gone are skipped.
+    pub fn apply_patch(&mut self, patch: &PackDiff) -> error::Result<()> {
+        for name in patch.removed() {
+            match self.remove_file(name) {
+                Ok(()) | Err(PackError::FileNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        for (name, contents, hide) in patch.contents() {
+            self.put_contents(Path::new(name), contents.to_vec())?;
+            self.set_hidden(name, hide)?;
+        }
+        Ok(())
+    }
+
+    /// Add or replace the file `name`, for changes made with exclusive access to the backpack,
+    /// where [`add_file`](Self::add_file) can't be used.
+    pub(crate) fn put_contents(&self, name: &Path, contents: Vec<u8>) -> error::Result<()> {
+        match self {
+            BackPack::Parsed { offsets, data, total_size, end_offset, validators, write_limits, subscribers, sorted_names, .. } => {
+                for validator in validators.iter().filter(|v| v.applies_to(name)) {
+                    validator.validate(name, &contents).map_err(|reason| PackError::InvalidAsset {
+                        name: name.to_path_buf(),
+                        validator: validator.name().to_string(),
+                        reason,
+                    })?;
+                }
+
+                self.make_room(contents.len() as u64)?;
+                let name_str = self.stored_name(name);
+                if name_str.is_empty() {
+                    return Err(NoName);
+                }
+
+                Self::check_write_limits(write_limits, &offsets.read(), &name_str, name, contents.len() as u64)?;
+
+                total_size.fetch_add(contents.len() as u64, Ordering::SeqCst);
+                let prev = end_offset.fetch_add((contents.len() as u64).max(1), Ordering::SeqCst);
+                let key = (prev, contents.len() as u64);
+                let replaced = offsets.write().insert(name_str, key).is_some();
+                data.insert(key, Box::new(RwLock::new(contents)));
+                *sorted_names.lock() = None;
+
+                let plain_name = entry_name::key_of(name.as_os_str()).into_owned();
+                subscribers.emit(if replaced {
+                    ChangeEvent::Modified(plain_name)
+                } else {
+                    ChangeEvent::Added(plain_name)
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Add a file called `name` holding `value` as JSON, see [`get_deserialized`](Self::get_deserialized).
+    #[cfg(feature = "json")]
+    pub fn put_serialized<T: serde::Serialize + ?Sized>(&'f self, name: impl AsRef<Path>, value: &T) -> error::Result<()> {
+        self.put_serialized_with(name, value, Json::default())
+    }
+
+    /// Add a file called `name` holding `value` serialized in `format`.
+    #[cfg(feature = "serde")]
+    pub fn put_serialized_with<T: serde::Serialize + ?Sized>(&'f self, name: impl AsRef<Path>, value: &T, format: impl Format) -> error::Result<()> {
+        let name = name.as_ref();
+        let contents = format.serialize(value).map_err(|reason| PackError::Serialize {
+            name: name.to_path_buf(),
+            reason,
+        })?;
+        self.add_file(InMemoryFile::from(contents).with_name(name))?;
+        Ok(())
+    }
+
+    /// The file called `name` read as JSON into a `T`, see [`put_serialized`](Self::put_serialized).
+    /// Fails with [`PackError::AssetLoad`] when it isn't a valid `T`.
+    #[cfg(feature = "json")]
+    pub fn get_deserialized<T: serde::de::DeserializeOwned>(&'f self, name: impl AsRef<Path>) -> error::Result<T> {
+        self.get_deserialized_with(name, Json::default())
+    }
+
+    /// The file called `name` read into a `T` from `format`.
+    #[cfg(feature = "serde")]
+    pub fn get_deserialized_with<T: serde::de::DeserializeOwned>(&'f self, name: impl AsRef<Path>, format: impl Format) -> error::Result<T> {
+        let name = name.as_ref();
+        let file = self.get_file(name)?;
+        let bytes = file.get_bytes();
+        format.deserialize(&bytes).map_err(|reason| PackError::AssetLoad {
+            name: name.to_path_buf(),
+            asset: std::any::type_name::<T>(),
+            reason,
+        })
+    }
+
+    pub fn get_file(&'f self, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        match self {
+            BackPack::Parsed { offsets, removals, trace, unverified, verify_checksums, .. } => {
+                let path_buf = name.as_ref().to_path_buf();
+                let name_str = self.resolved_name(name.as_ref());
+
+                // path when removal is not yet updated in main
+                if removals.get(&name_str).is_some() {
+                    return Err(PackError::FileNotFound(path_buf.clone()));
+                }
+
+                let key = *offsets.read().get(&name_str)
+                    .ok_or_else(|| PackError::FileNotFound(path_buf.clone()))?;
+
+                if let Some(trace) = trace.lock().as_mut() {
+                    trace.record(&name_str);
+                }
+
+                let data = self.open_slice(key)?;
+                if *verify_checksums {
+                    let mut unverified = unverified.lock();
+                    if let Some(checksum) = unverified.get(&key) {
+                        if crc32(&data.get_bytes().read()) != *checksum {
+                            return Err(PackError::ChecksumMismatch(path_buf));
+                        }
+                        unverified.remove(&key);
+                    }
+                }
+
+                Ok(InMemoryFile::Packed {
+                    name: path_buf,
+                    data,
+                })
+            }
+        }
+    }
+
+    /// A reader over a file's contents as they were added. Whatever was done to the contents
+    /// to store them is undone while reading, so callers don't need to know how a file is stored.
+    pub fn entry(&'f self, name: impl AsRef<Path>) -> error::Result<Box<dyn Read + 'f>> {
+        // compressed and encrypted files are decoded when the backpack is opened
+        Ok(Box::new(self.get_file(name)?))
+    }
+
+    /// A reader over a file's contents which can also seek, to decode formats like audio
+    /// straight out of the backpack. Use [`PackReader::get`](crate::pack::PackReader::get)
+    /// to do the same without loading the backpack.
+    pub fn entry_reader(&'f self, name: impl AsRef<Path>) -> error::Result<EntryReader<'f, 'backpack>> {
+        Ok(EntryReader::new(self.get_file(name)?))
+    }
+
+    /// The contents of `name` as text. When they aren't UTF-8, the error says on which line.
+    pub fn read_to_string(&'f self, name: impl AsRef<Path>) -> error::Result<String> {
+        let name = name.as_ref();
+        text::decode(name, self.get_file(name)?.get_bytes().to_vec())
+    }
+
+    /// The lines of the text file `name`, like of a manifest or a config file.
+    pub fn lines(&'f self, name: impl AsRef<Path>) -> error::Result<Lines<EntryReader<'f, 'backpack>>> {
+        Ok(Lines::new(self.entry_reader(name.as_ref())?, name.as_ref()))
+    }
+
+    /// Write a new file called `name` as a stream, for encoders which produce their output
+    /// through [`Write`]. The file is added when the writer is [finished](EntryWriter::finish).
+    /// Use [`PackWriter::entry_writer`](crate::pack::PackWriter::entry_writer) to stream
+    /// straight to the file of a backpack instead.
+    pub fn entry_writer(&'f self, name: impl AsRef<Path>) -> EntryWriter<'f, 'backpack> {
+        EntryWriter::new(self, name.as_ref().to_path_buf())
+    }
+
+    /// Read the contents of many files at once, in the same order as `names`. Files are read
+    /// in the order they are stored in and decoded in parallel, which beats reading them one
+    /// by one when loading a level. Every file succeeds or fails on its own.
+    pub fn read_many(&'f self, names: &[impl AsRef<Path> + Sync]) -> Vec<error::Result<Vec<u8>>>
+    where Self: Sync {
+        let offsets = match self {
+            BackPack::Parsed { offsets, .. } => offsets,
+        };
+
+        let resolved = names.iter().map(|name| self.resolved_name(name.as_ref())).collect::<Vec<_>>();
+        let mut order = {
+            let offsets = offsets.read();
+            (0..names.len())
+                .map(|i| (offsets.get(&resolved[i]).map(|(offset, _)| *offset), i))
+                .collect::<Vec<_>>()
+        };
+        order.sort();
+
+        let read = order.par_iter()
+            .map(|(_, i)| {
+                let mut contents = Vec::new();
+                self.entry(&names[*i])?.read_to_end(&mut contents)?;
+                Ok(contents)
+            })
+            .collect::<Vec<error::Result<_>>>();
+
+        // back in the order they were asked for
+        let mut res = (0..names.len()).map(|_| None).collect::<Vec<_>>();
+        for ((_, i), contents) in order.iter().zip(read) {
+            res[*i] = Some(contents);
+        }
+        res.into_iter().map(|r| r.expect("every file was read")).collect()
+    }
+
+    /// Run `command` with a file's contents streamed into its standard input, for shelling out
+    /// to tools like ffmpeg or texture compressors. Returns what the command wrote to its
+    /// standard output, or [`PackError::CommandFailed`] when it didn't exit successfully.
+    pub fn pipe_entry(&'f self, name: impl AsRef<Path>, command: &mut Command) -> error::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        self.entry(name)?.read_to_end(&mut contents)?;
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+
+        // write on another thread, the command may only read more input
+        // once its output is read
+        let output = std::thread::scope(|s| {
+            let writer = s.spawn(move || match stdin.write_all(&contents) {
+                // the command doesn't have to read all of its input
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                res => res,
+            });
+            let output = child.wait_with_output();
+            writer.join().expect("writing to the command panicked")?;
+            output
+        })?;
+
+        if !output.status.success() {
+            return Err(PackError::CommandFailed {
+                program: command.get_program().to_string_lossy().into_owned(),
+                status: output.status,
+            });
+        }
+        Ok(output.stdout)
+    }
+
+    /// Like [`pipe_entry`](Self::pipe_entry), adding what the command wrote to its standard output as the file `output`.
+    pub fn pipe_entry_into(&'f self, name: impl AsRef<Path>, command: &mut Command, output: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        let contents = self.pipe_entry(name, command)?;
+        self.add_file(InMemoryFile::from(contents).with_name(output))
+    }
+
+    /// A reader over a file's contents, encoded with `codec` while it's read.
+    pub fn entry_transcoded(&'f self, name: impl AsRef<Path>, codec: &dyn Codec) -> error::Result<Box<dyn Read + 'f>> {
+        codec.encode(self.entry(name)?)
+    }
+
+    /// A reader over a file's contents exactly as they are stored in the file of the backpack,
+    /// so still [compressed](Self::set_compression) and [encrypted](Self::set_encryption) when
+    /// they're stored like that. Files added or changed since the last flush aren't stored yet,
+    /// for those it reads what [`entry`](Self::entry) does.
+    pub fn entry_raw(&'f self, name: impl AsRef<Path>) -> error::Result<Box<dyn Read + 'f>> {
+        let f = self.get_file(name)?;
+        match self {
+            BackPack::Parsed { file, toc_blocks, packed, read_retry, .. } => {
+                let InMemoryFile::Packed { data, .. } = &f else { unreachable!("files in a backpack are packed") };
+                let Some(&(offset, length)) = packed.get(&data.identifier()) else {
+                    return Ok(Box::new(f));
+                };
+
+                let file = file.as_ref().ok_or(Closed)?;
+                let mut buf = vec![0; length as usize];
+                read_retry.run(|| file.read_exact_at(Self::convert_offset(toc_blocks, offset), &mut buf))?;
+                Ok(Box::new(Cursor::new(buf)))
+            }
+        }
+    }
+
+    /// A file's contents at an address aligned to the alignment it's stored with, suitable for
+    /// handing to APIs that require aligned memory such as GPU uploads. Borrowed from the backpack
+    /// without copying, like [`Entry::as_slice`](crate::pack::Entry::as_slice), when it's
+    /// [in memory](RawFile::InMemory) or memory mapped and the file is stored uncompressed there.
+    /// Otherwise, or when the backpack's own bytes aren't aligned, it's a copy.
+    pub fn entry_aligned_bytes(&'f self, name: impl AsRef<Path>) -> error::Result<AlignedBytes<'f>> {
+        let f = self.get_file(name.as_ref())?;
+        let BackPack::Parsed { file, toc_blocks, stored_size, alignment, alignments, .. } = self;
+        // the same alignment the file is written with when flushing
+        let alignment = alignments.lock().get(&self.resolved_name(name.as_ref())).copied().unwrap_or(*alignment);
+
+        // compressed, encrypted and changed files are moved past the stored size when they're read
+        let InMemoryFile::Packed { data, .. } = &f else { unreachable!("files in a backpack are packed") };
+        let (offset, length) = data.identifier();
+        if offset + length <= *stored_size {
+            let stored = file.as_ref().and_then(|file| file.slice_at(Self::convert_offset(toc_blocks, offset), length));
+            if let Some(bytes) = stored.and_then(|stored| AlignedBytes::borrowed(stored, alignment)) {
+                return Ok(bytes);
+            }
+        }
+        let bytes = AlignedBytes::new(&f.get_bytes(), alignment)?;
+        Ok(bytes)
+    }
+
+    /// Tell the operating system how the region of the backing file holding `name` is going
+    /// to be accessed, to steer what it keeps in the page cache.
+    /// Files added since the last flush aren't in the backing file yet, advising those does nothing.
+    pub fn advise(&self, name: impl AsRef<Path>, advice: Advice) -> error::Result<()> {
+        let name = name.as_ref();
+        match self {
+            BackPack::Parsed { file, offsets, removals, toc_blocks, stored_size, .. } => {
+                let name_str = self.resolved_name(name);
+                if removals.get(&name_str).is_some() {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+
+                let (offset, length) = *offsets.read().get(&name_str)
+                    .ok_or_else(|| PackError::FileNotFound(name.to_path_buf()))?;
+
+                if offset + length > *stored_size {
+                    return Ok(());
+                }
+
+                let file = file.as_ref().ok_or(Closed)?;
+                file.advise(Self::convert_offset(toc_blocks, offset), length, advice)
+            }
+        }
+    }
+
+    /// Describe every file currently in the backpack.
+    pub fn manifest(&self) -> Manifest {
+        match self {
+            BackPack::Parsed { offsets, toc_blocks, stored_size, .. } => {
+                let entries = offsets.read().iter()
+                    .map(|(name, key)| Self::manifest_entry(name, *key, toc_blocks, *stored_size))
+                    .collect();
+
+                Manifest::new(PACK_VERSION, entries)
+            }
+        }
+    }
+
+    fn manifest_entry(name: &str, (offset, length): (u64, u64), toc_blocks: &[u64], stored_size: u64) -> ManifestEntry {
+        // empty files aren't stored anywhere
+        let stored = length > 0 && offset + length <= stored_size;
+        let offset = stored.then(|| Self::convert_offset(toc_blocks, offset));
+        ManifestEntry::new(name, offset, length)
+    }
+
+    /// The files currently in the backpack with their sizes, offsets and metadata, sorted by name.
+    /// [Hidden](Self::set_hidden) files are left out, like in [`file_names`](Self::file_names).
+    pub fn entries(&self) -> Entries {
+        let names = self.sorted_names();
+        Entries::new(self.entry_refs(names.iter()))
+    }
+
+    /// The files whose names start with `prefix`, sorted by name, like `shaders/` for everything
+    /// in that directory. Found without going through the names of all other files.
+    pub fn with_prefix(&self, prefix: &str) -> Entries {
+        let names = self.sorted_names();
+        Entries::new(self.entry_refs(Self::names_with_prefix(&names, prefix)))
+    }
+
+    /// The files whose names match the glob `pattern`, sorted by name. `*` and `?` don't match
+    /// `/`, `**` does, so `textures/*.png` finds the images in `textures` but not in directories
+    /// under it. Only the files starting with the part of `pattern` before its first wildcard
+    /// are matched against it.
+    pub fn find(&self, pattern: &str) -> Entries {
+        let names = self.sorted_names();
+        let prefix = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+        let matching = Self::names_with_prefix(&names, prefix)
+            .filter(|name| directory::glob_match(pattern, name));
+        Entries::new(self.entry_refs(matching))
+    }
+
+    fn names_with_prefix<'n>(names: &'n [String], prefix: &'n str) -> impl Iterator<Item=&'n String> {
+        let start = names.partition_point(|name| name.as_str() < prefix);
+        names[start..].iter().take_while(move |name| name.starts_with(prefix))
+    }
+
+    /// The names of all files and directories, sorted. Sorted again only after they changed.
+    fn sorted_names(&self) -> Arc<Vec<String>> {
+        match self {
+            BackPack::Parsed { offsets, sorted_names, .. } => {
+                let mut sorted_names = sorted_names.lock();
+                sorted_names.get_or_insert_with(|| {
+                    let mut names = offsets.read().keys().cloned().collect::<Vec<_>>();
+                    names.sort();
+                    Arc::new(names)
+                }).clone()
+            }
+        }
+    }
+
+    /// Like [`entries`](Self::entries), with metadata which can be changed in place:
+    /// `for entry in &mut pack.entries_mut() { entry.metadata.tier = Some(Tier::Hot) }`.
+    pub fn entries_mut(&mut self) -> EntriesMut<'_, 'f, 'backpack> {
+        let names = self.sorted_names();
+        let entries = self.entry_refs(names.iter());
+        EntriesMut::new(self, entries)
+    }
+
+    /// The files named `names`, in that order. Directories and hidden files are left out.
+    fn entry_refs<'n>(&self, names: impl Iterator<Item=&'n String>) -> Vec<EntryRef> {
+        match self {
+            BackPack::Parsed { offsets, hidden, toc_blocks, stored_size, expiry, modified, attributes, tiers, .. } => {
+                let time = |secs: Option<&u64>| secs.map(|secs| UNIX_EPOCH + Duration::from_secs(*secs));
+                let offsets = offsets.read();
+                names
+                    .filter(|name| !name.ends_with('/') && !hidden.contains(*name))
+                    .filter_map(|name| Some((name, offsets.get(name)?)))
+                    .map(|(name, key)| {
+                        let ManifestEntry { name, offset, length, .. } = Self::manifest_entry(name, *key, toc_blocks, *stored_size);
+                        let metadata = EntryMetadata {
+                            modified: time(modified.get(&name)),
+                            expiry: time(expiry.get(&name)),
+                            tier: tiers.get(&name).copied(),
+                            attributes: attributes.get(&name).cloned().unwrap_or_default(),
+                        };
+                        EntryRef { name, size: length, offset, metadata }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Set the metadata of the file stored as `name`, for [`entries_mut`](Self::entries_mut).
+    pub(crate) fn set_metadata(&mut self, name: &str, metadata: &EntryMetadata) {
+        match self {
+            BackPack::Parsed { offsets, expiry, modified, attributes, tiers, .. } => {
+                if !offsets.read().contains_key(name) {
+                    return;
+                }
+
+                let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                for (times, time) in [(&mut *modified, metadata.modified), (&mut *expiry, metadata.expiry)] {
+                    match time {
+                        Some(time) => { times.insert(name.to_string(), secs(time)); }
+                        None => { times.remove(name); }
+                    }
+                }
+                match metadata.tier {
+                    Some(tier) => { tiers.insert(name.to_string(), tier); }
+                    None => { tiers.remove(name); }
+                }
+                if metadata.attributes.is_empty() {
+                    attributes.remove(name);
+                } else {
+                    attributes.insert(name.to_string(), metadata.attributes.clone());
+                }
+            }
+        }
+    }
+
+    /// Names of all files currently in the backpack, sorted by name.
+    /// [Hidden](Self::set_hidden) files are left out.
+    pub fn file_names(&self) -> Vec<String> {
+        self.file_names_with(false)
+    }
+
+    /// Like [`file_names`](Self::file_names), with the bytes the names have in the backpack,
+    /// also when they aren't UTF-8.
+    pub fn entry_names(&self) -> Vec<EntryName> {
+        self.file_names().into_iter().map(EntryName::from).collect()
     }
 
-    pub(crate) fn retrieve_slice(&self, s: &PackSlice) -> &RwLock<Vec<u8>> {
+    /// Names of all files currently in the backpack, sorted by name,
+    /// including [hidden](Self::set_hidden) files if `include_hidden` is set.
+    pub fn file_names_with(&self, include_hidden: bool) -> Vec<String> {
+        let mut res: Vec<String> = match self {
+            BackPack::Parsed { offsets, hidden, .. } => offsets.read().keys()
+                .filter(|name| !name.ends_with('/'))
+                .filter(|name| include_hidden || !hidden.contains(*name))
+                .cloned()
+                .collect(),
+        };
+        res.sort();
+        res
+    }
+
+    /// The files currently in the backpack sorted by `key`, so tools that display or compare
+    /// listings don't need to sort them again. Files with the same key are sorted by name.
+    /// [Hidden](Self::set_hidden) files are left out, like in [`file_names`](Self::file_names).
+    pub fn entries_sorted_by(&self, key: SortKey) -> Vec<ManifestEntry> {
         match self {
-            BackPack::PartiallyParsed { .. } => todo!(),
-            BackPack::Parsed { data, .. } => {
-                data.get(&s.identifier())
-                    .expect("no such file (only packslices obtained from a pack should be used in as_slice)")
+            BackPack::Parsed { offsets, hidden, toc_blocks, stored_size, .. } => {
+                let offsets = offsets.read();
+                let mut entries = offsets.iter()
+                    .filter(|(name, _)| !name.ends_with('/') && !hidden.contains(*name))
+                    .collect::<Vec<_>>();
+
+                match key {
+                    SortKey::Name => entries.sort_by_key(|(name, _)| *name),
+                    SortKey::Offset => entries.sort_by_key(|(name, (offset, _))| (*offset, *name)),
+                    SortKey::Size => entries.sort_by_key(|(name, (_, length))| (*length, *name)),
+                }
 
+                entries.into_iter()
+                    .map(|(name, key)| Self::manifest_entry(name, *key, toc_blocks, *stored_size))
+                    .collect()
             }
         }
     }
 
-    fn convert_offset(sorted_toc_block_locations: &Vec<u64>, mut offset: u64) -> u64 {
-        offset += PACK_HEADER_SIZE;
-
-        for i in sorted_toc_block_locations {
-            if offset <= *i {
-                offset += TOC_SIZE as u64;
+    /// Names of the directories which were [added](Self::add_dir) to the backpack, sorted by name.
+    pub fn dir_names(&self) -> Vec<String> {
+        match self {
+            BackPack::Parsed { offsets, .. } => {
+                let mut res = offsets.read().keys()
+                    .filter_map(|name| name.strip_suffix('/'))
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                res.sort();
+                res
             }
         }
-
-        offset
     }
 
-    fn create_toc(offsets: &HashMap<String, (u64, u64)>) -> error::Result<Vec<Vec<u8>>> {
-        if offsets.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        let mut res = Vec::new();
-        let mut curr = Cursor::new(Vec::new());
-        let ten_zeros: [u8; 10] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
-        curr.write(&ten_zeros)?;
-
-        let mut offsets = offsets.into_iter().collect::<Vec<_>>();
-        offsets.sort_by_key(|(_, (i, _))| i);
+    /// Add an empty directory, which is kept in the index as an entry named with a trailing `/`
+    /// and no contents. Directories only have to be added when they'd otherwise be empty,
+    /// the directories of files exist implicitly. Adding an existing directory does nothing.
+    pub fn add_dir(&self, name: impl AsRef<Path>) -> error::Result<()> {
+        match self {
+            BackPack::Parsed { offsets, data, end_offset, sorted_names, subscribers, .. } => {
+                let name = name.as_ref();
+                check_reserved(&format!("{}/", normalize_name(name)), name)?;
+                let name_str = format!("{}/", self.stored_name(name));
 
-        for (s, (offset, length)) in offsets {
-            let entry_size = 8 + 2 + 2 + s.bytes().len();
-            let filled = curr.seek(SeekFrom::Current(0))?;
+                let mut offsets = offsets.write();
+                if offsets.contains_key(&name_str) {
+                    return Ok(());
+                }
 
-            if filled + entry_size as u64 > TOC_SIZE as u64 {
-                curr.seek(SeekFrom::Start(0))?;
-                curr.write(&(filled as u16).to_le_bytes())?;
-                curr.write(&(TOC_SIZE as u64).to_le_bytes())?;
+                let key = (end_offset.fetch_add(1, Ordering::SeqCst), 0);
+                offsets.insert(name_str, key);
+                data.insert(key, Box::default());
+                drop(offsets);
+                *sorted_names.lock() = None;
 
-                let mut buf = curr.into_inner();
-                buf.resize(TOC_SIZE as usize, 0);
-                res.push(buf);
-                curr = Cursor::new(Vec::new());
-                curr.write(&ten_zeros)?;
-            } else {
-                curr.write(&(s.as_bytes().len() as u16).to_le_bytes())?;
-                curr.write(s.as_bytes())?;
-                curr.write(&offset.to_le_bytes())?;
-                curr.write(&length.to_le_bytes())?;
+                subscribers.emit(ChangeEvent::Added(format!("{}/", entry_name::key_of(name.as_os_str()))));
+                Ok(())
             }
         }
+    }
 
-        let filled = curr.seek(SeekFrom::Current(0))?;
-        curr.seek(SeekFrom::Start(0))?;
-        curr.write(&(filled as u16).to_le_bytes())?;
+    /// The names of the files and directories directly in the directory `name`, sorted, with
+    /// directories named with a trailing `/`. Directories holding files don't have to be
+    /// [added](Self::add_dir) to be listed. `""` or `/` lists the root of the backpack.
+    /// [Hidden](Self::set_hidden) files are left out. Names are listed as they're stored,
+    /// so this doesn't work with [name hashing](Self::set_name_hasher).
+    pub fn list_dir(&self, name: impl AsRef<Path>) -> error::Result<Vec<String>> {
+        match self {
+            BackPack::Parsed { offsets, hidden, .. } => {
+                let dir = normalize_name(name.as_ref());
+                let dir = dir.trim_end_matches('/');
+                let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
 
-        let mut buf = curr.into_inner();
-        buf.resize(TOC_SIZE as usize, 0);
-        res.push(buf);
+                // the root always exists, other directories when something is in them
+                let mut exists = prefix.is_empty();
+                let mut res = BTreeSet::new();
+                for stored in offsets.read().keys() {
+                    let Some(rest) = stored.strip_prefix(&prefix) else {
+                        continue;
+                    };
+                    exists = true;
+                    // the directory itself
+                    if rest.is_empty() || hidden.contains(stored) {
+                        continue;
+                    }
+                    match rest.split_once('/') {
+                        Some((child, _)) => res.insert(format!("{}/", child)),
+                        None => res.insert(rest.to_string()),
+                    };
+                }
 
+                if !exists {
+                    return Err(PackError::FileNotFound(PathBuf::from(dir)));
+                }
+                Ok(res.into_iter().collect())
+            }
+        }
+    }
 
-        Ok(res)
+    /// Serve the backpack as a read-only filesystem at the directory `mountpoint`, to browse it
+    /// with normal tools while debugging. Blocks until it's unmounted, see [`mount`](crate::pack::mount).
+    #[cfg(all(target_os = "linux", feature = "fuse"))]
+    pub fn mount(&'f self, mountpoint: impl AsRef<Path>) -> error::Result<()> {
+        crate::pack::mount(&self, mountpoint)
     }
 
-    fn write_headers(f: &mut RawFile, size: u64, offsets: &HashMap<String, (u64, u64)>) -> error::Result<()> {
-        let toc_blocks = Self::create_toc(offsets)?;
+    /// A copy-on-write view of the backpack. Files can be written and removed through it
+    /// without changing the backpack, and [`Overlay::export`] writes the result as a new one.
+    pub fn overlay(&'f self) -> Overlay<'f, 'backpack> {
+        Overlay::new(self)
+    }
 
-        f.write(PACK_MAGIC)?;
-        f.write(&PACK_VERSION.to_le_bytes())?;
-        f.write(&size.to_le_bytes())?;
-        if toc_blocks.is_empty() {
-            f.write(&0u64.to_le_bytes())?;
-        } else {
-            f.write(&(PACK_HEADER_SIZE as u64).to_le_bytes())?;
-        }
-        for i in toc_blocks {
-            f.write(&i)?;
-        }
+    /// Write every file to `dir` under its name, creating directories as needed, the reverse of
+    /// [`from_directory`](Self::from_directory). Existing files are overwritten. Fails with
+    /// [`PackError::UnsafePath`] before writing anything if a name would end up outside of `dir`,
+    /// a symbolic link in the backpack points outside of it, or a name is inside such a link.
+    /// Links which were already in `dir` are followed, unless [`ExtractOptions::sandbox`] is set.
+    /// Recorded modification times and permissions are restored, see [`extract_to_with`](Self::extract_to_with).
+    pub fn extract_to(&'f self, dir: impl AsRef<Path>) -> error::Result<()> {
+        self.extract_to_with(dir, &ExtractOptions::default())
+    }
 
-        Ok(())
+    /// Like [`extract_to`](Self::extract_to), restoring the [modification times](Self::modified)
+    /// and [permissions](Self::mode) of files as the `options` ask.
+    pub fn extract_to_with(&'f self, dir: impl AsRef<Path>, options: &ExtractOptions) -> error::Result<()> {
+        self.extract_to_with_progress(dir, options, &mut progress::ignore)
     }
 
-    fn parse_toc_block(filled: u16, block: &[u8], offsets: &mut HashMap<String, (u64, u64)>) -> error::Result<()> {
-        let mut curr: usize = 0;
-        while (curr as u16) < filled {
-            let mut strlen_bytes = [0u8; 2];
-            strlen_bytes.copy_from_slice(&block[curr..curr+2]);
-            curr += 2;
-            let strlen = u16::from_le_bytes(strlen_bytes);
+    /// Like [`extract_to_with`](Self::extract_to_with), reporting to `progress` after every
+    /// file, directory and link is written. When the progress is [cancelled](Progress::cancelled)
+    /// the entries written so far are left complete in `dir`, without restoring the metadata
+    /// of directories, and this fails with [`PackError::Cancelled`].
+    pub fn extract_to_with_progress(&'f self, dir: impl AsRef<Path>, options: &ExtractOptions, progress: &mut dyn Progress) -> error::Result<()> {
+        match self {
+            BackPack::Parsed { offsets, aliases, .. } => {
+                let dir = dir.as_ref();
+                let portable = options.portable_names || cfg!(windows);
+                let mut entries = offsets.read().iter()
+                    .map(|(name, key)| Ok((name.clone(), (directory::extract_path_with(dir, name, portable)?, name.ends_with('/'), *key))))
+                    .collect::<error::Result<Vec<_>>>()?;
+                entries.sort();
+                let mut hardlinks = aliases.iter()
+                    .map(|(alias, target)| Ok((directory::extract_path_with(dir, alias, portable)?, directory::extract_path_with(dir, target, portable)?, alias)))
+                    .collect::<error::Result<Vec<_>>>()?;
+                hardlinks.sort();
 
-            let mut string = Vec::new();
-            string.extend_from_slice(&block[curr..curr + strlen as usize]);
-            curr += strlen as usize;
+                // everything is checked before anything is written
+                options.limits.check_entries(entries.len() + hardlinks.len())?;
+                let mut bytes_total = 0;
+                for (name, (_, _, (_, length))) in &entries {
+                    bytes_total += length;
+                    options.limits.check_size(name, *length, bytes_total)?;
+                }
+                // links in the backpack point inside `dir`, and nothing is written through them
+                let mut links = HashSet::new();
+                for (name, _) in &entries {
+                    if let Some(target) = self.attribute(name, SYMLINK_ATTRIBUTE) {
+                        if !directory::link_stays_inside(name, &EntryName::from_bytes(target).to_path_buf()) {
+                            return Err(PackError::UnsafePath(PathBuf::from(name)));
+                        }
+                        links.insert(Path::new(name.as_str()));
+                    }
+                }
+                let names = entries.iter().map(|(name, _)| name).chain(aliases.iter().flat_map(|(alias, target)| [alias, target]));
+                for name in names {
+                    if Path::new(name).ancestors().skip(1).any(|ancestor| links.contains(ancestor)) {
+                        return Err(PackError::UnsafePath(PathBuf::from(name)));
+                    }
+                }
+                if options.sandbox {
+                    std::fs::create_dir_all(dir).at_path(dir)?;
+                }
+                let sandbox = match options.sandbox {
+                    true => Some(std::fs::canonicalize(dir).at_path(dir)?),
+                    false => None,
+                };
+                let mut tracker = Tracker::new(progress, entries.len() + hardlinks.len(), bytes_total);
 
-            let mut offset_bytes = [0u8; 8];
-            offset_bytes.copy_from_slice(&block[curr..curr+8]);
-            curr += 8;
-            let offset = u64::from_le_bytes(offset_bytes);
+                // files are written a batch at a time, in parallel with the `parallel` feature
+                let batch_size = if cfg!(feature = "parallel") { rayon::current_num_threads() } else { 1 };
+                let mut batch = Vec::new();
+                let mut dirs = Vec::new();
+                for (name, (path, is_dir, key)) in entries {
+                    if batch.len() >= batch_size {
+                        self.write_files(&mut batch, options, &mut tracker)?;
+                    }
+                    if is_dir {
+                        directory::create_dirs(sandbox.as_deref(), &path, &name)?;
+                        tracker.done(&name, 0)?;
+                        dirs.push((name, path));
+                        continue;
+                    }
+                    if let Some(parent) = path.parent() {
+                        directory::create_dirs(sandbox.as_deref(), parent, &name)?;
+                    }
+                    if let Some(sandbox) = &sandbox {
+                        directory::check_sandboxed(sandbox, &path, &name)?;
+                    }
+                    if let Some(target) = self.attribute(&name, SYMLINK_ATTRIBUTE) {
+                        directory::create_symlink(&EntryName::from_bytes(target).to_path_buf(), &path)?;
+                        tracker.done(&name, key.1)?;
+                        continue;
+                    }
+                    batch.push((name, path, key));
+                }
+                self.write_files(&mut batch, options, &mut tracker)?;
+                for (path, target, alias) in hardlinks {
+                    if let Some(parent) = path.parent() {
+                        directory::create_dirs(sandbox.as_deref(), parent, alias)?;
+                    }
+                    if let Some(sandbox) = &sandbox {
+                        directory::check_sandboxed(sandbox, &path, alias)?;
+                        directory::check_sandboxed(sandbox, &target, alias)?;
+                    }
+                    directory::create_hardlink(&target, &path)?;
+                    tracker.done(alias, 0)?;
+                }
+                // after their contents, which change the modification times of directories
+                for (name, path) in dirs.iter().rev() {
+                    directory::restore_metadata(path, self.modified(name), self.mode(name), options)?;
+                }
+                Ok(())
+            }
+        }
+    }
 
-            let mut length_bytes = [0u8; 8];
-            length_bytes.copy_from_slice(&block[curr..curr+8]);
-            curr += 8;
-            let length = u64::from_le_bytes(length_bytes);
+    /// Write the files in `batch` to their paths and empty it, for [`extract_to_with_progress`](Self::extract_to_with_progress).
+    fn write_files(&'f self, batch: &mut Vec<(String, PathBuf, (u64, u64))>, options: &ExtractOptions, tracker: &mut Tracker) -> error::Result<()> {
+        type File<'a> = (&'a PathBuf, &'a RwLock<Vec<u8>>, Vec<(u64, u64)>, Option<SystemTime>, Option<u32>);
+        let slices = batch.iter()
+            .map(|(_, _, key)| self.open_slice(*key))
+            .collect::<error::Result<Vec<_>>>()?;
+        let files = batch.iter()
+            .zip(&slices)
+            .map(|((name, path, _), slice)| {
+                let holes = if options.restore_holes { self.holes(name) } else { Vec::new() };
+                (path, slice.get_bytes(), holes, self.modified(name), self.mode(name))
+            })
+            .collect::<Vec<_>>();
+        let write = |(path, contents, holes, modified, mode): &File| {
+            match holes.is_empty() {
+                true => std::fs::write(path, &*contents.read()).at_path(path)?,
+                false => sparse::write_sparse(path, &contents.read(), holes)?,
+            }
+            directory::restore_metadata(path, *modified, *mode, options)
+        };
+        #[cfg(feature = "parallel")]
+        files.par_iter().try_for_each(write)?;
+        #[cfg(not(feature = "parallel"))]
+        files.iter().try_for_each(write)?;
 
-            let string = String::from_utf8(string)?;
-            offsets.insert(string, (offset, length));
+        for (name, _, key) in batch.drain(..) {
+            tracker.done(&name, key.1)?;
         }
-
         Ok(())
     }
 
-    fn parse_backwards_compatible(_file: &mut RawFile, version: u16) -> error::Result<(HashMap<String, (u64, u64)>, Vec<u64>)>{
-        match version {
-            _ => Err(PackError::Incompatible(version))
-        }
-    }
+    /// Write every file and [added](Self::add_dir) directory to `writer` as a tar archive, with
+    /// their [modification times](Self::modified), or the unix epoch for those which don't have one.
+    pub fn to_tar(&'f self, writer: impl Write) -> error::Result<()> {
+        match self {
+            BackPack::Parsed { offsets, modified, .. } => {
+                let mut entries = offsets.read().iter()
+                    .map(|(name, key)| (name.clone(), *key))
+                    .collect::<Vec<_>>();
+                entries.sort();
 
-    fn parse_headers(file: &mut RawFile) -> error::Result<(HashMap<String, (u64, u64)>, Vec<u64>)> {
-        let mut magic_bytes = [0u8; PACK_MAGIC.len()];
-        file.read_exact(&mut magic_bytes)?;
-        if magic_bytes != PACK_MAGIC {
-            return Err(PackError::BadMagic);
+                let mut members = Vec::new();
+                for (name, key) in entries {
+                    members.push(tar::Member {
+                        modified: modified.get(&name).copied().unwrap_or(0),
+                        contents: self.open_slice(key)?.get_bytes().read().clone(),
+                        name,
+                    });
+                }
+                tar::write_tar(writer, &members)
+            }
         }
+    }
 
-        let mut version_bytes = [0u8; 2];
-        file.read_exact(&mut version_bytes)?;
-        let version = u16::from_le_bytes(version_bytes);
-        if version != PACK_VERSION {
-            return Self::parse_backwards_compatible(file, version);
+    /// Whether `name` is a directory [added](Self::add_dir) to the backpack.
+    pub fn is_dir(&self, name: impl AsRef<Path>) -> bool {
+        match self {
+            BackPack::Parsed { offsets, .. } => offsets.read().contains_key(&format!("{}/", self.stored_name(name.as_ref()))),
         }
+    }
 
-
-        let mut size_bytes = [0u8; 8];
-        file.read_exact(&mut size_bytes)?;
-        let _pack_size = u64::from_le_bytes(size_bytes);
-
-        let mut first_toc_offset_bytes = [0u8; 8];
-        file.read_exact(&mut first_toc_offset_bytes)?;
-        let first_toc_offset = u64::from_le_bytes(first_toc_offset_bytes);
-
-        assert_eq!(file.current_offset()?, PACK_HEADER_SIZE);
-
-        let mut offsets = HashMap::new();
-        let mut toc_blocks = Vec::new();
-
-        let mut next_toc_offset = first_toc_offset;
-
-        while next_toc_offset != 0 {
-            toc_blocks.push(next_toc_offset);
-
-            file.seek(SeekFrom::Start(next_toc_offset))?;
-
-            let mut toc_filled_bytes = [0u8; 2];
-            file.read_exact(&mut toc_filled_bytes)?;
-            let toc_filled = u16::from_le_bytes(toc_filled_bytes);
-
-            let mut next_toc_bytes = [0u8; 8];
-            file.read_exact(&mut next_toc_bytes)?;
-            next_toc_offset = u64::from_le_bytes(next_toc_bytes);
-
-            let mut toc_block_bytes = [0u8; TOC_SIZE as usize - 10];
-            file.read_exact(&mut toc_block_bytes)?;
-            Self::parse_toc_block(toc_filled - 10, &toc_block_bytes, &mut offsets)?;
+    /// Listen for files being added, overwritten or removed from now on, so dependent state
+    /// can be rebuilt when something changes instead of polling for it.
+    /// Events carry names as passed in, before [name hashing](Self::set_name_hasher).
+    pub fn changes(&self) -> Changes {
+        match self {
+            BackPack::Parsed { subscribers, .. } => subscribers.subscribe(),
         }
-
-        Ok((offsets, toc_blocks))
     }
 
-    pub fn open_complete<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
-        let mut file = file.try_into().map_err(Into::into)?;
-
-        let (offsets, mut toc_blocks) = Self::parse_headers(&mut file)?;
-        toc_blocks.sort();
-
-        let data = FrozenMap::new();
-        let mut total_size = 0;
+    /// Write the backpack as a small metadata file holding the table of contents, and data files
+    /// of at most `max_data_file_size` bytes holding the contents, in the order they're stored.
+    /// A file larger than that gets a data file to itself. Launchers can fetch the metadata of
+    /// a new version, compare it with [`SplitPack::changed`] and download only what changed.
+    /// Expiry, modification times, aliases and attributes are carried in entries like
+    /// [`freeze`](Self::freeze) writes them. Open it again with [`open_split`](Self::open_split).
+    pub fn export_split(&'f self, max_data_file_size: u64) -> error::Result<SplitPack> {
+        match self {
+            BackPack::Parsed { offsets, hidden, expiry, modified, aliases, attributes, .. } => {
+                let mut live = offsets.read().iter()
+                    .map(|(name, key)| (name.clone(), *key))
+                    .collect::<Vec<_>>();
+                live.sort_by_key(|(_, (offset, _))| *offset);
 
-        for (_name, (offset, length)) in &offsets {
-            let new_offset = Self::convert_offset(&toc_blocks, *offset);
-            file.seek(SeekFrom::Start(new_offset))?;
+                let mut contents = Vec::new();
+                for (_, key) in &live {
+                    contents.push(self.open_slice(*key)?.get_bytes().read().clone());
+                }
 
-            let mut buf = vec![0; *length as usize];
-            file.read_exact(&mut buf)?;
+                let mut entries = live.iter()
+                    .zip(&contents)
+                    .map(|((name, _), contents)| (name.as_str(), contents.as_slice()))
+                    .collect::<Vec<_>>();
 
-            total_size += buf.len() as u64;
-            data.insert((*offset, *length), Box::new(RwLock::new(buf)));
+                let aliases = aliases.iter()
+                    .filter(|(_, target)| offsets.read().contains_key(*target))
+                    .map(|(alias, target)| (alias.clone(), target.clone()))
+                    .collect();
+                let attributes = attributes.iter()
+                    .filter(|(name, _)| offsets.read().contains_key(*name))
+                    .map(|(name, attributes)| (name.clone(), attributes.clone()))
+                    .collect();
+                let expiry_contents = encode_times(expiry);
+                let modified_contents = encode_times(modified);
+                let attribute_contents = encode_attributes(&attributes);
+                let alias_contents = encode_aliases(&aliases);
+                if !expiry.is_empty() {
+                    entries.push((EXPIRY_ENTRY, &expiry_contents));
+                }
+                if !modified.is_empty() {
+                    entries.push((MODIFIED_ENTRY, &modified_contents));
+                }
+                if !attributes.is_empty() {
+                    entries.push((ATTRIBUTES_ENTRY, &attribute_contents));
+                }
+                if !aliases.is_empty() {
+                    entries.push((ALIAS_ENTRY, &alias_contents));
+                }
+                SplitPack::write(&entries, hidden, max_data_file_size)
+            }
         }
-
-
-        Ok(Self::Parsed {
-            file: Some(file),
-            offsets: RwLock::new(offsets),
-            removals: FrozenMap::new(),
-            data,
-
-            // not closed
-            total_size: AtomicU64::new(total_size),
-            closed: false
-        })
     }
 
-    #[doc(hidden)]
-    pub fn open_partial<E: Into<PackError>>(_backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
-        todo!()
+    /// Write the backpack to `path` like [`freeze`](Self::freeze) does, so a crash halfway never
+    /// leaves a partly written backpack at `path`: it's written to a temporary file next to it,
+    /// synced to disk, and renamed over `path`. `path` holds either the old or the new backpack.
+    pub fn save_atomic(&'f self, path: impl AsRef<Path>) -> error::Result<()> {
+        self.save_atomic_with(path, FreezeOptions::default())
     }
 
-    /// Create a new pack in a file. Usually called after File::create().
-    /// Existing contents of the file are deleted.
-    ///
-    /// ```rust
-    /// # use backpack::RawFile;
-    /// # use backpack::BackPack;
-    /// # use backpack::PackError;
-    ///
-    /// # fn main() -> Result<(), PackError> {
-    ///     let file = RawFile::in_memory("test.bp");
-    ///     let bp = BackPack::create(file)?;
-    ///
-    ///     // when done with it
-    ///     bp.close();
-    /// #   Ok(())
-    /// # }
-    /// ```
-    ///
-    pub fn create<E: Into<PackError>>(backing: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
-        let mut file = backing.try_into().map_err(Into::into)?;
-        file.seek(SeekFrom::Start(0))?;
+    /// Like [`save_atomic`](Self::save_atomic), written with `options`.
+    pub fn save_atomic_with(&'f self, path: impl AsRef<Path>, options: FreezeOptions) -> error::Result<()> {
+        let path = path.as_ref();
+        let file_name = path.file_name().ok_or_else(|| PackError::FileNotFound(path.to_path_buf()))?;
+        let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
 
-        Ok(Self::Parsed {
-            file: Some(file),
-            offsets: Default::default(),
-            removals: FrozenMap::new(),
-            data: FrozenMap::new(),
-            // not closed
-            total_size: AtomicU64::new(0),
-            closed: false,
-        })
-    }
+        let written = std::fs::File::create(&tmp_path).at_path(&tmp_path).and_then(|mut f| {
+            self.freeze(&mut f, options)?;
+            f.sync_all().at_path(&tmp_path)
+        });
+        if let Err(e) = written.and_then(|_| std::fs::rename(&tmp_path, path).at_path(path)) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
 
-    /// Gets the number of bytes used to store files currently.
-    /// If packs get really large (contain lots of files) you
-    /// might want to flush
-    pub fn memory_bytes(&self) -> usize {
-        match self {
-            BackPack::PartiallyParsed { .. } => todo!(),
-            BackPack::Parsed { total_size, .. } => {
-                total_size.load(Ordering::SeqCst) as usize
-            },
+        // the rename itself only survives a crash once the directory is synced
+        #[cfg(unix)]
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::File::open(dir).and_then(|dir| dir.sync_all()).at_path(dir)?;
         }
+        Ok(())
     }
 
-    /// Write all changes since the last flush to the file
-    ///
-    /// ```rust
-    /// # use backpack::RawFile;
-    /// # use backpack::pack::BackPack;
-    /// # use backpack::pack::PackError;
-    /// # use std::io::Read;
-    ///
-    /// # fn main() -> Result<(), PackError> {
-    ///     let file = RawFile::in_memory("test.bp");
-    ///     let bp = BackPack::create(file)?;
-    ///
-    ///     // when done with it
-    ///     let mut file = bp.close()?;
-    ///     // now there's stuff in the file, which can be read again with `open`
-    ///     let bp = BackPack::open(file)?;
-    ///
-    /// #   Ok(())
-    /// # }
-    ///
-    /// ```
-    pub fn flush(&mut self) -> error::Result<()> {
+    /// Write an optimized copy of the backpack to `writer`, to ship once it won't change anymore.
+    /// Only files currently in the backpack are written, files with identical contents share
+    /// them, and files are laid out in the order of the trace in `options` so loading them is
+    /// one sequential read. The backpack itself is left as it is.
+    pub fn freeze(&'f self, mut writer: impl Write, options: FreezeOptions) -> error::Result<()> {
         match self {
-            BackPack::PartiallyParsed { .. } => { todo!() }
             BackPack::Parsed {
-                file,
                 offsets,
-                data,
-                removals,
+                hidden,
+                expiry,
+                modified,
+                attributes,
+                aliases,
+                alignment,
+                alignments,
+                index_protection,
+                compression,
+                min_compression_ratio,
+                compressions,
+                tiers,
+                encryption,
+                #[cfg(feature = "signing")]
+                signing,
                 ..
             } => {
-                let mut new_data = Vec::new();
-                let mut new_offsets = HashMap::new();
-                for (name, (start, length)) in offsets.read().deref() {
-                    let contents = data.get(&(*start, *length))
-                        .ok_or(PackError::InvalidEntry)?;
-
-                    if removals.get(name).is_some() {
-                        continue
+                if let Some(alignment) = options.alignment {
+                    if !alignment.is_power_of_two() {
+                        return Err(PackError::BadAlignment(alignment));
                     }
+                }
+                if options.index == IndexPlacement::End && *index_protection != IndexProtection::None {
+                    return Err(PackError::IncompatibleOptions("a protected index has to be at the start of the backpack"));
+                }
+                let default_alignment = options.alignment.unwrap_or(*alignment);
+                let alignments = alignments.lock();
+                let alignment_of = |name: &str| alignments.get(name).copied().unwrap_or(default_alignment);
 
-                    new_offsets.insert(name.clone(), (new_data.len() as u64, *length));
-                    new_data.extend(contents.read().deref());
+                let trace = options.trace.iter()
+                    .flat_map(|trace| trace.names().iter().enumerate())
+                    .map(|(i, name)| (name.as_str(), i))
+                    .collect::<HashMap<_, _>>();
+                let mut live = offsets.read().iter()
+                    .filter(|(name, _)| !METADATA_ENTRIES.contains(&name.as_str()))
+                    .map(|(name, key)| (name.clone(), *key))
+                    .collect::<Vec<_>>();
+                live.sort_by_key(|(name, (offset, _))| (trace.get(name.as_str()).copied().unwrap_or(usize::MAX), *offset));
+
+                let mut contents = Vec::new();
+                for (_, key) in &live {
+                    contents.push(self.open_slice(*key)?.get_bytes().read().clone());
                 }
+                let mut entries = live.iter()
+                    .zip(&contents)
+                    .map(|((name, _), contents)| (name.as_str(), contents.as_slice()))
+                    .collect::<Vec<_>>();
 
-                if let Some(file) = file {
-                    file.seek(SeekFrom::Start(0))?;
-                    BackPack::write_headers(file, new_data.len() as u64, &new_offsets)?;
+                #[cfg(feature = "signing")]
+                let signature = signing.as_ref().map(|key| key.sign(&signed_message(&entries, hidden, aliases, expiry, modified, attributes)));
 
-                    file.write_all(&new_data)?;
-                    Ok(())
-                } else {
-                    Err(Closed)
+                let compressions = compressions.lock();
+                let compression_of = |name: &str| compression_for(name, &compressions, tiers, *compression);
+                let mut compressed = Compressed::new();
+                let packed_contents = compression::compress_entries(&entries, compression_of, *min_compression_ratio, &mut compressed)?;
+                for (i, contents) in &packed_contents {
+                    entries[*i].1 = contents;
+                }
+                let mut encrypted = HashSet::new();
+                let encrypted_contents = match encryption {
+                    Some(key) => encryption::encrypt_entries(&entries, key, &mut encrypted)?,
+                    None => Vec::new(),
+                };
+                for (i, contents) in &encrypted_contents {
+                    entries[*i].1 = contents;
                 }
-            }
-        }
-    }
 
-    pub fn add_file<E: Into<PackError>>(&'f self, f: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<InMemoryFile<'f, 'backpack>> {
-        let mut f = f.try_into().map_err(Into::<PackError>::into)?;
+                let aliases = aliases.iter()
+                    .filter(|(_, target)| offsets.read().contains_key(*target))
+                    .map(|(alias, target)| (alias.clone(), target.clone()))
+                    .collect();
+                let expiry_contents = encode_times(expiry);
+                let modified_contents = encode_times(modified);
+                let attributes = attributes.iter()
+                    .filter(|(name, _)| offsets.read().contains_key(*name))
+                    .map(|(name, attributes)| (name.clone(), attributes.clone()))
+                    .collect();
+                let attribute_contents = encode_attributes(&attributes);
+                let alias_contents = encode_aliases(&aliases);
+                let tiers = tiers.iter()
+                    .filter(|(name, _)| offsets.read().contains_key(*name))
+                    .map(|(name, tier)| (name.clone(), *tier))
+                    .collect();
+                let tier_contents = encode_tiers(&tiers);
+                if !expiry.is_empty() {
+                    entries.push((EXPIRY_ENTRY, &expiry_contents));
+                }
+                if !modified.is_empty() {
+                    entries.push((MODIFIED_ENTRY, &modified_contents));
+                }
+                if !attributes.is_empty() {
+                    entries.push((ATTRIBUTES_ENTRY, &attribute_contents));
+                }
+                if !aliases.is_empty() {
+                    entries.push((ALIAS_ENTRY, &alias_contents));
+                }
+                if !tiers.is_empty() {
+                    entries.push((TIERS_ENTRY, &tier_contents));
+                }
+                let encryption_contents = encryption.as_ref().map(EncryptionKey::encode);
+                if let Some(contents) = &encryption_contents {
+                    entries.push((ENCRYPTION_ENTRY, contents));
+                }
+                #[cfg(feature = "signing")]
+                if let Some(signature) = &signature {
+                    entries.push((SIGNATURE_ENTRY, signature));
+                }
 
-        match self {
-            BackPack::PartiallyParsed { .. } => todo!(),
-            BackPack::Parsed {
-                offsets,
-                data,
-                total_size,
-                .. } => {
+                let checksums = match options.checksum {
+                    Checksum::Crc32 => checksums_of(&entries),
+                    Checksum::None => HashMap::new(),
+                };
+                let stored_alignments = entries.iter()
+                    .map(|(name, _)| (name.to_string(), alignment_of(name)))
+                    .filter(|(_, alignment)| *alignment > 1)
+                    .collect::<HashMap<_, _>>();
+                let data_start = match options.index {
+                    IndexPlacement::Start => {
+                        // the size of the table of contents doesn't depend on the offsets in it
+                        let placeholder = entries.iter()
+                            .map(|(name, contents)| (name.to_string(), (0, contents.len() as u64)))
+                            .collect::<HashMap<_, _>>();
+                        PACK_HEADER_SIZE + Self::create_toc_at(&placeholder, hidden, &compressed, &encrypted, &checksums, &stored_alignments, PACK_HEADER_SIZE)?.len() as u64 * TOC_SIZE as u64
+                    }
+                    IndexPlacement::End => PACK_HEADER_SIZE,
+                };
 
-                let mut f_data = Vec::new();
-                f.read_to_end(&mut f_data)?;
-                let prev = total_size.fetch_add(f_data.len() as u64, Ordering::SeqCst);
-                let key = (prev, f_data.len() as u64);
+                let mut layout = HashMap::new();
+                let mut stored = HashMap::<&[u8], (u64, u64)>::new();
+                let mut data = Vec::new();
+                for (name, contents) in &entries {
+                    if contents.is_empty() {
+                        layout.insert(name.to_string(), (0, 0));
+                        continue;
+                    }
 
-                let name = f.name().ok_or(NoName)?;
+                    let alignment = alignment_of(name);
+                    let duplicate = stored.get(contents)
+                        .filter(|(offset, _)| !options.keep_duplicates && (data_start + offset).is_multiple_of(alignment));
+                    if let Some(key) = duplicate {
+                        layout.insert(name.to_string(), *key);
+                        continue;
+                    }
 
-                offsets.write().deref_mut().insert(name.to_string_lossy().into_owned(), key);
-                data.insert(key, Box::new(RwLock::new(f_data)));
+                    let padding = aligned::padding(data_start + data.len() as u64, alignment);
+                    data.resize(data.len() + padding as usize, 0);
+                    let key = (data.len() as u64, contents.len() as u64);
+                    stored.entry(contents).or_insert(key);
+                    layout.insert(name.to_string(), key);
+                    data.extend_from_slice(contents);
+                }
 
-                Ok(InMemoryFile::Packed {
-                    name: name.to_path_buf(),
-                    data: PackSlice::new(key.0, key.1, self),
-                })
-            }
-        }
-    }
+                if options.index == IndexPlacement::End {
+                    // like a PackWriter writes it
+                    let first_block = PACK_HEADER_SIZE + data.len() as u64;
+                    let toc_blocks = Self::create_toc_at(&layout, hidden, &compressed, &encrypted, &checksums, &stored_alignments, first_block)?;
+                    let first_toc = if toc_blocks.is_empty() { 0 } else { first_block };
+                    writer.write_all(&PackHeader::new(data.len() as u64, first_toc).for_toc(&toc_blocks).to_bytes())?;
+                    writer.write_all(&data)?;
+                    for block in toc_blocks {
+                        writer.write_all(&block)?;
+                    }
+                    writer.flush()?;
+                    return Ok(());
+                }
 
-    pub fn add_empty_file(&'f self, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
-        self.add_file(InMemoryFile::new(name))
-    }
+                let mut index = Vec::new();
+                Self::write_headers(&mut index, data.len() as u64, &layout, hidden, &compressed, &encrypted, &checksums, &stored_alignments)?;
+                writer.write_all(&index)?;
+                writer.write_all(&data)?;
+                protection::write_trailer(&mut writer, &index, (index.len() + data.len()) as u64, *index_protection)?;
+                writer.flush()?;
 
-    pub fn add_file_named<E: Into<PackError>>(&'f self, f: impl TryInto<RawFile<'f, 'backpack>, Error=E>, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
-        self.add_file(f.try_into().map_err(Into::<PackError>::into)?.with_name(name))
+                Ok(())
+            }
+        }
     }
 
-    pub fn remove_file(&mut self, name: impl AsRef<Path>) -> error::Result<()> {
+    /// Hide a file from listings, for files like debug-only or tooling-only assets which ship
+    /// in the same backpack. Hidden files can still be opened by name, this is not access control.
+    /// Stored in the table of contents, so streaming and remote readers see it too.
+    pub fn set_hidden(&mut self, name: impl AsRef<Path>, hide: bool) -> error::Result<()> {
         let name = name.as_ref();
+        let name_str = self.stored_name(name);
         match self {
-            BackPack::PartiallyParsed { .. } => todo!(),
-            BackPack::Parsed {
-                offsets,
-                removals,
-                ..
-            } => {
-                if let Some(ref _identifier) = offsets.write().remove(name.to_string_lossy().as_ref()) {
-                    removals.insert(name.to_string_lossy().into_owned(), &());
-                    Ok(())
+            BackPack::Parsed { offsets, hidden, .. } => {
+                if !offsets.get_mut().contains_key(&name_str) {
+                    return Err(PackError::FileNotFound(name.to_path_buf()));
+                }
+
+                if hide {
+                    hidden.insert(name_str);
                 } else {
-                    Err(PackError::FileNotFound(name.to_path_buf()))
+                    hidden.remove(&name_str);
                 }
+                Ok(())
             }
         }
     }
 
-    pub fn get_file(&'f self, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+    pub fn is_hidden(&self, name: impl AsRef<Path>) -> bool {
         match self {
-            BackPack::PartiallyParsed { .. } => todo!(),
-            BackPack::Parsed { offsets, removals, .. } => {
-                let path_buf = name.as_ref().to_path_buf();
-
-                // path when removal is not yet updated in main
-                if removals.get(name.as_ref().to_string_lossy().as_ref()).is_some() {
-                    return Err(PackError::FileNotFound(path_buf.clone()));
-                }
-
-                let r = offsets.read();
-                let (offset, length) = r.get(name.as_ref().to_string_lossy().as_ref())
-                    .ok_or_else(|| PackError::FileNotFound(path_buf.clone()))?;
-
-                Ok(InMemoryFile::Packed {
-                    name: path_buf,
-                    data: PackSlice::new(*offset, *length, self)
-                })
-            }
+            BackPack::Parsed { hidden, .. } => hidden.contains(&self.resolved_name(name.as_ref())),
         }
     }
 
@@ -486,7 +4518,6 @@ impl<'f, 'backpack: 'f> BackPack<'f, 'backpack> {
     fn close_internal(mut self) -> error::Result<RawFile<'f, 'backpack>> {
         // make sure closing doesn't panic
         match &mut self {
-            BackPack::PartiallyParsed { closed, file, .. } |
             BackPack::Parsed { closed, file, .. } => {
                 *closed = true;
                 let mut file = file.take().ok_or(Closed)?;
@@ -505,8 +4536,9 @@ impl<'f, 'backpack: 'f> BackPack<'f, 'backpack> {
 
 impl<'f, 'backpack> Drop for BackPack<'f, 'backpack> {
     fn drop(&mut self) {
+        self.report_leaked_handles();
+
         match &self {
-            BackPack::PartiallyParsed {closed,  .. } |
             BackPack::Parsed { closed, .. } => {
                 if !closed {
                     log::warn!("dropping unsaved backpack may panic. Attempting best-effort cleanup.");
@@ -0,0 +1,272 @@
+use std::fs::File;
+use std::io::{self, BufRead, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use parking_lot::Mutex;
+
+/// How many bytes a [`BufferedFile`] collects before writing them, unless set with
+/// [`RawFile::with_write_buffer`](crate::RawFile::with_write_buffer).
+pub const DEFAULT_WRITE_BUFFER: usize = 64 * 1024;
+
+/// How many bytes are read ahead at once for [`BufRead`].
+pub(crate) const READ_BUFFER: usize = 8 * 1024;
+
+/// A file on disk which collects small writes and passes them to the operating system at
+/// once. Writes still pending are written before anything else is done with the file, like
+/// reading, seeking or syncing it, so it behaves like the file itself. What's still pending
+/// when it's dropped is written then, ignoring errors: [flush](Write::flush) first to see them.
+///
+/// Reading it as a [`BufRead`] reads ahead, and what wasn't used of that is given back before
+/// anything but reading is done with the file.
+pub struct BufferedFile {
+    file: File,
+    /// bytes to be written at the position of `file`
+    pending: Mutex<Vec<u8>>,
+    /// bytes read before the position of `file` which weren't used yet
+    read_ahead: Mutex<ReadAhead>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct ReadAhead {
+    bytes: Vec<u8>,
+    used: usize,
+}
+
+impl BufferedFile {
+    pub(crate) fn new(file: File) -> Self {
+        Self::with_capacity(file, DEFAULT_WRITE_BUFFER)
+    }
+
+    pub(crate) fn with_capacity(file: File, capacity: usize) -> Self {
+        Self {
+            file,
+            pending: Mutex::new(Vec::new()),
+            read_ahead: Mutex::new(ReadAhead::default()),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Collect up to `capacity` bytes before writing them, 0 writes everything right away.
+    /// Pending writes over the new capacity are written with the next write.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// The file, after writing what's pending, to use it directly.
+    pub fn get_ref(&self) -> io::Result<&File> {
+        give_back(&self.file, &mut self.read_ahead.lock())?;
+        write_pending(&self.file, &mut self.pending.lock())?;
+        Ok(&self.file)
+    }
+
+    /// Like [`get_ref`](Self::get_ref), for changing the file.
+    pub(crate) fn get_mut(&mut self) -> io::Result<&mut File> {
+        self.settle()?;
+        Ok(&mut self.file)
+    }
+
+    /// Put the position of the file where it seems to be, for anything but reading.
+    fn settle(&mut self) -> io::Result<()> {
+        give_back(&self.file, self.read_ahead.get_mut())?;
+        write_pending(&self.file, self.pending.get_mut())
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self::with_capacity(self.get_ref()?.try_clone()?, self.capacity))
+    }
+}
+
+/// Move the position of `file` back to before what wasn't used of `read_ahead`.
+fn give_back(mut file: &File, read_ahead: &mut ReadAhead) -> io::Result<()> {
+    let unused = read_ahead.bytes.len() - read_ahead.used;
+    if unused != 0 {
+        file.seek(SeekFrom::Current(-(unused as i64)))?;
+    }
+    read_ahead.bytes.clear();
+    read_ahead.used = 0;
+    Ok(())
+}
+
+/// Write out `pending`, dropping only what was written when it fails halfway.
+fn write_pending(mut file: &File, pending: &mut Vec<u8>) -> io::Result<()> {
+    while !pending.is_empty() {
+        match file.write(pending) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                pending.drain(..n);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl Write for BufferedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        give_back(&self.file, self.read_ahead.get_mut())?;
+        let pending = self.pending.get_mut();
+        if pending.len() + buf.len() > self.capacity {
+            write_pending(&self.file, pending)?;
+        }
+        // large writes gain nothing from a copy into the buffer
+        if buf.len() >= self.capacity {
+            return self.file.write(buf);
+        }
+        pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        give_back(&self.file, self.read_ahead.get_mut())?;
+        let pending = self.pending.get_mut();
+        if pending.len() + len > self.capacity {
+            write_pending(&self.file, pending)?;
+        }
+        if len >= self.capacity {
+            return self.file.write_vectored(bufs);
+        }
+        for buf in bufs {
+            pending.extend_from_slice(buf);
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        write_pending(&self.file, self.pending.get_mut())?;
+        self.file.flush()
+    }
+}
+
+impl Read for BufferedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_ahead = self.read_ahead.get_mut();
+        if read_ahead.used < read_ahead.bytes.len() {
+            let n = (&read_ahead.bytes[read_ahead.used..]).read(buf)?;
+            self.consume(n);
+            return Ok(n);
+        }
+        write_pending(&self.file, self.pending.get_mut())?;
+        self.file.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        let read_ahead = self.read_ahead.get_mut();
+        if read_ahead.used < read_ahead.bytes.len() {
+            let n = (&read_ahead.bytes[read_ahead.used..]).read_vectored(bufs)?;
+            self.consume(n);
+            return Ok(n);
+        }
+        write_pending(&self.file, self.pending.get_mut())?;
+        self.file.read_vectored(bufs)
+    }
+}
+
+impl BufRead for BufferedFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let read_ahead = self.read_ahead.get_mut();
+        if read_ahead.used == read_ahead.bytes.len() {
+            write_pending(&self.file, self.pending.get_mut())?;
+            read_ahead.bytes.resize(READ_BUFFER, 0);
+            read_ahead.used = 0;
+            match self.file.read(&mut read_ahead.bytes) {
+                Ok(n) => read_ahead.bytes.truncate(n),
+                Err(e) => {
+                    read_ahead.bytes.clear();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(&read_ahead.bytes[read_ahead.used..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let read_ahead = self.read_ahead.get_mut();
+        read_ahead.used = (read_ahead.used + amt).min(read_ahead.bytes.len());
+    }
+}
+
+impl Seek for BufferedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.settle()?;
+        self.file.seek(pos)
+    }
+}
+
+/// Bytes read ahead from some position, for [`BufRead`] on files which are read at an offset.
+#[derive(Default)]
+pub(crate) struct ReadWindow {
+    at: u64,
+    bytes: Vec<u8>,
+}
+
+impl ReadWindow {
+    /// What was read ahead from `position` on, reading it with `read` at an offset first when
+    /// it wasn't read yet.
+    pub(crate) fn fill(&mut self, position: u64, read: impl FnOnce(u64, &mut [u8]) -> io::Result<usize>) -> io::Result<&[u8]> {
+        let in_window = position >= self.at && position < self.at + self.bytes.len() as u64;
+        if !in_window {
+            self.bytes.resize(READ_BUFFER, 0);
+            self.at = position;
+            match read(position, &mut self.bytes) {
+                Ok(n) => self.bytes.truncate(n),
+                Err(e) => {
+                    self.bytes.clear();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(&self.bytes[(position - self.at) as usize..])
+    }
+
+    /// Forget what was read ahead, when the file changes.
+    pub(crate) fn clear(&mut self) {
+        self.bytes.clear();
+    }
+}
+
+impl Drop for BufferedFile {
+    fn drop(&mut self) {
+        if let Err(e) = write_pending(&self.file, self.pending.get_mut()) {
+            log::warn!("failed to write buffered data when closing file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use crate::pack::buffered::BufferedFile;
+
+    #[test]
+    fn test_buffered_writes() {
+        let path = std::env::temp_dir().join("backpack_test_buffered");
+        let mut file = BufferedFile::with_capacity(std::fs::File::options().create(true).truncate(true).read(true).write(true).open(&path).unwrap(), 8);
+
+        file.write_all(b"abc").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+        assert_eq!(file.get_ref().unwrap().metadata().unwrap().len(), 3);
+
+        // pending writes land before seeking away, and reads see them
+        file.write_all(b"def").unwrap();
+        file.seek(SeekFrom::Start(1)).unwrap();
+        file.write_all(b"X").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "aXcdef");
+
+        // larger than the buffer goes straight to the file
+        file.write_all(b"0123456789").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"aXcdef0123456789");
+
+        file.write_all(b"!").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), b"aXcdef0123456789!");
+        std::fs::remove_file(path).unwrap();
+    }
+}
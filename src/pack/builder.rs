@@ -0,0 +1,185 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::PackError;
+#[cfg(feature = "crypto")]
+use crate::pack::encryption::Encryption;
+use crate::pack::{BackPack, Compression, DirectoryOptions, FreezeOptions, IndexProtection, PackWriter, RawFile, WriteLimits};
+
+/// Where the table of contents of a new backpack goes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum IndexPlacement {
+    /// before the contents, where [`BackPack`] writes it. [`StreamReader`](crate::pack::StreamReader)s
+    /// can read these backpacks, and [listing](BackPack::list_fast) them takes a single read.
+    #[default]
+    Start,
+    /// after the contents, where a [`PackWriter`] writes it, so entries can be written as they come
+    End,
+}
+
+/// How the contents of entries are checksummed, so damage is noticed when they're
+/// [verified](BackPack::set_verify_checksums).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Checksum {
+    #[default]
+    Crc32,
+    /// store no checksums, damage goes unnoticed
+    None,
+}
+
+/// Options for a new backpack in one place, for writing it with a [`PackWriter`]
+/// or packing a directory into it with [`build_from_dir`](Self::build_from_dir).
+///
+/// ```no_run
+/// # use backpack::pack::{Compression, PackBuilder, PackError};
+/// # fn main() -> Result<(), PackError> {
+/// PackBuilder::new()
+///     .compression(Compression::Deflate)
+///     .alignment(4096)
+///     .metadata(".build/commit", "3e73afd")
+///     .build_from_dir("assets", std::fs::File::create("assets.bp")?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct PackBuilder {
+    compression: Compression,
+    min_compression_ratio: f64,
+    #[cfg(feature = "crypto")]
+    encryption: Option<Encryption>,
+    checksum: Checksum,
+    alignment: Option<u64>,
+    /// `None` for wherever the output writes it
+    index: Option<IndexPlacement>,
+    protection: IndexProtection,
+    limits: WriteLimits,
+    directory: DirectoryOptions,
+    /// hidden entries added to the backpack
+    metadata: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compress files with `compression`, see [`BackPack::set_compression`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Store files as is which compressing saves less than `ratio` of, see
+    /// [`BackPack::set_min_compression_ratio`].
+    pub fn min_compression_ratio(mut self, ratio: f64) -> Self {
+        self.min_compression_ratio = ratio;
+        self
+    }
+
+    /// Encrypt files, see [`BackPack::set_encryption`].
+    #[cfg(feature = "crypto")]
+    pub fn encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Align every file to `alignment` bytes, which must be a power of two.
+    pub fn alignment(mut self, alignment: u64) -> Self {
+        self.alignment = Some(alignment);
+        self
+    }
+
+    /// Where the table of contents goes. By default it goes where the output writes it,
+    /// at the start for [`build_from_dir`](Self::build_from_dir) and at the end for a [`writer`](Self::writer).
+    pub fn index_placement(mut self, placement: IndexPlacement) -> Self {
+        self.index = Some(placement);
+        self
+    }
+
+    /// See [`BackPack::set_index_protection`]. Only an index at the start can be protected.
+    pub fn index_protection(mut self, protection: IndexProtection) -> Self {
+        self.protection = protection;
+        self
+    }
+
+    /// Refuse to write a backpack over these limits.
+    pub fn limits(mut self, limits: WriteLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Which files [`build_from_dir`](Self::build_from_dir) packs, and what it records about them.
+    /// Their limits are replaced by the builder's.
+    pub fn directory_options(mut self, options: DirectoryOptions) -> Self {
+        self.directory = options;
+        self
+    }
+
+    /// Add a [hidden](BackPack::set_hidden) file `name` with `contents`, like a manifest
+    /// or build information, to the backpack.
+    pub fn metadata(mut self, name: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        self.metadata.push((name.as_ref().to_path_buf(), contents.into()));
+        self
+    }
+
+    /// A [`PackWriter`] writing to `file`, with the metadata already added. A writer copies
+    /// entries straight to the file, so it can't compress, encrypt, align or protect them, or
+    /// write the index at the start. Asking for any of that fails with [`PackError::IncompatibleOptions`].
+    pub fn writer<'f, 'backpack, E: Into<PackError>>(&self, file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<PackWriter<'f, 'backpack>> {
+        if self.compression != Compression::None {
+            return Err(PackError::IncompatibleOptions("a PackWriter can't compress entries"));
+        }
+        #[cfg(feature = "crypto")]
+        if self.encryption.is_some() {
+            return Err(PackError::IncompatibleOptions("a PackWriter can't encrypt entries"));
+        }
+        if self.alignment.is_some_and(|alignment| alignment > 1) {
+            return Err(PackError::IncompatibleOptions("a PackWriter can't align entries"));
+        }
+        if self.index == Some(IndexPlacement::Start) {
+            return Err(PackError::IncompatibleOptions("a PackWriter writes the index at the end"));
+        }
+        if self.protection != IndexProtection::None {
+            return Err(PackError::IncompatibleOptions("a protected index has to be at the start of the backpack"));
+        }
+
+        let mut writer = PackWriter::new(file)?;
+        writer.set_limits(self.limits);
+        writer.set_checksum(self.checksum);
+        for (name, contents) in &self.metadata {
+            writer.add_hidden_entry(name, contents.as_slice())?;
+        }
+        Ok(writer)
+    }
+
+    /// Pack every file in `dir` the [directory options](Self::directory_options) ask for,
+    /// like [`BackPack::from_directory`], and write the backpack to `output`.
+    pub fn build_from_dir(&self, dir: impl AsRef<Path>, output: impl Write) -> error::Result<()> {
+        let options = DirectoryOptions {
+            limits: self.limits,
+            ..self.directory.clone()
+        };
+        let mut pack = BackPack::from_directory(dir, &options)?;
+        pack.set_compression(self.compression)?;
+        pack.set_min_compression_ratio(self.min_compression_ratio)?;
+        #[cfg(feature = "crypto")]
+        pack.set_encryption(self.encryption.clone())?;
+        pack.set_index_protection(self.protection);
+        for (name, contents) in &self.metadata {
+            pack.put_contents(name, contents.clone())?;
+            pack.set_hidden(name, true)?;
+        }
+
+        pack.freeze(output, FreezeOptions {
+            alignment: self.alignment,
+            index: self.index.unwrap_or_default(),
+            checksum: self.checksum,
+            ..FreezeOptions::default()
+        })
+    }
+}
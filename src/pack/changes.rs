@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll, Waker};
+use parking_lot::Mutex;
+
+/// A change to the entries of a backpack, see [`BackPack::changes`](crate::BackPack::changes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// a file was added under a name which wasn't in use
+    Added(String),
+    /// a file was added over an existing file with the same name
+    Modified(String),
+    Removed(String),
+}
+
+impl ChangeEvent {
+    pub fn name(&self) -> &str {
+        match self {
+            ChangeEvent::Added(name) | ChangeEvent::Modified(name) | ChangeEvent::Removed(name) => name,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<ChangeEvent>,
+    /// the backpack is gone, no more events will follow
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Everyone listening for changes to a backpack.
+#[derive(Default)]
+pub struct Subscribers {
+    subscribers: Mutex<Vec<Weak<Mutex<State>>>>,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self) -> Changes {
+        let state = Arc::new(Mutex::new(State::default()));
+        self.subscribers.lock().push(Arc::downgrade(&state));
+        Changes { state }
+    }
+
+    pub(crate) fn emit(&self, event: ChangeEvent) {
+        let mut subscribers = self.subscribers.lock();
+        // forget about subscribers which were dropped
+        subscribers.retain(|subscriber| match subscriber.upgrade() {
+            Some(state) => {
+                let mut state = state.lock();
+                state.events.push_back(event.clone());
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+impl Drop for Subscribers {
+    fn drop(&mut self) {
+        for state in self.subscribers.get_mut().iter().filter_map(Weak::upgrade) {
+            let mut state = state.lock();
+            state.closed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// The changes made to a backpack since this was created with [`BackPack::changes`](crate::BackPack::changes).
+/// Wait for them with [`next`](Self::next), or use this as a `futures_core::Stream`
+/// with the `futures` feature. Ends when the backpack is dropped.
+pub struct Changes {
+    state: Arc<Mutex<State>>,
+}
+
+impl Changes {
+    /// The next change, if one happened already.
+    pub fn try_next(&self) -> Option<ChangeEvent> {
+        self.state.lock().events.pop_front()
+    }
+
+    /// Waits for the next change. `None` once the backpack is dropped and all changes were seen.
+    pub fn next(&self) -> NextChange<'_> {
+        NextChange { changes: self }
+    }
+
+    fn poll_change(&self, cx: &mut Context<'_>) -> Poll<Option<ChangeEvent>> {
+        let mut state = self.state.lock();
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures_core::Stream for Changes {
+    type Item = ChangeEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_change(cx)
+    }
+}
+
+/// Future returned by [`Changes::next`].
+pub struct NextChange<'c> {
+    changes: &'c Changes,
+}
+
+impl Future for NextChange<'_> {
+    type Output = Option<ChangeEvent>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.changes.poll_change(cx)
+    }
+}
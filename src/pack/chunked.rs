@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+use sha2::{Digest, Sha256};
+use crate::error;
+use crate::error::PackError;
+use crate::pack::{PackReader, PackWriter, RawFile};
+
+/// Entry of a chunked backpack listing the chunks of every file, as records of
+/// `{name}\0{ids}\0`, with the ids of the chunks in order as hex. It's hidden.
+pub const CHUNKED_ENTRY: &str = ".backpack/chunked";
+
+/// Chunks are hidden entries named after their id, under this directory.
+const CHUNK_DIR: &str = ".backpack/chunks/";
+
+/// How files are split into chunks, see [`ChunkedWriter`]. Cut points depend on the contents
+/// around them only, so an insertion early in a file changes the chunks around it and leaves
+/// the rest the same.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkingOptions {
+    pub min_size: usize,
+    /// Rounded down to a power of two.
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for ChunkingOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 4 * 1024,
+            avg_size: 16 * 1024,
+            max_size: 64 * 1024,
+        }
+    }
+}
+
+/// The sha-256 of the contents of a chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkId(pub [u8; 32]);
+
+impl ChunkId {
+    pub fn of(contents: &[u8]) -> Self {
+        Self(Sha256::digest(contents).into())
+    }
+
+    fn entry_name(&self) -> String {
+        format!("{}{}", CHUNK_DIR, self)
+    }
+
+    fn parse(hex: &str) -> error::Result<Self> {
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(PackError::InvalidEntry);
+        }
+        let mut res = [0; 32];
+        for (i, b) in res.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| PackError::InvalidEntry)?;
+        }
+        Ok(Self(res))
+    }
+}
+
+impl fmt::Display for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// Random values for the gear hash, from splitmix64 so they're the same everywhere.
+const GEAR: [u64; 256] = {
+    let mut res = [0; 256];
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        res[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    res
+};
+
+/// The `bits` highest bits set.
+fn high_mask(bits: u32) -> u64 {
+    !(u64::MAX >> bits.min(64))
+}
+
+/// Where the first chunk of `data` ends, with FastCDC: a gear hash is rolled over the bytes after
+/// `min_size`, and a chunk ends where its highest bits are zero. Before the average size more
+/// bits have to be zero than after it, which keeps chunk sizes close to the average.
+fn cut_point(data: &[u8], options: &ChunkingOptions) -> usize {
+    let max = data.len().min(options.max_size);
+    if max <= options.min_size {
+        return max;
+    }
+    let bits = options.avg_size.max(2).ilog2();
+    let (mask_small, mask_large) = (high_mask(bits + 1), high_mask(bits - 1));
+    let normal = options.avg_size.clamp(options.min_size, max);
+
+    let mut hash = 0u64;
+    for (i, b) in data.iter().enumerate().take(max).skip(options.min_size) {
+        hash = (hash << 1).wrapping_add(GEAR[*b as usize]);
+        let mask = if i < normal { mask_small } else { mask_large };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    max
+}
+
+/// Split `data` into content defined chunks, returning their lengths.
+pub fn chunk_lengths(mut data: &[u8], options: &ChunkingOptions) -> Vec<usize> {
+    let mut res = Vec::new();
+    while !data.is_empty() {
+        let n = cut_point(data, options);
+        res.push(n);
+        data = &data[n..];
+    }
+    res
+}
+
+fn encode_recipes(recipes: &BTreeMap<String, Vec<ChunkId>>) -> Vec<u8> {
+    let mut res = String::new();
+    for (name, chunks) in recipes {
+        res.push_str(name);
+        res.push('\0');
+        for chunk in chunks {
+            res.push_str(&chunk.to_string());
+        }
+        res.push('\0');
+    }
+    res.into_bytes()
+}
+
+fn decode_recipes(data: &[u8]) -> error::Result<BTreeMap<String, Vec<ChunkId>>> {
+    let data = String::from_utf8(data.to_vec())?;
+    let mut parts = data.split_terminator('\0');
+    let mut recipes = BTreeMap::new();
+    while let Some(name) = parts.next() {
+        let ids = parts.next().ok_or(PackError::InvalidEntry)?;
+        if ids.len() % 64 != 0 || !ids.is_ascii() {
+            return Err(PackError::InvalidEntry);
+        }
+        let chunks = (0..ids.len() / 64)
+            .map(|i| ChunkId::parse(&ids[i * 64..(i + 1) * 64]))
+            .collect::<error::Result<_>>()?;
+        recipes.insert(name.to_string(), chunks);
+    }
+    Ok(recipes)
+}
+
+/// Writes a backpack which stores files as content defined chunks, each stored once no matter
+/// how many files or versions of a file contain it. Meant for backups and build artifacts,
+/// where successive versions share most of their contents. Read it with [`ChunkedReader`].
+///
+/// Chunks are [hidden](crate::BackPack::set_hidden) entries named after the sha-256 of their
+/// contents, and [`CHUNKED_ENTRY`] lists which chunks make up every file.
+///
+/// To sync a backpack, send the [recipe](ChunkedReader::recipe) of every file, let the other
+/// side find which chunks it [misses](ChunkedReader::missing), and send only those to be
+/// [added](Self::add_chunk) before the recipes are [added](Self::add_recipe).
+pub struct ChunkedWriter<'f, 'backpack> {
+    writer: PackWriter<'f, 'backpack>,
+    options: ChunkingOptions,
+    chunks: HashSet<ChunkId>,
+    recipes: BTreeMap<String, Vec<ChunkId>>,
+}
+
+impl<'f, 'backpack> ChunkedWriter<'f, 'backpack> {
+    pub fn new(writer: PackWriter<'f, 'backpack>) -> Self {
+        Self::with_options(writer, ChunkingOptions::default())
+    }
+
+    pub fn with_options(writer: PackWriter<'f, 'backpack>, options: ChunkingOptions) -> Self {
+        Self {
+            writer,
+            options,
+            chunks: HashSet::new(),
+            recipes: BTreeMap::new(),
+        }
+    }
+
+    /// Store `contents` as a chunk, unless it's stored already, and return its id.
+    pub fn add_chunk(&mut self, contents: &[u8]) -> error::Result<ChunkId> {
+        let id = ChunkId::of(contents);
+        if self.chunks.insert(id) {
+            self.writer.add_reserved_entry(&id.entry_name(), contents)?;
+        }
+        Ok(id)
+    }
+
+    pub fn contains_chunk(&self, id: &ChunkId) -> bool {
+        self.chunks.contains(id)
+    }
+
+    /// Add a file called `name` made of the stored chunks `chunks`, in order.
+    pub fn add_recipe(&mut self, name: impl AsRef<Path>, chunks: Vec<ChunkId>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = name.to_string_lossy().into_owned();
+        if name_str.is_empty() {
+            return Err(PackError::NoName);
+        }
+        if self.recipes.contains_key(&name_str) {
+            return Err(PackError::FileExists(name.to_path_buf()));
+        }
+        if let Some(missing) = chunks.iter().find(|id| !self.chunks.contains(id)) {
+            return Err(PackError::FileNotFound(missing.entry_name().into()));
+        }
+        self.recipes.insert(name_str, chunks);
+        Ok(())
+    }
+
+    /// Split `contents` into chunks, store the ones which aren't stored yet and add the file.
+    /// Only up to the maximum chunk size of `contents` is in memory at once. Returns its length.
+    pub fn add_file(&mut self, name: impl AsRef<Path>, mut contents: impl Read) -> error::Result<u64> {
+        let name = name.as_ref();
+        if self.recipes.contains_key(name.to_string_lossy().as_ref()) {
+            return Err(PackError::FileExists(name.to_path_buf()));
+        }
+
+        let mut chunks = Vec::new();
+        let mut buf = Vec::with_capacity(self.options.max_size);
+        let mut length = 0;
+        let mut eof = false;
+        while !eof || !buf.is_empty() {
+            // chunks are cut with the maximum size in view, or all that's left
+            while !eof && buf.len() < self.options.max_size {
+                let start = buf.len();
+                buf.resize(self.options.max_size, 0);
+                match contents.read(&mut buf[start..]) {
+                    Ok(0) => {
+                        buf.truncate(start);
+                        eof = true;
+                    }
+                    Ok(n) => buf.truncate(start + n),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => buf.truncate(start),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if buf.is_empty() {
+                break;
+            }
+
+            let n = cut_point(&buf, &self.options);
+            chunks.push(self.add_chunk(&buf[..n])?);
+            buf.drain(..n);
+            length += n as u64;
+        }
+
+        self.add_recipe(name, chunks)?;
+        Ok(length)
+    }
+
+    /// Bytes of chunks stored so far.
+    pub fn size(&self) -> u64 {
+        self.writer.size()
+    }
+
+    /// Write the list of chunks of every file, and finish the backpack.
+    pub fn finish(mut self) -> error::Result<RawFile<'f, 'backpack>> {
+        self.writer.add_reserved_entry(CHUNKED_ENTRY, encode_recipes(&self.recipes).as_slice())?;
+        self.writer.finish()
+    }
+}
+
+/// Reads a backpack written by a [`ChunkedWriter`], putting files back together from their chunks.
+pub struct ChunkedReader<'f, 'backpack> {
+    reader: PackReader<'f, 'backpack>,
+    recipes: BTreeMap<String, Vec<ChunkId>>,
+}
+
+impl<'f, 'backpack> ChunkedReader<'f, 'backpack> {
+    /// Read the chunked backpack `reader` reads. Fails with [`PackError::FileNotFound`] when it
+    /// isn't chunked.
+    pub fn new(reader: PackReader<'f, 'backpack>) -> error::Result<Self> {
+        let recipes = decode_recipes(&reader.read(CHUNKED_ENTRY)?)?;
+        Ok(Self { reader, recipes })
+    }
+
+    /// Names of all files, sorted.
+    pub fn file_names(&self) -> Vec<&str> {
+        self.recipes.keys().map(String::as_str).collect()
+    }
+
+    pub fn contains(&self, name: impl AsRef<Path>) -> bool {
+        self.recipes.contains_key(name.as_ref().to_string_lossy().as_ref())
+    }
+
+    /// The chunks the file `name` is made of, in order.
+    pub fn recipe(&self, name: impl AsRef<Path>) -> error::Result<&[ChunkId]> {
+        let name = name.as_ref();
+        self.recipes.get(name.to_string_lossy().as_ref())
+            .map(Vec::as_slice)
+            .ok_or_else(|| PackError::FileNotFound(name.to_path_buf()))
+    }
+
+    pub fn contains_chunk(&self, id: &ChunkId) -> bool {
+        self.reader.contains(id.entry_name())
+    }
+
+    /// The chunks out of `chunks` which aren't in this backpack, once each, in order.
+    pub fn missing(&self, chunks: &[ChunkId]) -> Vec<ChunkId> {
+        let mut seen = HashSet::new();
+        chunks.iter()
+            .filter(|id| !self.contains_chunk(id) && seen.insert(**id))
+            .copied()
+            .collect()
+    }
+
+    /// The contents of the chunk `id`, checked against it.
+    pub fn chunk(&self, id: &ChunkId) -> error::Result<Vec<u8>> {
+        let name = id.entry_name();
+        let contents = self.reader.read(&name)?;
+        if ChunkId::of(&contents) != *id {
+            return Err(PackError::ChecksumMismatch(name.into()));
+        }
+        Ok(contents)
+    }
+
+    /// The whole contents of the file `name`.
+    pub fn read(&self, name: impl AsRef<Path>) -> error::Result<Vec<u8>> {
+        let mut res = Vec::new();
+        self.entry(name)?.read_to_end(&mut res)?;
+        Ok(res)
+    }
+
+    /// A reader over the contents of the file `name`, which has one chunk in memory at a time.
+    pub fn entry(&self, name: impl AsRef<Path>) -> error::Result<ChunkedEntry<'_, 'f, 'backpack>> {
+        Ok(ChunkedEntry {
+            reader: self,
+            chunks: self.recipe(name)?,
+            current: Vec::new(),
+            position: 0,
+        })
+    }
+
+    pub fn into_inner(self) -> PackReader<'f, 'backpack> {
+        self.reader
+    }
+}
+
+/// A file of a [`ChunkedReader`], see [`ChunkedReader::entry`].
+pub struct ChunkedEntry<'r, 'f, 'backpack> {
+    reader: &'r ChunkedReader<'f, 'backpack>,
+    /// chunks which weren't loaded yet
+    chunks: &'r [ChunkId],
+    current: Vec<u8>,
+    /// in `current`
+    position: usize,
+}
+
+impl Read for ChunkedEntry<'_, '_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            let Some((next, rest)) = self.chunks.split_first() else {
+                return Ok(0);
+            };
+            self.current = self.reader.chunk(next)?;
+            self.position = 0;
+            self.chunks = rest;
+        }
+
+        let n = buf.len().min(self.current.len() - self.position);
+        buf[..n].copy_from_slice(&self.current[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::chunked::{chunk_lengths, ChunkingOptions};
+
+    #[test]
+    fn test_content_defined() {
+        let options = ChunkingOptions { min_size: 64, avg_size: 256, max_size: 1024 };
+        let data = (0..20_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect::<Vec<_>>();
+        let lengths = chunk_lengths(&data, &options);
+        assert_eq!(lengths.iter().sum::<usize>(), data.len());
+        assert!(lengths[..lengths.len() - 1].iter().all(|n| (64..=1024).contains(n)));
+
+        // inserting a byte at the start only changes the first chunks
+        let mut shifted = vec![7];
+        shifted.extend_from_slice(&data);
+        let shifted_lengths = chunk_lengths(&shifted, &options);
+        assert_eq!(lengths[lengths.len() - 10..], shifted_lengths[shifted_lengths.len() - 10..]);
+    }
+}
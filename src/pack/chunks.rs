@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use parking_lot::{Condvar, Mutex};
+use crate::error;
+
+struct State {
+    chunks: VecDeque<error::Result<Vec<u8>>>,
+    sender_closed: bool,
+    receiver_closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+/// Creates a channel holding at most `capacity` chunks. The sender blocks when it's full,
+/// so whoever produces the chunks can't race ahead of whoever consumes them.
+pub fn chunk_channel(capacity: usize) -> (ChunkSender, ChunkReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            chunks: VecDeque::new(),
+            sender_closed: false,
+            receiver_closed: false,
+            waker: None,
+        }),
+        capacity: capacity.max(1),
+        not_full: Condvar::new(),
+        not_empty: Condvar::new(),
+    });
+
+    (ChunkSender { shared: shared.clone() }, ChunkReceiver { shared })
+}
+
+/// Sending half of a [`chunk_channel`].
+pub struct ChunkSender {
+    shared: Arc<Shared>,
+}
+
+impl ChunkSender {
+    /// Blocks until there is room for the chunk. Returns false when the receiver
+    /// is gone, and nobody is interested in more chunks.
+    pub fn send(&self, chunk: error::Result<Vec<u8>>) -> bool {
+        let mut state = self.shared.state.lock();
+        while state.chunks.len() >= self.shared.capacity && !state.receiver_closed {
+            self.shared.not_full.wait(&mut state);
+        }
+        if state.receiver_closed {
+            return false;
+        }
+
+        state.chunks.push_back(chunk);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.shared.not_empty.notify_one();
+        true
+    }
+}
+
+impl Drop for ChunkSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.sender_closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.shared.not_empty.notify_one();
+    }
+}
+
+/// Receiving half of a [`chunk_channel`]. Receive chunks by blocking with [`recv`](Self::recv)
+/// or iterating, or asynchronously with [`recv_async`](Self::recv_async).
+/// `None` means all chunks were received.
+pub struct ChunkReceiver {
+    shared: Arc<Shared>,
+}
+
+impl ChunkReceiver {
+    /// Reads `reader` on a new thread in chunks of `chunk_size` bytes,
+    /// never reading more than `capacity` chunks ahead.
+    pub fn from_reader(mut reader: impl Read + Send + 'static, chunk_size: usize, capacity: usize) -> Self {
+        let (sender, receiver) = chunk_channel(capacity);
+
+        std::thread::spawn(move || loop {
+            let mut chunk = Vec::with_capacity(chunk_size);
+            match (&mut reader).take(chunk_size as u64).read_to_end(&mut chunk) {
+                Ok(0) => break,
+                Ok(_) => if !sender.send(Ok(chunk)) {
+                    break;
+                },
+                Err(e) => {
+                    sender.send(Err(e.into()));
+                    break;
+                }
+            }
+        });
+
+        receiver
+    }
+
+    fn take(&self, state: &mut State) -> Option<error::Result<Vec<u8>>> {
+        let chunk = state.chunks.pop_front();
+        if chunk.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        chunk
+    }
+
+    /// Blocks until the next chunk is available.
+    pub fn recv(&self) -> Option<error::Result<Vec<u8>>> {
+        let mut state = self.shared.state.lock();
+        loop {
+            if let Some(chunk) = self.take(&mut state) {
+                return Some(chunk);
+            }
+            if state.sender_closed {
+                return None;
+            }
+            self.shared.not_empty.wait(&mut state);
+        }
+    }
+
+    /// Waits for the next chunk without blocking the thread.
+    pub fn recv_async(&self) -> Recv<'_> {
+        Recv { receiver: self }
+    }
+}
+
+impl Iterator for ChunkReceiver {
+    type Item = error::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+impl Drop for ChunkReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receiver_closed = true;
+        state.chunks.clear();
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Future returned by [`ChunkReceiver::recv_async`].
+pub struct Recv<'r> {
+    receiver: &'r ChunkReceiver,
+}
+
+impl Future for Recv<'_> {
+    type Output = Option<error::Result<Vec<u8>>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.receiver.shared.state.lock();
+        if let Some(chunk) = self.receiver.take(&mut state) {
+            return Poll::Ready(Some(chunk));
+        }
+        if state.sender_closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
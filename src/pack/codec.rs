@@ -0,0 +1,79 @@
+use std::io::Read;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::Compression;
+
+/// An encoding files can be converted to while they're read, see
+/// [`BackPack::entry_transcoded`](crate::BackPack::entry_transcoded).
+/// Implement it for any streaming encoder, for example to serve gzip to HTTP clients.
+pub trait Codec: Send + Sync {
+    /// Short name of the encoding, like the value of a `Content-Encoding` header.
+    fn name(&self) -> &str;
+
+    /// Wrap `input` in a reader producing the encoded bytes.
+    fn encode<'r>(&self, input: Box<dyn Read + 'r>) -> error::Result<Box<dyn Read + 'r>>;
+}
+
+/// The contents as they are, without any encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Identity;
+
+impl Codec for Identity {
+    fn name(&self) -> &str {
+        "identity"
+    }
+
+    fn encode<'r>(&self, input: Box<dyn Read + 'r>) -> error::Result<Box<dyn Read + 'r>> {
+        Ok(input)
+    }
+}
+
+/// Gzip, as HTTP clients accept it. Needs the `deflate` feature.
+#[cfg(feature = "deflate")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Gzip;
+
+#[cfg(feature = "deflate")]
+impl Codec for Gzip {
+    fn name(&self) -> &str {
+        "gzip"
+    }
+
+    fn encode<'r>(&self, input: Box<dyn Read + 'r>) -> error::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(flate2::read::GzEncoder::new(input, flate2::Compression::default())))
+    }
+}
+
+/// The contents compressed the way backpacks store them, for example to serve zstandard.
+/// Methods this build doesn't [support](Compression::is_supported) fail with
+/// [`PackError::UnsupportedCompression`]. lz4 has no streaming encoder, so it's compressed
+/// all at once.
+impl Codec for Compression {
+    fn name(&self) -> &str {
+        match self {
+            Compression::None => "identity",
+            Compression::Deflate => "deflate-raw",
+            Compression::Zstd { .. } => "zstd",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    fn encode<'r>(&self, input: Box<dyn Read + 'r>) -> error::Result<Box<dyn Read + 'r>> {
+        match *self {
+            Compression::None => Ok(input),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => Ok(Box::new(flate2::read::DeflateEncoder::new(input, flate2::Compression::default()))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => Ok(Box::new(zstd::stream::read::Encoder::new(input, level)?)),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut input = input;
+                let mut contents = Vec::new();
+                input.read_to_end(&mut contents)?;
+                Ok(Box::new(std::io::Cursor::new(self.compress(&contents)?)))
+            }
+            #[allow(unreachable_patterns)]
+            method => Err(PackError::UnsupportedCompression(method)),
+        }
+    }
+}
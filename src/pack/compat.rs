@@ -0,0 +1,192 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use crate::error;
+use crate::error::PackError;
+use crate::format::FormatError;
+use crate::pack::compression::{self, Compression};
+use crate::pack::encryption;
+use crate::pack::layout::{PackHeader, TocEntry, U16Le, ALIGNMENT_FIELD, CHECKSUM_FIELD, COMPRESSION_FIELD, CRITICAL_FIELD, DATA_FILE_FIELD, DIRECTORY, ENCRYPTION_FIELD, FLAGS_FIELD, HIDDEN};
+use crate::pack::protection::{self, IndexProtection};
+use crate::pack::{zip, BackPack, ALIAS_ENTRY, ATTRIBUTES_ENTRY, EXPIRY_ENTRY, MODIFIED_ENTRY, SIGNATURE_ENTRY, TIERS_ENTRY, PACK_VERSION};
+
+/// A part of the backpack format which a backpack may use, see [`BackPack::compatibility`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FormatFeature {
+    /// [hidden](BackPack::set_hidden) files
+    HiddenFiles,
+    /// [empty directories](BackPack::add_dir)
+    Directories,
+    /// files which [expire](BackPack::set_expiry)
+    Expiry,
+    /// [aliases](BackPack::set_alias) of files
+    Aliases,
+    /// [modification times](BackPack::set_modified) of files
+    ModificationTimes,
+    /// user defined [attributes](BackPack::set_attribute) of files
+    Attributes,
+    /// [tiers](BackPack::set_tier) of files
+    Tiers,
+    /// the backpack is [signed](BackPack::set_signing)
+    Signature,
+    /// the index is protected by a checksum, see [`IndexProtection`]
+    IndexChecksum,
+    /// a copy of the index is stored too, see [`IndexProtection`]
+    IndexCopy,
+    /// the backpack is also a zip archive, see [`OutputMode::ZipHybrid`](crate::pack::OutputMode::ZipHybrid)
+    ZipHybrid,
+    /// the metadata file of a [split](BackPack::export_split) backpack
+    SplitMetadata,
+    /// checksums of the contents of files
+    FileChecksums,
+    /// files [compressed](BackPack::set_compression) with this method
+    Compression(Compression),
+    /// files [encrypted](BackPack::set_encryption) with a passphrase
+    Encryption,
+    /// files [aligned](BackPack::set_alignment) to more than a byte, with their alignment recorded
+    Alignment,
+    /// a field in the index this version of the library doesn't know.
+    /// Unless it's critical it's safely ignored.
+    UnknownField { tag: u16, critical: bool },
+}
+
+impl FormatFeature {
+    fn problem(&self) -> Option<String> {
+        match self {
+            FormatFeature::SplitMetadata => Some("this is the metadata of a split backpack, open it with BackPack::open_split".to_string()),
+            FormatFeature::Compression(method) if !method.is_supported() => Some(format!("files are compressed with {:?}, which this build of the backpack library doesn't support", method)),
+            FormatFeature::Encryption if !cfg!(feature = "crypto") => Some("files are encrypted, which needs the crypto feature".to_string()),
+            FormatFeature::UnknownField { tag, critical: true } => Some(format!("the index uses field {:#06x}, which needs a newer version of the backpack library", tag)),
+            _ => None,
+        }
+    }
+}
+
+/// What a backpack uses of the backpack format, and whether this build of the library can read it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    /// version of the format the backpack is written in
+    pub version: u16,
+    /// the parts of the format the backpack uses. Empty when the version isn't supported,
+    /// since the index can't be read then.
+    pub features: BTreeSet<FormatFeature>,
+}
+
+impl CompatibilityReport {
+    /// Read the header and index of a backpack, without reading any files in it.
+    pub(crate) fn inspect(file: &mut (impl Read + Seek)) -> error::Result<Self> {
+        file.seek(SeekFrom::Start(0))?;
+        let header = PackHeader::read_from(file)?;
+        let mut res = Self {
+            version: header.version(),
+            features: BTreeSet::new(),
+        };
+        if res.version != PACK_VERSION {
+            return Ok(res);
+        }
+
+        let features = &mut res.features;
+        BackPack::read_toc_blocks(file, header.first_toc.get(), |filled, block| {
+            let filled = (filled as usize).min(block.len());
+            let mut curr = 0;
+            while curr < filled {
+                let (entry, len) = TocEntry::parse(&block[curr..])?;
+                curr += len;
+
+                if entry.name == EXPIRY_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::Expiry);
+                }
+                if entry.name == ALIAS_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::Aliases);
+                }
+                if entry.name == MODIFIED_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::ModificationTimes);
+                }
+                if entry.name == ATTRIBUTES_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::Attributes);
+                }
+                if entry.name == SIGNATURE_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::Signature);
+                }
+                if entry.name == TIERS_ENTRY.as_bytes() {
+                    features.insert(FormatFeature::Tiers);
+                }
+
+                for field in entry.fields() {
+                    let feature = match field? {
+                        (FLAGS_FIELD, value) => {
+                            let flags = U16Le::from_slice(value).ok_or(PackError::InvalidEntry)?.get();
+                            if flags & HIDDEN != 0 {
+                                features.insert(FormatFeature::HiddenFiles);
+                            }
+                            if flags & DIRECTORY != 0 {
+                                features.insert(FormatFeature::Directories);
+                            }
+                            continue;
+                        }
+                        (DATA_FILE_FIELD, _) => FormatFeature::SplitMetadata,
+                        (CHECKSUM_FIELD, _) => FormatFeature::FileChecksums,
+                        (ALIGNMENT_FIELD, _) => FormatFeature::Alignment,
+                        (COMPRESSION_FIELD, value) => match compression::decode_field(value) {
+                            Ok((method, _)) => FormatFeature::Compression(method),
+                            // a method from a newer version of the library
+                            Err(FormatError::UnsupportedIndexField(tag)) => FormatFeature::UnknownField { tag, critical: true },
+                            Err(e) => return Err(e.into()),
+                        },
+                        (ENCRYPTION_FIELD, value) => match encryption::decode_field(value) {
+                            Ok(()) => FormatFeature::Encryption,
+                            Err(PackError::UnsupportedIndexField(tag)) => FormatFeature::UnknownField { tag, critical: true },
+                            Err(e) => return Err(e),
+                        },
+                        (tag, _) => FormatFeature::UnknownField { tag, critical: tag & CRITICAL_FIELD != 0 },
+                    };
+                    features.insert(feature);
+                }
+            }
+            Ok(())
+        })?;
+
+        match protection::read_protection(file)? {
+            IndexProtection::None => {}
+            IndexProtection::Checksum => {
+                res.features.insert(FormatFeature::IndexChecksum);
+            }
+            IndexProtection::ChecksumAndCopy => {
+                res.features.insert(FormatFeature::IndexChecksum);
+                res.features.insert(FormatFeature::IndexCopy);
+            }
+        }
+        if zip::is_hybrid(file)? {
+            res.features.insert(FormatFeature::ZipHybrid);
+        }
+
+        Ok(res)
+    }
+
+    /// Why [`BackPack::open`] can't open the backpack, in words that can be shown to users.
+    /// Empty when it can be opened.
+    pub fn problems(&self) -> Vec<String> {
+        if self.version != PACK_VERSION {
+            return vec![format!("the backpack is written in format version {}, this version of the backpack library reads version {}", self.version, PACK_VERSION)];
+        }
+        self.features.iter().filter_map(FormatFeature::problem).collect()
+    }
+
+    /// Whether [`BackPack::open`] can open the backpack.
+    pub fn is_supported(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "format version {}", self.version)?;
+        for feature in &self.features {
+            write!(f, ", {:?}", feature)?;
+        }
+        for problem in self.problems() {
+            write!(f, "\n{}", problem)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::error;
+use crate::error::PackError;
+pub use crate::format::compression::Compression;
+pub(crate) use crate::format::compression::{decode_field, encode_field};
+
+/// Compressed files by name, with how they're compressed and their uncompressed length.
+pub(crate) type Compressed = HashMap<String, (Compression, u64)>;
+
+impl Compression {
+    pub(crate) fn compress(self, data: &[u8]) -> error::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                use std::io::Write;
+
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => Ok(zstd::stream::encode_all(data, level)?),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                use std::io::Write;
+
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+                Ok(encoder.finish().map_err(std::io::Error::other)?)
+            }
+            #[allow(unreachable_patterns)]
+            method => Err(PackError::UnsupportedCompression(method)),
+        }
+    }
+
+    /// Decompress `data`, which must decompress to exactly `len` bytes.
+    pub(crate) fn decompress(self, data: &[u8], len: u64) -> error::Result<Vec<u8>> {
+        let res = match self {
+            Compression::None => data.to_vec(),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                use std::io::Read;
+
+                // a damaged length shouldn't make us allocate everything up front
+                let mut res = Vec::with_capacity(len.min(data.len() as u64 * 4) as usize);
+                flate2::read::DeflateDecoder::new(data).take(len + 1).read_to_end(&mut res)?;
+                res
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => {
+                use std::io::Read;
+
+                let mut res = Vec::with_capacity(len.min(data.len() as u64 * 4) as usize);
+                zstd::stream::read::Decoder::new(data)?.take(len + 1).read_to_end(&mut res)?;
+                res
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                use std::io::Read;
+
+                let mut res = Vec::with_capacity(len.min(data.len() as u64 * 4) as usize);
+                lz4_flex::frame::FrameDecoder::new(data).take(len + 1).read_to_end(&mut res)?;
+                res
+            }
+            #[allow(unreachable_patterns)]
+            method => return Err(PackError::UnsupportedCompression(method)),
+        };
+
+        if res.len() as u64 != len {
+            return Err(PackError::InvalidEntry);
+        }
+        Ok(res)
+    }
+}
+
+/// Whether `compressed` bytes of `len` bytes of contents save at least `min_ratio` of them,
+/// see [`BackPack::set_min_compression_ratio`](crate::BackPack::set_min_compression_ratio).
+pub(crate) fn saves_enough(compressed: usize, len: usize, min_ratio: f64) -> bool {
+    compressed < len && compressed as f64 <= (1.0 - min_ratio) * len as f64
+}
+
+/// Compress the contents of every entry `method(name)` asks to be compressed, keeping only
+/// what saves at least `min_ratio` of its size, and record them in `compressed`. Entries which
+/// don't save enough are recorded as stored with [`Compression::None`], so the index shows
+/// compression was tried. Returns the compressed contents with their index in `entries`.
+pub(crate) fn compress_entries(entries: &[(&str, &[u8])], method: impl Fn(&str) -> Compression + Sync, min_ratio: f64, compressed: &mut Compressed) -> error::Result<Vec<(usize, Vec<u8>)>> {
+    // entries which don't save enough come back with `Compression::None` and no contents
+    let compress = |(i, (name, data)): (usize, &(&str, &[u8]))| -> error::Result<Option<(usize, Compression, Vec<u8>)>> {
+        let method = method(name);
+        if method == Compression::None || data.is_empty() {
+            return Ok(None);
+        }
+
+        let res = method.compress(data)?;
+        Ok(Some(match saves_enough(res.len(), data.len(), min_ratio) {
+            true => (i, method, res),
+            false => (i, Compression::None, Vec::new()),
+        }))
+    };
+    // with the `parallel` feature entries are compressed on all cores
+    #[cfg(feature = "parallel")]
+    let results = entries.par_iter().enumerate().map(compress).collect::<error::Result<Vec<_>>>()?;
+    #[cfg(not(feature = "parallel"))]
+    let results = entries.iter().enumerate().map(compress).collect::<error::Result<Vec<_>>>()?;
+
+    let mut contents = Vec::new();
+    for (i, method, res) in results.into_iter().flatten() {
+        let (name, data) = entries[i];
+        compressed.insert(name.to_string(), (method, data.len() as u64));
+        if method != Compression::None {
+            contents.push((i, res));
+        }
+    }
+    Ok(contents)
+}
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::compression::Compressed;
+use crate::pack::{BackPack, PackReader, RawFile};
+
+/// Entry of a patch listing what changed, as records of a kind and a name followed by `\0`.
+/// The kind is `+` for added files, `~` for changed files, `-` for removed files and `h` for
+/// files which are hidden. It's hidden in the patch.
+pub const PATCH_ENTRY: &str = ".backpack/patch";
+
+/// The files which differ between two backpacks, to ship a small patch instead of the whole
+/// new backpack. Made with [`between`](Self::between), stored with [`write`](Self::write)
+/// and [`open`](Self::open), and applied with [`BackPack::apply_patch`].
+///
+/// Only the contents of files are compared, directories and the metadata of files aren't
+/// part of a patch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackDiff {
+    /// contents of files which were added or changed, by name
+    changed: BTreeMap<String, Vec<u8>>,
+    /// files out of `changed` which didn't exist in the old backpack
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+    hidden: BTreeSet<String>,
+}
+
+impl PackDiff {
+    /// What changed from `old` to `new`, including [hidden](BackPack::set_hidden) files.
+    pub fn between<'a, 'b>(old: &'a BackPack<'a, '_>, new: &'b BackPack<'b, '_>) -> error::Result<Self> {
+        let old_names = old.file_names_with(true).into_iter().collect::<BTreeSet<_>>();
+        let new_names = new.file_names_with(true);
+
+        let mut res = Self::default();
+        for name in &new_names {
+            let contents = new.get_file(name)?.get_bytes().to_vec();
+            if old_names.contains(name) {
+                if *old.get_file(name)?.get_bytes() == contents[..] {
+                    continue;
+                }
+            } else {
+                res.added.insert(name.clone());
+            }
+            if new.is_hidden(name) {
+                res.hidden.insert(name.clone());
+            }
+            res.changed.insert(name.clone(), contents);
+        }
+
+        let new_names = new_names.into_iter().collect::<HashSet<_>>();
+        res.removed = old_names.into_iter()
+            .filter(|name| !new_names.contains(name))
+            .collect();
+        Ok(res)
+    }
+
+    /// Read a patch stored with [`write`](Self::write).
+    pub fn open<'f, 'backpack: 'f, E: Into<PackError>>(patch: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        let patch = PackReader::open(patch)?;
+        let index = String::from_utf8(patch.read(PATCH_ENTRY)?)?;
+
+        let mut res = Self::default();
+        for record in index.split_terminator('\0') {
+            let name = record.get(1..).filter(|name| !name.is_empty()).ok_or(PackError::InvalidEntry)?.to_string();
+            match record.as_bytes()[0] {
+                b'+' => {
+                    res.added.insert(name.clone());
+                    res.changed.insert(name.clone(), patch.read(&name)?);
+                }
+                b'~' => {
+                    res.changed.insert(name.clone(), patch.read(&name)?);
+                }
+                b'-' => {
+                    res.removed.insert(name);
+                }
+                b'h' => {
+                    res.hidden.insert(name);
+                }
+                _ => return Err(PackError::InvalidEntry),
+            }
+        }
+        Ok(res)
+    }
+
+    /// Store the patch as a backpack holding only the added and changed files.
+    pub fn write(&self, mut f: impl Write) -> error::Result<()> {
+        let mut index = String::new();
+        for name in self.changed.keys() {
+            index.push(if self.added.contains(name) { '+' } else { '~' });
+            index.push_str(name);
+            index.push('\0');
+        }
+        for (kind, names) in [('-', &self.removed), ('h', &self.hidden)] {
+            for name in names {
+                index.push(kind);
+                index.push_str(name);
+                index.push('\0');
+            }
+        }
+
+        let mut entries = self.changed.iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+            .collect::<Vec<_>>();
+        entries.push((PATCH_ENTRY, index.as_bytes()));
+        let hidden = HashSet::from([PATCH_ENTRY.to_string()]);
+
+        let mut pack = Vec::new();
+        BackPack::write_native(&mut pack, &entries, |_| 1, &HashMap::new(), &hidden, &Compressed::new(), &HashSet::new())?;
+        f.write_all(&pack)?;
+        Ok(())
+    }
+
+    /// Names of files which are new, sorted.
+    pub fn added(&self) -> Vec<&str> {
+        self.added.iter().map(String::as_str).collect()
+    }
+
+    /// Names of files which exist in both backpacks with different contents, sorted.
+    pub fn changed(&self) -> Vec<&str> {
+        self.changed.keys()
+            .filter(|name| !self.added.contains(*name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Names of files which are gone, sorted.
+    pub fn removed(&self) -> Vec<&str> {
+        self.removed.iter().map(String::as_str).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Contents of every added or changed file, and whether it's hidden.
+    pub(crate) fn contents(&self) -> impl Iterator<Item = (&str, &[u8], bool)> {
+        self.changed.iter().map(|(name, contents)| (name.as_str(), contents.as_slice(), self.hidden.contains(name)))
+    }
+}
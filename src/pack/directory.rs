@@ -0,0 +1,353 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::WriteLimits;
+use crate::pack::entry_name;
+use crate::pack::entry_name::EntryName;
+
+/// Which files [`BackPack::from_directory`](crate::BackPack::from_directory) packs.
+/// Patterns are matched against paths relative to the directory, separated by `/`.
+/// `*` matches any part of a name, `**` any number of directories, and `?` one character.
+#[derive(Clone, Debug)]
+pub struct DirectoryOptions {
+    /// only pack files matching one of these, or every file when empty
+    pub include: Vec<String>,
+    /// leave out files and directories matching one of these, also when they're included
+    pub exclude: Vec<String>,
+    /// record when files and directories were last [modified](crate::BackPack::modified), on by default
+    pub keep_modified: bool,
+    /// record the unix [permission bits](crate::BackPack::mode) of files and directories,
+    /// on by default. Has no effect on other platforms.
+    pub keep_permissions: bool,
+    /// pack symbolic links as [links](crate::BackPack::add_symlink) instead of following them, and
+    /// hard links to a file packed before as [aliases](crate::BackPack::set_alias) of it, on by default
+    pub keep_links: bool,
+    /// record the [holes](crate::BackPack::holes) of sparse files, on by default.
+    /// Only finds them on linux.
+    pub keep_holes: bool,
+    /// refuse directories which would make a backpack over these limits, before reading files
+    /// where possible
+    pub limits: WriteLimits,
+}
+
+impl Default for DirectoryOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            keep_modified: true,
+            keep_permissions: true,
+            keep_links: true,
+            keep_holes: true,
+            limits: WriteLimits::default(),
+        }
+    }
+}
+
+/// How [`BackPack::extract_to_with`](crate::BackPack::extract_to_with) writes files.
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    /// set the modification times of files and directories to the [recorded](crate::BackPack::modified)
+    /// ones, on by default
+    pub restore_modified: bool,
+    /// set the permission bits of files and directories to the [recorded](crate::BackPack::mode) ones,
+    /// on by default. Has no effect on other platforms than unix.
+    pub restore_permissions: bool,
+    /// leave the recorded [holes](crate::BackPack::holes) of files out, so they're sparse again
+    /// where the file system supports it, on by default
+    pub restore_holes: bool,
+    /// refuse backpacks whose files would go over these limits once extracted, before anything
+    /// is written. Sizes are the sizes of the files, not what they take in the backpack.
+    pub limits: WriteLimits,
+    /// also keep files from being written through links which were already in the directory,
+    /// or through directories which lead out of it. Links in the backpack itself are always
+    /// kept inside the directory, and never written through. Off by default.
+    pub sandbox: bool,
+    /// refuse names which couldn't be extracted on windows, like `aux.txt` or `a\b`, on every
+    /// platform. They're always refused on windows. Off by default.
+    pub portable_names: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            restore_modified: true,
+            restore_permissions: true,
+            restore_holes: true,
+            limits: WriteLimits::default(),
+            sandbox: false,
+            portable_names: false,
+        }
+    }
+}
+
+impl DirectoryOptions {
+    fn included(&self, name: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, name))
+    }
+
+    fn excluded(&self, name: &str) -> bool {
+        self.exclude.iter().any(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// Whether `name` matches the glob `pattern`.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match pattern {
+            [] => name.is_empty(),
+            // `**/` also matches no directories at all
+            [b'*', b'*', b'/', rest @ ..] => matches(rest, name) || (0..name.len()).any(|i| name[i] == b'/' && matches(rest, &name[i + 1..])),
+            [b'*', b'*', rest @ ..] => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            [b'*', rest @ ..] => (0..=name.len())
+                .take_while(|i| *i == 0 || name[i - 1] != b'/')
+                .any(|i| matches(rest, &name[i..])),
+            [b'?', rest @ ..] => name.first().is_some_and(|c| *c != b'/') && matches(rest, &name[1..]),
+            [c, rest @ ..] => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Collect the files in `dir` the options ask for, with their names relative to `dir`,
+/// and empty directories, named with a trailing `/`. Symlinks are collected as they are when the
+/// options keep links. Otherwise symlinks to files are followed, symlinks to directories aren't,
+/// so cycles can't make this go on forever.
+pub(crate) fn collect_files(dir: &Path, prefix: &str, options: &DirectoryOptions, files: &mut Vec<(String, PathBuf)>) -> error::Result<()> {
+    let mut entries = fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()).at_path(dir)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut empty = true;
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry_name::key_of(&entry.file_name()));
+        if options.excluded(&name) {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && options.keep_links {
+            if options.included(&name) {
+                files.push((name, entry.path()));
+                empty = false;
+            }
+        } else if file_type.is_dir() {
+            let before = files.len();
+            collect_files(&entry.path(), &format!("{}/", name), options, files)?;
+            empty &= files.len() == before;
+        } else if (file_type.is_file() || entry.path().is_file()) && options.included(&name) {
+            files.push((name, entry.path()));
+            empty = false;
+        }
+    }
+
+    let dir_name = prefix.trim_end_matches('/');
+    if empty && !dir_name.is_empty() && options.included(dir_name) {
+        files.push((prefix.to_string(), dir.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Create a symbolic link at `path` pointing to `target`, replacing what's there. Platforms
+/// without symbolic links, and windows without the privilege to create them, skip it with a warning.
+pub(crate) fn create_symlink(target: &Path, path: &Path) -> error::Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path).at_path(path)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, path).at_path(path)?;
+    #[cfg(windows)]
+    if let Err(e) = std::os::windows::fs::symlink_file(target, path) {
+        log::warn!("can't create symbolic link {:?} to {:?}, skipping it: {}", path, target, e);
+    }
+    #[cfg(not(any(unix, windows)))]
+    log::warn!("symbolic links aren't supported on this platform, skipping {:?} to {:?}", path, target);
+    Ok(())
+}
+
+/// Create a hard link at `path` to the file at `target`, replacing what's there.
+/// Where that fails, like across file systems, `target` is copied instead.
+pub(crate) fn create_hardlink(target: &Path, path: &Path) -> error::Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path).at_path(path)?;
+    }
+    if fs::hard_link(target, path).is_err() {
+        fs::copy(target, path).at_path(path)?;
+    }
+    Ok(())
+}
+
+/// What identifies the file `metadata` is of, when other hard links to it may be packed too.
+#[cfg(unix)]
+pub(crate) fn hardlink_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn hardlink_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Set the modification time and permission bits of the extracted file or directory at `path`,
+/// when they're known and the options ask for it.
+pub(crate) fn restore_metadata(path: &Path, modified: Option<SystemTime>, mode: Option<u32>, options: &ExtractOptions) -> error::Result<()> {
+    if let Some(modified) = modified.filter(|_| options.restore_modified) {
+        // directories can only be opened like this on unix
+        if cfg!(unix) || path.is_file() {
+            let file = fs::File::options().write(path.is_file()).read(path.is_dir()).open(path).at_path(path)?;
+            file.set_modified(modified).at_path(path)?;
+        }
+    }
+    #[cfg(unix)]
+    if let Some(mode) = mode.filter(|_| options.restore_permissions) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).at_path(path)?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Where the entry `name` is extracted to in `dir`, refusing names which would end up outside of it.
+/// On windows, names windows reserves are refused too, see [`extract_path_with`].
+pub(crate) fn extract_path(dir: &Path, name: &str) -> error::Result<PathBuf> {
+    extract_path_with(dir, name, cfg!(windows))
+}
+
+/// Like [`extract_path`], refusing names which can't be extracted on windows as well when
+/// `portable`: names with backslashes or drive letters, which windows would read as other
+/// paths, and [reserved names](reserved_on_windows).
+pub(crate) fn extract_path_with(dir: &Path, name: &str, portable: bool) -> error::Result<PathBuf> {
+    let unsafe_path = || PackError::UnsafePath(name.into());
+    if name.contains('\0') {
+        return Err(unsafe_path());
+    }
+
+    let mut res = dir.to_path_buf();
+    for component in EntryName::from(name).to_path_buf().components() {
+        match component {
+            Component::Normal(part) => {
+                if portable && reserved_on_windows(&part.to_string_lossy()) {
+                    return Err(unsafe_path());
+                }
+                res.push(part)
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::ParentDir | Component::Prefix(_) => return Err(unsafe_path()),
+        }
+    }
+    Ok(res)
+}
+
+/// Whether windows can't have a file called `part`: device names like `con` and `lpt1`, also with
+/// an extension, names ending in a dot or space, and names with characters like `\` and `:`.
+pub(crate) fn reserved_on_windows(part: &str) -> bool {
+    const DEVICES: [&str; 4] = ["con", "prn", "aux", "nul"];
+    const NUMBERED: [&str; 2] = ["com", "lpt"];
+
+    let stem = part.split('.').next().unwrap_or_default().trim_end().to_ascii_lowercase();
+    let device = DEVICES.contains(&stem.as_str())
+        || NUMBERED.iter().any(|prefix| stem.strip_prefix(prefix).is_some_and(|n| n.len() == 1 && n.as_bytes()[0].is_ascii_digit() && n != "0"));
+    device
+        || part.ends_with(['.', ' '])
+        || part.chars().any(|c| c < ' ' || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*'))
+}
+
+/// Whether a symbolic link extracted as `name` which points to `target` points inside the directory
+/// it's extracted to, going by the names alone.
+pub(crate) fn link_stays_inside(name: &str, target: &Path) -> bool {
+    let mut depth = Path::new(name).parent().map_or(0, |parent| {
+        parent.components().filter(|c| matches!(c, Component::Normal(_))).count()
+    });
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Fail unless writing to `path` stays inside `dir`, which is canonical: no link already at
+/// `path` is written through, and the directories it's in are inside `dir` after following links.
+/// Directories which don't exist yet are created as real directories, so only the closest
+/// existing one is checked.
+pub(crate) fn check_sandboxed(dir: &Path, path: &Path, name: &str) -> error::Result<()> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        return Err(PackError::UnsafePath(name.into()));
+    }
+    let Some(existing) = path.ancestors().skip(1).find(|ancestor| fs::symlink_metadata(ancestor).is_ok()) else {
+        return Ok(());
+    };
+    if !fs::canonicalize(existing).at_path(existing)?.starts_with(dir) {
+        return Err(PackError::UnsafePath(name.into()));
+    }
+    Ok(())
+}
+
+/// Create the directory `path` and the ones it's in, like [`fs::create_dir_all`]. With a
+/// `sandbox` they're created one at a time from the top, each [checked](check_sandboxed)
+/// before it's created, so nothing is created through a link which leads out of it.
+pub(crate) fn create_dirs(sandbox: Option<&Path>, path: &Path, name: &str) -> error::Result<()> {
+    let Some(sandbox) = sandbox else {
+        return fs::create_dir_all(path).at_path(path);
+    };
+    let missing = path.ancestors()
+        .take_while(|ancestor| fs::symlink_metadata(ancestor).is_err())
+        .collect::<Vec<_>>();
+    for dir in missing.into_iter().rev() {
+        check_sandboxed(sandbox, dir, name)?;
+        match fs::create_dir(dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e).at_path(dir),
+            _ => {}
+        }
+    }
+    check_sandboxed(sandbox, path, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::pack::directory::{extract_path_with, glob_match, link_stays_inside, reserved_on_windows};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.png", "wall.png"));
+        assert!(!glob_match("*.png", "textures/wall.png"));
+        assert!(glob_match("**/*.png", "wall.png"));
+        assert!(glob_match("**/*.png", "assets/textures/wall.png"));
+        assert!(glob_match("assets/**", "assets/textures/wall.png"));
+        assert!(!glob_match("assets/**", "other/wall.png"));
+        assert!(glob_match("level?.dat", "level1.dat"));
+        assert!(!glob_match("level?.dat", "level10.dat"));
+        assert!(glob_match("*", "readme"));
+        assert!(!glob_match("*", "docs/readme"));
+    }
+
+    #[test]
+    fn test_unsafe_names() {
+        let dir = Path::new("out");
+        assert_eq!(extract_path_with(dir, "a/./b.txt", true).unwrap(), Path::new("out/a/b.txt"));
+        for name in ["/etc/passwd", "../x", "a/../../x", "a\0b"] {
+            assert!(extract_path_with(dir, name, false).is_err(), "{}", name);
+        }
+        for name in ["..\\x", "C:\\x", "c:x", "con", "aux.txt", "dir/LPT1.log", "com7", "trailing.", "a|b"] {
+            assert!(extract_path_with(dir, name, true).is_err(), "{}", name);
+            assert!(cfg!(windows) || extract_path_with(dir, name, false).is_ok(), "{}", name);
+        }
+        assert!(!reserved_on_windows("console"));
+        assert!(!reserved_on_windows("com0"));
+        assert!(!reserved_on_windows("com10"));
+
+        assert!(link_stays_inside("a/link", Path::new("../b")));
+        assert!(link_stays_inside("a/link", Path::new("c/../../b")));
+        assert!(!link_stays_inside("a/link", Path::new("../../b")));
+        assert!(!link_stays_inside("link", Path::new("/etc/passwd")));
+    }
+}
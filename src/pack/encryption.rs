@@ -0,0 +1,312 @@
+//! XChaCha20-Poly1305 (RFC 8439, with the extended nonce of draft-irtf-cfrg-xchacha, from the
+//! `chacha20poly1305` crate) for encrypting the contents of files, with keys derived from a passphrase by PBKDF2-HMAC-SHA256.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::layout::{decode_encryption, U16Le, U32Le, XCHACHA20_POLY1305};
+
+const NONCE_SIZE: usize = 24;
+const TAG_SIZE: usize = 16;
+const SALT_SIZE: usize = 16;
+/// What encrypting adds to the size of a file.
+pub(crate) const OVERHEAD: u64 = (NONCE_SIZE + TAG_SIZE) as u64;
+
+/// Encrypt the files of a backpack with XChaCha20-Poly1305, with a key derived from a passphrase,
+/// see [`BackPack::set_encryption`](crate::BackPack::set_encryption).
+#[cfg(feature = "crypto")]
+#[derive(Clone)]
+pub struct Encryption {
+    passphrase: String,
+    iterations: u32,
+}
+
+#[cfg(feature = "crypto")]
+impl Encryption {
+    /// Rounds of PBKDF2 used unless set with [`with_iterations`](Self::with_iterations).
+    pub const DEFAULT_ITERATIONS: u32 = 600_000;
+    /// The most rounds of PBKDF2 a backpack may ask for, a few seconds of deriving.
+    pub const MAX_ITERATIONS: u32 = 10_000_000;
+
+    pub fn new(passphrase: impl Into<String>) -> Self {
+        Self {
+            passphrase: passphrase.into(),
+            iterations: Self::DEFAULT_ITERATIONS,
+        }
+    }
+
+    /// Rounds of PBKDF2 to derive the key with. More makes opening the backpack slower,
+    /// and guessing the passphrase too. At most [`MAX_ITERATIONS`](Self::MAX_ITERATIONS).
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations.clamp(1, Self::MAX_ITERATIONS);
+        self
+    }
+
+    /// Derive a key with a new random salt.
+    pub(crate) fn key(&self) -> error::Result<EncryptionKey> {
+        let mut salt = [0; SALT_SIZE];
+        fill_random(&mut salt)?;
+        Ok(EncryptionKey::derive(&self.passphrase, salt, self.iterations))
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A key derived from a passphrase, with what's needed to derive it again.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    key: [u8; 32],
+    salt: [u8; SALT_SIZE],
+    iterations: u32,
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptionKey {
+    #[cfg(feature = "crypto")]
+    fn derive(passphrase: &str, salt: [u8; SALT_SIZE], iterations: u32) -> Self {
+        let mut key = [0; 32];
+        pbkdf2_sha256(passphrase.as_bytes(), &salt, iterations, &mut key);
+        Self { key, salt, iterations }
+    }
+
+    /// Derive the key described by the contents of the encryption entry, written by
+    /// [`encode`](Self::encode), failing with [`PackError::WrongPassphrase`] for the wrong passphrase
+    /// and with [`PackError::Encrypted`] without one.
+    #[cfg(feature = "crypto")]
+    pub(crate) fn from_entry(passphrase: Option<&str>, entry: &[u8]) -> error::Result<Self> {
+        decode_field(entry)?;
+        let passphrase = passphrase.ok_or(PackError::Encrypted)?;
+        let iterations = entry.get(2..).and_then(U32Le::from_slice).ok_or(PackError::InvalidEntry)?.get();
+        // the count comes from the file, don't let it keep us busy for hours
+        if iterations > Encryption::MAX_ITERATIONS {
+            return Err(PackError::Malformed(format!("{iterations} rounds of PBKDF2 is more than the {} allowed", Encryption::MAX_ITERATIONS)));
+        }
+        let salt = entry.get(6..6 + SALT_SIZE).ok_or(PackError::InvalidEntry)?;
+        let check = entry.get(6 + SALT_SIZE..).ok_or(PackError::InvalidEntry)?;
+
+        let key = Self::derive(passphrase, salt.try_into().expect("salt has the right length"), iterations);
+        if !constant_time_eq(&key.check(), check) {
+            return Err(PackError::WrongPassphrase);
+        }
+        Ok(key)
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    pub(crate) fn from_entry(_passphrase: Option<&str>, _entry: &[u8]) -> error::Result<Self> {
+        Err(PackError::Encrypted)
+    }
+
+    /// The contents of the encryption entry: the method, the PBKDF2 iterations, the salt,
+    /// and a check value to tell a wrong passphrase apart from damaged files.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut res = encode_field().to_vec();
+        res.extend_from_slice(&U32Le::new(self.iterations).to_bytes());
+        res.extend_from_slice(&self.salt);
+        res.extend_from_slice(&self.check());
+        res
+    }
+
+    fn check(&self) -> [u8; TAG_SIZE] {
+        seal(&self.key, &[0; NONCE_SIZE], b"backpack key check", &mut [])
+    }
+
+    /// Encrypt the contents of the file called `name`, as a random nonce, the ciphertext and
+    /// the tag. The name is authenticated too, so contents can't be moved to another file.
+    pub(crate) fn encrypt(&self, name: &str, contents: &[u8]) -> error::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_SIZE];
+        fill_random(&mut nonce)?;
+
+        let mut res = Vec::with_capacity(contents.len() + OVERHEAD as usize);
+        res.extend_from_slice(&nonce);
+        res.extend_from_slice(contents);
+        let tag = seal(&self.key, &nonce, name.as_bytes(), &mut res[NONCE_SIZE..]);
+        res.extend_from_slice(&tag);
+        Ok(res)
+    }
+
+    /// Decrypt what [`encrypt`](Self::encrypt) made of the contents of `name`.
+    pub(crate) fn decrypt(&self, name: &str, data: &[u8]) -> error::Result<Vec<u8>> {
+        if data.len() < OVERHEAD as usize {
+            return Err(PackError::ChecksumMismatch(name.into()));
+        }
+        let (nonce, rest) = data.split_at(NONCE_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        let nonce: &[u8; NONCE_SIZE] = nonce.try_into().expect("split at the nonce size");
+
+        let mut res = ciphertext.to_vec();
+        if !open(&self.key, nonce, name.as_bytes(), &mut res, tag) {
+            return Err(PackError::ChecksumMismatch(name.into()));
+        }
+        Ok(res)
+    }
+}
+
+/// Check the method of an [`ENCRYPTION_FIELD`](crate::pack::ENCRYPTION_FIELD).
+pub(crate) fn decode_field(value: &[u8]) -> error::Result<()> {
+    Ok(decode_encryption(value)?)
+}
+
+pub(crate) fn encode_field() -> [u8; 2] {
+    U16Le::new(XCHACHA20_POLY1305).to_bytes()
+}
+
+/// Encrypt the contents of every entry with `key`, except empty ones, and record them in
+/// `encrypted`. Returns the encrypted contents with their index in `entries`.
+pub(crate) fn encrypt_entries(entries: &[(&str, &[u8])], key: &EncryptionKey, encrypted: &mut HashSet<String>) -> error::Result<Vec<(usize, Vec<u8>)>> {
+    let mut contents = Vec::new();
+    for (i, (name, data)) in entries.iter().enumerate() {
+        if data.is_empty() {
+            continue;
+        }
+
+        contents.push((i, key.encrypt(name, data)?));
+        encrypted.insert(name.to_string());
+    }
+
+    Ok(contents)
+}
+
+/// Fails when a file of an encrypted backpack isn't encrypted. Anyone could have put it there,
+/// since it doesn't need the key.
+pub(crate) fn check_encrypted(offsets: &HashMap<String, (u64, u64)>, encrypted: &HashSet<String>) -> error::Result<()> {
+    match offsets.iter().find(|(name, (_, length))| *length != 0 && !encrypted.contains(*name)) {
+        Some((name, _)) => Err(PackError::Unencrypted(name.into())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn fill_random(buf: &mut [u8]) -> error::Result<()> {
+    getrandom::getrandom(buf).map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+/// Keys only exist with the `crypto` feature, so nothing is ever encrypted without it.
+#[cfg(not(feature = "crypto"))]
+fn fill_random(_buf: &mut [u8]) -> error::Result<()> {
+    Err(PackError::Encrypted)
+}
+
+#[cfg(feature = "crypto")]
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let prf = Hmac::<Sha256>::new_from_slice(password).expect("hmac accepts keys of any length");
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut mac = prf.clone();
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize().into_bytes();
+        let mut block = u;
+
+        for _ in 1..iterations {
+            let mut mac = prf.clone();
+            mac.update(&u);
+            u = mac.finalize().into_bytes();
+            block.iter_mut().zip(&u).for_each(|(b, u)| *b ^= u);
+        }
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Encrypt `data` in place with XChaCha20-Poly1305, returning the tag.
+#[cfg(feature = "crypto")]
+fn seal(key: &[u8; 32], nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &mut [u8]) -> [u8; TAG_SIZE] {
+    use chacha20poly1305::aead::AeadInPlace;
+    use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+
+    XChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(nonce.into(), aad, data)
+        .expect("the contents of a backpack fit in the XChaCha20 keystream")
+        .into()
+}
+
+/// Check `tag` and decrypt `data` in place, false when it doesn't match.
+#[cfg(feature = "crypto")]
+fn open(key: &[u8; 32], nonce: &[u8; NONCE_SIZE], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+    use chacha20poly1305::aead::AeadInPlace;
+    use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+
+    XChaCha20Poly1305::new(key.into())
+        .decrypt_in_place_detached(nonce.into(), aad, data, tag.into())
+        .is_ok()
+}
+
+// Keys can't be made without the `crypto` feature, so there is nothing to seal or open.
+#[cfg(not(feature = "crypto"))]
+fn seal(_key: &[u8; 32], _nonce: &[u8; NONCE_SIZE], _aad: &[u8], _data: &mut [u8]) -> [u8; TAG_SIZE] {
+    unreachable!("keys only exist with the `crypto` feature")
+}
+
+#[cfg(not(feature = "crypto"))]
+fn open(_key: &[u8; 32], _nonce: &[u8; NONCE_SIZE], _aad: &[u8], _data: &mut [u8], _tag: &[u8]) -> bool {
+    unreachable!("keys only exist with the `crypto` feature")
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use crate::error::PackError;
+    use crate::pack::encryption::{open, seal, Encryption, EncryptionKey, NONCE_SIZE};
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // Test vector from draft-irtf-cfrg-xchacha-03.
+    #[test]
+    fn test_xchacha20_poly1305() {
+        let key: [u8; 32] = (0x80..0xa0).collect::<Vec<u8>>().try_into().unwrap();
+        let nonce: [u8; NONCE_SIZE] = (0x40..0x58).collect::<Vec<u8>>().try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let mut data = plaintext.to_vec();
+        let tag = seal(&key, &nonce, &aad, &mut data);
+        assert_eq!(data, hex("bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff921f9664c97637da9768812f615c68b13b52e"));
+        assert_eq!(tag.to_vec(), hex("c0875924c1c7987947deafd8780acf49"));
+
+        // and back, but not with another aad
+        let mut wrong = data.clone();
+        assert!(!open(&key, &nonce, b"other", &mut wrong, &tag));
+        assert!(open(&key, &nonce, &aad, &mut data, &tag));
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        let mut key = [0; 32];
+        crate::pack::encryption::pbkdf2_sha256(b"password", b"salt", 4096, &mut key);
+        assert_eq!(key.to_vec(), hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"));
+    }
+
+    #[test]
+    fn test_iterations_cap() {
+        let mut entry = Encryption::new("secret").with_iterations(1).key().unwrap().encode();
+        // would take hours to derive
+        entry[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(EncryptionKey::from_entry(Some("secret"), &entry), Err(PackError::Malformed(_))));
+
+        assert_eq!(Encryption::new("secret").with_iterations(u32::MAX).iterations, Encryption::MAX_ITERATIONS);
+    }
+}
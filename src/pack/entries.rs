@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use crate::pack::backpack::Tier;
+use crate::pack::BackPack;
+
+/// What the backpack knows about a file besides its contents.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// see [`BackPack::modified`]
+    pub modified: Option<SystemTime>,
+    /// see [`BackPack::expiry`]
+    pub expiry: Option<SystemTime>,
+    /// see [`BackPack::set_tier`]
+    pub tier: Option<Tier>,
+    /// see [`BackPack::set_attribute`]
+    pub attributes: BTreeMap<String, Vec<u8>>,
+}
+
+/// What an entry of a backpack is, see [`BackPack::entry_kind`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    /// an [added](BackPack::add_dir) directory
+    Directory,
+    /// a [symbolic link](BackPack::add_symlink) to this path
+    Symlink(PathBuf),
+    /// an [alias](BackPack::set_alias) of the file stored under this name, extracted as a hard link
+    Hardlink(String),
+}
+
+/// A file in a backpack, as listed by [`BackPack::entries`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryRef {
+    pub name: String,
+    pub size: u64,
+    /// Offset of the contents from the start of the backpack, `None` for files added since the
+    /// backpack was last flushed and for empty files, like in a [`Manifest`](crate::manifest::Manifest).
+    pub offset: Option<u64>,
+    pub metadata: EntryMetadata,
+}
+
+/// Iterator over the files of a backpack sorted by name, see [`BackPack::entries`].
+/// It lists the files as they were when it was created.
+pub struct Entries {
+    entries: std::vec::IntoIter<EntryRef>,
+}
+
+impl Entries {
+    pub(crate) fn new(entries: Vec<EntryRef>) -> Self {
+        Self {
+            entries: entries.into_iter(),
+        }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = EntryRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Entries {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back()
+    }
+}
+
+impl ExactSizeIterator for Entries {}
+
+/// The files of a backpack sorted by name, with metadata which can be changed in place,
+/// see [`BackPack::entries_mut`]. Changes to the [metadata](EntryRef::metadata) are made
+/// to the backpack when this is dropped, changes to the names, sizes and offsets are ignored.
+pub struct EntriesMut<'a, 'f, 'backpack> {
+    pack: &'a mut BackPack<'f, 'backpack>,
+    entries: Vec<EntryRef>,
+}
+
+impl<'a, 'f, 'backpack> EntriesMut<'a, 'f, 'backpack> {
+    pub(crate) fn new(pack: &'a mut BackPack<'f, 'backpack>, entries: Vec<EntryRef>) -> Self {
+        Self {
+            pack,
+            entries,
+        }
+    }
+}
+
+impl Deref for EntriesMut<'_, '_, '_> {
+    type Target = [EntryRef];
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl DerefMut for EntriesMut<'_, '_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.entries
+    }
+}
+
+impl<'e> IntoIterator for &'e mut EntriesMut<'_, '_, '_> {
+    type Item = &'e mut EntryRef;
+    type IntoIter = std::slice::IterMut<'e, EntryRef>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter_mut()
+    }
+}
+
+impl Drop for EntriesMut<'_, '_, '_> {
+    fn drop(&mut self) {
+        for entry in &self.entries {
+            self.pack.set_metadata(&entry.name, &entry.metadata);
+        }
+    }
+}
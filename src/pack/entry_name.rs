@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::PackError;
+pub(crate) use crate::format::names::{key_bytes, key_of_bytes};
+
+/// The name of an entry with the bytes it has in the backpack, which don't have to be UTF-8.
+/// Files with names which aren't UTF-8 on the host, like those from old file systems, keep the
+/// bytes of their name when they're packed and get them back when they're extracted.
+///
+/// Methods taking or returning names as strings, like [`BackPack::file_names`](crate::BackPack::file_names),
+/// write bytes which aren't UTF-8 as private use characters, so those strings still find the
+/// entry. Convert them with [`EntryName::from`], and back with [`to_str`](Self::to_str), which fails
+/// for names which aren't UTF-8, or [`to_string_lossy`](Self::to_string_lossy), which replaces those bytes.
+/// Names with the last 128 characters of the last private use plane can't be stored exactly.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryName {
+    bytes: Vec<u8>,
+}
+
+impl EntryName {
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        Self { bytes: bytes.into() }
+    }
+
+    /// The name with the bytes of `name`. On platforms other than unix, names which aren't
+    /// unicode have their unpaired surrogates replaced.
+    pub fn from_os_str(name: &OsStr) -> Self {
+        #[cfg(unix)]
+        let bytes = std::os::unix::ffi::OsStrExt::as_bytes(name).to_vec();
+        #[cfg(not(unix))]
+        let bytes = name.to_string_lossy().into_owned().into_bytes();
+        Self { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        std::str::from_utf8(&self.bytes).is_ok()
+    }
+
+    /// The name as text, failing with [`PackError::NonUtf8Name`] when it isn't UTF-8.
+    pub fn to_str(&self) -> error::Result<&str> {
+        std::str::from_utf8(&self.bytes).map_err(|_| PackError::NonUtf8Name(self.to_string_lossy().into_owned()))
+    }
+
+    /// The name as text, with bytes which aren't UTF-8 replaced by `�`.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.bytes)
+    }
+
+    /// The name as the string methods of backpacks take and return it, see [`EntryName`].
+    pub fn to_key(&self) -> Cow<'_, str> {
+        key_of_bytes(&self.bytes)
+    }
+
+    /// The name as a host string, with the same bytes on unix. Elsewhere bytes which aren't
+    /// UTF-8 are replaced.
+    pub fn to_os_string(&self) -> OsString {
+        #[cfg(unix)]
+        return std::os::unix::ffi::OsStringExt::from_vec(self.bytes.clone());
+        #[cfg(not(unix))]
+        return OsString::from(self.to_string_lossy().into_owned());
+    }
+
+    /// The name as a path, to pass to methods of backpacks or to create the file on the host.
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf::from(self.to_os_string())
+    }
+}
+
+/// Converts a name as returned by the string methods of backpacks, see [`EntryName`].
+impl From<&str> for EntryName {
+    fn from(key: &str) -> Self {
+        Self { bytes: key_bytes(key).into_owned() }
+    }
+}
+
+impl From<String> for EntryName {
+    fn from(key: String) -> Self {
+        Self::from(key.as_str())
+    }
+}
+
+impl From<&OsStr> for EntryName {
+    fn from(name: &OsStr) -> Self {
+        Self::from_os_str(name)
+    }
+}
+
+impl From<&Path> for EntryName {
+    fn from(name: &Path) -> Self {
+        Self::from_os_str(name.as_os_str())
+    }
+}
+
+impl fmt::Display for EntryName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_lossy())
+    }
+}
+
+/// The name of the file on the host called `name` as a string, see [`key_of_bytes`].
+pub(crate) fn key_of(name: &OsStr) -> Cow<'_, str> {
+    #[cfg(unix)]
+    return key_of_bytes(std::os::unix::ffi::OsStrExt::as_bytes(name));
+    #[cfg(not(unix))]
+    return name.to_string_lossy();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::entry_name::{key_bytes, key_of_bytes, EntryName};
+    use crate::pack::PackError;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(key_of_bytes(b"a/b.txt"), "a/b.txt");
+        for bytes in [&b"caf\xe9.txt"[..], b"\xff\xfe", b"ok \xc3\xa9 \x80"] {
+            let key = key_of_bytes(bytes);
+            assert!(key.len() > bytes.len());
+            assert_eq!(key_bytes(&key), bytes);
+        }
+
+        let name = EntryName::from_bytes(&b"caf\xe9.txt"[..]);
+        assert!(!name.is_utf8());
+        assert!(matches!(name.to_str(), Err(PackError::NonUtf8Name(_))));
+        assert_eq!(name.to_string_lossy(), "caf\u{FFFD}.txt");
+        assert_eq!(EntryName::from(name.to_key().as_ref()), name);
+        assert_eq!(EntryName::from("café").to_str().unwrap(), "café");
+    }
+}
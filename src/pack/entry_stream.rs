@@ -0,0 +1,88 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::pack::{BackPack, InMemoryFile};
+
+/// A seekable reader over the contents of a file, see [`BackPack::entry_reader`].
+/// Reads straight from the contents the backpack holds, without copying them first.
+/// Unlike the file itself, it can't be written to.
+pub struct EntryReader<'f, 'backpack> {
+    data: InMemoryFile<'f, 'backpack>,
+    len: u64,
+}
+
+impl<'f, 'backpack> EntryReader<'f, 'backpack> {
+    pub(crate) fn new(data: InMemoryFile<'f, 'backpack>) -> Self {
+        let len = data.get_bytes().len() as u64;
+        Self { data, len }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for EntryReader<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl BufRead for EntryReader<'_, '_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.data.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.data.consume(amt)
+    }
+}
+
+impl Seek for EntryReader<'_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+/// Writes a new file, see [`BackPack::entry_writer`]. Written contents are buffered until
+/// [`finish`](Self::finish) adds the file, dropping it without finishing adds nothing.
+pub struct EntryWriter<'f, 'backpack> {
+    pack: &'f BackPack<'f, 'backpack>,
+    name: PathBuf,
+    data: Vec<u8>,
+}
+
+impl<'f, 'backpack> EntryWriter<'f, 'backpack> {
+    pub(crate) fn new(pack: &'f BackPack<'f, 'backpack>, name: PathBuf) -> Self {
+        Self {
+            pack,
+            name,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Add the file with what was written to it. The backpack's
+    /// [collision policy](BackPack::set_collision_policy) decides what happens when its name is taken.
+    pub fn finish(self) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        self.pack.add_file(InMemoryFile::from(self.data).with_name(&self.name))
+    }
+}
+
+impl Write for EntryWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+use parking_lot::Mutex;
+use crate::error;
+use crate::RawFile;
+
+/// Operations on a [`FaultyFile`] which can be made to fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Read,
+    Write,
+    Seek,
+    Flush,
+    SetLen,
+    Sync,
+}
+
+/// When a fault happens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Only the nth call of the operation fails, counting from 0
+    NthCall(usize),
+    /// Reads or writes reaching this byte offset fail. Everything before it is still
+    /// read or written, so a write can be torn halfway through.
+    AtOffset(u64),
+    /// Every call fails
+    Always,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Fault {
+    operation: Operation,
+    trigger: Trigger,
+    kind: ErrorKind,
+}
+
+/// Wraps a [`RawFile`] and fails operations on it according to a schedule,
+/// to test how failures of the backing file are handled.
+///
+/// ```
+/// # use std::io::ErrorKind;
+/// # use backpack::{BackPack, RawFile};
+/// # use backpack::pack::{FaultyFile, Operation, Trigger};
+/// # fn main() -> Result<(), backpack::PackError> {
+/// let file = FaultyFile::new(RawFile::in_memory("test.bp"))
+///     .fail(Operation::Write, Trigger::Always, ErrorKind::StorageFull);
+/// let mut bp = BackPack::create(RawFile::from(file))?;
+/// assert!(bp.flush().is_err());
+/// bp.close_drop_unwritten_changes()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FaultyFile<'f, 'backpack> {
+    pub(crate) inner: RawFile<'f, 'backpack>,
+    pub(crate) faults: Vec<Fault>,
+    pub(crate) calls: Mutex<Vec<(Operation, usize)>>,
+    pub(crate) position: u64,
+}
+
+impl<'f, 'backpack> FaultyFile<'f, 'backpack> {
+    pub fn new(inner: RawFile<'f, 'backpack>) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+            calls: Mutex::new(Vec::new()),
+            position: 0,
+        }
+    }
+
+    /// Make `operation` fail with an error of `kind` when `trigger` happens.
+    pub fn fail(mut self, operation: Operation, trigger: Trigger, kind: ErrorKind) -> Self {
+        self.faults.push(Fault { operation, trigger, kind });
+        self
+    }
+
+    /// Stop failing; operations from now on succeed.
+    pub fn heal(&mut self) {
+        self.faults.clear();
+    }
+
+    /// How often `operation` was attempted, including failed attempts.
+    pub fn calls(&self, operation: Operation) -> usize {
+        self.calls.lock().iter()
+            .find(|(o, _)| *o == operation)
+            .map_or(0, |(_, n)| *n)
+    }
+
+    pub fn inner(&self) -> &RawFile<'f, 'backpack> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> RawFile<'f, 'backpack> {
+        self.inner
+    }
+
+    /// Counts the call, and returns how many bytes of a `len` byte read or write may go
+    /// through, or the error to fail with.
+    fn check(&self, operation: Operation, len: usize) -> io::Result<usize> {
+        let mut calls = self.calls.lock();
+        let call = match calls.iter_mut().find(|(o, _)| *o == operation) {
+            Some((_, n)) => {
+                *n += 1;
+                *n - 1
+            }
+            None => {
+                calls.push((operation, 1));
+                0
+            }
+        };
+
+        let mut allowed = len;
+        for fault in self.faults.iter().filter(|f| f.operation == operation) {
+            let error = || io::Error::new(fault.kind, format!("injected {:?} fault", operation));
+            match fault.trigger {
+                Trigger::Always => return Err(error()),
+                Trigger::NthCall(n) if n == call => return Err(error()),
+                Trigger::NthCall(_) => {}
+                Trigger::AtOffset(offset) => {
+                    let end = self.position + len as u64;
+                    if offset == self.position && len > 0 {
+                        return Err(error());
+                    } else if offset > self.position && offset < end {
+                        allowed = allowed.min((offset - self.position) as usize);
+                    }
+                }
+            }
+        }
+
+        Ok(allowed)
+    }
+
+    pub(crate) fn set_len(&mut self, size: u64) -> error::Result<()> {
+        self.check(Operation::SetLen, 0)?;
+        self.inner.set_len(size)
+    }
+
+    pub(crate) fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> error::Result<()> {
+        self.check(Operation::Read, buf.len())?;
+        self.inner.read_exact_at(offset, buf)
+    }
+
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> error::Result<usize> {
+        self.check(Operation::Read, buf.len())?;
+        self.inner.read_at(offset, buf)
+    }
+
+    pub(crate) fn write_at(&mut self, offset: u64, buf: &[u8]) -> error::Result<usize> {
+        self.check(Operation::Write, buf.len())?;
+        self.inner.write_at(offset, buf)
+    }
+
+    pub(crate) fn sync(&self) -> error::Result<()> {
+        self.check(Operation::Sync, 0)?;
+        Ok(())
+    }
+}
+
+impl Read for FaultyFile<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let allowed = self.check(Operation::Read, buf.len())?;
+        let n = self.inner.read(&mut buf[..allowed])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for FaultyFile<'_, '_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let available = self.inner.fill_buf()?.len();
+        let allowed = self.check(Operation::Read, available)?;
+        Ok(&self.inner.fill_buf()?[..allowed])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.position += amt as u64;
+    }
+}
+
+impl Write for FaultyFile<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let allowed = self.check(Operation::Write, buf.len())?;
+        let n = self.inner.write(&buf[..allowed])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check(Operation::Flush, 0)?;
+        self.inner.flush()
+    }
+}
+
+impl Seek for FaultyFile<'_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.check(Operation::Seek, 0)?;
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+impl<'f, 'backpack> From<FaultyFile<'f, 'backpack>> for RawFile<'f, 'backpack> {
+    fn from(f: FaultyFile<'f, 'backpack>) -> Self {
+        RawFile::Faulty(Box::new(f))
+    }
+}
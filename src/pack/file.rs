@@ -1,22 +1,78 @@
-use std::fs::Metadata;
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, Cursor, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::pack::in_memory::InMemoryFile;
-use crate::error::Result;
+use crate::error::{AtPath, Result};
+use crate::pack::advice;
+use crate::pack::advice::Advice;
+use crate::pack::faulty::FaultyFile;
+use parking_lot::{MappedRwLockReadGuard, Mutex};
+use crate::pack::maybe_ref::MaybeRef;
+use crate::pack::storage::{Storage, StorageFile};
+use crate::pack::buffered::BufferedFile;
+use crate::pack::sparse;
+use crate::pack::tee::TeeFile;
+use crate::pack::volumes::Volumes;
+#[cfg(feature = "mmap")]
+use crate::pack::mmap::MmapFile;
+
+/// What's known about a [`RawFile`], the same for files on disk and in memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub len: u64,
+    /// `None` when it's not known, like for files in memory
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// whether the file can't be written to, `None` for files in memory
+    pub readonly: Option<bool>,
+    /// unix permission bits, only known for files on disk on unix
+    pub mode: Option<u32>,
+}
+
+impl From<std::fs::Metadata> for FileMetadata {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        Self {
+            len: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            readonly: Some(metadata.permissions().readonly()),
+            mode,
+        }
+    }
+}
 
 pub enum RawFile<'f, 'backpack> {
     InMemory(InMemoryFile<'f, 'backpack>),
     Disk {
         name: Option<PathBuf>,
-        file: std::fs::File,
+        /// small writes are collected before they're written, see [`with_write_buffer`](Self::with_write_buffer)
+        file: BufferedFile,
     },
+    /// A file which fails on purpose, for testing
+    Faulty(Box<FaultyFile<'f, 'backpack>>),
+    /// A file on disk mapped into memory, see [`open_mmap`](Self::open_mmap)
+    #[cfg(feature = "mmap")]
+    Mmap(MmapFile),
+    /// A file kept in a custom [`Storage`] backend, see [`from_storage`](Self::from_storage)
+    Storage(StorageFile<'f>),
 }
 
 impl<'f, 'backpack> RawFile<'f, 'backpack> {
     pub fn into_memory(self) -> std::result::Result<InMemoryFile<'f, 'backpack>, RawFile<'f, 'backpack>> {
         match self {
             RawFile::InMemory(f) => Ok(f),
-            f @ RawFile::Disk { .. } => Err(f)
+            f @ (RawFile::Disk { .. } | RawFile::Faulty(_) | RawFile::Storage(_)) => Err(f),
+            #[cfg(feature = "mmap")]
+            f @ RawFile::Mmap(_) => Err(f),
         }
     }
 
@@ -30,12 +86,28 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
                 Ok(if let Some(name) = name {
                     InMemoryFile::Named {
                         name,
-                        data: Cursor::new(data),
+                        data: Cursor::new(data.into()),
                     }
                 } else {
                     data.into()
                 })
             }
+            RawFile::Faulty(f) => f.into_inner().convert_into_memory(),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => {
+                let data = f.as_bytes().to_vec();
+                Ok(match f.name.clone() {
+                    Some(name) => InMemoryFile::Named { name, data: Cursor::new(data.into()) },
+                    None => data.into(),
+                })
+            }
+            RawFile::Storage(f) => {
+                let data = f.read_all()?;
+                Ok(match f.name {
+                    Some(name) => InMemoryFile::Named { name, data: Cursor::new(data.into()) },
+                    None => data.into(),
+                })
+            }
         }
     }
 
@@ -48,6 +120,19 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
                     file,
                 }
             }
+            RawFile::Faulty(f) => {
+                let FaultyFile { inner, faults, calls, position } = *f;
+                RawFile::Faulty(Box::new(FaultyFile { inner: inner.with_name(name), faults, calls, position }))
+            }
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(mut f) => {
+                f.name = Some(name.as_ref().to_path_buf());
+                RawFile::Mmap(f)
+            }
+            RawFile::Storage(mut f) => {
+                f.name = Some(name.as_ref().to_path_buf());
+                RawFile::Storage(f)
+            }
         }
     }
 
@@ -55,45 +140,132 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
         Self::InMemory(InMemoryFile::new(name))
     }
 
+    /// A file kept in `storage`, for backends other than files on disk and memory,
+    /// like IndexedDB in the browser.
+    pub fn from_storage(storage: impl Storage + 'f) -> Self {
+        Self::Storage(StorageFile::new(Box::new(storage)))
+    }
+
+    /// A new backpack file split over volumes `base.001`, `base.002`, ... of at most
+    /// `volume_size` bytes, see [`Volumes`].
+    pub fn create_volumes(base: impl AsRef<Path>, volume_size: u64) -> Result<Self> {
+        Ok(Self::from_storage(Volumes::create(&base, volume_size)?).with_name(base))
+    }
+
+    /// The volumes of a backpack which was split with [`create_volumes`](Self::create_volumes),
+    /// read and written as one file.
+    pub fn open_volumes(base: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_storage(Volumes::open(&base, true)?).with_name(base))
+    }
+
+    /// This file, also writing everything written to it to `mirrors`, see [`TeeFile`].
+    pub fn tee(self, mirrors: impl IntoIterator<Item=RawFile<'f, 'backpack>>) -> Self {
+        Self::from_storage(TeeFile::new(self, mirrors))
+    }
+
     pub fn create(s: impl AsRef<Path>) -> Result<Self> {
         Ok(Self::Disk {
             name: Some(s.as_ref().to_path_buf()),
-            file: std::fs::File::create(s)?,
+            file: BufferedFile::new(std::fs::File::create(&s).at_path(s)?),
         })
     }
 
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
         Ok(Self::Disk {
             name: Some(path.as_ref().to_path_buf()),
-            file: std::fs::File::open(path)?,
+            file: BufferedFile::new(std::fs::File::open(&path).at_path(path)?),
         })
     }
 
+    /// An anonymous file on disk which is deleted when it's dropped, for data too large to keep
+    /// in memory which doesn't belong in a named file either. Created in [`std::env::temp_dir`].
+    pub fn temp() -> Result<Self> {
+        Self::temp_in(std::env::temp_dir())
+    }
+
+    /// Like [`temp`](Self::temp), in the directory `dir`. The file is removed from `dir` right
+    /// away, so it doesn't stay behind even if the process doesn't exit cleanly. Windows can't
+    /// remove open files, there it's removed when it's closed.
+    pub fn temp_in(dir: impl AsRef<Path>) -> Result<Self> {
+        Ok(temp_file(dir.as_ref()).at_path(dir)?.into())
+    }
+
+    /// Open a file on disk read-only and map it into memory, so reads are served from the
+    /// page cache without a system call each, and without reading the whole file up front.
+    ///
+    /// # Safety
+    /// The file must not be truncated or changed, by this or any other process, while it's open.
+    /// Reading a truncated part of the mapping kills the process with `SIGBUS`.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(&path).at_path(&path)?;
+        // Safety: the caller promises the file isn't changed while it's mapped
+        Ok(Self::Mmap(unsafe { MmapFile::map(file, Some(path.as_ref().to_path_buf()))? }))
+    }
+
+    /// Collect up to `capacity` bytes of writes to a file on disk before passing them to the
+    /// operating system, instead of the default of [`DEFAULT_WRITE_BUFFER`](crate::pack::DEFAULT_WRITE_BUFFER).
+    /// 0 writes everything right away. Has no effect on other files.
+    pub fn with_write_buffer(mut self, capacity: usize) -> Self {
+        if let RawFile::Disk { file, .. } = &mut self {
+            file.set_capacity(capacity);
+        }
+        self
+    }
+
     pub fn current_offset(&mut self) -> Result<u64> {
         match self {
-            RawFile::Disk { file, .. } => file.seek(SeekFrom::Current(0)).map_err(Into::into),
+            RawFile::Disk { file, .. } => file.stream_position().map_err(Into::into),
             RawFile::InMemory(f, ..) => Ok(f.current_offset()),
+            RawFile::Faulty(f) => Ok(f.position),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => Ok(f.current_offset()),
+            RawFile::Storage(f) => Ok(f.position),
         }
     }
 
     pub fn sync_all(&self) -> Result<()> {
         match self {
-            RawFile::Disk { file, .. } => file.sync_all().map_err(Into::into),
+            RawFile::Disk { file, .. } => file.get_ref()?.sync_all().map_err(Into::into),
             RawFile::InMemory(..) => Ok(()),
+            RawFile::Faulty(f) => f.sync().and_then(|_| f.inner.sync_all()),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(..) => Ok(()),
+            RawFile::Storage(f) => f.storage.sync().map_err(Into::into),
         }
     }
 
     pub fn sync_data(&self) -> Result<()> {
         match self {
             RawFile::InMemory(..) => Ok(()),
-            RawFile::Disk { file, .. } => file.sync_data().map_err(Into::into),
+            RawFile::Disk { file, .. } => file.get_ref()?.sync_data().map_err(Into::into),
+            RawFile::Faulty(f) => f.sync().and_then(|_| f.inner.sync_data()),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(..) => Ok(()),
+            RawFile::Storage(f) => f.storage.sync().map_err(Into::into),
         }
     }
 
-    pub fn metadata(&self) -> Result<Metadata> {
+    pub fn metadata(&self) -> Result<FileMetadata> {
         match self {
-            RawFile::InMemory(..) => todo!(),
-            RawFile::Disk { file, .. } => file.metadata().map_err(Into::into),
+            RawFile::InMemory(f) => Ok(FileMetadata {
+                len: f.get_bytes().len() as u64,
+                created: None,
+                modified: None,
+                readonly: None,
+                mode: None,
+            }),
+            RawFile::Disk { file, .. } => Ok(file.get_ref()?.metadata()?.into()),
+            RawFile::Faulty(f) => f.inner.metadata(),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => Ok(f.file.metadata()?.into()),
+            RawFile::Storage(f) => Ok(FileMetadata {
+                len: f.storage.len()?,
+                created: None,
+                modified: None,
+                readonly: None,
+                mode: None,
+            }),
         }
     }
 
@@ -107,6 +279,108 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
                     file: f,
                 })
             }
+            RawFile::Faulty(f) => Ok(RawFile::Faulty(Box::new(FaultyFile {
+                inner: f.inner.try_clone()?,
+                faults: f.faults.clone(),
+                calls: Mutex::new(f.calls.lock().clone()),
+                position: f.position,
+            }))),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => Ok(RawFile::Mmap(f.try_clone()?)),
+            RawFile::Storage(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "custom storage can't be cloned").into()),
+        }
+    }
+
+    /// `length` bytes at `offset`, borrowed from the file's buffer when it's in memory or memory
+    /// mapped. `None` for other files, and when the bytes aren't all in the file.
+    pub(crate) fn slice_at(&self, offset: u64, length: u64) -> Option<MaybeRef<'_, [u8]>> {
+        let range = usize::try_from(offset).ok()?..usize::try_from(offset.checked_add(length)?).ok()?;
+        match self {
+            RawFile::InMemory(f) => match f.get_bytes() {
+                MaybeRef::Regular(bytes) => bytes.get(range).map(MaybeRef::Regular),
+                MaybeRef::Ref(bytes) => MappedRwLockReadGuard::try_map(bytes, |bytes| bytes.get(range)).ok().map(MaybeRef::Ref),
+            },
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.as_bytes().get(range).map(MaybeRef::Regular),
+            RawFile::Disk { .. } | RawFile::Faulty(_) | RawFile::Storage(_) => None,
+        }
+    }
+
+    /// Read bytes at `offset` into `buf` without using the cursor, returning how many were read.
+    /// Files on disk are read with `pread` on unix and `seek_read` on windows, so threads can
+    /// read through shared references at the same time. Returns 0 only at the end of the file
+    /// or when `buf` is empty.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let copy = |bytes: &[u8], buf: &mut [u8]| {
+            let start = usize::try_from(offset).unwrap_or(usize::MAX).min(bytes.len());
+            let len = buf.len().min(bytes.len() - start);
+            buf[..len].copy_from_slice(&bytes[start..start + len]);
+            len
+        };
+        match self {
+            RawFile::InMemory(f) => Ok(copy(&f.get_bytes(), buf)),
+            RawFile::Disk { file, .. } => Ok(pread(file.get_ref()?, offset, buf)?),
+            RawFile::Faulty(f) => f.read_at(offset, buf),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => Ok(copy(f.as_bytes(), buf)),
+            RawFile::Storage(f) => Ok(f.storage.read_at(offset, buf)?),
+        }
+    }
+
+    /// Write bytes from `buf` at `offset` without moving the cursor, returning how many were
+    /// written. Files on disk are written with `pwrite` on unix and `seek_write` on windows.
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        match self {
+            RawFile::InMemory(f) => {
+                let position = f.current_offset();
+                f.seek(SeekFrom::Start(offset))?;
+                let written = f.write(buf);
+                f.seek(SeekFrom::Start(position))?;
+                Ok(written?)
+            }
+            RawFile::Disk { file, .. } => Ok(pwrite(file.get_mut()?, offset, buf)?),
+            RawFile::Faulty(f) => f.write_at(offset, buf),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(_) => Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "memory mapped files are read-only").into()),
+            RawFile::Storage(f) => {
+                f.window.clear();
+                Ok(f.storage.write_at(offset, buf)?)
+            }
+        }
+    }
+
+    /// Write all of `buf` at `offset` without moving the cursor, see [`write_at`](Self::write_at).
+    pub fn write_all_at(&mut self, mut offset: u64, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write_at(offset, buf) {
+                Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(crate::PackError::Io(e)) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read exactly `buf.len()` bytes at `offset`, without needing exclusive access to the file.
+    pub fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        match self {
+            RawFile::InMemory(f) => {
+                let bytes = f.get_bytes();
+                let src = usize::try_from(offset).ok()
+                    .and_then(|start| bytes.get(start..start.checked_add(buf.len())?))
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+                buf.copy_from_slice(src);
+                Ok(())
+            }
+            RawFile::Disk { file, .. } => read_file_at(file.get_ref()?, offset, buf),
+            RawFile::Faulty(f) => f.read_exact_at(offset, buf),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.read_exact_at(offset, buf),
+            RawFile::Storage(f) => f.read_exact_at(offset, buf),
         }
     }
 
@@ -116,7 +390,77 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
                 f.set_len(size)?;
                 Ok(())
             }
-            RawFile::Disk { file, .. } => file.set_len(size).map_err(Into::into),
+            RawFile::Disk { file, .. } => file.get_ref()?.set_len(size).map_err(Into::into),
+            RawFile::Faulty(f) => f.set_len(size),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(_) => Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "memory mapped files are read-only").into()),
+            RawFile::Storage(f) => {
+                f.window.clear();
+                f.storage.set_len(size).map_err(Into::into)
+            }
+        }
+    }
+
+    /// Tell the operating system how a region of the file is going to be accessed.
+    /// Only has an effect on files on disk.
+    pub fn advise(&self, offset: u64, length: u64, advice: Advice) -> Result<()> {
+        match self {
+            RawFile::InMemory(..) | RawFile::Storage(_) => Ok(()),
+            RawFile::Disk { file, .. } => advice::fadvise(file.get_ref()?, offset, length, advice),
+            RawFile::Faulty(f) => f.inner.advise(offset, length, advice),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.advise(offset, length, advice),
+        }
+    }
+
+    /// Make a region of the file read as zeros. Files on disk get a hole there which takes no
+    /// space where the platform and file system support it, otherwise zeros are written.
+    /// The length of the file stays the same, also when the region ends after it.
+    pub fn punch_hole(&mut self, offset: u64, length: u64) -> Result<()> {
+        if let RawFile::Disk { file, .. } = self {
+            if sparse::punch_hole(file.get_mut()?, offset, length)? {
+                return Ok(());
+            }
+        }
+        if let RawFile::Faulty(f) = self {
+            return f.inner.punch_hole(offset, length);
+        }
+
+        let len = self.metadata()?.len;
+        let end = offset.saturating_add(length).min(len);
+        let zeros = [0; 4096];
+        let mut position = offset;
+        while position < end {
+            let n = (end - position).min(zeros.len() as u64) as usize;
+            self.write_all_at(position, &zeros[..n])?;
+            position += n as u64;
+        }
+        Ok(())
+    }
+
+    /// Reserve space for a region of the file up front, growing the file when the region ends
+    /// after it. Only files on disk on linux get space reserved, others only grow.
+    pub fn allocate(&mut self, offset: u64, length: u64) -> Result<()> {
+        match self {
+            RawFile::Disk { file, .. } => sparse::allocate(file.get_mut()?, offset, length).map_err(Into::into),
+            RawFile::Faulty(f) => f.inner.allocate(offset, length),
+            _ => {
+                let end = offset.saturating_add(length);
+                if self.metadata()?.len < end {
+                    self.set_len(end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The holes in the file as offsets and lengths, regions which read as zeros and take no
+    /// space on disk. Empty for files in memory, and where the platform can't tell.
+    pub fn holes(&self) -> Result<Vec<(u64, u64)>> {
+        match self {
+            RawFile::Disk { file, .. } => Ok(sparse::holes(file.get_ref()?)?),
+            RawFile::Faulty(f) => f.inner.holes(),
+            _ => Ok(Vec::new()),
         }
     }
 
@@ -124,6 +468,10 @@ impl<'f, 'backpack> RawFile<'f, 'backpack> {
         match self {
             RawFile::InMemory(f, ..) => f.name(),
             RawFile::Disk { name,  .. } => name.as_deref(),
+            RawFile::Faulty(f) => f.inner.name(),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.name.as_deref(),
+            RawFile::Storage(f) => f.name(),
         }
     }
 }
@@ -134,7 +482,22 @@ impl Write for RawFile<'_, '_> {
             RawFile::Disk { file, .. } => {
                 file.write(buf)
             }
-            RawFile::InMemory(f, ..) => f.write(buf)
+            RawFile::InMemory(f, ..) => f.write(buf),
+            RawFile::Faulty(f) => f.write(buf),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.write(buf),
+            RawFile::Storage(f) => f.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        match self {
+            RawFile::Disk { file, .. } => file.write_vectored(bufs),
+            RawFile::InMemory(f, ..) => f.write_vectored(bufs),
+            RawFile::Faulty(f) => f.write_vectored(bufs),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.write_vectored(bufs),
+            RawFile::Storage(f) => f.write_vectored(bufs),
         }
     }
 
@@ -143,7 +506,11 @@ impl Write for RawFile<'_, '_> {
             RawFile::Disk { file, .. } => {
                 file.flush()
             }
-            RawFile::InMemory(f, ..) => f.flush()
+            RawFile::InMemory(f, ..) => f.flush(),
+            RawFile::Faulty(f) => f.flush(),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.flush(),
+            RawFile::Storage(f) => f.flush(),
         }
     }
 }
@@ -154,7 +521,47 @@ impl Read for RawFile<'_, '_> {
             RawFile::Disk { file, .. } => {
                 file.read(buf)
             }
-            RawFile::InMemory(f, ..) => f.read(buf)
+            RawFile::InMemory(f, ..) => f.read(buf),
+            RawFile::Faulty(f) => f.read(buf),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.read(buf),
+            RawFile::Storage(f) => f.read(buf),
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        match self {
+            RawFile::Disk { file, .. } => file.read_vectored(bufs),
+            RawFile::InMemory(f, ..) => f.read_vectored(bufs),
+            RawFile::Faulty(f) => f.read_vectored(bufs),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.read_vectored(bufs),
+            RawFile::Storage(f) => f.read_vectored(bufs),
+        }
+    }
+}
+
+/// Files on disk and in a [`Storage`] read ahead, the others are in memory already.
+impl BufRead for RawFile<'_, '_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            RawFile::Disk { file, .. } => file.fill_buf(),
+            RawFile::InMemory(f, ..) => f.fill_buf(),
+            RawFile::Faulty(f) => f.fill_buf(),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.fill_buf(),
+            RawFile::Storage(f) => f.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            RawFile::Disk { file, .. } => file.consume(amt),
+            RawFile::InMemory(f, ..) => f.consume(amt),
+            RawFile::Faulty(f) => f.consume(amt),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.consume(amt),
+            RawFile::Storage(f) => f.consume(amt),
         }
     }
 }
@@ -164,6 +571,10 @@ impl Seek for RawFile<'_, '_> {
         match self {
             RawFile::Disk { file, .. } => file.seek(pos),
             RawFile::InMemory(f, ..) => f.seek(pos),
+            RawFile::Faulty(f) => f.seek(pos),
+            #[cfg(feature = "mmap")]
+            RawFile::Mmap(f) => f.seek(pos),
+            RawFile::Storage(f) => f.seek(pos),
         }
     }
 }
@@ -171,7 +582,7 @@ impl Seek for RawFile<'_, '_> {
 impl From<std::fs::File> for RawFile<'_, '_> {
     fn from(f: std::fs::File) -> Self {
         Self::Disk {
-            file: f,
+            file: BufferedFile::new(f),
             name: None,
         }
     }
@@ -200,3 +611,106 @@ impl From<Vec<u8>> for RawFile<'_, '_> {
         RawFile::InMemory(s.into())
     }
 }
+
+/// Create an anonymous file in `dir`, see [`RawFile::temp_in`].
+pub(crate) fn temp_file(dir: &Path) -> Result<std::fs::File> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    let mut options = std::fs::File::options();
+    options.read(true).write(true).create_new(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_FLAG_DELETE_ON_CLOSE, files can't be removed while they're open
+        options.custom_flags(0x0400_0000);
+    }
+
+    loop {
+        let path = dir.join(format!(".backpack-{}-{}-{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed), nanos));
+        match options.open(&path) {
+            Ok(file) => {
+                #[cfg(not(windows))]
+                std::fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Read from `file` at `offset` without using its cursor where the platform allows,
+/// so reads from different threads through shared references don't get in each other's way.
+#[cfg(unix)]
+fn read_file_at(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_file_at(file: &std::fs::File, mut offset: u64, mut buf: &mut [u8]) -> Result<()> {
+    // moves the cursor, but everything else which uses the cursor seeks first
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn pread(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn pread(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn pread(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+#[cfg(unix)]
+fn pwrite(file: &mut std::fs::File, offset: u64, buf: &[u8]) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn pwrite(file: &mut std::fs::File, offset: u64, buf: &[u8]) -> std::io::Result<usize> {
+    // seek_write moves the cursor, which writes at the cursor rely on
+    let position = file.stream_position()?;
+    let written = std::os::windows::fs::FileExt::seek_write(file, buf, offset);
+    file.seek(SeekFrom::Start(position))?;
+    written
+}
+
+#[cfg(not(any(unix, windows)))]
+fn pwrite(file: &mut std::fs::File, offset: u64, buf: &[u8]) -> std::io::Result<usize> {
+    let position = file.stream_position()?;
+    file.seek(SeekFrom::Start(offset))?;
+    let written = file.write(buf);
+    file.seek(SeekFrom::Start(position))?;
+    written
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_file_at(file: &std::fs::File, offset: u64, buf: &mut [u8]) -> Result<()> {
+    // reads through a shared reference move the shared cursor, but everything
+    // else which uses the cursor seeks first
+    let mut file = file;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)?;
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, Request, FUSE_ROOT_ID};
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::{Vfs, VfsMetadata};
+
+/// how long the kernel may cache names and attributes
+const TTL: Duration = Duration::from_secs(1);
+
+/// Serve `vfs` as a read-only filesystem at `mountpoint`, an existing directory, so it can be
+/// browsed with `ls`, `cat` or a file manager. Blocks until the filesystem is unmounted,
+/// with `umount` or `fusermount -u`. Only on Linux.
+///
+/// Mounting directly needs root. Other users need `fusermount3` or `fusermount`, which
+/// come with fuse.
+pub fn mount(vfs: &dyn Vfs, mountpoint: impl AsRef<Path>) -> error::Result<()> {
+    let mountpoint = mountpoint.as_ref();
+    let options = [
+        MountOption::RO,
+        MountOption::NoSuid,
+        MountOption::NoDev,
+        MountOption::FSName("backpack".to_string()),
+        MountOption::Subtype("backpack".to_string()),
+    ];
+    fuser::mount2(Session::new(vfs), mountpoint, &options).at_path(mountpoint)
+}
+
+/// The errno a failed request is answered with.
+fn errno(e: &PackError) -> i32 {
+    match e {
+        PackError::FileNotFound(_) => libc::ENOENT,
+        PackError::UnsafePath(_) => libc::EACCES,
+        _ => libc::EIO,
+    }
+}
+
+/// The filesystem of a mounted [`Vfs`]. Requests are answered by the methods returning an
+/// errno on failure, the [`Filesystem`] implementation only passes on the answers.
+struct Session<'a> {
+    vfs: &'a dyn Vfs,
+    uid: u32,
+    gid: u32,
+    /// path of every inode, inode `i` at `i - 1`. Inodes are never forgotten, so a path
+    /// keeps its inode for as long as it's mounted
+    paths: Vec<String>,
+    inodes: HashMap<String, u64>,
+    /// contents of open files by handle
+    open: HashMap<u64, Vec<u8>>,
+    next_handle: u64,
+}
+
+impl<'a> Session<'a> {
+    fn new(vfs: &'a dyn Vfs) -> Self {
+        Self {
+            vfs,
+            // Safety: getuid and getgid can't fail
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            paths: vec![String::new()],
+            inodes: HashMap::from([(String::new(), FUSE_ROOT_ID)]),
+            open: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    fn path(&self, inode: u64) -> Result<&str, i32> {
+        let index = inode.checked_sub(1).ok_or(libc::ENOENT)?;
+        self.paths.get(index as usize).map(String::as_str).ok_or(libc::ENOENT)
+    }
+
+    /// The inode of `path`, a new one the first time it's seen.
+    fn inode(&mut self, path: &str) -> u64 {
+        if let Some(inode) = self.inodes.get(path) {
+            return *inode;
+        }
+        self.paths.push(path.to_string());
+        let inode = self.paths.len() as u64;
+        self.inodes.insert(path.to_string(), inode);
+        inode
+    }
+
+    fn metadata(&self, path: &str) -> Result<VfsMetadata, i32> {
+        self.vfs.metadata(Path::new(path)).map_err(|e| errno(&e))
+    }
+
+    /// The path of `name` in the directory `parent`.
+    fn child(parent: &str, name: &str) -> String {
+        match parent {
+            "" => name.to_string(),
+            parent => format!("{}/{}", parent, name),
+        }
+    }
+
+    fn attr(&self, inode: u64, metadata: &VfsMetadata) -> FileAttr {
+        let modified = metadata.modified.unwrap_or(UNIX_EPOCH);
+        let (kind, perm, nlink) = match metadata.is_dir {
+            true => (FileType::Directory, 0o555, 2),
+            false => (FileType::RegularFile, 0o444, 1),
+        };
+        FileAttr {
+            ino: inode,
+            size: metadata.len,
+            blocks: metadata.len.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: modified,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+
+    fn lookup_child(&mut self, parent: u64, name: &OsStr) -> Result<FileAttr, i32> {
+        let name = name.to_str().ok_or(libc::ENOENT)?;
+        let path = Self::child(self.path(parent)?, name);
+        let metadata = self.metadata(&path)?;
+        let inode = self.inode(&path);
+        Ok(self.attr(inode, &metadata))
+    }
+
+    fn attr_of(&self, inode: u64) -> Result<FileAttr, i32> {
+        Ok(self.attr(inode, &self.metadata(self.path(inode)?)?))
+    }
+
+    /// Read the file `inode` into memory, returning its handle.
+    fn open_file(&mut self, inode: u64, flags: i32) -> Result<u64, i32> {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return Err(libc::EROFS);
+        }
+        let path = self.path(inode)?;
+        if self.metadata(path)?.is_dir {
+            return Err(libc::EISDIR);
+        }
+        let contents = self.vfs.read(Path::new(path)).map_err(|e| errno(&e))?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.open.insert(handle, contents);
+        Ok(handle)
+    }
+
+    fn read_file(&self, handle: u64, offset: i64, size: u32) -> Result<&[u8], i32> {
+        let contents = self.open.get(&handle).ok_or(libc::EBADF)?;
+        let start = usize::try_from(offset).map_err(|_| libc::EINVAL)?.min(contents.len());
+        let end = start.saturating_add(size as usize).min(contents.len());
+        Ok(&contents[start..end])
+    }
+
+    /// The entries of the directory `inode`, `.` and `..` first.
+    fn dir_entries(&mut self, inode: u64) -> Result<Vec<(u64, FileType, String)>, i32> {
+        let path = self.path(inode)?.to_string();
+        if !self.metadata(&path)?.is_dir {
+            return Err(libc::ENOTDIR);
+        }
+        let names = self.vfs.read_dir(Path::new(&path)).map_err(|e| errno(&e))?;
+
+        let parent = match path.rsplit_once('/') {
+            Some((parent, _)) => self.inode(parent),
+            None => FUSE_ROOT_ID,
+        };
+        let mut entries = vec![(inode, FileType::Directory, ".".to_string()), (parent, FileType::Directory, "..".to_string())];
+        for name in names {
+            let (name, kind) = match name.strip_suffix('/') {
+                Some(dir) => (dir.to_string(), FileType::Directory),
+                None => (name, FileType::RegularFile),
+            };
+            entries.push((self.inode(&Self::child(&path, &name)), kind, name));
+        }
+        Ok(entries)
+    }
+}
+
+impl Filesystem for Session<'_> {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.lookup_child(parent, name) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, inode: u64, _handle: Option<u64>, reply: ReplyAttr) {
+        match self.attr_of(inode) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn access(&mut self, _req: &Request<'_>, _inode: u64, mask: i32, reply: ReplyEmpty) {
+        match mask & libc::W_OK {
+            0 => reply.ok(),
+            _ => reply.error(libc::EROFS),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, inode: u64, flags: i32, reply: ReplyOpen) {
+        match self.open_file(inode, flags) {
+            Ok(handle) => reply.opened(handle, 0),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn read(&mut self, _req: &Request<'_>, _inode: u64, handle: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
+        match self.read_file(handle, offset, size) {
+            Ok(data) => reply.data(data),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn release(&mut self, _req: &Request<'_>, _inode: u64, handle: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: ReplyEmpty) {
+        self.open.remove(&handle);
+        reply.ok();
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, inode: u64, _flags: i32, reply: ReplyOpen) {
+        match self.attr_of(inode) {
+            Ok(attr) if attr.kind == FileType::Directory => reply.opened(0, 0),
+            Ok(_) => reply.error(libc::ENOTDIR),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, inode: u64, _handle: u64, offset: i64, mut reply: ReplyDirectory) {
+        let entries = match self.dir_entries(inode) {
+            Ok(entries) => entries,
+            Err(e) => return reply.error(e),
+        };
+        for (i, (inode, kind, name)) in entries.into_iter().enumerate().skip(offset.max(0) as usize) {
+            // the offset is where the next entry is
+            if reply.add(inode, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use fuser::{FileType, FUSE_ROOT_ID};
+    use crate::pack::fuse::Session;
+    use crate::{BackPack, InMemoryFile, RawFile};
+
+    #[test]
+    fn test_session() {
+        let bp = BackPack::create(RawFile::in_memory("test.bp")).unwrap();
+        bp.add_file(InMemoryFile::from("hello").with_name("a.txt")).unwrap();
+        bp.add_file(InMemoryFile::from("wall").with_name("textures/wall.png")).unwrap();
+        let vfs = &bp;
+        let mut session = Session::new(&vfs);
+
+        let root = session.dir_entries(FUSE_ROOT_ID).unwrap();
+        let names = root.iter().map(|(_, kind, name)| (*kind, name.as_str())).collect::<Vec<_>>();
+        assert_eq!(names, [(FileType::Directory, "."), (FileType::Directory, ".."), (FileType::RegularFile, "a.txt"), (FileType::Directory, "textures")]);
+
+        let textures = session.lookup_child(FUSE_ROOT_ID, OsStr::new("textures")).unwrap();
+        assert_eq!(textures.kind, FileType::Directory);
+        let wall = session.lookup_child(textures.ino, OsStr::new("wall.png")).unwrap();
+        assert_eq!((wall.kind, wall.size, wall.perm), (FileType::RegularFile, 4, 0o444));
+        assert_eq!(session.attr_of(wall.ino).unwrap(), wall);
+        assert_eq!(session.lookup_child(FUSE_ROOT_ID, OsStr::new("missing")), Err(libc::ENOENT));
+        assert_eq!(session.dir_entries(wall.ino).unwrap_err(), libc::ENOTDIR);
+        assert_eq!(session.dir_entries(textures.ino).unwrap()[1].0, FUSE_ROOT_ID);
+
+        let handle = session.open_file(wall.ino, libc::O_RDONLY).unwrap();
+        assert_eq!(session.read_file(handle, 0, 100).unwrap(), b"wall");
+        assert_eq!(session.read_file(handle, 2, 1).unwrap(), b"l");
+        assert_eq!(session.read_file(handle, 10, 1).unwrap(), b"");
+        assert_eq!(session.open_file(wall.ino, libc::O_RDWR), Err(libc::EROFS));
+        assert_eq!(session.open_file(textures.ino, libc::O_RDONLY), Err(libc::EISDIR));
+        assert_eq!(session.read_file(handle + 1, 0, 1), Err(libc::EBADF));
+    }
+}
@@ -0,0 +1,92 @@
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use parking_lot::{Mutex, MutexGuard};
+
+/// A file's identifier and where a handle to it was opened.
+type Opened = ((u64, u64), Backtrace);
+
+/// Keeps count of the handles to files in a backpack which are still open.
+/// With leak detection on, it also remembers where every handle was opened.
+#[derive(Default)]
+pub struct Handles {
+    open: AtomicUsize,
+    next_id: AtomicU64,
+    /// number of open handles per file identifier
+    per_file: Mutex<HashMap<(u64, u64), usize>>,
+
+    leak_detection: AtomicBool,
+    /// identifier of the file and where the handle was opened, per handle id.
+    /// Only filled with leak detection on.
+    opened: Mutex<HashMap<u64, Opened>>,
+}
+
+impl Handles {
+    pub(crate) fn set_leak_detection(&self, enabled: bool) {
+        self.leak_detection.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.opened.lock().clear();
+        }
+    }
+
+    /// Register a new handle to the file `identifier`, which is closed when it's dropped.
+    pub(crate) fn open(self: &Arc<Self>, identifier: (u64, u64)) -> Handle {
+        self.open.fetch_add(1, Ordering::SeqCst);
+        *self.per_file.lock().entry(identifier).or_default() += 1;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        if self.leak_detection.load(Ordering::SeqCst) {
+            self.opened.lock().insert(id, (identifier, Backtrace::force_capture()));
+        }
+
+        Handle { handles: self.clone(), id, identifier }
+    }
+
+    fn close(&self, id: u64, identifier: (u64, u64)) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+        self.opened.lock().remove(&id);
+
+        let mut per_file = self.per_file.lock();
+        if let Some(count) = per_file.get_mut(&identifier) {
+            *count -= 1;
+            if *count == 0 {
+                per_file.remove(&identifier);
+            }
+        }
+    }
+
+    /// The files with open handles. No handles are opened or closed while the guard is held.
+    pub(crate) fn open_files(&self) -> MutexGuard<'_, HashMap<(u64, u64), usize>> {
+        self.per_file.lock()
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.open.load(Ordering::SeqCst)
+    }
+
+    /// The identifiers of files with open handles, and where those handles were opened.
+    pub(crate) fn leaked(&self) -> Vec<((u64, u64), String)> {
+        let mut res = self.opened.lock()
+            .iter()
+            .map(|(id, (identifier, backtrace))| (*id, *identifier, backtrace.to_string()))
+            .collect::<Vec<_>>();
+        res.sort_by_key(|(id, _, _)| *id);
+
+        res.into_iter().map(|(_, identifier, backtrace)| (identifier, backtrace)).collect()
+    }
+}
+
+/// An open handle to a file in a backpack. It doesn't borrow the backpack, so a
+/// backpack can be closed while handles to its files are still around.
+pub(crate) struct Handle {
+    handles: Arc<Handles>,
+    id: u64,
+    identifier: (u64, u64),
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.handles.close(self.id, self.identifier);
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::error;
+use crate::error::PackError;
+use crate::pack::crc32::Crc32;
+use crate::pack::RawFile;
+
+/// A hash function of bytes which arrive in parts, see [`HashingFile`]. Implemented for
+/// [`Crc32`], and with the `sha2` feature for every hash implementing [`sha2::Digest`],
+/// like SHA-256 or BLAKE3 with its `traits-preview` feature.
+pub trait StreamHasher {
+    type Output;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn finalize(self) -> Self::Output;
+}
+
+impl StreamHasher for Crc32 {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        Crc32::update(self, data)
+    }
+
+    fn finalize(self) -> u32 {
+        self.finish()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl<D: sha2::Digest> StreamHasher for D {
+    type Output = sha2::digest::Output<D>;
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data)
+    }
+
+    fn finalize(self) -> Self::Output {
+        sha2::Digest::finalize(self)
+    }
+}
+
+/// Hashes everything written to or read from a file as it passes through, so a backpack and
+/// its published checksum come out of a single pass. The hash is of the file from its start:
+/// bytes read or written again aren't hashed twice, but writing over bytes which were hashed
+/// or skipping ahead makes the hash [unusable](Self::finalize). [Freezing](crate::BackPack::freeze)
+/// a backpack or reading one from start to end is fine, a backpack changed in place isn't.
+pub struct HashingFile<'f, 'backpack, H> {
+    inner: RawFile<'f, 'backpack>,
+    hasher: H,
+    position: u64,
+    /// how much of the file from its start is hashed
+    hashed: u64,
+    /// whether bytes were skipped or changed after they were hashed
+    broken: bool,
+}
+
+impl<'f, 'backpack, H: StreamHasher + Default> HashingFile<'f, 'backpack, H> {
+    pub fn new(inner: RawFile<'f, 'backpack>) -> error::Result<Self> {
+        Self::with_hasher(inner, H::default())
+    }
+}
+
+impl<'f, 'backpack, H: StreamHasher> HashingFile<'f, 'backpack, H> {
+    /// Hash with `hasher`, starting where the cursor of `inner` is.
+    pub fn with_hasher(mut inner: RawFile<'f, 'backpack>, hasher: H) -> error::Result<Self> {
+        let position = inner.current_offset()?;
+        Ok(Self {
+            inner,
+            hasher,
+            position,
+            hashed: 0,
+            broken: false,
+        })
+    }
+
+    /// How many bytes from the start of the file are hashed.
+    pub fn hashed_len(&self) -> u64 {
+        self.hashed
+    }
+
+    pub fn get_ref(&self) -> &RawFile<'f, 'backpack> {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> RawFile<'f, 'backpack> {
+        self.inner
+    }
+
+    /// The hash of the first [`hashed_len`](Self::hashed_len) bytes of the file. Fails with
+    /// [`PackError::Unhashed`] when bytes were skipped, or written over after they were hashed.
+    pub fn finalize(self) -> error::Result<H::Output> {
+        if self.broken {
+            return Err(PackError::Unhashed);
+        }
+        Ok(self.hasher.finalize())
+    }
+
+    /// Hash what's new of the `data` which was read or written at the cursor, and move past it.
+    fn pass(&mut self, data: &[u8], written: bool) {
+        let end = self.position + data.len() as u64;
+        if self.position > self.hashed {
+            self.broken |= !data.is_empty();
+        } else if end > self.hashed {
+            self.broken |= written && self.position < self.hashed;
+            self.hasher.update(&data[(self.hashed - self.position) as usize..]);
+            self.hashed = end;
+        } else {
+            self.broken |= written && !data.is_empty();
+        }
+        self.position = end;
+    }
+}
+
+impl<H: StreamHasher> Write for HashingFile<'_, '_, H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pass(&buf[..n], true);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<H: StreamHasher> Read for HashingFile<'_, '_, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pass(&buf[..n], false);
+        Ok(n)
+    }
+}
+
+impl<H: StreamHasher> Seek for HashingFile<'_, '_, H> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use crate::pack::crc32::{crc32, Crc32};
+    use crate::pack::hashing::HashingFile;
+    use crate::pack::{PackError, RawFile};
+
+    #[test]
+    fn test_hashing_file() {
+        let mut file = HashingFile::<Crc32>::new(RawFile::in_memory("test")).unwrap();
+        file.write_all(b"hello ").unwrap();
+        file.write_all(b"world").unwrap();
+        assert_eq!(file.hashed_len(), 11);
+        assert_eq!(file.finalize().unwrap(), crc32(b"hello world"));
+
+        // reading again doesn't hash again
+        let mut file = HashingFile::<Crc32>::new(RawFile::from(b"hello world".to_vec())).unwrap();
+        let mut buf = [0; 5];
+        file.read_exact(&mut buf).unwrap();
+        file.seek(SeekFrom::Start(2)).unwrap();
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(file.finalize().unwrap(), crc32(b"hello world"));
+
+        let mut file = HashingFile::<Crc32>::new(RawFile::in_memory("test")).unwrap();
+        file.write_all(b"hello world").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"j").unwrap();
+        assert!(matches!(file.finalize(), Err(PackError::Unhashed)));
+
+        let mut file = HashingFile::<Crc32>::new(RawFile::from(b"hello world".to_vec())).unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert!(matches!(file.finalize(), Err(PackError::Unhashed)));
+    }
+}
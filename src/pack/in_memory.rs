@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
-use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
 use parking_lot::RwLockReadGuard;
 use crate::error;
 use crate::pack::maybe_ref::MaybeRef;
@@ -9,7 +10,7 @@ impl InMemoryFile<'_, '_> {
     pub fn new(name: impl AsRef<Path>) -> Self {
         Self::Named {
             name: name.as_ref().to_path_buf(),
-            data: Cursor::new(vec![]),
+            data: Cursor::default(),
         }
     }
 
@@ -43,7 +44,7 @@ impl InMemoryFile<'_, '_> {
         match self {
             InMemoryFile::Named { data, .. } |
             InMemoryFile::Unnamed { data } => {
-                data.get_mut().resize(size as usize, 0);
+                data.get_mut().make_mut().resize(size as usize, 0);
                 Ok(())
             }
             InMemoryFile::Packed { data, ..} => {
@@ -53,11 +54,11 @@ impl InMemoryFile<'_, '_> {
         }
     }
 
-    pub fn get_bytes(&self) -> MaybeRef<[u8]> {
+    pub fn get_bytes(&self) -> MaybeRef<'_, [u8]> {
         match self {
-            InMemoryFile::Named { data, .. } => data.get_ref().as_slice().into(),
+            InMemoryFile::Named { data, .. } => data.get_ref().as_ref().into(),
             InMemoryFile::Packed { data, .. } => RwLockReadGuard::map(data.get_bytes().read(), |i| i.as_slice()).into(),
-            InMemoryFile::Unnamed { data, .. } => data.get_ref().as_slice().into(),
+            InMemoryFile::Unnamed { data, .. } => data.get_ref().as_ref().into(),
         }
     }
 
@@ -85,15 +86,28 @@ impl InMemoryFile<'_, '_> {
     }
 
     pub fn try_clone(&self) -> error::Result<Self> {
+        Ok(self.clone())
+    }
+
+    /// A copy of the file as it is now, reading from the start. The copy shares the contents
+    /// until either of them is written to, so taking one is cheap however large the file is.
+    pub fn snapshot(&self) -> Self {
+        let mut res = self.clone();
+        match &mut res {
+            InMemoryFile::Named { data, .. } |
+            InMemoryFile::Unnamed { data } => data.set_position(0),
+            InMemoryFile::Packed { data, .. } => data.set_position(0),
+        }
+        res
+    }
+
+    /// Whether the contents are shared with a [snapshot](Self::snapshot) or clone, so the
+    /// next write copies them. Files of a backpack are always shared with the backpack.
+    pub fn is_shared(&self) -> bool {
         match self {
-            InMemoryFile::Named { .. } => todo!(),
-            InMemoryFile::Packed { data, name } => {
-                Ok(InMemoryFile::Packed {
-                    name: name.clone(),
-                    data: data.clone(),
-                })
-            }
-            InMemoryFile::Unnamed { .. } => todo!(),
+            InMemoryFile::Named { data, .. } |
+            InMemoryFile::Unnamed { data } => data.get_ref().is_shared(),
+            InMemoryFile::Packed { .. } => true,
         }
     }
 }
@@ -101,29 +115,82 @@ impl InMemoryFile<'_, '_> {
 pub enum InMemoryFile<'f, 'backpack> {
     Named {
         name: PathBuf,
-        data: Cursor<Vec<u8>>,
+        data: Cursor<SharedBytes>,
     },
     Packed {
         name: PathBuf,
         data: PackSlice<'f, 'backpack>,
     },
     Unnamed {
-        data: Cursor<Vec<u8>>,
+        data: Cursor<SharedBytes>,
     },
 }
 
+/// Clones share the contents, see [`InMemoryFile::snapshot`].
+impl Clone for InMemoryFile<'_, '_> {
+    fn clone(&self) -> Self {
+        match self {
+            InMemoryFile::Named { name, data } => InMemoryFile::Named { name: name.clone(), data: data.clone() },
+            InMemoryFile::Packed { name, data } => InMemoryFile::Packed { name: name.clone(), data: data.clone() },
+            InMemoryFile::Unnamed { data } => InMemoryFile::Unnamed { data: data.clone() },
+        }
+    }
+}
+
+/// The contents of an [`InMemoryFile`], shared between copies of it until one of them
+/// changes them. Changing them then copies them first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SharedBytes(Arc<Vec<u8>>);
+
+impl SharedBytes {
+    /// The contents to change, copied first when they're shared.
+    pub fn make_mut(&mut self) -> &mut Vec<u8> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+
+    /// The contents, copied when they're shared.
+    pub fn into_vec(self) -> Vec<u8> {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| shared.as_ref().clone())
+    }
+}
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SharedBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Arc::new(bytes))
+    }
+}
+
+/// Write to the contents in `data` with `write`, copying them first when they're shared.
+fn write_shared(data: &mut Cursor<SharedBytes>, write: impl FnOnce(&mut Cursor<&mut Vec<u8>>) -> std::io::Result<usize>) -> std::io::Result<usize> {
+    let position = data.position();
+    let mut cursor = Cursor::new(data.get_mut().make_mut());
+    cursor.set_position(position);
+    let res = write(&mut cursor);
+    let position = cursor.position();
+    data.set_position(position);
+    res
+}
+
 impl From<String> for InMemoryFile<'_, '_> {
     fn from(s: String) -> Self {
-        Self::Unnamed {
-            data: Cursor::new(s.into_bytes())
-        }
+        s.into_bytes().into()
     }
 }
 
 impl From<Vec<u8>> for InMemoryFile<'_, '_> {
     fn from(data: Vec<u8>) -> Self {
         Self::Unnamed {
-            data: Cursor::new(data)
+            data: Cursor::new(data.into())
         }
     }
 }
@@ -144,11 +211,39 @@ impl Read for InMemoryFile<'_, '_> {
     }
 }
 
+impl BufRead for InMemoryFile<'_, '_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            InMemoryFile::Named { data, .. } |
+            InMemoryFile::Unnamed { data } => data.fill_buf(),
+            InMemoryFile::Packed { data, .. } => data.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            InMemoryFile::Named { data, .. } |
+            InMemoryFile::Unnamed { data } => data.consume(amt),
+            InMemoryFile::Packed { data, .. } => data.consume(amt),
+        }
+    }
+}
+
 impl Write for InMemoryFile<'_, '_> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
             InMemoryFile::Named { data, .. } |
-            InMemoryFile::Unnamed { data } => data.write(buf),
+            InMemoryFile::Unnamed { data } => write_shared(data, |cursor| cursor.write(buf)),
+            InMemoryFile::Packed { .. } => {
+                Err(std::io::Error::new(ErrorKind::PermissionDenied, "can't write to file backed by backpack"))
+            }
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+        match self {
+            InMemoryFile::Named { data, .. } |
+            InMemoryFile::Unnamed { data } => write_shared(data, |cursor| cursor.write_vectored(bufs)),
             InMemoryFile::Packed { .. } => {
                 Err(std::io::Error::new(ErrorKind::PermissionDenied, "can't write to file backed by backpack"))
             }
@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+use std::io::{self, Read, Seek, SeekFrom};
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::Index;
+use crate::pack::layout::PackHeader;
+use crate::pack::{BackPack, METADATA_ENTRIES, PACK_HEADER_SIZE, PACK_VERSION, TOC_SIZE};
+
+/// How much of a backpack is read at once when only its index is needed. The table of
+/// contents follows the header, so for most backpacks this is all of it.
+const INDEX_READ: u64 = PACK_HEADER_SIZE + 16 * TOC_SIZE as u64;
+
+/// Summary of a backpack, read from its index only, see [`BackPack::stat`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackInfo {
+    /// version of the format the backpack is written in
+    pub version: u16,
+    /// size of the whole backpack
+    pub file_size: u64,
+    /// bytes of stored contents, as compressed and encrypted
+    pub data_size: u64,
+    /// number of files, without [hidden](BackPack::set_hidden) files and directories
+    pub files: usize,
+    /// total size of those files when they're read
+    pub files_size: u64,
+    /// number of table of contents blocks
+    pub toc_blocks: usize,
+}
+
+/// A file in a backpack as its index describes it, see [`BackPack::list_fast`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub name: String,
+    /// size when it's read. For encrypted files which aren't compressed, the stored size.
+    pub size: u64,
+    /// size as stored in the backpack
+    pub stored_size: u64,
+    /// where the stored contents start in the backpack
+    pub offset: u64,
+    pub compressed: bool,
+    /// stored as is because compressing didn't save enough, see
+    /// [`BackPack::set_min_compression_ratio`]
+    pub stored_as_is: bool,
+    pub encrypted: bool,
+    /// what the offset is a multiple of, see [`PackReader::alignment`](crate::pack::PackReader::alignment)
+    pub alignment: u64,
+}
+
+/// Reads ahead in windows, so reading a table of contents takes as few reads as possible.
+struct ReadAhead<R> {
+    inner: R,
+    /// where `window` starts
+    start: u64,
+    window: Vec<u8>,
+    position: u64,
+}
+
+impl<R: Read + Seek> ReadAhead<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            start: 0,
+            window: Vec::new(),
+            position: 0,
+        }
+    }
+
+    fn fill(&mut self, len: u64) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(self.position))?;
+        self.window.clear();
+        (&mut self.inner).take(len).read_to_end(&mut self.window)?;
+        self.start = self.position;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for ReadAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let in_window = self.position >= self.start && self.position < self.start + self.window.len() as u64;
+        if !in_window {
+            let len = if self.position == 0 { INDEX_READ } else { TOC_SIZE as u64 };
+            self.fill(len.max(buf.len() as u64))?;
+        }
+
+        let at = (self.position - self.start) as usize;
+        let n = buf.len().min(self.window.len() - at);
+        buf[..n].copy_from_slice(&self.window[at..at + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for ReadAhead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?,
+            SeekFrom::End(_) => self.inner.seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
+
+/// Space usage of a backpack, read from its index only, see [`BackPack::stats`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackStats {
+    /// the files like [`BackPack::list_fast`] lists them, largest stored size first
+    pub entries: Vec<IndexEntry>,
+    /// size of the whole backpack
+    pub file_size: u64,
+    /// total size of the files when they're read
+    pub files_size: u64,
+    /// bytes the files take up in the backpack. Files with the same contents share them,
+    /// those are counted once.
+    pub stored_size: u64,
+    /// bytes taken up by the header and the table of contents
+    pub index_size: u64,
+    /// bytes taken up by hidden files, directories and the backpack's own metadata, like
+    /// modification times and attributes
+    pub metadata_size: u64,
+    /// bytes of the data section which no entry uses, left by removed and overwritten files
+    /// and by alignment padding. [Compacting](BackPack::compact) frees them.
+    pub dead_size: u64,
+}
+
+impl PackStats {
+    /// How many times smaller the files are stored than they're read, 1 when nothing is compressed.
+    pub fn compression_ratio(&self) -> f64 {
+        ratio(self.files_size, self.entries.iter().map(|entry| entry.stored_size).sum())
+    }
+
+    /// The fraction of the backpack no entry uses, between 0 and 1.
+    pub fn dead_ratio(&self) -> f64 {
+        if self.file_size == 0 {
+            0.0
+        } else {
+            self.dead_size as f64 / self.file_size as f64
+        }
+    }
+
+    /// The `n` files which take up the most space in the backpack.
+    pub fn largest(&self, n: usize) -> &[IndexEntry] {
+        &self.entries[..n.min(self.entries.len())]
+    }
+}
+
+impl IndexEntry {
+    /// How many times smaller the file is stored than it's read, 1 when it isn't compressed.
+    pub fn compression_ratio(&self) -> f64 {
+        ratio(self.size, self.stored_size)
+    }
+}
+
+fn ratio(size: u64, stored_size: u64) -> f64 {
+    if stored_size == 0 {
+        1.0
+    } else {
+        size as f64 / stored_size as f64
+    }
+}
+
+/// The header, table of contents and size of a backpack.
+struct RawIndex {
+    header: PackHeader,
+    index: Index,
+    /// sorted
+    toc_blocks: Vec<u64>,
+    file_size: u64,
+}
+
+impl RawIndex {
+    fn read(file: impl Read + Seek) -> error::Result<Self> {
+        let mut file = ReadAhead::new(file);
+        let file_size = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let header = PackHeader::read_from(&mut file)?;
+        let version = header.version();
+        if version != PACK_VERSION {
+            return Err(PackError::Incompatible(version));
+        }
+
+        let mut index = Index::default();
+        let mut toc_blocks = BackPack::read_toc_blocks(&mut file, header.first_toc.get(), |filled, block| {
+            BackPack::parse_toc_block(filled, block, &mut index)
+        })?;
+        toc_blocks.sort();
+        Ok(Self { header, index, toc_blocks, file_size })
+    }
+
+    /// Whether `name` is a file, and not a directory, hidden or metadata.
+    fn is_file(&self, name: &str) -> bool {
+        !name.ends_with('/') && !self.index.hidden.contains(name) && !METADATA_ENTRIES.contains(&name)
+    }
+
+    /// The files, sorted by name.
+    fn entries(&self) -> Vec<IndexEntry> {
+        let index = &self.index;
+        let mut entries = index.offsets.iter()
+            .filter(|(name, _)| self.is_file(name))
+            .map(|(name, (offset, length))| {
+                let compressed = index.compressed.get(name);
+                IndexEntry {
+                    name: name.clone(),
+                    size: compressed.map_or(*length, |(_, len)| *len),
+                    stored_size: *length,
+                    offset: BackPack::convert_offset(&self.toc_blocks, *offset),
+                    compressed: compressed.is_some(),
+                    stored_as_is: index.stored.contains(name),
+                    encrypted: index.encrypted.contains(name),
+                    alignment: index.alignments.get(name).copied().unwrap_or(1),
+                }
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+}
+
+/// Read the header and table of contents of a backpack, and nothing else.
+pub(crate) fn read_index(file: impl Read + Seek) -> error::Result<(PackInfo, Vec<IndexEntry>)> {
+    let raw = RawIndex::read(file)?;
+    let entries = raw.entries();
+    let info = PackInfo {
+        version: raw.header.version(),
+        file_size: raw.file_size,
+        data_size: raw.header.size.get(),
+        files: entries.len(),
+        files_size: entries.iter().map(|entry| entry.size).sum(),
+        toc_blocks: raw.toc_blocks.len(),
+    };
+    Ok((info, entries))
+}
+
+/// Work out where the space in a backpack goes from its index.
+pub(crate) fn read_stats(file: impl Read + Seek) -> error::Result<PackStats> {
+    let raw = RawIndex::read(file)?;
+    let mut entries = raw.entries();
+    entries.sort_by(|a, b| b.stored_size.cmp(&a.stored_size).then_with(|| a.name.cmp(&b.name)));
+
+    // entries with the same contents share them
+    let mut files = HashSet::new();
+    let mut others = HashSet::new();
+    for (name, key) in &raw.index.offsets {
+        if raw.is_file(name) {
+            files.insert(*key);
+        } else {
+            others.insert(*key);
+        }
+    }
+    let stored_size = files.iter().map(|(_, length)| length).sum();
+    let metadata_size = others.difference(&files).map(|(_, length)| length).sum();
+
+    // ranges which overlap without being the same are counted once too
+    let mut ranges = files.union(&others).filter(|(_, length)| *length != 0).copied().collect::<Vec<_>>();
+    ranges.sort();
+    let mut live = 0;
+    let mut covered_to = 0;
+    for (offset, length) in ranges {
+        let end = offset + length;
+        live += end.saturating_sub(offset.max(covered_to));
+        covered_to = covered_to.max(end);
+    }
+
+    Ok(PackStats {
+        file_size: raw.file_size,
+        files_size: entries.iter().map(|entry| entry.size).sum(),
+        stored_size,
+        index_size: PACK_HEADER_SIZE + raw.toc_blocks.len() as u64 * TOC_SIZE as u64,
+        metadata_size,
+        dead_size: raw.header.size.get().saturating_sub(live),
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use super::ReadAhead;
+
+    /// Counts the reads which reach the file.
+    struct Counting(Cursor<Vec<u8>>, usize);
+
+    impl Read for Counting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 += 1;
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for Counting {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let data = (0..=255).cycle().take(100_000).collect::<Vec<u8>>();
+        let mut file = ReadAhead::new(Counting(Cursor::new(data.clone()), 0));
+
+        let mut buf = [0; 100];
+        file.read_exact(&mut buf).unwrap();
+        file.seek(SeekFrom::Start(5000)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[5000..5100]);
+        let reads = file.inner.1;
+
+        file.seek(SeekFrom::Start(90_000)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[90_000..90_100]);
+        assert!(file.inner.1 > reads);
+        assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 100_000);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+    }
+}
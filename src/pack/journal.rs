@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::crc32::crc32;
+use crate::pack::RawFile;
+
+/// Starts every journal, followed by the format version.
+const JOURNAL_MAGIC: &[u8; 8] = b"BPJRNL\x00\x01";
+
+/// The journal of the backpack at `pack`, next to it: `pack` with `.journal` appended to its name.
+pub fn journal_path(pack: impl AsRef<Path>) -> PathBuf {
+    let mut path = pack.as_ref().as_os_str().to_owned();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+/// What [`recover`] found of an interrupted change to a backpack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// There was no journal, the last change completed.
+    Clean,
+    /// The journal was only partly written, so the backpack wasn't touched yet and keeps
+    /// the version from before the change. The journal was removed.
+    RolledBack,
+    /// The journal was complete, so the change was written to the backpack again.
+    Completed,
+}
+
+/// Writes to a backpack which are first stored in a journal next to it, so a crash halfway
+/// through writing them is finished or undone by [`recover`].
+///
+/// The journal holds every write and the final length of the backpack, followed by a checksum.
+/// Only once it's synced to disk is the backpack itself changed, and once the backpack is synced
+/// the journal is removed. A journal with a matching checksum was complete, and is replayed.
+/// One without was interrupted before the backpack changed, and is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    writes: Vec<(u64, Vec<u8>)>,
+    len: u64,
+}
+
+impl Journal {
+    /// Writes which leave the backpack `len` bytes long.
+    pub(crate) fn new(len: u64) -> Self {
+        Self {
+            writes: Vec::new(),
+            len,
+        }
+    }
+
+    pub(crate) fn write(&mut self, offset: u64, bytes: Vec<u8>) {
+        self.writes.push((offset, bytes));
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut res = JOURNAL_MAGIC.to_vec();
+        res.extend_from_slice(&self.len.to_le_bytes());
+        res.extend_from_slice(&(self.writes.len() as u64).to_le_bytes());
+        for (offset, bytes) in &self.writes {
+            res.extend_from_slice(&offset.to_le_bytes());
+            res.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            res.extend_from_slice(bytes);
+        }
+        let checksum = crc32(&res);
+        res.extend_from_slice(&checksum.to_le_bytes());
+        res
+    }
+
+    /// The journal in `bytes`, or `None` when it was cut off or damaged.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (body, checksum) = bytes.split_at_checked(bytes.len().checked_sub(4)?)?;
+        if crc32(body) != u32::from_le_bytes(checksum.try_into().ok()?) {
+            return None;
+        }
+
+        let mut rest = body.strip_prefix(JOURNAL_MAGIC.as_slice())?;
+        let mut next = |len: usize| {
+            let (field, tail) = rest.split_at_checked(len)?;
+            rest = tail;
+            Some(field)
+        };
+        let read_u64 = |field: &[u8]| u64::from_le_bytes(field.try_into().unwrap());
+
+        let mut res = Self::new(read_u64(next(8)?));
+        for _ in 0..read_u64(next(8)?) {
+            let offset = read_u64(next(8)?);
+            let len = usize::try_from(read_u64(next(8)?)).ok()?;
+            res.write(offset, next(len)?.to_vec());
+        }
+        Some(res)
+    }
+
+    /// Store the journal next to the backpack in `file`, then make the writes to `file`
+    /// and remove the journal. `file` must be a [file on disk](RawFile::open) with a name.
+    pub(crate) fn commit(self, file: &mut RawFile) -> error::Result<()> {
+        let path = journaled_path(file)?.to_path_buf();
+        let journal = journal_path(&path);
+
+        // what was written before the journal, like appended contents, must be on disk
+        // before the journal can refer to it
+        file.flush()?;
+        file.sync_data()?;
+
+        let written = File::create(&journal).and_then(|mut f| {
+            f.write_all(&self.encode())?;
+            f.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| sync_parent(&journal)) {
+            let _ = std::fs::remove_file(&journal);
+            return Err(e).at_path(&journal);
+        }
+
+        self.apply(file)?;
+        std::fs::remove_file(&journal).at_path(&journal)?;
+        Ok(())
+    }
+
+    fn apply(&self, file: &mut RawFile) -> error::Result<()> {
+        for (offset, bytes) in &self.writes {
+            file.write_all_at(*offset, bytes)?;
+        }
+        file.set_len(self.len)?;
+        file.sync_all()
+    }
+}
+
+/// The path of `file` when it can be journaled, which files in memory can't.
+pub(crate) fn journaled_path<'a>(file: &'a RawFile) -> error::Result<&'a Path> {
+    match file {
+        RawFile::Disk { name: Some(name), .. } => Ok(name),
+        _ => Err(PackError::NotJournaled),
+    }
+}
+
+/// Finish or undo a change to the backpack at `path` which was interrupted, like by a crash,
+/// using its [journal](journal_path). Done by [`BackPack::open_journaled`](crate::BackPack::open_journaled)
+/// and [`PackWriter::open_append_journaled`](crate::pack::PackWriter::open_append_journaled)
+/// before they open the backpack.
+pub fn recover(path: impl AsRef<Path>) -> error::Result<Recovery> {
+    let path = path.as_ref();
+    let journal = journal_path(path);
+
+    let mut bytes = Vec::new();
+    match File::open(&journal) {
+        Ok(mut f) => f.read_to_end(&mut bytes).at_path(&journal)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Recovery::Clean),
+        Err(e) => return Err(e).at_path(&journal),
+    };
+
+    let res = match Journal::decode(&bytes) {
+        Some(entries) => {
+            let mut file = RawFile::from(File::options().write(true).open(path).at_path(path)?);
+            entries.apply(&mut file)?;
+            log::warn!("completed an interrupted change to {:?}", path);
+            Recovery::Completed
+        }
+        None => {
+            log::warn!("rolled back an interrupted change to {:?}", path);
+            Recovery::RolledBack
+        }
+    };
+    std::fs::remove_file(&journal).at_path(&journal)?;
+    Ok(res)
+}
+
+/// Sync the directory `path` is in, so a new file in it survives a crash.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::journal::{journal_path, recover, Journal, Recovery};
+
+    #[test]
+    fn test_journal_encoding() {
+        let mut journal = Journal::new(100);
+        journal.write(0, b"header".to_vec());
+        journal.write(64, b"toc".to_vec());
+        let bytes = journal.encode();
+
+        let decoded = Journal::decode(&bytes).unwrap();
+        assert_eq!(decoded.len, 100);
+        assert_eq!(decoded.writes, vec![(0, b"header".to_vec()), (64, b"toc".to_vec())]);
+
+        // a journal cut off anywhere was interrupted
+        for len in 0..bytes.len() {
+            assert!(Journal::decode(&bytes[..len]).is_none());
+        }
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = std::env::temp_dir().join("backpack_test_recover");
+        std::fs::write(&path, "old contents").unwrap();
+        assert_eq!(recover(&path).unwrap(), Recovery::Clean);
+
+        let mut journal = Journal::new(7);
+        journal.write(0, b"new".to_vec());
+        journal.write(3, b" one and more".to_vec());
+        let bytes = journal.encode();
+
+        // interrupted while writing the journal, the backpack wasn't touched
+        std::fs::write(journal_path(&path), &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(recover(&path).unwrap(), Recovery::RolledBack);
+        assert_eq!(std::fs::read(&path).unwrap(), b"old contents");
+        assert!(!journal_path(&path).exists());
+
+        // interrupted after, the writes are made again
+        std::fs::write(journal_path(&path), &bytes).unwrap();
+        assert_eq!(recover(&path).unwrap(), Recovery::Completed);
+        assert_eq!(std::fs::read(&path).unwrap(), b"new one");
+        assert!(!journal_path(&path).exists());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use memmap2::Mmap;
+use crate::error;
+use crate::pack::advice::Advice;
+
+/// A file on disk mapped into memory read-only, see [`RawFile::open_mmap`](crate::RawFile::open_mmap).
+/// Reads copy straight out of the page cache, without a system call per read.
+pub struct MmapFile {
+    pub(crate) name: Option<PathBuf>,
+    pub(crate) file: File,
+    map: Mmap,
+    position: u64,
+}
+
+impl MmapFile {
+    /// Map all of `file` into memory.
+    ///
+    /// # Safety
+    /// The file must not be truncated or changed by anyone while it's mapped.
+    /// Reading a truncated part of the mapping kills the process with `SIGBUS`.
+    pub(crate) unsafe fn map(file: File, name: Option<PathBuf>) -> error::Result<Self> {
+        // Safety: the caller promises the file stays as it is
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self { name, file, map, position: 0 })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    pub(crate) fn current_offset(&self) -> u64 {
+        self.position
+    }
+
+    pub(crate) fn try_clone(&self) -> error::Result<Self> {
+        // Safety: the original mapping already requires the file to stay unchanged
+        let mut res = unsafe { Self::map(self.file.try_clone()?, self.name.clone())? };
+        res.position = self.position;
+        Ok(res)
+    }
+
+    pub(crate) fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> error::Result<()> {
+        let src = usize::try_from(offset).ok()
+            .and_then(|start| self.as_bytes().get(start..start.checked_add(buf.len())?))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    /// Tell the operating system how a region of the mapping is going to be accessed.
+    /// Does nothing on other platforms than unix.
+    #[cfg(unix)]
+    pub(crate) fn advise(&self, offset: u64, length: u64, advice: Advice) -> error::Result<()> {
+        let start = (offset as usize).min(self.map.len());
+        let length = (length as usize).min(self.map.len() - start);
+        if length == 0 {
+            return Ok(());
+        }
+
+        let advice = match advice {
+            Advice::Normal => memmap2::Advice::Normal,
+            Advice::Sequential => memmap2::Advice::Sequential,
+            Advice::Random => memmap2::Advice::Random,
+            Advice::WillNeed => memmap2::Advice::WillNeed,
+            Advice::DontNeed => {
+                // Safety: the mapping is a shared and read-only one of a file, so dropped pages
+                // are read from the file again and its contents stay the same
+                unsafe { self.map.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, start, length)? };
+                return Ok(());
+            }
+        };
+        self.map.advise_range(advice, start, length)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn advise(&self, _offset: u64, _length: u64, _advice: Advice) -> error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for MmapFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.as_bytes();
+        let start = (self.position as usize).min(bytes.len());
+        let n = buf.len().min(bytes.len() - start);
+        buf[..n].copy_from_slice(&bytes[start..start + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for MmapFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let bytes = self.as_bytes();
+        Ok(&bytes[(self.position as usize).min(bytes.len())..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl Seek for MmapFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.map.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.position)
+    }
+}
+
+/// The mapping is read-only, like files opened with [`RawFile::open`](crate::RawFile::open).
+impl Write for MmapFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "memory mapped files are read-only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
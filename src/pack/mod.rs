@@ -3,53 +3,153 @@ mod backpack;
 mod file;
 mod in_memory;
 mod maybe_ref;
+mod stream;
+mod zip;
+mod aligned;
+mod advice;
+mod trace;
+mod chunks;
+mod validate;
+mod faulty;
+mod codec;
+mod protection;
+mod handles;
+mod names;
+mod changes;
+mod split;
+mod compat;
+mod writer;
+mod reader;
+mod temp;
+mod transaction;
+mod entry_stream;
+mod compression;
+mod encryption;
+mod directory;
+mod overlay;
+mod diff;
+mod progress;
+mod tar;
+mod entries;
+mod adaptive;
+mod slice_reader;
+mod storage;
+mod sparse;
+mod salvage;
+mod info;
+mod builder;
+mod tee;
+mod hashing;
+mod text;
+mod buffered;
+mod journal;
+mod vfs;
+mod pack_set;
+mod volumes;
+mod observer;
+mod entry_name;
+#[cfg(feature = "serde")]
+mod serialized;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "async")]
+mod async_file;
+#[cfg(feature = "async")]
+mod async_pack;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+mod fuse;
+#[cfg(feature = "chunked")]
+mod chunked;
 
-pub use file::RawFile;
-pub use in_memory::InMemoryFile;
-pub use crate::pack::backpack::BackPack;
+pub use file::{FileMetadata, RawFile};
+pub use in_memory::{InMemoryFile, SharedBytes};
+pub use stream::{StreamReader, StreamEntry};
+pub use aligned::AlignedBytes;
+pub use advice::Advice;
+pub use trace::AccessTrace;
+pub use protection::IndexProtection;
+pub use codec::{Codec, Identity};
+#[cfg(feature = "deflate")]
+pub use codec::Gzip;
+pub use names::{NameHasher, NameMatching};
+pub use changes::{ChangeEvent, Changes, NextChange};
+pub use split::{SplitEntry, SplitPack};
+pub use compat::{CompatibilityReport, FormatFeature};
+pub use writer::{PackEntryWriter, PackWriter};
+pub use reader::{Entry, PackReader};
+pub use temp::TempEntry;
+pub use transaction::Transaction;
+pub use entry_stream::{EntryReader, EntryWriter};
+pub use compression::Compression;
+pub use directory::{DirectoryOptions, ExtractOptions};
+pub use overlay::Overlay;
+pub use diff::{PackDiff, PATCH_ENTRY};
+pub use progress::{Cancellable, CancellationToken, Progress, ProgressUpdate};
+pub use entries::{Entries, EntriesMut, EntryKind, EntryMetadata, EntryRef};
+pub use adaptive::AdaptiveFile;
+pub use storage::{Storage, StorageFile};
+pub use buffered::{BufferedFile, DEFAULT_WRITE_BUFFER};
+pub use journal::{journal_path, recover, Recovery};
+pub use vfs::{DiskVfs, Vfs, VfsMetadata};
+pub use pack_set::PackSet;
+pub use volumes::{volume_path, Volumes};
+pub use observer::{AccessReport, EntryAccess, ReadEvent, ReadObserver};
+pub use entry_name::EntryName;
+pub use salvage::{ReadMode, SalvageReport};
+pub use info::{IndexEntry, PackInfo, PackStats};
+pub use builder::{Checksum, IndexPlacement, PackBuilder};
+pub use tee::TeeFile;
+pub use hashing::{HashingFile, StreamHasher};
+pub use text::Lines;
+#[cfg(feature = "serde")]
+pub use serialized::Format;
+#[cfg(feature = "json")]
+pub use serialized::Json;
+#[cfg(feature = "signing")]
+pub use signing::{SigningKey, PUBLIC_KEY_SIZE};
+#[cfg(feature = "crypto")]
+pub use encryption::Encryption;
+#[cfg(feature = "async")]
+pub use async_file::AsyncRawFile;
+#[cfg(feature = "async")]
+pub use async_pack::{AsyncPackReader, AsyncPackWriter};
+#[cfg(feature = "mmap")]
+pub use mmap::MmapFile;
+#[cfg(feature = "watch")]
+pub use watch::DirectoryWatcher;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+pub use fuse::mount;
+#[cfg(feature = "chunked")]
+pub use chunked::{chunk_lengths, ChunkId, ChunkedEntry, ChunkedReader, ChunkedWriter, ChunkingOptions, CHUNKED_ENTRY};
+#[cfg(feature = "obfuscation")]
+pub use names::SaltedSha256;
+pub use faulty::{FaultyFile, Operation, Trigger};
+pub use validate::{Validator, ForExtension};
+pub use chunks::{chunk_channel, ChunkSender, ChunkReceiver, Recv};
+pub(crate) use crate::pack::backpack::Index;
+pub(crate) use crate::format::{crc32, layout};
+pub(crate) use crate::format::layout::{COMPRESSION_FIELD, ENCRYPTION_FIELD};
+pub use crate::format::{parse_int, Crc32, SliceReader, PACK_HEADER_SIZE, PACK_MAGIC, PACK_VERSION, TOC_SIZE};
+pub use crate::pack::backpack::{BackPack, Collision, EntryOptions, FragmentationReport, FreezeOptions, OutputMode, Quota, SortKey, Tier, VerifyReport, WriteLimits, ALIAS_ENTRY, ATTRIBUTES_ENTRY, ENCRYPTION_ENTRY, EXPIRY_ENTRY, METADATA_ENTRIES, MODE_ATTRIBUTE, MODIFIED_ENTRY, RESERVED_PREFIX, SIGNATURE_ENTRY, SPARSE_ATTRIBUTE, SYMLINK_ATTRIBUTE, TIERS_ENTRY};
 pub use crate::error::{PackError, Result};
 
-pub const fn parse_int(s: &'static [u8]) -> u16 {
-    match s {
-        [] => 0,
-        [rest@.., h] => {
-            let rest_int = parse_int(rest);
-
-            let val = match h {
-                b'0' => 0,
-                b'1' => 1,
-                b'2' => 2,
-                b'3' => 3,
-                b'4' => 4,
-                b'5' => 5,
-                b'6' => 6,
-                b'7' => 7,
-                b'8' => 8,
-                b'9' => 9,
-                _ => panic!("couldn't parse to integer; unknown digit in string"),
-            };
-
-            rest_int * 10 + val
-        }
-    }
-}
-
-pub const PACK_MAGIC: &[u8] = b"BACKPACK";
-pub const PACK_VERSION: u16 = parse_int(env!("CARGO_PKG_VERSION_MAJOR").as_bytes());
-pub const TOC_SIZE: u16 = 4096;
-pub const PACK_HEADER_SIZE: u64 = 26;
-
 #[cfg(test)]
 mod tests {
     use crate::RawFile;
     use crate::pack::in_memory::InMemoryFile;
-    use crate::pack::PACK_VERSION;
+    use crate::pack::{PACK_HEADER_SIZE, PACK_VERSION};
     use crate::pack::backpack::BackPack;
     use crate::error::PackError;
+    use crate::pack::{chunk_channel, AccessTrace, Advice, ChunkReceiver, ForExtension, OutputMode, StreamReader, Tier};
+    use std::io::{Cursor, Read};
 
     #[test]
     pub fn test_version() {
-        assert_eq!(PACK_VERSION, env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap())
+        assert_eq!(PACK_VERSION, env!("CARGO_PKG_VERSION_MAJOR").parse::<u16>().unwrap())
     }
 
     #[test]
@@ -93,4 +193,4204 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_reserved_names() -> Result<(), PackError> {
+        use crate::pack::PackWriter;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for name in [".backpack/expiry", "./.backpack/encryption", "dir/../.backpack/other"] {
+            let f: InMemoryFile = "evil".into();
+            assert!(matches!(bp.add_file(f.with_name(name)), Err(PackError::ReservedName(_))), "{}", name);
+        }
+        assert!(matches!(bp.add_dir(".backpack"), Err(PackError::ReservedName(_))));
+        assert!(matches!(bp.add_symlink(".backpack/aliases", "a"), Err(PackError::ReservedName(_))));
+        assert!(matches!(bp.overlay().write(".backpack/tiers", "hot a"), Err(PackError::ReservedName(_))));
+
+        // names which only look like it are fine
+        let f: InMemoryFile = "a".into();
+        bp.add_file(f.with_name(".backpack"))?;
+        let f: InMemoryFile = "b".into();
+        bp.add_file(f.with_name("dir/.backpack/b"))?;
+        assert!(matches!(bp.rename(".backpack", ".backpack/modified"), Err(PackError::ReservedName(_))));
+        assert!(matches!(bp.set_alias(".backpack/attributes", ".backpack"), Err(PackError::ReservedName(_))));
+        assert!(matches!(bp.move_dir("dir", ".backpack"), Err(PackError::ReservedName(_))));
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_eq!(BackPack::open(bytes)?.file_names(), [".backpack", "dir/.backpack/b"]);
+
+        let mut writer = PackWriter::new(RawFile::in_memory("test.bp"))?;
+        assert!(matches!(writer.add_entry(".backpack/expiry", &b"evil"[..]), Err(PackError::ReservedName(_))));
+        assert!(matches!(writer.entry_writer(".backpack/expiry"), Err(PackError::ReservedName(_))));
+        writer.add_entry("a", &b"a"[..])?;
+        assert_eq!(BackPack::open(writer.finish()?.convert_into_memory()?)?.file_names(), ["a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_files() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let bp = BackPack::create(file)?;
+
+        // enough files to need multiple toc blocks
+        for i in 0..500 {
+            let f: InMemoryFile = format!("contents of {}", i).into();
+            bp.add_file(f.with_name(format!("file_{}.txt", i)))?;
+        }
+        let file = bp.close()?;
+
+        let bp = BackPack::open(file)?;
+        for i in 0..500 {
+            let f = bp.get_file(format!("file_{}.txt", i))?;
+            assert_eq!(&*f.get_bytes(), format!("contents of {}", i).as_bytes());
+        }
+
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let bp = BackPack::create(file)?;
+        for i in 0..300 {
+            let f: InMemoryFile = format!("contents of {}", i).into();
+            bp.add_file(f.with_name(format!("file_{}.txt", i)))?;
+        }
+        let file = bp.close()?;
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // a byte slice can only be read forwards
+        let mut stream = StreamReader::new(bytes.as_slice())?;
+        assert_eq!(stream.entries().count(), 300);
+
+        let mut seen = 0;
+        while let Some(mut entry) = stream.next_entry()? {
+            let name = entry.name().to_string();
+            let mut contents = String::new();
+            // leave every other entry unread, it should be skipped over
+            if seen % 2 == 0 {
+                entry.read_to_string(&mut contents)?;
+                assert_eq!(name.replace("file_", "contents of ").replace(".txt", ""), contents);
+            }
+            seen += 1;
+        }
+        assert_eq!(seen, 300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_hybrid() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let mut bp = BackPack::create(file)?;
+        bp.set_output_mode(OutputMode::ZipHybrid);
+
+        let f: InMemoryFile = "first".into();
+        bp.add_file(f.with_name("a.txt"))?;
+        let f: InMemoryFile = "second".into();
+        bp.add_file(f.with_name("dir/b.txt"))?;
+        let file = bp.close()?;
+
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+        // end of central directory record, followed by the comment
+        let eocd = &bytes[bytes.len() - 22 - 8..];
+        assert_eq!(&eocd[..4], &[0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(&eocd[22..], b"BACKPACK");
+
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(&*bp.get_file("a.txt")?.get_bytes(), b"first");
+        assert_eq!(&*bp.get_file("dir/b.txt")?.get_bytes(), b"second");
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_appended() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let bp = BackPack::create(file)?;
+        let f: InMemoryFile = "test".into();
+        bp.add_file(f.with_name("test.txt"))?;
+        let pack = bp.close()?;
+
+        let mut exe = Vec::new();
+        crate::sfx::build(pack, b"not really an executable".as_slice(), &mut exe)?;
+
+        let bp = BackPack::open_appended(Cursor::new(exe))?;
+        assert_eq!(&*bp.get_file("test.txt")?.get_bytes(), b"test");
+        bp.close()?;
+
+        assert!(matches!(
+            BackPack::open_appended(Cursor::new(b"no pack here, just a program".to_vec())),
+            Err(PackError::NoAppendedPack)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alignment() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let mut bp = BackPack::create(file)?;
+        bp.set_alignment(256)?;
+        assert!(bp.set_alignment(100).is_err());
+
+        for i in 0..10 {
+            let f: InMemoryFile = "x".repeat(i * 7 + 1).into();
+            bp.add_file(f.with_name(format!("file_{}", i)))?;
+        }
+        let file = bp.close()?;
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // the data starts after the header and one toc block,
+        // from there on every file starts on a 256 byte boundary
+        let data = &bytes[4352..];
+        assert_eq!(data.chunks(256).count(), 10);
+        for chunk in data.chunks(256) {
+            assert_eq!(chunk[0], b'x');
+        }
+
+        // the alignment recorded for the file counts, not the backpack's
+        let bp = BackPack::open(bytes.clone())?;
+        assert_eq!(bp.alignment(), 1);
+        let aligned = bp.entry_aligned_bytes("file_3")?;
+        assert_eq!(aligned.as_ptr() as usize % 256, 0);
+        assert_eq!((aligned.alignment(), &*aligned), (256, "x".repeat(22).as_bytes()));
+        drop(aligned);
+        bp.close()?;
+
+        // files without an alignment are borrowed from the backpack in memory
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("unaligned").with_name("a"))?;
+        let bp = BackPack::open(bp.close()?)?;
+        let aligned = bp.entry_aligned_bytes("a")?;
+        assert!(aligned.is_borrowed());
+        assert_eq!(&*aligned, b"unaligned");
+        drop(aligned);
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_advise() -> Result<(), PackError> {
+        let path = std::env::temp_dir().join("backpack_test_advise.bp");
+
+        let mut bp = BackPack::create(RawFile::create(&path)?)?;
+        let f: InMemoryFile = "first".into();
+        bp.add_file(f.with_name("first.txt"))?;
+        bp.flush()?;
+
+        // added after the flush, so only in memory
+        let f: InMemoryFile = "second".into();
+        bp.add_file(f.with_name("second.txt"))?;
+        bp.advise("first.txt", Advice::WillNeed)?;
+        bp.advise("second.txt", Advice::WillNeed)?;
+        assert!(matches!(bp.advise("third.txt", Advice::WillNeed), Err(PackError::FileNotFound(_))));
+        bp.close()?;
+
+        let bp = BackPack::open(RawFile::open(&path)?)?;
+        bp.advise("second.txt", Advice::Sequential)?;
+        bp.advise("first.txt", Advice::DontNeed)?;
+        assert_eq!(&*bp.get_file("first.txt")?.get_bytes(), b"first");
+        assert_eq!(&*bp.get_file("second.txt")?.get_bytes(), b"second");
+        bp.close_drop_unwritten_changes()?;
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_order() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let bp = BackPack::create(file)?;
+        for name in ["a", "b", "c", "d"] {
+            let f: InMemoryFile = name.into();
+            bp.add_file(f.with_name(name))?;
+        }
+        let file = bp.close()?;
+
+        let mut bp = BackPack::open(file)?;
+        bp.start_recording();
+        for name in ["c", "a", "c", "d"] {
+            bp.get_file(name)?;
+        }
+        let trace = bp.take_trace().unwrap();
+        assert_eq!(trace.names(), &["c", "a", "d"]);
+
+        let trace = AccessTrace::from_json(&trace.to_json())?;
+        bp.set_order(&trace);
+        let file = bp.close()?;
+
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+        let stream = StreamReader::new(bytes.as_slice())?;
+        let order = stream.entries().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order, ["c", "a", "d", "b"]);
+
+        let trace = AccessTrace::from_json(r#" [ "quote\"d", "\u00e9\ud83d\ude00" ] "#)?;
+        assert_eq!(trace.names(), &["quote\"d", "é😀"]);
+        assert_eq!(AccessTrace::from_json(&trace.to_json())?, trace);
+        assert!(AccessTrace::from_json("[\"unterminated]").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiers() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let mut bp = BackPack::create(file)?;
+        for name in ["a", "b", "c", "d", "e"] {
+            let f: InMemoryFile = name.into();
+            bp.add_file(f.with_name(name))?;
+        }
+        bp.set_tier("a", Some(Tier::Cold));
+        bp.set_tier("e", Some(Tier::Hot));
+        let file = bp.close()?;
+
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+        let stream = StreamReader::new(bytes.as_slice())?;
+        let order = stream.entries().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order, ["e", "b", "c", "d", "a"]);
+
+        let mut bp = BackPack::open(bytes)?;
+        bp.set_tiers_from_trace(&["d", "b"].into_iter().collect());
+        let file = bp.close()?;
+
+        let bytes = file.into_memory().ok().unwrap().get_bytes().to_vec();
+        let stream = StreamReader::new(bytes.as_slice())?;
+        let order = stream.entries().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order, ["d", "b", "e", "c", "a"]);
+
+        // tiers are kept when the backpack is reopened
+        let mut bp = BackPack::open(bytes)?;
+        bp.set_tier("c", None);
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let stream = StreamReader::new(bytes.as_slice())?;
+        let order = stream.entries().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(order, ["d", "b", "c", "e", "a"]);
+        assert_eq!(BackPack::open(bytes)?.file_names(), ["a", "b", "c", "d", "e"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiers_flush() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        let f: InMemoryFile = "a".into();
+        bp.add_file(f.with_name("a"))?;
+        bp.set_tier("a", Some(Tier::Hot));
+        bp.flush()?;
+        // the entry holding the tiers isn't a file after flushing either
+        assert_eq!(bp.file_names(), ["a"]);
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(bp.file_names(), ["a"]);
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), b"a");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn test_tier_compression() -> Result<(), PackError> {
+        use crate::pack::{Compression, PackReader};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_compression(Compression::Deflate)?;
+        for name in ["hot", "cold", "none"] {
+            bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name(name))?;
+        }
+        bp.set_tier("hot", Some(Tier::Hot));
+        bp.set_tier("cold", Some(Tier::Cold));
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // only hot files can be borrowed, the others are compressed
+        let reader = PackReader::open(bytes.clone())?;
+        assert_eq!(&*reader.get("hot")?.as_slice()?.unwrap(), &[7; 10000][..]);
+        assert!(reader.get("cold").is_err());
+        assert!(reader.get("none").is_err());
+
+        // a cold file which becomes hot is stored uncompressed from the next flush on
+        let mut bp = BackPack::open(bytes)?;
+        bp.set_tier("cold", Some(Tier::Hot));
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(&*reader.get("cold")?.as_slice()?.unwrap(), &[7; 10000][..]);
+        assert_eq!(reader.read("none")?, vec![7; 10000]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.add_file(InMemoryFile::from("hello").with_name("a"))?;
+        bp.flush()?;
+
+        let mut contents = String::new();
+        bp.entry("a")?.read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello");
+
+        let mut raw = Vec::new();
+        bp.entry_raw("a")?.read_to_end(&mut raw)?;
+        assert_eq!(raw, b"hello");
+
+        assert!(matches!(bp.entry("b"), Err(PackError::FileNotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn test_entry_raw_compressed() -> Result<(), PackError> {
+        use crate::pack::Compression;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_compression(Compression::Deflate)?;
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("a"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        for flushed in [false, true] {
+            let mut bp = BackPack::open(bytes.clone())?;
+            if flushed {
+                bp.flush()?;
+            }
+            let mut raw = Vec::new();
+            bp.entry_raw("a")?.read_to_end(&mut raw)?;
+            assert!(raw.len() < 10000);
+            assert_eq!(Compression::Deflate.decompress(&raw, 10000)?, vec![7; 10000]);
+            let mut contents = Vec::new();
+            bp.entry("a")?.read_to_end(&mut contents)?;
+            assert_eq!(contents, vec![7; 10000]);
+
+            // not stored yet
+            bp.add_file(InMemoryFile::from(vec![8; 10000]).with_name("b"))?;
+            let mut raw = Vec::new();
+            bp.entry_raw("b")?.read_to_end(&mut raw)?;
+            assert_eq!(raw, vec![8; 10000]);
+            bp.close_drop_unwritten_changes()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_validator() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.add_validator(ForExtension::new("json", |_: &std::path::Path, contents: &[u8]| {
+            if contents.starts_with(b"{") { Ok(()) } else { Err("not a json object".to_string()) }
+        }));
+
+        bp.add_file(InMemoryFile::from("{}").with_name("a.json"))?;
+        bp.add_file(InMemoryFile::from("[]").with_name("b.txt"))?;
+        match bp.add_file(InMemoryFile::from("[]").with_name("c.json")) {
+            Err(PackError::InvalidAsset { name, reason, .. }) => {
+                assert_eq!(name, std::path::Path::new("c.json"));
+                assert_eq!(reason, "not a json object");
+            }
+            _ => panic!("invalid file was accepted"),
+        }
+
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, ["a.json", "b.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_faulty_file() -> Result<(), PackError> {
+        use std::io::ErrorKind;
+        use crate::pack::{FaultyFile, Operation, Trigger};
+
+        // the first flush is torn halfway through the data
+        let file = FaultyFile::new(RawFile::in_memory("test.bp"))
+            .fail(Operation::Write, Trigger::AtOffset(4200), ErrorKind::StorageFull)
+            .fail(Operation::SetLen, Trigger::NthCall(1), ErrorKind::Other);
+        let mut bp = BackPack::create(RawFile::from(file))?;
+        bp.add_file(InMemoryFile::from("a".repeat(200)).with_name("a"))?;
+        match bp.flush() {
+            Err(PackError::Io(e)) => assert_eq!(e.kind(), ErrorKind::StorageFull),
+            _ => panic!("flush should have failed"),
+        }
+        // nothing was lost, the files are still in memory
+        assert_eq!(bp.get_file("a")?.get_bytes().len(), 200);
+
+        let RawFile::Faulty(mut file) = bp.close_drop_unwritten_changes()? else { panic!() };
+        assert!(file.calls(Operation::Write) > 0);
+        file.heal();
+
+        let mut bp = BackPack::create(RawFile::Faulty(file))?;
+        bp.add_file(InMemoryFile::from("b").with_name("b"))?;
+        bp.flush()?;
+        let bytes = bp.close()?.convert_into_memory()?.get_bytes().to_vec();
+        let bp = BackPack::open(RawFile::from(bytes))?;
+        assert_eq!(&*bp.get_file("b")?.get_bytes(), b"b");
+
+        // reads fail on the nth call
+        let file = FaultyFile::new(RawFile::from(b"BACKPACK".to_vec()))
+            .fail(Operation::Read, Trigger::NthCall(0), ErrorKind::Interrupted);
+        assert!(BackPack::open(RawFile::from(file)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quota() -> Result<(), PackError> {
+        use crate::pack::Quota;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_quota("saves/", Some(Quota { max_bytes: Some(10), max_entries: Some(2) }));
+
+        bp.add_file(InMemoryFile::from("aaaaaa").with_name("saves/a"))?;
+        assert!(matches!(bp.add_file(InMemoryFile::from("bbbbbb").with_name("saves/b")), Err(PackError::QuotaExceeded(_))));
+        // replacing a file only counts the new version
+        bp.add_file(InMemoryFile::from("aaaaaaaaaa").with_name("saves/a"))?;
+        bp.add_file(InMemoryFile::from("").with_name("saves/c"))?;
+        match bp.add_file(InMemoryFile::from("").with_name("saves/d")) {
+            Err(e) => assert_eq!(std::io::Error::from(e).kind(), std::io::ErrorKind::QuotaExceeded),
+            Ok(_) => panic!("entry quota not enforced"),
+        }
+        bp.add_file(InMemoryFile::from("x".repeat(100)).with_name("other/e"))?;
+
+        bp.set_quota("saves/", None);
+        bp.add_file(InMemoryFile::from("").with_name("saves/d"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry() -> Result<(), PackError> {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("old").with_name("a"))?;
+        bp.add_file(InMemoryFile::from("new").with_name("b"))?;
+        bp.add_file(InMemoryFile::from("kept").with_name("c"))?;
+        bp.set_expiry("a", Some(UNIX_EPOCH + Duration::from_secs(100)))?;
+        bp.set_expiry("b", Some(UNIX_EPOCH + Duration::from_secs(200)))?;
+        assert!(bp.set_expiry("d", None).is_err());
+
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let mut bp = BackPack::open(RawFile::from(bytes))?;
+        assert_eq!(bp.expiry("b"), Some(UNIX_EPOCH + Duration::from_secs(200)));
+        assert_eq!(bp.expiry("c"), None);
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        assert_eq!(bp.purge_expired_at(UNIX_EPOCH + Duration::from_secs(150))?, ["a"]);
+        bp.flush()?;
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, ["b", "c"]);
+        assert_eq!(bp.expiry("a"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_committed() -> Result<(), PackError> {
+        use std::io::Write;
+        use crate::pack::PackWriter;
+
+        let path = std::env::temp_dir().join("backpack_test_open_committed.bp");
+
+        let committed = |path: &std::path::Path| -> Result<Vec<(String, Vec<u8>)>, PackError> {
+            let reader = BackPack::open_committed(path)?;
+            let mut names = reader.file_names();
+            names.sort();
+            let res = names.into_iter()
+                .map(|name| Ok((name.clone(), reader.get_file(&name)?.get_bytes().to_vec())))
+                .collect();
+            reader.close_drop_unwritten_changes()?;
+            res
+        };
+
+        let mut writer = PackWriter::new(RawFile::create(&path)?)?;
+        writer.add_entry("first.txt", &b"first"[..])?;
+        writer.commit()?;
+        writer.add_entry("second.txt", &b"second"[..])?;
+
+        // sees the commit while the writer is in the middle of the next entry
+        let mut entry = writer.entry_writer("partial.txt")?;
+        entry.write_all(b"part")?;
+        assert_eq!(committed(&path)?, [("first.txt".to_string(), b"first".to_vec())]);
+        drop(entry);
+
+        writer.commit()?;
+        assert_eq!(committed(&path)?.len(), 2);
+        writer.add_entry("third.txt", &b"third"[..])?;
+        assert_eq!(committed(&path)?.len(), 2);
+        writer.finish()?;
+        assert_eq!(committed(&path)?.len(), 3);
+
+        // the old table of contents is rewritten when committing while appending
+        let mut writer = BackPack::open_append(&path)?;
+        writer.add_entry("fourth.txt", &b"fourth"[..])?;
+        writer.commit()?;
+        let files = committed(&path)?;
+        assert_eq!(files.len(), 4);
+        assert_eq!(files[0], ("first.txt".to_string(), b"first".to_vec()));
+        assert_eq!(files[1], ("fourth.txt".to_string(), b"fourth".to_vec()));
+        writer.finish()?;
+        let finished = BackPack::open(RawFile::open(&path)?)?;
+        assert_eq!(finished.file_names().len(), 4);
+        finished.close_drop_unwritten_changes()?;
+
+        // a finished backpack which was cut off never becomes readable
+        let len = std::fs::metadata(&path)?.len();
+        std::fs::OpenOptions::new().write(true).open(&path)?.set_len(len / 2)?;
+        assert!(BackPack::open_committed(&path).is_err());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_collision() -> Result<(), PackError> {
+        use crate::pack::Collision;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_collision_policy(Collision::Error);
+        bp.add_file(InMemoryFile::from("a").with_name("dir/a.txt"))?;
+        assert!(matches!(bp.add_file(InMemoryFile::from("b").with_name("dir/a.txt")), Err(PackError::FileExists(_))));
+
+        let f = bp.add_file_with(InMemoryFile::from("b").with_name("dir/a.txt"), Collision::KeepBoth)?;
+        assert_eq!(f.name(), Some(std::path::Path::new("dir/a (1).txt")));
+        bp.add_file_with(InMemoryFile::from("c").with_name("dir/a.txt"), Collision::KeepBoth)?;
+        assert_eq!(&*bp.get_file("dir/a (2).txt")?.get_bytes(), b"c");
+
+        bp.add_file_with(InMemoryFile::from("a").with_name("dir/a.txt"), Collision::DedupeIfIdentical)?;
+        assert!(bp.add_file_with(InMemoryFile::from("x").with_name("dir/a.txt"), Collision::DedupeIfIdentical).is_err());
+        assert_eq!(bp.file_names().len(), 3);
+
+        bp.add_file_with(InMemoryFile::from("x").with_name("dir/a.txt"), Collision::Overwrite)?;
+        assert_eq!(&*bp.get_file("dir/a.txt")?.get_bytes(), b"x");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sidecars() -> Result<(), PackError> {
+        let pack = |with_sidecars: bool| -> Result<Vec<u8>, PackError> {
+            let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+            bp.set_alignment(64)?;
+            bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+            bp.add_file(InMemoryFile::from("b").with_name("b"))?;
+            bp.add_file(InMemoryFile::from("c").with_name("c"))?;
+            if with_sidecars {
+                bp.set_sidecar("b", Some(b"preload".to_vec()))?;
+                bp.set_sidecar("c", Some(vec![1; 100]))?;
+                assert!(bp.set_sidecar("d", None).is_err());
+            }
+            bp.flush()?;
+            if with_sidecars {
+                // too large for the padding
+                assert_eq!(bp.sidecar("c"), None);
+            }
+            Ok(bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec())
+        };
+
+        let with = pack(true)?;
+        assert_eq!(with.len(), pack(false)?.len());
+
+        let bp = BackPack::open(RawFile::from(with))?;
+        assert_eq!(bp.sidecar("b"), Some(&b"preload"[..]));
+        assert_eq!(bp.sidecar("a"), None);
+        assert_eq!(bp.sidecar("c"), None);
+        assert_eq!(&*bp.get_file("b")?.get_bytes(), b"b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcoded() -> Result<(), PackError> {
+        use crate::pack::{Codec, Identity};
+
+        /// encodes every byte as two hex digits
+        struct Hex;
+        impl Codec for Hex {
+            fn name(&self) -> &str {
+                "hex"
+            }
+
+            fn encode<'r>(&self, input: Box<dyn Read + 'r>) -> Result<Box<dyn Read + 'r>, PackError> {
+                Ok(Box::new(HexReader(input)))
+            }
+        }
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("hi").with_name("a"))?;
+
+        let mut hex = String::new();
+        bp.entry_transcoded("a", &Hex)?.read_to_string(&mut hex)?;
+        assert_eq!(hex, "6869");
+
+        let mut same = String::new();
+        bp.entry_transcoded("a", &Identity)?.read_to_string(&mut same)?;
+        assert_eq!(same, "hi");
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcoded_compression() -> Result<(), PackError> {
+        use crate::pack::{Codec, Compression};
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("a"))?;
+
+        let methods = [Compression::None, Compression::Deflate, Compression::Zstd { level: 3 }, Compression::Lz4];
+        for method in methods {
+            let transcoded = bp.entry_transcoded("a", &method);
+            if !method.is_supported() {
+                assert!(matches!(transcoded, Err(PackError::UnsupportedCompression(_))), "{}", method.name());
+                continue;
+            }
+            let mut encoded = Vec::new();
+            transcoded?.read_to_end(&mut encoded)?;
+            assert!(method == Compression::None || encoded.len() < 10000, "{}", method.name());
+            assert_eq!(method.decompress(&encoded, 10000)?, vec![7; 10000], "{}", method.name());
+        }
+
+        #[cfg(feature = "deflate")]
+        {
+            let mut gzip = Vec::new();
+            bp.entry_transcoded("a", &crate::pack::Gzip)?.read_to_end(&mut gzip)?;
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(gzip.as_slice()).read_to_end(&mut decoded)?;
+            assert_eq!(decoded, vec![7; 10000]);
+        }
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    struct HexReader<'r>(Box<dyn Read + 'r>);
+
+    impl Read for HexReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut input = vec![0; buf.len() / 2];
+            let n = self.0.read(&mut input)?;
+            for (i, b) in input[..n].iter().enumerate() {
+                buf[2 * i..2 * i + 2].copy_from_slice(format!("{:02x}", b).as_bytes());
+            }
+            Ok(2 * n)
+        }
+    }
+
+    #[test]
+    fn test_read_many() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for i in 0..20 {
+            bp.add_file(InMemoryFile::from(i.to_string()).with_name(format!("{}", i)))?;
+        }
+        bp.flush()?;
+
+        let names = ["7", "missing", "3", "19", "7"];
+        let res = bp.read_many(&names);
+        assert_eq!(res.len(), 5);
+        assert_eq!(res[0].as_ref().unwrap(), b"7");
+        assert!(matches!(res[1], Err(PackError::FileNotFound(_))));
+        assert_eq!(res[2].as_ref().unwrap(), b"3");
+        assert_eq!(res[3].as_ref().unwrap(), b"19");
+        assert_eq!(res[4].as_ref().unwrap(), b"7");
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_protection() -> Result<(), PackError> {
+        use crate::pack::IndexProtection;
+
+        let pack = |protection| -> Result<Vec<u8>, PackError> {
+            let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+            bp.set_index_protection(protection);
+            bp.add_file(InMemoryFile::from("a").with_name("a.txt"))?;
+            bp.add_file(InMemoryFile::from("b").with_name("b.txt"))?;
+            let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+            // damage the name of the first file in the table of contents
+            bytes[PACK_HEADER_SIZE as usize + 12] ^= 1;
+            Ok(bytes)
+        };
+
+        let bp = BackPack::open(RawFile::from(pack(IndexProtection::ChecksumAndCopy)?))?;
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, ["a.txt", "b.txt"]);
+        assert_eq!(&*bp.get_file("b.txt")?.get_bytes(), b"b");
+        bp.close_drop_unwritten_changes()?;
+
+        assert!(matches!(BackPack::open(RawFile::from(pack(IndexProtection::Checksum)?)), Err(PackError::DamagedIndex)));
+
+        // unprotected, the damage goes unnoticed
+        let bp = BackPack::open(RawFile::from(pack(IndexProtection::None)?))?;
+        assert!(bp.get_file("a.txt").is_err() || bp.get_file("b.txt").is_err());
+        bp.close_drop_unwritten_changes()?;
+
+        // an unprotected backpack ending in what looks like a trailer isn't taken for a protected one
+        let mut contents = vec![0xff; 36];
+        contents.extend_from_slice(b"BPINDEX\0");
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(contents.clone()).with_name("trailer"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(bytes.ends_with(&contents));
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(*bp.get_file("trailer")?.get_bytes(), contents);
+        bp.close()?;
+        Ok(())
+    }
+
+    /// Polls a future to completion on the current thread.
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        struct Unpark(std::thread::Thread);
+        impl std::task::Wake for Unpark {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::sync::Arc::new(Unpark(std::thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut f = std::pin::pin!(f);
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(res) => return res,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks() -> Result<(), PackError> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let (sender, receiver) = chunk_channel(2);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let producer = {
+            let sent = sent.clone();
+            std::thread::spawn(move || {
+                for i in 0..5u8 {
+                    if !sender.send(Ok(vec![i])) {
+                        break;
+                    }
+                    sent.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+
+        // the producer can't get further ahead of what was received than the capacity
+        assert_eq!(receiver.recv().unwrap()?, [0]);
+        assert!(sent.load(Ordering::SeqCst) <= 1 + 2);
+        assert_eq!(block_on(receiver.recv_async()).unwrap()?, [1]);
+        assert!(sent.load(Ordering::SeqCst) <= 2 + 2);
+
+        // dropping the receiver stops the producer before it sent everything
+        drop(receiver);
+        producer.join().unwrap();
+        assert!(sent.load(Ordering::SeqCst) < 5);
+
+        let receiver = ChunkReceiver::from_reader(Cursor::new(b"abcdefg".to_vec()), 3, 1);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = block_on(receiver.recv_async()) {
+            chunks.push(chunk?);
+        }
+        assert_eq!(chunks, [b"abc".to_vec(), b"def".to_vec(), b"g".to_vec()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_handles() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_leak_detection(true);
+        drop(bp.add_file(InMemoryFile::from("a").with_name("a.txt"))?);
+        drop(bp.add_file(InMemoryFile::from("b").with_name("b.txt"))?);
+        assert_eq!(bp.open_handles(), 0);
+
+        let a = bp.get_file("a.txt")?;
+        let b = bp.entry("b.txt")?;
+        let a2 = a.try_clone()?;
+        assert_eq!(bp.open_handles(), 3);
+
+        drop(a);
+        drop(b);
+        assert_eq!(bp.open_handles(), 1);
+
+        // a forgotten handle is never closed
+        std::mem::forget(a2);
+        assert_eq!(bp.open_handles(), 1);
+        let leaked = bp.handles().leaked();
+        assert_eq!(leaked.len(), 1);
+        assert!(leaked[0].1.contains("test_open_handles"));
+
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_future_index_fields() -> Result<(), PackError> {
+        use crate::pack::layout::{encode_fields, PackHeader, TocBlockHeader, TocEntry, U16Le, U64Le, CRITICAL_FIELD};
+        use crate::pack::TOC_SIZE;
+
+        // a pack as a later version might write it, with fields this version doesn't know
+        let pack = |fields: &[(u16, &[u8])]| -> Result<Vec<u8>, PackError> {
+            let fields = encode_fields(fields)?;
+            let mut entries = Vec::new();
+            TocEntry { name: b"a.txt", offset: 0, length: 1, fields: &fields }.write_to(&mut entries)?;
+            TocEntry { name: b"b.txt", offset: 1, length: 2, fields: &[] }.write_to(&mut entries)?;
+
+            let header = TocBlockHeader {
+                filled: U16Le::new((TocBlockHeader::SIZE + entries.len()) as u16),
+                next: U64Le::new(0),
+            };
+
+            let mut bytes = PackHeader::new(3, PACK_HEADER_SIZE).to_bytes().to_vec();
+            bytes.extend_from_slice(&header.to_bytes());
+            bytes.extend_from_slice(&entries);
+            bytes.resize(PACK_HEADER_SIZE as usize + TOC_SIZE as usize, 0);
+            bytes.extend_from_slice(b"abb");
+            Ok(bytes)
+        };
+
+        let bytes = pack(&[(0x100, b"some value"), (0x101, b"")])?;
+        let bp = BackPack::open(RawFile::from(bytes.clone()))?;
+        assert_eq!(&*bp.get_file("a.txt")?.get_bytes(), b"a");
+        assert_eq!(&*bp.get_file("b.txt")?.get_bytes(), b"bb");
+        bp.close_drop_unwritten_changes()?;
+
+        let stream = StreamReader::new(bytes.as_slice())?;
+        assert_eq!(stream.entries().map(|(name, _)| name).collect::<Vec<_>>(), ["a.txt", "b.txt"]);
+
+        let bytes = pack(&[(0x100, b"some value"), (CRITICAL_FIELD | 0x102, b"")])?;
+        assert!(matches!(
+            BackPack::open(RawFile::from(bytes)),
+            Err(PackError::UnsupportedIndexField(tag)) if tag == CRITICAL_FIELD | 0x102
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_limit() -> Result<(), PackError> {
+        use crate::pack::Collision;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for (name, c) in [("a", b'a'), ("b", b'b'), ("c", b'c')] {
+            bp.add_file(InMemoryFile::from(vec![c; 100]).with_name(name))?;
+        }
+        bp.flush()?;
+        bp.set_memory_limit(Some(250));
+        assert_eq!(bp.memory_bytes(), 300);
+
+        // two stored files have to go to make room
+        drop(bp.add_file(InMemoryFile::from(vec![b'd'; 100]).with_name("d"))?);
+        assert_eq!(bp.memory_bytes(), 200);
+
+        // dropped files are read back when opened
+        for (name, c) in [("a", b'a'), ("b", b'b'), ("c", b'c')] {
+            assert_eq!(&*bp.get_file(name)?.get_bytes(), &[c; 100][..]);
+        }
+        assert!(bp.memory_bytes() <= 250);
+        drop(bp.add_file_with(InMemoryFile::from(vec![b'a'; 100]).with_name("a"), Collision::DedupeIfIdentical)?);
+
+        // files that were never flushed can't be dropped
+        let e = InMemoryFile::from(vec![b'e'; 200]).with_name("e");
+        assert!(matches!(bp.add_file(e), Err(PackError::MemoryLimit(250))));
+
+        let bytes = bp.close()?.convert_into_memory()?.get_bytes().to_vec();
+        let bp = BackPack::open(RawFile::from(bytes))?;
+        for (name, c) in [("a", b'a'), ("b", b'b'), ("c", b'c'), ("d", b'd')] {
+            assert_eq!(&*bp.get_file(name)?.get_bytes(), &[c; 100][..]);
+        }
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_hidden() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_output_mode(OutputMode::ZipHybrid);
+        bp.add_file(InMemoryFile::from("a").with_name("a.txt"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug.txt"))?;
+        bp.set_hidden("debug.txt", true)?;
+        assert!(bp.set_hidden("missing.txt", true).is_err());
+
+        assert_eq!(bp.file_names(), ["a.txt"]);
+        let mut names = bp.file_names_with(true);
+        names.sort();
+        assert_eq!(names, ["a.txt", "debug.txt"]);
+
+        let bytes = bp.close()?.convert_into_memory()?.get_bytes().to_vec();
+        let mut bp = BackPack::open(RawFile::from(bytes.clone()))?;
+        assert!(bp.is_hidden("debug.txt"));
+        assert_eq!(bp.file_names(), ["a.txt"]);
+        // hidden files can still be opened by name
+        assert_eq!(&*bp.get_file("debug.txt")?.get_bytes(), b"debug");
+
+        let stream = StreamReader::new(bytes.as_slice())?;
+        assert_eq!(stream.entries().map(|(name, _)| name).collect::<Vec<_>>(), ["a.txt"]);
+        let stream = StreamReader::new_with(bytes.as_slice(), true)?;
+        assert_eq!(stream.entries().map(|(name, _)| name).collect::<Vec<_>>(), ["a.txt", "debug.txt"]);
+
+        bp.set_hidden("debug.txt", false)?;
+        assert_eq!(bp.file_names_with(false).len(), 2);
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragmentation_report() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![0; 100]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![0; 50]).with_name("b"))?;
+        bp.add_file(InMemoryFile::from(vec![0; 10]).with_name("c"))?;
+        bp.flush()?;
+        assert_eq!(bp.fragmentation_report().dead_ratio(), 0.0);
+
+        // overwriting a stored file, and a file which only exists in memory
+        bp.add_file(InMemoryFile::from(vec![1; 20]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![1; 30]).with_name("d"))?;
+        bp.add_file(InMemoryFile::from(vec![2; 40]).with_name("d"))?;
+        bp.remove_file("b")?;
+
+        let report = bp.fragmentation_report();
+        assert_eq!(report.live_bytes, 10 + 20 + 40);
+        assert_eq!(report.dead_bytes, 100 + 50 + 30);
+        assert_eq!(report.tombstones, 1);
+        assert_eq!(report.recoverable_bytes, 150);
+
+        bp.flush()?;
+        let report = bp.fragmentation_report();
+        assert_eq!((report.live_bytes, report.dead_bytes, report.tombstones), (70, 0, 0));
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_hasher() -> Result<(), PackError> {
+        use crate::pack::crc32::crc32;
+        use crate::pack::Collision;
+
+        let hash = |name: &str| format!("{:08x}", crc32(name.as_bytes()));
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_name_hasher(hash);
+        bp.set_collision_policy(Collision::KeepBoth);
+        bp.add_file(InMemoryFile::from("1").with_name("secret/level.dat"))?;
+        let second = bp.add_file(InMemoryFile::from("2").with_name("secret/level.dat"))?;
+        assert_eq!(second.name().unwrap(), std::path::Path::new("secret/level (1).dat"));
+        drop(second);
+
+        let mut names = bp.file_names();
+        names.sort();
+        let mut expected = vec![hash("secret/level.dat"), hash("secret/level (1).dat")];
+        expected.sort();
+        assert_eq!(names, expected);
+
+        let bytes = bp.close()?.convert_into_memory()?.get_bytes().to_vec();
+        assert!(!bytes.windows(6).any(|w| w == b"secret"));
+
+        let mut bp = BackPack::open(RawFile::from(bytes.clone()))?;
+        assert!(bp.get_file("secret/level.dat").is_err());
+        bp.set_name_hasher(hash);
+        assert_eq!(&*bp.get_file("secret/level.dat")?.get_bytes(), b"1");
+        assert_eq!(&*bp.get_file("secret/level (1).dat")?.get_bytes(), b"2");
+        bp.remove_file("secret/level.dat")?;
+        assert_eq!(bp.file_names().len(), 1);
+        bp.close_drop_unwritten_changes()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes() -> Result<(), PackError> {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        use crate::pack::{ChangeEvent, Collision};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        let changes = bp.changes();
+        assert_eq!(changes.try_next(), None);
+
+        bp.add_file(InMemoryFile::from("1").with_name("a"))?;
+        bp.add_file(InMemoryFile::from("2").with_name("a"))?;
+        bp.set_collision_policy(Collision::KeepBoth);
+        bp.add_file(InMemoryFile::from("3").with_name("a"))?;
+        bp.remove_file("a")?;
+
+        assert_eq!(changes.try_next(), Some(ChangeEvent::Added("a".to_string())));
+        assert_eq!(changes.try_next(), Some(ChangeEvent::Modified("a".to_string())));
+        assert_eq!(changes.try_next(), Some(ChangeEvent::Added("a (1)".to_string())));
+
+        let waker = Arc::new(Noop).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut next = std::pin::pin!(changes.next());
+        assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(Some(ChangeEvent::Removed("a".to_string()))));
+
+        let mut next = std::pin::pin!(changes.next());
+        assert_eq!(next.as_mut().poll(&mut cx), Poll::Pending);
+
+        // the stream ends with the backpack
+        drop(bp);
+        assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split() -> Result<(), PackError> {
+        use crate::pack::SplitPack;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("aaaa").with_name("a"))?;
+        bp.add_file(InMemoryFile::from("bbbb").with_name("b"))?;
+        bp.add_file(InMemoryFile::from("a much larger file").with_name("large"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug"))?;
+        bp.set_hidden("debug", true)?;
+
+        let split = bp.export_split(8)?;
+        assert!(split.metadata.len() < 8192);
+        assert_eq!(split.data_files.len(), 3);
+        assert_eq!(split.data_files[0], b"aaaabbbb");
+        assert_eq!(split.data_files[1], b"a much larger file");
+
+        let entries = SplitPack::read_metadata(&mut Cursor::new(&split.metadata))?;
+        let large = entries.iter().find(|e| e.name == "large").unwrap();
+        assert_eq!((large.data_file, large.range()), (1, 0..18));
+        assert!(entries.iter().find(|e| e.name == "debug").unwrap().hidden);
+
+        // the metadata is not a backpack by itself
+        assert!(matches!(BackPack::open(RawFile::from(split.metadata.clone())), Err(PackError::UnsupportedIndexField(_))));
+
+        let mut data_files = split.data_files.iter().map(Cursor::new).collect::<Vec<_>>();
+        let opened = BackPack::open_split(Cursor::new(&split.metadata), &mut data_files)?;
+        let mut large_contents = String::new();
+        opened.get_file("large")?.read_to_string(&mut large_contents)?;
+        assert_eq!(large_contents, "a much larger file");
+        assert!(opened.is_hidden("debug"));
+        drop(opened);
+
+        // only the changed file has to be downloaded
+        bp.add_file(InMemoryFile::from("cccc").with_name("b"))?;
+        let new_entries = SplitPack::read_metadata(&mut Cursor::new(&bp.export_split(8)?.metadata))?;
+        let changed = SplitPack::changed(&entries, &new_entries);
+        assert_eq!(changed.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["b"]);
+
+        // metadata of files survives the split
+        let expiry = std::time::UNIX_EPOCH + std::time::Duration::from_secs(4_000_000_000);
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        bp.set_expiry("a", Some(expiry))?;
+        bp.set_modified("a", Some(modified))?;
+        bp.set_alias("alias", "a")?;
+        bp.set_attribute("a", "owner", "me")?;
+        let split = bp.export_split(8)?;
+        let mut data_files = split.data_files.iter().map(Cursor::new).collect::<Vec<_>>();
+        let opened = BackPack::open_split(Cursor::new(&split.metadata), &mut data_files)?;
+        assert_eq!(opened.expiry("a"), Some(expiry));
+        assert_eq!(opened.modified("a"), Some(modified));
+        assert_eq!(&*opened.get_file("alias")?.get_bytes(), b"aaaa");
+        assert_eq!(opened.attribute("a", "owner"), Some(&b"me"[..]));
+        drop(opened);
+
+        // a data file shorter than the metadata says is refused before reading it
+        let mut data_files = split.data_files.iter().map(|data| Cursor::new(&data[..data.len() / 2])).collect::<Vec<_>>();
+        assert!(matches!(BackPack::open_split(Cursor::new(&split.metadata), &mut data_files), Err(PackError::InvalidEntry)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_all() -> Result<(), PackError> {
+        use crate::pack::Collision;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_collision_policy(Collision::KeepBoth);
+
+        let files = (0..20).map(|i| (format!("gen/{}", i % 10), Cursor::new(vec![i as u8; i])));
+        let added = bp.add_all(files)?;
+        assert_eq!(added.len(), 20);
+        assert_eq!(added[10], std::path::Path::new("gen/0 (1)"));
+
+        let mut contents = Vec::new();
+        bp.get_file("gen/9 (1)")?.read_to_end(&mut contents)?;
+        assert_eq!(contents, vec![19; 19]);
+
+        // a failing source stops the import, but what was read before it is kept
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let files: Vec<(&str, Box<dyn Read>)> = vec![("ok", Box::new(Cursor::new(b"ok"))), ("broken", Box::new(Broken))];
+        assert!(bp.add_all(files).is_err());
+        assert!(bp.get_file("ok").is_ok());
+        assert!(bp.get_file("broken").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_with_options() -> Result<(), PackError> {
+        use crate::pack::EntryOptions;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("abc").with_name("a"))?;
+        let texture = EntryOptions { alignment: Some(4096), ..Default::default() };
+        bp.add_with_options("texture", Cursor::new(vec![1; 100]), texture)?;
+        bp.add_file(InMemoryFile::from("def").with_name("b"))?;
+
+        let bad = EntryOptions { alignment: Some(3), ..Default::default() };
+        assert!(matches!(bp.add_with_options("bad", Cursor::new(b""), bad), Err(PackError::BadAlignment(3))));
+
+        let bp = BackPack::open(bp.close()?.convert_into_memory()?)?;
+        let manifest = bp.manifest();
+        let texture = manifest.entry("texture").unwrap().offset.unwrap();
+        assert_eq!(texture % 4096, 0);
+        // only the texture is padded
+        assert_eq!(manifest.entry("a").unwrap().offset.unwrap(), PACK_HEADER_SIZE + 4096);
+        assert_eq!(manifest.entry("b").unwrap().offset.unwrap(), texture + 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_nested() -> Result<(), PackError> {
+        let no_bad = |_: &std::path::Path, contents: &[u8]| if contents == b"bad" { Err("bad contents".to_string()) } else { Ok(()) };
+
+        let inner = BackPack::create(RawFile::in_memory("inner.bp"))?;
+        inner.add_file(InMemoryFile::from("good").with_name("good.txt"))?;
+        inner.add_file(InMemoryFile::from("bad").with_name("bad.txt"))?;
+        let inner = inner.close()?.convert_into_memory()?.get_bytes().to_vec();
+
+        let mut outer = BackPack::create(RawFile::in_memory("outer.bp"))?;
+        outer.add_validator(no_bad);
+        outer.add_file(InMemoryFile::from("fine").with_name("readme"))?;
+        outer.add_file(InMemoryFile::from(inner).with_name("dlc.bp"))?;
+        let mut outer = BackPack::open(outer.close()?.convert_into_memory()?)?;
+        outer.add_validator(no_bad);
+
+        let report = outer.verify(false)?;
+        assert!(report.is_ok());
+        assert!(report.nested.is_empty());
+
+        let report = outer.verify(true)?;
+        assert!(!report.is_ok());
+        assert_eq!(report.index, None);
+        assert_eq!(report.nested.len(), 1);
+        let (name, nested) = &report.nested[0];
+        assert_eq!(name, "dlc.bp");
+        assert_eq!(nested.files.len(), 1);
+        assert_eq!(nested.files[0].0, "bad.txt");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_quick() -> Result<(), PackError> {
+        use crate::pack::IndexProtection;
+        use std::io::{Seek, SeekFrom, Write};
+
+        let path = std::env::temp_dir().join("backpack_test_verify_quick.bp");
+        let read_write = || std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path);
+
+        let mut bp = BackPack::create(read_write()?)?;
+        bp.set_index_protection(IndexProtection::Checksum);
+        assert!(bp.verify_quick()?.is_ok());
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("large"))?;
+        bp.add_file(InMemoryFile::from("small").with_name("small"))?;
+        bp.flush()?;
+        assert!(bp.verify_quick()?.is_ok());
+        bp.close()?;
+
+        // damaged on disk while it's open
+        let mut bp = BackPack::open(RawFile::open(&path)?)?;
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(PACK_HEADER_SIZE + 20))?;
+        file.write_all(b"garbage")?;
+        assert!(bp.verify_quick()?.index.is_some());
+
+        // cut off
+        bp.close_drop_unwritten_changes()?;
+        let mut bp = BackPack::create(read_write()?)?;
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("large"))?;
+        bp.flush()?;
+        file.set_len(5000)?;
+        let report = bp.verify_quick()?;
+        assert_eq!(report.index, Some("files reach past the end of the file: large".to_string()));
+        bp.close_drop_unwritten_changes()?;
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_pipe_entry() -> Result<(), PackError> {
+        use std::process::Command;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("hello pipes").with_name("in.txt"))?;
+
+        assert_eq!(bp.pipe_entry("in.txt", Command::new("tr").args(["a-z", "A-Z"]))?, b"HELLO PIPES");
+
+        bp.pipe_entry_into("in.txt", Command::new("wc").arg("-c"), "count.txt")?;
+        let mut count = String::new();
+        bp.get_file("count.txt")?.read_to_string(&mut count)?;
+        assert_eq!(count.trim(), "11");
+
+        assert!(matches!(bp.pipe_entry("in.txt", &mut Command::new("false")), Err(PackError::CommandFailed { .. })));
+        assert!(matches!(bp.pipe_entry("missing", &mut Command::new("cat")), Err(PackError::FileNotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_entries() -> Result<(), PackError> {
+        // packed files can't be written to, but their contents can be changed in place
+        let append = |f: InMemoryFile, contents: &[u8]| match f {
+            InMemoryFile::Packed { data, .. } => data.get_bytes().write().extend_from_slice(contents),
+            _ => unreachable!(),
+        };
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("before").with_name("before"))?;
+        bp.add_empty_file("marker")?;
+        bp.add_empty_file(".gitkeep")?;
+        bp.add_empty_file("scratch")?;
+        bp.add_file(InMemoryFile::from("after").with_name("after"))?;
+        bp.add_dir("levels/unused")?;
+        bp.add_dir("levels/unused")?;
+        assert!(bp.is_dir("levels/unused"));
+
+        // empty files are separate files, even though neither has contents
+        append(bp.get_file("scratch")?, b"written");
+        assert_eq!(&*bp.get_file(".gitkeep")?.get_bytes(), b"");
+        bp.remove_file("scratch")?;
+
+        bp.flush()?;
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, vec![".gitkeep", "after", "before", "marker"]);
+
+        let mut bp = BackPack::open(bp.close()?.convert_into_memory()?)?;
+        assert_eq!(bp.dir_names(), vec!["levels/unused"]);
+        assert!(!bp.is_dir("levels"));
+        assert_eq!(bp.manifest().entry("marker").unwrap().offset, None);
+        assert_eq!(bp.manifest().entry(".gitkeep").unwrap().offset, None);
+        assert_eq!(&*bp.get_file("after")?.get_bytes(), b"after");
+
+        // no space is taken up by them
+        let stored = bp.manifest().entries.iter().filter_map(|e| Some(e.offset? + e.length)).max().unwrap();
+        assert_eq!(stored, PACK_HEADER_SIZE + 4096 + 11);
+
+        append(bp.get_file("marker")?, b"now with contents");
+        assert_eq!(&*bp.get_file(".gitkeep")?.get_bytes(), b"");
+        bp.flush()?;
+        assert_eq!(&*bp.get_file("marker")?.get_bytes(), b"now with contents");
+        assert_eq!(&*bp.get_file(".gitkeep")?.get_bytes(), b"");
+
+        // streaming readers see directories as entries too
+        let bytes = bp.close()?.convert_into_memory()?.get_bytes().to_vec();
+        let stream = StreamReader::new(Cursor::new(bytes))?;
+        assert!(stream.entries().any(|(name, length)| name == "levels/unused/" && length == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sorted_entries() -> Result<(), PackError> {
+        use crate::pack::SortKey;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("ccc").with_name("b"))?;
+        bp.add_file(InMemoryFile::from("a").with_name("c"))?;
+        bp.add_file(InMemoryFile::from("bb").with_name("a"))?;
+        bp.flush()?;
+        bp.add_file(InMemoryFile::from("dddd").with_name("0-new"))?;
+
+        assert_eq!(bp.file_names(), vec!["0-new", "a", "b", "c"]);
+
+        let names = |key| bp.entries_sorted_by(key).into_iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names(SortKey::Name), vec!["0-new", "a", "b", "c"]);
+        assert_eq!(names(SortKey::Offset), vec!["b", "c", "a", "0-new"]);
+        assert_eq!(names(SortKey::Size), vec!["c", "a", "b", "0-new"]);
+        assert_eq!(bp.entries_sorted_by(SortKey::Name)[0].offset, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compatibility() -> Result<(), PackError> {
+        use crate::pack::{FormatFeature, IndexProtection, PACK_VERSION};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_index_protection(IndexProtection::ChecksumAndCopy);
+        bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug"))?;
+        bp.set_hidden("debug", true)?;
+        bp.add_dir("empty")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let report = BackPack::compatibility_of(Cursor::new(&bytes))?;
+        assert_eq!(report.version, PACK_VERSION);
+        assert_eq!(report.features.iter().copied().collect::<Vec<_>>(), vec![
+            FormatFeature::HiddenFiles,
+            FormatFeature::Directories,
+            FormatFeature::IndexChecksum,
+            FormatFeature::IndexCopy,
+            FormatFeature::FileChecksums,
+        ]);
+        assert!(report.is_supported());
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_output_mode(OutputMode::ZipHybrid);
+        bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let report = BackPack::compatibility_of(Cursor::new(&bytes))?;
+        assert!(report.features.contains(&FormatFeature::ZipHybrid));
+        assert!(report.is_supported());
+
+        // split metadata can't be opened as a normal backpack, and the report says how to open it
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+        let metadata = bp.export_split(8)?.metadata;
+        bp.close()?;
+        let report = BackPack::compatibility_of(Cursor::new(&metadata))?;
+        assert!(report.features.contains(&FormatFeature::SplitMetadata));
+        assert!(!report.is_supported());
+        assert!(report.problems()[0].contains("open_split"));
+
+        // a newer format version
+        let mut newer = bytes.clone();
+        newer[8..10].copy_from_slice(&(PACK_VERSION + 1).to_le_bytes());
+        let report = BackPack::compatibility_of(Cursor::new(&newer))?;
+        assert!(report.features.is_empty());
+        assert!(!report.is_supported());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fields_version() -> Result<(), PackError> {
+        let version = |bytes: &[u8]| u16::from_le_bytes(bytes[8..10].try_into().unwrap());
+
+        // without fields, readers from before fields existed can read the backpack
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_empty_file("empty")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_eq!(version(&bytes), PACK_VERSION);
+
+        // with them, they see a version they don't know
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_ne!(version(&bytes), PACK_VERSION);
+        assert_eq!(BackPack::compatibility_of(Cursor::new(&bytes))?.version, PACK_VERSION);
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), b"a");
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mmap() -> Result<(), PackError> {
+        use crate::pack::Advice;
+        use std::io::{Seek, SeekFrom, Write};
+
+        // other test runs may have this mapped
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().subsec_nanos();
+        let path = std::env::temp_dir().join(format!("backpack_test_mmap_{}_{}.bp", std::process::id(), nanos));
+        let bp = BackPack::create(RawFile::create(&path)?)?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("large"))?;
+        bp.close()?;
+
+        // Safety: nothing else touches the file during the test
+        let mut file = unsafe { RawFile::open_mmap(&path)? };
+        let len = std::fs::metadata(&path)?.len();
+        assert_eq!(file.seek(SeekFrom::End(0))?, len);
+        assert!(file.write(b"x").is_err());
+        file.seek(SeekFrom::Start(0))?;
+
+        let bp = BackPack::open(file)?;
+        bp.advise("large", Advice::WillNeed)?;
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), b"first");
+        assert_eq!(&*bp.get_file("large")?.get_bytes(), &[7; 10000][..]);
+        bp.close_drop_unwritten_changes()?;
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliases() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("blue").with_name("skins/blue.png"))?;
+        bp.add_file(InMemoryFile::from("red").with_name("skins/red.png"))?;
+        bp.set_alias("default_skin.png", "skins/blue.png")?;
+        assert_eq!(&*bp.get_file("default_skin.png")?.get_bytes(), b"blue");
+
+        assert!(matches!(bp.set_alias("skins/red.png", "skins/blue.png"), Err(PackError::FileExists(_))));
+        assert!(matches!(bp.set_alias("x", "missing"), Err(PackError::FileNotFound(_))));
+        // an alias of an alias points to the file
+        bp.set_alias("fallback.png", "default_skin.png")?;
+        assert_eq!(bp.alias_target("fallback.png").as_deref(), Some("skins/blue.png"));
+
+        // aliases are not files
+        assert_eq!(bp.file_names().len(), 2);
+        let file = bp.close()?;
+
+        let mut bp = BackPack::open(file)?;
+        assert_eq!(bp.file_names().len(), 2);
+        assert_eq!(bp.aliases(), vec![
+            ("default_skin.png".to_string(), "skins/blue.png".to_string()),
+            ("fallback.png".to_string(), "skins/blue.png".to_string()),
+        ]);
+        bp.set_alias("default_skin.png", "skins/red.png")?;
+        assert_eq!(&*bp.get_file("default_skin.png")?.get_bytes(), b"red");
+
+        // aliases of removed files are dropped
+        bp.remove_file("skins/blue.png")?;
+        assert!(bp.get_file("fallback.png").is_err());
+        bp.flush()?;
+        assert_eq!(bp.aliases(), vec![("default_skin.png".to_string(), "skins/red.png".to_string())]);
+        assert!(bp.remove_alias("default_skin.png"));
+        assert!(bp.get_file("default_skin.png").is_err());
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_retry() -> Result<(), PackError> {
+        use crate::pack::{FaultyFile, Operation, Trigger};
+        use crate::remote::RetryPolicy;
+        use std::io::ErrorKind;
+        use std::time::Duration;
+
+        let file = FaultyFile::new(RawFile::in_memory("test.bp"))
+            .fail(Operation::Read, Trigger::NthCall(0), ErrorKind::Interrupted)
+            .fail(Operation::Read, Trigger::NthCall(1), ErrorKind::Interrupted);
+        let mut bp = BackPack::create(RawFile::from(file))?;
+        bp.add_file(InMemoryFile::from(vec![b'a'; 100]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![b'b'; 100]).with_name("b"))?;
+        bp.flush()?;
+        // both files are dropped from memory and have to be read again
+        bp.set_memory_limit(Some(50));
+        drop(bp.add_file(InMemoryFile::from("c").with_name("c"))?);
+
+        assert!(bp.get_file("a").is_err());
+
+        bp.set_read_retry(RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            jitter: false,
+            ..RetryPolicy::default()
+        });
+        assert_eq!(&*bp.get_file("b")?.get_bytes(), &[b'b'; 100][..]);
+        bp.close_drop_unwritten_changes()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_writer() -> Result<(), PackError> {
+        use crate::pack::PackWriter;
+
+        let mut writer = PackWriter::new(RawFile::in_memory("test.bp"))?;
+        assert_eq!(writer.add_entry("a", Cursor::new(vec![1; 100]))?, 100);
+        writer.add_entry("empty", std::io::empty())?;
+        // more entries than fit in one block of the table of contents
+        for i in 0..300 {
+            writer.add_entry(format!("many/{:03}", i), format!("file {}", i).as_bytes())?;
+        }
+        writer.add_hidden_entry("debug", "debug".as_bytes())?;
+        assert!(matches!(writer.add_entry("a", std::io::empty()), Err(PackError::FileExists(_))));
+        let file = writer.finish()?;
+
+        let mut bp = BackPack::open(file)?;
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), &[1; 100][..]);
+        assert!(bp.get_file("empty")?.get_bytes().is_empty());
+        assert_eq!(&*bp.get_file("many/299")?.get_bytes(), b"file 299");
+        assert!(bp.is_hidden("debug"));
+        assert_eq!(bp.file_names().len(), 302);
+
+        // flushing moves the table of contents to the front
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let mut reader = StreamReader::new(bytes.as_slice())?;
+        assert_eq!(reader.entries().count(), 302);
+        assert!(reader.next_entry()?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_reader() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+        use std::io::{Seek, SeekFrom};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        bp.add_file(InMemoryFile::from((0..=255).collect::<Vec<u8>>()).with_name("bytes"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug"))?;
+        bp.set_hidden("debug", true)?;
+        bp.set_alias("b", "bytes")?;
+        bp.add_dir("empty")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.file_names(), ["a", "bytes"]);
+        assert!(reader.is_hidden("debug"));
+        assert_eq!(reader.read("a")?, b"first");
+        assert!(matches!(reader.get("missing"), Err(PackError::FileNotFound(_))));
+
+        // entries read what they need, from wherever they are
+        let mut entry = reader.get("b")?;
+        assert_eq!((entry.name(), entry.len()), ("bytes", 256));
+        entry.seek(SeekFrom::End(-6))?;
+        let mut tail = Vec::new();
+        entry.read_to_end(&mut tail)?;
+        assert_eq!(tail, [250, 251, 252, 253, 254, 255]);
+
+        let mut a = reader.get("a")?;
+        entry.seek(SeekFrom::Start(1))?;
+        let (mut x, mut y) = ([0; 2], [0; 2]);
+        a.read_exact(&mut x)?;
+        entry.read_exact(&mut y)?;
+        assert_eq!((&x, &y), (b"fi", &[1, 2]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_entry() -> Result<(), PackError> {
+        use std::io::Write;
+        use std::path::Path;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        {
+            let mut discarded = bp.temp_entry("staging/")?;
+            let mut kept = bp.temp_entry("staging/")?;
+            assert_eq!(discarded.name(), Path::new("staging/0.tmp"));
+            assert_eq!(kept.name(), Path::new("staging/1.tmp"));
+
+            discarded.write_all(b"not needed")?;
+            discarded.flush()?;
+            assert_eq!(&*bp.get_file("staging/0.tmp")?.get_bytes(), b"not needed");
+            drop(discarded);
+            assert!(bp.get_file("staging/0.tmp").is_err());
+
+            kept.write_all(b"derived")?;
+            let f = kept.persist("derived.bin")?;
+            assert_eq!(&*f.get_bytes(), b"derived");
+        }
+        assert_eq!(bp.file_names(), ["derived.bin"]);
+
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(bp.file_names(), ["derived.bin"]);
+        bp.close_drop_unwritten_changes()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze() -> Result<(), PackError> {
+        use crate::pack::{FreezeOptions, SortKey, PACK_HEADER_SIZE, TOC_SIZE};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![1; 1000]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![1; 1000]).with_name("copy of a"))?;
+        bp.add_file(InMemoryFile::from(vec![2; 1000]).with_name("removed"))?;
+        bp.add_file(InMemoryFile::from("b").with_name("b"))?;
+        bp.set_alias("alias of b", "b")?;
+        bp.remove_file("removed")?;
+
+        let mut trace = AccessTrace::new();
+        trace.record("b");
+        let mut frozen = Vec::new();
+        bp.freeze(&mut frozen, FreezeOptions { trace: Some(trace), ..FreezeOptions::default() })?;
+        bp.close()?;
+
+        // one copy of the duplicated contents, and nothing of the removed file
+        let size = frozen.len();
+        assert!(size < 2 * PACK_HEADER_SIZE as usize + TOC_SIZE as usize + 1000);
+
+        let bp = BackPack::open(frozen)?;
+        assert_eq!(bp.file_names(), ["a", "b", "copy of a"]);
+        assert_eq!(&*bp.get_file("copy of a")?.get_bytes(), &[1; 1000][..]);
+        assert_eq!(&*bp.get_file("alias of b")?.get_bytes(), b"b");
+        // traced files come first
+        let entries = bp.entries_sorted_by(SortKey::Offset);
+        assert_eq!(entries[0].name, "b");
+        bp.close_drop_unwritten_changes()?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn test_compression() -> Result<(), PackError> {
+        use crate::pack::{Compression, EntryOptions, PackReader, PACK_HEADER_SIZE, TOC_SIZE};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_compression(Compression::Deflate)?;
+        bp.add_file(InMemoryFile::from(vec![7; 10000]).with_name("compressed"))?;
+        bp.add_with_options("stored", &[3; 10000][..], EntryOptions { compression: Some(Compression::None), ..EntryOptions::default() })?;
+        bp.add_file(InMemoryFile::from("tiny").with_name("tiny"))?;
+        bp.flush()?;
+        // still readable after flushing, and flushing again keeps it compressed
+        assert_eq!(&*bp.get_file("compressed")?.get_bytes(), &[7; 10000][..]);
+        bp.flush()?;
+
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        // only the stored file takes up its full size
+        assert!(bytes.len() < (PACK_HEADER_SIZE + TOC_SIZE as u64) as usize + 10000 + 1000);
+
+        let reader = PackReader::open(bytes.clone())?;
+        assert_eq!(reader.read("compressed")?, vec![7; 10000]);
+        assert!(reader.get("compressed").is_err());
+        assert_eq!(reader.read("stored")?, vec![3; 10000]);
+
+        let bp = BackPack::open(bytes.clone())?;
+        assert_eq!(bp.file_names(), ["compressed", "stored", "tiny"]);
+        assert_eq!(&*bp.get_file("compressed")?.get_bytes(), &[7; 10000][..]);
+        assert_eq!(&*bp.get_file("stored")?.get_bytes(), &[3; 10000][..]);
+        assert_eq!(&*bp.get_file("tiny")?.get_bytes(), b"tiny");
+        bp.close_drop_unwritten_changes()?;
+
+        // files stay compressed when an opened backpack is flushed
+        let mut bp = BackPack::open(bytes.clone())?;
+        bp.flush()?;
+        assert_eq!(bp.close()?.into_memory().ok().unwrap().get_bytes().len(), bytes.len());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn test_min_compression_ratio() -> Result<(), PackError> {
+        use std::io::{Cursor, Read};
+        use crate::pack::{Compression, PackReader};
+        use crate::testing::{Content, FixtureBuilder, SizeDistribution};
+
+        let random = FixtureBuilder::new(1).entries(4).sizes(SizeDistribution::Fixed(4096)).content(Content::Random).build()?;
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        assert!(bp.set_min_compression_ratio(1.0).is_err());
+        bp.set_compression(Compression::Deflate)?;
+        bp.set_min_compression_ratio(0.05)?;
+        for (name, contents) in &random.files {
+            bp.add_file(InMemoryFile::from(contents.clone()).with_name(name))?;
+        }
+        bp.add_file(InMemoryFile::from("grass stone ".repeat(500)).with_name("text"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // random bytes don't compress, they fall back to being stored, and that's recorded
+        let entries = BackPack::list_fast_of(Cursor::new(bytes.clone()))?;
+        for entry in &entries {
+            let random = entry.name != "text";
+            assert_eq!(entry.compressed, !random, "{}", entry.name);
+            assert_eq!(entry.stored_as_is, random, "{}", entry.name);
+        }
+
+        // stored files can be streamed, and survive flushing again
+        let reader = PackReader::open(bytes.clone())?;
+        let (name, contents) = &random.files[0];
+        let mut streamed = Vec::new();
+        reader.get(name)?.read_to_end(&mut streamed)?;
+        assert_eq!(streamed, *contents);
+        assert_eq!(reader.read_to_string("text")?, "grass stone ".repeat(500));
+
+        let mut bp = BackPack::open(bytes)?;
+        bp.set_compression(Compression::Deflate)?;
+        bp.set_min_compression_ratio(0.05)?;
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_eq!(BackPack::list_fast_of(Cursor::new(bytes))?.iter().filter(|entry| entry.stored_as_is).count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "zstd", feature = "lz4"))]
+    fn test_zstd_lz4() -> Result<(), PackError> {
+        use std::io::Cursor;
+        use crate::pack::{Compression, EntryOptions, PackReader};
+
+        let text = "grass stone ".repeat(1000);
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for (name, method) in [("zstd", Compression::Zstd { level: 3 }), ("lz4", Compression::Lz4)] {
+            let options = EntryOptions { compression: Some(method), ..EntryOptions::default() };
+            bp.add_with_options(name, text.as_bytes(), options)?;
+        }
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(bytes.len() < text.len());
+        assert!(BackPack::list_fast_of(Cursor::new(bytes.clone()))?.iter().all(|entry| entry.compressed));
+
+        let bp = BackPack::open(bytes.clone())?;
+        assert_eq!(&*bp.get_file("zstd")?.get_bytes(), text.as_bytes());
+        assert_eq!(&*bp.get_file("lz4")?.get_bytes(), text.as_bytes());
+        bp.close_drop_unwritten_changes()?;
+
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.read_to_string("zstd")?, text);
+        assert_eq!(reader.read_to_string("lz4")?, text);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(all(feature = "zstd", feature = "lz4")))]
+    fn test_unsupported_compression() -> Result<(), PackError> {
+        use crate::pack::{Compression, EntryOptions};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        let unsupported = [Compression::Zstd { level: 3 }, Compression::Lz4].into_iter().filter(|method| !method.is_supported());
+        for method in unsupported {
+            assert!(matches!(bp.set_compression(method), Err(PackError::UnsupportedCompression(m)) if m == method));
+            let options = EntryOptions { compression: Some(method), ..EntryOptions::default() };
+            assert!(matches!(bp.add_with_options("a", &b"a"[..], options), Err(PackError::UnsupportedCompression(_))));
+        }
+        bp.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksums() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("intact").with_name("intact"))?;
+        bp.add_file(InMemoryFile::from("bit rot").with_name("damaged"))?;
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let position = bytes.windows(7).position(|w| w == b"bit rot").unwrap();
+        bytes[position] ^= 1;
+
+        // without verification the damage goes unnoticed
+        let bp = BackPack::open(bytes.clone())?;
+        assert_eq!(&*bp.get_file("damaged")?.get_bytes(), b"cit rot");
+        bp.close_drop_unwritten_changes()?;
+
+        let mut bp = BackPack::open(bytes.clone())?;
+        bp.set_verify_checksums(true);
+        assert_eq!(&*bp.get_file("intact")?.get_bytes(), b"intact");
+        assert!(matches!(bp.get_file("damaged"), Err(PackError::ChecksumMismatch(_))));
+
+        let report = bp.verify_all()?;
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].0, "damaged");
+        assert!(!report.is_ok());
+        bp.close_drop_unwritten_changes()?;
+
+        let mut reader = PackReader::open(bytes)?;
+        assert_eq!(reader.read("damaged")?, b"cit rot");
+        reader.set_verify(true);
+        assert_eq!(reader.read("intact")?, b"intact");
+        assert!(matches!(reader.read("damaged"), Err(PackError::ChecksumMismatch(_))));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_encryption_forged() -> Result<(), PackError> {
+        use std::collections::{HashMap, HashSet};
+        use std::path::Path;
+        use crate::pack::compression::Compressed;
+        use crate::pack::{Encryption, PackReader, ENCRYPTION_ENTRY};
+
+        // the encryption entry of a real encrypted backpack, with a file which isn't encrypted
+        let key = Encryption::new("hunter2").with_iterations(1).key()?.encode();
+        let entries: [(&str, &[u8]); 2] = [("a", b"evil"), (ENCRYPTION_ENTRY, &key)];
+        let mut forged = Vec::new();
+        BackPack::write_native(&mut forged, &entries, |_| 1, &HashMap::new(), &HashSet::new(), &Compressed::new(), &HashSet::new())?;
+
+        assert!(matches!(BackPack::open_encrypted(forged.clone(), "hunter2"), Err(PackError::Unencrypted(name)) if name == Path::new("a")));
+        assert!(matches!(PackReader::open_encrypted(forged, "hunter2"), Err(PackError::Unencrypted(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_encryption() -> Result<(), PackError> {
+        use crate::pack::{Encryption, FormatFeature, PackReader};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_encryption(Some(Encryption::new("hunter2").with_iterations(1)))?;
+        bp.add_file(InMemoryFile::from("top secret contents").with_name("secret"))?;
+        bp.add_empty_file("empty")?;
+        bp.flush()?;
+        assert_eq!(&*bp.get_file("secret")?.get_bytes(), b"top secret contents");
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(!bytes.windows(19).any(|w| w == b"top secret contents"));
+
+        assert!(matches!(BackPack::open(bytes.clone()), Err(PackError::Encrypted)));
+        assert!(matches!(BackPack::open_encrypted(bytes.clone(), "hunter3"), Err(PackError::WrongPassphrase)));
+        assert!(BackPack::compatibility_of(Cursor::new(&bytes))?.features.contains(&FormatFeature::Encryption));
+
+        // files stay encrypted with the same key when flushing again
+        let mut bp = BackPack::open_encrypted(bytes, "hunter2")?;
+        assert_eq!(bp.file_names(), ["empty", "secret"]);
+        assert_eq!(&*bp.get_file("secret")?.get_bytes(), b"top secret contents");
+        bp.add_file(InMemoryFile::from("more secrets").with_name("more"))?;
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(!bytes.windows(12).any(|w| w == b"more secrets"));
+
+        let reader = PackReader::open_encrypted(bytes.clone(), "hunter2")?;
+        assert_eq!(reader.read("secret")?, b"top secret contents");
+        assert_eq!(reader.read("more")?, b"more secrets");
+        assert!(reader.get("secret").is_err());
+        assert!(matches!(PackReader::open(bytes.clone()), Err(PackError::Encrypted)));
+
+        // and can be decrypted for good
+        let mut bp = BackPack::open_encrypted(bytes, "hunter2")?;
+        bp.set_encryption(None)?;
+        bp.flush()?;
+        let plain = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(plain.windows(19).any(|w| w == b"top secret contents"));
+        assert_eq!(&*BackPack::open(plain)?.get_file("more")?.get_bytes(), b"more secrets");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async() -> Result<(), PackError> {
+        use crate::pack::{AsyncPackReader, AsyncPackWriter, AsyncRawFile, PackReader};
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            let mut writer = AsyncPackWriter::new(AsyncRawFile::in_memory()).await?;
+            writer.add_entry("a", &b"first"[..]).await?;
+            writer.add_hidden_entry("b", &b"second"[..]).await?;
+            writer.add_entry("empty", &b""[..]).await?;
+            assert!(matches!(writer.add_entry("a", &b""[..]).await, Err(PackError::FileExists(_))));
+            let bytes = writer.finish().await?.into_memory().unwrap();
+
+            // readable by the blocking reader too
+            let reader = PackReader::open(bytes.clone())?;
+            assert_eq!(reader.read("b")?, b"second");
+
+            let mut reader = AsyncPackReader::open(bytes.into()).await?;
+            assert_eq!(reader.file_names(), ["a", "empty"]);
+            assert!(reader.is_hidden("b"));
+            assert_eq!(reader.read("a").await?, b"first");
+            assert_eq!(reader.read("b").await?, b"second");
+            assert_eq!(reader.read("empty").await?, b"");
+            assert!(matches!(reader.read("c").await, Err(PackError::FileNotFound(_))));
+
+            // and through a file on disk
+            let path = std::env::temp_dir().join("backpack_test_async.bp");
+            let mut writer = AsyncPackWriter::new(AsyncRawFile::create(&path).await?).await?;
+            writer.add_entry("on disk", &[5; 100_000][..]).await?;
+            writer.finish().await?;
+            let mut reader = AsyncPackReader::open(AsyncRawFile::open(&path).await?).await?;
+            assert_eq!(reader.read("on disk").await?, vec![5; 100_000]);
+            std::fs::remove_file(path)?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_directories() -> Result<(), PackError> {
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("wall").with_name("assets/textures/wall.png"))?;
+        bp.add_file(InMemoryFile::from("floor").with_name("./assets//textures/floor.png"))?;
+        bp.add_file(InMemoryFile::from("theme").with_name("assets/sounds/../music/theme.ogg"))?;
+        bp.add_file(InMemoryFile::from("readme").with_name("readme.txt"))?;
+        bp.add_dir("assets/empty")?;
+
+        // every path to a file leads to the same file
+        assert_eq!(&*bp.get_file("assets/textures/floor.png")?.get_bytes(), b"floor");
+        assert_eq!(&*bp.get_file("assets/./textures/../textures/wall.png")?.get_bytes(), b"wall");
+        assert_eq!(&*bp.get_file("../readme.txt")?.get_bytes(), b"readme");
+        assert!(matches!(bp.add_file(InMemoryFile::from("x").with_name("assets/..")), Err(PackError::NoName)));
+
+        assert_eq!(bp.list_dir("")?, ["assets/", "readme.txt"]);
+        assert_eq!(bp.list_dir("/")?, ["assets/", "readme.txt"]);
+        assert_eq!(bp.list_dir("assets")?, ["empty/", "music/", "textures/"]);
+        assert_eq!(bp.list_dir("assets/textures/")?, ["floor.png", "wall.png"]);
+        assert!(bp.list_dir("assets/empty")?.is_empty());
+        assert!(matches!(bp.list_dir("assets/missing"), Err(PackError::FileNotFound(_))));
+        assert!(matches!(bp.list_dir("readme.txt"), Err(PackError::FileNotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_directory() -> Result<(), PackError> {
+        use crate::pack::{DirectoryOptions, PackWriter};
+
+        let src = std::env::temp_dir().join("backpack_test_from_directory");
+        let dst = std::env::temp_dir().join("backpack_test_extract_to");
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+        std::fs::create_dir_all(src.join("assets/textures"))?;
+        std::fs::create_dir_all(src.join("assets/empty"))?;
+        std::fs::create_dir_all(src.join("target/debug"))?;
+        std::fs::write(src.join("readme.txt"), "readme")?;
+        std::fs::write(src.join("assets/textures/wall.png"), "wall")?;
+        std::fs::write(src.join("assets/textures/wall.png.tmp"), "scratch")?;
+        std::fs::write(src.join("target/debug/build.log"), "log")?;
+
+        let options = DirectoryOptions { exclude: vec!["target".to_string(), "**/*.tmp".to_string()], ..DirectoryOptions::default() };
+        let bp = BackPack::from_directory(&src, &options)?;
+        assert_eq!(bp.file_names(), ["assets/textures/wall.png", "readme.txt"]);
+        assert!(bp.is_dir("assets/empty"));
+
+        let options = DirectoryOptions { include: vec!["**/*.png".to_string()], ..DirectoryOptions::default() };
+        assert_eq!(BackPack::from_directory(&src, &options)?.file_names(), ["assets/textures/wall.png"]);
+
+        let bp = BackPack::from_directory(&src, &DirectoryOptions { exclude: vec!["target".to_string()], ..DirectoryOptions::default() })?;
+        bp.extract_to(&dst)?;
+        assert_eq!(std::fs::read(dst.join("readme.txt"))?, b"readme");
+        assert_eq!(std::fs::read(dst.join("assets/textures/wall.png.tmp"))?, b"scratch");
+        assert!(dst.join("assets/empty").is_dir());
+        assert!(!dst.join("target").exists());
+
+        // names from elsewhere can't escape the directory
+        let mut writer = PackWriter::new(RawFile::in_memory("evil.bp"))?;
+        writer.add_entry("../evil", &b"evil"[..])?;
+        let bp = BackPack::open(writer.finish()?.convert_into_memory()?)?;
+        assert!(matches!(bp.extract_to(&dst), Err(PackError::UnsafePath(_))));
+        assert!(!dst.join("../evil").exists());
+
+        std::fs::remove_dir_all(src)?;
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> Result<(), PackError> {
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        let f = bp.add_file(InMemoryFile::from("twelve bytes").with_name("file"))?;
+        let metadata = RawFile::from(f).metadata()?;
+        assert_eq!(metadata.len, 12);
+        assert_eq!(metadata.modified, None);
+        assert_eq!(RawFile::in_memory("empty").metadata()?.len, 0);
+
+        let path = std::env::temp_dir().join("backpack_test_metadata.bp");
+        std::fs::write(&path, "on disk")?;
+        let metadata = RawFile::open(&path)?.metadata()?;
+        assert_eq!(metadata.len, 7);
+        assert!(metadata.modified.is_some());
+        assert_eq!(metadata.readonly, Some(false));
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("a").with_name("a.txt"))?;
+        bp.add_file(InMemoryFile::from("b").with_name("dir/b.txt"))?;
+        bp.add_file(InMemoryFile::from("secret").with_name("secret"))?;
+        bp.set_hidden("secret", true)?;
+        bp.add_dir("empty")?;
+
+        let mut overlay = bp.overlay();
+        overlay.write("a.txt", "changed")?;
+        overlay.write("./new.txt", "new")?;
+        overlay.remove("dir/b.txt")?;
+        assert!(matches!(overlay.remove("missing"), Err(PackError::FileNotFound(_))));
+
+        assert_eq!(overlay.read("a.txt")?, b"changed");
+        assert_eq!(overlay.read("new.txt")?, b"new");
+        assert!(matches!(overlay.read("dir/b.txt"), Err(PackError::FileNotFound(_))));
+        assert_eq!(overlay.read("secret")?, b"secret");
+        assert_eq!(overlay.file_names(), ["a.txt", "new.txt"]);
+        assert_eq!(overlay.changed(), ["a.txt", "dir/b.txt", "new.txt"]);
+
+        // the backpack itself is left alone
+        assert_eq!(&*bp.get_file("a.txt")?.get_bytes(), b"a");
+        assert!(bp.get_file("new.txt").is_err());
+
+        let committed = overlay.commit()?;
+        assert_eq!(committed.file_names(), ["a.txt", "new.txt"]);
+        assert_eq!(&*committed.get_file("a.txt")?.get_bytes(), b"changed");
+        assert!(committed.is_hidden("secret"));
+        assert!(committed.is_dir("empty"));
+
+        overlay.discard();
+        assert_eq!(overlay.read("a.txt")?, b"a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_append() -> Result<(), PackError> {
+        use crate::pack::{IndexProtection, PackReader, PackWriter, TOC_SIZE};
+
+        let path = std::env::temp_dir().join("backpack_test_open_append.bp");
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_index_protection(IndexProtection::ChecksumAndCopy);
+        bp.add_file(InMemoryFile::from("a").with_name("a.txt"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug"))?;
+        bp.set_hidden("debug", true)?;
+        let before = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        std::fs::write(&path, &before)?;
+
+        // the protection can't be kept, which has to be agreed to
+        assert!(matches!(BackPack::open_append(&path), Err(PackError::ProtectedIndex)));
+        assert_eq!(std::fs::read(&path)?, before);
+        let file = std::fs::File::options().read(true).write(true).open(&path)?;
+        let mut writer = PackWriter::open_append_dropping_protection(RawFile::from(file))?;
+        writer.add_entry("b.txt", "b".as_bytes())?;
+        // more entries than fit in the old table of contents
+        for i in 0..300 {
+            writer.add_entry(format!("many/{:03}", i), format!("file {}", i).as_bytes())?;
+        }
+        assert!(matches!(writer.add_entry("a.txt", std::io::empty()), Err(PackError::FileExists(_))));
+        writer.finish()?;
+
+        // the old entries weren't moved
+        let after = std::fs::read(&path)?;
+        let data = PACK_HEADER_SIZE as usize + TOC_SIZE as usize;
+        assert_eq!(after[data..data + 6], before[data..data + 6]);
+
+        let bp = BackPack::open(RawFile::open(&path)?)?;
+        assert_eq!(&*bp.get_file("a.txt")?.get_bytes(), b"a");
+        assert_eq!(&*bp.get_file("b.txt")?.get_bytes(), b"b");
+        assert_eq!(&*bp.get_file("many/299")?.get_bytes(), b"file 299");
+        assert!(bp.is_hidden("debug"));
+        assert_eq!(bp.file_names().len(), 302);
+        bp.close_drop_unwritten_changes()?;
+
+        // appending again, to a backpack with toc blocks in the middle
+        let mut writer = BackPack::open_append(&path)?;
+        writer.add_entry("c.txt", "c".as_bytes())?;
+        writer.finish()?;
+        let reader = PackReader::open(RawFile::open(&path)?)?;
+        assert_eq!(reader.read("a.txt")?, b"a");
+        assert_eq!(reader.read("many/150")?, b"file 150");
+        assert_eq!(reader.read("c.txt")?, b"c");
+        std::fs::remove_file(path)?;
+
+        let mut writer = PackWriter::new(RawFile::in_memory("test.bp"))?;
+        writer.add_entry("first", "first".as_bytes())?;
+        let bytes = writer.finish()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let mut writer = PackWriter::open_append(RawFile::from(bytes))?;
+        writer.add_entry("second", "second".as_bytes())?;
+        let bp = BackPack::open(writer.finish()?)?;
+        assert_eq!(bp.file_names(), ["first", "second"]);
+        assert_eq!(&*bp.get_file("first")?.get_bytes(), b"first");
+        assert_eq!(&*bp.get_file("second")?.get_bytes(), b"second");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_and_compact() -> Result<(), PackError> {
+        let path = std::env::temp_dir().join("backpack_test_compact.bp");
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![1; 1000]).with_name("big"))?;
+        bp.add_file(InMemoryFile::from("small").with_name("small"))?;
+        std::fs::write(&path, &*bp.close()?.into_memory().ok().unwrap().get_bytes())?;
+
+        let mut writer = BackPack::open_append(&path)?;
+        assert_eq!(writer.dead_space(), 0);
+        writer.remove_entry("big")?;
+        assert!(matches!(writer.remove_entry("big"), Err(PackError::FileNotFound(_))));
+        assert_eq!(writer.dead_space(), 1000);
+        writer.add_entry("new", "new".as_bytes())?;
+        writer.remove_entry("new")?;
+        writer.add_entry("new", "newer".as_bytes())?;
+        assert_eq!(writer.dead_space(), 1003);
+        writer.finish()?;
+
+        let bp = BackPack::open(RawFile::open(&path)?)?;
+        assert_eq!(bp.file_names(), ["new", "small"]);
+        assert_eq!(&*bp.get_file("new")?.get_bytes(), b"newer");
+        bp.close_drop_unwritten_changes()?;
+
+        assert!(BackPack::compact(&path)? >= 1003);
+        assert_eq!(BackPack::open_append(&path)?.dead_space(), 0);
+        let bp = BackPack::open(RawFile::open(&path)?)?;
+        assert_eq!(bp.file_names(), ["new", "small"]);
+        assert_eq!(&*bp.get_file("small")?.get_bytes(), b"small");
+        bp.close_drop_unwritten_changes()?;
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe() -> Result<(), PackError> {
+        use crate::pack::{PackReader, PackWriter, TOC_SIZE};
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![5; 1000]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![5; 1000]).with_name("b"))?;
+        bp.add_file(InMemoryFile::from(vec![6; 1000]).with_name("c"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_eq!(bytes.len() as u64, PACK_HEADER_SIZE + TOC_SIZE as u64 + 2000);
+
+        // changing one of them leaves the other alone, after opening and after flushing
+        let mut bp = BackPack::open(bytes)?;
+        bp.get_file("a")?.set_len(10)?;
+        assert_eq!(&*bp.get_file("b")?.get_bytes(), &[5; 1000][..]);
+        bp.add_file(InMemoryFile::from(vec![5; 1000]).with_name("d"))?;
+        bp.flush()?;
+        bp.get_file("d")?.set_len(20)?;
+        assert_eq!(&*bp.get_file("b")?.get_bytes(), &[5; 1000][..]);
+        bp.close_drop_unwritten_changes()?;
+
+        let mut writer = PackWriter::new(RawFile::in_memory("test.bp"))?;
+        writer.add_entry("a", &[5; 1000][..])?;
+        assert_eq!(writer.add_entry("b", &[5; 1000][..])?, 1000);
+        writer.add_entry("c", &[6; 1000][..])?;
+        assert_eq!(writer.size(), 2000);
+        let bytes = writer.finish()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // also against what's in a backpack appended to
+        let mut writer = PackWriter::open_append(RawFile::from(bytes))?;
+        writer.add_entry("d", &[6; 1000][..])?;
+        assert_eq!(writer.size(), 2000);
+        let reader = PackReader::open(writer.finish()?)?;
+        assert_eq!(reader.read("b")?, vec![5; 1000]);
+        assert_eq!(reader.read("d")?, vec![6; 1000]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar() -> Result<(), PackError> {
+        use std::time::{Duration, UNIX_EPOCH};
+        use crate::pack::FormatFeature;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("readme").with_name("readme.txt"))?;
+        bp.add_file(InMemoryFile::from(vec![3; 2000]).with_name("assets/big.bin"))?;
+        bp.add_dir("empty")?;
+        bp.set_modified("readme.txt", Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)))?;
+        assert!(bp.set_modified("missing", None).is_err());
+        bp.flush()?;
+        assert_eq!(bp.modified("readme.txt"), Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)));
+
+        let mut tar = Vec::new();
+        bp.to_tar(&mut tar)?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let report = BackPack::compatibility_of(Cursor::new(&bytes))?;
+        assert!(report.features.contains(&FormatFeature::ModificationTimes));
+
+        let bp = BackPack::from_tar(tar.as_slice())?;
+        assert_eq!(bp.file_names(), ["assets/big.bin", "readme.txt"]);
+        assert_eq!(&*bp.get_file("assets/big.bin")?.get_bytes(), &[3; 2000][..]);
+        assert!(bp.is_dir("empty"));
+        assert_eq!(bp.modified("readme.txt"), Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)));
+        assert_eq!(bp.modified("assets/big.bin"), Some(UNIX_EPOCH));
+
+        // a member which is in the archive twice is kept as it was last
+        let mut twice = tar[..tar.len() - 1024].to_vec();
+        twice.extend_from_slice(&tar);
+        let bp = BackPack::from_tar(twice.as_slice())?;
+        assert_eq!(bp.file_names(), ["assets/big.bin", "readme.txt"]);
+
+        assert!(matches!(BackPack::from_tar(&tar[..700]), Err(PackError::Io(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_zip() -> Result<(), PackError> {
+        let file = RawFile::in_memory("test.bp");
+        let mut bp = BackPack::create(file)?;
+        bp.set_output_mode(OutputMode::ZipHybrid);
+        let f: InMemoryFile = "first".into();
+        bp.add_file(f.with_name("a.txt"))?;
+        let f: InMemoryFile = "second".into();
+        bp.add_file(f.with_name("dir/b.txt"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // read through the zip central directory, not the backpack index
+        let bp = BackPack::from_zip(std::io::Cursor::new(bytes))?;
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, vec!["a.txt", "dir/b.txt"]);
+        assert_eq!(&*bp.get_file("dir/b.txt")?.get_bytes(), b"second");
+        assert!(bp.modified("a.txt").is_some());
+
+        assert!(BackPack::from_zip(std::io::Cursor::new(b"not a zip".to_vec())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        let f: InMemoryFile = "first".into();
+        bp.add_file(f.with_name("a.txt"))?;
+        let f: InMemoryFile = "second".into();
+        bp.add_file(f.with_name("b.txt"))?;
+        bp.add_empty_file("c.txt")?;
+        bp.add_dir("dir")?;
+        bp.flush()?;
+
+        let entries = bp.entries();
+        assert_eq!(entries.len(), 3);
+        let names = entries.rev().map(|entry| entry.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["c.txt", "b.txt", "a.txt"]);
+
+        let b = bp.entries().nth(1).unwrap();
+        assert_eq!(b.size, 6);
+        let offset = b.offset.unwrap() as usize;
+        assert!(bp.entries().next_back().unwrap().offset.is_none());
+
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        for entry in &mut bp.entries_mut() {
+            entry.metadata.modified = Some(time);
+            entry.metadata.tier = Some(Tier::Hot);
+        }
+        assert_eq!(bp.modified("b.txt"), Some(time));
+        assert_eq!(bp.entries().next().unwrap().metadata.tier, Some(Tier::Hot));
+
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert_eq!(&bytes[offset..offset + 6], b"second");
+        let bp = BackPack::open(bytes)?;
+        assert!(bp.entries().all(|entry| entry.metadata.modified == Some(time)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for name in ["textures/wall.png", "textures/floor.png", "textures/old/wall.png", "textures/readme.txt", "shaders/a.glsl", "texturesx.png"] {
+            bp.add_empty_file(name)?;
+        }
+        bp.add_dir("shaders/empty")?;
+
+        let names = |entries: crate::pack::Entries| entries.map(|entry| entry.name).collect::<Vec<_>>();
+        assert_eq!(names(bp.with_prefix("shaders/")), vec!["shaders/a.glsl"]);
+        assert_eq!(names(bp.find("textures/*.png")), vec!["textures/floor.png", "textures/wall.png"]);
+        assert_eq!(names(bp.find("textures/**/wall.png")), vec!["textures/old/wall.png", "textures/wall.png"]);
+        assert_eq!(bp.find("*.png").len(), 1);
+        assert_eq!(bp.with_prefix("missing/").len(), 0);
+
+        // the sorted names are kept up to date
+        bp.add_empty_file("shaders/b.glsl")?;
+        assert_eq!(bp.with_prefix("shaders/").len(), 2);
+        bp.remove_file("shaders/a.glsl")?;
+        bp.set_hidden("shaders/b.glsl", true)?;
+        assert_eq!(bp.with_prefix("shaders/").len(), 0);
+        bp.flush()?;
+        assert_eq!(bp.with_prefix("textures/").len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_cache() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from(vec![1; 10]).with_name("a"))?;
+        bp.add_file(InMemoryFile::from(vec![2; 10]).with_name("b"))?;
+        bp.add_file(InMemoryFile::from(vec![3; 100]).with_name("large"))?;
+        bp.set_alias("alias", "a")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let reader = PackReader::open(bytes)?.with_cache(15);
+        assert_eq!(reader.cached_bytes(), 0);
+        assert_eq!(reader.read("a")?, vec![1; 10]);
+        assert_eq!(reader.cached_bytes(), 10);
+        // aliases share the entry of their file
+        assert_eq!(reader.read("alias")?, vec![1; 10]);
+        assert_eq!(reader.cached_bytes(), 10);
+        // too large to be kept
+        assert_eq!(reader.read("large")?, vec![3; 100]);
+        assert_eq!(reader.cached_bytes(), 10);
+        // makes room by evicting a
+        assert_eq!(reader.read("b")?, vec![2; 10]);
+        assert_eq!(reader.cached_bytes(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_reader() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+        use std::io::{Seek, SeekFrom};
+
+        let path = std::env::temp_dir().join("backpack_test_concurrent_reader");
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for i in 0..20u8 {
+            bp.add_file(InMemoryFile::from(vec![i; 1000 + i as usize]).with_name(i.to_string()))?;
+        }
+        std::fs::write(&path, bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec())?;
+
+        let reader = PackReader::open(RawFile::open(&path)?)?;
+        std::thread::scope(|s| {
+            for t in 0..8u8 {
+                let reader = &reader;
+                s.spawn(move || {
+                    for round in 0..50u8 {
+                        let i = (t + round) % 20;
+                        assert_eq!(reader.read(i.to_string()).unwrap(), vec![i; 1000 + i as usize]);
+
+                        let mut entry = reader.get(i.to_string()).unwrap();
+                        entry.seek(SeekFrom::Start(500)).unwrap();
+                        let mut rest = Vec::new();
+                        entry.read_to_end(&mut rest).unwrap();
+                        assert_eq!(rest, vec![i; 500 + i as usize]);
+                    }
+                });
+            }
+        });
+
+        drop(reader);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_borrowed_read() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        bp.add_empty_file("empty")?;
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let reader = PackReader::open(bytes.clone())?;
+        let entry = reader.get("a")?;
+        assert_eq!(&*entry.as_slice()?.unwrap(), b"first");
+        assert_eq!(reader.get("empty")?.as_slice()?.unwrap().len(), 0);
+
+        let path = std::env::temp_dir().join("backpack_test_borrowed_read");
+        std::fs::write(&path, &bytes)?;
+        let reader = PackReader::open(RawFile::open(&path)?)?;
+        assert!(reader.get("a")?.as_slice()?.is_none());
+        drop(reader);
+        std::fs::remove_file(path)?;
+
+        // damaged contents are noticed when verifying, like when reading
+        let at = bytes.windows(5).position(|w| w == b"first").unwrap();
+        bytes[at] = b'F';
+        let mut reader = PackReader::open(bytes)?;
+        assert_eq!(&*reader.get("a")?.as_slice()?.unwrap(), b"First");
+        reader.set_verify(true);
+        assert!(matches!(reader.get("a")?.as_slice(), Err(PackError::ChecksumMismatch(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_temp_file() -> Result<(), PackError> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let dir = std::env::temp_dir().join("backpack_test_temp_file");
+        std::fs::create_dir_all(&dir)?;
+
+        let mut file = RawFile::temp_in(&dir)?;
+        file.write_all(b"spilled")?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"spilled");
+        #[cfg(unix)]
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+        // works as the backing file of a backpack
+        let bp = BackPack::create(RawFile::temp_in(&dir)?)?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        let bp = BackPack::open(bp.close()?)?;
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), b"first");
+
+        drop((file, bp));
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+        std::fs::remove_dir(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_adaptive_file() -> Result<(), PackError> {
+        use crate::pack::AdaptiveFile;
+        use std::io::{Seek, SeekFrom, Write};
+
+        let dir = std::env::temp_dir().join("backpack_test_adaptive_file");
+        std::fs::create_dir_all(&dir)?;
+
+        let mut file = AdaptiveFile::new_in(10, &dir);
+        file.write_all(b"small")?;
+        assert!(!file.is_spilled());
+        file.write_all(b" and then larger")?;
+        assert!(file.is_spilled());
+
+        file.seek(SeekFrom::Start(4))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        assert_eq!(contents, "l and then larger");
+        file.set_len(5)?;
+
+        let mut file = RawFile::from(file);
+        file.seek(SeekFrom::Start(0))?;
+        contents.clear();
+        file.read_to_string(&mut contents)?;
+        assert_eq!(contents, "small");
+        drop(file);
+
+        // files which didn't spill become files in memory
+        let bp = BackPack::create(AdaptiveFile::new_in(1 << 20, &dir))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        assert!(bp.close()?.into_memory().is_ok());
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+        std::fs::remove_dir(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate() -> Result<(), PackError> {
+        let path = std::env::temp_dir().join("backpack_test_migrate");
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // already in the current version
+        std::fs::write(&path, &bytes)?;
+        assert!(!BackPack::migrate(&path)?);
+        assert_eq!(std::fs::read(&path)?, bytes);
+
+        // versions it can't read are left alone
+        let mut newer = bytes.clone();
+        newer[8..10].copy_from_slice(&(PACK_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &newer)?;
+        let err = BackPack::migrate(&path).unwrap_err();
+        assert!(matches!(err, PackError::Incompatible(v) if v == PACK_VERSION + 1));
+        assert!(err.to_string().contains(&format!("reads version {}", PACK_VERSION)));
+        assert_eq!(std::fs::read(&path)?, newer);
+
+        std::fs::write(&path, b"not a backpack at all, but long enough")?;
+        assert!(matches!(BackPack::migrate(&path), Err(PackError::BadMagic)));
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_context() -> Result<(), PackError> {
+        use std::error::Error;
+
+        // I/O errors say which file they're about
+        let path = std::env::temp_dir().join("backpack_test_error_context_missing");
+        let err = RawFile::open(&path).err().unwrap();
+        assert!(matches!(&err, PackError::IoAt { path: p, source } if *p == path && source.kind() == std::io::ErrorKind::NotFound));
+        assert!(err.source().is_some());
+        assert_eq!(std::io::Error::from(err).kind(), std::io::ErrorKind::NotFound);
+
+        // damage in the table of contents says where it is
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let toc = PACK_HEADER_SIZE as usize;
+        // how much of the block is filled, less than its header
+        bytes[toc..toc + 2].copy_from_slice(&1u16.to_le_bytes());
+        assert!(matches!(BackPack::open(bytes), Err(PackError::CorruptIndex { offset }) if offset == toc as u64));
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice_reader() -> Result<(), PackError> {
+        use crate::format::{FormatError, SliceReader};
+        use std::borrow::Cow;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        bp.add_file(InMemoryFile::from("debug").with_name("debug"))?;
+        bp.set_hidden("debug", true)?;
+        bp.set_alias("b", "a")?;
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let reader = SliceReader::open(&bytes)?;
+        assert_eq!(reader.file_names(), ["a"]);
+        assert!(reader.is_hidden("debug"));
+        let contents = reader.get("b")?;
+        assert_eq!(contents, b"first");
+        // borrowed from the bytes, not copied
+        assert!(bytes.as_ptr_range().contains(&contents.as_ptr()));
+        assert!(matches!(reader.read("a")?, Cow::Borrowed(b"first")));
+        assert!(matches!(reader.get("missing"), Err(FormatError::FileNotFound(_))));
+
+        let at = bytes.windows(5).position(|w| w == b"first").unwrap();
+        bytes[at] = b'F';
+        let mut reader = SliceReader::open(&bytes)?;
+        assert_eq!(reader.get("a")?, b"First");
+        reader.set_verify(true);
+        assert!(matches!(reader.get("a"), Err(FormatError::ChecksumMismatch(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_slice_reader_protected_index() -> Result<(), PackError> {
+        use crate::format::SliceReader;
+        use crate::pack::IndexProtection;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_index_protection(IndexProtection::ChecksumAndCopy);
+        bp.add_file(InMemoryFile::from("contents").with_name("a"))?;
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // the first index is damaged, the copy after the data is used
+        bytes[PACK_HEADER_SIZE as usize + 12] ^= 0xff;
+        let reader = SliceReader::open(&bytes)?;
+        assert_eq!(reader.get("a")?, b"contents");
+        assert_eq!(&*reader.read("a")?, b"contents");
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_storage() -> Result<(), PackError> {
+        use crate::pack::Storage;
+        use parking_lot::Mutex;
+        use std::sync::Arc;
+
+        // storage shared with the test, like a browser database outliving the backpack
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Storage for Shared {
+            fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0.lock().read_at(offset, buf)
+            }
+
+            fn write_at(&mut self, offset: u64, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().write_at(offset, buf)
+            }
+
+            fn len(&self) -> std::io::Result<u64> {
+                Ok(self.0.lock().len() as u64)
+            }
+
+            fn set_len(&mut self, size: u64) -> std::io::Result<()> {
+                Storage::set_len(&mut *self.0.lock(), size)
+            }
+        }
+
+        let storage = Shared::default();
+        let bp = BackPack::create(RawFile::from_storage(storage.clone()).with_name("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first").with_name("a"))?;
+        let file = bp.close()?;
+        assert_eq!(file.name(), Some(std::path::Path::new("test.bp")));
+        assert_eq!(file.metadata()?.len, storage.0.lock().len() as u64);
+
+        let bp = BackPack::open(RawFile::from_storage(storage.clone()))?;
+        assert_eq!(bp.get_file("a")?.get_bytes().as_ref(), b"first");
+        let bytes = bp.close()?.convert_into_memory()?;
+        assert_eq!(bytes.get_bytes().as_ref(), storage.0.lock().as_slice());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_serialized() -> Result<(), PackError> {
+        use crate::pack::Json;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Config {
+            name: String,
+            volume: u8,
+        }
+
+        let config = Config { name: "game".to_string(), volume: 7 };
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.put_serialized("config.json", &config)?;
+        bp.put_serialized_with("pretty.json", &config, Json { pretty: true })?;
+
+        assert_eq!(bp.get_deserialized::<Config>("config.json")?, config);
+        assert_eq!(bp.get_file("config.json")?.get_bytes().as_ref(), br#"{"name":"game","volume":7}"#);
+        assert!(bp.get_file("pretty.json")?.get_bytes().contains(&b'\n'));
+        assert!(matches!(bp.get_deserialized::<u32>("config.json"), Err(PackError::AssetLoad { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_attributes() -> Result<(), PackError> {
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("{}").with_name("config.json"))?;
+        bp.add_file(InMemoryFile::from("b").with_name("b"))?;
+        bp.set_attribute("config.json", "content-type", "application/json")?;
+        bp.set_attribute("config.json", "hash", vec![0, 1, 2])?;
+        bp.set_attribute("b", "build", "42")?;
+        assert!(bp.set_attribute("missing", "build", "42").is_err());
+        assert_eq!(bp.attribute("config.json", "content-type"), Some(&b"application/json"[..]));
+        assert_eq!(bp.remove_attribute("b", "build"), Some(b"42".to_vec()));
+        assert!(bp.attributes("b").is_empty());
+
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let mut bp = BackPack::open(bytes)?;
+        assert_eq!(bp.file_names(), ["b", "config.json"]);
+        assert_eq!(bp.attribute("config.json", "hash"), Some(&[0, 1, 2][..]));
+        let entry = bp.entries().find(|entry| entry.name == "config.json").unwrap();
+        assert_eq!(entry.metadata.attributes.get("content-type").map(Vec::as_slice), Some(&b"application/json"[..]));
+
+        for entry in &mut bp.entries_mut() {
+            entry.metadata.attributes.insert("build".to_string(), b"43".to_vec());
+        }
+        assert_eq!(bp.attribute("b", "build"), Some(&b"43"[..]));
+        bp.remove_file("config.json")?;
+        assert!(bp.attributes("config.json").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_preserve_metadata() -> Result<(), PackError> {
+        use crate::pack::{DirectoryOptions, ExtractOptions};
+
+        let src = std::env::temp_dir().join("backpack_test_preserve_src");
+        let dst = std::env::temp_dir().join("backpack_test_preserve_dst");
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+        std::fs::create_dir_all(src.join("bin"))?;
+        std::fs::write(src.join("bin/run.sh"), "#!/bin/sh")?;
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        std::fs::File::options().write(true).open(src.join("bin/run.sh"))?.set_modified(modified)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(src.join("bin/run.sh"), std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        let bp = BackPack::from_directory(&src, &DirectoryOptions::default())?;
+        assert_eq!(bp.modified("bin/run.sh"), Some(modified));
+        #[cfg(unix)]
+        assert_eq!(bp.mode("bin/run.sh").map(|mode| mode & 0o777), Some(0o755));
+
+        bp.extract_to(&dst)?;
+        let metadata = std::fs::metadata(dst.join("bin/run.sh"))?;
+        assert_eq!(metadata.modified()?, modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+
+        std::fs::remove_dir_all(&dst)?;
+        let options = ExtractOptions { restore_modified: false, ..ExtractOptions::default() };
+        bp.extract_to_with(&dst, &options)?;
+        assert_ne!(std::fs::metadata(dst.join("bin/run.sh"))?.modified()?, modified);
+
+        let options = DirectoryOptions { keep_modified: false, keep_permissions: false, ..DirectoryOptions::default() };
+        let bp = BackPack::from_directory(&src, &options)?;
+        assert_eq!(bp.modified("bin/run.sh"), None);
+        assert_eq!(bp.mode("bin/run.sh"), None);
+
+        std::fs::remove_dir_all(src)?;
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_links() -> Result<(), PackError> {
+        use crate::pack::{DirectoryOptions, EntryKind};
+
+        let src = std::env::temp_dir().join("backpack_test_links_src");
+        let dst = std::env::temp_dir().join("backpack_test_links_dst");
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+        std::fs::create_dir_all(src.join("lib"))?;
+        std::fs::write(src.join("lib/index.js"), "index")?;
+        std::fs::hard_link(src.join("lib/index.js"), src.join("main.js"))?;
+        std::os::unix::fs::symlink("lib", src.join("current"))?;
+        std::os::unix::fs::symlink("missing", src.join("dangling"))?;
+
+        let mut bp = BackPack::from_directory(&src, &DirectoryOptions::default())?;
+        assert_eq!(bp.entry_kind("lib/index.js"), Some(EntryKind::File));
+        assert_eq!(bp.entry_kind("main.js"), Some(EntryKind::Hardlink("lib/index.js".to_string())));
+        assert_eq!(bp.entry_kind("current"), Some(EntryKind::Symlink("lib".into())));
+        assert_eq!(bp.entry_kind("dangling"), Some(EntryKind::Symlink("missing".into())));
+        assert_eq!(bp.entry_kind("nothing"), None);
+
+        bp.add_symlink("index.js", "lib/index.js")?;
+        assert!(matches!(bp.add_symlink("index.js", "lib"), Err(PackError::FileExists(_))));
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(bp.entry_kind("index.js"), Some(EntryKind::Symlink("lib/index.js".into())));
+
+        bp.extract_to(&dst)?;
+        assert_eq!(std::fs::read_link(dst.join("current"))?, std::path::PathBuf::from("lib"));
+        assert_eq!(std::fs::read_link(dst.join("dangling"))?, std::path::PathBuf::from("missing"));
+        assert_eq!(std::fs::read(dst.join("index.js"))?, b"index");
+        assert_eq!(std::fs::read(dst.join("main.js"))?, b"index");
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(std::fs::metadata(dst.join("main.js"))?.ino(), std::fs::metadata(dst.join("lib/index.js"))?.ino());
+
+        // following links packs copies, and leaves out links to directories
+        let options = DirectoryOptions { keep_links: false, ..DirectoryOptions::default() };
+        let bp = BackPack::from_directory(&src, &options)?;
+        assert_eq!(bp.file_names(), ["lib/index.js", "main.js"]);
+
+        std::fs::remove_dir_all(src)?;
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn test_signing() -> Result<(), PackError> {
+        use crate::pack::{FormatFeature, PackReader, SigningKey};
+
+        let key = SigningKey::from_pkcs8(&SigningKey::generate_pkcs8()?)?;
+        let other = SigningKey::from_seed(&[1; 32])?;
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_signing(Some(key));
+        bp.add_file(InMemoryFile::from("signed contents").with_name("a"))?;
+        bp.add_empty_file("b")?;
+        bp.set_attribute("a", "owner", "me")?;
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let public_key = SigningKey::from_pkcs8(&SigningKey::generate_pkcs8()?)?.public_key();
+        assert!(BackPack::compatibility_of(Cursor::new(&bytes))?.features.contains(&FormatFeature::Signature));
+
+        assert!(matches!(BackPack::open_verified(bytes.clone(), &public_key), Err(PackError::BadSignature)));
+        assert!(matches!(BackPack::open_verified(bytes.clone(), &other.public_key()), Err(PackError::BadSignature)));
+        let signer = BackPack::open(bytes.clone())?.signer().unwrap();
+        let bp = BackPack::open_verified(bytes.clone(), &signer)?;
+        assert_eq!(bp.file_names(), ["a", "b"]);
+        assert_eq!(PackReader::open(bytes.clone())?.file_names(), ["a", "b"]);
+
+        // changing the contents breaks the signature
+        let mut tampered = bytes.clone();
+        let at = tampered.windows(15).position(|w| w == b"signed contents").unwrap();
+        tampered[at] = b'S';
+        assert!(matches!(BackPack::open_verified(tampered, &signer), Err(PackError::BadSignature)));
+
+        // and so does changing the metadata, without signing again
+        let mut bp = BackPack::open(bytes.clone())?;
+        bp.set_attribute("a", "owner", "you")?;
+        bp.flush()?;
+        let unsigned = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(matches!(BackPack::open_verified(unsigned, &signer), Err(PackError::Unsigned)));
+
+        let mut bp = BackPack::open(bytes)?;
+        bp.set_signing(Some(other));
+        bp.set_attribute("a", "owner", "you")?;
+        bp.flush()?;
+        let resigned = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        assert!(matches!(BackPack::open_verified(resigned.clone(), &signer), Err(PackError::BadSignature)));
+        let other = SigningKey::from_seed(&[1; 32])?;
+        assert_eq!(BackPack::open_verified(resigned, &other.public_key())?.attribute("a", "owner"), Some(&b"you"[..]));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "signing", feature = "crypto"))]
+    #[test]
+    fn test_signing_encrypted() -> Result<(), PackError> {
+        use crate::pack::{Encryption, SigningKey};
+
+        let key = SigningKey::from_seed(&[2; 32])?;
+        let public_key = key.public_key();
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_signing(Some(key));
+        bp.set_encryption(Some(Encryption::new("hunter2").with_iterations(1)))?;
+        bp.add_file(InMemoryFile::from("secret").with_name("a"))?;
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let bp = BackPack::open_verified_encrypted(bytes.clone(), "hunter2", &public_key)?;
+        assert_eq!(&*bp.get_file("a")?.get_bytes(), b"secret");
+        assert!(matches!(BackPack::open_verified_encrypted(bytes.clone(), "hunter3", &public_key), Err(PackError::WrongPassphrase)));
+        let other = SigningKey::from_seed(&[3; 32])?.public_key();
+        assert!(matches!(BackPack::open_verified_encrypted(bytes, "hunter2", &other), Err(PackError::BadSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch() -> Result<(), PackError> {
+        use crate::pack::PackDiff;
+
+        let large = vec![7u8; 10_000];
+        let old = BackPack::create(RawFile::in_memory("old.bp"))?;
+        old.add_file(InMemoryFile::from(large.clone()).with_name("same"))?;
+        old.add_file(InMemoryFile::from("old contents").with_name("changed"))?;
+        old.add_file(InMemoryFile::from("going away").with_name("removed"))?;
+        let mut new = BackPack::create(RawFile::in_memory("new.bp"))?;
+        new.add_file(InMemoryFile::from(large).with_name("same"))?;
+        new.add_file(InMemoryFile::from("new contents").with_name("changed"))?;
+        new.add_file(InMemoryFile::from("hello").with_name("added"))?;
+        new.add_file(InMemoryFile::from("secret").with_name("hidden"))?;
+        new.set_hidden("hidden", true)?;
+
+        let diff = PackDiff::between(&old, &new)?;
+        assert_eq!(diff.added(), ["added", "hidden"]);
+        assert_eq!(diff.changed(), ["changed"]);
+        assert_eq!(diff.removed(), ["removed"]);
+        assert!(PackDiff::between(&new, &new)?.is_empty());
+
+        let mut patch = Vec::new();
+        diff.write(&mut patch)?;
+        assert!(patch.len() < 10_000);
+        assert_eq!(PackDiff::open(patch.clone())?, diff);
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for name in old.file_names() {
+            bp.add_file(InMemoryFile::from(old.get_file(&name)?.get_bytes().to_vec()).with_name(name))?;
+        }
+        bp.flush()?;
+        bp.apply_patch(&PackDiff::open(patch)?)?;
+        bp.flush()?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let bp = BackPack::open(bytes)?;
+        assert_eq!(bp.file_names(), ["added", "changed", "same"]);
+        assert!(bp.is_hidden("hidden"));
+        assert_eq!(&*bp.get_file("changed")?.get_bytes(), b"new contents");
+        assert!(PackDiff::between(&bp, &new)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress() -> Result<(), PackError> {
+        use crate::pack::{DirectoryOptions, ExtractOptions, ProgressUpdate};
+
+        let src = std::env::temp_dir().join("backpack_test_progress_src");
+        let dst = std::env::temp_dir().join("backpack_test_progress_dst");
+        let packed = std::env::temp_dir().join("backpack_test_progress.bp");
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+        std::fs::create_dir_all(src.join("dir"))?;
+        std::fs::write(src.join("a"), "12345")?;
+        std::fs::write(src.join("dir/b"), "123")?;
+
+        let mut updates = Vec::new();
+        let mut record = |update: ProgressUpdate| updates.push((update.entry.to_string(), update.entries_done, update.entries_total, update.bytes_done, update.bytes_total));
+        let bp = BackPack::from_directory_with_progress(&src, &DirectoryOptions::default(), &mut record)?;
+        assert_eq!(updates, [("a".to_string(), 1, 2, 5, 8), ("dir/b".to_string(), 2, 2, 8, 8)]);
+
+        let mut last = None;
+        bp.extract_to_with_progress(&dst, &ExtractOptions::default(), &mut |update: ProgressUpdate| last = Some((update.entries_done, update.bytes_done, update.fraction())))?;
+        assert_eq!(last, Some((2, 8, 1.0)));
+        assert_eq!(std::fs::read(dst.join("dir/b"))?, b"123");
+
+        bp.freeze(std::fs::File::create(&packed)?, crate::pack::FreezeOptions::default())?;
+        let mut bp = BackPack::open(std::fs::File::options().read(true).write(true).open(&packed)?)?;
+        let mut count = 0;
+        let report = bp.verify_all_with_progress(&mut |update: ProgressUpdate| count = update.entries_done)?;
+        assert!(report.is_ok());
+        // the entries holding modification times and modes are checked too
+        assert_eq!(count, 4);
+        drop(bp);
+
+        let mut bytes = 0;
+        BackPack::compact_with_progress(&packed, &mut |update: ProgressUpdate| bytes = update.bytes_done)?;
+        assert_eq!(bytes, 8);
+        assert_eq!(&*BackPack::open(std::fs::read(&packed)?)?.get_file("a")?.get_bytes(), b"12345");
+
+        std::fs::remove_dir_all(src)?;
+        std::fs::remove_dir_all(dst)?;
+        std::fs::remove_file(packed)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> Result<(), PackError> {
+        use crate::pack::{CancellationToken, ExtractOptions, ProgressUpdate};
+
+        let dst = std::env::temp_dir().join("backpack_test_cancel");
+        let _ = std::fs::remove_dir_all(&dst);
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for name in ["a", "b", "c"] {
+            bp.add_file(InMemoryFile::from(name).with_name(name))?;
+        }
+
+        // cancelled from the progress of the first entry, as a GUI would from another thread
+        let token = CancellationToken::new();
+        let mut progress = token.with_progress(|update: ProgressUpdate| if update.entry == "a" { token.cancel() });
+        let res = bp.extract_to_with_progress(&dst, &ExtractOptions::default(), &mut progress);
+        assert!(matches!(res, Err(PackError::Cancelled)));
+        assert_eq!(std::fs::read(dst.join("a"))?, b"a");
+        assert!(!dst.join("b").exists());
+
+        let mut token = CancellationToken::new();
+        token.cancel();
+        bp.flush()?;
+        assert!(matches!(bp.verify_all_with_progress(&mut token), Err(PackError::Cancelled)));
+
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_many() -> Result<(), PackError> {
+        use crate::pack::ProgressUpdate;
+
+        let dst = std::env::temp_dir().join("backpack_test_extract_many");
+        let _ = std::fs::remove_dir_all(&dst);
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        for i in 0..100 {
+            bp.add_file(InMemoryFile::from(format!("contents {}", i)).with_name(format!("dir{}/file{}", i % 7, i)))?;
+        }
+
+        let mut done = Vec::new();
+        bp.extract_to_with_progress(&dst, &Default::default(), &mut |update: ProgressUpdate| done.push(update.entries_done))?;
+        assert_eq!(done, (1..=100).collect::<Vec<_>>());
+        for i in 0..100 {
+            assert_eq!(std::fs::read_to_string(dst.join(format!("dir{}/file{}", i % 7, i)))?, format!("contents {}", i));
+        }
+
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_positional_io() -> Result<(), PackError> {
+        use std::io::{IoSlice, Seek, Write};
+
+        let path = std::env::temp_dir().join("backpack_test_positional");
+        let disk = std::fs::File::options().create(true).truncate(true).read(true).write(true).open(&path)?;
+        for mut file in [RawFile::from(disk), RawFile::in_memory("test")] {
+            let header = 4u32.to_le_bytes();
+            let written = file.write_vectored(&[IoSlice::new(&header), IoSlice::new(b"body")])?;
+            assert_eq!(written, 8);
+
+            // positional writes and reads leave the cursor alone
+            file.write_all_at(0, &5u32.to_le_bytes())?;
+            file.write_all(b"!")?;
+            let mut buf = [0; 5];
+            assert_eq!(file.read_at(4, &mut buf)?, 5);
+            assert_eq!(&buf, b"body!");
+            assert_eq!(file.read_at(9, &mut buf)?, 0);
+            file.read_exact_at(0, &mut buf[..4])?;
+            assert_eq!(buf[..4], 5u32.to_le_bytes());
+            assert_eq!(file.stream_position()?, 9);
+        }
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_atomic() -> Result<(), PackError> {
+        let dir = std::env::temp_dir().join("backpack_test_save_atomic");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("assets.bp");
+        std::fs::write(&path, "old contents")?;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("contents").with_name("a"))?;
+        bp.save_atomic(&path)?;
+        assert_eq!(&*BackPack::open(std::fs::read(&path)?)?.get_file("a")?.get_bytes(), b"contents");
+        // no temporary file is left behind
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        // a failed save leaves the old backpack
+        assert!(bp.save_atomic(dir.join("missing/assets.bp")).is_err());
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_journaled() -> Result<(), PackError> {
+        use crate::pack::{journal_path, PackReader, PackWriter};
+
+        let path = std::env::temp_dir().join("backpack_test_journaled");
+        {
+            let bp = BackPack::create(RawFile::create(&path)?)?;
+            bp.add_file(InMemoryFile::from("a").with_name("a"))?;
+            bp.add_file(InMemoryFile::from("b").with_name("b"))?;
+            bp.close()?;
+        }
+
+        // files in memory have nowhere to keep a journal
+        assert!(matches!(BackPack::create(RawFile::in_memory("test.bp"))?.set_journaled(true), Err(PackError::NotJournaled)));
+
+        let mut bp = BackPack::open_journaled(&path)?;
+        bp.remove_file("a")?;
+        bp.flush()?;
+        assert!(!journal_path(&path).exists());
+        drop(bp);
+
+        let mut writer = PackWriter::open_append_journaled(&path)?;
+        writer.add_entry("c", "c".as_bytes())?;
+        writer.finish()?;
+        assert!(!journal_path(&path).exists());
+
+        let reader = PackReader::open(RawFile::open(&path)?)?;
+        assert!(reader.read("a").is_err());
+        assert_eq!(reader.read("b")?, b"b");
+        assert_eq!(reader.read("c")?, b"c");
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vfs() -> Result<(), PackError> {
+        use std::path::Path;
+        use crate::pack::{DirectoryOptions, DiskVfs, Vfs};
+
+        let dir = std::env::temp_dir().join("backpack_test_vfs");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("assets/textures"))?;
+        std::fs::write(dir.join("readme.txt"), "readme")?;
+        std::fs::write(dir.join("assets/textures/wall.png"), "wall")?;
+
+        // the same code reads both
+        fn check(vfs: &dyn Vfs) -> Result<(), PackError> {
+            assert_eq!(vfs.read(Path::new("assets/textures/wall.png"))?, b"wall");
+            assert_eq!(vfs.read_dir(Path::new(""))?, ["assets/", "readme.txt"]);
+            assert_eq!(vfs.read_dir(Path::new("assets"))?, ["textures/"]);
+            assert_eq!(vfs.metadata(Path::new("readme.txt"))?.len, 6);
+            assert!(vfs.metadata(Path::new("assets/textures"))?.is_dir);
+            assert!(vfs.exists(Path::new("assets")));
+            assert!(!vfs.exists(Path::new("missing.txt")));
+            assert!(matches!(vfs.open(Path::new("missing.txt")), Err(PackError::FileNotFound(_))));
+            Ok(())
+        }
+
+        let bp = BackPack::from_directory(&dir, &DirectoryOptions::default())?;
+        check(&&bp)?;
+        let disk = DiskVfs::new(&dir);
+        check(&disk)?;
+        assert!(matches!(disk.open(Path::new("../escape")), Err(PackError::UnsafePath(_))));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_set() -> Result<(), PackError> {
+        use std::path::Path;
+        use crate::pack::{PackSet, Vfs};
+
+        let base = BackPack::create(RawFile::in_memory("base.bp"))?;
+        base.add_file(InMemoryFile::from("base").with_name("levels/1.txt"))?;
+        base.add_file(InMemoryFile::from("base").with_name("levels/2.txt"))?;
+        base.add_file(InMemoryFile::from("file").with_name("music"))?;
+        let dlc = BackPack::create(RawFile::in_memory("dlc.bp"))?;
+        dlc.add_file(InMemoryFile::from("dlc").with_name("levels/2.txt"))?;
+        dlc.add_file(InMemoryFile::from("dlc").with_name("levels/3.txt"))?;
+        dlc.add_file(InMemoryFile::from("song").with_name("music/theme.ogg"))?;
+
+        let mut set = PackSet::new();
+        assert!(set.is_empty());
+        set.mount(&base).mount(&dlc);
+        assert_eq!(set.len(), 2);
+
+        assert_eq!(set.read(Path::new("levels/1.txt"))?, b"base");
+        assert_eq!(set.read(Path::new("levels/2.txt"))?, b"dlc");
+        assert_eq!(set.which("levels/1.txt"), Some(0));
+        assert_eq!(set.which("levels/2.txt"), Some(1));
+        assert_eq!(set.which("missing"), None);
+        assert_eq!(set.read_dir(Path::new("levels"))?, ["1.txt", "2.txt", "3.txt"]);
+        // the directory of the dlc shadows the file of the base
+        assert_eq!(set.read_dir(Path::new(""))?, ["levels/", "music/"]);
+        assert!(set.metadata(Path::new("music"))?.is_dir);
+
+        assert!(set.unmount().is_some());
+        assert_eq!(set.read(Path::new("levels/2.txt"))?, b"base");
+        assert!(matches!(set.open(Path::new("levels/3.txt")), Err(PackError::FileNotFound(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_watch() -> Result<(), PackError> {
+        use crate::pack::{ChangeEvent, DirectoryOptions, DirectoryWatcher};
+
+        let dir = std::env::temp_dir().join("backpack_test_watch");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("textures"))?;
+        std::fs::write(dir.join("textures/wall.png"), "wall")?;
+        std::fs::write(dir.join("old.txt"), "old")?;
+
+        let options = DirectoryOptions::default();
+        let mut bp = BackPack::from_directory(&dir, &options)?;
+        let mut watcher = DirectoryWatcher::new(&dir, &options)?;
+        let changes = bp.changes();
+        assert_eq!(watcher.poll()?, []);
+
+        std::fs::write(dir.join("textures/wall.png"), "brick wall")?;
+        std::fs::write(dir.join("new.txt"), "new")?;
+        std::fs::remove_file(dir.join("old.txt"))?;
+        assert_eq!(watcher.refresh(&mut bp)?, [
+            ChangeEvent::Added("new.txt".to_string()),
+            ChangeEvent::Removed("old.txt".to_string()),
+            ChangeEvent::Modified("textures/wall.png".to_string()),
+        ]);
+        assert_eq!(bp.file_names(), ["new.txt", "textures/wall.png"]);
+        assert_eq!(&*bp.get_file("textures/wall.png")?.get_bytes(), b"brick wall");
+        // listeners of the backpack hear about it too
+        assert_eq!(std::iter::from_fn(|| changes.try_next()).count(), 3);
+
+        std::fs::write(dir.join("new.txt"), "newer")?;
+        assert_eq!(watcher.wait(std::time::Duration::from_millis(1), std::time::Duration::from_secs(5))?, [ChangeEvent::Modified("new.txt".to_string())]);
+        assert_eq!(watcher.poll()?, []);
+
+        // the change is reported, instead of found by polling an hour later
+        let writer = std::thread::spawn({
+            let dir = dir.clone();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                std::fs::write(dir.join("textures/floor.png"), "floor")
+            }
+        });
+        let start = std::time::Instant::now();
+        assert_eq!(watcher.wait(std::time::Duration::from_secs(3600), std::time::Duration::from_secs(60))?, [ChangeEvent::Added("textures/floor.png".to_string())]);
+        assert!(start.elapsed() < std::time::Duration::from_secs(30));
+        writer.join().unwrap()?;
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "fuse"))]
+    #[test]
+    fn test_fuse_mount() -> Result<(), PackError> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let dir = std::env::temp_dir().join("backpack_test_fuse");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("hello").with_name("a.txt"))?;
+        bp.add_file(InMemoryFile::from("wall").with_name("textures/wall.png"))?;
+
+        // browse the mount from another thread, and unmount it when done
+        let failed = Arc::new(AtomicBool::new(false));
+        let browser = std::thread::spawn({
+            let dir = dir.clone();
+            let failed = failed.clone();
+            move || {
+                let start = Instant::now();
+                while std::fs::metadata(dir.join("a.txt")).is_err() {
+                    if failed.load(Ordering::SeqCst) || start.elapsed() > Duration::from_secs(10) {
+                        return None;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                let mut root = std::fs::read_dir(&dir).unwrap()
+                    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                    .collect::<Vec<_>>();
+                root.sort();
+                let res = (root, std::fs::read(dir.join("a.txt")), std::fs::read(dir.join("textures/wall.png")), std::fs::write(dir.join("a.txt"), "changed").is_err());
+
+                let target = std::ffi::CString::new(dir.as_os_str().as_bytes()).unwrap();
+                // Safety: the target is a valid C string
+                unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+                Some(res)
+            }
+        });
+
+        let mounted = bp.mount(&dir);
+        failed.store(true, Ordering::SeqCst);
+        let browsed = browser.join().unwrap();
+        match mounted {
+            // fuse isn't available everywhere, like in some containers
+            Err(e) if browsed.is_none() => {
+                eprintln!("skipping fuse test: {}", e);
+            }
+            res => {
+                res?;
+                let (root, a, wall, read_only) = browsed.expect("mounted");
+                assert_eq!(root, ["a.txt", "textures"]);
+                assert_eq!(a?, b"hello");
+                assert_eq!(wall?, b"wall");
+                assert!(read_only);
+            }
+        }
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_extract() -> Result<(), PackError> {
+        use crate::pack::{DirectoryOptions, FreezeOptions, PackWriter, StreamReader};
+
+        let src = std::env::temp_dir().join("backpack_test_stream_extract_src");
+        let dst = std::env::temp_dir().join("backpack_test_stream_extract");
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+        std::fs::create_dir_all(src.join("a"))?;
+        std::fs::create_dir_all(src.join("dir"))?;
+        std::fs::write(src.join("a/one.txt"), "same")?;
+        std::fs::write(src.join("two.txt"), "same")?;
+        std::fs::write(src.join("empty.txt"), "")?;
+
+        // with modification times, which are stored as metadata entries
+        let bp = BackPack::from_directory(&src, &DirectoryOptions::default())?;
+        let mut frozen = Vec::new();
+        // files with the same contents share them in the frozen backpack
+        bp.freeze(&mut frozen, FreezeOptions::default())?;
+
+        let mut stream = StreamReader::new(frozen.as_slice())?;
+        assert!(stream.entries().all(|(name, _)| !name.starts_with(".backpack/")));
+        stream.extract_to(&dst)?;
+        assert_eq!(std::fs::read(dst.join("a/one.txt"))?, b"same");
+        assert_eq!(std::fs::read(dst.join("two.txt"))?, b"same");
+        assert_eq!(std::fs::read(dst.join("empty.txt"))?, b"");
+        assert!(dst.join("dir").is_dir());
+        assert!(!dst.join(".backpack").exists());
+
+        let mut writer = PackWriter::new(RawFile::in_memory("evil.bp"))?;
+        writer.add_entry("../evil", &b"evil"[..])?;
+        let bp = BackPack::open(writer.finish()?.convert_into_memory()?)?;
+        let mut frozen = Vec::new();
+        bp.freeze(&mut frozen, FreezeOptions::default())?;
+        assert!(matches!(StreamReader::new(frozen.as_slice())?.extract_to(&dst), Err(PackError::UnsafePath(_))));
+
+        std::fs::remove_dir_all(src)?;
+        std::fs::remove_dir_all(dst)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_streams() -> Result<(), PackError> {
+        use std::io::{Seek, SeekFrom, Write};
+        use crate::pack::{PackReader, PackWriter};
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("0123456789").with_name("a.txt"))?;
+        let mut reader = bp.entry_reader("a.txt")?;
+        assert_eq!(reader.len(), 10);
+        reader.seek(SeekFrom::End(-3))?;
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        assert_eq!(contents, "789");
+        assert!(bp.entry_reader("missing.txt").is_err());
+
+        let mut writer = bp.entry_writer("b.txt");
+        writer.write_all(b"first-")?;
+        writer.write_all(b"second")?;
+        assert!(bp.get_file("b.txt").is_err());
+        writer.finish()?;
+        assert_eq!(bp.get_file("b.txt")?.get_bytes().as_ref(), b"first-second");
+        // dropping a writer adds nothing
+        drop(bp.entry_writer("c.txt"));
+        assert!(bp.get_file("c.txt").is_err());
+
+        let mut writer = PackWriter::new(RawFile::in_memory("streamed.bp"))?;
+        let mut entry = writer.entry_writer("a.txt")?;
+        entry.write_all(b"streamed ")?;
+        entry.write_all(b"contents")?;
+        assert_eq!(entry.finish()?, 17);
+        let mut entry = writer.entry_writer("dropped.txt")?;
+        entry.write_all(b"never finished")?;
+        drop(entry);
+        writer.add_entry("b.txt", &b"after"[..])?;
+        // the same contents are only stored once
+        let mut entry = writer.entry_writer("c.txt")?;
+        entry.write_all(b"streamed contents")?;
+        entry.finish()?;
+        assert_eq!(writer.size(), 22);
+        assert!(matches!(writer.entry_writer("a.txt"), Err(PackError::FileExists(_))));
+
+        let reader = PackReader::open(writer.finish()?)?;
+        assert_eq!(reader.file_names(), ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(reader.read("b.txt")?, b"after");
+        assert_eq!(reader.read("c.txt")?, b"streamed contents");
+        let mut entry = reader.get("a.txt")?;
+        entry.seek(SeekFrom::Start(9))?;
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        assert_eq!(contents, "contents");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "chunked")]
+    #[test]
+    fn test_chunked() {
+        use crate::pack::{ChunkedReader, ChunkedWriter, ChunkingOptions, PackReader, PackWriter};
+        use crate::RawFile;
+        use std::io::Read;
+
+        let options = ChunkingOptions { min_size: 256, avg_size: 1024, max_size: 4096 };
+        let v1 = (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 11) as u8).collect::<Vec<_>>();
+        let mut v2 = v1.clone();
+        v2.splice(50_000..50_000, b"a small change".iter().copied());
+
+        let mut writer = ChunkedWriter::with_options(PackWriter::new(RawFile::in_memory("test.bp")).unwrap(), options);
+        assert_eq!(writer.add_file("v1.bin", v1.as_slice()).unwrap(), v1.len() as u64);
+        writer.add_file("v2.bin", v2.as_slice()).unwrap();
+        writer.add_file("empty", &[][..]).unwrap();
+        assert!(writer.add_file("v1.bin", &[][..]).is_err());
+        // the second version shares almost all of its chunks with the first
+        assert!(writer.size() < (v1.len() + v1.len() / 4) as u64);
+        let file = writer.finish().unwrap();
+
+        let reader = ChunkedReader::new(PackReader::open(file).unwrap()).unwrap();
+        assert_eq!(reader.file_names(), vec!["empty", "v1.bin", "v2.bin"]);
+        assert_eq!(reader.read("v1.bin").unwrap(), v1);
+        assert_eq!(reader.read("empty").unwrap(), Vec::<u8>::new());
+        let mut streamed = Vec::new();
+        reader.entry("v2.bin").unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, v2);
+        assert!(reader.read("v3.bin").is_err());
+
+        // sync v2 to a backpack which only has v1
+        let mut old = ChunkedWriter::with_options(PackWriter::new(RawFile::in_memory("test.bp")).unwrap(), options);
+        old.add_file("v1.bin", v1.as_slice()).unwrap();
+        let old = ChunkedReader::new(PackReader::open(old.finish().unwrap()).unwrap()).unwrap();
+        let recipe = reader.recipe("v2.bin").unwrap();
+        let missing = old.missing(recipe);
+        assert!(!missing.is_empty() && missing.len() < recipe.len() / 4);
+
+        let mut synced = ChunkedWriter::with_options(PackWriter::new(RawFile::in_memory("test.bp")).unwrap(), options);
+        for id in old.recipe("v1.bin").unwrap() {
+            synced.add_chunk(&old.chunk(id).unwrap()).unwrap();
+        }
+        for id in &missing {
+            synced.add_chunk(&reader.chunk(id).unwrap()).unwrap();
+        }
+        synced.add_recipe("v2.bin", recipe.to_vec()).unwrap();
+        let synced = ChunkedReader::new(PackReader::open(synced.finish().unwrap()).unwrap()).unwrap();
+        assert_eq!(synced.read("v2.bin").unwrap(), v2);
+
+        // a plain backpack isn't chunked
+        assert!(ChunkedReader::new(PackReader::open(PackWriter::new(RawFile::in_memory("test.bp")).unwrap().finish().unwrap()).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_transaction() {
+        use crate::pack::PackError;
+        use crate::RawFile;
+
+        let path = std::env::temp_dir().join("backpack_test_transaction");
+        {
+            let bp = BackPack::create(RawFile::create(&path).unwrap()).unwrap();
+            bp.add_file(InMemoryFile::from(b"a".to_vec()).with_name("a.txt")).unwrap();
+            bp.add_file(InMemoryFile::from(b"b".to_vec()).with_name("b.txt")).unwrap();
+        }
+
+        let mut bp = BackPack::open(std::fs::OpenOptions::new().read(true).write(true).open(&path).unwrap()).unwrap();
+        bp.set_hidden("b.txt", true).unwrap();
+
+        // dropped without committing
+        let mut t = bp.transaction();
+        t.remove("a.txt");
+        drop(t);
+        assert_eq!(bp.file_names(), vec!["a.txt"]);
+
+        // the rename of a missing file fails after the other changes were made, so they're undone
+        let mut t = bp.transaction();
+        t.add("c.txt", &b"c"[..]).unwrap();
+        t.remove("a.txt");
+        t.rename("b.txt", "a.txt");
+        t.rename("missing.txt", "d.txt");
+        assert_eq!(t.len(), 4);
+        assert!(matches!(t.commit(), Err(PackError::FileNotFound(_))));
+        assert_eq!(bp.file_names(), vec!["a.txt"]);
+        assert!(bp.is_hidden("b.txt"));
+
+        let mut t = bp.transaction();
+        t.add("c.txt", &b"c"[..]).unwrap();
+        t.remove("a.txt");
+        t.rename("b.txt", "a.txt");
+        t.add("b.txt", &b"new b"[..]).unwrap();
+        t.commit().unwrap();
+        bp.flush().unwrap();
+        drop(bp);
+
+        let bp = BackPack::open(RawFile::open(&path).unwrap().convert_into_memory().unwrap()).unwrap();
+        assert_eq!(bp.file_names(), vec!["b.txt", "c.txt"]);
+        assert!(bp.is_hidden("a.txt"));
+        assert_eq!(bp.get_file("a.txt").unwrap().get_bytes().as_ref(), b"b");
+        assert_eq!(bp.get_file("b.txt").unwrap().get_bytes().as_ref(), b"new b");
+        drop(bp);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rename() {
+        use crate::pack::PackError;
+
+        let path = std::env::temp_dir().join("backpack_test_rename");
+        {
+            let bp = BackPack::create(RawFile::create(&path).unwrap()).unwrap();
+            for name in ["assets/a.png", "assets/ui/b.png", "assets/ui/c.png", "images/ui/c.png", "readme"] {
+                bp.add_file(InMemoryFile::from(name.as_bytes().to_vec()).with_name(name)).unwrap();
+            }
+            bp.add_dir("assets/empty").unwrap();
+        }
+
+        let mut bp = BackPack::open(std::fs::OpenOptions::new().read(true).write(true).open(&path).unwrap()).unwrap();
+        bp.set_attribute("readme", "lang", "en").unwrap();
+        bp.set_alias("docs", "readme").unwrap();
+        bp.rename("readme", "README.md").unwrap();
+        assert!(matches!(bp.rename("readme", "x"), Err(PackError::FileNotFound(_))));
+        assert!(matches!(bp.rename("assets/a.png", "README.md"), Err(PackError::FileExists(_))));
+        assert_eq!(bp.attribute("README.md", "lang"), Some(&b"en"[..]));
+        assert_eq!(bp.alias_target("docs").as_deref(), Some("README.md"));
+
+        // images/ui/c.png is in the way
+        assert!(matches!(bp.move_dir("assets/ui", "images/ui"), Err(PackError::FileExists(_))));
+        assert!(bp.get_file("assets/ui/b.png").is_ok());
+        assert!(matches!(bp.move_dir("nothing", "images"), Err(PackError::FileNotFound(_))));
+
+        assert_eq!(bp.move_dir("assets", "images/assets/").unwrap(), 4);
+        // the other way around, moving everything into a subdirectory of itself
+        assert_eq!(bp.move_dir("images", "images/old").unwrap(), 5);
+        bp.flush().unwrap();
+        drop(bp);
+
+        let bp = BackPack::open(RawFile::open(&path).unwrap().convert_into_memory().unwrap()).unwrap();
+        assert_eq!(bp.file_names(), vec![
+            "README.md",
+            "images/old/assets/a.png",
+            "images/old/assets/ui/b.png",
+            "images/old/assets/ui/c.png",
+            "images/old/ui/c.png",
+        ]);
+        assert_eq!(bp.dir_names(), vec!["images/old/assets/empty"]);
+        assert_eq!(bp.get_file("images/old/assets/ui/b.png").unwrap().get_bytes().as_ref(), b"assets/ui/b.png");
+        assert_eq!(bp.get_file("docs").unwrap().get_bytes().as_ref(), b"readme");
+        drop(bp);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sparse() {
+        use crate::pack::{DirectoryOptions, ExtractOptions};
+        use crate::RawFile;
+
+        let dir = std::env::temp_dir().join("backpack_test_sparse");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let path = dir.join("src/disk.img");
+
+        let mut file = RawFile::from(std::fs::File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap());
+        file.allocate(0, 1 << 20).unwrap();
+        assert_eq!(file.metadata().unwrap().len, 1 << 20);
+        file.write_all_at(0, &[1; 4096]).unwrap();
+        file.write_all_at((1 << 20) - 4096, &[2; 4096]).unwrap();
+        file.punch_hole(4096, (1 << 20) - 8192).unwrap();
+        assert_eq!(file.metadata().unwrap().len, 1 << 20);
+        let mut middle = [7; 16];
+        file.read_exact_at(1 << 19, &mut middle).unwrap();
+        assert_eq!(middle, [0; 16]);
+        let holes = file.holes().unwrap();
+        drop(file);
+
+        let mut in_memory = RawFile::from(vec![1; 100]);
+        in_memory.punch_hole(10, 1000).unwrap();
+        assert_eq!(in_memory.metadata().unwrap().len, 100);
+        assert!(in_memory.holes().unwrap().is_empty());
+
+        let contents = std::fs::read(&path).unwrap();
+        let bp = BackPack::from_directory(dir.join("src"), &DirectoryOptions::default()).unwrap();
+        assert_eq!(bp.holes("disk.img"), holes);
+        bp.extract_to(dir.join("out")).unwrap();
+        bp.extract_to_with(dir.join("dense"), &ExtractOptions { restore_holes: false, ..ExtractOptions::default() }).unwrap();
+        assert_eq!(std::fs::read(dir.join("out/disk.img")).unwrap(), contents);
+        assert_eq!(std::fs::read(dir.join("dense/disk.img")).unwrap(), contents);
+
+        // holes are only found where the file system supports them
+        if !holes.is_empty() {
+            assert_eq!(holes, vec![(4096, (1 << 20) - 8192)]);
+            let extracted = RawFile::open(dir.join("out/disk.img")).unwrap().holes().unwrap();
+            assert_eq!(extracted, holes);
+            assert!(RawFile::open(dir.join("dense/disk.img")).unwrap().holes().unwrap().is_empty());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_limits() {
+        use crate::pack::{DirectoryOptions, PackError, PackReader, PackWriter, WriteLimits};
+        use crate::RawFile;
+        use std::io::Write;
+
+        let limits = WriteLimits { max_entry_size: Some(100), max_pack_size: Some(250), max_entries: Some(3) };
+        let mut writer = PackWriter::new(RawFile::in_memory("test.bp")).unwrap();
+        writer.set_limits(limits);
+        assert!(matches!(writer.add_entry("huge", &[0; 1000][..]), Err(PackError::EntryTooLarge(_, 100))));
+        writer.add_entry("a", &[1; 100][..]).unwrap();
+        writer.add_entry("b", &[2; 100][..]).unwrap();
+        assert!(matches!(writer.add_entry("c", &[3; 100][..]), Err(PackError::PackTooLarge(250))));
+        let mut entry = writer.entry_writer("c").unwrap();
+        assert!(entry.write_all(&[3; 60]).is_err());
+        drop(entry);
+        writer.add_entry("c", &[3; 50][..]).unwrap();
+        assert!(matches!(writer.add_entry("d", &[][..]), Err(PackError::TooManyEntries(3))));
+        let reader = PackReader::open(writer.finish().unwrap()).unwrap();
+        assert_eq!(reader.file_names(), vec!["a", "b", "c"]);
+        assert_eq!(reader.read("c").unwrap(), vec![3; 50]);
+
+        let path = std::env::temp_dir().join("backpack_test_write_limits");
+        let _ = std::fs::remove_file(&path);
+        let mut bp = BackPack::create(RawFile::create(&path).unwrap()).unwrap();
+        bp.set_write_limits(limits);
+        assert!(matches!(bp.add_file(InMemoryFile::from(vec![0; 101]).with_name("huge")), Err(PackError::EntryTooLarge(_, 100))));
+        bp.add_file(InMemoryFile::from(vec![1; 100]).with_name("a")).unwrap();
+        bp.add_file(InMemoryFile::from(vec![2; 100]).with_name("b")).unwrap();
+        // replacing a file only counts the new contents
+        bp.add_file(InMemoryFile::from(vec![2; 100]).with_name("b")).unwrap();
+        assert!(matches!(bp.add_file(InMemoryFile::from(vec![3; 51]).with_name("c")), Err(PackError::PackTooLarge(250))));
+        bp.add_file(InMemoryFile::from(vec![3; 10]).with_name("c")).unwrap();
+        assert!(matches!(bp.add_file(InMemoryFile::from(vec![]).with_name("d")), Err(PackError::TooManyEntries(3))));
+        assert_eq!(bp.file_names(), vec!["a", "b", "c"]);
+        drop(bp);
+        std::fs::remove_file(&path).unwrap();
+
+        let dir = std::env::temp_dir().join("backpack_test_write_limits_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, size) in [("a", 10), ("b", 200)] {
+            std::fs::write(dir.join(name), vec![0; size]).unwrap();
+        }
+        let options = DirectoryOptions { limits, ..DirectoryOptions::default() };
+        assert!(matches!(BackPack::from_directory(&dir, &options), Err(PackError::EntryTooLarge(_, 100))));
+        let options = DirectoryOptions { limits: WriteLimits { max_entries: Some(1), ..WriteLimits::default() }, ..DirectoryOptions::default() };
+        assert!(matches!(BackPack::from_directory(&dir, &options), Err(PackError::TooManyEntries(1))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_hardening() {
+        use crate::pack::{ExtractOptions, PackError, WriteLimits};
+        use crate::RawFile;
+
+        let dir = std::env::temp_dir().join("backpack_test_extract_hardening");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        let sandbox = ExtractOptions { sandbox: true, ..ExtractOptions::default() };
+
+        // a link pointing out of the directory
+        let mut bp = BackPack::create(RawFile::create(dir.join("link.bp")).unwrap()).unwrap();
+        bp.add_symlink("escape", "../outside").unwrap();
+        bp.add_symlink("docs/inside", "../readme").unwrap();
+        assert!(matches!(bp.extract_to_with(dir.join("a"), &sandbox), Err(PackError::UnsafePath(_))));
+        assert!(!dir.join("a/docs").exists());
+        bp.remove_file("escape").unwrap();
+        bp.extract_to_with(dir.join("a"), &sandbox).unwrap();
+        drop(bp);
+
+        // and files written through a link in the backpack, also without the sandbox
+        let mut bp = BackPack::create(RawFile::create(dir.join("through.bp")).unwrap()).unwrap();
+        bp.add_symlink("escape", dir.join("outside")).unwrap();
+        bp.add_file(InMemoryFile::from("pwned").with_name("escape/through")).unwrap();
+        assert!(matches!(bp.extract_to(dir.join("e")), Err(PackError::UnsafePath(_))));
+        assert!(!dir.join("outside/through").exists());
+        assert!(!dir.join("e").exists());
+        bp.remove_file("escape").unwrap();
+        bp.add_symlink("escape", "docs").unwrap();
+        assert!(matches!(bp.extract_to(dir.join("e")), Err(PackError::UnsafePath(_))));
+        bp.remove_file("escape/through").unwrap();
+        bp.extract_to(dir.join("e")).unwrap();
+        drop(bp);
+
+        // a link which was already in the directory
+        let bp = BackPack::create(RawFile::create(dir.join("file.bp")).unwrap()).unwrap();
+        bp.add_file(InMemoryFile::from("pwned").with_name("escape/file")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("b/escape")).unwrap();
+        assert!(matches!(bp.extract_to_with(dir.join("b"), &sandbox), Err(PackError::UnsafePath(_))));
+        assert!(!dir.join("outside/file").exists());
+        // nothing is created through the link on the way to a file deeper down
+        let nested = BackPack::create(RawFile::create(dir.join("nested.bp")).unwrap()).unwrap();
+        nested.add_file(InMemoryFile::from("pwned").with_name("escape/sub/file")).unwrap();
+        assert!(matches!(nested.extract_to_with(dir.join("b"), &sandbox), Err(PackError::UnsafePath(_))));
+        assert!(!dir.join("outside/sub").exists());
+        drop(nested);
+        // without the sandbox the link is followed
+        bp.extract_to(dir.join("b")).unwrap();
+        assert!(dir.join("outside/file").exists());
+
+        let limits = ExtractOptions { limits: WriteLimits { max_pack_size: Some(4), ..WriteLimits::default() }, ..ExtractOptions::default() };
+        assert!(matches!(bp.extract_to_with(dir.join("c"), &limits), Err(PackError::PackTooLarge(4))));
+        assert!(!dir.join("c").exists());
+        drop(bp);
+
+        let bp = BackPack::create(RawFile::create(dir.join("names.bp")).unwrap()).unwrap();
+        bp.add_file(InMemoryFile::from("x").with_name("docs/aux.txt")).unwrap();
+        let portable = ExtractOptions { portable_names: true, ..ExtractOptions::default() };
+        assert!(matches!(bp.extract_to_with(dir.join("d"), &portable), Err(PackError::UnsafePath(_))));
+        bp.extract_to(dir.join("d")).unwrap();
+        drop(bp);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_modes() -> Result<(), PackError> {
+        use crate::pack::ReadMode;
+
+        let bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("first file").with_name("first"))?;
+        bp.add_file(InMemoryFile::from("second file").with_name("docs/second"))?;
+        bp.add_file(InMemoryFile::from("last file").with_name("last"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        BackPack::open_with_mode(bytes.clone(), ReadMode::Strict)?.close_drop_unwritten_changes()?;
+        let (bp, report) = BackPack::recover(bytes.clone())?;
+        assert!(report.is_complete());
+        assert_eq!(report.recovered, ["docs/second", "first", "last"]);
+        assert_eq!(&*bp.get_file("docs/second")?.get_bytes(), b"second file");
+
+        // damaged contents only fail when they're read, unless opening strictly
+        let mut damaged = bytes.clone();
+        let position = damaged.windows(11).position(|w| w == b"second file").unwrap();
+        damaged[position] ^= 1;
+        BackPack::open_with_mode(damaged.clone(), ReadMode::Normal)?.close_drop_unwritten_changes()?;
+        assert!(matches!(BackPack::open_with_mode(damaged.clone(), ReadMode::Strict), Err(PackError::ChecksumMismatch(_))));
+        let (bp, report) = BackPack::recover(damaged.clone())?;
+        assert_eq!(report.recovered, ["first", "last"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "docs/second");
+        assert!(matches!(report.skipped[0].1, PackError::ChecksumMismatch(_)));
+        assert_eq!(&*bp.get_file("first")?.get_bytes(), b"first file");
+        assert!(bp.get_file("docs/second").is_err());
+        bp.close_drop_unwritten_changes()?;
+
+        let bp = BackPack::open_with_mode(damaged, ReadMode::Recover)?;
+        assert_eq!(&*bp.get_file("last")?.get_bytes(), b"last file");
+        bp.close_drop_unwritten_changes()?;
+
+        // cut off in the middle of the last file
+        let position = bytes.windows(9).position(|w| w == b"last file").unwrap();
+        let truncated = bytes[..position + 4].to_vec();
+        assert!(BackPack::open_with_mode(truncated.clone(), ReadMode::Strict).is_err());
+        let (bp, report) = BackPack::recover(truncated)?;
+        assert_eq!(report.recovered, ["docs/second", "first"]);
+        assert_eq!(report.skipped.len() + report.lost_blocks.len(), 1);
+        assert_eq!(&*bp.get_file("first")?.get_bytes(), b"first file");
+        bp.close_drop_unwritten_changes()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stat() -> Result<(), PackError> {
+        use std::io::Cursor;
+        use crate::pack::{PackReader, PACK_VERSION};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.add_file(InMemoryFile::from("hello").with_name("docs/hello.txt"))?;
+        bp.add_file(InMemoryFile::from("a bit longer").with_name("longer"))?;
+        bp.add_file(InMemoryFile::from("secret").with_name("hidden"))?;
+        bp.add_dir("empty")?;
+        bp.set_hidden("hidden", true)?;
+        bp.set_alias("alias", "longer")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let info = BackPack::stat_of(Cursor::new(&bytes))?;
+        assert_eq!(info.version, PACK_VERSION);
+        assert_eq!(info.file_size, bytes.len() as u64);
+        assert_eq!(info.files, 2);
+        assert_eq!(info.files_size, 17);
+        assert_eq!(info.toc_blocks, 1);
+
+        let entries = BackPack::list_fast_of(Cursor::new(&bytes))?;
+        let reader = PackReader::open(bytes.clone())?;
+        assert_eq!(entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), reader.file_names());
+        for entry in &entries {
+            let mut contents = vec![0; entry.stored_size as usize];
+            contents.copy_from_slice(&bytes[entry.offset as usize..(entry.offset + entry.stored_size) as usize]);
+            assert_eq!(contents, reader.read(&entry.name)?);
+            assert!(!entry.compressed && !entry.encrypted);
+        }
+
+        let path = std::env::temp_dir().join("backpack_test_stat.bp");
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(BackPack::stat(&path)?, info);
+        assert_eq!(BackPack::list_fast(&path)?, entries);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(BackPack::stat(&path), Err(PackError::IoAt { .. })));
+        assert!(matches!(BackPack::stat_of(Cursor::new(b"not a backpack")), Err(PackError::BadMagic) | Err(PackError::Io(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_builder() -> Result<(), PackError> {
+        use std::io::Cursor;
+        use crate::pack::{Checksum, IndexPlacement, IndexProtection, PackBuilder, PackReader, StreamReader, TOC_SIZE};
+
+        let dir = std::env::temp_dir().join("backpack_test_pack_builder");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/nested")).unwrap();
+        std::fs::write(dir.join("src/a.txt"), "aaaa").unwrap();
+        std::fs::write(dir.join("src/nested/b.txt"), "bbbbbbbb").unwrap();
+
+        let builder = PackBuilder::new()
+            .alignment(64)
+            .metadata(".build/commit", "3e73afd");
+        let mut bytes = Vec::new();
+        builder.build_from_dir(dir.join("src"), &mut bytes)?;
+        let bp = BackPack::open(bytes.clone())?;
+        assert_eq!(bp.file_names(), ["a.txt", "nested/b.txt"]);
+        assert_eq!(&*bp.get_file(".build/commit")?.get_bytes(), b"3e73afd");
+        bp.close_drop_unwritten_changes()?;
+        let entries = BackPack::list_fast_of(Cursor::new(&bytes))?;
+        assert!(entries.iter().all(|entry| entry.offset % 64 == 0));
+        assert_eq!(StreamReader::new(Cursor::new(&bytes))?.entries().count(), 2);
+
+        // the index after the contents, without checksums
+        let mut bytes = Vec::new();
+        builder.clone()
+            .index_placement(IndexPlacement::End)
+            .checksum(Checksum::None)
+            .build_from_dir(dir.join("src"), &mut bytes)?;
+        let mut reader = PackReader::open(bytes.clone())?;
+        reader.set_verify(true);
+        assert_eq!(reader.read("nested/b.txt")?, b"bbbbbbbb");
+        let first_toc = u64::from_le_bytes(bytes[18..26].try_into().unwrap());
+        assert_eq!(first_toc, bytes.len() as u64 - TOC_SIZE as u64);
+        let err = builder.clone()
+            .index_placement(IndexPlacement::End)
+            .index_protection(IndexProtection::Checksum)
+            .build_from_dir(dir.join("src"), Vec::new());
+        assert!(matches!(err, Err(PackError::IncompatibleOptions(_))));
+
+        // a writer can't do everything
+        assert!(matches!(builder.writer(RawFile::in_memory("test.bp")), Err(PackError::IncompatibleOptions(_))));
+        let mut writer = PackBuilder::new()
+            .metadata(".build/commit", "3e73afd")
+            .checksum(Checksum::None)
+            .writer(RawFile::in_memory("test.bp"))?;
+        writer.add_entry("file", "contents".as_bytes())?;
+        let bytes = writer.finish()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.file_names(), ["file"]);
+        assert_eq!(reader.read(".build/commit")?, b"3e73afd");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorded_alignment() -> Result<(), PackError> {
+        use std::io::Cursor;
+        use crate::pack::{EntryOptions, FormatFeature, PackReader, SliceReader};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_alignment(64)?;
+        bp.add_file(InMemoryFile::from("vertices").with_name("mesh"))?;
+        bp.add_with_options("texture", &[1; 100][..], EntryOptions { alignment: Some(4096), ..EntryOptions::default() })?;
+        bp.add_empty_file("empty")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let reader = PackReader::open(bytes.clone())?;
+        assert_eq!(reader.alignment("mesh")?, 64);
+        assert_eq!(reader.alignment("texture")?, 4096);
+        assert_eq!(reader.alignment("empty")?, 1);
+        assert!(reader.alignment("missing").is_err());
+        assert_eq!(SliceReader::open(&bytes)?.alignment("texture")?, 4096);
+        for entry in BackPack::list_fast_of(Cursor::new(&bytes))? {
+            assert!(entry.offset.is_multiple_of(entry.alignment));
+        }
+        assert!(BackPack::compatibility_of(Cursor::new(&bytes))?.features.contains(&FormatFeature::Alignment));
+
+        // files keep their alignment when the backpack is changed, new ones don't get it
+        let bp = BackPack::open(bytes)?;
+        bp.add_file(InMemoryFile::from("new").with_name("new"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.alignment("mesh")?, 64);
+        assert_eq!(reader.alignment("texture")?, 4096);
+        assert_eq!(reader.alignment("new")?, 1);
+        assert_eq!(reader.read("texture")?, [1; 100]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tee() -> Result<(), PackError> {
+        use std::io::ErrorKind;
+        use crate::pack::{FaultyFile, Operation, PackReader, PackWriter, Trigger};
+
+        let dir = std::env::temp_dir().join("backpack_test_tee");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let open = |name: &str| RawFile::from(std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(dir.join(name)).unwrap());
+        let open_rw = |path: &std::path::Path| RawFile::from(std::fs::OpenOptions::new().read(true).write(true).open(path).unwrap());
+
+        let mut writer = PackWriter::new(open("primary.bp").tee([open("mirror.bp"), open("backup.bp")]))?;
+        writer.add_entry("a", "first".as_bytes())?;
+        writer.add_entry("b", "second".as_bytes())?;
+        writer.finish()?.sync_all()?;
+        let primary = std::fs::read(dir.join("primary.bp")).unwrap();
+        assert_eq!(primary, std::fs::read(dir.join("mirror.bp")).unwrap());
+        assert_eq!(primary, std::fs::read(dir.join("backup.bp")).unwrap());
+        assert_eq!(PackReader::open(RawFile::open(dir.join("mirror.bp"))?)?.read("b")?, b"second");
+
+        // a backpack changed in place, rewriting parts of the file
+        let bp = BackPack::create(open("primary.bp").tee([open("mirror.bp")]))?;
+        bp.add_file(InMemoryFile::from("contents").with_name("file"))?;
+        bp.close()?;
+        let mut bp = BackPack::open(open_rw(&dir.join("primary.bp")).tee([open_rw(&dir.join("mirror.bp"))]))?;
+        bp.remove_file("file")?;
+        bp.flush()?;
+        drop(bp);
+        assert_eq!(std::fs::read(dir.join("primary.bp")).unwrap(), std::fs::read(dir.join("mirror.bp")).unwrap());
+
+        // a write only succeeds when it succeeds everywhere
+        let failing = RawFile::from(FaultyFile::new(RawFile::in_memory("mirror")).fail(Operation::Write, Trigger::Always, ErrorKind::BrokenPipe));
+        assert!(PackWriter::new(RawFile::in_memory("primary").tee([failing])).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_hashing_file() -> Result<(), PackError> {
+        use crate::pack::crc32::crc32;
+        use crate::pack::{Crc32, FreezeOptions, HashingFile};
+
+        let bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("contents").with_name("file"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        // hashed while it's frozen
+        let bp = BackPack::open(bytes)?;
+        let mut frozen = Vec::new();
+        bp.freeze(&mut frozen, FreezeOptions::default())?;
+        let mut hashing = HashingFile::<Crc32>::new(RawFile::in_memory("frozen"))?;
+        bp.freeze(&mut hashing, FreezeOptions::default())?;
+        let hashed_len = hashing.hashed_len();
+        let checksum = hashing.finalize()?;
+        assert_eq!(hashed_len, frozen.len() as u64);
+        assert_eq!(checksum, crc32(&frozen));
+
+        #[cfg(feature = "sha2")]
+        {
+            use std::io::Read;
+            use sha2::{Digest, Sha256};
+            let mut hashing = HashingFile::<Sha256>::new(RawFile::from(frozen.clone()))?;
+            hashing.read_to_end(&mut Vec::new())?;
+            assert_eq!(hashing.finalize()?, Sha256::digest(&frozen));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lines() -> Result<(), PackError> {
+        use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+        use crate::pack::PackReader;
+
+        let bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("name = backpack\r\nversion = 1\n\nlast").with_name("manifest.toml"))?;
+        bp.add_file(InMemoryFile::from(b"fine\nalso fine\n\xff\n".to_vec()).with_name("broken.txt"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let bp = BackPack::open(bytes.clone())?;
+        let lines = bp.lines("manifest.toml")?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(lines, ["name = backpack", "version = 1", "", "last"]);
+        assert_eq!(bp.read_to_string("manifest.toml")?, "name = backpack\r\nversion = 1\n\nlast");
+        assert!(matches!(bp.read_to_string("broken.txt"), Err(PackError::Line { line: 3, .. })));
+        let mut lines = bp.lines("broken.txt")?;
+        assert_eq!(lines.nth(1).unwrap()?, "also fine");
+        assert_eq!(lines.offset(), 15);
+        assert!(matches!(lines.next(), Some(Err(PackError::Line { line: 3, .. }))));
+        assert!(lines.next().is_none());
+
+        // files from the backpack are buffered readers themselves
+        let mut file = bp.get_file("manifest.toml")?;
+        let mut first = String::new();
+        file.read_line(&mut first)?;
+        assert_eq!(first, "name = backpack\r\n");
+        assert_eq!(file.current_offset(), 17);
+
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.lines("manifest.toml")?.count(), 4);
+        assert_eq!(reader.read_to_string("manifest.toml")?, bp.read_to_string("manifest.toml")?);
+        #[cfg(feature = "deflate")]
+        {
+            use crate::pack::Compression;
+            let mut bp = BackPack::create(RawFile::in_memory("compressed"))?;
+            bp.set_compression(Compression::Deflate)?;
+            bp.add_file(InMemoryFile::from("a\nb\n".repeat(100)).with_name("compressed.txt"))?;
+            let reader = PackReader::open(bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec())?;
+            assert_eq!(reader.lines("compressed.txt")?.count(), 200);
+        }
+
+        // reading ahead on disk doesn't move where writes go
+        let path = std::env::temp_dir().join("backpack_test_lines");
+        let mut file = RawFile::from(std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path).unwrap());
+        file.write_all(b"one\ntwo\nthree\n")?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut line = String::new();
+        file.read_line(&mut line)?;
+        assert_eq!(file.current_offset()?, 4);
+        file.write_all(b"TWO")?;
+        let mut rest = String::new();
+        file.read_to_string(&mut rest)?;
+        assert_eq!(rest, "\nthree\n");
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\nTWO\nthree\n");
+        std::fs::remove_file(path).unwrap();
+
+        let file = RawFile::from_storage(b"one\ntwo\n".to_vec());
+        assert_eq!(file.lines().map(|line| line.unwrap()).collect::<Vec<_>>(), ["one", "two"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<(), PackError> {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let mut file = InMemoryFile::from(vec![1; 1 << 20]).with_name("large");
+        file.seek(SeekFrom::Start(10))?;
+        let mut snapshot = file.snapshot();
+        assert!(file.is_shared() && snapshot.is_shared());
+        assert_eq!(snapshot.current_offset(), 0);
+        assert!(std::ptr::eq(file.get_bytes().as_ptr(), snapshot.get_bytes().as_ptr()));
+
+        // the writer gets its own copy, the snapshot keeps what it had
+        file.write_all(&[2; 5])?;
+        assert!(!file.is_shared() && !snapshot.is_shared());
+        assert_eq!(file.get_bytes()[10..16], [2, 2, 2, 2, 2, 1]);
+        let mut start = [0; 16];
+        snapshot.read_exact(&mut start)?;
+        assert_eq!(start, [1; 16]);
+        let copy = file.clone();
+        assert_eq!(*copy.get_bytes(), *file.get_bytes());
+        assert_eq!(copy.name(), file.name());
+
+        let bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("contents").with_name("file"))?;
+        let mut entry = bp.get_file("file")?;
+        entry.seek(SeekFrom::Start(3))?;
+        let mut contents = String::new();
+        entry.snapshot().read_to_string(&mut contents)?;
+        assert_eq!(contents, "contents");
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<(), PackError> {
+        use std::io::Cursor;
+        use crate::pack::{PACK_HEADER_SIZE, TOC_SIZE};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("a".repeat(1000)).with_name("large"))?;
+        bp.add_file(InMemoryFile::from("b".repeat(10)).with_name("small"))?;
+        bp.add_file(InMemoryFile::from("b".repeat(10)).with_name("same"))?;
+        bp.add_file(InMemoryFile::from("gone soon").with_name("removed"))?;
+        bp.add_file(InMemoryFile::from("manifest").with_name(".manifest"))?;
+        bp.set_hidden(".manifest", true)?;
+        bp.flush()?;
+        bp.remove_file("removed")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let stats = BackPack::stats_of(Cursor::new(bytes.clone()))?;
+        assert_eq!(stats.entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), ["large", "same", "small"]);
+        assert_eq!(stats.largest(1)[0].name, "large");
+        assert_eq!(stats.largest(10).len(), 3);
+        assert_eq!(stats.file_size, bytes.len() as u64);
+        assert_eq!(stats.files_size, 1020);
+        assert_eq!(stats.stored_size, 1010);
+        assert_eq!(stats.metadata_size, 8);
+        assert_eq!(stats.index_size, PACK_HEADER_SIZE + TOC_SIZE as u64);
+        assert_eq!(stats.compression_ratio(), 1.0);
+        assert_eq!(stats.largest(1)[0].compression_ratio(), 1.0);
+        assert_eq!(stats.dead_size, BackPack::stat_of(Cursor::new(bytes))?.data_size - 1018);
+
+        #[cfg(feature = "deflate")]
+        {
+            use crate::pack::Compression;
+            let mut bp = BackPack::create(RawFile::in_memory("compressed"))?;
+            bp.set_compression(Compression::Deflate)?;
+            bp.add_file(InMemoryFile::from("a".repeat(10000)).with_name("compressed"))?;
+            let stats = BackPack::stats_of(Cursor::new(bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec()))?;
+            assert!(stats.compression_ratio() > 10.0);
+            assert!(stats.stored_size < stats.files_size);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_matching() -> Result<(), PackError> {
+        use crate::pack::{Collision, NameMatching, PackReader};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("readme").with_name("Docs/README.md"))?;
+        bp.add_file(InMemoryFile::from("exact").with_name("docs/readme.md"))?;
+        assert!(matches!(bp.get_file("DOCS/Readme.md"), Err(PackError::FileNotFound(_))));
+
+        bp.set_name_matching(NameMatching::CaseInsensitive);
+        assert_eq!(bp.name_matching(), NameMatching::CaseInsensitive);
+        // a name which is there exactly is used as is
+        assert_eq!(*bp.get_file("docs/readme.md")?.get_bytes(), *b"exact");
+        assert_eq!(*bp.get_file("DOCS/Readme.md")?.get_bytes(), *b"readme");
+
+        // adding a name which matches replaces the file under the name it has
+        bp.add_file(InMemoryFile::from("icon").with_name("Icon.PNG"))?;
+        bp.add_file(InMemoryFile::from("new icon").with_name("icon.png"))?;
+        assert_eq!(bp.file_names().iter().filter(|name| name.eq_ignore_ascii_case("icon.png")).count(), 1);
+        assert!(bp.file_names().contains(&"Icon.PNG".to_string()));
+        assert_eq!(*bp.get_file("ICON.png")?.get_bytes(), *b"new icon");
+        bp.set_collision_policy(Collision::Error);
+        assert!(matches!(bp.add_file(InMemoryFile::from("icon").with_name("ICON.PNG")), Err(PackError::FileExists(_))));
+        bp.rename("icon.png", "logo.png")?;
+        assert!(bp.get_file("Logo.png").is_ok());
+        bp.remove_file("LOGO.PNG")?;
+        assert!(bp.get_file("logo.png").is_err());
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let mut reader = PackReader::open(bytes)?;
+        assert!(reader.read("DOCS/README.MD").is_err());
+        reader.set_name_matching(NameMatching::CaseInsensitive);
+        assert_eq!(reader.read("DOCS/README.MD")?, b"readme");
+        assert_eq!(reader.read("docs/readme.md")?, b"exact");
+
+        #[cfg(feature = "unicode")]
+        {
+            let mut bp = BackPack::create(RawFile::in_memory("test"))?;
+            bp.add_file(InMemoryFile::from("coffee").with_name("caf\u{e9}.txt"))?;
+            assert!(bp.get_file("cafe\u{301}.txt").is_err());
+            bp.set_name_matching(NameMatching::Normalized);
+            assert_eq!(*bp.get_file("cafe\u{301}.txt")?.get_bytes(), *b"coffee");
+            assert!(bp.get_file("CAFE\u{301}.txt").is_err());
+            bp.set_name_matching(NameMatching::NormalizedCaseInsensitive);
+            assert_eq!(*bp.get_file("CAFE\u{301}.TXT")?.get_bytes(), *b"coffee");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_volumes() -> Result<(), PackError> {
+        use crate::pack::{volume_path, PackReader, Storage, Volumes};
+
+        let base = std::env::temp_dir().join("backpack_test_volumes.bp");
+        let large: String = (0..10000).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+
+        let bp = BackPack::create(RawFile::create_volumes(&base, 4096)?)?;
+        bp.add_file(InMemoryFile::from(large.clone()).with_name("large"))?;
+        bp.add_file(InMemoryFile::from("small").with_name("small"))?;
+        bp.close()?;
+
+        let volumes = Volumes::open(&base, false)?;
+        assert!(volumes.paths().len() >= 3);
+        assert_eq!(volumes.volume_size(), 4096);
+        assert!(!volume_path(&base, volumes.paths().len()).exists());
+        for path in &volumes.paths()[..volumes.paths().len() - 1] {
+            assert_eq!(std::fs::metadata(path)?.len(), 4096);
+        }
+        drop(volumes);
+
+        let bp = BackPack::open(RawFile::open_volumes(&base)?)?;
+        assert_eq!(&*bp.get_file("large")?.get_bytes(), large.as_bytes());
+        assert_eq!(&*bp.get_file("small")?.get_bytes(), b"small");
+        bp.close_drop_unwritten_changes()?;
+
+        let reader = PackReader::open(RawFile::open_volumes(&base)?)?;
+        assert_eq!(reader.read_to_string("large")?, large);
+
+        // shrinking removes the volumes past the end, creating again removes the old set
+        let mut volumes = Volumes::open(&base, true)?;
+        volumes.set_len(5000)?;
+        assert_eq!(volumes.paths().len(), 2);
+        assert!(!volume_path(&base, 2).exists());
+        assert_eq!(volumes.len()?, 5000);
+        drop(volumes);
+        let volumes = Volumes::create(&base, 4096)?;
+        assert_eq!(volumes.len()?, 0);
+        assert!(!volume_path(&base, 1).exists());
+        drop(volumes);
+
+        std::fs::remove_file(volume_path(&base, 0))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch() -> Result<(), PackError> {
+        use crate::pack::PackReader;
+
+        let path = std::env::temp_dir().join("backpack_test_prefetch.bp");
+        let bp = BackPack::create(RawFile::create(&path)?)?;
+        for level in 0..5 {
+            bp.add_file(InMemoryFile::from(format!("level {level}")).with_name(format!("level{level}.map")))?;
+        }
+        bp.close()?;
+
+        let mut reader = PackReader::open(RawFile::open(&path)?)?;
+        reader.prefetch(["level1.map", "level2.map"])?;
+        assert!(matches!(reader.prefetch(["missing"]), Err(PackError::FileNotFound(_))));
+
+        reader.set_read_ahead(2);
+        for level in 0..5 {
+            assert_eq!(reader.read_to_string(format!("level{level}.map"))?, format!("level {level}"));
+        }
+        drop(reader);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_observer() -> Result<(), PackError> {
+        use std::io::Read;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use crate::pack::{AccessReport, PackReader};
+
+        let mut bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("player").with_name("player.png"))?;
+        bp.add_file(InMemoryFile::from("enemy").with_name("enemy.png"))?;
+        bp.add_file(InMemoryFile::from("unused").with_name("unused.png"))?;
+        bp.set_alias("hero.png", "player.png")?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let report = AccessReport::new();
+        let reader = PackReader::open(bytes.clone())?.with_cache(1024).with_observer(report.clone());
+        reader.read("player.png")?;
+        reader.read("hero.png")?;
+        let mut contents = String::new();
+        reader.get("enemy.png")?.read_to_string(&mut contents)?;
+        assert!(reader.read("missing.png").is_err());
+
+        let accesses = report.accesses();
+        assert_eq!(accesses.keys().collect::<Vec<_>>(), ["enemy.png", "player.png"]);
+        assert_eq!(accesses["player.png"].reads, 2);
+        assert_eq!(accesses["player.png"].bytes, 12);
+        assert_eq!(accesses["enemy.png"].bytes, 5);
+        assert_eq!(report.unread(&reader), ["unused.png"]);
+
+        let cached = AtomicU64::new(0);
+        let reader = PackReader::open(bytes)?.with_cache(1024).with_observer(|event: crate::pack::ReadEvent| {
+            cached.fetch_add(event.cached as u64, Ordering::Relaxed);
+        });
+        reader.read("player.png")?;
+        reader.read("player.png")?;
+        drop(reader);
+        assert_eq!(cached.load(Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_non_utf8_names() -> Result<(), PackError> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+        use crate::pack::{DirectoryOptions, EntryName, PackReader};
+
+        let name = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+        let bp = BackPack::create(RawFile::in_memory("test"))?;
+        bp.add_file(InMemoryFile::from("coffee").with_name(name))?;
+        bp.add_file(InMemoryFile::from("tea").with_name("cafe.txt"))?;
+        let bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+        // the name is stored with its own bytes
+        assert!(bytes.windows(8).any(|window| window == b"caf\xe9.txt"));
+
+        let bp = BackPack::open(bytes.clone())?;
+        let names = bp.entry_names();
+        assert_eq!(names.iter().map(EntryName::as_bytes).collect::<Vec<_>>(), [&b"cafe.txt"[..], b"caf\xe9.txt"]);
+        assert!(matches!(names[1].to_str(), Err(PackError::NonUtf8Name(_))));
+        assert_eq!(names[1].to_string_lossy(), "caf\u{FFFD}.txt");
+        assert_eq!(&*bp.get_file(name)?.get_bytes(), b"coffee");
+        assert_eq!(&*bp.get_file(&bp.file_names()[1])?.get_bytes(), b"coffee");
+        assert!(bp.get_file("caf\u{FFFD}.txt").is_err());
+
+        let reader = PackReader::open(bytes)?;
+        assert_eq!(reader.read(names[1].to_path_buf())?, b"coffee");
+
+        let dir = std::env::temp_dir().join("backpack_test_non_utf8_names");
+        let _ = std::fs::remove_dir_all(&dir);
+        bp.extract_to(&dir)?;
+        assert_eq!(std::fs::read(dir.join(name))?, b"coffee");
+
+        let repacked = BackPack::from_directory(&dir, &DirectoryOptions::default())?;
+        assert_eq!(repacked.entry_names(), names);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
 }
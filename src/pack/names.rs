@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+/// How names are compared when files are looked up or added, see
+/// [`BackPack::set_name_matching`](crate::BackPack::set_name_matching).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum NameMatching {
+    /// names match when they're the same
+    #[default]
+    Exact,
+    /// names match regardless of case, like on windows and macOS: `README.md` finds `readme.md`
+    CaseInsensitive,
+    /// names match when they're the same after NFC unicode normalization, so an `é` written as
+    /// `e` and a combining accent, like macOS writes names, finds an `é` written as one character
+    #[cfg(feature = "unicode")]
+    Normalized,
+    /// names match when they're the same after normalizing them, regardless of case
+    #[cfg(feature = "unicode")]
+    NormalizedCaseInsensitive,
+}
+
+impl NameMatching {
+    /// What `name` is compared by, names with the same key match.
+    pub(crate) fn key<'n>(&self, name: &'n str) -> Cow<'n, str> {
+        match self {
+            NameMatching::Exact => Cow::Borrowed(name),
+            NameMatching::CaseInsensitive => Cow::Owned(name.to_lowercase()),
+            #[cfg(feature = "unicode")]
+            NameMatching::Normalized => icu_normalizer::ComposingNormalizerBorrowed::new_nfc().normalize(name),
+            #[cfg(feature = "unicode")]
+            NameMatching::NormalizedCaseInsensitive => {
+                Cow::Owned(icu_normalizer::ComposingNormalizerBorrowed::new_nfc().normalize(name).to_lowercase())
+            }
+        }
+    }
+}
+
+/// Turns the name of a file into the name it's stored under, see
+/// [`BackPack::set_name_hasher`](crate::BackPack::set_name_hasher).
+///
+/// The hash must never change for a name, or files can't be found anymore.
+/// It must also never contain a `/`, which keeps hashes apart from names the backpack reserves.
+pub trait NameHasher: Send + Sync {
+    fn hash(&self, name: &str) -> String;
+}
+
+impl<F> NameHasher for F where F: Fn(&str) -> String + Send + Sync {
+    fn hash(&self, name: &str) -> String {
+        self(name)
+    }
+}
+
+/// Hashes names with SHA-256 and a salt, and stores them as hex.
+#[cfg(feature = "obfuscation")]
+pub struct SaltedSha256 {
+    salt: Vec<u8>,
+}
+
+#[cfg(feature = "obfuscation")]
+impl SaltedSha256 {
+    pub fn new(salt: impl Into<Vec<u8>>) -> Self {
+        Self { salt: salt.into() }
+    }
+}
+
+#[cfg(feature = "obfuscation")]
+impl NameHasher for SaltedSha256 {
+    fn hash(&self, name: &str) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.salt);
+        hasher.update(name.as_bytes());
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::names::NameMatching;
+
+    #[test]
+    #[cfg(feature = "obfuscation")]
+    fn test_salted_sha256() {
+        use crate::pack::names::{NameHasher, SaltedSha256};
+
+        // sha256("abc")
+        assert_eq!(
+            SaltedSha256::new("a").hash("bc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(SaltedSha256::new("x").hash("bc"), SaltedSha256::new("y").hash("bc"));
+    }
+
+    #[test]
+    fn test_name_matching() {
+        assert_ne!(NameMatching::Exact.key("Dir/README.md"), NameMatching::Exact.key("dir/readme.md"));
+        assert_eq!(NameMatching::CaseInsensitive.key("Dir/README.md"), NameMatching::CaseInsensitive.key("dir/readme.md"));
+        assert_eq!(NameMatching::CaseInsensitive.key("ÉTÉ"), "été");
+
+        #[cfg(feature = "unicode")]
+        {
+            let composed = "caf\u{e9}";
+            let decomposed = "cafe\u{301}";
+            assert_ne!(NameMatching::CaseInsensitive.key(composed), NameMatching::CaseInsensitive.key(decomposed));
+            assert_eq!(NameMatching::Normalized.key(decomposed), composed);
+            assert_ne!(NameMatching::Normalized.key("CAFE\u{301}"), composed);
+            assert_eq!(NameMatching::NormalizedCaseInsensitive.key("CAFE\u{301}"), composed);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::Mutex;
+use crate::pack::PackReader;
+
+/// A read of an entry of a [`PackReader`], reported to its [`ReadObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadEvent<'a> {
+    /// the name the entry is stored under, not the alias it may have been read by
+    pub entry: &'a str,
+    /// how many bytes of contents were returned
+    pub bytes: u64,
+    /// how long the read took, including decrypting and decompressing
+    pub duration: Duration,
+    /// whether the contents came from the [cache](PackReader::with_cache)
+    pub cached: bool,
+}
+
+/// Told about every read of a [`PackReader`] it's [registered](PackReader::with_observer) with,
+/// for logging which assets are loaded and how long that takes. [Reading](PackReader::read) an
+/// entry is one read, [streaming](PackReader::get) one reports every call to `read`.
+/// Implemented for closures taking a [`ReadEvent`].
+pub trait ReadObserver: Send + Sync {
+    fn read(&self, event: ReadEvent);
+}
+
+impl<F: Fn(ReadEvent) + Send + Sync> ReadObserver for F {
+    fn read(&self, event: ReadEvent) {
+        self(event)
+    }
+}
+
+/// How often an entry was read, see [`AccessReport`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryAccess {
+    pub reads: u64,
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+/// A [`ReadObserver`] which counts the reads of every entry, to find out which entries an
+/// application actually loads and trim the rest from its backpacks. Clones add to the same report.
+#[derive(Debug, Clone, Default)]
+pub struct AccessReport {
+    accesses: Arc<Mutex<HashMap<String, EntryAccess>>>,
+}
+
+impl AccessReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The entries which were read, by name.
+    pub fn accesses(&self) -> BTreeMap<String, EntryAccess> {
+        self.accesses.lock().iter().map(|(name, access)| (name.clone(), *access)).collect()
+    }
+
+    /// The files of `reader` which weren't read so far, sorted.
+    pub fn unread<'r>(&self, reader: &'r PackReader) -> Vec<&'r str> {
+        let accesses = self.accesses.lock();
+        reader.file_names().into_iter().filter(|name| !accesses.contains_key(*name)).collect()
+    }
+}
+
+impl ReadObserver for AccessReport {
+    fn read(&self, event: ReadEvent) {
+        let mut accesses = self.accesses.lock();
+        let access = accesses.entry(event.entry.to_string()).or_default();
+        access.reads += 1;
+        access.bytes += event.bytes;
+        access.duration += event.duration;
+    }
+}
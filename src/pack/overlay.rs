@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::{check_reserved, normalize_name};
+use crate::pack::compression::Compressed;
+use crate::pack::{BackPack, RawFile};
+
+/// A copy-on-write view of a backpack, see [`BackPack::overlay`]. Changes are kept in memory
+/// and never touch the backpack, reads of files which weren't changed fall through to it.
+pub struct Overlay<'f, 'backpack> {
+    base: &'f BackPack<'f, 'backpack>,
+    /// changed files by name, `None` for removed ones
+    changes: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl<'f, 'backpack> Overlay<'f, 'backpack> {
+    pub(crate) fn new(base: &'f BackPack<'f, 'backpack>) -> Self {
+        Self {
+            base,
+            changes: HashMap::new(),
+        }
+    }
+
+    /// The contents of `name`, as changed in the overlay or else as they are in the backpack.
+    pub fn read(&self, name: impl AsRef<Path>) -> error::Result<Vec<u8>> {
+        let name = name.as_ref();
+        match self.changes.get(&normalize_name(name)) {
+            Some(Some(contents)) => Ok(contents.clone()),
+            Some(None) => Err(PackError::FileNotFound(name.to_path_buf())),
+            None => Ok(self.base.get_file(name)?.get_bytes().to_vec()),
+        }
+    }
+
+    /// Set the contents of `name` in the overlay, whether or not it's in the backpack.
+    pub fn write(&mut self, name: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> error::Result<()> {
+        let path = name.as_ref();
+        let name = normalize_name(path);
+        if name.is_empty() {
+            return Err(PackError::NoName);
+        }
+        check_reserved(&name, path)?;
+        self.changes.insert(name, Some(contents.into()));
+        Ok(())
+    }
+
+    /// Remove `name` from the overlay. The backpack keeps it.
+    pub fn remove(&mut self, name: impl AsRef<Path>) -> error::Result<()> {
+        let name = name.as_ref();
+        if !self.contains(name) {
+            return Err(PackError::FileNotFound(name.to_path_buf()));
+        }
+        self.changes.insert(normalize_name(name), None);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: impl AsRef<Path>) -> bool {
+        match self.changes.get(&normalize_name(name.as_ref())) {
+            Some(change) => change.is_some(),
+            None => self.base.get_file(name).is_ok(),
+        }
+    }
+
+    /// Names of the files changed in the overlay, including removed ones, sorted.
+    pub fn changed(&self) -> Vec<&str> {
+        let mut res = self.changes.keys().map(String::as_str).collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    /// Names of all files as seen through the overlay, sorted, without hidden files.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut res = self.base.file_names().into_iter()
+            .filter(|name| !self.changes.contains_key(name))
+            .chain(self.changes.iter().filter(|(_, change)| change.is_some()).map(|(name, _)| name.clone()))
+            .filter(|name| !self.base.is_hidden(name))
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    /// Forget all changes.
+    pub fn discard(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Write the backpack with the changes of the overlay applied to `writer`, as a new backpack.
+    /// Hidden files and empty directories of the backpack are kept, its expiry, aliases and
+    /// modification times aren't.
+    pub fn export(&self, mut writer: impl Write) -> error::Result<()> {
+        let mut names = self.base.file_names_with(true).into_iter()
+            .chain(self.base.dir_names().into_iter().map(|name| format!("{}/", name)))
+            .filter(|name| !self.changes.contains_key(name))
+            .collect::<Vec<_>>();
+        names.sort();
+
+        let mut contents = Vec::new();
+        for name in &names {
+            contents.push(if name.ends_with('/') { Vec::new() } else { self.base.get_file(name)?.get_bytes().to_vec() });
+        }
+        let mut changed = self.changes.iter()
+            .filter_map(|(name, change)| Some((name.as_str(), change.as_deref()?)))
+            .collect::<Vec<_>>();
+        changed.sort();
+
+        let entries = names.iter()
+            .map(String::as_str)
+            .zip(contents.iter().map(Vec::as_slice))
+            .chain(changed)
+            .collect::<Vec<_>>();
+        let hidden = entries.iter()
+            .filter(|(name, _)| self.base.is_hidden(name))
+            .map(|(name, _)| name.to_string())
+            .collect::<HashSet<_>>();
+
+        BackPack::write_native(&mut writer, &entries, |_| 1, &HashMap::new(), &hidden, &Compressed::new(), &HashSet::new())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The backpack with the changes of the overlay applied, as a new backpack in memory.
+    pub fn commit(&self) -> error::Result<BackPack<'static, 'static>> {
+        let mut pack = Vec::new();
+        self.export(&mut pack)?;
+        BackPack::open_complete(RawFile::from(pack))
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::{Vfs, VfsMetadata};
+
+/// Several backpacks, or other [`Vfs`]es, layered into one tree of files, like a game with
+/// mods or patches on top of its base assets. Lookups search from the last mounted layer
+/// down, so later mounts shadow files of the same name in earlier ones. Directories are
+/// merged: listing one lists what's in it in every layer.
+///
+/// ```rust
+/// # use backpack::pack::{BackPack, PackSet, PackError, Vfs};
+/// # use backpack::{InMemoryFile, RawFile};
+/// # use std::path::Path;
+/// # fn main() -> Result<(), PackError> {
+/// let base = BackPack::create(RawFile::in_memory("base.bp"))?;
+/// base.add_file(InMemoryFile::from("base").with_name("level.txt"))?;
+/// let patch = BackPack::create(RawFile::in_memory("patch.bp"))?;
+/// patch.add_file(InMemoryFile::from("patched").with_name("level.txt"))?;
+///
+/// let mut set = PackSet::new();
+/// set.mount(&base).mount(&patch);
+/// assert_eq!(set.read(Path::new("level.txt"))?, b"patched");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct PackSet<'a> {
+    /// lowest priority first
+    layers: Vec<Box<dyn Vfs + 'a>>,
+}
+
+impl<'a> PackSet<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put `layer` on top of the layers mounted so far.
+    pub fn mount(&mut self, layer: impl Vfs + 'a) -> &mut Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Take off the layer mounted last.
+    pub fn unmount(&mut self) -> Option<Box<dyn Vfs + 'a>> {
+        self.layers.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// The position in mount order of the layer `path` is found in, which is the
+    /// highest one that has it.
+    pub fn which(&self, path: impl AsRef<Path>) -> Option<usize> {
+        let path = path.as_ref();
+        self.layers.iter().rposition(|layer| layer.exists(path))
+    }
+
+    fn top(&self, path: &Path) -> error::Result<&dyn Vfs> {
+        self.which(path)
+            .map(|i| self.layers[i].as_ref())
+            .ok_or_else(|| PackError::FileNotFound(path.to_path_buf()))
+    }
+}
+
+impl Vfs for PackSet<'_> {
+    fn open(&self, path: &Path) -> error::Result<Box<dyn Read + '_>> {
+        self.top(path)?.open(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> error::Result<Vec<String>> {
+        let mut found = false;
+        let mut res = BTreeSet::new();
+        for layer in &self.layers {
+            if let Ok(names) = layer.read_dir(path) {
+                found = true;
+                res.extend(names);
+            }
+        }
+        // a file in a higher layer shadows a directory of the same name in a lower one
+        // and the other way around, only the highest is listed
+        let res = res.iter()
+            .filter(|name| match name.strip_suffix('/') {
+                Some(dir) => !res.contains(dir) || self.metadata(&path.join(dir)).is_ok_and(|m| m.is_dir),
+                None => !res.contains(&format!("{}/", name)) || self.metadata(&path.join(name)).is_ok_and(|m| !m.is_dir),
+            })
+            .cloned()
+            .collect();
+
+        match found {
+            true => Ok(res),
+            false => Err(PackError::FileNotFound(path.to_path_buf())),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> error::Result<VfsMetadata> {
+        self.top(path)?.metadata(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.which(path).is_some()
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::error;
+use crate::error::PackError;
+
+/// How far a long operation like [`BackPack::extract_to_with_progress`](crate::BackPack::extract_to_with_progress)
+/// is, reported to a [`Progress`] after every entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressUpdate<'a> {
+    /// the entry which was just processed
+    pub entry: &'a str,
+    pub entries_done: usize,
+    pub entries_total: usize,
+    /// bytes of contents processed so far, including those of `entry`
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+impl ProgressUpdate<'_> {
+    /// How much of the bytes are done, from 0 to 1. Operations on only empty files count entries instead.
+    pub fn fraction(&self) -> f64 {
+        match (self.bytes_total, self.entries_total) {
+            (0, 0) => 1.0,
+            (0, entries) => self.entries_done as f64 / entries as f64,
+            (bytes, _) => self.bytes_done as f64 / bytes as f64,
+        }
+    }
+}
+
+/// Receives [updates](ProgressUpdate) from long operations, for example to drive a progress bar.
+/// Implemented for closures taking a [`ProgressUpdate`].
+pub trait Progress {
+    fn update(&mut self, update: ProgressUpdate);
+
+    /// Whether the operation should stop. Checked after every update, the operation then fails
+    /// with [`PackError::Cancelled`].
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(ProgressUpdate)> Progress for F {
+    fn update(&mut self, update: ProgressUpdate) {
+        self(update)
+    }
+}
+
+/// Cancels long operations from another thread, like a GUI stopping an extraction. Pass it
+/// as the [`Progress`] of an operation, or [combined](Self::with_progress) with another one.
+/// Clones cancel the same operations.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// `progress` which is also cancelled by this token.
+    pub fn with_progress<P: Progress>(&self, progress: P) -> Cancellable<P> {
+        Cancellable {
+            progress,
+            token: self.clone(),
+        }
+    }
+}
+
+impl Progress for CancellationToken {
+    fn update(&mut self, _update: ProgressUpdate) {}
+
+    fn cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+/// A [`Progress`] which can be cancelled, see [`CancellationToken::with_progress`].
+#[derive(Debug, Clone)]
+pub struct Cancellable<P> {
+    progress: P,
+    token: CancellationToken,
+}
+
+impl<P: Progress> Progress for Cancellable<P> {
+    fn update(&mut self, update: ProgressUpdate) {
+        self.progress.update(update)
+    }
+
+    fn cancelled(&self) -> bool {
+        self.token.is_cancelled() || self.progress.cancelled()
+    }
+}
+
+/// Counts what's done of an operation and reports it.
+pub(crate) struct Tracker<'p> {
+    progress: &'p mut dyn Progress,
+    entries_done: usize,
+    entries_total: usize,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+impl<'p> Tracker<'p> {
+    pub(crate) fn new(progress: &'p mut dyn Progress, entries_total: usize, bytes_total: u64) -> Self {
+        Self {
+            progress,
+            entries_done: 0,
+            entries_total,
+            bytes_done: 0,
+            bytes_total,
+        }
+    }
+
+    /// Report that `entry`, holding `bytes` bytes, is done. Fails when the operation is cancelled.
+    pub(crate) fn done(&mut self, entry: &str, bytes: u64) -> error::Result<()> {
+        self.entries_done += 1;
+        self.bytes_done += bytes;
+        self.progress.update(ProgressUpdate {
+            entry,
+            entries_done: self.entries_done,
+            entries_total: self.entries_total,
+            bytes_done: self.bytes_done,
+            bytes_total: self.bytes_total,
+        });
+        match self.progress.cancelled() {
+            true => Err(PackError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Progress for operations nobody is watching.
+pub(crate) fn ignore(_: ProgressUpdate) {}
@@ -0,0 +1,110 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::Index;
+use crate::pack::crc32::crc32;
+use crate::pack::layout::{IndexTrailer, U32Le, U64Le, TRAILER_MAGIC};
+use crate::BackPack;
+
+const TRAILER_SIZE: u64 = IndexTrailer::SIZE as u64;
+
+/// How well the index (the header and table of contents) of a backpack is protected
+/// against damage. Without it, damage to the index makes the whole backpack unreadable.
+/// Only backpacks written with [`OutputMode::Native`](crate::pack::OutputMode::Native) are protected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum IndexProtection {
+    #[default]
+    None,
+    /// Detect a damaged index, and refuse to open the backpack instead of reading garbage
+    Checksum,
+    /// Also store a second copy of the index after the data, which is used when
+    /// the first one is damaged
+    ChecksumAndCopy,
+}
+
+/// Write the trailer at `at`, the end of the backpack. `index` is the header and table of contents.
+pub(crate) fn write_trailer(f: &mut impl Write, index: &[u8], at: u64, protection: IndexProtection) -> error::Result<()> {
+    let (copy_offset, copy) = match protection {
+        IndexProtection::None => return Ok(()),
+        IndexProtection::Checksum => (0, &[][..]),
+        IndexProtection::ChecksumAndCopy => {
+            f.write_all(index)?;
+            (at, index)
+        }
+    };
+
+    let trailer = IndexTrailer {
+        index_len: U64Le::new(index.len() as u64),
+        index_crc: U32Le::new(crc32(index)),
+        copy_offset: U64Le::new(copy_offset),
+        copy_len: U64Le::new(copy.len() as u64),
+        copy_crc: U32Le::new(crc32(copy)),
+        magic: *TRAILER_MAGIC,
+    };
+    f.write_all(&trailer.to_bytes())?;
+    Ok(())
+}
+
+fn read_at(file: &mut (impl Read + Seek), offset: u64, length: u64) -> error::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut res = Vec::new();
+    file.take(length).read_to_end(&mut res)?;
+    if res.len() as u64 != length {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(res)
+}
+
+fn read_trailer(file: &mut (impl Read + Seek)) -> error::Result<Option<IndexTrailer>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < TRAILER_SIZE {
+        return Ok(None);
+    }
+
+    let trailer = read_at(file, len - TRAILER_SIZE, TRAILER_SIZE)?;
+    Ok(IndexTrailer::parse(&trailer))
+}
+
+fn protection_of_trailer(trailer: &IndexTrailer) -> IndexProtection {
+    if trailer.copy_len.get() == 0 {
+        IndexProtection::Checksum
+    } else {
+        IndexProtection::ChecksumAndCopy
+    }
+}
+
+/// How the index of a backpack is protected, without checking or parsing the index.
+pub(crate) fn read_protection(file: &mut (impl Read + Seek)) -> error::Result<IndexProtection> {
+    Ok(read_trailer(file)?.map_or(IndexProtection::None, |trailer| protection_of_trailer(&trailer)))
+}
+
+/// Parse the index of a backpack, checking it against the trailer if there is one
+/// and falling back to the copy when it's damaged. Also returns how the index was protected.
+pub(crate) fn parse_protected(file: &mut (impl Read + Seek)) -> error::Result<(Index, IndexProtection)> {
+    let Some(trailer) = read_trailer(file)? else {
+        file.seek(SeekFrom::Start(0))?;
+        return Ok((BackPack::parse_headers(file)?, IndexProtection::None));
+    };
+    let protection = protection_of_trailer(&trailer);
+
+    let (index_len, index_crc) = (trailer.index_len.get(), trailer.index_crc.get());
+    let (copy_offset, copy_len, copy_crc) = (trailer.copy_offset.get(), trailer.copy_len.get(), trailer.copy_crc.get());
+
+    if let Ok(index) = read_at(file, 0, index_len) {
+        if crc32(&index) == index_crc {
+            return Ok((BackPack::parse_headers(&mut Cursor::new(index))?, protection));
+        }
+    }
+
+    if copy_len != 0 {
+        let copy = read_at(file, copy_offset, copy_len)?;
+        if crc32(&copy) == copy_crc {
+            log::warn!("index of backpack is damaged, using its copy");
+            // the copy is byte for byte the same as the original,
+            // so the offsets in it are valid relative to its start
+            return Ok((BackPack::parse_headers(&mut Cursor::new(copy))?, protection));
+        }
+    }
+
+    Err(PackError::DamagedIndex)
+}
@@ -0,0 +1,543 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use parking_lot::Mutex;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::{decode_aliases, Index};
+use crate::pack::advice::Advice;
+use crate::pack::compression::Compressed;
+use crate::pack::crc32::crc32;
+use crate::pack::encryption;
+use crate::pack::encryption::EncryptionKey;
+use crate::pack::entry_name;
+use crate::pack::maybe_ref::MaybeRef;
+use crate::pack::names::NameMatching;
+use crate::pack::observer::{ReadEvent, ReadObserver};
+use crate::pack::text;
+use crate::pack::text::Lines;
+#[cfg(feature = "http")]
+use crate::remote::{DiskCache, HttpSource};
+#[cfg(any(feature = "http", feature = "object-store"))]
+use crate::remote::{RangeStorage, INDEX_READ_AHEAD};
+#[cfg(feature = "object-store")]
+use crate::remote::{S3Config, S3Source};
+use crate::pack::{protection, BackPack, RawFile, ALIAS_ENTRY, COMPRESSION_FIELD, ENCRYPTION_ENTRY, ENCRYPTION_FIELD, METADATA_ENTRIES};
+
+/// Reads a backpack without loading it. Opening only parses the table of contents,
+/// and the contents of an entry are read from the file when the entry is read,
+/// so it works for backpacks much larger than memory. Read-only.
+///
+/// Readers can be shared between threads. Entries are read at their offset without a shared
+/// cursor, so reads from different threads don't wait for each other.
+pub struct PackReader<'f, 'backpack> {
+    file: RawFile<'f, 'backpack>,
+    /// absolute offset and length of every entry
+    entries: HashMap<String, (u64, u64)>,
+    hidden: HashSet<String>,
+    aliases: HashMap<String, String>,
+    compressed: Compressed,
+    checksums: HashMap<String, u32>,
+    alignments: HashMap<String, u64>,
+    verify: bool,
+    encrypted: HashSet<String>,
+    encryption: Option<EncryptionKey>,
+    cache: Option<Mutex<EntryCache>>,
+    name_matching: NameMatching,
+    /// names of entries and aliases by their key, for `name_matching`
+    matched_names: HashMap<String, String>,
+    /// how many of the following entries are prefetched when an entry is read
+    read_ahead: usize,
+    /// offset and length of every entry in the order they're stored, for `read_ahead`
+    by_offset: Vec<(u64, u64)>,
+    observer: Option<Box<dyn ReadObserver + 'f>>,
+}
+
+/// Entries kept in memory as they're returned by [`PackReader::read`], see [`PackReader::with_cache`].
+struct EntryCache {
+    /// contents by stored name, with when they were last used
+    entries: HashMap<String, (Vec<u8>, u64)>,
+    /// names by when they were last used, the least recently used first
+    by_use: BTreeMap<u64, String>,
+    total_size: u64,
+    max_bytes: u64,
+    /// counts uses, to order them
+    clock: u64,
+}
+
+impl EntryCache {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            total_size: 0,
+            max_bytes,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<Vec<u8>> {
+        let (contents, last_used) = self.entries.get_mut(name)?;
+        self.by_use.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.by_use.insert(self.clock, name.to_string());
+        Some(contents.clone())
+    }
+
+    /// Keep `contents`, evicting the least recently used entries to make room.
+    /// Entries larger than the whole cache aren't kept.
+    fn insert(&mut self, name: &str, contents: &[u8]) {
+        let size = contents.len() as u64;
+        if size > self.max_bytes || self.entries.contains_key(name) {
+            return;
+        }
+
+        while self.total_size + size > self.max_bytes {
+            let Some((_, oldest)) = self.by_use.pop_first() else { break };
+            if let Some((evicted, _)) = self.entries.remove(&oldest) {
+                self.total_size -= evicted.len() as u64;
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(name.to_string(), (contents.to_vec(), self.clock));
+        self.by_use.insert(self.clock, name.to_string());
+        self.total_size += size;
+    }
+}
+
+impl<'f, 'backpack> PackReader<'f, 'backpack> {
+    pub fn open<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        Self::open_with_passphrase(file.try_into().map_err(Into::into)?, None)
+    }
+
+    /// Open a backpack [encrypted](BackPack::set_encryption) with `passphrase`,
+    /// failing with [`PackError::WrongPassphrase`] when it's the wrong one.
+    #[cfg(feature = "crypto")]
+    pub fn open_encrypted<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>, passphrase: &str) -> error::Result<Self> {
+        Self::open_with_passphrase(file.try_into().map_err(Into::into)?, Some(passphrase))
+    }
+
+    /// Open a backpack on a web server. Only its index is downloaded, with `Range` requests,
+    /// and entries are downloaded when they're read. Keep entries which are read more than once
+    /// in memory [with a cache](Self::with_cache), or use [`open_url_cached`](Self::open_url_cached).
+    #[cfg(feature = "http")]
+    pub fn open_url(url: &str) -> error::Result<Self> {
+        let source = HttpSource::new(url);
+        let len = source.len()?;
+        Self::open(RawFile::from_storage(RangeStorage::new(source, len, INDEX_READ_AHEAD)))
+    }
+
+    /// Like [`open_url`](Self::open_url), keeping everything downloaded in a [`DiskCache`] in
+    /// `cache_dir` of at most `max_bytes`, so it isn't downloaded again in the next session.
+    /// The backpack at `url` is assumed not to change, the cache is keyed on the url only.
+    #[cfg(feature = "http")]
+    pub fn open_url_cached(url: &str, cache_dir: impl AsRef<Path>, max_bytes: u64) -> error::Result<Self> {
+        let source = HttpSource::new(url);
+        let len = source.len()?;
+        let cached = DiskCache::new(source, cache_dir, url, max_bytes)?;
+        Self::open(RawFile::from_storage(RangeStorage::new(cached, len, INDEX_READ_AHEAD)))
+    }
+
+    /// Open a backpack in S3-compatible storage, like Google Cloud Storage through its XML API
+    /// or MinIO. Like [`open_url`](Self::open_url), only the index is downloaded when opening it.
+    /// Write one with [`S3Upload`](crate::remote::S3Upload).
+    #[cfg(feature = "object-store")]
+    pub fn open_s3(config: S3Config, key: &str) -> error::Result<Self> {
+        let source = S3Source::new(config, key);
+        let len = source.len()?;
+        Self::open(RawFile::from_storage(RangeStorage::new(source, len, INDEX_READ_AHEAD)))
+    }
+
+    fn open_with_passphrase(mut file: RawFile<'f, 'backpack>, passphrase: Option<&str>) -> error::Result<Self> {
+        let (Index { offsets, mut toc_blocks, hidden, compressed, checksums, encrypted, alignments, .. }, _) = protection::parse_protected(&mut file)?;
+        toc_blocks.sort();
+
+        let mut entries = offsets.into_iter()
+            .map(|(name, (offset, length))| (name, (BackPack::convert_offset(&toc_blocks, offset), length)))
+            .collect::<HashMap<_, _>>();
+
+        let aliases = match entries.get(ALIAS_ENTRY) {
+            Some(&(offset, length)) => {
+                let mut buf = vec![0; length as usize];
+                file.read_exact_at(offset, &mut buf)?;
+                decode_aliases(&buf)?
+            }
+            None => HashMap::new(),
+        };
+        let encryption = match entries.get(ENCRYPTION_ENTRY) {
+            Some(&(offset, length)) => {
+                let mut buf = vec![0; length as usize];
+                file.read_exact_at(offset, &mut buf)?;
+                Some(EncryptionKey::from_entry(passphrase, &buf)?)
+            }
+            None if !encrypted.is_empty() => return Err(PackError::InvalidEntry),
+            None => None,
+        };
+        for name in METADATA_ENTRIES {
+            entries.remove(*name);
+        }
+        if encryption.is_some() {
+            encryption::check_encrypted(&entries, &encrypted)?;
+        }
+
+        Ok(Self {
+            file,
+            entries,
+            hidden,
+            aliases,
+            compressed,
+            checksums,
+            alignments,
+            verify: false,
+            encrypted,
+            encryption,
+            cache: None,
+            name_matching: NameMatching::Exact,
+            matched_names: HashMap::new(),
+            read_ahead: 0,
+            by_offset: Vec::new(),
+            observer: None,
+        })
+    }
+
+    /// Keep up to `max_bytes` of entries in memory once they're [read](Self::read), decrypted and
+    /// decompressed, so reading them again doesn't touch the file. When the cache is full the
+    /// least recently read entries make room. Entries read through [`get`](Self::get) aren't cached.
+    pub fn with_cache(mut self, max_bytes: u64) -> Self {
+        self.cache = Some(Mutex::new(EntryCache::new(max_bytes)));
+        self
+    }
+
+    /// Tell `observer` about every read of an entry, see [`ReadObserver`]. Without an observer
+    /// reads aren't timed.
+    pub fn with_observer(mut self, observer: impl ReadObserver + 'f) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// How many bytes of entries are [cached](Self::with_cache) at the moment.
+    pub fn cached_bytes(&self) -> u64 {
+        self.cache.as_ref().map_or(0, |cache| cache.lock().total_size)
+    }
+
+    /// Check entries against the checksum stored for them when they're [read](Self::read),
+    /// failing with [`PackError::ChecksumMismatch`] when they were damaged on disk.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// How names are compared when entries are looked up, see [`BackPack::set_name_matching`].
+    pub fn set_name_matching(&mut self, matching: NameMatching) {
+        self.name_matching = matching;
+        self.matched_names.clear();
+        if matching == NameMatching::Exact {
+            return;
+        }
+
+        let mut names = self.entries.keys().chain(self.aliases.keys()).collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            self.matched_names.entry(matching.key(name).into_owned()).or_insert_with(|| name.clone());
+        }
+    }
+
+    /// Start loading `names` in the background, so reading them later doesn't wait for the disk.
+    /// Useful to hide the latency of loading a level behind other work. Only a hint: for files
+    /// on disk the operating system reads ahead, other files ignore it.
+    pub fn prefetch<P: AsRef<Path>>(&self, names: impl IntoIterator<Item=P>) -> error::Result<()> {
+        for name in names {
+            let (_, (start, length)) = self.find(name.as_ref())?;
+            self.file.advise(start, length, Advice::WillNeed)?;
+        }
+        Ok(())
+    }
+
+    /// Whenever an entry is read, [prefetch](Self::prefetch) the `entries` stored after it,
+    /// for backpacks which are read in about the order they were written. 0, the default, turns
+    /// it off.
+    pub fn set_read_ahead(&mut self, entries: usize) {
+        self.read_ahead = entries;
+        self.by_offset = match entries {
+            0 => Vec::new(),
+            _ => {
+                let mut by_offset = self.entries.values().copied().collect::<Vec<_>>();
+                by_offset.sort();
+                by_offset.dedup();
+                by_offset
+            }
+        };
+    }
+
+    /// The region of the entries to read ahead after the one stored at `start`.
+    fn read_ahead_region(&self, start: u64) -> Option<(u64, u64)> {
+        if self.read_ahead == 0 {
+            return None;
+        }
+        let index = self.by_offset.partition_point(|(offset, _)| *offset <= start);
+        let (first, _) = *self.by_offset.get(index)?;
+        let (last, length) = self.by_offset[(index + self.read_ahead).min(self.by_offset.len()) - 1];
+        Some((first, last + length - first))
+    }
+
+    fn read_ahead_after(&self, start: u64) {
+        if let Some((offset, length)) = self.read_ahead_region(start) {
+            // only a hint, reading the entry itself doesn't depend on it
+            let _ = self.file.advise(offset, length, Advice::WillNeed);
+        }
+    }
+
+    /// The stored name and location of the entry called `name`, or of the entry it's an alias of.
+    fn find(&self, name: &Path) -> error::Result<(&str, (u64, u64))> {
+        let name_str = entry_name::key_of(name.as_os_str());
+        let lookup = |name: &str| self.entries.get_key_value(name)
+            .or_else(|| self.entries.get_key_value(self.aliases.get(name)?));
+        lookup(&name_str)
+            .or_else(|| lookup(self.matched_names.get(self.name_matching.key(&name_str).as_ref())?))
+            .map(|(name, key)| (name.as_str(), *key))
+            .ok_or_else(|| PackError::FileNotFound(name.to_path_buf()))
+    }
+
+    /// The entry called `name`, or the entry it's an [alias](BackPack::set_alias) of.
+    /// Nothing is read until the entry is. Compressed and encrypted entries can only be [read](Self::read) whole.
+    pub fn get(&self, name: impl AsRef<Path>) -> error::Result<Entry<'_, 'f, 'backpack>> {
+        let (name_str, (start, length)) = self.find(name.as_ref())?;
+        if self.compressed.contains_key(name_str) {
+            return Err(PackError::UnsupportedIndexField(COMPRESSION_FIELD));
+        }
+        if self.encrypted.contains(name_str) {
+            return Err(PackError::UnsupportedIndexField(ENCRYPTION_FIELD));
+        }
+        self.read_ahead_after(start);
+
+        Ok(Entry {
+            reader: self,
+            name: name_str,
+            start,
+            length,
+            position: 0,
+        })
+    }
+
+    /// Read all of an entry into memory, decrypting and decompressing it if needed.
+    pub fn read(&self, name: impl AsRef<Path>) -> error::Result<Vec<u8>> {
+        let (name_str, key) = self.find(name.as_ref())?;
+        let Some(observer) = &self.observer else {
+            return self.read_cached(name_str, key).map(|(contents, _)| contents);
+        };
+
+        let started = Instant::now();
+        let (contents, cached) = self.read_cached(name_str, key)?;
+        observer.read(ReadEvent {
+            entry: name_str,
+            bytes: contents.len() as u64,
+            duration: started.elapsed(),
+            cached,
+        });
+        Ok(contents)
+    }
+
+    /// The contents of an entry, and whether they came from the cache.
+    fn read_cached(&self, name_str: &str, key: (u64, u64)) -> error::Result<(Vec<u8>, bool)> {
+        let Some(cache) = &self.cache else {
+            return Ok((self.read_stored(name_str, key)?, false));
+        };
+
+        if let Some(contents) = cache.lock().get(name_str) {
+            return Ok((contents, true));
+        }
+        // not holding the lock while reading, so other threads can use the cache meanwhile
+        let contents = self.read_stored(name_str, key)?;
+        cache.lock().insert(name_str, &contents);
+        Ok((contents, false))
+    }
+
+    fn read_stored(&self, name_str: &str, (start, length): (u64, u64)) -> error::Result<Vec<u8>> {
+        self.read_ahead_after(start);
+        let mut buf = vec![0; length as usize];
+        self.file.read_exact_at(start, &mut buf)?;
+
+        if self.verify && self.checksums.get(name_str).is_some_and(|checksum| crc32(&buf) != *checksum) {
+            return Err(PackError::ChecksumMismatch(PathBuf::from(name_str)));
+        }
+
+        if self.encrypted.contains(name_str) {
+            buf = self.encryption.as_ref().ok_or(PackError::InvalidEntry)?.decrypt(name_str, &buf)?;
+        }
+        match self.compressed.get(name_str) {
+            Some((method, len)) => method.decompress(&buf, *len),
+            None => Ok(buf),
+        }
+    }
+
+    /// The contents of `name` as text. When they aren't UTF-8, the error says on which line.
+    pub fn read_to_string(&self, name: impl AsRef<Path>) -> error::Result<String> {
+        text::decode(name.as_ref(), self.read(name.as_ref())?)
+    }
+
+    /// The lines of the text file `name`, read as they're needed. Compressed and encrypted
+    /// entries are read whole first.
+    pub fn lines(&self, name: impl AsRef<Path>) -> error::Result<Lines<Box<dyn BufRead + '_>>> {
+        let name = name.as_ref();
+        let inner: Box<dyn BufRead> = match self.get(name) {
+            Ok(entry) => Box::new(BufReader::new(entry)),
+            Err(PackError::UnsupportedIndexField(_)) => Box::new(Cursor::new(self.read(name)?)),
+            Err(e) => return Err(e),
+        };
+        Ok(Lines::new(inner, name))
+    }
+
+    pub fn contains(&self, name: impl AsRef<Path>) -> bool {
+        self.find(name.as_ref()).is_ok()
+    }
+
+    /// Names of all files, sorted, without [hidden](BackPack::set_hidden) files and directories.
+    pub fn file_names(&self) -> Vec<&str> {
+        let mut res = self.entries.keys()
+            .filter(|name| !name.ends_with('/') && !self.hidden.contains(*name))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    pub fn is_hidden(&self, name: impl AsRef<Path>) -> bool {
+        self.find(name.as_ref()).is_ok_and(|(name, _)| self.hidden.contains(name))
+    }
+
+    /// The alignment the stored contents of `name` start at, relative to the start of the
+    /// backpack, as recorded by the writer. 1 when no alignment was recorded.
+    pub fn alignment(&self, name: impl AsRef<Path>) -> error::Result<u64> {
+        let (name_str, _) = self.find(name.as_ref())?;
+        Ok(self.alignments.get(name_str).copied().unwrap_or(1))
+    }
+
+    pub fn into_inner(self) -> RawFile<'f, 'backpack> {
+        self.file
+    }
+}
+
+/// An entry of a [`PackReader`], read from the backpack's file on demand.
+/// Entries share the file, so they can be read at the same time from different threads.
+pub struct Entry<'r, 'f, 'backpack> {
+    reader: &'r PackReader<'f, 'backpack>,
+    name: &'r str,
+    /// absolute offset in the file
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<'r> Entry<'r, '_, '_> {
+    /// The name the entry is stored under, which is not the name it was opened by for aliases.
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// The contents of the entry borrowed from the backpack, without copying them, when the
+    /// backpack is [in memory](RawFile::InMemory) or memory mapped.
+    /// `None` for backpacks on disk, which have to be read. Checked against their checksum
+    /// like [`PackReader::read`] does when [verifying](PackReader::set_verify).
+    pub fn as_slice(&self) -> error::Result<Option<MaybeRef<'r, [u8]>>> {
+        let Some(contents) = self.reader.file.slice_at(self.start, self.length) else {
+            return Ok(None);
+        };
+        if self.reader.verify && self.reader.checksums.get(self.name).is_some_and(|checksum| crc32(&contents) != *checksum) {
+            return Err(PackError::ChecksumMismatch(PathBuf::from(self.name)));
+        }
+        Ok(Some(contents))
+    }
+}
+
+impl Read for Entry<'_, '_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.position);
+        let n = (buf.len() as u64).min(remaining) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+
+        // only timed when someone is watching
+        let started = self.reader.observer.as_ref().map(|_| Instant::now());
+        self.reader.file.read_exact_at(self.start + self.position, &mut buf[..n])?;
+        self.position += n as u64;
+        if let (Some(observer), Some(started)) = (&self.reader.observer, started) {
+            observer.read(ReadEvent {
+                entry: self.name,
+                bytes: n as u64,
+                duration: started.elapsed(),
+                cached: false,
+            });
+        }
+        Ok(n)
+    }
+}
+
+impl Seek for Entry<'_, '_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::reader::EntryCache;
+    use crate::pack::{BackPack, InMemoryFile, PackReader, RawFile};
+
+    #[test]
+    fn test_least_recently_used() {
+        let mut cache = EntryCache::new(30);
+        cache.insert("a", &[1; 10]);
+        cache.insert("b", &[2; 10]);
+        cache.insert("c", &[3; 10]);
+        // a is now used more recently than b
+        assert_eq!(cache.get("a"), Some(vec![1; 10]));
+
+        cache.insert("d", &[4; 10]);
+        assert_eq!(cache.get("b"), None);
+        assert!(cache.get("a").is_some() && cache.get("c").is_some() && cache.get("d").is_some());
+
+        cache.insert("e", &[5; 25]);
+        assert_eq!(cache.total_size, 25);
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_read_ahead_region() {
+        let bp = BackPack::create(RawFile::in_memory("test")).unwrap();
+        for name in ["a", "b", "c", "d"] {
+            bp.add_file(InMemoryFile::from(name.repeat(10)).with_name(name)).unwrap();
+        }
+        let bytes = bp.close().unwrap().into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let mut reader = PackReader::open(bytes).unwrap();
+        let start = |name: &str| reader.entries[name].0;
+        let (a, b, c, d) = (start("a"), start("b"), start("c"), start("d"));
+        assert_eq!(reader.read_ahead_region(a), None);
+
+        reader.set_read_ahead(2);
+        let (offset, length) = reader.read_ahead_region(a).unwrap();
+        assert_eq!(offset, b);
+        assert_eq!(offset + length, c + 10);
+        // only what's left after the last but one
+        assert_eq!(reader.read_ahead_region(c), Some((d, 10)));
+        assert_eq!(reader.read_ahead_region(d), None);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::{decode_aliases, decode_attributes, decode_tiers, decode_times, normalize_name, Index};
+use crate::pack::crc32::crc32;
+use crate::pack::entry_name;
+use crate::pack::layout::{PackHeader, TocBlockHeader, TocEntry};
+use crate::pack::{protection, BackPack, RawFile, ALIAS_ENTRY, ATTRIBUTES_ENTRY, ENCRYPTION_ENTRY, EXPIRY_ENTRY, MODIFIED_ENTRY, PACK_VERSION, SIGNATURE_ENTRY, TIERS_ENTRY, TOC_SIZE};
+
+/// How much of a backpack which isn't quite right is accepted when it's opened,
+/// see [`BackPack::open_with_mode`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ReadMode {
+    /// What [`BackPack::open`] does: damage which makes entries unreadable is an error,
+    /// oddities which don't, like a toc block claiming more than it holds, are let through.
+    #[default]
+    Normal,
+    /// Any malformed structure is an error, and every checksum is checked up front.
+    /// For backpacks from untrusted sources.
+    Strict,
+    /// Salvage as many entries as possible from a truncated or damaged backpack,
+    /// see [`BackPack::recover`].
+    Recover,
+}
+
+/// What [`BackPack::recover`] couldn't salvage.
+#[derive(Debug, Default)]
+pub struct SalvageReport {
+    /// names of the files which were recovered, sorted
+    pub recovered: Vec<String>,
+    /// entries which were in the index, but whose contents couldn't be recovered
+    pub skipped: Vec<(String, PackError)>,
+    /// where the table of contents blocks are which couldn't be read, the entries in them are lost
+    pub lost_blocks: Vec<(u64, PackError)>,
+}
+
+impl SalvageReport {
+    /// Whether nothing was lost.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty() && self.lost_blocks.is_empty()
+    }
+}
+
+/// What could be salvaged from a damaged backpack.
+pub(crate) struct Salvaged {
+    pub entries: Vec<(String, Vec<u8>)>,
+    pub hidden: HashSet<String>,
+    pub report: SalvageReport,
+}
+
+fn malformed(what: impl Into<String>) -> PackError {
+    PackError::Malformed(what.into())
+}
+
+/// Check everything about the structure of the backpack in `file` which opening it normally
+/// lets through, and the checksums of all entries.
+pub(crate) fn check_strict(file: &mut RawFile<'_, '_>) -> error::Result<()> {
+    let file_len = file.metadata()?.len;
+    file.seek(SeekFrom::Start(0))?;
+    let header = PackHeader::read_from(file)?;
+    if header.version() != PACK_VERSION {
+        return Err(PackError::Incompatible(header.version()));
+    }
+
+    let mut index = Index::default();
+    let mut names = HashSet::new();
+    let mut toc_blocks = BackPack::read_toc_blocks(file, header.first_toc.get(), |filled, block| {
+        let filled = filled as usize;
+        if filled > block.len() {
+            return Err(PackError::InvalidEntry);
+        }
+        let mut curr = 0;
+        while curr < filled {
+            let (entry, len) = TocEntry::parse(&block[curr..filled])?;
+            curr += len;
+            let name = entry_name::key_of_bytes(entry.name).into_owned();
+            if !names.insert(name.clone()) {
+                return Err(malformed(format!("{:?} is in the index twice", name)));
+            }
+        }
+        BackPack::parse_toc_block(filled as u16, block, &mut index)
+    })?;
+    toc_blocks.sort();
+
+    let data_size = header.size.get();
+    let mut ranges = Vec::new();
+    for (name, (offset, length)) in &index.offsets {
+        if name.is_empty() || name.starts_with('/') || normalize_name(Path::new(name)) != *name {
+            return Err(malformed(format!("{:?} isn't a normalized relative name", name)));
+        }
+        if *length == 0 {
+            continue;
+        }
+        let end = offset.checked_add(*length).ok_or_else(|| malformed(format!("{:?} is too long", name)))?;
+        if end > data_size {
+            return Err(malformed(format!("{:?} ends after the data section", name)));
+        }
+        let location = BackPack::convert_offset(&toc_blocks, *offset);
+        if location.saturating_add(*length) > file_len {
+            return Err(malformed(format!("{:?} ends after the end of the file", name)));
+        }
+        if index.alignments.get(name).is_some_and(|alignment| !location.is_multiple_of(*alignment)) {
+            return Err(malformed(format!("{:?} isn't aligned like the index says", name)));
+        }
+        ranges.push((*offset, end));
+
+        if let Some(checksum) = index.checksums.get(name) {
+            let mut buf = vec![0; *length as usize];
+            file.read_exact_at(location, &mut buf)?;
+            if crc32(&buf) != *checksum {
+                return Err(PackError::ChecksumMismatch(name.into()));
+            }
+        }
+    }
+
+    // entries with the same contents share them, others mustn't overlap
+    ranges.sort();
+    ranges.dedup();
+    for pair in ranges.windows(2) {
+        if pair[1].0 < pair[0].1 {
+            return Err(malformed(format!("entries at offsets {} and {} overlap", pair[0].0, pair[1].0)));
+        }
+    }
+    Ok(())
+}
+
+/// Read the table of contents of the backpack in `file` as far as it can be read,
+/// entry by entry, and the toc blocks it could be read from.
+fn salvage_index(file: &mut RawFile<'_, '_>, report: &mut SalvageReport) -> error::Result<(Index, Vec<u64>)> {
+    // an intact index, or an intact copy of it
+    if let Ok((index, _)) = protection::parse_protected(file) {
+        let toc_blocks = index.toc_blocks.clone();
+        return Ok((index, toc_blocks));
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    let header = PackHeader::read_from(file)?;
+    if header.version() != PACK_VERSION {
+        return Err(PackError::Incompatible(header.version()));
+    }
+
+    let mut index = Index::default();
+    let mut toc_blocks = Vec::new();
+    let mut next = header.first_toc.get();
+    while next != 0 {
+        let offset = next;
+        if toc_blocks.contains(&offset) {
+            report.lost_blocks.push((offset, PackError::CorruptIndex { offset }));
+            break;
+        }
+
+        let mut block = vec![0; TOC_SIZE as usize];
+        if let Err(e) = file.read_exact_at(offset, &mut block) {
+            report.lost_blocks.push((offset, e));
+            break;
+        }
+        toc_blocks.push(offset);
+        let block_header = TocBlockHeader::from_bytes(block[..TocBlockHeader::SIZE].try_into().expect("a toc block is larger than its header"));
+        next = block_header.next.get();
+
+        let body = &block[TocBlockHeader::SIZE..];
+        let filled = match block_header.entries_len() {
+            Ok(filled) => (filled as usize).min(body.len()),
+            Err(e) => {
+                report.lost_blocks.push((offset, e.into()));
+                continue;
+            }
+        };
+        // the entries before a damaged one are still good
+        let mut curr = 0;
+        while curr < filled {
+            let parsed = TocEntry::parse(&body[curr..filled])
+                .map_err(PackError::from)
+                .and_then(|(_, len)| BackPack::parse_toc_block(len as u16, &body[curr..curr + len], &mut index).map(|_| len));
+            match parsed {
+                Ok(len) => curr += len,
+                Err(e) => {
+                    report.lost_blocks.push((offset, e));
+                    break;
+                }
+            }
+        }
+    }
+
+    toc_blocks.sort();
+    Ok((index, toc_blocks))
+}
+
+/// Salvage every entry of the backpack in `file` whose index record and contents are intact.
+pub(crate) fn salvage(file: &mut RawFile<'_, '_>) -> error::Result<Salvaged> {
+    let mut report = SalvageReport::default();
+    let (index, toc_blocks) = salvage_index(file, &mut report)?;
+
+    let mut entries = Vec::new();
+    let mut names = index.offsets.iter().collect::<Vec<_>>();
+    names.sort();
+    for (name, (offset, length)) in names {
+        // the rebuilt backpack isn't what was signed, and isn't encrypted
+        if name == SIGNATURE_ENTRY || name == ENCRYPTION_ENTRY {
+            continue;
+        }
+
+        let contents = (|| {
+            if index.encrypted.contains(name) {
+                return Err(PackError::Encrypted);
+            }
+            let mut buf = vec![0; *length as usize];
+            if *length != 0 {
+                file.read_exact_at(BackPack::convert_offset(&toc_blocks, *offset), &mut buf)?;
+            }
+            if index.checksums.get(name).is_some_and(|checksum| crc32(&buf) != *checksum) {
+                return Err(PackError::ChecksumMismatch(name.into()));
+            }
+            if let Some((method, len)) = index.compressed.get(name) {
+                buf = method.decompress(&buf, *len)?;
+            }
+            // metadata which can't be read would make the whole backpack unreadable
+            match name.as_str() {
+                EXPIRY_ENTRY | MODIFIED_ENTRY => { decode_times(&buf)?; }
+                ATTRIBUTES_ENTRY => { decode_attributes(&buf)?; }
+                ALIAS_ENTRY => { decode_aliases(&buf)?; }
+                TIERS_ENTRY => { decode_tiers(&buf)?; }
+                _ => {}
+            }
+            Ok(buf)
+        })();
+
+        match contents {
+            Ok(contents) => entries.push((name.clone(), contents)),
+            Err(e) => report.skipped.push((name.clone(), e)),
+        }
+    }
+
+    report.recovered = entries.iter()
+        .map(|(name, _)| name.clone())
+        .filter(|name| !name.starts_with(".backpack/"))
+        .collect();
+    Ok(Salvaged {
+        entries,
+        hidden: index.hidden,
+        report,
+    })
+}
@@ -0,0 +1,32 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A way of turning values into the contents of a file and back, for
+/// [`BackPack::put_serialized_with`](crate::BackPack::put_serialized_with) and
+/// [`BackPack::get_deserialized_with`](crate::BackPack::get_deserialized_with).
+pub trait Format {
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, String>;
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String>;
+}
+
+/// Files as JSON, the format of [`BackPack::put_serialized`](crate::BackPack::put_serialized).
+#[cfg(feature = "json")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Json {
+    /// indent the JSON so it's readable when extracted
+    pub pretty: bool,
+}
+
+#[cfg(feature = "json")]
+impl Format for Json {
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self.pretty {
+            true => serde_json::to_vec_pretty(value),
+            false => serde_json::to_vec(value),
+        }.map_err(|e| e.to_string())
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())
+    }
+}
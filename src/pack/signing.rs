@@ -0,0 +1,95 @@
+//! Ed25519 signatures over the files of a backpack and their metadata, made with `ring`.
+
+use std::fmt;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use crate::error;
+use crate::error::PackError;
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+
+/// A private key to sign backpacks with, see [`BackPack::set_signing`](crate::BackPack::set_signing).
+pub struct SigningKey {
+    pair: Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// A new random key, as a PKCS#8 document to keep it in. Load it with [`from_pkcs8`](Self::from_pkcs8).
+    pub fn generate_pkcs8() -> error::Result<Vec<u8>> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| PackError::InvalidSigningKey)?;
+        Ok(document.as_ref().to_vec())
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> error::Result<Self> {
+        Ok(Self {
+            pair: Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8).map_err(|_| PackError::InvalidSigningKey)?,
+        })
+    }
+
+    /// The key derived from the 32 byte `seed`, as in RFC 8032.
+    pub fn from_seed(seed: &[u8; 32]) -> error::Result<Self> {
+        Ok(Self {
+            pair: Ed25519KeyPair::from_seed_unchecked(seed).map_err(|_| PackError::InvalidSigningKey)?,
+        })
+    }
+
+    /// The public key to [verify](crate::BackPack::open_verified) backpacks signed with this key with.
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        self.pair.public_key().as_ref().try_into().expect("ed25519 public keys are 32 bytes")
+    }
+
+    /// The contents of the [signature entry](crate::pack::SIGNATURE_ENTRY) for `message`,
+    /// the public key followed by the signature.
+    pub(crate) fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let mut res = self.public_key().to_vec();
+        res.extend_from_slice(self.pair.sign(message).as_ref());
+        res
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The public key which made the signature in the contents of a signature entry.
+pub(crate) fn signer(entry: &[u8]) -> error::Result<[u8; PUBLIC_KEY_SIZE]> {
+    if entry.len() != PUBLIC_KEY_SIZE + SIGNATURE_SIZE {
+        return Err(PackError::InvalidEntry);
+    }
+    Ok(entry[..PUBLIC_KEY_SIZE].try_into().unwrap())
+}
+
+/// Check that the signature entry `entry` holds a signature of `message` by `public_key`.
+pub(crate) fn verify(entry: &[u8], message: &[u8], public_key: &[u8; PUBLIC_KEY_SIZE]) -> error::Result<()> {
+    // the stored key is only informational, what counts is the key the caller trusts
+    signer(entry)?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(message, &entry[PUBLIC_KEY_SIZE..])
+        .map_err(|_| PackError::BadSignature)
+}
+
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    ring::digest::digest(&ring::digest::SHA256, data).as_ref().try_into().expect("sha256 digests are 32 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::signing::{verify, SigningKey};
+    use crate::PackError;
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::from_pkcs8(&SigningKey::generate_pkcs8().unwrap()).unwrap();
+        let other = SigningKey::from_seed(&[7; 32]).unwrap();
+        let entry = key.sign(b"message");
+
+        verify(&entry, b"message", &key.public_key()).unwrap();
+        assert!(matches!(verify(&entry, b"massage", &key.public_key()), Err(PackError::BadSignature)));
+        assert!(matches!(verify(&entry, b"message", &other.public_key()), Err(PackError::BadSignature)));
+    }
+}
@@ -1,13 +1,19 @@
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom, Write};
 use std::ops::{Deref, DerefMut};
 use parking_lot::RwLock;
 use crate::BackPack;
+use crate::pack::buffered::ReadWindow;
+use crate::pack::handles::Handle;
 
 pub struct PackSlice<'f, 'backpack> {
     start: u64,
     end: u64,
 
     pos: u64,
+    /// registered with the pack so it knows which handles are open, closed when dropped
+    _handle: Handle,
+    /// copied out for [`BufRead`], the contents are behind a lock
+    window: ReadWindow,
 
     pub(crate) pack: &'f BackPack<'f, 'backpack>
 }
@@ -18,6 +24,8 @@ impl Clone for PackSlice<'_, '_> {
             start: self.start,
             end: self.end,
             pos: self.pos,
+            _handle: self.pack.handles().open((self.start, self.end)),
+            window: ReadWindow::default(),
             pack: self.pack
         }
     }
@@ -29,6 +37,8 @@ impl<'f, 'backpack> PackSlice<'f, 'backpack> {
             start,
             end,
             pos: 0,
+            _handle: pack.handles().open((start, end)),
+            window: ReadWindow::default(),
             pack
         }
     }
@@ -37,6 +47,10 @@ impl<'f, 'backpack> PackSlice<'f, 'backpack> {
         self.pos
     }
 
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
     pub fn identifier(&self) -> (u64, u64) {
         (self.start, self.end)
     }
@@ -48,6 +62,7 @@ impl<'f, 'backpack> PackSlice<'f, 'backpack> {
     pub fn resize(&mut self, size: u64) {
         let v = self.pack.retrieve_slice(self);
         v.write().resize(size as usize, 0);
+        self.window.clear();
     }
 }
 
@@ -74,6 +89,7 @@ impl Write for PackSlice<'_, '_> {
         c.set_position(self.pos);
         let res = c.write(buf)?;
         self.pos = c.position();
+        self.window.clear();
 
         Ok(res)
     }
@@ -98,4 +114,21 @@ impl Seek for PackSlice<'_, '_> {
 
         Ok(res)
     }
-}
\ No newline at end of file
+}
+
+impl BufRead for PackSlice<'_, '_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let pack = self.pack;
+        let g = pack.retrieve_slice(self).read();
+        self.window.fill(self.pos, |pos, buf| {
+            let rest = g.get(pos as usize..).unwrap_or_default();
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            Ok(n)
+        })
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
@@ -0,0 +1,13 @@
+use std::borrow::Cow;
+use crate::error;
+use crate::format::SliceReader;
+
+impl<'a> SliceReader<'a> {
+    /// The contents of an entry, decompressed if needed. Only compressed entries are copied.
+    pub fn read(&self, name: &str) -> error::Result<Cow<'a, [u8]>> {
+        match self.stored(name)? {
+            (contents, Some((method, len))) => Ok(Cow::Owned(method.decompress(contents, len)?)),
+            (contents, None) => Ok(Cow::Borrowed(contents)),
+        }
+    }
+}
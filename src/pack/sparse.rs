@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use crate::error;
+use crate::error::{AtPath, PackError};
+
+/// Free the disk space of a region of `file`, which reads as zeros afterwards. Returns whether
+/// the platform and file system could, when they can't nothing happens.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn punch_hole(file: &File, offset: u64, length: u64) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if length == 0 {
+        return Ok(true);
+    }
+    // Safety: the file descriptor is valid for as long as we borrow the file
+    let res = unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, offset as libc::off_t, length as libc::off_t)
+    };
+    if res != 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(false),
+            _ => Err(e),
+        };
+    }
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn punch_hole(_file: &File, _offset: u64, _length: u64) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Reserve disk space for a region of `file`, growing it when the region ends after it.
+/// Where space can't be reserved up front, the file only grows.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn allocate(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if length == 0 {
+        return Ok(());
+    }
+    // Safety: the file descriptor is valid for as long as we borrow the file
+    let res = unsafe {
+        libc::fallocate(file.as_raw_fd(), 0, offset as libc::off_t, length as libc::off_t)
+    };
+    if res != 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => grow(file, offset + length),
+            _ => Err(e),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn allocate(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    grow(file, offset + length)
+}
+
+fn grow(file: &File, len: u64) -> io::Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)?;
+    }
+    Ok(())
+}
+
+/// The holes in `file`, as offsets and lengths, found with `SEEK_HOLE` and `SEEK_DATA`.
+/// Empty where the platform or file system can't tell.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn holes(file: &File) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = file.metadata()?.len();
+    let seek = |offset: u64, whence| {
+        // Safety: the file descriptor is valid for as long as we borrow the file. Seeking moves
+        // the cursor, but files are only read with pread while looking for holes.
+        let res = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+        match res {
+            -1 => match io::Error::last_os_error() {
+                // no data after offset
+                e if e.raw_os_error() == Some(libc::ENXIO) => Ok(len),
+                e => Err(e),
+            },
+            n => Ok(n as u64),
+        }
+    };
+
+    let mut res = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let hole = seek(offset, libc::SEEK_HOLE)?;
+        if hole >= len {
+            break;
+        }
+        let data = seek(hole, libc::SEEK_DATA)?.min(len);
+        res.push((hole, data - hole));
+        offset = data;
+    }
+    Ok(res)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn holes(_file: &File) -> io::Result<Vec<(u64, u64)>> {
+    Ok(Vec::new())
+}
+
+/// Holes as the value of [`SPARSE_ATTRIBUTE`](crate::pack::SPARSE_ATTRIBUTE), `{offset}+{length}` separated by `,`.
+pub(crate) fn encode_holes(holes: &[(u64, u64)]) -> String {
+    holes.iter()
+        .map(|(offset, length)| format!("{}+{}", offset, length))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub(crate) fn decode_holes(value: &[u8]) -> error::Result<Vec<(u64, u64)>> {
+    let value = std::str::from_utf8(value).map_err(|_| PackError::InvalidEntry)?;
+    value.split(',')
+        .filter(|hole| !hole.is_empty())
+        .map(|hole| {
+            let (offset, length) = hole.split_once('+').ok_or(PackError::InvalidEntry)?;
+            Ok((offset.parse().map_err(|_| PackError::InvalidEntry)?, length.parse().map_err(|_| PackError::InvalidEntry)?))
+        })
+        .collect()
+}
+
+/// The holes out of `holes` which are within `contents` and only hold zeros there.
+pub(crate) fn zeroed_holes(contents: &[u8], holes: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    holes.iter()
+        .copied()
+        .filter(|&(offset, length)| {
+            let Some(end) = offset.checked_add(length).filter(|end| *end <= contents.len() as u64) else {
+                return false;
+            };
+            contents[offset as usize..end as usize].iter().all(|b| *b == 0)
+        })
+        .collect()
+}
+
+/// Write `contents` to a new file at `path`, leaving out the regions in `holes`, so they
+/// become holes again on file systems which support them.
+pub(crate) fn write_sparse(path: &Path, contents: &[u8], holes: &[(u64, u64)]) -> error::Result<()> {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let mut holes = zeroed_holes(contents, holes);
+    holes.sort_unstable();
+    let mut file = File::create(path).at_path(path)?;
+    let mut offset = 0;
+    for (start, length) in holes.into_iter().chain([(contents.len() as u64, 0)]) {
+        if start > offset {
+            file.seek(SeekFrom::Start(offset)).at_path(path)?;
+            file.write_all(&contents[offset as usize..start as usize]).at_path(path)?;
+        }
+        offset = offset.max(start + length);
+    }
+    // a hole at the end is only there once the file has its length
+    file.set_len(contents.len() as u64).at_path(path)?;
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::ops::Range;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::crc32::crc32;
+use crate::pack::entry_name;
+use crate::pack::layout::{encode_fields, PackHeader, TocEntry, U16Le, U32Le, CHECKSUM_FIELD, CRITICAL_FIELD, DATA_FILE_FIELD, FLAGS_FIELD, HIDDEN};
+use crate::pack::{BackPack, PACK_HEADER_SIZE, PACK_VERSION};
+
+/// A backpack split into a small metadata file holding the table of contents,
+/// and data files holding the contents. See [`BackPack::export_split`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitPack {
+    pub metadata: Vec<u8>,
+    pub data_files: Vec<Vec<u8>>,
+}
+
+/// A file in a split backpack, as described by the metadata file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitEntry {
+    pub name: String,
+    /// index of the data file holding the contents
+    pub data_file: u16,
+    /// from the start of the data file
+    pub offset: u64,
+    pub length: u64,
+    pub crc32: Option<u32>,
+    pub hidden: bool,
+}
+
+impl SplitEntry {
+    /// Where the contents are in the data file.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.length
+    }
+}
+
+impl SplitPack {
+    /// Write `entries` split over data files of at most `max_data_file_size` bytes.
+    /// Files larger than that get a data file to themselves.
+    pub(crate) fn write(entries: &[(&str, &[u8])], hidden: &HashSet<String>, max_data_file_size: u64) -> error::Result<Self> {
+        let mut data_files: Vec<Vec<u8>> = Vec::new();
+        let mut locations = HashMap::new();
+        let mut fields = HashMap::new();
+
+        for (name, contents) in entries {
+            let fits = data_files.last()
+                .is_some_and(|last| last.is_empty() || (last.len() + contents.len()) as u64 <= max_data_file_size);
+            if !fits {
+                data_files.push(Vec::new());
+            }
+
+            let data_file: u16 = (data_files.len() - 1).try_into().map_err(|_| PackError::TooManyDataFiles)?;
+            let current = data_files.last_mut().expect("a data file was just added");
+            locations.insert(name.to_string(), (current.len() as u64, contents.len() as u64));
+            current.extend_from_slice(contents);
+
+            let data_file = U16Le::new(data_file).to_bytes();
+            let checksum = U32Le::new(crc32(contents)).to_bytes();
+            let flags = U16Le::new(HIDDEN).to_bytes();
+            let mut entry_fields = vec![(DATA_FILE_FIELD, &data_file[..]), (CHECKSUM_FIELD, &checksum[..])];
+            if hidden.contains(*name) {
+                entry_fields.push((FLAGS_FIELD, &flags[..]));
+            }
+            fields.insert(name.to_string(), encode_fields(&entry_fields)?);
+        }
+
+        let toc_blocks = BackPack::create_toc_with(&locations, PACK_HEADER_SIZE, |name| Ok(fields[name].clone()))?;
+        let first_toc = if toc_blocks.is_empty() { 0 } else { PACK_HEADER_SIZE };
+        let mut metadata = PackHeader::new(0, first_toc).for_toc(&toc_blocks).to_bytes().to_vec();
+        for block in toc_blocks {
+            metadata.extend_from_slice(&block);
+        }
+
+        Ok(Self { metadata, data_files })
+    }
+
+    /// Read the entries described by a metadata file, sorted by data file and offset.
+    pub fn read_metadata(metadata: &mut (impl Read + Seek)) -> error::Result<Vec<SplitEntry>> {
+        let header = PackHeader::read_from(metadata)?;
+        if header.version() != PACK_VERSION {
+            return Err(PackError::Incompatible(header.version()));
+        }
+
+        let mut entries = Vec::new();
+        BackPack::read_toc_blocks(metadata, header.first_toc.get(), |filled, block| {
+            let filled = (filled as usize).min(block.len());
+            let mut curr = 0;
+            while curr < filled {
+                let (entry, len) = TocEntry::parse(&block[curr..])?;
+                curr += len;
+
+                let mut res = SplitEntry {
+                    name: entry_name::key_of_bytes(entry.name).into_owned(),
+                    data_file: 0,
+                    offset: entry.offset,
+                    length: entry.length,
+                    crc32: None,
+                    hidden: false,
+                };
+
+                let mut has_data_file = false;
+                for field in entry.fields() {
+                    match field? {
+                        (DATA_FILE_FIELD, value) => {
+                            res.data_file = U16Le::from_slice(value).ok_or(PackError::InvalidEntry)?.get();
+                            has_data_file = true;
+                        }
+                        (CHECKSUM_FIELD, value) => res.crc32 = Some(U32Le::from_slice(value).ok_or(PackError::InvalidEntry)?.get()),
+                        (FLAGS_FIELD, value) => res.hidden = U16Le::from_slice(value).ok_or(PackError::InvalidEntry)?.get() & HIDDEN != 0,
+                        (tag, _) if tag & CRITICAL_FIELD != 0 => return Err(PackError::UnsupportedIndexField(tag)),
+                        _ => {}
+                    }
+                }
+
+                // a normal backpack, not a metadata file
+                if !has_data_file {
+                    return Err(PackError::InvalidEntry);
+                }
+                entries.push(res);
+            }
+            Ok(())
+        })?;
+
+        entries.sort_by_key(|e| (e.data_file, e.offset));
+        Ok(entries)
+    }
+
+    /// The entries in `new` whose contents aren't in `old` under the same name,
+    /// which are the ranges a launcher has to download to go from `old` to `new`.
+    /// Entries without a checksum are always considered changed.
+    pub fn changed<'e>(old: &[SplitEntry], new: &'e [SplitEntry]) -> Vec<&'e SplitEntry> {
+        let old = old.iter()
+            .filter_map(|e| Some((e.name.as_str(), (e.length, e.crc32?))))
+            .collect::<HashMap<_, _>>();
+
+        new.iter()
+            .filter(|e| e.crc32.is_none_or(|crc| old.get(e.name.as_str()) != Some(&(e.length, crc))))
+            .collect()
+    }
+}
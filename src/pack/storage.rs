@@ -0,0 +1,221 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::pack::buffered::ReadWindow;
+
+/// Where the bytes of a backpack are kept, for backends which aren't a file on disk or a
+/// buffer in memory, like IndexedDB or ranges fetched over http when running in the browser.
+/// Wrap one in a [`RawFile`](crate::RawFile) with [`RawFile::from_storage`](crate::RawFile::from_storage)
+/// to use it for a backpack.
+///
+/// Storage is accessed at explicit offsets, the cursor [`RawFile`](crate::RawFile) needs is kept for it.
+pub trait Storage: Send + Sync {
+    /// Read bytes at `offset` into `buf`, returning how many were read. Returns 0 only at the end
+    /// of the storage or when `buf` is empty.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Write bytes from `buf` at `offset`, growing the storage when writing past its end,
+    /// and return how many were written.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize>;
+
+    fn len(&self) -> io::Result<u64>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Grow or shrink the storage to `size` bytes, filling it with zeroes when it grows.
+    fn set_len(&mut self, size: u64) -> io::Result<()>;
+
+    /// Make sure everything written so far is persisted.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Storage for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(self.len());
+        let len = buf.len().min(self.len() - start);
+        buf[..len].copy_from_slice(&self[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let start = usize::try_from(offset).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let end = start.checked_add(buf.len()).ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        if end > self.len() {
+            self.resize(end, 0);
+        }
+        self[start..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(Vec::len(self) as u64)
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        let size = usize::try_from(size).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        self.resize(size, 0);
+        Ok(())
+    }
+}
+
+impl Storage for std::fs::File {
+    #[cfg(unix)]
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+
+    #[cfg(unix)]
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write(buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        std::fs::File::set_len(self, size)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// A [`Storage`] with the cursor and name a [`RawFile`](crate::RawFile) has.
+pub struct StorageFile<'f> {
+    pub(crate) storage: Box<dyn Storage + 'f>,
+    pub(crate) name: Option<PathBuf>,
+    pub(crate) position: u64,
+    /// read ahead for [`BufRead`], cleared when the storage is written
+    pub(crate) window: ReadWindow,
+}
+
+impl<'f> StorageFile<'f> {
+    pub(crate) fn new(storage: Box<dyn Storage + 'f>) -> Self {
+        Self {
+            storage,
+            name: None,
+            position: 0,
+            window: ReadWindow::default(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> Option<&Path> {
+        self.name.as_deref()
+    }
+
+    pub(crate) fn read_exact_at(&self, mut offset: u64, mut buf: &mut [u8]) -> error::Result<()> {
+        while !buf.is_empty() {
+            match self.storage.read_at(offset, buf) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything in the storage.
+    pub(crate) fn read_all(&self) -> error::Result<Vec<u8>> {
+        let len = usize::try_from(self.storage.len()?).map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let mut res = vec![0; len];
+        self.read_exact_at(0, &mut res)?;
+        Ok(res)
+    }
+}
+
+impl Read for StorageFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.storage.read_at(self.position, buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for StorageFile<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let storage = &self.storage;
+        self.window.fill(self.position, |position, buf| storage.read_at(position, buf))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl Write for StorageFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.window.clear();
+        let n = self.storage.write_at(self.position, buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for StorageFile<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.storage.len()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::storage::Storage;
+
+    #[test]
+    fn test_vec_storage() {
+        let mut storage = Vec::new();
+        assert_eq!(storage.write_at(2, b"ab").unwrap(), 2);
+        assert_eq!(storage, b"\0\0ab");
+
+        let mut buf = [0; 4];
+        assert_eq!(storage.read_at(3, &mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"b");
+        assert_eq!(storage.read_at(10, &mut buf).unwrap(), 0);
+
+        Storage::set_len(&mut storage, 1).unwrap();
+        assert_eq!(Storage::len(&storage).unwrap(), 1);
+    }
+}
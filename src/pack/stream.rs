@@ -0,0 +1,192 @@
+use std::io::{self, Read, Take};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::directory;
+use crate::pack::{PACK_HEADER_SIZE, PACK_VERSION, TOC_SIZE};
+use crate::pack::METADATA_ENTRIES;
+use crate::pack::layout::{PackHeader, TocBlockHeader, COMPRESSION_FIELD, ENCRYPTION_FIELD};
+use crate::pack::Index;
+use crate::BackPack;
+
+/// Reads a backpack front to back from a source that can't seek, like stdin or a pipe.
+/// Only the table of contents is kept in memory, entries are read straight from the source
+/// in the order they are stored in.
+pub struct StreamReader<R> {
+    inner: R,
+    position: u64,
+
+    /// name, absolute offset and length, sorted by offset
+    entries: Vec<(String, u64, u64)>,
+    next: usize,
+}
+
+/// An entry of a [`StreamReader`]. Reads the entry contents directly from the stream.
+pub struct StreamEntry<'s, R> {
+    name: &'s str,
+    data: Take<&'s mut R>,
+    position: &'s mut u64,
+}
+
+impl<R: Read> StreamReader<R> {
+    /// Read a backpack from `inner`, skipping [hidden](crate::BackPack::set_hidden) entries.
+    pub fn new(inner: R) -> error::Result<Self> {
+        Self::new_with(inner, false)
+    }
+
+    /// Read a backpack from `inner`, including hidden entries if `include_hidden` is set.
+    pub fn new_with(mut inner: R, include_hidden: bool) -> error::Result<Self> {
+        let header = PackHeader::read_from(&mut inner)?;
+        let version = header.version();
+        if version != PACK_VERSION {
+            return Err(PackError::Incompatible(version));
+        }
+
+        let mut next_toc_offset = header.first_toc.get();
+
+        let mut res = Self {
+            inner,
+            position: PACK_HEADER_SIZE,
+            entries: Vec::new(),
+            next: 0,
+        };
+
+        let mut index = Index::default();
+        let mut toc_blocks = Vec::new();
+
+        while next_toc_offset != 0 {
+            res.skip_to(next_toc_offset)?;
+            toc_blocks.push(next_toc_offset);
+
+            let mut block = vec![0u8; TOC_SIZE as usize];
+            res.inner.read_exact(&mut block)?;
+            res.position += TOC_SIZE as u64;
+
+            let (header, entries) = block.split_at(TocBlockHeader::SIZE);
+            let header = TocBlockHeader::from_bytes(header.try_into().unwrap());
+            next_toc_offset = header.next.get();
+
+            BackPack::parse_toc_block(header.entries_len()?, entries, &mut index)?;
+        }
+        // compressed entries can't be handed out as a slice of the stream
+        if !index.compressed.is_empty() {
+            return Err(PackError::UnsupportedIndexField(COMPRESSION_FIELD));
+        }
+        if !index.encrypted.is_empty() {
+            return Err(PackError::UnsupportedIndexField(ENCRYPTION_FIELD));
+        }
+
+        let Index { mut offsets, hidden, .. } = index;
+        // metadata of the backpack, not files
+        for name in METADATA_ENTRIES {
+            offsets.remove(*name);
+        }
+        res.entries = offsets.into_iter()
+            .filter(|(name, _)| include_hidden || !hidden.contains(name))
+            .map(|(name, (offset, length))| (name, BackPack::convert_offset(&toc_blocks, offset), length))
+            .collect();
+        res.entries.sort_by_key(|(_, offset, _)| *offset);
+
+        Ok(res)
+    }
+
+    /// Names and lengths of all entries in the order they will be returned by [`next_entry`](Self::next_entry).
+    /// Available without reading any entry data.
+    pub fn entries(&self) -> impl Iterator<Item=(&str, u64)> {
+        self.entries.iter().map(|(name, _, length)| (name.as_str(), *length))
+    }
+
+    /// Advance to the next entry in the stream. Any unread data of the previous entry is skipped.
+    pub fn next_entry(&mut self) -> error::Result<Option<StreamEntry<'_, R>>> {
+        let Some((_, offset, length)) = self.entries.get(self.next) else {
+            return Ok(None);
+        };
+        let (offset, length) = (*offset, *length);
+        self.skip_to(offset)?;
+
+        let (name, _, _) = &self.entries[self.next];
+        self.next += 1;
+
+        Ok(Some(StreamEntry {
+            name,
+            data: (&mut self.inner).take(length),
+            position: &mut self.position,
+        }))
+    }
+
+    /// Write the entries which weren't read yet to `dir` under their name, creating directories
+    /// as needed, like [`BackPack::extract_to`] does. Fails with [`PackError::UnsafePath`] before
+    /// writing anything if a name would end up outside of `dir`. Entries sharing their contents
+    /// are read from the stream once.
+    pub fn extract_to(&mut self, dir: impl AsRef<Path>) -> error::Result<()> {
+        let dir = dir.as_ref();
+        let entries = self.entries[self.next..].iter()
+            .map(|(name, offset, length)| Ok((directory::extract_path(dir, name)?, name.ends_with('/'), *offset, *length)))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let mut last: Option<(u64, u64, PathBuf)> = None;
+        for (path, is_dir, offset, length) in entries {
+            self.next += 1;
+            if is_dir {
+                std::fs::create_dir_all(&path).at_path(&path)?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).at_path(parent)?;
+            }
+
+            match &last {
+                // empty files aren't anywhere in the stream
+                _ if length == 0 => std::fs::write(&path, []).at_path(&path)?,
+                Some((last_offset, last_length, first)) if (*last_offset, *last_length) == (offset, length) => {
+                    std::fs::copy(first, &path).at_path(&path)?;
+                }
+                _ => {
+                    self.skip_to(offset)?;
+                    let mut file = std::fs::File::create(&path).at_path(&path)?;
+                    let copied = io::copy(&mut (&mut self.inner).take(length), &mut file)?;
+                    self.position += copied;
+                    if copied != length {
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    last = Some((offset, length, path));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_to(&mut self, offset: u64) -> error::Result<()> {
+        if offset < self.position {
+            return Err(PackError::NotSequential);
+        }
+
+        let to_skip = offset - self.position;
+        let skipped = io::copy(&mut (&mut self.inner).take(to_skip), &mut io::sink())?;
+        self.position += skipped;
+        if skipped != to_skip {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<R> StreamEntry<'_, R> {
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Number of bytes of this entry that haven't been read yet
+    pub fn remaining(&self) -> u64 {
+        self.data.limit()
+    }
+}
+
+impl<R: Read> Read for StreamEntry<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let res = self.data.read(buf)?;
+        *self.position += res as u64;
+        Ok(res)
+    }
+}
@@ -0,0 +1,294 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::normalize_name;
+
+const BLOCK_SIZE: usize = 512;
+
+const REGULAR: u8 = b'0';
+/// regular file, as written by very old tars
+const REGULAR_OLD: u8 = 0;
+const DIRECTORY: u8 = b'5';
+/// pax extended header for the next member
+const PAX_HEADER: u8 = b'x';
+/// pax extended header for all following members
+const PAX_GLOBAL_HEADER: u8 = b'g';
+/// gnu extension, the next member's name is the contents of this one
+const GNU_LONG_NAME: u8 = b'L';
+
+/// A file or directory in a tar archive. Directories are named with a trailing `/`.
+pub(crate) struct Member {
+    pub name: String,
+    /// seconds since the unix epoch
+    pub modified: u64,
+    pub contents: Vec<u8>,
+}
+
+fn read_block(r: &mut impl Read, block: &mut [u8; BLOCK_SIZE]) -> error::Result<bool> {
+    let mut filled = 0;
+    while filled < BLOCK_SIZE {
+        match r.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Read a member's contents of `size` bytes, and the padding after them.
+fn read_contents(r: &mut impl Read, size: u64) -> error::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    r.take(size).read_to_end(&mut contents)?;
+    if contents.len() as u64 != size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let padding = padding(size);
+    io::copy(&mut r.take(padding), &mut io::sink())?;
+    Ok(contents)
+}
+
+fn padding(size: u64) -> u64 {
+    (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64
+}
+
+/// A string field, which ends at the first NUL or at the end of the field.
+fn field_str(field: &[u8]) -> String {
+    let end = field.iter().position(|c| *c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// A number field, in octal, or in base-256 when its first byte has the high bit set.
+fn field_number(field: &[u8]) -> error::Result<u64> {
+    if field.first().is_some_and(|c| c & 0x80 != 0) {
+        return field[1..].iter().try_fold(u64::from(field[0] & 0x7f), |n, c| {
+            n.checked_mul(256).map(|n| n + u64::from(*c)).ok_or(PackError::InvalidEntry)
+        });
+    }
+
+    let digits = field_str(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| PackError::InvalidEntry)
+}
+
+/// The records of a pax extended header, as `{length} {key}={value}\n`.
+fn pax_records(data: &[u8]) -> error::Result<Vec<(String, String)>> {
+    let mut res = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|c| *c == b' ').ok_or(PackError::InvalidEntry)?;
+        let len = std::str::from_utf8(&rest[..space]).ok()
+            .and_then(|len| len.parse::<usize>().ok())
+            .filter(|len| *len > space && *len <= rest.len())
+            .ok_or(PackError::InvalidEntry)?;
+        let record = String::from_utf8_lossy(&rest[space + 1..len - 1]).into_owned();
+        let (key, value) = record.split_once('=').ok_or(PackError::InvalidEntry)?;
+        res.push((key.to_string(), value.to_string()));
+        rest = &rest[len..];
+    }
+    Ok(res)
+}
+
+/// Read the regular files and directories in the tar archive `r`, in the order they're in.
+/// Names are normalized like the names of files in a backpack. Links and other special
+/// files are skipped, since a backpack can't hold them.
+pub(crate) fn read_tar(mut r: impl Read) -> error::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut block = [0; BLOCK_SIZE];
+    // set by extended headers, for the member after them
+    let mut long_name = None;
+    let mut pax_modified = None;
+
+    while read_block(&mut r, &mut block)? {
+        // the archive ends with two empty blocks, one is enough to stop
+        if block.iter().all(|c| *c == 0) {
+            break;
+        }
+
+        let checksum = field_number(&block[148..156])?;
+        let sum = block.iter().enumerate()
+            .map(|(i, c)| if (148..156).contains(&i) { u64::from(b' ') } else { u64::from(*c) })
+            .sum::<u64>();
+        if checksum != sum {
+            return Err(PackError::InvalidEntry);
+        }
+
+        let size = field_number(&block[124..136])?;
+        let typeflag = block[156];
+        match typeflag {
+            PAX_HEADER => {
+                for (key, value) in pax_records(&read_contents(&mut r, size)?)? {
+                    match key.as_str() {
+                        "path" => long_name = Some(value),
+                        // may have a fraction of a second, which is dropped
+                        "mtime" => pax_modified = value.split('.').next().and_then(|secs| secs.parse().ok()),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            GNU_LONG_NAME => {
+                long_name = Some(field_str(&read_contents(&mut r, size)?));
+                continue;
+            }
+            _ => {}
+        }
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let name = field_str(&block[0..100]);
+            // ustar splits long names over a prefix and the name
+            match &block[257..263] {
+                b"ustar\0" => match field_str(&block[345..500]) {
+                    prefix if prefix.is_empty() => name,
+                    prefix => format!("{}/{}", prefix, name),
+                },
+                _ => name,
+            }
+        });
+        let modified = pax_modified.take().unwrap_or(field_number(&block[136..148])?);
+
+        let contents = read_contents(&mut r, size)?;
+        let name = normalize_name(Path::new(&name));
+        let name = name.trim_start_matches('/');
+        match typeflag {
+            REGULAR | REGULAR_OLD if !name.is_empty() && !name.ends_with('/') => members.push(Member {
+                name: name.to_string(),
+                modified,
+                contents,
+            }),
+            DIRECTORY if !name.trim_end_matches('/').is_empty() => members.push(Member {
+                name: format!("{}/", name.trim_end_matches('/')),
+                modified,
+                contents: Vec::new(),
+            }),
+            // the root directory, `./`
+            DIRECTORY | PAX_GLOBAL_HEADER => {}
+            _ => log::warn!("skipping {:?} in tar archive, which isn't a regular file or directory", name),
+        }
+    }
+
+    Ok(members)
+}
+
+/// Write `value` to a number field, in octal ending in a NUL when it fits and in base-256 otherwise.
+fn write_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        field[..digits].copy_from_slice(format!("{:0width$o}", value, width = digits).as_bytes());
+        field[digits] = 0;
+    } else {
+        field.fill(0);
+        for (i, c) in value.to_be_bytes().iter().rev().enumerate() {
+            let at = field.len() - 1 - i;
+            field[at] = *c;
+        }
+        field[0] |= 0x80;
+    }
+}
+
+fn header(name: &[u8], size: u64, modified: u64, typeflag: u8) -> [u8; BLOCK_SIZE] {
+    let mut block = [0; BLOCK_SIZE];
+    block[..name.len()].copy_from_slice(name);
+    let mode = if typeflag == DIRECTORY { 0o755 } else { 0o644 };
+    write_number(&mut block[100..108], mode);
+    // owner and group
+    write_number(&mut block[108..116], 0);
+    write_number(&mut block[116..124], 0);
+    write_number(&mut block[124..136], size);
+    write_number(&mut block[136..148], modified);
+    block[156] = typeflag;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+
+    // the checksum is computed with its own field filled with spaces
+    block[148..156].fill(b' ');
+    let sum = block.iter().map(|c| u64::from(*c)).sum::<u64>();
+    block[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+    block
+}
+
+/// A pax record, which counts its own length.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {}={}\n", key, value);
+    let mut len = rest.len() + 1;
+    while (len.to_string().len() + rest.len()) != len {
+        len = len.to_string().len() + rest.len();
+    }
+    format!("{}{}", len, rest)
+}
+
+fn write_member(w: &mut impl Write, member: &Member) -> error::Result<()> {
+    let is_dir = member.name.ends_with('/');
+    let typeflag = if is_dir { DIRECTORY } else { REGULAR };
+
+    // names which don't fit in the header get a pax header with the full name before them
+    let name = if member.name.len() > 100 {
+        let record = pax_record("path", &member.name);
+        w.write_all(&header(b"././@PaxHeader", record.len() as u64, member.modified, PAX_HEADER))?;
+        w.write_all(record.as_bytes())?;
+        w.write_all(&vec![0; padding(record.len() as u64) as usize])?;
+
+        let mut end = 100;
+        while !member.name.is_char_boundary(end) {
+            end -= 1;
+        }
+        &member.name.as_bytes()[..end]
+    } else {
+        member.name.as_bytes()
+    };
+
+    w.write_all(&header(name, member.contents.len() as u64, member.modified, typeflag))?;
+    w.write_all(&member.contents)?;
+    w.write_all(&vec![0; padding(member.contents.len() as u64) as usize])?;
+    Ok(())
+}
+
+/// Write `members` to `w` as a tar archive, in the pax format.
+pub(crate) fn write_tar(mut w: impl Write, members: &[Member]) -> error::Result<()> {
+    for member in members {
+        write_member(&mut w, member)?;
+    }
+    // the end of the archive
+    w.write_all(&[0; 2 * BLOCK_SIZE])?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pack::tar::{pax_record, read_tar, write_tar, Member};
+
+    #[test]
+    fn test_pax_record() {
+        assert_eq!(pax_record("path", "a"), "9 path=a\n");
+        // the length going from one to two digits counts itself
+        assert_eq!(pax_record("path", "abcd"), "13 path=abcd\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long = format!("{}/file", "directory".repeat(20));
+        let members = vec![
+            Member { name: "dir/".to_string(), modified: 1, contents: Vec::new() },
+            Member { name: "dir/a.txt".to_string(), modified: 2, contents: b"a".to_vec() },
+            Member { name: long.clone(), modified: 3, contents: vec![1; 1000] },
+        ];
+        let mut tar = Vec::new();
+        write_tar(&mut tar, &members).unwrap();
+        assert_eq!(tar.len() % 512, 0);
+
+        let read = read_tar(tar.as_slice()).unwrap();
+        assert_eq!(read.len(), 3);
+        for (read, written) in read.iter().zip(&members) {
+            assert_eq!(read.name, written.name);
+            assert_eq!(read.modified, written.modified);
+            assert_eq!(read.contents, written.contents);
+        }
+    }
+}
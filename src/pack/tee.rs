@@ -0,0 +1,57 @@
+use std::io;
+use crate::pack::{RawFile, Storage};
+
+/// Writes the same bytes to several files at once, like writing a backpack to disk while it's
+/// uploaded or keeping a mirror copy, in one pass. Reads and the length come from the first
+/// file, the primary, so it should be one which can be read back. Use it as the file of a
+/// backpack with [`RawFile::tee`] or [`RawFile::from_storage`].
+///
+/// Every write goes to every file at the same offset, and only succeeds when it succeeded for
+/// all of them. After a failed write the files may differ.
+pub struct TeeFile<'f, 'backpack> {
+    files: Vec<RawFile<'f, 'backpack>>,
+}
+
+impl<'f, 'backpack> TeeFile<'f, 'backpack> {
+    pub fn new(primary: RawFile<'f, 'backpack>, mirrors: impl IntoIterator<Item=RawFile<'f, 'backpack>>) -> Self {
+        Self {
+            files: std::iter::once(primary).chain(mirrors).collect(),
+        }
+    }
+
+    /// The primary file followed by the mirrors.
+    pub fn into_inner(self) -> Vec<RawFile<'f, 'backpack>> {
+        self.files
+    }
+}
+
+impl Storage for TeeFile<'_, '_> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.files[0].read_at(offset, buf)?)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        for file in &mut self.files {
+            file.write_all_at(offset, buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.files[0].metadata()?.len)
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        for file in &mut self.files {
+            file.set_len(size)?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        for file in &self.files {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
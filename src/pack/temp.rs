@@ -0,0 +1,70 @@
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::pack::backpack::Collision;
+use crate::pack::{BackPack, InMemoryFile};
+
+/// A file staged under a temporary name, see [`BackPack::temp_entry`].
+/// Written contents are buffered, and [`flush`](Write::flush) stores them under the temporary name.
+/// Dropping it without calling [`persist`](Self::persist) removes the temporary file again.
+pub struct TempEntry<'f, 'backpack> {
+    pack: &'f BackPack<'f, 'backpack>,
+    name: PathBuf,
+    data: Cursor<Vec<u8>>,
+    persisted: bool,
+}
+
+impl<'f, 'backpack> TempEntry<'f, 'backpack> {
+    pub(crate) fn new(pack: &'f BackPack<'f, 'backpack>, name: PathBuf) -> Self {
+        Self {
+            pack,
+            name,
+            data: Cursor::new(Vec::new()),
+            persisted: false,
+        }
+    }
+
+    /// The temporary name the file is stored under until it's persisted.
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Keep the file, under `name`, with what was written to it. The temporary name is removed.
+    /// The backpack's [collision policy](BackPack::set_collision_policy) decides what happens
+    /// when `name` is taken, on failure the file stays under its temporary name until dropped.
+    pub fn persist(mut self, name: impl AsRef<Path>) -> error::Result<InMemoryFile<'f, 'backpack>> {
+        let contents = std::mem::take(self.data.get_mut());
+        let f = self.pack.add_file(InMemoryFile::from(contents).with_name(name))?;
+
+        self.persisted = true;
+        self.pack.tombstone(&self.name);
+        Ok(f)
+    }
+}
+
+impl Write for TempEntry<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    /// Store what was written so far under the temporary name.
+    fn flush(&mut self) -> io::Result<()> {
+        let contents = InMemoryFile::from(self.data.get_ref().clone()).with_name(&self.name);
+        self.pack.add_file_with(contents, Collision::Overwrite)?;
+        Ok(())
+    }
+}
+
+impl Seek for TempEntry<'_, '_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl Drop for TempEntry<'_, '_> {
+    fn drop(&mut self) {
+        if !self.persisted {
+            self.pack.tombstone(&self.name);
+        }
+    }
+}
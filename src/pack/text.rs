@@ -0,0 +1,93 @@
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::PackError;
+
+/// The lines of a text file in a backpack, without their line endings, see [`BackPack::lines`](crate::BackPack::lines)
+/// and [`PackReader::lines`](crate::pack::PackReader::lines). Unlike [`BufRead::lines`], errors say
+/// which file and line they're about. Iteration stops after an error.
+pub struct Lines<R> {
+    inner: R,
+    name: PathBuf,
+    /// lines returned so far
+    line: u64,
+    /// where the next line starts
+    offset: u64,
+    failed: bool,
+}
+
+impl<R: BufRead> Lines<R> {
+    pub(crate) fn new(inner: R, name: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            name: name.into(),
+            line: 0,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// The number of the line returned last, counting from 1.
+    pub fn line_number(&self) -> u64 {
+        self.line
+    }
+
+    /// Where in the file the next line starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = error::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let mut line = String::new();
+        match self.inner.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(n) => {
+                self.line += 1;
+                self.offset += n as u64;
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(source) => {
+                self.failed = true;
+                Some(Err(PackError::Line {
+                    name: self.name.clone(),
+                    line: self.line + 1,
+                    source,
+                }))
+            }
+        }
+    }
+}
+
+/// The contents of the file `name` as text, or an error with the line which isn't valid UTF-8.
+pub(crate) fn decode(name: &Path, contents: Vec<u8>) -> error::Result<String> {
+    String::from_utf8(contents).map_err(|e| {
+        let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        PackError::Line {
+            name: name.to_path_buf(),
+            line: valid.iter().filter(|b| **b == b'\n').count() as u64 + 1,
+            source: io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    })
+}
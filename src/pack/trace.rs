@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::str::Chars;
+use std::iter::Peekable;
+use crate::error;
+use crate::error::PackError;
+
+/// The order in which files of a backpack were first accessed.
+/// Record one with [`BackPack::start_recording`](crate::BackPack::start_recording) and pass it
+/// to [`BackPack::set_order`](crate::BackPack::set_order) to lay a pack out in that order,
+/// so loading the same files again becomes one sequential read.
+///
+/// Traces are stored as a json array of names.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessTrace {
+    order: Vec<String>,
+    seen: HashSet<String>,
+}
+
+impl AccessTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an access. Only the first access of every name is kept.
+    pub fn record(&mut self, name: &str) {
+        if !self.seen.contains(name) {
+            self.seen.insert(name.to_string());
+            self.order.push(name.to_string());
+        }
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.order
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut res = String::from("[");
+        for (i, name) in self.order.iter().enumerate() {
+            if i != 0 {
+                res.push(',');
+            }
+            res.push_str("\n  \"");
+            for c in name.chars() {
+                match c {
+                    '"' => res.push_str("\\\""),
+                    '\\' => res.push_str("\\\\"),
+                    '\n' => res.push_str("\\n"),
+                    '\r' => res.push_str("\\r"),
+                    '\t' => res.push_str("\\t"),
+                    c if (c as u32) < 0x20 => {
+                        let _ = write!(res, "\\u{:04x}", c as u32);
+                    }
+                    c => res.push(c),
+                }
+            }
+            res.push('"');
+        }
+        res.push_str("\n]\n");
+        res
+    }
+
+    pub fn from_json(s: &str) -> error::Result<Self> {
+        let mut chars = s.chars().peekable();
+        let mut res = Self::new();
+
+        skip_whitespace(&mut chars);
+        expect(&mut chars, '[')?;
+        skip_whitespace(&mut chars);
+
+        if chars.peek() == Some(&']') {
+            chars.next();
+        } else {
+            loop {
+                skip_whitespace(&mut chars);
+                let name = parse_string(&mut chars)?;
+                res.record(&name);
+                skip_whitespace(&mut chars);
+
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    _ => return Err(PackError::InvalidTrace("expected ',' or ']'")),
+                }
+            }
+        }
+
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return Err(PackError::InvalidTrace("trailing characters after trace"));
+        }
+
+        Ok(res)
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> error::Result<()> {
+        w.write_all(self.to_json().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn read_from(mut r: impl Read) -> error::Result<Self> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Self::from_json(&s)
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for AccessTrace {
+    fn from_iter<T: IntoIterator<Item=S>>(iter: T) -> Self {
+        let mut res = Self::new();
+        for name in iter {
+            res.record(&name.into());
+        }
+        res
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> error::Result<()> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(PackError::InvalidTrace("unexpected character")),
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> error::Result<u32> {
+    let mut res = 0;
+    for _ in 0..4 {
+        let digit = chars.next()
+            .and_then(|c| c.to_digit(16))
+            .ok_or(PackError::InvalidTrace("invalid unicode escape"))?;
+        res = res * 16 + digit;
+    }
+    Ok(res)
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> error::Result<String> {
+    expect(chars, '"')?;
+
+    let mut res = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(PackError::InvalidTrace("unterminated string")),
+            Some('"') => return Ok(res),
+            Some('\\') => {
+                let c = match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => {
+                        let mut code = parse_hex4(chars)?;
+                        // characters outside the basic plane are written as a surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            expect(chars, '\\')?;
+                            expect(chars, 'u')?;
+                            let low = parse_hex4(chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(PackError::InvalidTrace("invalid surrogate pair"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        char::from_u32(code).ok_or(PackError::InvalidTrace("invalid unicode escape"))?
+                    }
+                    _ => return Err(PackError::InvalidTrace("invalid escape")),
+                };
+                res.push(c);
+            }
+            Some(c) => res.push(c),
+        }
+    }
+}
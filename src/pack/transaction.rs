@@ -0,0 +1,95 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::pack::BackPack;
+
+enum Change {
+    Add(PathBuf, Vec<u8>),
+    Remove(PathBuf),
+    Rename(PathBuf, PathBuf),
+}
+
+impl Change {
+    fn names(&self) -> impl Iterator<Item=&Path> {
+        let (first, second) = match self {
+            Change::Add(name, _) | Change::Remove(name) => (name, None),
+            Change::Rename(from, to) => (from, Some(to)),
+        };
+        std::iter::once(first.as_path()).chain(second.map(PathBuf::as_path))
+    }
+}
+
+/// Changes to a backpack which are made together or not at all, see [`BackPack::transaction`].
+/// Nothing changes until the transaction is [committed](Self::commit), dropping it
+/// without committing throws the staged changes away.
+pub struct Transaction<'t, 'f, 'backpack> {
+    pack: &'t mut BackPack<'f, 'backpack>,
+    changes: Vec<Change>,
+}
+
+impl<'t, 'f, 'backpack: 'f> Transaction<'t, 'f, 'backpack> {
+    pub(crate) fn new(pack: &'t mut BackPack<'f, 'backpack>) -> Self {
+        Self {
+            pack,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Stage adding the file `name`, replacing it if it exists. `contents` is read right away.
+    pub fn add(&mut self, name: impl AsRef<Path>, mut contents: impl Read) -> error::Result<()> {
+        let mut data = Vec::new();
+        contents.read_to_end(&mut data)?;
+        self.changes.push(Change::Add(name.as_ref().to_path_buf(), data));
+        Ok(())
+    }
+
+    /// Stage removing the file `name`, which has to exist by then.
+    pub fn remove(&mut self, name: impl AsRef<Path>) {
+        self.changes.push(Change::Remove(name.as_ref().to_path_buf()));
+    }
+
+    /// Stage renaming the file `from` to `to`, which can't exist by then.
+    pub fn rename(&mut self, from: impl AsRef<Path>, to: impl AsRef<Path>) {
+        self.changes.push(Change::Rename(from.as_ref().to_path_buf(), to.as_ref().to_path_buf()));
+    }
+
+    /// Number of staged changes.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Make the staged changes, in the order they were staged. When one of them fails, the
+    /// ones made before it are undone and the backpack is left as it was. Like other changes,
+    /// they're written to the backing file with the next flush.
+    pub fn commit(mut self) -> error::Result<()> {
+        let changes = std::mem::take(&mut self.changes);
+        let records = self.pack.records(changes.iter().flat_map(Change::names));
+
+        for change in changes {
+            if let Err(e) = self.apply(change) {
+                self.pack.restore_records(records);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Throw the staged changes away, the same as dropping the transaction.
+    pub fn rollback(self) {}
+
+    fn apply(&mut self, change: Change) -> error::Result<()> {
+        match change {
+            Change::Add(name, contents) => {
+                self.pack.put_contents(&name, contents)?;
+                self.pack.revive(&name);
+                Ok(())
+            }
+            Change::Remove(name) => self.pack.remove_file(name),
+            Change::Rename(from, to) => self.pack.rename(from, to),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+/// Checks files before they are added to a backpack, so broken assets are rejected
+/// when packing instead of failing when they're loaded.
+/// Returning an error makes [`add_file`](crate::BackPack::add_file) fail with
+/// [`PackError::InvalidAsset`](crate::PackError::InvalidAsset).
+pub trait Validator: Send + Sync {
+    /// Whether this validator checks the file called `name`. Checks every file by default.
+    fn applies_to(&self, _name: &Path) -> bool {
+        true
+    }
+
+    /// Why `contents` are not a valid file called `name`, if they aren't.
+    fn validate(&self, name: &Path, contents: &[u8]) -> Result<(), String>;
+
+    /// Name of the validator, reported in errors.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<F: Fn(&Path, &[u8]) -> Result<(), String> + Send + Sync> Validator for F {
+    fn validate(&self, name: &Path, contents: &[u8]) -> Result<(), String> {
+        self(name, contents)
+    }
+}
+
+/// Only validates files with a certain extension.
+pub struct ForExtension<V> {
+    extension: String,
+    validator: V,
+}
+
+impl<V: Validator> ForExtension<V> {
+    pub fn new(extension: impl Into<String>, validator: V) -> Self {
+        Self {
+            extension: extension.into(),
+            validator,
+        }
+    }
+}
+
+impl<V: Validator> Validator for ForExtension<V> {
+    fn applies_to(&self, name: &Path) -> bool {
+        name.extension().is_some_and(|e| e.eq_ignore_ascii_case(&self.extension)) && self.validator.applies_to(name)
+    }
+
+    fn validate(&self, name: &Path, contents: &[u8]) -> Result<(), String> {
+        self.validator.validate(name, contents)
+    }
+
+    fn name(&self) -> &str {
+        self.validator.name()
+    }
+}
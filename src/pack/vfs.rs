@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::{BackPack, EntryKind};
+use crate::pack::entry_name;
+
+/// What a [`Vfs`] knows about a file or directory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VfsMetadata {
+    /// length of the contents, 0 for directories
+    pub len: u64,
+    pub is_dir: bool,
+    /// `None` when it's not known
+    pub modified: Option<SystemTime>,
+}
+
+/// Read-only access to a tree of files, so code can load its files from a directory
+/// during development and from a backpack in release without caring which it is.
+/// Implemented by [`&BackPack`](BackPack) and by [`DiskVfs`] for a directory on disk.
+///
+/// Paths are relative to the root of the tree and use `/` as separator. [`read_dir`](Self::read_dir)
+/// lists names like [`BackPack::list_dir`] does: sorted, with directories named with a trailing `/`.
+pub trait Vfs {
+    fn open(&self, path: &Path) -> error::Result<Box<dyn Read + '_>>;
+
+    /// The names of the files and directories directly in the directory `path`.
+    /// `""` lists the root.
+    fn read_dir(&self, path: &Path) -> error::Result<Vec<String>>;
+
+    fn metadata(&self, path: &Path) -> error::Result<VfsMetadata>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// The whole contents of the file at `path`.
+    fn read(&self, path: &Path) -> error::Result<Vec<u8>> {
+        let mut res = Vec::new();
+        self.open(path)?.read_to_end(&mut res)?;
+        Ok(res)
+    }
+}
+
+impl<'f, 'backpack: 'f> Vfs for &'f BackPack<'f, 'backpack> {
+    fn open(&self, path: &Path) -> error::Result<Box<dyn Read + '_>> {
+        self.entry(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> error::Result<Vec<String>> {
+        self.list_dir(path)
+    }
+
+    fn metadata(&self, path: &Path) -> error::Result<VfsMetadata> {
+        match self.entry_kind(path) {
+            Some(EntryKind::Directory) => {}
+            Some(_) => {
+                return Ok(VfsMetadata {
+                    len: self.get_file(path)?.get_bytes().len() as u64,
+                    is_dir: false,
+                    modified: self.modified(path),
+                });
+            }
+            // directories holding files don't have to be added to exist
+            None => {
+                self.list_dir(path)?;
+            }
+        }
+        Ok(VfsMetadata {
+            len: 0,
+            is_dir: true,
+            modified: self.modified(path),
+        })
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.entry_kind(path).is_some() || self.list_dir(path).is_ok()
+    }
+}
+
+/// A [`Vfs`] over a directory on disk. Paths can't leave the directory, they fail
+/// with [`PackError::UnsafePath`].
+#[derive(Clone, Debug)]
+pub struct DiskVfs {
+    root: PathBuf,
+}
+
+impl DiskVfs {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where `path` is on disk.
+    fn resolve(&self, path: &Path) -> error::Result<PathBuf> {
+        let mut res = self.root.clone();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => res.push(part),
+                Component::CurDir | Component::RootDir => {}
+                Component::ParentDir | Component::Prefix(_) => return Err(PackError::UnsafePath(path.to_path_buf())),
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl Vfs for DiskVfs {
+    fn open(&self, path: &Path) -> error::Result<Box<dyn Read + '_>> {
+        let full = self.resolve(path)?;
+        match File::open(&full) {
+            Ok(file) if !file.metadata().at_path(&full)?.is_dir() => Ok(Box::new(file)),
+            Ok(_) => Err(PackError::FileNotFound(path.to_path_buf())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(PackError::FileNotFound(path.to_path_buf())),
+            Err(e) => Err(e).at_path(full),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> error::Result<Vec<String>> {
+        let full = self.resolve(path)?;
+        let mut res = Vec::new();
+        for entry in std::fs::read_dir(&full).at_path(&full)? {
+            let entry = entry.at_path(&full)?;
+            let mut name = entry_name::key_of(&entry.file_name()).into_owned();
+            if entry.file_type().at_path(entry.path())?.is_dir() {
+                name.push('/');
+            }
+            res.push(name);
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    fn metadata(&self, path: &Path) -> error::Result<VfsMetadata> {
+        let full = self.resolve(path)?;
+        let metadata = match std::fs::metadata(&full) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(PackError::FileNotFound(path.to_path_buf())),
+            Err(e) => return Err(e).at_path(full),
+        };
+        Ok(VfsMetadata {
+            len: if metadata.is_dir() { 0 } else { metadata.len() },
+            is_dir: metadata.is_dir(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
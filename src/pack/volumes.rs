@@ -0,0 +1,153 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::Storage;
+
+/// A backpack split over several files of at most a fixed size, `pack.bp.001`, `pack.bp.002`
+/// and so on, for distribution channels which limit the size of files. The volumes are read and
+/// written as one file: use them as the file of a backpack with [`RawFile::create_volumes`](crate::RawFile::create_volumes)
+/// and [`RawFile::open_volumes`](crate::RawFile::open_volumes).
+///
+/// Every volume but the last is exactly the volume size, new volumes are created as the
+/// backpack grows and removed when it shrinks.
+pub struct Volumes {
+    base: PathBuf,
+    volume_size: u64,
+    files: Vec<File>,
+}
+
+/// The path of volume `index` of the backpack at `base`, counting from 0.
+pub fn volume_path(base: impl AsRef<Path>, index: usize) -> PathBuf {
+    let mut path = OsString::from(base.as_ref());
+    path.push(format!(".{:03}", index + 1));
+    path.into()
+}
+
+impl Volumes {
+    /// Start a new set of volumes of at most `volume_size` bytes at `base`. Volumes of a set
+    /// which was there before are removed.
+    pub fn create(base: impl AsRef<Path>, volume_size: u64) -> error::Result<Self> {
+        if volume_size == 0 {
+            return Err(PackError::IncompatibleOptions("volumes must be able to hold at least one byte"));
+        }
+        let mut volumes = Self {
+            base: base.as_ref().to_path_buf(),
+            volume_size,
+            files: Vec::new(),
+        };
+        volumes.add_volume()?;
+        volumes.remove_from(1)?;
+        Ok(volumes)
+    }
+
+    /// Open the volumes at `base`, from `base.001` to the last one which exists. The volume size
+    /// is the size of the first volume, the backpack can be changed when they're opened with
+    /// `writable`.
+    pub fn open(base: impl AsRef<Path>, writable: bool) -> error::Result<Self> {
+        let base = base.as_ref().to_path_buf();
+        let mut files = Vec::new();
+        loop {
+            let path = volume_path(&base, files.len());
+            match File::options().read(true).write(writable).open(&path) {
+                Ok(file) => files.push(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !files.is_empty() => break,
+                Err(e) => return Err(e).at_path(path),
+            }
+        }
+
+        let lens = files.iter().map(|file| Ok(file.metadata()?.len())).collect::<io::Result<Vec<_>>>()?;
+        let volume_size = match lens[..] {
+            [_] => u64::MAX,
+            _ => lens[0],
+        };
+        if volume_size == 0 || lens[..lens.len() - 1].iter().any(|len| *len != volume_size) || lens[lens.len() - 1] > volume_size {
+            return Err(PackError::Malformed(format!("the volumes of {:?} don't have the same size", base)));
+        }
+        Ok(Self { base, volume_size, files })
+    }
+
+    pub fn volume_size(&self) -> u64 {
+        self.volume_size
+    }
+
+    /// The paths of the volumes, in order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        (0..self.files.len()).map(|index| volume_path(&self.base, index)).collect()
+    }
+
+    fn add_volume(&mut self) -> error::Result<()> {
+        let path = volume_path(&self.base, self.files.len());
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(&path).at_path(path)?;
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Remove the volumes from `index` on, also those which aren't open.
+    fn remove_from(&mut self, index: usize) -> error::Result<()> {
+        self.files.truncate(index);
+        for index in index.. {
+            let path = volume_path(&self.base, index);
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e).at_path(path),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Volumes {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let index = (offset / self.volume_size) as usize;
+        let Some(file) = self.files.get(index) else {
+            return Ok(0);
+        };
+        let within = offset % self.volume_size;
+        let n = (buf.len() as u64).min(self.volume_size - within) as usize;
+        file.read_at(within, &mut buf[..n])
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let index = (offset / self.volume_size) as usize;
+        // volumes before the one written to are full
+        while self.files.len() <= index {
+            let last = self.files.len() - 1;
+            self.files[last].set_len(self.volume_size)?;
+            self.add_volume()?;
+        }
+        let within = offset % self.volume_size;
+        let n = (buf.len() as u64).min(self.volume_size - within) as usize;
+        self.files[index].write_at(within, &buf[..n])
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        let full = (self.files.len() - 1) as u64 * self.volume_size;
+        Ok(full + self.files[self.files.len() - 1].metadata()?.len())
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        let volumes = size.div_ceil(self.volume_size).max(1) as usize;
+        if volumes < self.files.len() {
+            self.remove_from(volumes)?;
+        }
+        while self.files.len() < volumes {
+            self.add_volume()?;
+        }
+        let last = volumes - 1;
+        for file in &self.files[..last] {
+            file.set_len(self.volume_size)?;
+        }
+        self.files[last].set_len(size - last as u64 * self.volume_size)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        for file in &self.files {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
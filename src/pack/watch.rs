@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::directory::collect_files;
+use crate::pack::{BackPack, ChangeEvent, DirectoryOptions, Overlay};
+
+/// What a file on disk looked like when it was last seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Watches the directory a backpack was [built from](BackPack::from_directory), to hot-reload
+/// files while developing instead of rebuilding the whole backpack. The directory is scanned
+/// when it's [polled](Self::poll), comparing the length and modification time of every file
+/// to the last scan. Only files are watched, empty directories aren't.
+///
+/// [Waiting](Self::wait) for changes scans again when the operating system reports a change in
+/// the directory. Only where it can't, the directory is polled instead.
+///
+/// [`refresh`](Self::refresh) brings a backpack up to date with the directory, which also
+/// reaches anyone listening to its [changes](BackPack::changes).
+pub struct DirectoryWatcher {
+    dir: PathBuf,
+    options: DirectoryOptions,
+    seen: HashMap<String, Stamp>,
+    /// `None` when the directory is polled
+    notifications: Option<Notifications>,
+}
+
+/// Reports from the operating system that something in a directory changed.
+struct Notifications {
+    /// watches for as long as it's kept
+    _watcher: RecommendedWatcher,
+    changed: Receiver<()>,
+}
+
+impl Notifications {
+    fn new(dir: &Path) -> Option<Self> {
+        let (sender, changed) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            // scanning the directory accesses it, which mustn't count as a change.
+            // Errors might mean changes were missed, so those do
+            if !event.is_ok_and(|event| matches!(event.kind, EventKind::Access(_))) {
+                let _ = sender.send(());
+            }
+        }).ok()?;
+        watcher.watch(dir, RecursiveMode::Recursive).ok()?;
+        Some(Self { _watcher: watcher, changed })
+    }
+}
+
+impl DirectoryWatcher {
+    /// Watch `dir` for changes to the files `options` selects, from now on.
+    pub fn new(dir: impl AsRef<Path>, options: &DirectoryOptions) -> error::Result<Self> {
+        let mut res = Self {
+            dir: dir.as_ref().to_path_buf(),
+            options: options.clone(),
+            seen: HashMap::new(),
+            // before scanning, so nothing changes unnoticed in between
+            notifications: Notifications::new(dir.as_ref()),
+        };
+        res.seen = res.scan()?;
+        Ok(res)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn scan(&self) -> error::Result<HashMap<String, Stamp>> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, "", &self.options, &mut files)?;
+        Ok(files.into_iter()
+            .filter(|(name, _)| !name.ends_with('/'))
+            // files removed while scanning are gone
+            .filter_map(|(name, path)| {
+                let metadata = std::fs::metadata(&path).ok()?;
+                Some((name, Stamp { path, len: metadata.len(), modified: metadata.modified().ok() }))
+            })
+            .collect())
+    }
+
+    /// The changes since the last scan, sorted by name, and the new scan.
+    fn changes(&self) -> error::Result<(Vec<ChangeEvent>, HashMap<String, Stamp>)> {
+        let now = self.scan()?;
+        let mut events = now.iter()
+            .filter_map(|(name, stamp)| match self.seen.get(name) {
+                None => Some(ChangeEvent::Added(name.clone())),
+                Some(seen) if seen != stamp => Some(ChangeEvent::Modified(name.clone())),
+                Some(_) => None,
+            })
+            .chain(self.seen.keys()
+                .filter(|name| !now.contains_key(*name))
+                .map(|name| ChangeEvent::Removed(name.clone())))
+            .collect::<Vec<_>>();
+        events.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok((events, now))
+    }
+
+    /// The files which were added, changed or removed since the last poll, sorted by name.
+    pub fn poll(&mut self) -> error::Result<Vec<ChangeEvent>> {
+        let (events, now) = self.changes()?;
+        self.seen = now;
+        Ok(events)
+    }
+
+    /// [Poll](Self::poll) whenever the operating system reports a change in the directory, until
+    /// something changed or `timeout` passed, in which case no changes are returned. Where changes
+    /// can't be reported, it polls every `interval` instead.
+    pub fn wait(&mut self, interval: Duration, timeout: Duration) -> error::Result<Vec<ChangeEvent>> {
+        let start = Instant::now();
+        loop {
+            let events = self.poll()?;
+            if !events.is_empty() || start.elapsed() >= timeout {
+                return Ok(events);
+            }
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            let Some(notifications) = &self.notifications else {
+                std::thread::sleep(interval.min(remaining));
+                continue;
+            };
+            match notifications.changed.recv_timeout(remaining) {
+                // one scan covers every change reported so far
+                Ok(()) => while notifications.changed.try_recv().is_ok() {},
+                Err(RecvTimeoutError::Timeout) => {}
+                // the watcher stopped, poll from now on
+                Err(RecvTimeoutError::Disconnected) => self.notifications = None,
+            }
+        }
+    }
+
+    /// Poll, and make the same changes to `bp`: changed files are read again, removed files are
+    /// removed. When it fails, the next refresh makes the same changes again. Returns the changes.
+    pub fn refresh(&mut self, bp: &mut BackPack) -> error::Result<Vec<ChangeEvent>> {
+        let (events, now) = self.changes()?;
+        for event in &events {
+            match event {
+                ChangeEvent::Added(name) | ChangeEvent::Modified(name) => {
+                    let stamp = &now[name];
+                    bp.put_contents(Path::new(name), std::fs::read(&stamp.path).at_path(&stamp.path)?)?;
+                    if self.options.keep_modified {
+                        bp.set_modified(name, stamp.modified)?;
+                    }
+                }
+                ChangeEvent::Removed(name) => ignore_missing(bp.remove_file(name))?,
+            }
+        }
+        self.seen = now;
+        Ok(events)
+    }
+
+    /// Like [`refresh`](Self::refresh), for an [overlay](BackPack::overlay) of a backpack, leaving
+    /// the backpack under it as it is.
+    pub fn refresh_overlay(&mut self, overlay: &mut Overlay) -> error::Result<Vec<ChangeEvent>> {
+        let (events, now) = self.changes()?;
+        for event in &events {
+            match event {
+                ChangeEvent::Added(name) | ChangeEvent::Modified(name) => {
+                    let path = &now[name].path;
+                    overlay.write(name, std::fs::read(path).at_path(path)?)?;
+                }
+                ChangeEvent::Removed(name) => ignore_missing(overlay.remove(name))?,
+            }
+        }
+        self.seen = now;
+        Ok(events)
+    }
+}
+
+/// Removing a file which a failed refresh removed already is fine.
+fn ignore_missing(res: error::Result<()>) -> error::Result<()> {
+    match res {
+        Err(PackError::FileNotFound(_)) => Ok(()),
+        res => res,
+    }
+}
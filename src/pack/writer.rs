@@ -0,0 +1,485 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::pack::backpack::{check_reserved, Index, WriteLimits};
+use crate::pack::compression::{Compressed, Compression};
+use crate::pack::builder::Checksum;
+use crate::pack::crc32::Crc32;
+use crate::pack::entry_name;
+use crate::pack::journal;
+use crate::pack::journal::Journal;
+use crate::pack::layout::{CommitTrailer, PackHeader, U32Le, U64Le, COMMIT_MAGIC};
+use crate::pack::{protection, BackPack, IndexProtection, RawFile, PACK_HEADER_SIZE, TOC_SIZE};
+
+/// Writes a backpack one entry at a time, without knowing all entries up front and without
+/// holding more than a small buffer in memory. Entries are copied straight to the file, and the
+/// table of contents follows them when the backpack is [finished](Self::finish).
+///
+/// Because the table of contents comes last, a [`StreamReader`](crate::pack::StreamReader)
+/// can't read the result. Opening it with [`BackPack::open`] and flushing it rewrites it in
+/// the usual layout.
+///
+/// A writer can also [add entries](Self::open_append) to an existing backpack, and
+/// [commit](Self::commit) the entries added so far for readers while it keeps writing.
+pub struct PackWriter<'f, 'backpack> {
+    file: RawFile<'f, 'backpack>,
+    offsets: HashMap<String, (u64, u64)>,
+    hidden: HashSet<String>,
+    checksums: HashMap<String, u32>,
+    compressed: Compressed,
+    /// alignment of the entries of the backpack appended to, new ones aren't aligned
+    alignments: HashMap<String, u64>,
+    /// where the toc blocks of the backpack appended to are, sorted
+    toc_blocks: Vec<u64>,
+    /// where contents with a checksum and length are, in the index and in the file
+    stored: HashMap<(u32, u64), ((u64, u64), u64)>,
+    /// bytes of contents written so far
+    size: u64,
+    /// whether `finish` goes through a journal, see [`set_journaled`](Self::set_journaled)
+    journaled: bool,
+    limits: WriteLimits,
+    checksum: Checksum,
+}
+
+impl<'f, 'backpack> PackWriter<'f, 'backpack> {
+    /// Start writing a backpack to `file`, from its start. Until [`finish`](Self::finish)
+    /// is called the file is not a valid backpack, but [committed](Self::commit) entries
+    /// can be read with [`BackPack::open_committed`].
+    pub fn new<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        let mut file = file.try_into().map_err(Into::into)?;
+        file.seek(SeekFrom::Start(0))?;
+        // the real header is written when the size and table of contents are known
+        file.write_all(&PackHeader::new(0, 0).to_bytes())?;
+
+        Ok(Self {
+            file,
+            offsets: HashMap::new(),
+            hidden: HashSet::new(),
+            checksums: HashMap::new(),
+            compressed: Compressed::new(),
+            alignments: HashMap::new(),
+            toc_blocks: Vec::new(),
+            stored: HashMap::new(),
+            size: 0,
+            journaled: false,
+            limits: WriteLimits::default(),
+            checksum: Checksum::default(),
+        })
+    }
+
+    /// Add entries to the backpack in `file`, without rewriting the entries already in it.
+    /// New entries are written after the existing ones, and [`finish`](Self::finish) only
+    /// rewrites the table of contents. Until then the file is not a valid backpack.
+    ///
+    /// The blocks of the old table of contents stay where they are, so the new table of contents
+    /// doesn't fit in one piece at the start anymore, which a [protected](crate::pack::IndexProtection)
+    /// index has to. Backpacks with a protected index fail with [`PackError::ProtectedIndex`], see
+    /// [`open_append_dropping_protection`](Self::open_append_dropping_protection). Encrypted
+    /// backpacks can't be appended to, and fail with [`PackError::Encrypted`].
+    pub fn open_append<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        Self::append_to(file.try_into().map_err(Into::into)?, false)
+    }
+
+    /// Like [`open_append`](Self::open_append), also for backpacks with a
+    /// [protected](crate::pack::IndexProtection) index, which isn't protected anymore once
+    /// the writer is [finished](Self::finish).
+    pub fn open_append_dropping_protection<E: Into<PackError>>(file: impl TryInto<RawFile<'f, 'backpack>, Error=E>) -> error::Result<Self> {
+        Self::append_to(file.try_into().map_err(Into::into)?, true)
+    }
+
+    fn append_to(mut file: RawFile<'f, 'backpack>, drop_protection: bool) -> error::Result<Self> {
+        let (Index { offsets, mut toc_blocks, hidden, mut compressed, checksums, encrypted, alignments, stored: stored_as_is }, protection) = protection::parse_protected(&mut file)?;
+        if protection != IndexProtection::None && !drop_protection {
+            return Err(PackError::ProtectedIndex);
+        }
+        if !encrypted.is_empty() {
+            return Err(PackError::Encrypted);
+        }
+        toc_blocks.sort();
+
+        // new entries go after everything the backpack uses, old toc blocks included
+        let end = offsets.values()
+            .filter(|(_, length)| *length != 0)
+            .map(|(offset, length)| BackPack::convert_offset(&toc_blocks, *offset) + length)
+            .chain(toc_blocks.iter().map(|block| block + TOC_SIZE as u64))
+            .fold(PACK_HEADER_SIZE, u64::max);
+        file.seek(SeekFrom::Start(end))?;
+
+        let stored = offsets.iter()
+            .filter(|(name, (_, length))| *length != 0 && !compressed.contains_key(*name))
+            .filter_map(|(name, key)| Some(((*checksums.get(name)?, key.1), (*key, BackPack::convert_offset(&toc_blocks, key.0)))))
+            .collect();
+        // keep recording which entries compressing didn't pay off for
+        for name in stored_as_is {
+            if let Some((_, length)) = offsets.get(&name) {
+                compressed.insert(name, (Compression::None, *length));
+            }
+        }
+
+        Ok(Self {
+            file,
+            size: end - PACK_HEADER_SIZE - toc_blocks.len() as u64 * TOC_SIZE as u64,
+            offsets,
+            hidden,
+            checksums,
+            compressed,
+            alignments,
+            toc_blocks,
+            stored,
+            journaled: false,
+            limits: WriteLimits::default(),
+            checksum: Checksum::default(),
+        })
+    }
+
+    /// [Append](Self::open_append) to the backpack at `path`, with [journaling](Self::set_journaled) on.
+    /// A change to it which was interrupted, like by a crash, is [finished or undone](journal::recover) first.
+    pub fn open_append_journaled(path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        journal::recover(path)?;
+        let file = std::fs::File::options().read(true).write(true).open(path).at_path(path)?;
+        let mut writer = Self::open_append(RawFile::from(file).with_name(path))?;
+        writer.set_journaled(true)?;
+        Ok(writer)
+    }
+
+    /// Write the new table of contents and header in [`finish`](Self::finish) through a
+    /// [journal](journal::journal_path) next to the backpack. When appending, a crash before
+    /// or during `finish` then leaves the backpack as it was before, or with all new entries
+    /// once it's [recovered](journal::recover). Fails with [`PackError::NotJournaled`] unless
+    /// the backpack is in a named file on disk.
+    pub fn set_journaled(&mut self, on: bool) -> error::Result<()> {
+        if on {
+            journal::journaled_path(&self.file)?;
+        }
+        self.journaled = on;
+        Ok(())
+    }
+
+    /// Fail adding entries which would go over `limits`, with [`PackError::EntryTooLarge`],
+    /// [`PackError::PackTooLarge`] or [`PackError::TooManyEntries`]. Contents are checked as
+    /// they're written, before they're known to be stored only once, and entries already in a
+    /// backpack which is [appended to](Self::open_append) count too. The writer can still be used
+    /// after an entry was refused.
+    pub fn set_limits(&mut self, limits: WriteLimits) {
+        self.limits = limits;
+    }
+
+    /// How entries added from now on are checksummed.
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Copy `contents` into the backpack as `name`. Returns the length of the contents.
+    /// When an entry with the same contents is already in the backpack, they're stored only once.
+    pub fn add_entry(&mut self, name: impl AsRef<Path>, contents: impl Read) -> error::Result<u64> {
+        let name = name.as_ref();
+        let name_str = entry_name::key_of(name.as_os_str()).into_owned();
+        check_reserved(&name_str, name)?;
+        self.copy_entry(name_str, name, contents)
+    }
+
+    /// Like [`add_hidden_entry`](Self::add_hidden_entry), for entries of the backpack itself,
+    /// whose names are [reserved](crate::pack::RESERVED_PREFIX).
+    #[cfg(feature = "chunked")]
+    pub(crate) fn add_reserved_entry(&mut self, name: &str, contents: impl Read) -> error::Result<u64> {
+        let length = self.copy_entry(name.to_string(), Path::new(name), contents)?;
+        self.hidden.insert(name.to_string());
+        Ok(length)
+    }
+
+    /// Copy `contents` into the backpack as `name_str`, the stored name of `name`.
+    fn copy_entry(&mut self, name_str: String, name: &Path, mut contents: impl Read) -> error::Result<u64> {
+        let start = self.begin_entry(&name_str, name)?;
+        let mut buf = vec![0; 64 * 1024];
+        let mut crc = Crc32::new();
+        let mut length = 0;
+        loop {
+            let n = match contents.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if let Err(e) = self.limits.check_size(&name_str, length + n as u64, self.size + length + n as u64) {
+                // the next entry starts where this one did
+                self.file.seek(SeekFrom::Start(start))?;
+                return Err(e);
+            }
+            crc.update(&buf[..n]);
+            self.file.write_all(&buf[..n])?;
+            length += n as u64;
+        }
+        self.end_entry(name_str, start, length, crc.finish())
+    }
+
+    /// Write a new entry called `name` as a stream, for encoders which produce their output
+    /// through [`Write`]. What's written goes straight to the file, the entry is added when
+    /// the writer is [finished](PackEntryWriter::finish). Dropping it without finishing
+    /// adds nothing, and the next entry is written over what it wrote.
+    pub fn entry_writer(&mut self, name: impl AsRef<Path>) -> error::Result<PackEntryWriter<'_, 'f, 'backpack>> {
+        let name = name.as_ref();
+        let name_str = entry_name::key_of(name.as_os_str()).into_owned();
+        check_reserved(&name_str, name)?;
+        let start = self.begin_entry(&name_str, name)?;
+        Ok(PackEntryWriter {
+            writer: self,
+            name: name_str,
+            start,
+            length: 0,
+            crc: Crc32::new(),
+            finished: false,
+        })
+    }
+
+    /// Where the contents of a new entry `name_str`, the stored name of `name`, start.
+    fn begin_entry(&self, name_str: &str, name: &Path) -> error::Result<u64> {
+        if name_str.is_empty() {
+            return Err(PackError::NoName);
+        }
+        if self.offsets.contains_key(name_str) {
+            return Err(PackError::FileExists(name.to_path_buf()));
+        }
+        self.limits.check_entries(self.offsets.len() + 1)?;
+        Ok(PACK_HEADER_SIZE + self.toc_blocks.len() as u64 * TOC_SIZE as u64 + self.size)
+    }
+
+    /// Add the entry `name_str` with the `length` bytes written at `start`, which have checksum `crc`.
+    fn end_entry(&mut self, name_str: String, start: u64, length: u64, crc: u32) -> error::Result<u64> {
+        // empty files take up no space
+        if length == 0 {
+            self.offsets.insert(name_str, (0, 0));
+            return Ok(0);
+        }
+
+        if self.checksum == Checksum::Crc32 {
+            self.checksums.insert(name_str.clone(), crc);
+        }
+        if let Some((key, location)) = self.stored.get(&(crc, length)).copied() {
+            // files which can't be read back, like ones opened only for writing, aren't deduplicated
+            if self.same_contents(location, start, length).unwrap_or(false) {
+                // the next entry goes where the copy was
+                self.file.seek(SeekFrom::Start(start))?;
+                self.offsets.insert(name_str, key);
+                return Ok(length);
+            }
+        }
+        self.file.seek(SeekFrom::Start(start + length))?;
+
+        let key = (self.size, length);
+        self.stored.entry((crc, length)).or_insert((key, start));
+        self.offsets.insert(name_str, key);
+        self.size += length;
+
+        Ok(length)
+    }
+
+    /// Whether the `length` bytes at `a` and `b` in the file are the same.
+    fn same_contents(&self, a: u64, b: u64, length: u64) -> error::Result<bool> {
+        let mut buf_a = vec![0; 64 * 1024];
+        let mut buf_b = vec![0; 64 * 1024];
+        let mut done = 0;
+        while done < length {
+            let n = (length - done).min(buf_a.len() as u64) as usize;
+            self.file.read_exact_at(a + done, &mut buf_a[..n])?;
+            self.file.read_exact_at(b + done, &mut buf_b[..n])?;
+            if buf_a[..n] != buf_b[..n] {
+                return Ok(false);
+            }
+            done += n as u64;
+        }
+        Ok(true)
+    }
+
+    /// Like [`add_entry`](Self::add_entry), for a file left out of listings,
+    /// see [`BackPack::set_hidden`].
+    pub fn add_hidden_entry(&mut self, name: impl AsRef<Path>, contents: impl Read) -> error::Result<u64> {
+        let length = self.add_entry(&name, contents)?;
+        self.hidden.insert(entry_name::key_of(name.as_ref().as_os_str()).into_owned());
+        Ok(length)
+    }
+
+    /// Remove the entry `name`, which was already in the backpack or was added by this writer.
+    /// Its contents stay in the file as [dead space](Self::dead_space), only the table of contents
+    /// stops referring to them, so readers of any version agree the entry is gone.
+    /// [`BackPack::compact`] gets rid of the dead space.
+    pub fn remove_entry(&mut self, name: impl AsRef<Path>) -> error::Result<()> {
+        let name = name.as_ref();
+        let name_str = entry_name::key_of(name.as_os_str());
+        if self.offsets.remove(name_str.as_ref()).is_none() {
+            return Err(PackError::FileNotFound(name.to_path_buf()));
+        }
+        self.hidden.remove(name_str.as_ref());
+        self.checksums.remove(name_str.as_ref());
+        self.compressed.remove(name_str.as_ref());
+        self.alignments.remove(name_str.as_ref());
+        Ok(())
+    }
+
+    /// Bytes of contents written so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes in the backpack which no entry uses, like the contents of [removed](Self::remove_entry)
+    /// entries and the padding before aligned ones.
+    pub fn dead_space(&self) -> u64 {
+        let mut ranges = self.offsets.values()
+            .filter(|(_, length)| *length != 0)
+            .map(|(offset, length)| (*offset, offset + length))
+            .collect::<Vec<_>>();
+        ranges.sort();
+
+        // entries can share their contents, so count every byte once
+        let mut used = 0;
+        let mut covered = 0;
+        for (start, end) in ranges {
+            used += end.saturating_sub(start.max(covered));
+            covered = covered.max(end);
+        }
+        self.size - used
+    }
+
+    /// The toc blocks for the entries so far with where they go, in the order of the chain,
+    /// and where the blocks after the entries end.
+    fn table_of_contents(&self) -> error::Result<(Vec<u64>, Vec<Vec<u8>>, u64)> {
+        let first_block = PACK_HEADER_SIZE + self.size + self.toc_blocks.len() as u64 * TOC_SIZE as u64;
+        let toc_blocks = BackPack::create_toc_in(&self.offsets, &self.hidden, &self.compressed, &HashSet::new(), &self.checksums, &self.alignments, &self.toc_blocks, first_block)?;
+        let locations = self.toc_blocks.iter().copied()
+            .chain((0..).map(|i| first_block + i * TOC_SIZE as u64))
+            .take(toc_blocks.len())
+            .collect::<Vec<_>>();
+        let end = first_block + (toc_blocks.len() - self.toc_blocks.len()) as u64 * TOC_SIZE as u64;
+        Ok((locations, toc_blocks, end))
+    }
+
+    /// Make the entries added so far readable with [`BackPack::open_committed`], while the
+    /// writer keeps adding entries. The table of contents is written after the entries with a
+    /// trailer holding its checksum, and then the header is pointed at it. Later entries go
+    /// after the trailer, so a reader sees the last committed generation whole, up to the next
+    /// commit or [`finish`](Self::finish).
+    ///
+    /// The table of contents of a commit stays in the file as [dead space](Self::dead_space).
+    /// When appending, the blocks of the old table of contents are rewritten in place, so a
+    /// reader of the generation before may have to try again.
+    pub fn commit(&mut self) -> error::Result<()> {
+        let (locations, toc_blocks, end) = self.table_of_contents()?;
+        let entries_end = PACK_HEADER_SIZE + self.toc_blocks.len() as u64 * TOC_SIZE as u64 + self.size;
+        let first_toc = locations.first().copied().unwrap_or(0);
+        let mut crc = Crc32::new();
+        for block in &toc_blocks {
+            crc.update(block);
+        }
+        let trailer = CommitTrailer {
+            size: U64Le::new(self.size),
+            first_toc: U64Le::new(first_toc),
+            toc_crc: U32Le::new(crc.finish()),
+            magic: *COMMIT_MAGIC,
+        };
+        let header = PackHeader::new(self.size, first_toc).for_toc(&toc_blocks).to_bytes();
+
+        // the header goes last, readers follow it to the new generation only once it's complete
+        let committed_end = end + CommitTrailer::SIZE as u64;
+        if self.journaled {
+            let mut journal = Journal::new(committed_end);
+            for (location, block) in locations.iter().zip(toc_blocks) {
+                journal.write(*location, block);
+            }
+            journal.write(end, trailer.to_bytes().to_vec());
+            journal.write(0, header.to_vec());
+            journal.commit(&mut self.file)?;
+        } else {
+            for (location, block) in locations.iter().zip(&toc_blocks) {
+                self.file.seek(SeekFrom::Start(*location))?;
+                self.file.write_all(block)?;
+            }
+            self.file.seek(SeekFrom::Start(end))?;
+            self.file.write_all(&trailer.to_bytes())?;
+            self.file.flush()?;
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+            self.file.flush()?;
+        }
+
+        // later generations don't refer to what this one wrote after the entries
+        self.size += committed_end - entries_end;
+        self.file.seek(SeekFrom::Start(committed_end))?;
+        Ok(())
+    }
+
+    /// Write the table of contents after the entries and the header before them,
+    /// and return the finished file.
+    pub fn finish(mut self) -> error::Result<RawFile<'f, 'backpack>> {
+        let (locations, toc_blocks, end) = self.table_of_contents()?;
+        let first_toc = locations.first().copied().unwrap_or(0);
+        let header = PackHeader::new(self.size, first_toc).for_toc(&toc_blocks).to_bytes();
+
+        if self.journaled {
+            let mut journal = Journal::new(end);
+            for (location, block) in locations.iter().zip(toc_blocks) {
+                journal.write(*location, block);
+            }
+            journal.write(0, header.to_vec());
+            journal.commit(&mut self.file)?;
+        } else {
+            for (location, block) in locations.iter().zip(&toc_blocks) {
+                self.file.seek(SeekFrom::Start(*location))?;
+                self.file.write_all(block)?;
+            }
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+            // whatever followed the old table of contents, like the trailer of a protected index
+            self.file.set_len(end)?;
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()?;
+
+        Ok(self.file)
+    }
+}
+
+/// A new entry of a [`PackWriter`] being written, see [`PackWriter::entry_writer`].
+pub struct PackEntryWriter<'w, 'f, 'backpack> {
+    writer: &'w mut PackWriter<'f, 'backpack>,
+    name: String,
+    /// where the contents start in the file
+    start: u64,
+    length: u64,
+    crc: Crc32,
+    finished: bool,
+}
+
+impl PackEntryWriter<'_, '_, '_> {
+    /// Add the entry with what was written to it. Returns the length of its contents.
+    pub fn finish(mut self) -> error::Result<u64> {
+        self.finished = true;
+        let name = std::mem::take(&mut self.name);
+        self.writer.end_entry(name, self.start, self.length, self.crc.finish())
+    }
+}
+
+impl Write for PackEntryWriter<'_, '_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = self.length + buf.len() as u64;
+        self.writer.limits.check_size(&self.name, length, self.writer.size + length)?;
+        let n = self.writer.file.write(buf)?;
+        self.crc.update(&buf[..n]);
+        self.length += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.file.flush()
+    }
+}
+
+impl Drop for PackEntryWriter<'_, '_, '_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // the next entry starts where this one did
+            let _ = self.writer.file.seek(SeekFrom::Start(self.start));
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::pack::backpack::normalize_name;
+use crate::pack::compression::Compression;
+use crate::pack::crc32::crc32;
+use crate::pack::tar;
+use crate::pack::{PACK_HEADER_SIZE, PACK_MAGIC, TOC_SIZE};
+use crate::{BackPack, RawFile};
+use crate::pack::backpack::Layout;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+/// size of the end of central directory record, without the comment
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+/// size of the zip64 end of central directory locator, right before the end of central directory record
+const ZIP64_LOCATOR_SIZE: u64 = 20;
+/// size of the zip64 end of central directory record, without its extensible data
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE: usize = 56;
+/// size of a central directory header, without the name, extra field and comment
+const CENTRAL_HEADER_SIZE: usize = 46;
+/// size of a local file header, without the name and extra field
+const LOCAL_HEADER_SIZE: usize = 30;
+
+/// general purpose flag 0: the member is encrypted
+const ENCRYPTED: u16 = 1;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// extra field with the 64 bit sizes and offset of a member
+const ZIP64_EXTRA: u16 = 0x0001;
+/// extra field with the modification time in seconds since the unix epoch
+const TIMESTAMP_EXTRA: u16 = 0x5455;
+/// upper byte of "version made by" for archives made on unix, whose external attributes hold the file mode
+const MADE_BY_UNIX: u8 = 3;
+const SYMLINK_MODE: u32 = 0o120000;
+
+/// version 2.0, needed for directories and (future) deflate
+const ZIP_VERSION: u16 = 20;
+/// general purpose flag 11: names are utf-8
+const UTF8_NAMES: u16 = 1 << 11;
+/// 1980-01-01, the earliest date zip can represent
+const DOS_DATE: u16 = 0x0021;
+
+struct Member<'a> {
+    name: &'a str,
+    crc: u32,
+    size: u32,
+    local_header_offset: u32,
+}
+
+fn to_u32(v: u64) -> error::Result<u32> {
+    v.try_into().map_err(|_| PackError::ZipTooLarge)
+}
+
+fn write_common_fields(buf: &mut Vec<u8>, m: &Member) -> error::Result<()> {
+    buf.write_all(&UTF8_NAMES.to_le_bytes())?;
+    // compression method: stored
+    buf.write_all(&0u16.to_le_bytes())?;
+    // modification time and date
+    buf.write_all(&0u16.to_le_bytes())?;
+    buf.write_all(&DOS_DATE.to_le_bytes())?;
+    buf.write_all(&m.crc.to_le_bytes())?;
+    // compressed and uncompressed size
+    buf.write_all(&m.size.to_le_bytes())?;
+    buf.write_all(&m.size.to_le_bytes())?;
+    buf.write_all(&(m.name.len() as u16).to_le_bytes())?;
+    // extra field length
+    buf.write_all(&0u16.to_le_bytes())?;
+    Ok(())
+}
+
+/// Whether `f` is a backpack written with [`write_hybrid`], recognized by the
+/// zip comment marking it as a backpack at the very end.
+pub(crate) fn is_hybrid(f: &mut (impl Read + Seek)) -> error::Result<bool> {
+    let tail_len = END_OF_CENTRAL_DIRECTORY_SIZE + PACK_MAGIC.len() as u64;
+    let len = f.seek(SeekFrom::End(0))?;
+    if len < tail_len {
+        return Ok(false);
+    }
+
+    let mut tail = vec![0; tail_len as usize];
+    f.seek(SeekFrom::Start(len - tail_len))?;
+    f.read_exact(&mut tail)?;
+
+    Ok(tail[..4] == END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes()
+        && tail[20..22] == (PACK_MAGIC.len() as u16).to_le_bytes()
+        && &tail[22..] == PACK_MAGIC)
+}
+
+/// Write a backpack which is at the same time a valid zip archive.
+///
+/// The backpack header and table of contents come first, just like in a normal backpack.
+/// Every entry is then preceded by a zip local file header, and a zip central directory
+/// follows the last entry. Zip readers find the archive from the end of the file and
+/// ignore the backpack header, while backpack finds its entries through its own table of contents.
+pub(crate) fn write_hybrid(f: &mut RawFile, entries: &[(&str, &[u8])], hidden: &HashSet<String>) -> error::Result<Layout> {
+    let mut data = Vec::new();
+    let mut offsets = HashMap::new();
+    let mut members = Vec::new();
+    let mut checksums = HashMap::new();
+
+    for (name, contents) in entries {
+        let local_header_offset = data.len() as u64;
+        let member = Member {
+            name,
+            crc: crc32(contents),
+            size: to_u32(contents.len() as u64)?,
+            local_header_offset: to_u32(local_header_offset)?,
+        };
+
+        data.write_all(&LOCAL_HEADER_SIGNATURE.to_le_bytes())?;
+        data.write_all(&ZIP_VERSION.to_le_bytes())?;
+        write_common_fields(&mut data, &member)?;
+        data.write_all(name.as_bytes())?;
+
+        offsets.insert(name.to_string(), (data.len() as u64, contents.len() as u64));
+        if !contents.is_empty() {
+            checksums.insert(name.to_string(), member.crc);
+        }
+        data.write_all(contents)?;
+
+        members.push(member);
+    }
+
+    // zip offsets are absolute, so they have to skip over the backpack header
+    let toc_blocks = BackPack::create_toc_at(&offsets, hidden, &HashMap::new(), &HashSet::new(), &checksums, &HashMap::new(), PACK_HEADER_SIZE)?.len() as u64;
+    let data_start = PACK_HEADER_SIZE + toc_blocks * TOC_SIZE as u64;
+
+    let mut central_directory = Vec::new();
+    for member in &mut members {
+        member.local_header_offset = to_u32(data_start + member.local_header_offset as u64)?;
+
+        central_directory.write_all(&CENTRAL_HEADER_SIGNATURE.to_le_bytes())?;
+        // version made by and version needed
+        central_directory.write_all(&ZIP_VERSION.to_le_bytes())?;
+        central_directory.write_all(&ZIP_VERSION.to_le_bytes())?;
+        write_common_fields(&mut central_directory, member)?;
+        // comment length, disk number, internal and external attributes
+        central_directory.write_all(&0u16.to_le_bytes())?;
+        central_directory.write_all(&0u16.to_le_bytes())?;
+        central_directory.write_all(&0u16.to_le_bytes())?;
+        central_directory.write_all(&0u32.to_le_bytes())?;
+        central_directory.write_all(&member.local_header_offset.to_le_bytes())?;
+        central_directory.write_all(member.name.as_bytes())?;
+    }
+
+    let num_members: u16 = members.len().try_into().map_err(|_| PackError::ZipTooLarge)?;
+    let central_directory_offset = to_u32(data_start + data.len() as u64)?;
+
+    let toc_blocks = BackPack::write_headers(f, data.len() as u64, &offsets, hidden, &HashMap::new(), &HashSet::new(), &checksums, &HashMap::new())?;
+    f.write_all(&data)?;
+    f.write_all(&central_directory)?;
+
+    f.write_all(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())?;
+    // number of this disk, and disk where the central directory starts
+    f.write_all(&0u16.to_le_bytes())?;
+    f.write_all(&0u16.to_le_bytes())?;
+    f.write_all(&num_members.to_le_bytes())?;
+    f.write_all(&num_members.to_le_bytes())?;
+    f.write_all(&to_u32(central_directory.len() as u64)?.to_le_bytes())?;
+    f.write_all(&central_directory_offset.to_le_bytes())?;
+    // the comment marks the zip as a backpack
+    f.write_all(&(PACK_MAGIC.len() as u16).to_le_bytes())?;
+    f.write_all(PACK_MAGIC)?;
+
+    Ok(Layout {
+        offsets,
+        toc_blocks,
+        data_size: data.len() as u64,
+        sidecars: Vec::new(),
+    })
+}
+
+fn u16_at(buf: &[u8], at: usize) -> error::Result<u16> {
+    buf.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or(PackError::InvalidEntry)
+}
+
+fn u32_at(buf: &[u8], at: usize) -> error::Result<u32> {
+    Ok(u32::from(u16_at(buf, at)?) | u32::from(u16_at(buf, at + 2)?) << 16)
+}
+
+fn u64_at(buf: &[u8], at: usize) -> error::Result<u64> {
+    Ok(u64::from(u32_at(buf, at)?) | u64::from(u32_at(buf, at + 4)?) << 32)
+}
+
+fn read_at(f: &mut (impl Read + Seek), at: u64, len: usize) -> error::Result<Vec<u8>> {
+    f.seek(SeekFrom::Start(at))?;
+    let mut buf = Vec::new();
+    f.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(buf)
+}
+
+/// Seconds since the unix epoch of an ms-dos time and date. Zip doesn't say which
+/// time zone they're in, they're taken to be in UTC.
+fn dos_time(time: u16, date: u16) -> u64 {
+    let year = 1980 + u64::from(date >> 9);
+    let month = u64::from((date >> 5) & 0xf).clamp(1, 12);
+    let day = u64::from(date & 0x1f).max(1);
+
+    // days since the epoch, counting years from march so leap days come last
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let days_in_year = (153 * month + 2) / 5 + day - 1;
+    let days = year * 365 + year / 4 - year / 100 + year / 400 + days_in_year - 719_468;
+
+    let seconds = u64::from(time >> 11) * 3600 + u64::from((time >> 5) & 0x3f) * 60 + u64::from(time & 0x1f) * 2;
+    days * 86_400 + seconds
+}
+
+/// The end of central directory record: where the central directory starts, how long
+/// it is and how many members it has, and how many bytes come before the archive.
+struct CentralDirectory {
+    offset: u64,
+    size: u64,
+    members: u64,
+    prefix: u64,
+}
+
+fn find_central_directory(f: &mut (impl Read + Seek)) -> error::Result<CentralDirectory> {
+    let len = f.seek(SeekFrom::End(0))?;
+    // the record ends in a comment of at most 64k
+    let tail_len = len.min(END_OF_CENTRAL_DIRECTORY_SIZE + u64::from(u16::MAX));
+    let tail = read_at(f, len - tail_len, tail_len as usize)?;
+
+    let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+    let at = (0..tail.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE as usize - 1)).rev()
+        .find(|at| tail[*at..*at + 4] == signature
+            && at + END_OF_CENTRAL_DIRECTORY_SIZE as usize + usize::from(tail[at + 20]) + (usize::from(tail[at + 21]) << 8) <= tail.len())
+        .ok_or(PackError::InvalidEntry)?;
+    let record = &tail[at..];
+    let record_offset = len - tail_len + at as u64;
+
+    let members = u64::from(u16_at(record, 10)?);
+    let size = u64::from(u32_at(record, 12)?);
+    let offset = u64::from(u32_at(record, 16)?);
+    if members != u64::from(u16::MAX) && size != u64::from(u32::MAX) && offset != u64::from(u32::MAX) {
+        // self-extracting archives have an executable before them, which offsets don't count
+        let prefix = record_offset.checked_sub(offset + size).ok_or(PackError::InvalidEntry)?;
+        return Ok(CentralDirectory { offset, size, members, prefix });
+    }
+
+    // too large for the record, the real values are in the zip64 record the locator points to
+    let locator = read_at(f, record_offset.checked_sub(ZIP64_LOCATOR_SIZE).ok_or(PackError::InvalidEntry)?, ZIP64_LOCATOR_SIZE as usize)?;
+    if u32_at(&locator, 0)? != ZIP64_LOCATOR_SIGNATURE {
+        return Err(PackError::InvalidEntry);
+    }
+    let record = read_at(f, u64_at(&locator, 8)?, ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE)?;
+    if u32_at(&record, 0)? != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+        return Err(PackError::InvalidEntry);
+    }
+    Ok(CentralDirectory {
+        members: u64_at(&record, 32)?,
+        size: u64_at(&record, 40)?,
+        offset: u64_at(&record, 48)?,
+        prefix: 0,
+    })
+}
+
+/// A member as described by its central directory header.
+struct Header {
+    name: String,
+    flags: u16,
+    method: u16,
+    modified: u64,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    local_header_offset: u64,
+    is_symlink: bool,
+}
+
+/// Parse the central directory header at `at`, returns it with the offset of the next one.
+fn read_header(cd: &[u8], at: usize) -> error::Result<(Header, usize)> {
+    if u32_at(cd, at)? != CENTRAL_HEADER_SIGNATURE {
+        return Err(PackError::InvalidEntry);
+    }
+    let name_len = usize::from(u16_at(cd, at + 28)?);
+    let extra_len = usize::from(u16_at(cd, at + 30)?);
+    let comment_len = usize::from(u16_at(cd, at + 32)?);
+    let name_start = at + CENTRAL_HEADER_SIZE;
+    let extra_start = name_start + name_len;
+    let name = cd.get(name_start..extra_start).ok_or(PackError::InvalidEntry)?;
+    let extra = cd.get(extra_start..extra_start + extra_len).ok_or(PackError::InvalidEntry)?;
+
+    let made_by = cd[at + 5];
+    let external = u32_at(cd, at + 38)?;
+    let mut header = Header {
+        name: String::from_utf8_lossy(name).into_owned(),
+        flags: u16_at(cd, at + 8)?,
+        method: u16_at(cd, at + 10)?,
+        modified: dos_time(u16_at(cd, at + 12)?, u16_at(cd, at + 14)?),
+        crc: u32_at(cd, at + 16)?,
+        compressed_size: u64::from(u32_at(cd, at + 20)?),
+        size: u64::from(u32_at(cd, at + 24)?),
+        local_header_offset: u64::from(u32_at(cd, at + 42)?),
+        is_symlink: made_by == MADE_BY_UNIX && (external >> 16) & 0o170000 == SYMLINK_MODE,
+    };
+
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let id = u16_at(rest, 0)?;
+        let len = usize::from(u16_at(rest, 2)?);
+        let data = rest.get(4..4 + len).ok_or(PackError::InvalidEntry)?;
+        match id {
+            // only the fields which didn't fit are in it, in this order
+            ZIP64_EXTRA => {
+                let mut values = data.chunks_exact(8).map(|v| u64_at(v, 0));
+                for field in [&mut header.size, &mut header.compressed_size, &mut header.local_header_offset] {
+                    if *field == u64::from(u32::MAX) {
+                        *field = values.next().ok_or(PackError::InvalidEntry)??;
+                    }
+                }
+            }
+            // flag 0 means the modification time is there
+            TIMESTAMP_EXTRA if data.len() >= 5 && data[0] & 1 != 0 => {
+                header.modified = i32::from_le_bytes([data[1], data[2], data[3], data[4]]).max(0) as u64;
+            }
+            _ => {}
+        }
+        rest = &rest[4 + len..];
+    }
+
+    Ok((header, extra_start + extra_len + comment_len))
+}
+
+/// Read the files and directories in the zip archive `f`, in the order of its central directory.
+/// Names are normalized like the names of files in a backpack. Members may be stored or deflated,
+/// deflated ones need the `deflate` feature. Symbolic links are skipped.
+pub(crate) fn read_zip(f: &mut (impl Read + Seek)) -> error::Result<Vec<tar::Member>> {
+    let cd = find_central_directory(f)?;
+    let central_directory = read_at(f, cd.prefix + cd.offset, cd.size.try_into().map_err(|_| PackError::InvalidEntry)?)?;
+
+    let mut members = Vec::new();
+    let mut at = 0;
+    for _ in 0..cd.members {
+        let (header, next) = read_header(&central_directory, at)?;
+        at = next;
+
+        let name = normalize_name(Path::new(&header.name));
+        let name = name.trim_start_matches('/');
+        if header.is_symlink {
+            log::warn!("skipping symbolic link {:?} in zip archive", name);
+            continue;
+        }
+        if header.name.ends_with('/') {
+            if !name.trim_end_matches('/').is_empty() {
+                members.push(tar::Member {
+                    name: format!("{}/", name.trim_end_matches('/')),
+                    modified: header.modified,
+                    contents: Vec::new(),
+                });
+            }
+            continue;
+        }
+        if name.is_empty() {
+            continue;
+        }
+
+        if header.flags & ENCRYPTED != 0 {
+            return Err(PackError::UnsupportedZip("encryption"));
+        }
+        let method = match header.method {
+            STORED => Compression::None,
+            DEFLATED => Compression::Deflate,
+            _ => return Err(PackError::UnsupportedZip("a compression method other than stored or deflated")),
+        };
+
+        // the local header repeats the name, and may have a different extra field
+        let local_header_offset = cd.prefix + header.local_header_offset;
+        let local_header = read_at(f, local_header_offset, LOCAL_HEADER_SIZE)?;
+        if u32_at(&local_header, 0)? != LOCAL_HEADER_SIGNATURE {
+            return Err(PackError::InvalidEntry);
+        }
+        let data_offset = local_header_offset + LOCAL_HEADER_SIZE as u64
+            + u64::from(u16_at(&local_header, 26)?) + u64::from(u16_at(&local_header, 28)?);
+        let data = read_at(f, data_offset, header.compressed_size.try_into().map_err(|_| PackError::InvalidEntry)?)?;
+
+        let contents = method.decompress(&data, header.size)?;
+        if crc32(&contents) != header.crc {
+            return Err(PackError::ChecksumMismatch(name.into()));
+        }
+        members.push(tar::Member {
+            name: name.to_string(),
+            modified: header.modified,
+            contents,
+        });
+    }
+
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::RawFile;
+    use crate::error::PackError;
+    use crate::pack::backpack::BackPack;
+    use crate::pack::crc32::crc32;
+    use crate::pack::in_memory::InMemoryFile;
+    use crate::pack::OutputMode;
+    use crate::pack::zip::{dos_time, find_central_directory, read_at, read_header, u16_at, LOCAL_HEADER_SIZE};
+
+    #[test]
+    fn test_dos_time() {
+        // 1980-01-01 00:00:00
+        assert_eq!(dos_time(0, 0x0021), 315_532_800);
+        // 2020-05-06 07:08:10
+        assert_eq!(dos_time(7 << 11 | 8 << 5 | 5, 40 << 9 | 5 << 5 | 6), 1_588_748_890);
+    }
+
+    #[test]
+    fn test_hybrid_round_trip() -> Result<(), PackError> {
+        let binary = (0..=255u8).cycle().take(10_000).collect::<Vec<_>>();
+        let files: [(&str, &[u8]); 4] = [
+            ("a.txt", b"first"),
+            ("dir/b.txt", b"second"),
+            ("dir/sub/binary.bin", &binary),
+            ("empty.txt", b""),
+        ];
+
+        let mut bp = BackPack::create(RawFile::in_memory("test.bp"))?;
+        bp.set_output_mode(OutputMode::ZipHybrid);
+        for (name, contents) in files {
+            let f: InMemoryFile = contents.to_vec().into();
+            bp.add_file(f.with_name(name))?;
+        }
+        let mut bytes = bp.close()?.into_memory().ok().unwrap().get_bytes().to_vec();
+
+        let path = std::env::temp_dir().join("backpack_test_zip_hybrid.zip");
+        std::fs::write(&path, &bytes)?;
+        let bp = BackPack::open_zip(&path)?;
+        let mut names = bp.file_names();
+        names.sort();
+        assert_eq!(names, files.map(|(name, _)| name));
+        for (name, contents) in files {
+            assert_eq!(&*bp.get_file(name)?.get_bytes(), contents, "{name}");
+        }
+
+        // every member's crc in the central directory matches its contents
+        let mut f = Cursor::new(&bytes);
+        let cd = find_central_directory(&mut f)?;
+        let central_directory = read_at(&mut f, cd.prefix + cd.offset, cd.size as usize)?;
+        let mut at = 0;
+        let mut crcs = Vec::new();
+        for _ in 0..cd.members {
+            let (header, next) = read_header(&central_directory, at)?;
+            at = next;
+            if let Some((_, contents)) = files.iter().find(|(name, _)| *name == header.name) {
+                assert_eq!(header.crc, crc32(contents), "{}", header.name);
+                crcs.push((header.name, header.local_header_offset));
+            }
+        }
+        assert_eq!(crcs.len(), files.len());
+
+        // and they're checked, a flipped byte in a member is found when reading it
+        let (_, offset) = crcs.iter().find(|(name, _)| name == "dir/b.txt").unwrap();
+        let local_header = &bytes[*offset as usize..];
+        let data = *offset as usize + LOCAL_HEADER_SIZE
+            + usize::from(u16_at(local_header, 26)?) + usize::from(u16_at(local_header, 28)?);
+        assert_eq!(&bytes[data..data + 6], b"second");
+        bytes[data] ^= 1;
+        std::fs::write(&path, &bytes)?;
+        assert!(matches!(BackPack::open_zip(&path), Err(PackError::ChecksumMismatch(name)) if name == std::path::Path::new("dir/b.txt")));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
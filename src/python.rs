@@ -0,0 +1,170 @@
+//! The native module of the `backpack` Python package in `python/`, which maturin builds
+//! with this feature: `pip install ./python`, or `maturin develop` in `python/`.
+
+use std::path::PathBuf;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyIterator, PyList, PyType};
+use crate::pack::{PackReader, PackWriter};
+use crate::{PackError, RawFile};
+
+create_exception!(backpack, BackpackError, PyOSError, "An operation on a backpack failed.");
+
+fn error(e: PackError) -> PyErr {
+    BackpackError::new_err(e.to_string())
+}
+
+/// An open backpack. Only its index is in memory, entries are read when they're opened.
+/// Iterating over it gives the names of its entries, sorted.
+#[pyclass(module = "backpack")]
+struct Pack {
+    reader: Option<PackReader<'static, 'static>>,
+    /// names of the entries, sorted
+    names: Vec<String>,
+}
+
+impl Pack {
+    fn reader(&self) -> PyResult<&PackReader<'static, 'static>> {
+        self.reader.as_ref().ok_or_else(|| PyValueError::new_err("use of a closed backpack"))
+    }
+}
+
+#[pymethods]
+impl Pack {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let reader = RawFile::open(path).and_then(PackReader::open).map_err(error)?;
+        let mut names = reader.file_names().into_iter().map(str::to_string).collect::<Vec<_>>();
+        names.sort();
+        Ok(Self { reader: Some(reader), names })
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.reader()?;
+        Ok(self.names.len())
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        self.reader()?;
+        PyList::new(py, &self.names)?.try_iter()
+    }
+
+    fn __contains__(&self, name: &str) -> PyResult<bool> {
+        self.reader()?;
+        Ok(self.names.binary_search_by(|other| other.as_str().cmp(name)).is_ok())
+    }
+
+    fn names(&self) -> PyResult<Vec<String>> {
+        self.reader()?;
+        Ok(self.names.clone())
+    }
+
+    /// The contents of the entry called `name`.
+    fn read<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let contents = self.reader()?.read(name).map_err(error)?;
+        Ok(PyBytes::new(py, &contents))
+    }
+
+    /// The entry called `name` as a binary stream.
+    fn open<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyAny>> {
+        let contents = self.read(py, name)?;
+        py.import("io")?.getattr("BytesIO")?.call1((contents,))
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, _exc_type: Option<&Bound<'_, PyType>>, _exc: Option<&Bound<'_, PyAny>>, _traceback: Option<&Bound<'_, PyAny>>) {
+        self.close();
+    }
+}
+
+/// Writes a new backpack, entry by entry. The entries are only in the backpack once it's
+/// finished. Used as a context manager, it finishes when the block succeeds and is
+/// discarded when it raises.
+#[pyclass(module = "backpack")]
+struct Writer {
+    writer: Option<PackWriter<'static, 'static>>,
+}
+
+impl Writer {
+    fn writer(&mut self) -> PyResult<&mut PackWriter<'static, 'static>> {
+        self.writer.as_mut().ok_or_else(|| PyValueError::new_err("use of a finished writer"))
+    }
+}
+
+#[pymethods]
+impl Writer {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let writer = RawFile::create(path).and_then(PackWriter::new).map_err(error)?;
+        Ok(Self { writer: Some(writer) })
+    }
+
+    /// Add an entry called `name`. `contents` is bytes-like, or a binary stream which is
+    /// read to the end.
+    fn write(&mut self, name: &str, contents: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = contents.py();
+        let contents = match contents.hasattr("read")? {
+            true => contents.call_method0("read")?,
+            false => contents.clone(),
+        };
+        let contents = py.get_type::<PyBytes>().call1((contents,))?.cast_into::<PyBytes>()?;
+        self.writer()?.add_entry(name, contents.as_bytes()).map_err(error)?;
+        Ok(())
+    }
+
+    /// Write the index of the backpack. The writer can't be used afterwards.
+    fn finish(&mut self) -> PyResult<()> {
+        self.writer()?;
+        let writer = self.writer.take().expect("checked above");
+        writer.finish().and_then(|mut file| Ok(std::io::Write::flush(&mut file)?)).map_err(error)
+    }
+
+    /// Stop writing without finishing the backpack, which leaves it empty.
+    fn discard(&mut self) {
+        self.writer = None;
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(&mut self, exc_type: Option<&Bound<'_, PyType>>, _exc: Option<&Bound<'_, PyAny>>, _traceback: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        match exc_type {
+            None if self.writer.is_some() => self.finish(),
+            _ => {
+                self.discard();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Open the backpack at `path` for reading.
+#[pyfunction(name = "open")]
+fn open_pack(path: PathBuf) -> PyResult<Pack> {
+    Pack::new(path)
+}
+
+/// Start writing a new backpack to `path`, replacing what's there.
+#[pyfunction]
+fn create(path: PathBuf) -> PyResult<Writer> {
+    Writer::new(path)
+}
+
+#[pymodule]
+fn _backpack(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("BackpackError", m.py().get_type::<BackpackError>())?;
+    m.add_class::<Pack>()?;
+    m.add_class::<Writer>()?;
+    m.add_function(wrap_pyfunction!(open_pack, m)?)?;
+    m.add_function(wrap_pyfunction!(create, m)?)?;
+    Ok(())
+}
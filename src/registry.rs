@@ -0,0 +1,161 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use crate::error;
+use crate::error::PackError;
+use crate::remote::{RangeSource, RemoteBackPack};
+use crate::BackPack;
+
+/// Somewhere the contents of assets can be read from.
+pub trait AssetSource {
+    fn read_asset(&self, name: &Path) -> error::Result<Vec<u8>>;
+}
+
+impl<'f, 'backpack> AssetSource for &'f BackPack<'f, 'backpack> {
+    fn read_asset(&self, name: &Path) -> error::Result<Vec<u8>> {
+        Ok(self.get_file(name)?.get_bytes().to_vec())
+    }
+}
+
+impl<S: RangeSource> AssetSource for RemoteBackPack<S> {
+    fn read_asset(&self, name: &Path) -> error::Result<Vec<u8>> {
+        self.read_file(name)
+    }
+}
+
+type Loader<T> = Box<dyn Fn(&Path, &[u8]) -> Result<T, String> + Send + Sync>;
+type CachedAsset = Arc<dyn Any + Send + Sync>;
+
+/// Loads files from a backpack as Rust types, with a loader registered for every type.
+/// Loaded assets are cached until they are [invalidated](Self::invalidate),
+/// for example when a file watcher notices they changed.
+///
+/// ```
+/// # use backpack::{BackPack, InMemoryFile, RawFile, PackError};
+/// # use backpack::registry::AssetRegistry;
+/// # fn main() -> Result<(), PackError> {
+/// let bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+/// bp.add_file(InMemoryFile::from("42").with_name("answer.txt"))?;
+///
+/// let mut registry = AssetRegistry::new(&bp);
+/// registry.register(|_, bytes| String::from_utf8_lossy(bytes).parse::<u32>().map_err(|e| e.to_string()));
+/// assert_eq!(*registry.get::<u32>("answer.txt")?, 42);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AssetRegistry<A> {
+    source: A,
+    loaders: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    cache: Mutex<HashMap<(TypeId, String), CachedAsset>>,
+}
+
+impl<A: AssetSource> AssetRegistry<A> {
+    pub fn new(source: A) -> Self {
+        Self {
+            source,
+            loaders: HashMap::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn source(&self) -> &A {
+        &self.source
+    }
+
+    /// Use `loader` to turn files into `T`s, replacing any loader registered for `T` before.
+    pub fn register<T: Send + Sync + 'static>(&mut self, loader: impl Fn(&Path, &[u8]) -> Result<T, String> + Send + Sync + 'static) {
+        let loader: Loader<T> = Box::new(loader);
+        self.loaders.insert(TypeId::of::<T>(), Box::new(loader));
+        self.cache.lock().retain(|(type_id, _), _| *type_id != TypeId::of::<T>());
+    }
+
+    /// Get the file called `name` as a `T`, loading it if it isn't cached.
+    pub fn get<T: Send + Sync + 'static>(&self, name: impl AsRef<Path>) -> error::Result<Arc<T>> {
+        let name = name.as_ref();
+        let key = (TypeId::of::<T>(), name.to_string_lossy().into_owned());
+
+        if let Some(asset) = self.cache.lock().get(&key) {
+            return Ok(asset.clone().downcast().expect("cached asset has the type it's keyed by"));
+        }
+
+        let loader = self.loaders.get(&key.0)
+            .and_then(|loader| loader.downcast_ref::<Loader<T>>())
+            .ok_or(PackError::NoLoader(type_name::<T>()))?;
+
+        let bytes = self.source.read_asset(name)?;
+        let asset = Arc::new(loader(name, &bytes).map_err(|reason| PackError::AssetLoad {
+            name: name.to_path_buf(),
+            asset: type_name::<T>(),
+            reason,
+        })?);
+
+        self.cache.lock().insert(key, asset.clone());
+        Ok(asset)
+    }
+
+    /// Forget the cached versions of `name`, so it is loaded again the next time it's requested.
+    /// Assets already handed out are unaffected.
+    pub fn invalidate(&self, name: impl AsRef<Path>) {
+        let name = name.as_ref().to_string_lossy();
+        self.cache.lock().retain(|(_, cached), _| *cached != name);
+    }
+
+    /// Forget all cached assets.
+    pub fn clear(&self) {
+        self.cache.lock().clear();
+    }
+
+    pub fn is_cached<T: 'static>(&self, name: impl AsRef<Path>) -> bool {
+        let key = (TypeId::of::<T>(), name.as_ref().to_string_lossy().into_owned());
+        self.cache.lock().contains_key(&key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use crate::error::{PackError, Result};
+    use crate::registry::AssetRegistry;
+    use crate::{BackPack, InMemoryFile, RawFile};
+
+    #[derive(Debug, PartialEq)]
+    struct Level(Vec<String>);
+
+    #[test]
+    fn test_registry() -> Result<()> {
+        let bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.add_file(InMemoryFile::from("a,b").with_name("level.csv"))?;
+        bp.add_file(InMemoryFile::from("\u{0}").with_name("broken.csv"))?;
+
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut registry = AssetRegistry::new(&bp);
+        let counter = loads.clone();
+        registry.register(move |_, bytes| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let s = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+            if s.contains('\u{0}') {
+                return Err("nul in level".to_string());
+            }
+            Ok(Level(s.split(',').map(str::to_string).collect()))
+        });
+
+        let level = registry.get::<Level>("level.csv")?;
+        assert_eq!(*level, Level(vec!["a".to_string(), "b".to_string()]));
+        assert!(Arc::ptr_eq(&level, &registry.get::<Level>("level.csv")?));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        registry.invalidate("level.csv");
+        assert!(!registry.is_cached::<Level>("level.csv"));
+        registry.get::<Level>("level.csv")?;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(registry.get::<Level>("broken.csv"), Err(PackError::AssetLoad { .. })));
+        assert!(matches!(registry.get::<String>("level.csv"), Err(PackError::NoLoader(_))));
+        assert!(matches!(registry.get::<Level>("missing.csv"), Err(PackError::FileNotFound(_))));
+
+        Ok(())
+    }
+}
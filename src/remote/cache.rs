@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use parking_lot::Mutex;
+use crate::error;
+use crate::error::{AtPath, PackError};
+use crate::remote::RangeSource;
+
+/// The directory in the root passed to [`DiskCache::new`] the cache keeps its files in.
+pub const CACHE_DIR: &str = "backpack-cache";
+
+/// Marks [`CACHE_DIR`] as a cache, so nothing else is ever cleaned up. Its contents follow the
+/// cache directory tagging convention, so backup tools skip the cache too.
+const MARKER: &str = "CACHEDIR.TAG";
+const MARKER_CONTENTS: &str = "Signature: 8a477f597d28d172789f06886806bc55\n# This directory is a cache of backpack (https://github.com/jonay2000/backpack).\n";
+
+struct CachedFile {
+    size: u64,
+    last_used: SystemTime,
+}
+
+struct CacheState {
+    /// every file in the cache directory, of every source sharing it
+    files: HashMap<PathBuf, CachedFile>,
+    total_size: u64,
+    /// ranges cached for our source: offset to the longest length cached at that offset,
+    /// and whether that range runs up to the end of the source
+    ranges: BTreeMap<u64, (u64, bool)>,
+}
+
+/// A persistent read-through cache on disk for a [`RangeSource`], so data fetched from
+/// remote storage doesn't have to be fetched again in the next session.
+///
+/// The cache lives in a [`CACHE_DIR`] directory in the root it's given, so the root can be shared
+/// with other programs, like `~/.cache`. Multiple sources can share a cache, each gets its own
+/// subdirectory based on its key. When the cache grows past its size limit, the least recently
+/// used ranges are evicted. Only files the cache wrote itself are ever removed.
+pub struct DiskCache<S> {
+    source: S,
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Mutex<CacheState>,
+}
+
+/// Stable across runs and platforms, unlike the standard library hasher
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn range_file_name(offset: u64, length: u64, at_end: bool) -> String {
+    format!("{:016x}-{:016x}{}", offset, length, if at_end { "-end" } else { "" })
+}
+
+/// Whether `name` is the name of the subdirectory of a source, see [`DiskCache::new`].
+fn is_source_dir_name(name: &std::ffi::OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.len() == 16 && name.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn parse_range_file_name(path: &Path) -> Option<(u64, u64, bool)> {
+    let name = path.file_name()?.to_str()?;
+    let (name, at_end) = match name.strip_suffix("-end") {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let (offset, length) = name.split_once('-')?;
+    Some((u64::from_str_radix(offset, 16).ok()?, u64::from_str_radix(length, 16).ok()?, at_end))
+}
+
+impl<S: RangeSource> DiskCache<S> {
+    /// Cache `source` in the [`CACHE_DIR`] in `root`, which is shared by all sources with a different `key`.
+    /// The key identifies the source across sessions, for example its url and version.
+    /// Fails with [`PackError::IncompatibleOptions`] when there's a [`CACHE_DIR`] in `root`
+    /// which isn't a cache.
+    pub fn new(source: S, root: impl AsRef<Path>, key: &str, max_bytes: u64) -> error::Result<Self> {
+        let root = root.as_ref().join(CACHE_DIR);
+        let marker = root.join(MARKER);
+        if !marker.exists() {
+            if fs::read_dir(&root).is_ok_and(|mut entries| entries.next().is_some()) {
+                return Err(PackError::IncompatibleOptions("the cache directory has files which aren't part of a cache"));
+            }
+            fs::create_dir_all(&root).at_path(&root)?;
+            fs::write(&marker, MARKER_CONTENTS).at_path(&marker)?;
+        }
+        let dir = root.join(format!("{:016x}", fnv1a(key.as_bytes())));
+        fs::create_dir_all(&dir).at_path(&dir)?;
+
+        let mut state = CacheState {
+            files: HashMap::new(),
+            total_size: 0,
+            ranges: BTreeMap::new(),
+        };
+
+        for source_dir in fs::read_dir(&root)? {
+            let source_dir = source_dir?;
+            if !source_dir.file_type()?.is_dir() || !is_source_dir_name(&source_dir.file_name()) {
+                continue;
+            }
+            let ours = source_dir.path() == dir;
+
+            for f in fs::read_dir(source_dir.path())? {
+                let f = f?;
+                let path = f.path();
+                let Some((offset, length, at_end)) = parse_range_file_name(&path) else {
+                    // unfinished writes from an earlier session. Other sources clean up their own,
+                    // they may be writing them right now
+                    if ours && path.extension().is_some_and(|extension| extension == "tmp") {
+                        let _ = fs::remove_file(&path);
+                    }
+                    continue;
+                };
+
+                let metadata = f.metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                if ours {
+                    state.add_range(offset, length, at_end);
+                }
+                state.total_size += metadata.len();
+                state.files.insert(path, CachedFile {
+                    size: metadata.len(),
+                    last_used: metadata.modified().unwrap_or(UNIX_EPOCH),
+                });
+            }
+        }
+
+        // the limit may be lower than in an earlier session
+        state.evict(&dir, max_bytes);
+
+        Ok(Self {
+            source,
+            dir,
+            max_bytes,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Total size of everything in the cache directory
+    pub fn cached_bytes(&self) -> u64 {
+        self.state.lock().total_size
+    }
+
+    fn lookup(&self, offset: u64, length: u64) -> Option<Vec<u8>> {
+        let mut state = self.state.lock();
+
+        // a range which runs up to the end of the source also answers
+        // any request which extends past the end
+        let (start, cached_length, at_end) = state.ranges.range(..=offset)
+            .rev()
+            .find(|(start, (cached_length, at_end))| {
+                let cached_end = *start + *cached_length;
+                offset + length <= cached_end || (*at_end && offset <= cached_end)
+            })
+            .map(|(start, (cached_length, at_end))| (*start, *cached_length, *at_end))?;
+        let path = self.dir.join(range_file_name(start, cached_length, at_end));
+
+        let read = || -> std::io::Result<Vec<u8>> {
+            let mut f = fs::File::open(&path)?;
+            f.seek(SeekFrom::Start(offset - start))?;
+            let mut data = vec![0; length.min(start + cached_length - offset) as usize];
+            f.read_exact(&mut data)?;
+            Ok(data)
+        };
+
+        match read() {
+            Ok(data) => {
+                let now = SystemTime::now();
+                if let Some(f) = state.files.get_mut(&path) {
+                    f.last_used = now;
+                }
+                // best effort, so the next session knows it was recently used
+                let _ = fs::File::options().write(true).open(&path).and_then(|f| f.set_modified(now));
+                Some(data)
+            }
+            Err(e) => {
+                log::warn!("removing unreadable range from backpack cache: {}", e);
+                state.remove(&self.dir, &path);
+                None
+            }
+        }
+    }
+
+    fn store(&self, offset: u64, data: &[u8], at_end: bool) -> error::Result<()> {
+        if data.is_empty() || data.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.dir.join(range_file_name(offset, data.len() as u64, at_end));
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut f = fs::File::create(&tmp_path)?;
+            f.write_all(data)?;
+        }
+        fs::rename(&tmp_path, &path)?;
+
+        let mut state = self.state.lock();
+        if let Some(old) = state.files.insert(path, CachedFile {
+            size: data.len() as u64,
+            last_used: SystemTime::now(),
+        }) {
+            state.total_size -= old.size;
+        }
+        state.total_size += data.len() as u64;
+        state.add_range(offset, data.len() as u64, at_end);
+        state.evict(&self.dir, self.max_bytes);
+
+        Ok(())
+    }
+}
+
+impl CacheState {
+    fn add_range(&mut self, offset: u64, length: u64, at_end: bool) {
+        let longest = self.ranges.entry(offset).or_insert((0, false));
+        if length >= longest.0 {
+            *longest = (length, at_end || longest.1);
+        }
+    }
+
+    fn remove(&mut self, dir: &Path, path: &Path) {
+        if let Some(f) = self.files.remove(path) {
+            self.total_size -= f.size;
+        }
+        let _ = fs::remove_file(path);
+
+        if path.parent() == Some(dir) {
+            if let Some((offset, length, _)) = parse_range_file_name(path) {
+                if self.ranges.get(&offset).map(|(l, _)| *l) == Some(length) {
+                    self.ranges.remove(&offset);
+                }
+            }
+        }
+    }
+
+    /// Remove the least recently used files until the cache fits in `max_bytes`
+    fn evict(&mut self, dir: &Path, max_bytes: u64) {
+        if self.total_size <= max_bytes {
+            return;
+        }
+
+        let mut by_age = self.files.iter()
+            .map(|(path, f)| (f.last_used, path.clone()))
+            .collect::<Vec<_>>();
+        by_age.sort();
+
+        for (_, path) in by_age {
+            if self.total_size <= max_bytes {
+                break;
+            }
+            self.remove(dir, &path);
+        }
+    }
+}
+
+impl<S: RangeSource> RangeSource for DiskCache<S> {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        if let Some(data) = self.lookup(offset, length) {
+            return Ok(data);
+        }
+
+        let data = self.source.read_range(offset, length)?;
+        // a broken cache shouldn't break reading
+        if let Err(e) = self.store(offset, &data, (data.len() as u64) < length) {
+            log::warn!("failed to write to backpack cache: {}", e);
+        }
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{self, Read};
+use once_cell::sync::OnceCell;
+use crate::error;
+use crate::error::PackError;
+use crate::remote::{RangeSource, RetryPolicy};
+
+/// Reads a file on a web server with `Range` requests, like a backpack opened with
+/// [`PackReader::open_url`](crate::pack::PackReader::open_url).
+/// Failed requests are retried with the default [`RetryPolicy`].
+pub struct HttpSource {
+    url: String,
+    agent: ureq::Agent,
+    retry: RetryPolicy,
+    len: OnceCell<u64>,
+}
+
+fn call(request: ureq::Request) -> error::Result<ureq::Response> {
+    match request.call() {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, _)) => Err(PackError::HttpStatus(status)),
+        Err(ureq::Error::Transport(e)) => Err(PackError::Network(e.to_string())),
+    }
+}
+
+impl HttpSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::Agent::new(),
+            retry: RetryPolicy::default(),
+            len: OnceCell::new(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// The length of the file, asked for with a `HEAD` request the first time.
+    pub fn len(&self) -> error::Result<u64> {
+        self.len.get_or_try_init(|| self.retry.run(|| self.fetch_len())).copied()
+    }
+
+    pub fn is_empty(&self) -> error::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn fetch_len(&self) -> error::Result<u64> {
+        let response = call(self.agent.head(&self.url))?;
+        if let Some(len) = response.header("content-length").and_then(|len| len.trim().parse().ok()) {
+            return Ok(len);
+        }
+
+        // no length in the response to HEAD, the total size of a range has it too
+        let response = call(self.agent.get(&self.url).set("range", "bytes=0-0"))?;
+        response.header("content-range")
+            .and_then(|range| range.rsplit_once('/')?.1.trim().parse().ok())
+            .ok_or_else(|| PackError::Network(format!("{} didn't send its length", self.url)))
+    }
+
+    fn get_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        let range = format!("bytes={}-{}", offset, offset + length - 1);
+        let response = match call(self.agent.get(&self.url).set("range", &range)) {
+            Ok(response) => response,
+            // the range starts after the end of the file
+            Err(PackError::HttpStatus(416)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        // servers may ignore the range and send the whole file
+        let skip = if response.status() == 206 { 0 } else { offset };
+        let mut reader = response.into_reader();
+        io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+
+        let mut data = Vec::new();
+        reader.take(length).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl RangeSource for HttpSource {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        // a ranged GET is idempotent, so a half-read response can just be requested again
+        self.retry.run(|| self.get_range(offset, length))
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, ErrorKind};
+use parking_lot::Mutex;
+use crate::error;
+use crate::pack::Storage;
+use crate::remote::{S3Config, S3Writer, DEFAULT_PART_SIZE};
+
+/// An object being uploaded to S3-compatible storage, as [`Storage`] to write a backpack to
+/// with a [`PackWriter`](crate::pack::PackWriter), without a temporary file. Writes are
+/// uploaded in parts as they come in, except for the first part, which is kept in memory until
+/// the end so the header of the backpack can still be written. Writing anywhere else which was
+/// written before fails, and nothing can be read back.
+///
+/// [Syncing](crate::RawFile::sync_all) the file completes the upload, after which it can't be
+/// written to anymore. An upload which wasn't completed is aborted when it's dropped.
+///
+/// ```no_run
+/// # use backpack::pack::{PackError, PackWriter};
+/// # use backpack::remote::{S3Config, S3Upload};
+/// # use backpack::RawFile;
+/// # fn main() -> Result<(), PackError> {
+/// # let config: S3Config = todo!();
+/// let mut writer = PackWriter::new(RawFile::from_storage(S3Upload::new(config, "artifacts/build.bp")?))?;
+/// writer.add_entry("manifest.json", "{}".as_bytes())?;
+/// writer.finish()?.sync_all()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct S3Upload {
+    /// `None` once the upload is complete
+    writer: Mutex<Option<S3Writer>>,
+    /// the first part, uploaded last
+    first: Vec<u8>,
+    /// what was written after the first part and isn't uploaded yet
+    buffer: Vec<u8>,
+    /// etags of the parts after the first
+    parts: Vec<String>,
+    len: u64,
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::Unsupported, message.to_string())
+}
+
+impl S3Upload {
+    pub fn new(config: S3Config, key: impl Into<String>) -> error::Result<Self> {
+        Self::with_part_size(config, key, DEFAULT_PART_SIZE)
+    }
+
+    /// Upload in parts of `part_size` bytes, which S3 wants to be at least 5 MiB.
+    pub fn with_part_size(config: S3Config, key: impl Into<String>, part_size: usize) -> error::Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(Some(S3Writer::with_part_size(config, key, part_size)?)),
+            first: Vec::new(),
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            len: 0,
+        })
+    }
+
+    fn append(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        let writer = self.writer.get_mut().as_ref().ok_or_else(|| unsupported("the upload is complete"))?;
+        let part_size = writer.part_size();
+
+        let n = buf.len().min(part_size - self.first.len());
+        self.first.extend_from_slice(&buf[..n]);
+        buf = &buf[n..];
+
+        while !buf.is_empty() {
+            let n = buf.len().min(part_size - self.buffer.len());
+            self.buffer.extend_from_slice(&buf[..n]);
+            buf = &buf[n..];
+            if self.buffer.len() == part_size {
+                // the first part is number 1
+                let etag = writer.put_part(self.parts.len() + 2, &self.buffer)?;
+                self.parts.push(etag);
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for S3Upload {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(unsupported("an upload can't be read"))
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        if offset == self.len {
+            self.append(buf)?;
+            self.len += buf.len() as u64;
+            return Ok(buf.len());
+        }
+
+        let end = offset.checked_add(buf.len() as u64).ok_or_else(|| unsupported("write past the end"))?;
+        if offset > self.len || end > self.first.len() as u64 {
+            return Err(unsupported("only the first part of an upload can be written again"));
+        }
+        self.first[offset as usize..end as usize].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        match size == self.len {
+            true => Ok(()),
+            false => Err(unsupported("an upload can't be resized")),
+        }
+    }
+
+    /// Upload the rest and complete the upload.
+    fn sync(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock();
+        let Some(upload) = writer.as_mut() else {
+            return Ok(());
+        };
+
+        let mut parts = vec![upload.put_part(1, &self.first)?];
+        parts.extend(self.parts.iter().cloned());
+        if !self.buffer.is_empty() {
+            parts.push(upload.put_part(parts.len() + 1, &self.buffer)?);
+        }
+        upload.complete(&parts)?;
+        *writer = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use crate::error;
+use crate::error::PackError;
+use crate::remote::{RangeReader, RangeSource};
+use crate::BackPack;
+use crate::pack::Index;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::pack::{COMPRESSION_FIELD, ENCRYPTION_FIELD, PACK_VERSION};
+use crate::pack::{chunk_channel, ChunkReceiver};
+
+/// How much is read at once while parsing the header and table of contents.
+/// Covers the header and the first 15 toc blocks.
+pub(crate) const INDEX_READ_AHEAD: u64 = 64 * 1024;
+
+/// How [`RemoteBackPack::read_files`] merges the ranges of multiple files into fewer requests.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Coalescing {
+    /// Ranges at most this many bytes apart are fetched in one request, the gap is downloaded and discarded.
+    pub max_gap: u64,
+    /// Ranges are not merged into requests larger than this.
+    pub max_request_size: u64,
+}
+
+impl Default for Coalescing {
+    fn default() -> Self {
+        Self {
+            max_gap: 64 * 1024,
+            max_request_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// A read-only backpack in remote storage. Opening it only fetches the table of contents,
+/// files are fetched when they are read.
+pub struct RemoteBackPack<S> {
+    source: S,
+    /// absolute offset and length of every file
+    entries: HashMap<String, (u64, u64)>,
+    hidden: HashSet<String>,
+    coalescing: Coalescing,
+}
+
+impl<S: RangeSource> RemoteBackPack<S> {
+    pub fn open(source: S) -> error::Result<Self> {
+        let mut reader = RangeReader::new(&source, INDEX_READ_AHEAD);
+        let Index { offsets, mut toc_blocks, hidden, compressed, encrypted, .. } = BackPack::parse_headers(&mut reader)?;
+        toc_blocks.sort();
+        // files are read as ranges of the source, which doesn't work for compressed or encrypted files
+        if !compressed.is_empty() {
+            return Err(PackError::UnsupportedIndexField(COMPRESSION_FIELD));
+        }
+        if !encrypted.is_empty() {
+            return Err(PackError::UnsupportedIndexField(ENCRYPTION_FIELD));
+        }
+
+        let entries = offsets.into_iter()
+            .map(|(name, (offset, length))| (name, (BackPack::convert_offset(&toc_blocks, offset), length)))
+            .collect();
+
+        Ok(Self {
+            source,
+            entries,
+            hidden,
+            coalescing: Coalescing::default(),
+        })
+    }
+
+    pub fn set_coalescing(&mut self, coalescing: Coalescing) {
+        self.coalescing = coalescing;
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Names of all files in the backpack, sorted by name.
+    /// [Hidden](BackPack::set_hidden) files are left out.
+    pub fn file_names(&self) -> Vec<String> {
+        self.file_names_with(false)
+    }
+
+    /// Names of all files in the backpack, sorted by name,
+    /// including [hidden](BackPack::set_hidden) files if `include_hidden` is set.
+    pub fn file_names_with(&self, include_hidden: bool) -> Vec<String> {
+        let mut res = self.entries.keys()
+            // directories
+            .filter(|name| !name.ends_with('/'))
+            .filter(|name| include_hidden || !self.hidden.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    pub fn is_hidden(&self, name: impl AsRef<Path>) -> bool {
+        self.hidden.contains(name.as_ref().to_string_lossy().as_ref())
+    }
+
+    /// Describe every file in the backpack.
+    pub fn manifest(&self) -> Manifest {
+        let entries = self.entries.iter()
+            .map(|(name, (offset, length))| ManifestEntry::new(name.clone(), Some(*offset), *length))
+            .collect();
+
+        Manifest::new(PACK_VERSION, entries)
+    }
+
+    pub fn file_size(&self, name: impl AsRef<Path>) -> error::Result<u64> {
+        Ok(self.location(name.as_ref())?.1)
+    }
+
+    /// Fetch the contents of a file.
+    pub fn read_file(&self, name: impl AsRef<Path>) -> error::Result<Vec<u8>> {
+        let (offset, length) = self.location(name.as_ref())?;
+
+        let data = self.source.read_range(offset, length)?;
+        if data.len() as u64 != length {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(data)
+    }
+
+    /// Fetch a file in chunks of `chunk_size` bytes on a background thread, which stays at most
+    /// `capacity` chunks ahead of the receiver. Meant for media which is consumed as it plays,
+    /// share the source with an [`Arc`](std::sync::Arc) to make it cheap to clone.
+    pub fn stream_file(&self, name: impl AsRef<Path>, chunk_size: usize, capacity: usize) -> error::Result<ChunkReceiver>
+    where S: Clone + Send + 'static {
+        let (offset, length) = self.location(name.as_ref())?;
+        let source = self.source.clone();
+        let chunk_size = chunk_size.max(1) as u64;
+        let (sender, receiver) = chunk_channel(capacity);
+
+        std::thread::spawn(move || {
+            let mut position = 0;
+            while position < length {
+                let chunk_length = chunk_size.min(length - position);
+                let chunk = source.read_range(offset + position, chunk_length)
+                    .and_then(|chunk| if chunk.len() as u64 == chunk_length {
+                        Ok(chunk)
+                    } else {
+                        Err(std::io::Error::from(ErrorKind::UnexpectedEof).into())
+                    });
+
+                let failed = chunk.is_err();
+                if !sender.send(chunk) || failed {
+                    break;
+                }
+                position += chunk_length;
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    /// Fetch the contents of multiple files, in the same order as `names`. Files stored close
+    /// to each other are fetched with a single request, see [`Coalescing`].
+    pub fn read_files(&self, names: &[impl AsRef<Path>]) -> error::Result<Vec<Vec<u8>>> {
+        let locations = names.iter()
+            .map(|name| self.location(name.as_ref()))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let mut res = vec![Vec::new(); names.len()];
+        for (start, length, files) in coalesce(&locations, self.coalescing) {
+            let data = self.source.read_range(start, length)?;
+
+            for i in files {
+                let (offset, length) = locations[i];
+                let file_start = (offset - start) as usize;
+                let file_end = file_start + length as usize;
+                if file_end > data.len() {
+                    return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+                }
+                res[i] = data[file_start..file_end].to_vec();
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Download only the given files and write them to `out` as a standalone pack.
+    /// Files keep the order they have in the remote pack.
+    pub fn export_subset(&self, names: &[impl AsRef<Path>], out: &mut impl Write) -> error::Result<()> {
+        let mut names = names.iter()
+            .map(|name| name.as_ref().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names.sort_by_key(|name| self.entries.get(name).map(|(offset, _)| *offset));
+
+        let contents = self.read_files(&names)?;
+        let entries = names.iter()
+            .zip(&contents)
+            .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+            .collect::<Vec<_>>();
+
+        BackPack::write_native(out, &entries, |_| 1, &HashMap::new(), &self.hidden, &HashMap::new(), &HashSet::new())?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn location(&self, name: &Path) -> error::Result<(u64, u64)> {
+        self.entries.get(name.to_string_lossy().as_ref())
+            .copied()
+            .ok_or_else(|| PackError::FileNotFound(name.to_path_buf()))
+    }
+}
+
+/// Merge ranges into requests of `(start, length, indices of the ranges it covers)`.
+pub(crate) fn coalesce(ranges: &[(u64, u64)], coalescing: Coalescing) -> Vec<(u64, u64, Vec<usize>)> {
+    let mut order = (0..ranges.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| ranges[*i].0);
+
+    let mut res: Vec<(u64, u64, Vec<usize>)> = Vec::new();
+    for i in order {
+        let (offset, length) = ranges[i];
+
+        if let Some((start, current_length, files)) = res.last_mut() {
+            let end = *start + *current_length;
+            let new_end = end.max(offset + length);
+
+            if offset <= end + coalescing.max_gap && new_end - *start <= coalescing.max_request_size {
+                *current_length = new_end - *start;
+                files.push(i);
+                continue;
+            }
+        }
+
+        res.push((offset, length, vec![i]));
+    }
+
+    res
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::error;
+use crate::error::PackError;
+use crate::remote::RangeSource;
+
+/// Kinds of failures a [`RetryPolicy`] can decide to retry on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The connection couldn't be made or broke down
+    Network,
+    /// The request timed out or was interrupted
+    Timeout,
+    /// The server asked us to slow down (429 or 503)
+    Throttled,
+    /// Any other server error (5xx)
+    ServerError,
+    /// Errors which won't go away by trying again, like a missing object or bad credentials
+    Permanent,
+}
+
+impl ErrorClass {
+    pub fn of(e: &PackError) -> Self {
+        match e {
+            PackError::Network(_) => ErrorClass::Network,
+            PackError::HttpStatus(429 | 503) => ErrorClass::Throttled,
+            PackError::HttpStatus(500..=599) => ErrorClass::ServerError,
+            PackError::Io(e) => match e.kind() {
+                ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock => ErrorClass::Timeout,
+                ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected | ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof => ErrorClass::Network,
+                _ => ErrorClass::Permanent,
+            },
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// When and how often to retry failed requests to remote storage, or failed reads
+/// of a backpack's file, see [`BackPack::set_read_retry`](crate::BackPack::set_read_retry).
+/// Only use it for idempotent requests, which have the same effect when sent twice.
+///
+/// The delay before retry `n` is `base_delay * 2^n`, capped at `max_delay`.
+/// With jitter, a random delay between zero and that is used instead, so many
+/// clients failing at once don't all retry at the same moment.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    pub retry_on: HashSet<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retry_on: [ErrorClass::Network, ErrorClass::Timeout, ErrorClass::Throttled, ErrorClass::ServerError]
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// xorshift, seeded from the clock. Good enough to spread out retries.
+fn random() -> u64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+
+    let mut x = STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1) | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn should_retry(&self, e: &PackError) -> bool {
+        self.retry_on.contains(&ErrorClass::of(e))
+    }
+
+    /// How long to wait after attempt `attempt` (starting at 0) failed
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        if self.jitter {
+            let nanos = delay.as_nanos() as u64;
+            Duration::from_nanos(random() % nanos.saturating_add(1))
+        } else {
+            delay
+        }
+    }
+
+    /// Run `f` until it succeeds, fails with an error that shouldn't be retried,
+    /// or runs out of attempts.
+    pub fn run<T>(&self, mut f: impl FnMut() -> error::Result<T>) -> error::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt + 1 < self.max_attempts && self.should_retry(&e) => {
+                    let delay = self.delay(attempt);
+                    log::debug!("retrying in {:?} after: {}", delay, e);
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Retries failed reads of a [`RangeSource`] according to a [`RetryPolicy`].
+/// Reading a range is idempotent, so it's always safe to retry.
+pub struct Retrying<S> {
+    source: S,
+    policy: RetryPolicy,
+}
+
+impl<S: RangeSource> Retrying<S> {
+    pub fn new(source: S, policy: RetryPolicy) -> Self {
+        Self { source, policy }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+impl<S: RangeSource> RangeSource for Retrying<S> {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        self.policy.run(|| self.source.read_range(offset, length))
+    }
+}
@@ -0,0 +1,390 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+use crate::error;
+use crate::error::PackError;
+use crate::remote::{RangeSource, RetryPolicy};
+
+/// Default size of the parts of a multipart upload. S3 requires at least 5 MiB for every part but the last.
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Where a bucket lives and how to authenticate to it. Works with any S3-compatible storage,
+/// objects are addressed path-style as `{endpoint}/{bucket}/{key}`.
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    /// for example `https://s3.eu-west-1.amazonaws.com`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut res = String::with_capacity(data.len() * 2);
+    for b in data {
+        let _ = write!(res, "{:02x}", b);
+    }
+    res
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything except unreserved characters, and `/` when `keep_slash` is set.
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => res.push(b as char),
+            b'/' if keep_slash => res.push('/'),
+            b => {
+                let _ = write!(res, "%{:02X}", b);
+            }
+        }
+    }
+    res
+}
+
+/// `(yyyymmdd, yyyymmddThhmmssZ)` for a time
+fn amz_dates(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let date_time = format!("{}T{:02}{:02}{:02}Z", date, rem / 3600, rem % 3600 / 60, rem % 60);
+    (date, date_time)
+}
+
+/// A request signed with AWS signature version 4
+pub(crate) struct SignedRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl S3Config {
+    fn host(&self) -> &str {
+        let without_scheme = self.endpoint.split_once("://").map(|(_, rest)| rest).unwrap_or(&self.endpoint);
+        without_scheme.trim_end_matches('/')
+    }
+
+    fn sign(&self, method: &str, key: &str, query: &[(&str, &str)], extra_headers: &[(&str, &str)], payload: &[u8], time: SystemTime) -> SignedRequest {
+        let path = format!("/{}/{}", self.bucket, key.trim_start_matches('/'));
+        self.sign_path(method, &path, query, extra_headers, payload, time)
+    }
+
+    pub(crate) fn sign_path(&self, method: &str, path: &str, query: &[(&str, &str)], extra_headers: &[(&str, &str)], payload: &[u8], time: SystemTime) -> SignedRequest {
+        let (date, date_time) = amz_dates(time);
+        let payload_hash = sha256_hex(payload);
+        let canonical_uri = uri_encode(path, true);
+
+        let mut query = query.iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let canonical_query = query.iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut headers = vec![
+            ("host".to_string(), self.host().to_string()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), date_time.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        for (k, v) in extra_headers {
+            headers.push((k.to_lowercase(), v.trim().to_string()));
+        }
+        headers.sort();
+
+        let canonical_headers = headers.iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect::<String>();
+        let signed_headers = headers.iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", date_time, scope, sha256_hex(canonical_request.as_bytes()));
+
+        let k_date = hmac(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        let k_region = hmac(&k_date, self.region.as_bytes());
+        let k_service = hmac(&k_region, b"s3");
+        let k_signing = hmac(&k_service, b"aws4_request");
+        let signature = hex(&hmac(&k_signing, string_to_sign.as_bytes()));
+
+        headers.push(("authorization".to_string(), format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        )));
+        // the http client sets the host itself
+        headers.retain(|(k, _)| k != "host");
+
+        let mut url = format!("{}{}", self.endpoint.trim_end_matches('/'), canonical_uri);
+        if !canonical_query.is_empty() {
+            url.push('?');
+            url.push_str(&canonical_query);
+        }
+
+        SignedRequest { url, headers }
+    }
+
+    fn send(&self, agent: &ureq::Agent, method: &str, request: SignedRequest, body: &[u8]) -> error::Result<ureq::Response> {
+        let mut r = agent.request(method, &request.url);
+        for (k, v) in &request.headers {
+            r = r.set(k, v);
+        }
+
+        match r.send_bytes(body) {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(status, _)) => Err(PackError::HttpStatus(status)),
+            Err(ureq::Error::Transport(e)) => Err(PackError::Network(e.to_string())),
+        }
+    }
+}
+
+/// Reads an object in S3-compatible storage with ranged GET requests.
+/// Open it as a pack with [`RemoteBackPack`](crate::remote::RemoteBackPack).
+/// Failed requests are retried with the default [`RetryPolicy`].
+pub struct S3Source {
+    config: S3Config,
+    key: String,
+    agent: ureq::Agent,
+    retry: RetryPolicy,
+    len: OnceCell<u64>,
+}
+
+impl S3Source {
+    pub fn new(config: S3Config, key: impl Into<String>) -> Self {
+        Self {
+            config,
+            key: key.into(),
+            agent: ureq::Agent::new(),
+            retry: RetryPolicy::default(),
+            len: OnceCell::new(),
+        }
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// The length of the object, asked for with a `HEAD` request the first time.
+    pub fn len(&self) -> error::Result<u64> {
+        self.len.get_or_try_init(|| self.retry.run(|| {
+            let request = self.config.sign("HEAD", &self.key, &[], &[], b"", SystemTime::now());
+            let response = self.config.send(&self.agent, "HEAD", request, b"")?;
+            response.header("content-length")
+                .and_then(|len| len.trim().parse().ok())
+                .ok_or_else(|| PackError::Network("no length in response to HEAD".to_string()))
+        })).copied()
+    }
+
+    pub fn is_empty(&self) -> error::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn get_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        let range = format!("bytes={}-{}", offset, offset + length - 1);
+        let request = self.config.sign("GET", &self.key, &[], &[("range", &range)], b"", SystemTime::now());
+
+        let response = match self.config.send(&self.agent, "GET", request, b"") {
+            Ok(response) => response,
+            // the range starts after the end of the object
+            Err(PackError::HttpStatus(416)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        // servers may ignore the range and send the whole object
+        let skip = if response.status() == 206 { 0 } else { offset };
+        let mut reader = response.into_reader();
+        io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+
+        let mut data = Vec::new();
+        reader.take(length).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl RangeSource for S3Source {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        // a ranged GET is idempotent, so a half-read response can just be requested again
+        self.retry.run(|| self.get_range(offset, length))
+    }
+}
+
+/// Uploads an object to S3-compatible storage with a multipart upload, one part at a time,
+/// so an object of any size can be written without holding it in memory.
+/// Call [`finish`](Self::finish) to complete the upload; dropping the writer aborts it.
+///
+/// Only part uploads are retried: uploading the same part twice just replaces it,
+/// but starting or completing an upload twice is not safe.
+pub struct S3Writer {
+    config: S3Config,
+    key: String,
+    agent: ureq::Agent,
+    retry: RetryPolicy,
+
+    upload_id: String,
+    part_size: usize,
+    buffer: Vec<u8>,
+    /// etag of every uploaded part
+    parts: Vec<String>,
+    finished: bool,
+}
+
+impl S3Writer {
+    pub fn new(config: S3Config, key: impl Into<String>) -> error::Result<Self> {
+        Self::with_part_size(config, key, DEFAULT_PART_SIZE)
+    }
+
+    pub fn with_part_size(config: S3Config, key: impl Into<String>, part_size: usize) -> error::Result<Self> {
+        let key = key.into();
+        let agent = ureq::Agent::new();
+
+        let request = config.sign("POST", &key, &[("uploads", "")], &[], b"", SystemTime::now());
+        let response = config.send(&agent, "POST", request, b"")?.into_string()?;
+        let upload_id = xml_element(&response, "UploadId")
+            .ok_or_else(|| PackError::Network("no upload id in response to starting a multipart upload".to_string()))?
+            .to_string();
+
+        Ok(Self {
+            config,
+            key,
+            agent,
+            retry: RetryPolicy::default(),
+            upload_id,
+            part_size,
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            finished: false,
+        })
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub(crate) fn part_size(&self) -> usize {
+        self.part_size
+    }
+
+    /// Upload `body` as part `part_number`, counting from 1, and return its etag.
+    pub(crate) fn put_part(&self, part_number: usize, body: &[u8]) -> error::Result<String> {
+        let part_number = part_number.to_string();
+        let query = [("partNumber", part_number.as_str()), ("uploadId", self.upload_id.as_str())];
+        let response = self.retry.run(|| {
+            let request = self.config.sign("PUT", &self.key, &query, &[], body, SystemTime::now());
+            self.config.send(&self.agent, "PUT", request, body)
+        })?;
+
+        Ok(response.header("etag")
+            .ok_or_else(|| PackError::Network("no etag in response to uploading a part".to_string()))?
+            .to_string())
+    }
+
+    fn upload_part(&mut self) -> error::Result<()> {
+        let body = std::mem::take(&mut self.buffer);
+        let etag = self.put_part(self.parts.len() + 1, &body)?;
+        self.parts.push(etag);
+        Ok(())
+    }
+
+    /// Upload what's left and complete the upload, making the object visible.
+    pub fn finish(mut self) -> error::Result<()> {
+        if !self.buffer.is_empty() || self.parts.is_empty() {
+            self.upload_part()?;
+        }
+        let parts = std::mem::take(&mut self.parts);
+        self.complete(&parts)
+    }
+
+    /// Complete the upload with the parts with etags `parts`, in order, making the object visible.
+    pub(crate) fn complete(&mut self, parts: &[String]) -> error::Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (i, etag) in parts.iter().enumerate() {
+            let _ = write!(body, "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", i + 1, etag);
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let request = self.config.sign("POST", &self.key, &[("uploadId", &self.upload_id)], &[], body.as_bytes(), SystemTime::now());
+        let response = self.config.send(&self.agent, "POST", request, body.as_bytes())?.into_string()?;
+        // S3 can report errors in the body of a successful response
+        if xml_element(&response, "Code").is_some() {
+            return Err(PackError::Network(format!("completing the multipart upload failed: {}", response)));
+        }
+
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for S3Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.part_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+
+        if self.buffer.len() >= self.part_size {
+            self.upload_part().map_err(Into::<io::Error>::into)?;
+        }
+
+        Ok(n)
+    }
+
+    /// Parts can only be uploaded once they are large enough, so flushing does nothing.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for S3Writer {
+    fn drop(&mut self) {
+        if !self.finished {
+            let request = self.config.sign("DELETE", &self.key, &[("uploadId", &self.upload_id)], &[], b"", SystemTime::now());
+            if let Err(e) = self.config.send(&self.agent, "DELETE", request, b"") {
+                log::warn!("failed to abort unfinished multipart upload of {}: {}", self.key, e);
+            }
+        }
+    }
+}
+
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
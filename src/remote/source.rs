@@ -0,0 +1,163 @@
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use parking_lot::Mutex;
+use crate::error;
+use crate::pack::Storage;
+
+/// Storage which can only be read in ranges, like a file on a web server or in a bucket.
+pub trait RangeSource {
+    /// Read `length` bytes starting at `offset`. Returns fewer bytes only
+    /// when the range extends past the end of the source.
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>>;
+}
+
+impl RangeSource for [u8] {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        let start = (offset as usize).min(self.len());
+        let end = (offset.saturating_add(length) as usize).min(self.len());
+        Ok(self[start..end].to_vec())
+    }
+}
+
+impl RangeSource for Vec<u8> {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        self.as_slice().read_range(offset, length)
+    }
+}
+
+impl<S: RangeSource + ?Sized> RangeSource for &S {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        (**self).read_range(offset, length)
+    }
+}
+
+impl<S: RangeSource + ?Sized> RangeSource for Box<S> {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        (**self).read_range(offset, length)
+    }
+}
+
+impl<S: RangeSource + ?Sized> RangeSource for Arc<S> {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        (**self).read_range(offset, length)
+    }
+}
+
+/// Reads a [`RangeSource`] like a file. Reads are done in chunks of at least `read_ahead`
+/// bytes, so parsing small headers doesn't turn into a request per field.
+pub struct RangeReader<S> {
+    source: S,
+    position: u64,
+    read_ahead: u64,
+
+    buffer_start: u64,
+    buffer: Vec<u8>,
+}
+
+impl<S: RangeSource> RangeReader<S> {
+    pub fn new(source: S, read_ahead: u64) -> Self {
+        Self {
+            source,
+            position: 0,
+            read_ahead,
+            buffer_start: 0,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<S: RangeSource> Read for RangeReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            let length = (buf.len() as u64).max(self.read_ahead);
+            self.buffer = self.source.read_range(self.position, length).map_err(Into::<io::Error>::into)?;
+            self.buffer_start = self.position;
+        }
+
+        let start = (self.position - self.buffer_start) as usize;
+        let available = &self.buffer[start.min(self.buffer.len())..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<S: RangeSource> Seek for RangeReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(p) => p,
+            SeekFrom::Current(d) => self.position.checked_add_signed(d)
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "seek to a negative position"))?,
+            SeekFrom::End(_) => return Err(io::Error::new(ErrorKind::Unsupported, "the length of a range source is unknown")),
+        };
+        Ok(self.position)
+    }
+}
+
+/// A [`RangeSource`] of known length as read-only [`Storage`], to open it as a
+/// [`RawFile`](crate::RawFile). Like [`RangeReader`], small reads fetch at least `read_ahead`
+/// bytes and are served from those until a read falls outside of them.
+pub struct RangeStorage<S> {
+    source: S,
+    len: u64,
+    read_ahead: u64,
+    /// offset and contents of the last range fetched
+    buffer: Mutex<(u64, Vec<u8>)>,
+}
+
+impl<S: RangeSource> RangeStorage<S> {
+    pub fn new(source: S, len: u64, read_ahead: u64) -> Self {
+        Self {
+            source,
+            len,
+            read_ahead,
+            buffer: Mutex::new((0, Vec::new())),
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+impl<S: RangeSource + Send + Sync> Storage for RangeStorage<S> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // large reads, like whole entries, go straight to the source
+        if buf.len() as u64 >= self.read_ahead {
+            let data = self.source.read_range(offset, buf.len() as u64).map_err(Into::<io::Error>::into)?;
+            buf[..data.len()].copy_from_slice(&data);
+            return Ok(data.len());
+        }
+
+        let mut buffer = self.buffer.lock();
+        let (start, data) = &mut *buffer;
+        if offset < *start || offset >= *start + data.len() as u64 {
+            *data = self.source.read_range(offset, self.read_ahead).map_err(Into::<io::Error>::into)?;
+            *start = offset;
+        }
+
+        let available = &data[(offset - *start) as usize..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        Ok(n)
+    }
+
+    fn write_at(&mut self, _offset: u64, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "a range source is read-only"))
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn set_len(&mut self, _size: u64) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "a range source is read-only"))
+    }
+}
@@ -0,0 +1,58 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use crate::error;
+use crate::error::PackError;
+
+/// Marks the end of an executable with a backpack appended to it.
+pub const TRAILER_MAGIC: &[u8] = b"BACKPSFX";
+/// Offset of the pack (8 bytes), length of the pack (8 bytes) and the magic.
+pub const TRAILER_SIZE: u64 = 8 + 8 + TRAILER_MAGIC.len() as u64;
+
+/// Build a self-extracting executable: the `stub` executable followed by the backpack
+/// in `pack` and a trailer telling the stub where to find the pack.
+/// The stub can open the pack with [`BackPack::from_current_exe`](crate::BackPack::from_current_exe).
+///
+/// The output still has to be made executable by the caller.
+pub fn build(mut pack: impl Read, mut stub: impl Read, mut out: impl Write) -> error::Result<()> {
+    let pack_offset = io::copy(&mut stub, &mut out)?;
+    let pack_length = io::copy(&mut pack, &mut out)?;
+
+    out.write_all(&pack_offset.to_le_bytes())?;
+    out.write_all(&pack_length.to_le_bytes())?;
+    out.write_all(TRAILER_MAGIC)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Read the backpack appended to an executable built with [`build`].
+pub fn read_appended(mut f: impl Read + Seek) -> error::Result<Vec<u8>> {
+    let end = f.seek(SeekFrom::End(0))?;
+    if end < TRAILER_SIZE {
+        return Err(PackError::NoAppendedPack);
+    }
+    f.seek(SeekFrom::Start(end - TRAILER_SIZE))?;
+
+    let mut trailer = [0u8; TRAILER_SIZE as usize];
+    f.read_exact(&mut trailer)?;
+    if &trailer[16..] != TRAILER_MAGIC {
+        return Err(PackError::NoAppendedPack);
+    }
+
+    let mut offset_bytes = [0u8; 8];
+    offset_bytes.copy_from_slice(&trailer[0..8]);
+    let offset = u64::from_le_bytes(offset_bytes);
+
+    let mut length_bytes = [0u8; 8];
+    length_bytes.copy_from_slice(&trailer[8..16]);
+    let length = u64::from_le_bytes(length_bytes);
+
+    if offset.checked_add(length).is_none_or(|pack_end| pack_end > end - TRAILER_SIZE) {
+        return Err(PackError::NoAppendedPack);
+    }
+
+    f.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0; length as usize];
+    f.read_exact(&mut data)?;
+
+    Ok(data)
+}
@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use crate::error;
+use crate::remote::RangeSource;
+
+/// A backpack in a sealed, read-only shared memory file (memfd), which other processes
+/// can map without loading their own copy, for example an editor and its game preview.
+/// Pass the descriptor from [`share`](Self::share) to a child process and open it there
+/// with [`from_fd`](Self::from_fd). Read it with [`RemoteBackPack`](crate::remote::RemoteBackPack),
+/// which only copies the files that are read.
+pub struct SharedPack {
+    fd: OwnedFd,
+    ptr: *const u8,
+    len: usize,
+}
+
+// Safety: the mapping is read-only, and sealed so nobody can change it while it's mapped
+unsafe impl Send for SharedPack {}
+unsafe impl Sync for SharedPack {}
+
+const SEALS: libc::c_int = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+
+fn check(res: libc::c_int) -> error::Result<libc::c_int> {
+    if res < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(res)
+}
+
+impl SharedPack {
+    /// Copy the bytes of a backpack into a new shared memory file.
+    pub fn create(pack: &[u8]) -> error::Result<Self> {
+        // Safety: the name is a valid C string
+        let fd = check(unsafe {
+            libc::memfd_create(c"backpack".as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+        })?;
+        // Safety: memfd_create returned a new descriptor which nothing else owns
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(pack)?;
+
+        // Safety: the descriptor is valid as long as we own the file
+        check(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, SEALS) })?;
+        Self::from_fd(file.into())
+    }
+
+    /// Map a shared pack from a descriptor created by [`share`](Self::share).
+    /// Fails if the memory isn't sealed, since another process could change it under our feet.
+    pub fn from_fd(fd: OwnedFd) -> error::Result<Self> {
+        // Safety: we own the descriptor
+        let seals = check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GET_SEALS) })?;
+        if seals & libc::F_SEAL_WRITE == 0 || seals & libc::F_SEAL_SHRINK == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "shared pack is not sealed").into());
+        }
+
+        let len = File::from(fd.try_clone()?).metadata()?.len() as usize;
+        let ptr = if len == 0 {
+            std::ptr::null()
+        } else {
+            // Safety: maps `len` bytes of a file which is at least that long and can't shrink
+            let ptr = unsafe {
+                libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, fd.as_raw_fd(), 0)
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            ptr as *const u8
+        };
+
+        Ok(Self { fd, ptr, len })
+    }
+
+    /// A copy of the descriptor which is inherited by child processes, unlike the original.
+    /// Tell the child its number, for example in an environment variable.
+    pub fn share(&self) -> error::Result<OwnedFd> {
+        // Safety: dup doesn't copy the close-on-exec flag, the new descriptor is ours
+        let fd = check(unsafe { libc::dup(self.fd.as_raw_fd()) })?;
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // Safety: the mapping lives as long as self, and is sealed against changes
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl AsFd for SharedPack {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl RangeSource for SharedPack {
+    fn read_range(&self, offset: u64, length: u64) -> error::Result<Vec<u8>> {
+        self.as_bytes().read_range(offset, length)
+    }
+}
+
+impl Drop for SharedPack {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // Safety: unmaps what from_fd mapped, nothing borrows it anymore
+            unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use crate::error::Result;
+    use crate::remote::RemoteBackPack;
+    use crate::shared::SharedPack;
+    use crate::testing::FixtureBuilder;
+
+    #[test]
+    fn test_shared_pack() -> Result<()> {
+        let fixture = FixtureBuilder::new(3).entries(5).build()?;
+        let shared = SharedPack::create(&fixture.bytes)?;
+
+        // what a child process would do with the inherited descriptor
+        let child = SharedPack::from_fd(shared.share()?)?;
+        assert_eq!(child.as_bytes(), fixture.bytes.as_slice());
+        let bp = RemoteBackPack::open(&child)?;
+        for (name, contents) in &fixture.files {
+            assert_eq!(&bp.read_file(name)?, contents);
+        }
+
+        // sealed against changes
+        assert!(File::from(shared.share()?).write_all(b"x").is_err());
+
+        // unsealed memory is refused
+        let fd = unsafe { libc::memfd_create(c"unsealed".as_ptr(), libc::MFD_CLOEXEC) };
+        assert!(fd >= 0);
+        assert!(SharedPack::from_fd(unsafe { std::os::fd::FromRawFd::from_raw_fd(fd) }).is_err());
+
+        Ok(())
+    }
+}
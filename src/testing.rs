@@ -0,0 +1,287 @@
+use std::ops::RangeInclusive;
+use crate::error;
+use crate::pack::{OutputMode, PACK_HEADER_SIZE};
+use crate::{BackPack, InMemoryFile, RawFile};
+
+/// Small seeded random number generator (splitmix64), so fixtures are the same on every run.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn range(&mut self, range: RangeInclusive<u64>) -> u64 {
+        let span = range.end() - range.start();
+        if span == u64::MAX {
+            return self.next_u64();
+        }
+        range.start() + self.next_u64() % (span + 1)
+    }
+}
+
+/// How the sizes of generated files are distributed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SizeDistribution {
+    Fixed(usize),
+    Uniform { min: usize, max: usize },
+    /// Many small files and a few large ones, like most real asset collections.
+    LogUniform { min: usize, max: usize },
+}
+
+impl SizeDistribution {
+    fn sample(&self, rng: &mut Rng) -> usize {
+        match *self {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => rng.range(min as u64..=max.max(min) as u64) as usize,
+            SizeDistribution::LogUniform { min, max } => {
+                let (low, high) = (((min.max(1)) as f64).ln(), (max.max(min).max(1) as f64).ln());
+                let t = rng.next_u64() as f64 / u64::MAX as f64;
+                ((low + t * (high - low)).exp().round() as usize).clamp(min, max.max(min))
+            }
+        }
+    }
+}
+
+/// What generated files contain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Content {
+    /// Incompressible random bytes
+    Random,
+    /// Compressible, text-like bytes
+    Text,
+    Zeros,
+}
+
+/// Ways to damage a generated backpack, to test how readers handle broken files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// Damage the magic number
+    Magic,
+    /// Claim a version from the future
+    Version,
+    /// Make the first table of contents entry claim an impossibly long name
+    TocEntry,
+    /// Cut off this many bytes from the end
+    Truncate(usize),
+    /// Flip all bits of the byte at this offset
+    FlipByte(usize),
+}
+
+/// Generates backpacks for tests and benchmarks. Everything is derived from the seed,
+/// so the same builder always produces the same backpack.
+///
+/// ```
+/// # use backpack::testing::{FixtureBuilder, SizeDistribution};
+/// # use backpack::remote::RemoteBackPack;
+/// # fn main() -> Result<(), backpack::PackError> {
+/// let fixture = FixtureBuilder::new(7)
+///     .entries(20)
+///     .sizes(SizeDistribution::LogUniform { min: 1, max: 4096 })
+///     .build()?;
+///
+/// let bp = RemoteBackPack::open(fixture.bytes.clone())?;
+/// for (name, contents) in &fixture.files {
+///     assert_eq!(&bp.read_file(name)?, contents);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FixtureBuilder {
+    seed: u64,
+    entries: usize,
+    sizes: SizeDistribution,
+    content: Content,
+    max_depth: usize,
+    output_mode: OutputMode,
+    alignment: u64,
+    corruptions: Vec<Corruption>,
+}
+
+/// A generated backpack and the files it contains.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    pub bytes: Vec<u8>,
+    /// Names and contents of all files, in the order they were added
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+const WORDS: &[&str] = &["grass", "stone", "water", "player", "enemy", "sound", "level", "tile", "shader", "menu"];
+
+impl FixtureBuilder {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            entries: 16,
+            sizes: SizeDistribution::Uniform { min: 0, max: 1024 },
+            content: Content::Random,
+            max_depth: 2,
+            output_mode: OutputMode::Native,
+            alignment: 1,
+            corruptions: Vec::new(),
+        }
+    }
+
+    pub fn entries(mut self, entries: usize) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    pub fn sizes(mut self, sizes: SizeDistribution) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    pub fn content(mut self, content: Content) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// How many directories deep generated names go.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    pub fn alignment(mut self, alignment: u64) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Damage the backpack after it's written. Corruptions are applied in the order they're added.
+    pub fn corrupt(mut self, corruption: Corruption) -> Self {
+        self.corruptions.push(corruption);
+        self
+    }
+
+    fn name(&self, rng: &mut Rng, index: usize) -> String {
+        let depth = rng.range(0..=self.max_depth as u64);
+        let mut name = String::new();
+        for _ in 0..depth {
+            name.push_str(WORDS[rng.range(0..=WORDS.len() as u64 - 1) as usize]);
+            name.push('/');
+        }
+        // the index keeps names unique
+        name.push_str(&format!("{}-{}.bin", WORDS[index % WORDS.len()], index));
+        name
+    }
+
+    fn contents(&self, rng: &mut Rng, size: usize) -> Vec<u8> {
+        match self.content {
+            Content::Random => (0..size).map(|_| rng.next_u64() as u8).collect(),
+            Content::Zeros => vec![0; size],
+            Content::Text => {
+                let mut res = Vec::with_capacity(size + 8);
+                while res.len() < size {
+                    res.extend_from_slice(WORDS[rng.range(0..=WORDS.len() as u64 - 1) as usize].as_bytes());
+                    res.push(b' ');
+                }
+                res.truncate(size);
+                res
+            }
+        }
+    }
+
+    pub fn build(&self) -> error::Result<Fixture> {
+        let mut rng = Rng::new(self.seed);
+        let files = (0..self.entries)
+            .map(|i| {
+                let name = self.name(&mut rng, i);
+                let size = self.sizes.sample(&mut rng);
+                (name, self.contents(&mut rng, size))
+            })
+            .collect::<Vec<_>>();
+
+        let mut bp = BackPack::create(RawFile::InMemory(InMemoryFile::from(Vec::new())))?;
+        bp.set_output_mode(self.output_mode);
+        bp.set_alignment(self.alignment)?;
+        for (name, contents) in &files {
+            bp.add_file(InMemoryFile::from(contents.clone()).with_name(name))?;
+        }
+
+        let mut bytes = bp.close()?
+            .into_memory().ok().expect("fixture is built in memory")
+            .get_bytes().to_vec();
+
+        for corruption in &self.corruptions {
+            apply(&mut bytes, *corruption);
+        }
+
+        Ok(Fixture { bytes, files })
+    }
+}
+
+fn apply(bytes: &mut Vec<u8>, corruption: Corruption) {
+    let flip = |bytes: &mut Vec<u8>, at: usize| if let Some(b) = bytes.get_mut(at) {
+        *b ^= 0xff;
+    };
+
+    match corruption {
+        Corruption::Magic => flip(bytes, 0),
+        Corruption::Version => if bytes.len() >= 10 {
+            bytes[8..10].copy_from_slice(&u16::MAX.to_le_bytes());
+        },
+        Corruption::TocEntry => {
+            // first toc block starts after the header, its entries after filled and next
+            let at = PACK_HEADER_SIZE as usize + 10;
+            if bytes.len() >= at + 2 {
+                bytes[at..at + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+            }
+        }
+        Corruption::Truncate(n) => bytes.truncate(bytes.len().saturating_sub(n)),
+        Corruption::FlipByte(at) => flip(bytes, at),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{PackError, Result};
+    use crate::remote::RemoteBackPack;
+    use crate::testing::{Content, Corruption, FixtureBuilder, SizeDistribution};
+
+    #[test]
+    fn test_fixtures() -> Result<()> {
+        let builder = FixtureBuilder::new(1)
+            .entries(50)
+            .sizes(SizeDistribution::LogUniform { min: 1, max: 10_000 })
+            .content(Content::Text);
+        let fixture = builder.build()?;
+        assert_eq!(fixture.bytes, builder.build()?.bytes);
+        assert_ne!(fixture.bytes, builder.clone().content(Content::Random).build()?.bytes);
+
+        let bp = RemoteBackPack::open(fixture.bytes.clone())?;
+        assert_eq!(bp.file_names().len(), 50);
+        for (name, contents) in &fixture.files {
+            assert!((1..=10_000).contains(&contents.len()));
+            assert_eq!(&bp.read_file(name)?, contents);
+        }
+
+        let broken = |c| RemoteBackPack::open(builder.clone().corrupt(c).build().unwrap().bytes);
+        assert!(matches!(broken(Corruption::Magic), Err(PackError::BadMagic)));
+        assert!(matches!(broken(Corruption::Version), Err(PackError::Incompatible(_))));
+        assert!(broken(Corruption::TocEntry).is_err());
+
+        let truncated = RemoteBackPack::open(builder.clone().corrupt(Corruption::Truncate(10)).build()?.bytes)?;
+        let (last, _) = fixture.files.iter()
+            .max_by_key(|(name, _)| bp.manifest().entry(name).unwrap().offset)
+            .unwrap();
+        assert!(truncated.read_file(last).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+use crate::{BackPack, InMemoryFile};
+
+/// A read-only backpack exposed to javascript as `BackPack`.
+#[wasm_bindgen(js_name = BackPack)]
+pub struct JsBackPack {
+    inner: Option<BackPack<'static, 'static>>,
+}
+
+#[wasm_bindgen(js_class = BackPack)]
+impl JsBackPack {
+    /// Open a backpack from the contents of an `ArrayBuffer`.
+    #[wasm_bindgen(js_name = fromArrayBuffer)]
+    pub fn from_array_buffer(buffer: &ArrayBuffer) -> Result<JsBackPack, JsError> {
+        Self::from_bytes(Uint8Array::new(buffer).to_vec())
+    }
+
+    /// Download a backpack with `fetch` and open it.
+    #[wasm_bindgen(js_name = fromUrl)]
+    pub async fn from_url(url: String) -> Result<JsBackPack, JsValue> {
+        let window = web_sys::window().ok_or_else(|| JsError::new("no window available to fetch from"))?;
+
+        let response: Response = JsFuture::from(window.fetch_with_str(&url)).await?.dyn_into()?;
+        if !response.ok() {
+            return Err(JsError::new(&format!("failed to fetch {}: status {}", url, response.status())).into());
+        }
+
+        let buffer: ArrayBuffer = JsFuture::from(response.array_buffer()?).await?.dyn_into()?;
+        Ok(Self::from_array_buffer(&buffer)?)
+    }
+
+    /// Names of all entries in the backpack.
+    pub fn list(&self) -> Vec<String> {
+        self.pack().file_names()
+    }
+
+    /// Read the full contents of an entry.
+    #[wasm_bindgen(js_name = readEntry)]
+    pub fn read_entry(&self, name: &str) -> Result<Uint8Array, JsError> {
+        let f = self.pack().get_file(name)?;
+        let bytes = Uint8Array::from(&*f.get_bytes());
+        Ok(bytes)
+    }
+}
+
+impl JsBackPack {
+    fn from_bytes(data: Vec<u8>) -> Result<Self, JsError> {
+        Ok(Self {
+            inner: Some(BackPack::open(InMemoryFile::from(data))?),
+        })
+    }
+
+    fn pack(&self) -> &BackPack<'_, 'static> {
+        self.inner.as_ref().expect("backpack is only taken when dropped")
+    }
+}
+
+impl Drop for JsBackPack {
+    fn drop(&mut self) {
+        // the javascript side can't modify the pack, so there is nothing to write back
+        if let Some(bp) = self.inner.take() {
+            let _ = bp.close_drop_unwritten_changes();
+        }
+    }
+}